@@ -0,0 +1,329 @@
+//! Aggregate functions and GROUP BY: the rows a SELECT matched are put in
+//! groups by their GROUP BY values (all of them in one group if there is no
+//! GROUP BY), and the query then reads a table with a row per group, its
+//! columns named after the GROUP BY expressions and the aggregates as they
+//! are written, the way window functions are read from columns named after
+//! them.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::budget;
+use crate::error::DbError;
+use crate::expr::{BinaryOp, Expr};
+use crate::functions::Functions;
+use crate::interrupt;
+use crate::parser::{Order, SortKey};
+use crate::profile;
+use crate::query::sort_value;
+use crate::vectorized;
+use crate::window::Window;
+use crate::{DataType, Table};
+
+/// What an aggregate computes from the rows of a group.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Avg,
+    Min, // As ORDER BY compares them, so under the column's collation
+    Max,
+}
+
+impl AggregateFunction {
+    pub fn parse(name: &str) -> Option<AggregateFunction> {
+        match name.to_ascii_uppercase().as_str() {
+            "COUNT" => Some(AggregateFunction::Count),
+            "SUM" => Some(AggregateFunction::Sum),
+            "AVG" => Some(AggregateFunction::Avg),
+            "MIN" => Some(AggregateFunction::Min),
+            "MAX" => Some(AggregateFunction::Max),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AggregateFunction::Count => "COUNT",
+            AggregateFunction::Sum => "SUM",
+            AggregateFunction::Avg => "AVG",
+            AggregateFunction::Min => "MIN",
+            AggregateFunction::Max => "MAX",
+        }
+    }
+}
+
+/// `<function>(<expr>)`, or `COUNT(*)`: one value from the rows of a group.
+/// Values are never NULL, so `COUNT(<expr>)` counts every row, as
+/// `COUNT(*)` does.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregate {
+    pub function: AggregateFunction,
+    pub arg: Option<Expr>, // None for COUNT(*)
+}
+
+impl Aggregate {
+    /// Whether it is `COUNT(*)`, which a table's file header can answer.
+    pub fn counts_rows(&self) -> bool {
+        self.function == AggregateFunction::Count && self.arg.is_none()
+    }
+
+    /// The same aggregate with every column name replaced by what `rename` gives for it.
+    pub fn rename_columns(&self, rename: &mut impl FnMut(&str) -> Result<String, DbError>) -> Result<Aggregate, DbError> {
+        Ok(Aggregate { function: self.function, arg: self.arg.as_ref().map(|arg| arg.rename_columns(rename)).transpose()? })
+    }
+
+    /// The aggregate's value over `rows`, positions in `table`. None for an
+    /// AVG, MIN or MAX of no rows.
+    fn compute(&self, table: &Table, rows: &[usize], functions: &Functions) -> Result<Option<DataType>, DbError> {
+        let fail = |reason: String| DbError::InvalidExpression(format!("{}: {}", self, reason));
+        let Some(arg) = &self.arg else {
+            return Ok(Some(DataType::Integer32(count(rows.len())?)));
+        };
+        match self.function {
+            AggregateFunction::Count => Ok(Some(DataType::Integer32(count(rows.len())?))),
+            AggregateFunction::Sum | AggregateFunction::Avg => {
+                let column = arg.column().and_then(|column| table.data.get(column));
+                let total = match column.and_then(|values| vectorized::sum(values, rows)) {
+                    Some(sum) => sum.map_err(fail)?,
+                    None => {
+                        let mut total = DataType::Integer32(0);
+                        for &row in rows {
+                            interrupt::check()?;
+                            let value = arg.eval(table, row, functions)?;
+                            if !matches!(value, DataType::Integer32(_) | DataType::Float32(_)) {
+                                return Err(fail(format!("{} adds up numbers, not '{}'", self.function.name(), value)));
+                            }
+                            total = BinaryOp::Add.apply(&total, &value).map_err(fail)?;
+                        }
+                        total
+                    }
+                };
+                Ok(match (self.function, total) {
+                    (AggregateFunction::Sum, total) => Some(total),
+                    (_, _) if rows.is_empty() => None,
+                    (_, DataType::Integer32(total)) => Some(DataType::Float32(total as f32 / rows.len() as f32)),
+                    (_, DataType::Float32(total)) => Some(DataType::Float32(total / rows.len() as f32)),
+                    _ => unreachable!("SUM and AVG have a numeric total"),
+                })
+            }
+            AggregateFunction::Min | AggregateFunction::Max => {
+                let mut best: Option<(DataType, DataType)> = None; // Sort key and value
+                for &row in rows {
+                    interrupt::check()?;
+                    let key = sort_value(table, arg, row, functions)?;
+                    let better = best.as_ref().is_none_or(|(best, _)| match self.function {
+                        AggregateFunction::Min => key < *best,
+                        _ => key > *best,
+                    });
+                    if better {
+                        let value = match arg.column() {
+                            Some(column) => table.data[column][row].clone(),
+                            None => arg.eval(table, row, functions)?,
+                        };
+                        best = Some((key, value));
+                    }
+                }
+                Ok(best.map(|(_, value)| value))
+            }
+        }
+    }
+}
+
+pub(crate) fn count(rows: usize) -> Result<i32, DbError> {
+    i32::try_from(rows).map_err(|_| DbError::InvalidExpression("COUNT: result is too large for an int".to_string()))
+}
+
+impl fmt::Display for Aggregate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.arg {
+            Some(arg) => write!(f, "{}({})", self.function.name(), arg),
+            None => write!(f, "{}(*)", self.function.name()),
+        }
+    }
+}
+
+/// A SELECT's rows after grouping: the table to read them from, the rows of
+/// it to read, and the columns and order with every GROUP BY expression in
+/// them read from its column.
+pub(crate) struct Grouped {
+    pub table: Arc<Table>,
+    pub rows: Vec<usize>,
+    pub columns: Vec<Expr>,
+    pub order: Order,
+}
+
+/// `rows` of `table`, the rows a query matched, as a row per group when
+/// `columns` or `order` use aggregates or `order` has GROUP BY expressions,
+/// and as they are otherwise. Without GROUP BY there is one group, which an
+/// AVG, MIN or MAX of no rows leaves out.
+pub(crate) fn grouped(table: Arc<Table>, rows: Vec<usize>, columns: Vec<Expr>, order: Order, functions: &Functions) -> Result<Grouped, DbError> {
+    let exprs: Vec<&Expr> = columns.iter().chain(order.by.iter().map(|key| &key.expr)).collect();
+    let mut aggregates: Vec<&Aggregate> = Vec::new();
+    for aggregate in exprs.iter().flat_map(|expr| expr.aggregates()) {
+        if !aggregates.contains(&aggregate) {
+            aggregates.push(aggregate);
+        }
+    }
+    if aggregates.is_empty() && order.group.is_empty() {
+        return Ok(Grouped { table, rows, columns, order });
+    }
+    let began = profile::begin();
+    for aggregate in &aggregates {
+        if let Some(arg) = &aggregate.arg {
+            if !arg.aggregates().is_empty() {
+                return Err(DbError::InvalidExpression(format!("{}: aggregate functions cannot be nested", aggregate)));
+            }
+            arg.check(&table, functions)?;
+        }
+    }
+    for expr in &order.group {
+        if !expr.aggregates().is_empty() || !expr.windows().is_empty() {
+            return Err(DbError::InvalidExpression(format!("{}: GROUP BY cannot use aggregate or window functions", expr)));
+        }
+        expr.check(&table, functions)?;
+    }
+
+    // Groups in the order their first rows came in, told apart by their
+    // values as ORDER BY compares them
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut found: HashMap<Vec<DataType>, usize> = HashMap::new();
+    if order.group.is_empty() {
+        groups.push(rows);
+    } else {
+        for row in rows {
+            interrupt::check()?;
+            let key = order.group.iter().map(|expr| sort_value(&table, expr, row, functions)).collect::<Result<Vec<_>, _>>()?;
+            let group = match found.get(&key) {
+                Some(&group) => group,
+                None => {
+                    budget::charge(budget::size_of(&key))?;
+                    found.insert(key, groups.len());
+                    groups.push(Vec::new());
+                    groups.len() - 1
+                }
+            };
+            groups[group].push(row);
+        }
+    }
+
+    let mut values: Vec<Vec<DataType>> = Vec::with_capacity(groups.len());
+    let mut names: Vec<String> = Vec::new();
+    for group in &groups {
+        let mut row = Vec::new();
+        for expr in &order.group {
+            row.push(match expr.column() {
+                Some(column) => table.data[column][group[0]].clone(),
+                None => expr.eval(&table, group[0], functions)?,
+            });
+        }
+        let mut complete = true;
+        for aggregate in &aggregates {
+            match aggregate.compute(&table, group, functions)? {
+                Some(value) => row.push(value),
+                None => complete = false,
+            }
+        }
+        budget::charge(budget::size_of(&row))?;
+        if complete {
+            values.push(row);
+        }
+    }
+
+    let mut summary = Table::new(&table.name, Vec::new(), None, 0, 0);
+    let keys = order.group.iter().map(|expr| (expr.to_string(), Some(expr), None));
+    let computed = aggregates.iter().map(|aggregate| (aggregate.to_string(), aggregate.arg.as_ref(), Some(aggregate.function)));
+    for (i, (name, expr, function)) in keys.chain(computed).enumerate() {
+        if summary.fields.contains_key(&name) {
+            continue;
+        }
+        let column: Vec<DataType> = values.iter().map(|row| row[i].clone()).collect();
+        let typ = match (function, expr.and_then(Expr::column)) {
+            (Some(AggregateFunction::Count), _) => "int".to_string(),
+            (Some(AggregateFunction::Avg), _) => "float".to_string(),
+            (Some(AggregateFunction::Sum), _) if column.iter().all(|value| matches!(value, DataType::Integer32(_))) => "int".to_string(),
+            (Some(AggregateFunction::Sum), _) => "float".to_string(),
+            (None | Some(AggregateFunction::Min | AggregateFunction::Max), Some(source)) => {
+                if let Some(collation) = table.collations.get(source) {
+                    summary.collations.insert(name.clone(), *collation);
+                }
+                table.fields[source].clone()
+            }
+            (None | Some(AggregateFunction::Min | AggregateFunction::Max), None) => type_of(&column),
+        };
+        let column = match typ.as_str() {
+            "float" => column.into_iter().map(|value| match value {
+                DataType::Integer32(i) => DataType::Float32(i as f32),
+                value => value,
+            }).collect(),
+            _ => column,
+        };
+        summary.fields.insert(name.clone(), typ);
+        summary.columns.push(name.clone());
+        summary.data.insert(name.clone(), column);
+        names.push(name);
+    }
+    let count = values.len();
+    profile::record(began, || format!("Group into {}", names.join(", ")), None, count, None);
+
+    let summary = Arc::new(summary);
+    let columns = columns.iter().map(|expr| regrouped(expr, &order.group, &summary)).collect::<Result<_, _>>()?;
+    let by = order.by.iter()
+        .map(|key| Ok(SortKey { expr: regrouped(&key.expr, &order.group, &summary)?, ..key.clone() }))
+        .collect::<Result<_, DbError>>()?;
+    Ok(Grouped { table: summary, rows: (0..count).collect(), columns, order: Order { by, ..order } })
+}
+
+// The type a computed column takes: its values' if they all share one, text otherwise
+fn type_of(values: &[DataType]) -> String {
+    let mut names = values.iter().map(DataType::type_name);
+    let first = names.next().unwrap_or("string");
+    if names.all(|name| name == first) { first } else { "string" }.to_string()
+}
+
+/// `expr` reading each GROUP BY expression it holds from that expression's
+/// column of `summary`. Fails if it still reads a column outside an
+/// aggregate, which has no single value in a group.
+fn regrouped(expr: &Expr, group: &[Expr], summary: &Table) -> Result<Expr, DbError> {
+    let expr = replace_keys(expr, group);
+    for column in loose_columns(&expr) {
+        if !summary.fields.contains_key(column) {
+            return Err(DbError::InvalidExpression(format!(
+                "{}: a column of a query with aggregates must be in its GROUP BY or inside an aggregate function", column
+            )));
+        }
+    }
+    Ok(expr)
+}
+
+fn replace_keys(expr: &Expr, group: &[Expr]) -> Expr {
+    if group.contains(expr) {
+        return Expr::Column(expr.to_string());
+    }
+    let replace = |expr: &Expr| replace_keys(expr, group);
+    match expr {
+        Expr::Column(_) | Expr::Literal(_) | Expr::Aggregate(_) => expr.clone(),
+        Expr::Call { function, args } => Expr::Call { function: function.clone(), args: args.iter().map(replace).collect() },
+        Expr::Binary { op, left, right } => Expr::Binary { op: *op, left: Box::new(replace(left)), right: Box::new(replace(right)) },
+        Expr::Cast { expr, to } => Expr::Cast { expr: Box::new(replace(expr)), to: to.clone() },
+        Expr::Window(window) => Expr::Window(Box::new(Window {
+            arg: window.arg.as_ref().map(replace),
+            partition: window.partition.iter().map(replace).collect(),
+            order: window.order.iter().map(|key| SortKey { expr: replace(&key.expr), ..key.clone() }).collect(),
+            ..**window
+        })),
+    }
+}
+
+// The columns `expr` reads other than through an aggregate
+fn loose_columns(expr: &Expr) -> Vec<&str> {
+    match expr {
+        Expr::Column(name) => vec![name],
+        Expr::Literal(_) | Expr::Aggregate(_) => Vec::new(),
+        Expr::Call { args, .. } => args.iter().flat_map(loose_columns).collect(),
+        Expr::Binary { left, right, .. } => [loose_columns(left), loose_columns(right)].concat(),
+        Expr::Cast { expr, .. } => loose_columns(expr),
+        Expr::Window(window) => window.exprs().flat_map(loose_columns).collect(),
+    }
+}
@@ -0,0 +1,153 @@
+//! Rows appended beside a table's file. A checkpoint that finds a table has
+//! only had rows inserted since its file was last written adds them in a
+//! blob of their own, `<table>.<n>.append`, instead of writing the whole
+//! table again, so what it writes grows with the rows inserted rather than
+//! with the table. The blobs hold the log records that inserted the rows,
+//! and are replayed onto the file when the table is read, the same way as
+//! the log. Once the rows appended come to a quarter of those in the file,
+//! or there are `MAX_APPENDS` blobs, a checkpoint folds them back in by
+//! writing the table whole, so an insert costs its own row plus its share
+//! of a rewrite that comes less often the bigger the table.
+
+use std::io;
+
+use crate::database::Database;
+use crate::error::DbError;
+use crate::storage;
+use crate::wal::{WalOp, WalRecord};
+use crate::Table;
+
+// Rows in a table's file for each row appended beside it, below which the
+// appended rows are folded in
+const FOLD_RATIO: usize = 4;
+
+// Blobs appended to a table before they are folded into its file, so a
+// table is read from a bounded number of them
+const MAX_APPENDS: usize = 256;
+
+/// What a table's file and the blobs appended beside it hold.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Saved {
+    pub lsn: u64,        // Of the last change they contain
+    pub rows: usize,     // Those appended included
+    pub appended: usize, // Rows in the blobs
+    pub blobs: usize,    // Blobs appended, those already folded in included
+}
+
+impl Saved {
+    /// What the file of `table` holds, with nothing appended beside it.
+    pub fn file(table: &Table) -> Saved {
+        Saved { lsn: table.lsn, rows: table.row_count(), appended: 0, blobs: 0 }
+    }
+}
+
+/// The changes of `records` to table `name` after `lsn`, each record cut
+/// down to them, if every one is an insert; None if any is not, or if
+/// there are none.
+pub fn inserts(name: &str, records: &[WalRecord], lsn: u64) -> Option<Vec<WalRecord>> {
+    let mut inserts = Vec::new();
+    for record in records.iter().filter(|record| record.lsn > lsn) {
+        let op = match &record.op {
+            WalOp::Transaction { ops } => {
+                let ops: Vec<WalOp> = ops.iter().filter(|op| op.table() == Some(name)).cloned().collect();
+                if ops.is_empty() {
+                    continue;
+                }
+                WalOp::Transaction { ops }
+            }
+            op if op.table() == Some(name) => op.clone(),
+            _ => continue,
+        };
+        let ops = match &op {
+            WalOp::Transaction { ops } => ops.as_slice(),
+            op => std::slice::from_ref(op),
+        };
+        if !ops.iter().all(|op| matches!(op, WalOp::Insert { .. })) {
+            return None;
+        }
+        inserts.push(WalRecord { lsn: record.lsn, op, at: record.at });
+    }
+    (!inserts.is_empty()).then_some(inserts)
+}
+
+// The rows `op` inserts
+fn inserted(op: &WalOp) -> usize {
+    match op {
+        WalOp::Insert { .. } => 1,
+        WalOp::Transaction { ops } => ops.iter().map(inserted).sum(),
+        _ => 0,
+    }
+}
+
+impl Database {
+    /// Replays the rows appended beside the file of `table` onto it, as read
+    /// from the file. Returns what they hold together.
+    pub(crate) fn read_appended(&self, table: &mut Table) -> Result<Saved, DbError> {
+        let mut saved = Saved::file(table);
+        while let Some(bytes) = self.storage.read(&storage::append_key(&table.name, saved.blobs))? {
+            let records: Vec<WalRecord> = serde_json::from_slice(&bytes).map_err(|e| DbError::CorruptTable {
+                table: table.name.clone(),
+                reason: format!("unreadable rows in {} ({})", storage::append_key(&table.name, saved.blobs), e),
+            })?;
+            // Blobs from before the file was last written whole are in it already
+            for record in records {
+                if record.lsn <= table.lsn {
+                    continue;
+                }
+                table.apply(&record.op, record.at);
+                table.lsn = record.lsn;
+                saved.appended += inserted(&record.op);
+            }
+            saved.blobs += 1;
+        }
+        saved.lsn = table.lsn;
+        saved.rows = table.row_count();
+        Ok(saved)
+    }
+
+    /// The rows appended beside the file of table `name` after `lsn`, the
+    /// last change its file holds, without reading the file.
+    pub(crate) fn appended_rows(&self, name: &str, lsn: u64) -> Result<usize, DbError> {
+        let mut rows = 0;
+        let mut n = 0;
+        while let Some(bytes) = self.storage.read(&storage::append_key(name, n))? {
+            let records: Vec<WalRecord> = serde_json::from_slice(&bytes).map_err(io::Error::from)?;
+            rows += records.iter().filter(|record| record.lsn > lsn).map(|record| inserted(&record.op)).sum::<usize>();
+            n += 1;
+        }
+        Ok(rows)
+    }
+
+    /// Appends `records`, the inserts into `table` since its files held
+    /// `saved`, in a blob beside its file. Returns what they hold now, or
+    /// None if it is time the rows appended were folded into the file, or
+    /// the records do not account for every row added.
+    pub(crate) fn append(&mut self, table: &Table, saved: Saved, records: &[WalRecord]) -> Result<Option<Saved>, DbError> {
+        let rows: usize = records.iter().map(|record| inserted(&record.op)).sum();
+        if table.row_count() != saved.rows + rows || table.partitioning.is_some() || table.external.is_some() {
+            return Ok(None);
+        }
+        let appended = saved.appended + rows;
+        if saved.blobs >= MAX_APPENDS || appended * FOLD_RATIO > saved.rows - saved.appended {
+            return Ok(None);
+        }
+        self.check_read_write()?;
+        let bytes = serde_json::to_vec(records).map_err(io::Error::from)?;
+        self.storage.write(&storage::append_key(&table.name, saved.blobs), &bytes)?;
+        Ok(Some(Saved { lsn: table.lsn, rows: table.row_count(), appended, blobs: saved.blobs + 1 }))
+    }
+
+    /// Removes the blobs appended beside the file of table `name`, once it
+    /// holds their rows or is gone. The last goes first, so those a crash
+    /// leaves are still read in order, and folded in or removed next time.
+    pub(crate) fn remove_appended(&mut self, name: &str) -> Result<(), DbError> {
+        let mut blobs = 0;
+        while self.storage.exists(&storage::append_key(name, blobs)) {
+            blobs += 1;
+        }
+        for n in (0..blobs).rev() {
+            self.storage.remove(&storage::append_key(name, n))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,141 @@
+//! `AsyncDatabase`: the database for async programs, such as a web service
+//! on tokio. Statements run on a thread of their own, one at a time, and
+//! each call returns a future of its result, so reading and writing files
+//! never blocks the executor. The futures need no particular runtime.
+
+use std::future::Future;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use crate::database::Database;
+use crate::error::DbError;
+use crate::query::Rows;
+
+type Job = Box<dyn FnOnce(&mut Database) + Send>;
+
+/// A `Database` owned by a thread that runs what it is sent. Dropping the
+/// handle lets the thread finish what it was sent and close the database.
+pub struct AsyncDatabase {
+    jobs: Sender<Job>,
+}
+
+impl AsyncDatabase {
+    pub fn new(db: Database) -> AsyncDatabase {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        thread::spawn(move || {
+            let mut db = db;
+            for job in queue {
+                job(&mut db);
+            }
+        });
+        AsyncDatabase { jobs }
+    }
+
+    /// `Database::open_dir`, opened on another thread.
+    pub fn open_dir(dir: &Path) -> Reply<AsyncDatabase> {
+        let dir = dir.to_path_buf();
+        off_thread(move || Ok(AsyncDatabase::new(Database::open_dir(&dir)?)))
+    }
+
+    /// `Database::open_file`, opened on another thread.
+    pub fn open_file(path: &Path) -> Reply<AsyncDatabase> {
+        let path = path.to_path_buf();
+        off_thread(move || Ok(AsyncDatabase::new(Database::open_file(&path)?)))
+    }
+
+    /// Runs `f` with the database to itself, after everything sent before it.
+    pub fn execute<T: Send + 'static>(&self, f: impl FnOnce(&mut Database) -> Result<T, DbError> + Send + 'static) -> Reply<T> {
+        let (reply, complete) = Reply::new();
+        let job: Job = Box::new(move |db| {
+            // A panic fails this call only, as it would have failed the caller
+            let result = panic::catch_unwind(AssertUnwindSafe(|| f(db)));
+            complete.finish(result.unwrap_or_else(|_| Err(stopped("a statement panicked"))));
+        });
+        // Should the thread be gone, the job is dropped with `complete`,
+        // which fails the reply
+        let _ = self.jobs.send(job);
+        reply
+    }
+
+    /// `Database::query`: parses and runs one SELECT.
+    pub fn query(&self, sql: &str) -> Reply<Rows> {
+        let sql = sql.to_string();
+        self.execute(move |db| db.query(&sql))
+    }
+}
+
+// Runs `f` on a thread of its own, for work with no database to queue on
+fn off_thread<T: Send + 'static>(f: impl FnOnce() -> Result<T, DbError> + Send + 'static) -> Reply<T> {
+    let (reply, complete) = Reply::new();
+    thread::spawn(move || complete.finish(f()));
+    reply
+}
+
+fn stopped(reason: &str) -> DbError {
+    DbError::Io(io::Error::other(format!("the database thread gave no result: {}", reason)))
+}
+
+/// The result of a call on an `AsyncDatabase`, once its thread gets to it.
+pub struct Reply<T> {
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+struct Slot<T> {
+    result: Option<Result<T, DbError>>,
+    waker: Option<Waker>,
+}
+
+// Fills in a reply; dropped without a result, it fails it
+struct Complete<T> {
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+impl<T> Reply<T> {
+    fn new() -> (Reply<T>, Complete<T>) {
+        let slot = Arc::new(Mutex::new(Slot { result: None, waker: None }));
+        (Reply { slot: Arc::clone(&slot) }, Complete { slot })
+    }
+}
+
+impl<T> Future for Reply<T> {
+    type Output = Result<T, DbError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = lock(&self.slot);
+        match slot.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> Complete<T> {
+    fn finish(self, result: Result<T, DbError>) {
+        lock(&self.slot).result = Some(result);
+    }
+}
+
+impl<T> Drop for Complete<T> {
+    fn drop(&mut self) {
+        let mut slot = lock(&self.slot);
+        if slot.result.is_none() {
+            slot.result = Some(Err(stopped("the thread has stopped")));
+        }
+        if let Some(waker) = slot.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+fn lock<T>(slot: &Mutex<Slot<T>>) -> MutexGuard<'_, Slot<T>> {
+    slot.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
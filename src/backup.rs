@@ -0,0 +1,133 @@
+use std::path::Path;
+
+use crate::database::Database;
+use crate::error::DbError;
+use crate::wal::{self, WalOp};
+
+// Backups holding the whole database in one file are recognised by this
+// extension; any other path is a directory
+const FILE_EXTENSION: &str = "rdb";
+
+/// Blobs that make up a database's contents: tables, their indexes, and
+/// settings, users and views. Locks, the log and temporary files are not.
+pub(crate) fn is_content(key: &str) -> bool {
+    key.ends_with(".json") || key.ends_with(".idx") || key.ends_with(".append") || key.ends_with(".conf")
+}
+
+// A backup at `path`, encrypted under `passphrase` if it is, or if it is new
+fn open(path: &Path, passphrase: Option<&str>) -> Result<Database, DbError> {
+    let opened = if path.extension().is_some_and(|ext| ext == FILE_EXTENSION) {
+        Database::open_file_with_key(path, passphrase)
+    } else {
+        Database::open_dir_with_key(path, passphrase)
+    };
+    Ok(opened?)
+}
+
+impl Database {
+    /// Copies the database to `path`: a single `.rdb` file, or else a data
+    /// directory, either of which can be opened directly. The log is folded
+    /// into the table files first, so the copy holds every committed change
+    /// and nothing else; a database open read-only cannot fold it in, so its
+    /// records are copied along instead. Returns the number of tables copied.
+    pub fn backup(&mut self, path: &Path) -> Result<usize, DbError> {
+        if self.in_transaction() {
+            return Err(DbError::TransactionActive);
+        }
+        let occupied = path.is_file() || path.read_dir().is_ok_and(|mut entries| entries.next().is_some());
+        if occupied {
+            return Err(DbError::BackupFailed(format!("'{}' already exists and is not empty", path.display())));
+        }
+        if !self.is_read_only() {
+            self.checkpoint()?;
+        }
+
+        let mut target = open(path, self.passphrase())?;
+        let mut tables = 0;
+        for key in self.storage.keys()?.into_iter().filter(|key| is_content(key)) {
+            let Some(blob) = self.storage.read(&key)? else { continue };
+            target.storage.write(&key, &blob)?;
+            if key.ends_with(".json") {
+                tables += 1;
+            }
+        }
+        let unfolded = match self.is_read_only() {
+            true => self.wal.records()?.into_iter().filter(|record| !matches!(record.op, WalOp::Checkpoint)).collect(),
+            false => Vec::new(),
+        };
+        if !unfolded.is_empty() {
+            for record in unfolded {
+                target.wal.append_record(record)?;
+            }
+            return Ok(tables);
+        }
+        // Tables remember the last LSN they contain, which the copy's log must not go back behind
+        target.wal.advance_to(self.last_lsn());
+        target.wal.truncate()?;
+        Ok(tables)
+    }
+
+    /// Replaces the contents of the database with the backup at `path`,
+    /// made by `backup` or a copy of a data directory or `.rdb` file.
+    /// Records left in the backup's log are applied. With `until`, the
+    /// changes logged after the backup are replayed from the log archive up
+    /// to that time (seconds since the Unix epoch). Temporary tables stay.
+    /// Returns the number of tables restored.
+    pub fn restore_backup(&mut self, path: &Path, until: Option<u64>) -> Result<usize, DbError> {
+        if self.in_transaction() {
+            return Err(DbError::TransactionActive);
+        }
+        if !path.exists() {
+            return Err(DbError::BackupFailed(format!("'{}' does not exist", path.display())));
+        }
+        // Archives the records not archived yet, which a point in time may need
+        self.checkpoint()?;
+        let archived = match until {
+            None => Vec::new(),
+            Some(until) => {
+                let dir = self.settings()?.wal_archive.ok_or_else(|| DbError::BackupFailed(
+                    "UNTIL needs the log archive; see SET WAL ARCHIVE".to_string()
+                ))?;
+                wal::archived_records(Path::new(&dir), self.wal.key().as_ref())?.into_iter()
+                    .take_while(|record| record.at <= until)
+                    .collect()
+            }
+        };
+
+        let mut backup = open(path, self.passphrase())?;
+        let names: Vec<String> = backup.stored_names()?;
+        let mut tables = Vec::new();
+        for name in &names {
+            let mut table = backup.load_table(name)?.clone();
+            for record in &archived {
+                if record.lsn > table.lsn && record.op.tables().contains(&name.as_str()) {
+                    table.apply(&record.op, record.at);
+                    table.lsn = record.lsn;
+                }
+            }
+            tables.push(table);
+        }
+        let mut settings = Vec::new();
+        for key in backup.storage.keys()?.into_iter().filter(|key| key.ends_with(".conf")) {
+            if let Some(blob) = backup.storage.read(&key)? {
+                settings.push((key, blob));
+            }
+        }
+
+        for key in self.storage.keys()?.into_iter().filter(|key| is_content(key)) {
+            self.storage.remove(&key)?;
+        }
+        self.forget_stored_tables();
+        // Settings first, so the tables are written with the backup's codec
+        for (key, blob) in &settings {
+            self.storage.write(key, blob)?;
+        }
+        for table in &tables {
+            self.save_table(table)?;
+        }
+        self.wal.advance_to(backup.last_lsn().max(tables.iter().map(|t| t.lsn).max().unwrap_or(0)));
+        self.wal.truncate()?;
+        Ok(tables.len())
+    }
+}
+
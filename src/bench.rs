@@ -0,0 +1,190 @@
+//! `rust_db bench`: a synthetic workload run through the same engine as
+//! every other statement, so a change to storage, indexes or the WAL can be
+//! measured without outside tools. It loads a table of `rows` rows, runs
+//! `ops` statements drawn at random from the mix, and reports throughput and
+//! latency percentiles for each kind of statement.
+
+use std::time::{Duration, Instant};
+
+use rust_db::parser;
+
+use crate::commands::{Engine, Output, Quiet};
+
+/// The table the benchmark creates, and drops once it is done.
+const TABLE: &str = "bench";
+
+// Values of the `value` column, which scans filter on
+const VALUES: u64 = 1000;
+
+/// What `rust_db bench` runs.
+#[derive(Debug)]
+pub struct Workload {
+    pub rows: usize,
+    pub ops: usize,
+    pub mix: Mix,
+    pub seed: u64,
+}
+
+impl Default for Workload {
+    fn default() -> Workload {
+        Workload { rows: 10_000, ops: 10_000, mix: Mix::default(), seed: 1 }
+    }
+}
+
+/// How often each kind of statement is picked, relative to the others.
+#[derive(Debug, Clone, Copy)]
+pub struct Mix {
+    pub insert: u32, // A new row
+    pub lookup: u32, // One row by its primary key
+    pub scan: u32,   // A filter on an unindexed column, reading every row
+}
+
+impl Default for Mix {
+    fn default() -> Mix {
+        Mix { insert: 20, lookup: 70, scan: 10 }
+    }
+}
+
+impl Mix {
+    /// Reads `insert=<n>,lookup=<n>,scan=<n>`; a kind left out is never picked.
+    pub fn parse(text: &str) -> Result<Mix, String> {
+        let mut mix = Mix { insert: 0, lookup: 0, scan: 0 };
+        for part in text.split(',') {
+            let (kind, weight) = part.split_once('=')
+                .ok_or_else(|| format!("Expected <kind>=<weight> in the mix, found '{}'", part))?;
+            let weight = weight.trim().parse().map_err(|_| format!("Invalid weight '{}' in the mix", weight))?;
+            match kind.trim() {
+                "insert" => mix.insert = weight,
+                "lookup" => mix.lookup = weight,
+                "scan" => mix.scan = weight,
+                kind => return Err(format!("Unknown kind '{}' in the mix; use insert, lookup or scan", kind)),
+            }
+        }
+        if mix.insert + mix.lookup + mix.scan == 0 {
+            return Err("The mix needs at least one kind with a weight above 0".to_string());
+        }
+        Ok(mix)
+    }
+}
+
+/// Runs `workload` against a new table and reports on it to `out`. Returns
+/// false if a statement failed, which ends the run.
+pub fn run(engine: &mut Engine, out: &mut dyn Output, workload: &Workload) -> bool {
+    let mut random = Random(workload.seed.max(1));
+    let create = format!("CREATE TABLE {} id:int PRIMARY KEY name:string value:int", TABLE);
+    if let Err(e) = execute(engine, &create) {
+        out.error(&format!("Could not create the '{}' table: {}", TABLE, e));
+        return false;
+    }
+
+    let result = measure(engine, workload, &mut random);
+    if let Err(e) = execute(engine, &format!("DROP TABLE {}", TABLE)) {
+        out.error(&format!("Could not drop the '{}' table: {}", TABLE, e));
+    }
+    match result {
+        Ok(results) => {
+            report(out, &results);
+            true
+        }
+        Err(e) => {
+            out.error(&e);
+            false
+        }
+    }
+}
+
+/// The load, then the mix, with the time each statement took.
+fn measure(engine: &mut Engine, workload: &Workload, random: &mut Random) -> Result<Vec<Timings>, String> {
+    let mut load = Timings::new("load (insert)");
+    for id in 0..workload.rows {
+        load.time(engine, &insert(id, random))?;
+    }
+
+    let mix = workload.mix;
+    let mut kinds = [Timings::new("insert"), Timings::new("lookup"), Timings::new("scan")];
+    let total = mix.insert + mix.lookup + mix.scan;
+    let mut next_id = workload.rows;
+    for _ in 0..workload.ops {
+        let pick = random.below(total as u64) as u32;
+        let (kind, sql) = if pick < mix.insert {
+            next_id += 1;
+            (0, insert(next_id - 1, random))
+        } else if pick < mix.insert + mix.lookup && next_id > 0 {
+            (1, format!("SELECT * FROM {} WHERE id = {}", TABLE, random.below(next_id as u64)))
+        } else {
+            (2, format!("SELECT * FROM {} WHERE value < {}", TABLE, random.below(VALUES / 100) + 1))
+        };
+        kinds[kind].time(engine, &sql)?;
+    }
+    Ok(std::iter::once(load).chain(kinds).filter(|timings| !timings.latencies.is_empty()).collect())
+}
+
+fn insert(id: usize, random: &mut Random) -> String {
+    format!("INSERT INTO {} VALUES ({}, 'row {}', {})", TABLE, id, id, random.below(VALUES))
+}
+
+fn report(out: &mut dyn Output, results: &[Timings]) {
+    let columns = ["operation", "count", "ops/s", "mean ms", "p50 ms", "p95 ms", "p99 ms", "max ms"];
+    let rows = results.iter()
+        .map(|timings| {
+            let mut latencies = timings.latencies.clone();
+            latencies.sort();
+            let ms = |d: Duration| format!("{:.3}", d.as_secs_f64() * 1000.0);
+            let percentile = |q: f64| latencies[((latencies.len() - 1) as f64 * q).round() as usize];
+            let total: Duration = latencies.iter().sum();
+            vec![
+                timings.name.to_string(),
+                latencies.len().to_string(),
+                format!("{:.0}", latencies.len() as f64 / total.as_secs_f64()),
+                ms(total / latencies.len() as u32),
+                ms(percentile(0.5)),
+                ms(percentile(0.95)),
+                ms(percentile(0.99)),
+                ms(latencies[latencies.len() - 1]),
+            ]
+        })
+        .collect();
+    let types = ["string", "int", "int", "float", "float", "float", "float", "float"];
+    out.rows(&columns, &types, rows);
+}
+
+/// How long each statement of one kind took, parsing included.
+struct Timings {
+    name: &'static str,
+    latencies: Vec<Duration>,
+}
+
+impl Timings {
+    fn new(name: &'static str) -> Timings {
+        Timings { name, latencies: Vec::new() }
+    }
+
+    fn time(&mut self, engine: &mut Engine, sql: &str) -> Result<(), String> {
+        let started = Instant::now();
+        execute(engine, sql)?;
+        self.latencies.push(started.elapsed());
+        Ok(())
+    }
+}
+
+fn execute(engine: &mut Engine, sql: &str) -> Result<(), String> {
+    let statement = parser::parse(sql).map_err(|e| e.to_string())?;
+    let mut quiet = Quiet::default();
+    engine.execute(&mut quiet, statement, sql, None);
+    match quiet.error {
+        Some(error) => Err(format!("{}: {}", sql, error)),
+        None => Ok(()),
+    }
+}
+
+/// A xorshift generator, so the same seed gives the same workload.
+struct Random(u64);
+
+impl Random {
+    fn below(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n
+    }
+}
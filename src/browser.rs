@@ -0,0 +1,112 @@
+//! Keeping a database in a browser, for the library compiled to wasm32:
+//! `LocalStorage` keeps each blob under a key of the page's
+//! `window.localStorage`, so the tables outlive the page. Open it with
+//! `Database::open_with(Box::new(LocalStorage::new("app")))`.
+//!
+//! IndexedDB only answers through callbacks the engine cannot wait on, so
+//! local storage, which answers at once, is what the `Storage` trait can
+//! be given; browsers cap it at a few megabytes per origin, and a write
+//! past that fails with the browser's error.
+
+use std::io;
+
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD;
+use wasm_bindgen::JsValue;
+
+use crate::storage::Storage;
+
+/// The blobs of one database in the page's local storage, each under its
+/// key behind the database's name, base64-encoded as local storage keeps
+/// only text. Several databases, or other data of the page, can share it.
+pub struct LocalStorage {
+    prefix: String,
+}
+
+impl LocalStorage {
+    pub fn new(name: &str) -> LocalStorage {
+        LocalStorage { prefix: format!("{}:", name) }
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    // The keys kept under this database's name, sub-namespaces included
+    fn all_keys(&self) -> io::Result<Vec<String>> {
+        let store = store()?;
+        let mut keys = Vec::new();
+        for i in 0..store.length().map_err(error)? {
+            if let Some(key) = store.key(i).map_err(error)?
+                && let Some(key) = key.strip_prefix(&self.prefix)
+            {
+                keys.push(key.to_string());
+            }
+        }
+        Ok(keys)
+    }
+}
+
+impl Storage for LocalStorage {
+    fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        let Some(text) = store()?.get_item(&self.key(key)).map_err(error)? else { return Ok(None) };
+        STANDARD.decode(text).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    // A single setItem, which replaces the value at once
+    fn write(&mut self, key: &str, bytes: &[u8]) -> io::Result<()> {
+        store()?.set_item(&self.key(key), &STANDARD.encode(bytes)).map_err(error)
+    }
+
+    fn remove(&mut self, key: &str) -> io::Result<bool> {
+        let store = store()?;
+        let key = self.key(key);
+        let existed = store.get_item(&key).map_err(error)?.is_some();
+        store.remove_item(&key).map_err(error)?;
+        Ok(existed)
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> io::Result<()> {
+        let store = store()?;
+        let text = store.get_item(&self.key(from)).map_err(error)?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, from.to_string()))?;
+        store.set_item(&self.key(to), &text).map_err(error)?;
+        store.remove_item(&self.key(from)).map_err(error)
+    }
+
+    fn exists(&self, key: &str) -> bool {
+        store().ok().and_then(|store| store.get_item(&self.key(key)).ok().flatten()).is_some()
+    }
+
+    fn keys(&self) -> io::Result<Vec<String>> {
+        Ok(self.all_keys()?.into_iter().filter(|k| !k.contains('/')).collect())
+    }
+
+    // What the blobs take once decoded, not the text the browser counts
+    fn size(&self) -> io::Result<u64> {
+        let store = store()?;
+        let mut total = 0;
+        for key in self.all_keys()? {
+            let text = store.get_item(&self.key(&key)).map_err(error)?.unwrap_or_default();
+            total += (text.len() / 4 * 3) as u64;
+        }
+        Ok(total)
+    }
+
+    // Each write is a single setItem, so none is ever left torn
+    fn discard_torn_writes(&mut self) -> io::Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+}
+
+// Looked up on each call: the browser's handle cannot be held by a
+// backend that must be Send and Sync
+fn store() -> io::Result<web_sys::Storage> {
+    let window = web_sys::window().ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "no window to find local storage in"))?;
+    window.local_storage().map_err(error)?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "local storage is turned off"))
+}
+
+fn error(value: JsValue) -> io::Error {
+    io::Error::other(value.as_string().unwrap_or_else(|| format!("{:?}", value)))
+}
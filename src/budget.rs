@@ -0,0 +1,49 @@
+//! How much memory a statement may take for what a query builds up as it
+//! runs: joined rows, sort keys, window values and the result itself. Each
+//! of these is `charge`d as it grows, and the statement fails once the total
+//! passes the limit rather than growing until the process runs out of
+//! memory. The figures are estimates of the data held, not a count kept by
+//! the allocator, and nothing is given back before the statement ends.
+
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::error::DbError;
+use crate::DataType;
+
+static LIMIT: AtomicUsize = AtomicUsize::new(0); // In bytes, 0 for none
+static USED: AtomicUsize = AtomicUsize::new(0);
+
+/// Starts the count for the statement about to run, which may use `limit`
+/// bytes, or as much as it needs if None.
+pub fn start(limit: Option<usize>) {
+    LIMIT.store(limit.unwrap_or(0), Ordering::Relaxed);
+    USED.store(0, Ordering::Relaxed);
+}
+
+/// Counts `bytes` more against the running statement, failing if that
+/// takes it past its limit.
+pub(crate) fn charge(bytes: usize) -> Result<(), DbError> {
+    let used = USED.fetch_add(bytes, Ordering::Relaxed) + bytes;
+    match LIMIT.load(Ordering::Relaxed) {
+        0 => Ok(()),
+        limit if used > limit => Err(DbError::MemoryLimit(limit)),
+        _ => Ok(()),
+    }
+}
+
+/// Roughly what holding `values` takes.
+pub(crate) fn size_of(values: &[DataType]) -> usize {
+    values.iter()
+        .map(|value| mem::size_of::<DataType>() + match value {
+            DataType::String(s) => s.len(),
+            DataType::Array(items) => size_of(items),
+            DataType::Integer32(_) | DataType::Float32(_) => 0,
+        })
+        .sum()
+}
+
+/// Roughly what a list of `count` rows of `width` positions takes.
+pub(crate) fn size_of_rows(count: usize, width: usize) -> usize {
+    count * (mem::size_of::<Vec<usize>>() + width * mem::size_of::<usize>())
+}
@@ -0,0 +1,236 @@
+use std::sync::Arc;
+
+use crate::functions::Function;
+use crate::random::{self, Rng};
+use crate::table;
+use crate::time::{self, Clock};
+use crate::DataType;
+
+/// The built-in functions whose result differs from call to call with the
+/// same arguments.
+pub const VOLATILE: [&str; 3] = ["now", "random", "uuid"];
+
+/// The functions every database starts with, by lowercase name, reading the
+/// time from `clock` and drawing random numbers from `rng`. A function
+/// registered under the same name replaces one of these.
+pub fn all(clock: &Arc<dyn Clock>, rng: &Arc<dyn Rng>) -> Vec<(&'static str, Function)> {
+    vec![
+        ("upper", Arc::new(|args: &[DataType]| Ok(DataType::String(text(args, 1, 1)?[0].to_uppercase())))),
+        ("lower", Arc::new(|args: &[DataType]| Ok(DataType::String(text(args, 1, 1)?[0].to_lowercase())))),
+        ("length", Arc::new(|args: &[DataType]| count(text(args, 1, 1)?[0].chars().count()))),
+        ("trim", Arc::new(|args: &[DataType]| Ok(DataType::String(text(args, 1, 1)?[0].trim().to_string())))),
+        ("substr", Arc::new(substr)),
+        ("replace", Arc::new(|args: &[DataType]| {
+            let args = text(args, 3, 3)?;
+            Ok(DataType::String(args[0].replace(&args[1], &args[2])))
+        })),
+        ("concat", Arc::new(|args: &[DataType]| Ok(DataType::String(text(args, 1, usize::MAX)?.concat())))),
+        ("abs", Arc::new(|args: &[DataType]| match number(args, 1, 1)?[0] {
+            Number::Int(i) => i.checked_abs().map(DataType::Integer32).ok_or_else(overflow),
+            Number::Float(f) => Ok(DataType::Float32(f.abs())),
+        })),
+        ("round", Arc::new(round)),
+        ("ceil", Arc::new(|args: &[DataType]| Ok(float_only(number(args, 1, 1)?[0], f32::ceil)))),
+        ("floor", Arc::new(|args: &[DataType]| Ok(float_only(number(args, 1, 1)?[0], f32::floor)))),
+        ("mod", Arc::new(|args: &[DataType]| match number(args, 2, 2)?[..] {
+            [_, Number::Int(0)] => Err("division by zero".to_string()),
+            [Number::Int(a), Number::Int(b)] => Ok(DataType::Integer32(a.wrapping_rem(b))),
+            [a, b] => Ok(DataType::Float32(a.float() % b.float())),
+            _ => unreachable!("two arguments"),
+        })),
+        ("power", Arc::new(|args: &[DataType]| match number(args, 2, 2)?[..] {
+            [Number::Int(base), Number::Int(exponent)] if exponent >= 0 => {
+                base.checked_pow(exponent as u32).map(DataType::Integer32).ok_or_else(overflow)
+            }
+            [base, exponent] => Ok(DataType::Float32(base.float().powf(exponent.float()))),
+            _ => unreachable!("two arguments"),
+        })),
+        ("random", random(Arc::clone(rng))),
+        ("uuid", uuid(Arc::clone(rng))),
+        ("now", now(Arc::clone(clock))),
+        ("date", Arc::new(|args: &[DataType]| {
+            let (year, month, day) = date(args)?;
+            Ok(DataType::String(format!("{:04}-{:02}-{:02}", year, month, day)))
+        })),
+        ("year", Arc::new(|args: &[DataType]| {
+            let year = date(args)?.0;
+            i32::try_from(year).map(DataType::Integer32).map_err(|_| overflow())
+        })),
+        ("month", Arc::new(|args: &[DataType]| Ok(DataType::Integer32(date(args)?.1 as i32)))),
+        ("day", Arc::new(|args: &[DataType]| Ok(DataType::Integer32(date(args)?.2 as i32)))),
+        ("point", Arc::new(|args: &[DataType]| {
+            let [lat, lon] = number(args, 2, 2)?[..] else { unreachable!("two arguments") };
+            table::point(lat.float(), lon.float())
+                .ok_or_else(|| "latitude must be within -90 and 90, and longitude within -180 and 180".to_string())
+        })),
+        ("distance", Arc::new(distance)),
+    ]
+}
+
+/// `NOW()`: the time by `clock`, as `YYYY-MM-DD HH:MM:SS` (UTC).
+pub fn now(clock: Arc<dyn Clock>) -> Function {
+    Arc::new(move |args: &[DataType]| {
+        arity(args, 0, 0)?;
+        Ok(DataType::String(time::format_timestamp(clock.now())))
+    })
+}
+
+/// `RANDOM()`: a number from 0 up to but not including 1, drawn from `rng`.
+pub fn random(rng: Arc<dyn Rng>) -> Function {
+    Arc::new(move |args: &[DataType]| {
+        arity(args, 0, 0)?;
+        Ok(DataType::Float32(random::float(rng.as_ref())))
+    })
+}
+
+/// `UUID()`: a random UUID drawn from `rng`, as a string.
+pub fn uuid(rng: Arc<dyn Rng>) -> Function {
+    Arc::new(move |args: &[DataType]| {
+        arity(args, 0, 0)?;
+        Ok(DataType::String(random::uuid(rng.as_ref())))
+    })
+}
+
+/// `DISTANCE(p, q)`: the great-circle distance between two points in
+/// kilometres, by the haversine formula on a sphere of the Earth's mean
+/// radius.
+fn distance(args: &[DataType]) -> Result<DataType, String> {
+    const EARTH_RADIUS_KM: f64 = 6371.0;
+    arity(args, 2, 2)?;
+    let [p, q] = [&args[0], &args[1]].map(|arg| {
+        table::point_of(arg)
+            .map(|(lat, lon)| (f64::from(lat).to_radians(), f64::from(lon).to_radians()))
+            .ok_or_else(|| format!("expects points, not '{}'", arg))
+    });
+    let ((lat1, lon1), (lat2, lon2)) = (p?, q?);
+    let h = ((lat2 - lat1) / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+    Ok(DataType::Float32((2.0 * EARTH_RADIUS_KM * h.sqrt().asin()) as f32))
+}
+
+/// The date of a timestamp argument, written `YYYY-MM-DD[ HH:MM[:SS]]`.
+fn date(args: &[DataType]) -> Result<(i64, u32, u32), String> {
+    let text = &text(args, 1, 1)?[0];
+    time::parse_timestamp(text)
+        .map(time::date_of)
+        .ok_or_else(|| format!("expects a timestamp like 'YYYY-MM-DD HH:MM:SS', not '{}'", text))
+}
+
+/// A numeric argument. Functions of ints give ints where the result is
+/// always whole, and floats otherwise.
+#[derive(Clone, Copy)]
+enum Number {
+    Int(i32),
+    Float(f32),
+}
+
+impl Number {
+    fn float(self) -> f32 {
+        match self {
+            Number::Int(i) => i as f32,
+            Number::Float(f) => f,
+        }
+    }
+}
+
+/// `ROUND(x[, digits])`: `x` rounded half away from zero to `digits`
+/// decimal places (0 by default; negative rounds to tens, hundreds, ...).
+fn round(args: &[DataType]) -> Result<DataType, String> {
+    arity(args, 1, 2)?;
+    let digits = match args.get(1) {
+        Some(digits) => integer(digits, "digits")?,
+        None => 0,
+    };
+    match number(&args[..1], 1, 1)?[0] {
+        Number::Int(i) if digits >= 0 => Ok(DataType::Integer32(i)),
+        Number::Int(i) => {
+            let scale = 10f64.powi(-digits);
+            let rounded = (i as f64 / scale).round() * scale;
+            if rounded.abs() > i32::MAX as f64 {
+                return Err(overflow());
+            }
+            Ok(DataType::Integer32(rounded as i32))
+        }
+        // In f64, so the scaling adds no error of its own
+        Number::Float(f) => {
+            let scale = 10f64.powi(digits);
+            Ok(DataType::Float32(((f as f64 * scale).round() / scale) as f32))
+        }
+    }
+}
+
+/// `f` applied to a float; an int is already whole.
+fn float_only(value: Number, f: fn(f32) -> f32) -> DataType {
+    match value {
+        Number::Int(i) => DataType::Integer32(i),
+        Number::Float(x) => DataType::Float32(f(x)),
+    }
+}
+
+fn number(args: &[DataType], min: usize, max: usize) -> Result<Vec<Number>, String> {
+    arity(args, min, max)?;
+    args.iter()
+        .map(|arg| match arg {
+            DataType::Integer32(i) => Ok(Number::Int(*i)),
+            DataType::Float32(f) => Ok(Number::Float(*f)),
+            other => Err(format!("expects numbers, not '{}'", other)),
+        })
+        .collect()
+}
+
+fn overflow() -> String {
+    "result is too large for an int".to_string()
+}
+
+/// `SUBSTR(s, start[, length])`: characters from `start`, counting from 1,
+/// to the end or for `length` characters. A start before the first
+/// character counts the characters before it against the length.
+fn substr(args: &[DataType]) -> Result<DataType, String> {
+    arity(args, 2, 3)?;
+    let start = integer(&args[1], "start")? as i64;
+    let end = match args.get(2) {
+        Some(length) => match integer(length, "length")? {
+            length if length < 0 => return Err("length must not be negative".to_string()),
+            length => Some(start + length as i64),
+        },
+        None => None,
+    };
+    let text = args[0].to_string();
+    let substring = text.chars()
+        .enumerate()
+        .filter(|&(i, _)| {
+            let position = i as i64 + 1;
+            position >= start && end.is_none_or(|end| position < end)
+        })
+        .map(|(_, c)| c)
+        .collect();
+    Ok(DataType::String(substring))
+}
+
+fn arity(args: &[DataType], min: usize, max: usize) -> Result<(), String> {
+    if args.len() < min || args.len() > max {
+        let expected = match (min, max) {
+            (min, max) if min == max => format!("{}", min),
+            (min, usize::MAX) => format!("at least {}", min),
+            (min, max) => format!("{} to {}", min, max),
+        };
+        return Err(format!("takes {} argument(s), got {}", expected, args.len()));
+    }
+    Ok(())
+}
+
+/// The arguments as text, numbers written as they print.
+fn text(args: &[DataType], min: usize, max: usize) -> Result<Vec<String>, String> {
+    arity(args, min, max)?;
+    Ok(args.iter().map(DataType::to_string).collect())
+}
+
+fn integer(value: &DataType, name: &str) -> Result<i32, String> {
+    match value {
+        DataType::Integer32(i) => Ok(*i),
+        other => Err(format!("{} must be an int, not '{}'", name, other)),
+    }
+}
+
+fn count(n: usize) -> Result<DataType, String> {
+    i32::try_from(n).map(DataType::Integer32).map_err(|_| overflow())
+}
@@ -0,0 +1,194 @@
+use crate::database::Database;
+use crate::error::DbError;
+use crate::expr::Expr;
+use crate::parser::Order;
+use crate::{DataType, Table};
+
+/// Every table and view: its kind (`table`, `temporary`, `view` or
+/// `materialized view`), rows, columns and indexes.
+pub const TABLES: &str = "__tables";
+/// Every column of every table, in order, with its type.
+pub const COLUMNS: &str = "__columns";
+/// Every index of every table, with its columns, kind and uniqueness.
+pub const INDEXES: &str = "__indexes";
+/// Every view, index and trigger, with the table or view it rests on.
+pub const DEPENDENCIES: &str = "__dependencies";
+
+/// Whether `name` is one of the system catalog tables, which cannot be
+/// created, written or dropped.
+pub fn is_system_table(name: &str) -> bool {
+    [TABLES, COLUMNS, INDEXES, DEPENDENCIES].contains(&name)
+}
+
+/// The name among `names` closest to `name`, ignoring case, if it is only
+/// a few typing mistakes away.
+pub fn closest(name: &str, names: &[String]) -> Option<String> {
+    let name = name.to_lowercase();
+    // About one slip in three letters, and at least one
+    let allowed = (name.chars().count() / 3).max(1);
+    names.iter()
+        .map(|candidate| (edit_distance(&name, &candidate.to_lowercase()), candidate))
+        .filter(|(distance, _)| *distance <= allowed)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.clone())
+}
+
+// The number of characters to insert, delete or replace to turn `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let replaced = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = replaced.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+impl Database {
+    /// The error for a table or view `name` that does not exist, suggesting
+    /// the one with the closest name, if any is close.
+    pub fn table_not_found(&self, name: &str) -> DbError {
+        let mut names = self.table_names().unwrap_or_default();
+        names.extend(self.views().unwrap_or_default().into_iter().map(|view| view.name));
+        DbError::TableNotFound { suggestion: closest(name, &names), name: name.to_string() }
+    }
+
+    /// A system catalog table, built from the database as it is now, or
+    /// None if `name` is not one.
+    pub fn system_table(&mut self, name: &str) -> Result<Option<Table>, DbError> {
+        let (columns, rows): (&[(&str, &str)], Vec<Vec<DataType>>) = match name {
+            TABLES => (
+                &[("name", "string"), ("kind", "string"), ("rows", "int"), ("columns", "int"), ("indexes", "int"), ("engine", "string"), ("comment", "string")],
+                self.table_rows()?,
+            ),
+            COLUMNS => (
+                &[
+                    ("table_name", "string"),
+                    ("name", "string"),
+                    ("type", "string"),
+                    ("position", "int"),
+                    ("primary_key", "string"),
+                    ("generated", "string"),
+                    ("collation", "string"),
+                    ("comment", "string"),
+                ],
+                self.column_rows()?,
+            ),
+            INDEXES => (
+                &[("name", "string"), ("table_name", "string"), ("columns", "string"), ("kind", "string"), ("unique", "string")],
+                self.index_rows()?,
+            ),
+            DEPENDENCIES => (
+                &[("name", "string"), ("kind", "string"), ("table_name", "string"), ("depends_on", "string")],
+                self.dependency_rows()?,
+            ),
+            _ => return Ok(None),
+        };
+
+        let columns = columns.iter().map(|(name, kind)| (name.to_string(), kind.to_string())).collect();
+        let mut table = Table::new(name, columns, None, self.last_lsn(), self.now());
+        for row in rows {
+            for (column, value) in table.columns.iter().zip(row) {
+                table.data.get_mut(column).unwrap().push(value);
+            }
+        }
+        Ok(Some(table))
+    }
+
+    fn table_rows(&mut self) -> Result<Vec<Vec<DataType>>, DbError> {
+        let views = self.views()?;
+        let mut rows = Vec::new();
+        // Materialized views are listed once, as views, though they are also tables
+        for name in self.table_names()?.into_iter().filter(|name| !views.iter().any(|view| &view.name == name)) {
+            let temp = self.is_temp(&name);
+            let row_count = self.count_rows(&name)?;
+            let table = self.definition(&name)?;
+            rows.push(vec![
+                DataType::String(name),
+                DataType::String(if temp { "temporary" } else { "table" }.to_string()),
+                count(row_count),
+                count(table.columns.len()),
+                count(table.index_defs.len()),
+                DataType::String(table.engine.name().to_string()),
+                DataType::String(table.comment.unwrap_or_default()),
+            ]);
+        }
+        for view in views {
+            let kind = if view.materialized.is_some() { "materialized view" } else { "view" };
+            let found = self.select(&view.name, &[], &[], &Order::default())?;
+            rows.push(vec![
+                DataType::String(view.name),
+                DataType::String(kind.to_string()),
+                count(found.rows.len()),
+                count(found.columns.len()),
+                count(0),
+                DataType::String("-".to_string()), // Views are not given an engine
+                DataType::String(String::new()),
+            ]);
+        }
+        Ok(rows)
+    }
+
+    fn column_rows(&mut self) -> Result<Vec<Vec<DataType>>, DbError> {
+        let mut rows = Vec::new();
+        for name in self.table_names()? {
+            let table = self.definition(&name)?;
+            for (i, column) in table.columns.iter().enumerate() {
+                rows.push(vec![
+                    DataType::String(name.clone()),
+                    DataType::String(column.clone()),
+                    DataType::String(table.fields[column].clone()),
+                    count(i + 1),
+                    yes_no(table.primary_key.as_ref() == Some(column)),
+                    DataType::String(table.generated.get(column).map(Expr::to_string).unwrap_or_default()),
+                    DataType::String(table.collation(column).name().to_string()),
+                    DataType::String(table.column_comments.get(column).cloned().unwrap_or_default()),
+                ]);
+            }
+        }
+        Ok(rows)
+    }
+
+    fn index_rows(&mut self) -> Result<Vec<Vec<DataType>>, DbError> {
+        let mut rows = Vec::new();
+        for name in self.table_names()? {
+            let table = self.definition(&name)?;
+            for def in &table.index_defs {
+                rows.push(vec![
+                    DataType::String(def.name.clone()),
+                    DataType::String(name.clone()),
+                    DataType::String(def.columns.join(", ")),
+                    DataType::String(def.kind.keyword().to_string()),
+                    yes_no(def.unique),
+                ]);
+            }
+        }
+        Ok(rows)
+    }
+
+    fn dependency_rows(&mut self) -> Result<Vec<Vec<DataType>>, DbError> {
+        let rows = self.dependencies()?
+            .into_iter()
+            .map(|dependency| vec![
+                DataType::String(dependency.name),
+                DataType::String(dependency.kind.to_string()),
+                DataType::String(dependency.table.unwrap_or_default()),
+                DataType::String(dependency.on),
+            ])
+            .collect();
+        Ok(rows)
+    }
+}
+
+fn count(n: usize) -> DataType {
+    DataType::Integer32(i32::try_from(n).unwrap_or(i32::MAX))
+}
+
+fn yes_no(value: bool) -> DataType {
+    DataType::String(if value { "yes" } else { "no" }.to_string())
+}
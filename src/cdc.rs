@@ -0,0 +1,123 @@
+//! Change data capture: every committed insert, update and delete, sent to
+//! subscribers as it commits, so a cache or search index kept outside the
+//! database can follow it. Changes inside a transaction are held back until
+//! COMMIT and dropped on ROLLBACK. Temporary tables send none, and a
+//! partitioned table's changes are sent as its own, not its partitions'.
+
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use serde_json::Value;
+
+use crate::database::Database;
+use crate::error::DbError;
+use crate::jsonl;
+use crate::wal::WalOp;
+use crate::{DataType, Table};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventKind {
+    Insert,
+    Update,
+    Delete,
+}
+
+impl EventKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::Insert => "insert",
+            EventKind::Update => "update",
+            EventKind::Delete => "delete",
+        }
+    }
+}
+
+/// One row changed by a committed statement.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub lsn: u64, // Of the log record that committed it, shared by a transaction's changes
+    pub table: String,
+    pub kind: EventKind,
+    pub old: Option<Vec<(String, DataType)>>, // The row as it was, for updates and deletes
+    pub new: Option<Vec<(String, DataType)>>, // The row as it is now, for inserts and updates
+}
+
+impl Event {
+    /// The event as one line of JSON: `lsn`, `table`, `op`, and `old` and
+    /// `new` as objects keyed by column, where they apply.
+    pub fn to_json(&self) -> String {
+        let row = |row: &[(String, DataType)]| {
+            let fields: Vec<String> = row.iter()
+                .map(|(column, value)| format!("{}:{}", Value::from(column.as_str()), jsonl::json(value)))
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        };
+        let mut fields = vec![
+            format!("\"lsn\":{}", self.lsn),
+            format!("\"table\":{}", Value::from(self.table.as_str())),
+            format!("\"op\":\"{}\"", self.kind.name()),
+        ];
+        fields.extend(self.old.as_deref().map(|old| format!("\"old\":{}", row(old))));
+        fields.extend(self.new.as_deref().map(|new| format!("\"new\":{}", row(new))));
+        format!("{{{}}}", fields.join(","))
+    }
+}
+
+/// A channel taking the events of one table, or of every table if None.
+pub(crate) struct Subscriber {
+    table: Option<String>,
+    sender: Sender<Event>,
+}
+
+/// The events `op` makes of `table`, which it has not been applied to yet.
+pub(crate) fn capture(table: &Table, op: &WalOp) -> Vec<Event> {
+    let row = |row: usize| -> Vec<(String, DataType)> {
+        table.columns.iter().map(|col| (col.clone(), table.data[col][row].clone())).collect()
+    };
+    let event = |kind, old, new| Event { lsn: 0, table: table.name.clone(), kind, old, new };
+    match op {
+        WalOp::Insert { row: values, .. } => {
+            vec![event(EventKind::Insert, None, Some(table.columns.iter().cloned().zip(values.iter().cloned()).collect()))]
+        }
+        WalOp::Delete { index, .. } => vec![event(EventKind::Delete, Some(row(*index)), None)],
+        WalOp::DeleteRows { rows, .. } => rows.iter().map(|&i| event(EventKind::Delete, Some(row(i)), None)).collect(),
+        WalOp::Update { row: i, values, .. } => {
+            let old = row(*i);
+            let mut new = old.clone();
+            for (column, value) in values {
+                if let Some((_, slot)) = new.iter_mut().find(|(col, _)| col == column) {
+                    *slot = value.clone();
+                }
+            }
+            vec![event(EventKind::Update, Some(old), Some(new))]
+        }
+        // Soft-deleted rows leave the table as far as readers see, and come back when undeleted
+        WalOp::Tombstone { rows, deleted: true, .. } => rows.iter().map(|&i| event(EventKind::Delete, Some(row(i)), None)).collect(),
+        WalOp::Tombstone { rows, deleted: false, .. } => rows.iter().map(|&i| event(EventKind::Insert, None, Some(row(i)))).collect(),
+        WalOp::Transaction { .. } | WalOp::Checkpoint => Vec::new(),
+    }
+}
+
+/// Sends `events`, committed at `lsn`, to the subscribers of their tables,
+/// forgetting those that went away.
+pub(crate) fn publish(subscribers: &mut Vec<Subscriber>, lsn: u64, events: Vec<Event>) {
+    for mut event in events {
+        event.lsn = lsn;
+        subscribers.retain(|subscriber| match &subscriber.table {
+            Some(table) if *table != event.table => true,
+            _ => subscriber.sender.send(event.clone()).is_ok(),
+        });
+    }
+}
+
+impl Database {
+    /// A channel of the changes to `table`, or to every table if None, from
+    /// the next one committed on. Dropping the receiver ends the subscription.
+    pub fn subscribe(&mut self, table: Option<&str>) -> Result<Receiver<Event>, DbError> {
+        if let Some(table) = table {
+            self.load_table(table)?;
+        }
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(Subscriber { table: table.map(str::to_string), sender });
+        Ok(receiver)
+    }
+}
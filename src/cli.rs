@@ -0,0 +1,437 @@
+use std::env;
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::LevelFilter;
+
+use rust_db::database::Limits;
+use rust_db::parser::OnError;
+
+use crate::bench::{Mix, Workload};
+use crate::config::{self, ListenerConfig};
+use crate::repl::RowFormat;
+use crate::server::{Auth, Listeners, Security};
+use crate::tls::Tls;
+
+const DATA_DIR_ENV: &str = "RUSTDB_DATA_DIR";
+const ENCRYPTION_KEY_ENV: &str = "RUSTDB_ENCRYPTION_KEY";
+const DEFAULT_DATA_DIR: &str = "data";
+
+const DEFAULT_HOST: &str = "127.0.0.1";
+const DEFAULT_PORT: u16 = 4000;
+
+const DEFAULT_LOG_LEVEL: LevelFilter = LevelFilter::Warn;
+
+pub const USAGE: &str = "Usage: rust_db [serve [--host <addr>] [--port <port>] [--pg-port <port>] [--http <port>] [--auth any|password|token] [--tls-cert <file> --tls-key <file>] [--follow <host:port> [--user <name> | --token] [--tls-ca <file>]]] [--read-only] [--data-dir <dir> | --memory] [<file.rdb>]
+       rust_db [--file <script.sql> | -c <statements>] [--continue-on-error | --single-transaction] [--format table|csv|json|vertical] [--read-only] [--data-dir <dir> | --memory] [<file.rdb>]
+       rust_db migrate [--dir <migrations>] [--data-dir <dir>] [<file.rdb>]
+       rust_db bench [--rows <n>] [--ops <n>] [--mix insert=<n>,lookup=<n>,scan=<n>] [--seed <n>] [--format table|csv|json|vertical] [--data-dir <dir> | --memory] [<file.rdb>]
+       rust_db connect [--user <name> | --token] [--tls-ca <file>] <host:port>
+Any but connect also take [--config <rustdb.toml>] [--log-level off|error|warn|info|debug|trace] [--slow-query-ms <ms> [--slow-query-log <file>]] [--ask-key]";
+
+/// Where the database lives, resolved from flags, environment and defaults.
+#[derive(Debug)]
+pub enum Location {
+    Dir(PathBuf),
+    File(PathBuf),
+    Memory,
+}
+
+/// What to do with the database once it is open.
+#[derive(Debug)]
+pub enum Mode {
+    /// The prompt, or the statements piped to stdin if it is not a terminal.
+    Repl,
+    /// Accept client connections on `addr` instead of reading stdin, plus
+    /// PostgreSQL clients on `pg_addr` and HTTP requests on `http_addr` if set,
+    /// each secured as `listeners` says. With `follow`, replicate the server
+    /// there, logging in as `user` or with a `token`, over TLS trusting
+    /// `tls_ca` if given.
+    Serve {
+        addr: String,
+        pg_addr: Option<String>,
+        http_addr: Option<String>,
+        listeners: Box<ListenerOptions>,
+        follow: Option<String>,
+        user: Option<String>,
+        token: bool,
+        tls_ca: Option<PathBuf>,
+    },
+    /// Apply pending migrations from `dir` (`migrations/` if unset) and exit.
+    Migrate { dir: Option<String> },
+    /// Run the statements in `file` and exit.
+    Script { file: String },
+    /// Run the statements given with `-c` and exit.
+    Command { sql: String },
+    /// Run a synthetic workload and report how fast it went. `scratch` is
+    /// set when the database is a temporary directory, removed afterwards.
+    Bench { workload: Workload, scratch: bool },
+}
+
+/// How one listener is to be secured: the logins it accepts and the
+/// certificate and key files to encrypt with, if any.
+#[derive(Debug, Default)]
+pub struct Listener {
+    pub auth: Auth,
+    pub tls: Option<(PathBuf, PathBuf)>,
+    pub origins: Vec<String>,
+}
+
+impl Listener {
+    fn load(&self) -> Result<Security, String> {
+        let tls = self.tls.as_ref().map(|(cert, key)| Tls::load(cert, key).map(Arc::new)).transpose()?;
+        Ok(Security { auth: self.auth, tls, origins: self.origins.clone() })
+    }
+}
+
+/// How each listener of `serve` is to be secured.
+#[derive(Debug, Default)]
+pub struct ListenerOptions {
+    pub native: Listener,
+    pub pg: Listener,
+    pub http: Listener,
+}
+
+impl ListenerOptions {
+    /// Reads the certificates, for the server to listen with.
+    pub fn load(&self) -> Result<Listeners, String> {
+        Ok(Listeners { native: self.native.load()?, pg: self.pg.load()?, http: self.http.load()? })
+    }
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub location: Location,
+    pub mode: Mode,
+    /// What a script, `-c` or piped input does at a failing statement.
+    pub on_error: OnError,
+    /// How result sets are printed, if given.
+    pub format: Option<RowFormat>,
+    /// The least severe log records shown; `info` shows every statement.
+    pub log_level: LevelFilter,
+    /// Statements taking at least this long are logged as slow.
+    pub slow_query: Option<Duration>,
+    /// Where slow statements are logged instead of stderr.
+    pub slow_query_log: Option<PathBuf>,
+    /// Statements running longer than this are cancelled.
+    pub statement_timeout: Option<Duration>,
+    /// Bytes a query may hold in intermediate results before it fails.
+    pub query_memory: Option<usize>,
+    /// SELECT results kept to be served again, if any.
+    pub result_cache: Option<usize>,
+    pub limits: Limits,
+    /// The passphrase the database is encrypted with, or is to be.
+    pub encryption_key: Option<String>,
+    /// Whether to prompt for the passphrase instead.
+    pub ask_key: bool,
+    /// Whether the database is opened only to be read.
+    pub read_only: bool,
+}
+
+#[derive(Debug)]
+pub enum Command {
+    /// Open a local database.
+    Open(Box<Options>),
+    /// Talk to a server instead of opening anything locally.
+    /// Logs in as `user` or with an access token if `token`, and encrypts
+    /// trusting the certificates in `tls_ca` if given.
+    Connect { addr: String, user: Option<String>, token: bool, tls_ca: Option<PathBuf> },
+}
+
+/// Parses command-line arguments (without the program name), filling in
+/// what they leave out from the config file. Flags take precedence over
+/// `RUSTDB_DATA_DIR`, which takes precedence over the config file, which
+/// takes precedence over the defaults (`data/`).
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut args = args.into_iter().peekable();
+    if args.next_if(|arg| arg == "connect").is_some() {
+        let mut addr = None;
+        let mut user = None;
+        let mut token = false;
+        let mut tls_ca = None;
+        while let Some(arg) = args.next() {
+            if arg == "--user" {
+                user = Some(args.next().ok_or("--user requires a name")?);
+            } else if arg == "--token" {
+                token = true;
+            } else if arg == "--tls-ca" {
+                tls_ca = Some(PathBuf::from(args.next().ok_or("--tls-ca requires a file")?));
+            } else if arg.starts_with('-') {
+                return Err(format!("Unknown option '{}'", arg));
+            } else if addr.is_none() {
+                addr = Some(arg);
+            } else {
+                return Err(format!("Unexpected argument '{}'", arg));
+            }
+        }
+        let addr = addr.ok_or("connect requires a server address (host:port)")?;
+        if token && user.is_some() {
+            return Err("Use either --user or --token, not both".to_string());
+        }
+        return Ok(Command::Connect { addr, user, token, tls_ca });
+    }
+
+    let mut data_dir: Option<PathBuf> = None;
+    let mut file: Option<PathBuf> = None;
+    let mut memory = false;
+    let mut host: Option<String> = None;
+    let mut port: Option<u16> = None;
+    let mut pg_port: Option<u16> = None;
+    let mut http_port: Option<u16> = None;
+    let mut follow: Option<String> = None;
+    let mut user: Option<String> = None;
+    let mut token = false;
+    let mut tls_ca: Option<PathBuf> = None;
+    let mut auth: Option<String> = None;
+    let mut tls_cert: Option<PathBuf> = None;
+    let mut tls_key: Option<PathBuf> = None;
+    let mut migrations_dir: Option<String> = None;
+    let mut script: Option<String> = None;
+    let mut command: Option<String> = None;
+    let mut continue_on_error = false;
+    let mut single_transaction = false;
+    let mut format: Option<RowFormat> = None;
+    let mut config_path: Option<PathBuf> = None;
+    let mut log_level: Option<LevelFilter> = None;
+    let mut slow_query: Option<Duration> = None;
+    let mut slow_query_log: Option<PathBuf> = None;
+    let mut ask_key = false;
+    let mut read_only = false;
+    let mut workload = Workload::default();
+    let mut workload_given = false;
+
+    let serve = args.next_if(|arg| arg == "serve").is_some();
+    let migrate = !serve && args.next_if(|arg| arg == "migrate").is_some();
+    let bench = !serve && !migrate && args.next_if(|arg| arg == "bench").is_some();
+    while let Some(arg) = args.next() {
+        if arg == "--rows" || arg == "--ops" || arg == "--seed" {
+            let value = args.next().ok_or_else(|| format!("{} requires a number", arg))?;
+            let n = value.parse().map_err(|_| format!("Invalid number '{}'", value))?;
+            match arg.as_str() {
+                "--rows" => workload.rows = n as usize,
+                "--ops" => workload.ops = n as usize,
+                _ => workload.seed = n,
+            }
+            workload_given = true;
+        } else if arg == "--mix" {
+            workload.mix = Mix::parse(&args.next().ok_or("--mix requires weights, like insert=20,lookup=70,scan=10")?)?;
+            workload_given = true;
+        } else if arg == "--dir" {
+            migrations_dir = Some(args.next().ok_or("--dir requires a directory")?);
+        } else if arg == "--file" {
+            script = Some(args.next().ok_or("--file requires a script")?);
+        } else if arg == "-c" {
+            command = Some(args.next().ok_or("-c requires statements to run")?);
+        } else if arg == "--format" {
+            let value = args.next().ok_or("--format requires table, csv, json or vertical")?;
+            format = Some(RowFormat::parse(&value).ok_or_else(|| format!("Unknown output format '{}'", value))?);
+        } else if arg == "--log-level" {
+            let value = args.next().ok_or("--log-level requires a level")?;
+            log_level = Some(parse_log_level(&value)?);
+        } else if arg == "--slow-query-ms" {
+            let value = args.next().ok_or("--slow-query-ms requires a number of milliseconds")?;
+            let ms = value.parse().map_err(|_| format!("Invalid number of milliseconds '{}'", value))?;
+            slow_query = Some(Duration::from_millis(ms));
+        } else if arg == "--slow-query-log" {
+            slow_query_log = Some(PathBuf::from(args.next().ok_or("--slow-query-log requires a file")?));
+        } else if arg == "--config" {
+            config_path = Some(PathBuf::from(args.next().ok_or("--config requires a file")?));
+        } else if arg == "--ask-key" {
+            ask_key = true;
+        } else if arg == "--read-only" {
+            read_only = true;
+        } else if arg == "--continue-on-error" {
+            continue_on_error = true;
+        } else if arg == "--single-transaction" {
+            single_transaction = true;
+        } else if arg == "--host" {
+            host = Some(args.next().ok_or("--host requires an address")?);
+        } else if arg == "--port" {
+            let value = args.next().ok_or("--port requires a number")?;
+            port = Some(value.parse().map_err(|_| format!("Invalid port '{}'", value))?);
+        } else if arg == "--pg-port" {
+            let value = args.next().ok_or("--pg-port requires a number")?;
+            pg_port = Some(value.parse().map_err(|_| format!("Invalid port '{}'", value))?);
+        } else if arg == "--http" {
+            let value = args.next().ok_or("--http requires a port number")?;
+            http_port = Some(value.parse().map_err(|_| format!("Invalid port '{}'", value))?);
+        } else if arg == "--follow" {
+            follow = Some(args.next().ok_or("--follow requires a server address (host:port)")?);
+        } else if arg == "--user" {
+            user = Some(args.next().ok_or("--user requires a name")?);
+        } else if arg == "--token" {
+            token = true;
+        } else if arg == "--tls-ca" {
+            tls_ca = Some(PathBuf::from(args.next().ok_or("--tls-ca requires a file")?));
+        } else if arg == "--auth" {
+            auth = Some(args.next().ok_or("--auth requires any, password or token")?);
+        } else if arg == "--tls-cert" {
+            tls_cert = Some(PathBuf::from(args.next().ok_or("--tls-cert requires a file")?));
+        } else if arg == "--tls-key" {
+            tls_key = Some(PathBuf::from(args.next().ok_or("--tls-key requires a file")?));
+        } else if let Some(dir) = arg.strip_prefix("--data-dir=") {
+            data_dir = Some(PathBuf::from(dir));
+        } else if arg == "--data-dir" {
+            let dir = args.next().ok_or("--data-dir requires a directory")?;
+            data_dir = Some(PathBuf::from(dir));
+        } else if arg == "--memory" {
+            memory = true;
+        } else if arg.starts_with('-') {
+            return Err(format!("Unknown option '{}'", arg));
+        } else if file.is_none() {
+            file = Some(PathBuf::from(arg));
+        } else {
+            return Err(format!("Unexpected argument '{}'", arg));
+        }
+    }
+
+    if memory && (file.is_some() || data_dir.is_some()) {
+        return Err("--memory cannot be combined with a data directory or file".to_string());
+    }
+
+    if !serve && (host.is_some() || port.is_some() || pg_port.is_some() || http_port.is_some() || follow.is_some()) {
+        return Err("--host, --port, --pg-port, --http and --follow only apply to serve".to_string());
+    }
+    if !serve && (auth.is_some() || tls_cert.is_some() || tls_key.is_some()) {
+        return Err("--auth, --tls-cert and --tls-key only apply to serve".to_string());
+    }
+    if read_only && (migrate || bench) {
+        return Err("--read-only cannot be combined with migrate or bench".to_string());
+    }
+    if read_only && memory {
+        return Err("--read-only needs a data directory or database file to read".to_string());
+    }
+    if read_only && follow.is_some() {
+        return Err("--read-only cannot be combined with --follow, which writes what the leader sends".to_string());
+    }
+    if (user.is_some() || token || tls_ca.is_some()) && follow.is_none() {
+        return Err("--user, --token and --tls-ca only apply to serve --follow and connect".to_string());
+    }
+    if token && user.is_some() {
+        return Err("Use either --user or --token, not both".to_string());
+    }
+    if !migrate && migrations_dir.is_some() {
+        return Err("--dir only applies to migrate".to_string());
+    }
+    if !bench && workload_given {
+        return Err("--rows, --ops, --mix and --seed only apply to bench".to_string());
+    }
+    if (serve || migrate) && (script.is_some() || command.is_some() || continue_on_error || format.is_some()) {
+        return Err("--file, -c, --continue-on-error and --format cannot be combined with serve or migrate".to_string());
+    }
+    if bench && (script.is_some() || command.is_some() || continue_on_error) {
+        return Err("--file, -c and --continue-on-error cannot be combined with bench".to_string());
+    }
+    if single_transaction && script.is_none() && command.is_none() {
+        return Err("--single-transaction only applies to --file and -c".to_string());
+    }
+    if single_transaction && continue_on_error {
+        return Err("Use either --single-transaction or --continue-on-error, not both".to_string());
+    }
+    if script.is_some() && command.is_some() {
+        return Err("Use either --file or -c, not both".to_string());
+    }
+
+    let config = config::load(config_path.as_deref())?;
+    let format = match (format, &config.format) {
+        (None, Some(value)) => Some(RowFormat::parse(value).ok_or_else(|| format!("Unknown output format '{}' in the config file", value))?),
+        (format, _) => format,
+    };
+    let log_level = match (log_level, &config.log_level) {
+        (Some(level), _) => level,
+        (None, Some(value)) => parse_log_level(value)?,
+        (None, None) => DEFAULT_LOG_LEVEL,
+    };
+    let slow_query = slow_query.or(config.slow_query_ms.map(Duration::from_millis));
+    let slow_query_log = slow_query_log.or(config.slow_query_log);
+    if slow_query_log.is_some() && slow_query.is_none() {
+        return Err("A slow-query log requires a threshold (--slow-query-ms or slow_query_ms)".to_string());
+    }
+    let statement_timeout = config.query.statement_timeout_ms.filter(|&ms| ms > 0).map(Duration::from_millis);
+    let query_memory = config.query.max_memory_mb.filter(|&mb| mb > 0).map(|mb| mb.saturating_mul(1024 * 1024));
+    // Like the data directory, the environment wins over the config file
+    let encryption_key = env::var(ENCRYPTION_KEY_ENV).ok().or(config.encryption_key);
+    let result_cache = config.cache.results.filter(|&results| results > 0);
+    let defaults = Limits::default();
+    let limits = Limits {
+        cache_tables: config.cache.tables.unwrap_or(defaults.cache_tables),
+        checkpoint_bytes: config.wal.checkpoint_bytes.unwrap_or(defaults.checkpoint_bytes),
+        max_recursion: config.query.max_recursion.unwrap_or(defaults.max_recursion),
+        max_database_bytes: config.quota.max_database_mb.filter(|&mb| mb > 0).map(|mb| mb.saturating_mul(1024 * 1024)),
+    };
+
+    // Unless told where, a benchmark gets a directory of its own rather than
+    // writing to the usual data
+    let scratch = bench && !memory && file.is_none() && data_dir.is_none();
+    let location = match (file, data_dir) {
+        _ if memory => Location::Memory,
+        _ if scratch => Location::Dir(env::temp_dir().join(format!("rustdb-bench-{}", process::id()))),
+        (Some(_), Some(_)) => return Err("Use either --data-dir or a database file, not both".to_string()),
+        (Some(file), None) => Location::File(file),
+        (None, Some(dir)) => Location::Dir(dir),
+        (None, None) => Location::Dir(
+            env::var_os(DATA_DIR_ENV).map(PathBuf::from)
+                .or(config.data_dir)
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_DATA_DIR)),
+        ),
+    };
+    let mode = if serve {
+        // A flag wins over the listener's own section, which wins over [server]
+        let listener = |section: &ListenerConfig| -> Result<Listener, String> {
+            let auth = match auth.as_deref().or(section.auth.as_deref()).or(config.server.auth.as_deref()) {
+                Some(name) => Auth::parse(name).ok_or_else(|| format!("Unknown authentication '{}'; use any, password or token", name))?,
+                None => Auth::default(),
+            };
+            let cert = tls_cert.as_ref().or(section.tls_cert.as_ref()).or(config.server.tls_cert.as_ref());
+            let key = tls_key.as_ref().or(section.tls_key.as_ref()).or(config.server.tls_key.as_ref());
+            let tls = match (cert, key) {
+                (Some(cert), Some(key)) => Some((cert.clone(), key.clone())),
+                (None, None) => None,
+                _ => return Err("TLS requires both a certificate and its private key (--tls-cert and --tls-key)".to_string()),
+            };
+            Ok(Listener { auth, tls, origins: section.allowed_origins.clone() })
+        };
+        if !config.server.native.allowed_origins.is_empty() || !config.server.pg.allowed_origins.is_empty() {
+            return Err("allowed_origins only applies to [server.http]".to_string());
+        }
+        let listeners = Box::new(ListenerOptions {
+            native: listener(&config.server.native)?,
+            pg: listener(&config.server.pg)?,
+            http: listener(&config.server.http)?,
+        });
+        let host = host.or(config.server.host);
+        let host = host.as_deref().unwrap_or(DEFAULT_HOST);
+        Mode::Serve {
+            addr: format!("{}:{}", host, port.or(config.server.port).unwrap_or(DEFAULT_PORT)),
+            pg_addr: pg_port.or(config.server.pg_port).map(|port| format!("{}:{}", host, port)),
+            http_addr: http_port.or(config.server.http_port).map(|port| format!("{}:{}", host, port)),
+            listeners,
+            follow,
+            user,
+            token,
+            tls_ca,
+        }
+    } else if migrate {
+        Mode::Migrate { dir: migrations_dir }
+    } else if bench {
+        Mode::Bench { workload, scratch }
+    } else if let Some(file) = script {
+        Mode::Script { file }
+    } else if let Some(sql) = command {
+        Mode::Command { sql }
+    } else {
+        Mode::Repl
+    };
+    let on_error = match (continue_on_error, single_transaction) {
+        (true, _) => OnError::Continue,
+        (false, true) => OnError::RollBack,
+        (false, false) => OnError::Stop,
+    };
+    Ok(Command::Open(Box::new(Options { location, mode, on_error, format, log_level, slow_query, slow_query_log, statement_timeout, query_memory, result_cache, limits, encryption_key, ask_key, read_only })))
+}
+
+fn parse_log_level(value: &str) -> Result<LevelFilter, String> {
+    value.parse().map_err(|_| format!("Unknown log level '{}'", value))
+}
@@ -0,0 +1,124 @@
+use std::env;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use rust_db::parser::{self, Statement};
+use rust_db::protocol::{self, Frame};
+
+use crate::repl::LineEditor;
+use crate::tls::{self, Connection};
+
+const PASSWORD_ENV: &str = "RUSTDB_PASSWORD";
+const TOKEN_ENV: &str = "RUSTDB_TOKEN";
+
+/// What a client logs in to a server with.
+pub enum Credentials {
+    Password { user: String, password: String },
+    Token(String),
+}
+
+impl Credentials {
+    /// The credentials to log in as `user`, or with an access token if
+    /// `token`, read from the environment or a prompt. None for neither.
+    pub fn read(user: Option<&str>, token: bool) -> io::Result<Option<Credentials>> {
+        if token {
+            return match env::var(TOKEN_ENV) {
+                Ok(token) => Ok(Some(Credentials::Token(token))),
+                Err(_) => rpassword::prompt_password("Access token: ").map(|token| Some(Credentials::Token(token))),
+            };
+        }
+        user.map(|user| Ok(Credentials::Password { user: user.to_string(), password: password(user)? })).transpose()
+    }
+
+    pub fn frame(&self) -> Frame {
+        match self {
+            Credentials::Password { user, password } => Frame::Login { user: user.clone(), password: password.clone() },
+            Credentials::Token(token) => Frame::Token(token.clone()),
+        }
+    }
+}
+
+/// A REPL against a remote server, logging in with `login` if given, over
+/// TLS if given the certificates to trust. Returns false if the connection
+/// or the login failed.
+pub fn run(addr: &str, login: Option<Credentials>, tls_ca: Option<&Path>) -> bool {
+    let stream = match tls::connect(addr, tls_ca) {
+        Ok(stream) => stream,
+        Err(e) => {
+            println!("Error: Could not connect to {}: {}", addr, e);
+            return false;
+        }
+    };
+
+    match session(stream, login) {
+        Ok(ok) => ok,
+        Err(e) => {
+            println!("Error: Connection lost: {}", e);
+            false
+        }
+    }
+}
+
+fn session(stream: Connection, login: Option<Credentials>) -> io::Result<bool> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    if let Some(login) = login {
+        protocol::write_frame(&mut writer, &login.frame())?;
+        writer.flush()?;
+        if !answer(&mut reader)? {
+            return Ok(false);
+        }
+    }
+    println!("Connected to {}", writer.get_ref().peer_addr()?);
+
+    let mut editor = LineEditor::new(None).map_err(io::Error::other)?;
+    loop {
+        // End of input just hangs up; the server rolls back an open transaction
+        let Some(input) = editor.read_line("dbms> ").map_err(io::Error::other)? else {
+            return Ok(true);
+        };
+        editor.remember(&input);
+        if input.trim().is_empty() {
+            continue;
+        }
+
+        protocol::write_frame(&mut writer, &Frame::Query(input.trim_end().to_string()))?;
+        writer.flush()?;
+        answer(&mut reader)?;
+
+        // The server has already closed its end after answering EXIT
+        if matches!(parser::parse(&input), Ok(Statement::Exit)) {
+            return Ok(true);
+        }
+    }
+}
+
+/// The password to log in as `user` with. Scripts can pass it in the
+/// environment instead of a prompt.
+fn password(user: &str) -> io::Result<String> {
+    match env::var(PASSWORD_ENV) {
+        Ok(password) => Ok(password),
+        Err(_) => rpassword::prompt_password(format!("Password for {}: ", user)),
+    }
+}
+
+/// Prints the server's answer as it arrives, up to `Done`. Returns false if
+/// it reported an error.
+fn answer(reader: &mut impl Read) -> io::Result<bool> {
+    let mut ok = true;
+    loop {
+        match protocol::read_frame(reader)? {
+            Some(Frame::Output(text)) => println!("{}", text),
+            Some(Frame::Error(message)) => {
+                println!("Error: {}", message);
+                ok = false;
+            }
+            Some(Frame::Done) => return Ok(ok),
+            Some(Frame::Query(_) | Frame::Login { .. } | Frame::Token(_) | Frame::Replicate | Frame::Change(_)) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "unexpected message from server"));
+            }
+            None => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "server closed the connection")),
+        }
+    }
+}
@@ -0,0 +1,67 @@
+//! Collations of string columns, chosen per column with `<col>:string
+//! COLLATE <collation>`: how its values compare in conditions, sort in
+//! ORDER BY, fall into groups in GROUP BY and clash in unique indexes. `binary`, the default, compares the
+//! bytes as they are. `nocase` compares ignoring case, so 'Alice' and
+//! 'alice' are one value. `unicode` sorts as a reader expects, letters
+//! with accents next to the plain letter ('é' by 'e', not after 'z') and
+//! case second, while values still only equal themselves.
+//!
+//! Each collation maps a value to a key that sorts and compares as the
+//! collation says, and index entries are kept under those keys.
+
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::DataType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Collation {
+    #[default]
+    Binary,
+    NoCase,
+    Unicode,
+}
+
+impl Collation {
+    pub const NAMES: [&str; 3] = ["binary", "nocase", "unicode"];
+
+    pub fn parse(name: &str) -> Option<Collation> {
+        match name.to_ascii_lowercase().as_str() {
+            "binary" => Some(Collation::Binary),
+            "nocase" => Some(Collation::NoCase),
+            "unicode" => Some(Collation::Unicode),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Collation::Binary => "binary",
+            Collation::NoCase => "nocase",
+            Collation::Unicode => "unicode",
+        }
+    }
+
+    pub fn is_binary(&self) -> bool {
+        *self == Collation::Binary
+    }
+
+    /// What `value` compares and sorts as under the collation. Only strings
+    /// change.
+    pub fn key<'a>(&self, value: &'a DataType) -> Cow<'a, DataType> {
+        match (self, value) {
+            (Collation::NoCase, DataType::String(text)) => Cow::Owned(DataType::String(text.to_lowercase())),
+            // The letters without accents or case first, then the value itself
+            // to tell apart those that only differ there
+            (Collation::Unicode, DataType::String(text)) => {
+                let base: String = text.nfd().filter(|c| !is_combining_mark(*c)).collect::<String>().to_lowercase();
+                Cow::Owned(DataType::String(format!("{}\u{0}{}", base, text)))
+            }
+            _ => Cow::Borrowed(value),
+        }
+    }
+}
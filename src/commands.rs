@@ -0,0 +1,2341 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+
+use prettytable::{format, Table as PTable, Row, Cell};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use rust_db::budget;
+use rust_db::catalog;
+use rust_db::cdc;
+use rust_db::databases::DataRoot;
+use rust_db::dependencies::Dependents;
+use rust_db::expr::Expr;
+use rust_db::external::External;
+use rust_db::formats::{self, Format};
+use rust_db::functions::Functions;
+use rust_db::history;
+use rust_db::index::{IndexDef, IndexKind};
+use rust_db::interrupt;
+use rust_db::migrations;
+use rust_db::parser::{self, ConflictAction, Cte, InsertValue, OnConflict, OnError, Order, Predicate, SetValue, Source, Statement, TableRef};
+use rust_db::partition::{self, PartitionBy, Partitioning};
+use rust_db::planner;
+use rust_db::profile;
+use rust_db::query::Cursor;
+use rust_db::recovery;
+use rust_db::storage::Compression;
+use rust_db::table::present;
+use rust_db::triggers::{Event, Timing, Trigger};
+use rust_db::timestamps;
+use rust_db::tombstones;
+use rust_db::ttl;
+use rust_db::users::{self, Privilege, Requirement};
+use rust_db::variables::{self, Variables};
+use rust_db::views::Freshness;
+use rust_db::wal::{Synchronous, WalOp};
+use rust_db::time;
+use rust_db::{parse_value, Database, DataType, DbError, Rows, Table};
+
+use crate::result_cache::{self, Recording, ResultCache};
+use crate::sessions;
+
+/// Where the results of a statement go: the terminal or a client connection.
+pub trait Output {
+    fn line(&mut self, text: &str);
+    fn error(&mut self, message: &str);
+    /// An error from the database, shown with its code unless the output
+    /// has somewhere to put the code apart from the message.
+    fn failure(&mut self, error: &DbError) {
+        self.error(&format!("[{}] {}", error.code(), error));
+    }
+    /// A result set, every value already formatted, with the type of each
+    /// column as in a schema.
+    fn rows(&mut self, columns: &[&str], types: &[&str], rows: Vec<Vec<String>>);
+    /// The number of rows a statement changed, for the query log.
+    fn affected(&mut self, _rows: usize) {}
+}
+
+/// Draws a result set as a bordered table. Values longer than
+/// `MAX_CELL_WIDTH` columns on screen are cut short with an ellipsis, and
+/// `int` and `float` columns are aligned to the right.
+pub fn render(columns: &[&str], types: &[&str], rows: Vec<Vec<String>>) -> PTable {
+    let style = |i: usize| if matches!(types[i], "int" | "float") { "r" } else { "l" };
+
+    let mut p_table = PTable::new();
+    p_table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    let header: Vec<Cell> = columns.iter().enumerate()
+        .map(|(i, col)| Cell::new(col).style_spec(&format!("bFg{}", style(i))))
+        .collect();
+    p_table.set_titles(Row::new(header));
+    for row in rows {
+        let cells = row.iter().enumerate()
+            .map(|(i, val)| Cell::new(&truncate(val, MAX_CELL_WIDTH)).style_spec(style(i)))
+            .collect();
+        p_table.add_row(Row::new(cells));
+    }
+    p_table
+}
+
+/// Cuts `text` down to `width` columns on screen, counting wide characters
+/// as two, with `…` marking the cut. Each line is cut on its own.
+fn truncate(text: &str, width: usize) -> String {
+    let lines: Vec<String> = text.lines()
+        .map(|line| {
+            if line.width() <= width {
+                return line.to_string();
+            }
+            let mut cut = String::new();
+            let mut used = 0;
+            for c in line.chars() {
+                let w = c.width().unwrap_or(0);
+                if used + w >= width {
+                    break;
+                }
+                cut.push(c);
+                used += w;
+            }
+            cut.push('…');
+            cut
+        })
+        .collect();
+    lines.join("\n")
+}
+
+/// Keeps only the first error, for statements run on the user's behalf
+/// whose own output is not shown.
+#[derive(Default)]
+pub struct Quiet {
+    pub error: Option<String>,
+}
+
+impl Output for Quiet {
+    fn line(&mut self, _text: &str) {}
+
+    fn error(&mut self, message: &str) {
+        self.error.get_or_insert_with(|| message.to_string());
+    }
+
+    fn rows(&mut self, _columns: &[&str], _types: &[&str], _rows: Vec<Vec<String>>) {}
+}
+
+/// Passes everything on, noting which line of a script (and which file,
+/// if it came from one) an error comes from.
+pub struct Located<'a> {
+    out: &'a mut dyn Output,
+    path: Option<&'a str>,
+    line: usize,
+    pub failed: bool,
+}
+
+impl<'a> Located<'a> {
+    pub fn new(out: &'a mut dyn Output, path: Option<&'a str>, line: usize) -> Located<'a> {
+        Located { out, path, line, failed: false }
+    }
+}
+
+impl Output for Located<'_> {
+    fn line(&mut self, text: &str) {
+        self.out.line(text);
+    }
+
+    fn error(&mut self, message: &str) {
+        match self.path {
+            Some(path) => self.out.error(&format!("{}, line {}: {}", path, self.line, message)),
+            None => self.out.error(&format!("Line {}: {}", self.line, message)),
+        }
+        self.failed = true;
+    }
+
+    fn rows(&mut self, columns: &[&str], types: &[&str], rows: Vec<Vec<String>>) {
+        self.out.rows(columns, types, rows);
+    }
+
+    fn affected(&mut self, rows: usize) {
+        self.out.affected(rows);
+    }
+}
+
+/// Passes everything on, counting the rows returned or changed and noting
+/// whether the statement failed, for the query log.
+struct Logged<'a> {
+    out: &'a mut dyn Output,
+    rows: usize,
+    failed: bool,
+}
+
+impl Output for Logged<'_> {
+    fn line(&mut self, text: &str) {
+        self.out.line(text);
+    }
+
+    fn error(&mut self, message: &str) {
+        self.out.error(message);
+        self.failed = true;
+    }
+
+    fn failure(&mut self, error: &DbError) {
+        self.out.failure(error);
+        self.failed = true;
+    }
+
+    fn rows(&mut self, columns: &[&str], types: &[&str], rows: Vec<Vec<String>>) {
+        self.rows += rows.len();
+        self.out.rows(columns, types, rows);
+    }
+
+    fn affected(&mut self, rows: usize) {
+        self.rows += rows;
+        self.out.affected(rows);
+    }
+}
+
+/// `text`, the statement parsed as `statement`, as it may be shown in the
+/// query log and SHOW PROCESSLIST: passwords stay out.
+pub fn shown(statement: &Statement, text: &str) -> String {
+    match statement {
+        Statement::CreateUser { name, .. } => format!("CREATE USER {} PASSWORD ...", name),
+        Statement::Rekey(Some(_)) => "REKEY ...".to_string(),
+        _ => text.trim().to_string(),
+    }
+}
+
+macro_rules! say {
+    ($out:expr, $($arg:tt)*) => { $out.line(&format!($($arg)*)) };
+}
+
+/// The log target every statement is recorded under, at info level.
+pub const QUERY_LOG: &str = "rust_db::query";
+/// The log target slow statements are also recorded under, at warn level.
+pub const SLOW_QUERY_LOG: &str = "rust_db::slow_query";
+
+// Wider values are cut short when drawn as a table
+const MAX_CELL_WIDTH: usize = 40;
+
+// Triggers that keep firing each other stop here instead of recursing forever
+const MAX_TRIGGER_DEPTH: usize = 16;
+
+/// The open database, plus the data directory holding the other databases
+/// it can switch to (none for a single file or `--memory`).
+pub struct Engine {
+    pub db: Database,
+    root: Option<DataRoot>,
+    current: String,
+    /// Statements taking at least this long go to the slow-query log.
+    pub slow_query: Option<Duration>,
+    /// Statements running longer than this are cancelled.
+    pub statement_timeout: Option<Duration>,
+    /// Whether each change is committed on its own. When off, the first
+    /// change outside a transaction begins one, which lasts until COMMIT or
+    /// ROLLBACK.
+    pub autocommit: bool,
+    /// Whether the changes of the statements of an input line are fsynced
+    /// each as its statement ends, or together once the line has run.
+    pub synchronous: Synchronous,
+    /// Bytes a query may hold in intermediate results before it fails.
+    pub query_memory: Option<usize>,
+    /// Cursors DECLAREd and not yet closed, by name.
+    cursors: HashMap<String, Cursor>,
+    /// Whether COPY FROM STDIN without rows may read the process's standard
+    /// input, which is free when the statements come from `-c` or `--file`.
+    pub read_stdin: bool,
+    /// The leader this server follows, if it is a follower, which takes no
+    /// writes of its own until PROMOTE.
+    pub following: Option<String>,
+    /// SELECT results kept to be served again, if turned on.
+    pub results: Option<ResultCache>,
+    /// The session's variables, substituted for `@name` in its statements.
+    pub variables: Variables,
+}
+
+impl Engine {
+    pub fn new(db: Database, root: Option<DataRoot>, current: &str) -> Engine {
+        Engine { db, root, current: current.to_string(), slow_query: None, statement_timeout: None, autocommit: true, synchronous: Synchronous::default(), query_memory: None, cursors: HashMap::new(), read_stdin: false, following: None, results: None, variables: Variables::new() }
+    }
+
+    /// Parses one statement, its variables substituted first.
+    pub fn parse(&self, text: &str) -> Result<Statement, DbError> {
+        parser::parse(&variables::substitute(text, &self.variables)?)
+    }
+
+    /// Runs one statement on behalf of `user`, reporting its results and
+    /// errors to `out`. Without a user (the local REPL, or a server nobody
+    /// has to log in to) every statement is allowed. `text` is what the
+    /// statement was parsed from, for the query log. Returns false for EXIT,
+    /// which is left to the caller.
+    pub fn execute(&mut self, out: &mut dyn Output, statement: Statement, text: &str, user: Option<&str>) -> bool {
+        let text = shown(&statement, text);
+        let started = Instant::now();
+        let mut logged = Logged { out, rows: 0, failed: false };
+        interrupt::set_timeout(self.statement_timeout);
+        budget::start(self.query_memory);
+        let keep_going = self.run(&mut logged, statement, &text, user);
+        interrupt::set_timeout(None);
+
+        let elapsed = started.elapsed();
+        let ms = elapsed.as_secs_f64() * 1000.0;
+        let status = if logged.failed { "failed" } else { "ok" };
+        let user = user.unwrap_or("-");
+        log::info!(target: QUERY_LOG, "{:.3} ms, {} row(s), {}, user {}: {}", ms, logged.rows, status, user, text);
+        if self.slow_query.is_some_and(|threshold| elapsed >= threshold) {
+            log::warn!(target: SLOW_QUERY_LOG, "{:.3} ms, {} row(s), {}, user {}: {}", ms, logged.rows, status, user, text);
+        }
+        keep_going
+    }
+
+    /// `execute` for a statement that only reads, run beside another
+    /// session's open transaction: it reads what has been committed. Its
+    /// result is not cached, as the transaction would not read the same.
+    pub fn execute_committed(&mut self, out: &mut dyn Output, statement: Statement, text: &str, user: Option<&str>) -> bool {
+        let suspended = self.db.suspend();
+        let results = self.results.take();
+        let keep_going = self.execute(out, statement, text, user);
+        self.results = results;
+        self.db.resume(suspended);
+        keep_going
+    }
+
+    /// Starts running the statements of one input line. With `synchronous`
+    /// batched their changes are fsynced together, at `end_line`.
+    pub fn begin_line(&mut self) {
+        if self.synchronous == Synchronous::Batched {
+            self.db.begin_batch();
+        }
+    }
+
+    /// Makes the changes of the line's statements durable. Returns false,
+    /// reporting why to `out`, if that fails.
+    pub fn end_line(&mut self, out: &mut dyn Output) -> bool {
+        match self.db.end_batch() {
+            Ok(()) => true,
+            Err(e) => {
+                out.failure(&e);
+                false
+            }
+        }
+    }
+
+    /// Deletes the expired rows of every table in memory, unless this is a
+    /// follower, which deletes them as its leader does, or a transaction is
+    /// open. Returns the number deleted.
+    pub fn sweep_expired(&mut self) -> Result<usize, DbError> {
+        if self.following.is_some() || self.db.in_transaction() {
+            return Ok(0);
+        }
+        self.db.sweep_expired()
+    }
+
+    /// A channel of the changes committed to `table` from now on, for
+    /// SUBSCRIBE, if `user` may read it.
+    pub fn subscribe(&mut self, table: &str, user: Option<&str>) -> Result<Receiver<cdc::Event>, DbError> {
+        if let Some(user) = user {
+            self.authorize(user, &Statement::Subscribe(table.to_string()))?;
+        }
+        self.db.subscribe(Some(table))
+    }
+
+    fn run(&mut self, out: &mut dyn Output, statement: Statement, text: &str, user: Option<&str>) -> bool {
+        if self.db.in_transaction() && !statement.allowed_in_transaction() {
+            out.failure(&DbError::TransactionActive);
+            return true;
+        }
+        if let Some(user) = user && let Err(e) = self.authorize(user, &statement) {
+            out.failure(&e);
+            return true;
+        }
+        if let Some(leader) = &self.following && !statement.is_read_only() {
+            out.failure(&DbError::ReadOnly(format!("this server follows {}; write there, or PROMOTE this one", leader)));
+            return true;
+        }
+        if self.db.is_read_only() && !statement.is_read_only() {
+            out.failure(&DbError::ReadOnly("it was opened with --read-only".to_string()));
+            return true;
+        }
+        if !self.autocommit && !self.db.in_transaction() && !statement.is_read_only() && statement.allowed_in_transaction()
+            && let Err(e) = self.db.begin()
+        {
+            out.failure(&e);
+            return true;
+        }
+        // Expired rows go before a write sees them, so their keys are free again
+        if let Requirement::Table(table, Privilege::Insert | Privilege::Update | Privilege::Delete) = users::requirement(&statement)
+            && let Err(e) = self.db.purge_expired(table)
+            && !matches!(e, DbError::TableNotFound { .. })
+        {
+            out.failure(&e);
+            return true;
+        }
+        if let Some(results) = &mut self.results {
+            if matches!(statement, Statement::Use(_) | Statement::DropDatabase(_)) {
+                results.clear();
+            } else if let Some(key) = result_cache::key(&statement, text) {
+                return self.run_cached(out, statement, key, user);
+            }
+        }
+        self.dispatch(out, statement, user)
+    }
+
+    // Serves a query from the result cache, or runs it and keeps its result
+    fn run_cached(&mut self, out: &mut dyn Output, statement: Statement, key: String, user: Option<&str>) -> bool {
+        let results = self.results.as_mut().expect("only called with the result cache on");
+        if let Some(shown) = results.get(&key, &self.db) {
+            result_cache::replay(out, shown);
+            return true;
+        }
+        let at = self.db.version();
+        self.db.record_reads();
+        let mut recording = Recording::new(out);
+        let keep_going = self.dispatch(&mut recording, statement, user);
+        let reads = self.db.take_reads();
+        if !recording.failed {
+            let results = self.results.as_mut().expect("only called with the result cache on");
+            results.put(key, recording.shown, reads, at);
+        }
+        keep_going
+    }
+
+    fn dispatch(&mut self, out: &mut dyn Output, statement: Statement, user: Option<&str>) -> bool {
+        let db = &mut self.db;
+        let statement = match db.resolve_subqueries(statement) {
+            Ok(statement) => statement,
+            Err(e) => {
+                out.failure(&e);
+                return true;
+            }
+        };
+        match statement {
+            Statement::CreateTable { name, mut columns, generated, primary_key, temp, partition_by, ttl, soft_delete, timestamps, engine, collations, sequences } => {
+                if timestamps {
+                    timestamps::add_columns(&mut columns);
+                }
+                let mut table = Table::new(&name, columns, primary_key, db.last_lsn(), db.now());
+                table.collations = collations.into_iter().filter(|(_, collation)| !collation.is_binary()).collect();
+                table.generated = generated.into_iter().collect();
+                table.ttl = ttl;
+                table.tombstones = soft_delete.then(Vec::new);
+                table.timestamps = timestamps;
+                table.engine = engine;
+                table.sequences = sequences.into_iter().collect();
+                create_table(out, db, table, temp, partition_by)
+            }
+            Statement::CreateExternalTable { name, columns, location, options } => {
+                let table = Table::new(&name, columns, None, db.last_lsn(), db.now());
+                match db.create_external_table(table, External::new(&location, &options)) {
+                    Ok(rows) => say!(out, "External table '{}' created over '{}' ({} row(s))", name, location, rows),
+                    Err(e) => out.failure(&e),
+                }
+            }
+            Statement::AddPartition { table, name, below } => match db.add_partition(&table, &name, below) {
+                Ok(()) => say!(out, "Partition '{}' added to '{}'", name, table),
+                Err(e) => out.failure(&e),
+            },
+            Statement::DropPartition { table, name } => match db.drop_partition(&table, &name) {
+                Ok(()) => say!(out, "Partition '{}' of '{}' dropped", name, table),
+                Err(e) => out.failure(&e),
+            },
+            Statement::SetHistoryRetention { table, retention } => match (db.set_history_retention(&table, retention), retention) {
+                (Ok(()), Some(retention)) => {
+                    say!(out, "Table '{}' keeps its changes for {}", table, history::retention_text(retention).to_lowercase())
+                }
+                (Ok(()), None) => say!(out, "Table '{}' no longer keeps history", table),
+                (Err(e), _) => out.failure(&e),
+            },
+            Statement::SetTtl { table, column } => match db.set_ttl(&table, column.clone()) {
+                Ok(()) => match column {
+                    Some(column) => say!(out, "Rows of table '{}' expire at the time in '{}'", table, column),
+                    None => say!(out, "Rows of table '{}' no longer expire", table),
+                },
+                Err(e) => out.failure(&e),
+            },
+            Statement::SetSoftDelete { table, on } => match db.set_soft_delete(&table, on) {
+                Ok(()) if on => say!(out, "DELETE on table '{}' now marks rows deleted", table),
+                Ok(()) => say!(out, "DELETE on table '{}' now removes rows", table),
+                Err(e) => out.failure(&e),
+            },
+            Statement::SetTimestamps { table, on } => match db.set_timestamps(&table, on) {
+                Ok(()) if on => say!(out, "Table '{}' now keeps when each row was created and updated", table),
+                Ok(()) => say!(out, "Table '{}' no longer keeps when rows are created and updated", table),
+                Err(e) => out.failure(&e),
+            },
+            Statement::SetMaxRows { table, max_rows } => match db.set_max_rows(&table, max_rows) {
+                Ok(()) => match max_rows {
+                    Some(max_rows) => say!(out, "Table '{}' may hold up to {} row(s)", table, max_rows),
+                    None => say!(out, "Table '{}' may hold any number of rows", table),
+                },
+                Err(e) => out.failure(&e),
+            },
+            Statement::SetEngine { table, engine } => match db.set_engine(&table, engine) {
+                Ok(()) => say!(out, "Table '{}' now uses the {} engine", table, engine.name()),
+                Err(e) => out.failure(&e),
+            },
+            Statement::SetDefault { table, column, sequence } => match db.set_default(&table, &column, sequence.clone()) {
+                Ok(()) => match sequence {
+                    Some(sequence) => say!(out, "Column '{}.{}' now takes its DEFAULT from sequence '{}'", table, column, sequence),
+                    None => say!(out, "Column '{}.{}' no longer has a DEFAULT", table, column),
+                },
+                Err(e) => out.failure(&e),
+            },
+            Statement::CreateSequence { name, start, increment } => match db.create_sequence(&name, start, increment) {
+                Ok(()) => say!(out, "Sequence '{}' created", name),
+                Err(e) => out.failure(&e),
+            },
+            Statement::DropSequence { name, cascade } => match db.drop_sequence(&name, cascade) {
+                Ok(()) => say!(out, "Sequence '{}' dropped", name),
+                Err(e) => out.failure(&e),
+            },
+            Statement::Nextval(sequence) => match db.nextval(&sequence) {
+                Ok(value) => out.rows(&["nextval"], &["int"], vec![vec![value.to_string()]]),
+                Err(e) => out.failure(&e),
+            },
+            Statement::Setval { sequence, value } => match db.setval(&sequence, value) {
+                Ok(()) => out.rows(&["setval"], &["int"], vec![vec![value.to_string()]]),
+                Err(e) => out.failure(&e),
+            },
+            Statement::Comment { table, column, text } => {
+                let target = match &column {
+                    Some(column) => format!("column '{}.{}'", table, column),
+                    None => format!("table '{}'", table),
+                };
+                let removed = text.as_ref().is_none_or(String::is_empty);
+                match db.set_comment(&table, column.as_deref(), text) {
+                    Ok(()) if removed => say!(out, "Comment on {} removed", target),
+                    Ok(()) => say!(out, "Comment on {} set", target),
+                    Err(e) => out.failure(&e),
+                }
+            }
+            Statement::CreateIndex { name, table, columns, kind } => {
+                create_index(out, db, &name, &table, columns, kind)
+            }
+            Statement::DropIndex { name, table } => drop_index(out, db, &name, table),
+            Statement::ShowIndexes(table) => show_indexes(out, db, &table),
+            Statement::Reindex(table) => reindex(out, db, &table),
+            Statement::CreateView { name, table, filter, materialized } => {
+                create_view(out, db, &name, &table, filter, materialized)
+            }
+            Statement::DropView { name, cascade } => drop_view(out, db, &name, cascade),
+            Statement::RefreshView(name) => refresh_view(out, db, &name),
+            Statement::RefreshTable(name) => refresh_table(out, db, &name),
+            Statement::CreateTrigger { name, table, timing, event, body } => {
+                create_trigger(out, db, &table, Trigger { name, timing, event, body })
+            }
+            Statement::DropTrigger { name, table } => drop_trigger(out, db, &table, &name),
+
+            Statement::ShowTables => show_tables(out, db),
+            Statement::ShowTableStatus => show_table_status(out, db),
+            Statement::ShowCreateTable(table) => show_create_table(out, db, &table),
+            Statement::Describe(table) => describe(out, db, &table),
+            Statement::ShowSequences => show_sequences(out, db),
+
+            Statement::CreateDatabase(name) => create_database(out, &self.root, &name),
+            Statement::DropDatabase(name) => drop_database(out, &self.root, &self.current, &name),
+            Statement::ShowDatabases => show_databases(out, &self.root, &self.current),
+            Statement::Use(name) => use_database(out, &self.root, db, &mut self.current, &name),
+            Statement::DropTable { name, cascade } => drop_table(out, db, &name, cascade),
+
+            Statement::CreateUser { name, password, superuser } => {
+                create_user(out, db, &name, &password, superuser)
+            }
+            Statement::DropUser(name) => drop_user(out, db, &name),
+            Statement::ShowUsers => show_users(out, db),
+            Statement::CreateToken { name, user } => create_token(out, db, &name, &user),
+            Statement::DropToken(name) => drop_token(out, db, &name),
+            Statement::ShowTokens => show_tokens(out, db),
+            Statement::Grant { privileges, table, user } => grant(out, db, &privileges, &table, &user),
+            Statement::Revoke { privileges, table, user } => revoke(out, db, &privileges, &table, &user),
+            Statement::ShowGrants(user) => show_grants(out, db, &user),
+            // The server answers these itself, without waiting for the engine
+            Statement::ShowProcesslist => sessions::show(out, None, user),
+            Statement::Kill(target) => match sessions::kill(target, None, user) {
+                Ok(()) => say!(out, "Session {} killed", target),
+                Err(e) => out.failure(&e),
+            },
+
+            Statement::Insert { table, values, on_conflict, returning } => {
+                insert_row(out, db, &table, values, on_conflict.as_ref(), returning.as_deref())
+            }
+            Statement::Select { from, joins, columns, filter, order } => {
+                select_rows(out, db, &TableRef::list(from, joins), &columns, &filter, &order)
+            }
+            Statement::With { ctes, query } => match db.with(&ctes, &query) {
+                Ok(result) => show_rows(out, &result),
+                Err(e) => out.failure(&e),
+            },
+            Statement::Declare { name, query } => declare(out, db, &mut self.cursors, name, *query),
+            Statement::Fetch { name, count } => fetch(out, &mut self.cursors, &name, count),
+            Statement::Close(None) => {
+                let count = self.cursors.len();
+                self.cursors.clear();
+                say!(out, "{} cursor(s) closed", count);
+            }
+            Statement::Close(Some(name)) => match self.cursors.remove(&name) {
+                Some(_) => say!(out, "Cursor '{}' closed", name),
+                None => out.failure(&DbError::CursorNotFound(name)),
+            },
+            Statement::Delete { table, filter, returning } => delete_rows(out, db, &table, &filter, returning.as_deref()),
+            Statement::Update { table, set, filter, returning } => update_rows(out, db, &table, &set, &filter, returning.as_deref()),
+            Statement::Undelete { table, filter } => match db.undelete(&table, &filter) {
+                Ok(rows) => {
+                    out.affected(rows);
+                    say!(out, "{} row(s) undeleted", rows)
+                }
+                Err(e) => out.failure(&e),
+            },
+            Statement::Purge { table, filter } => match db.purge_deleted(&table, &filter) {
+                Ok(rows) => {
+                    out.affected(rows);
+                    say!(out, "{} row(s) purged", rows)
+                }
+                Err(e) => out.failure(&e),
+            },
+            Statement::Count(table) => count_rows(out, db, &table),
+            Statement::Analyze(table) => analyze(out, db, &table),
+            Statement::ShowStats(table) => show_stats(out, db, &table),
+            Statement::Explain { statement, analyze: true } => match *statement {
+                Statement::Select { from, joins, columns, filter, order } => {
+                    explain_analyze(out, db, &TableRef::list(from, joins), &columns, &filter, &order)
+                }
+                _ => unreachable!("the parser only accepts EXPLAIN ANALYZE SELECT"),
+            },
+            Statement::Explain { statement, analyze: false } => match *statement {
+                Statement::Select { from, joins, filter, .. } if !joins.is_empty() => {
+                    match db.explain_join(&TableRef::list(from, joins), &filter) {
+                        Ok(plan) => say!(out, "{}", plan),
+                        Err(e) => out.failure(&e),
+                    }
+                }
+                Statement::Select { from, filter, .. } => explain(out, db, &from.table, &from.source, &filter),
+                Statement::Delete { table, filter, .. } => explain(out, db, &table, &Source::Current, &filter),
+                _ => unreachable!("the parser only accepts EXPLAIN SELECT or DELETE"),
+            },
+
+            Statement::Begin => begin(out, db),
+            Statement::Commit => commit(out, db),
+            Statement::Rollback => rollback(out, db),
+            Statement::Savepoint(name) => savepoint(out, db, &name),
+            Statement::RollbackTo(name) => rollback_to(out, db, &name),
+            Statement::Release(name) => release(out, db, &name),
+
+            Statement::Checkpoint => {
+                checkpoint(out, db);
+                say!(out, "Checkpoint complete");
+            }
+            Statement::Flush => {
+                let written = checkpoint(out, db);
+                say!(out, "Flushed {} table(s)", written);
+            }
+            Statement::Vacuum(table) => vacuum(out, db, table.as_deref()),
+
+            Statement::SetCompression(codec) => set_compression(out, db, &codec),
+            Statement::SetWalArchive(dir) => set_wal_archive(out, db, dir),
+            Statement::Rekey(passphrase) => rekey(out, db, passphrase.as_deref()),
+            Statement::SetStatementTimeout(0) => {
+                self.statement_timeout = None;
+                say!(out, "Statements may run for as long as they take");
+            }
+            Statement::SetStatementTimeout(ms) => {
+                self.statement_timeout = Some(Duration::from_millis(ms));
+                say!(out, "Statements running longer than {} ms are cancelled", ms);
+            }
+            Statement::SetAutocommit(on) => {
+                self.autocommit = on;
+                match on {
+                    true if db.in_transaction() => say!(out, "Autocommit on; COMMIT or ROLLBACK the open transaction"),
+                    true => say!(out, "Autocommit on"),
+                    false => say!(out, "Autocommit off; changes wait for COMMIT"),
+                }
+            }
+            Statement::SetSynchronous(synchronous) => {
+                self.synchronous = synchronous;
+                match synchronous {
+                    Synchronous::Full => say!(out, "Synchronous full; each statement's changes reach the disk before it returns"),
+                    Synchronous::Batched => say!(out, "Synchronous batched; the changes of a line's statements reach the disk together"),
+                }
+            }
+            Statement::SetVariable { name, value } => {
+                let column = |column: &str| Err(DbError::InvalidExpression(format!("{}: a variable's value cannot refer to column '{}'", value, column)));
+                match value.eval_with(&column, &db.functions()) {
+                    Ok(value) => {
+                        say!(out, "@{} = {}", name, rust_db::dump::literal(&value));
+                        self.variables.insert(name, value);
+                    }
+                    Err(e) => out.failure(&e),
+                }
+            }
+            Statement::Import { path, table, format } => import(out, db, &path, &table, &format),
+            Statement::Copy { table, format, data } => copy(out, db, &table, &format, data, self.read_stdin),
+            Statement::Export { query, path, format } => match *query {
+                Statement::Select { from, joins, columns, filter, order } => {
+                    let result = db.select_from(&TableRef::list(from, joins), &columns, &filter, &order);
+                    export(out, result, &path, &format)
+                }
+                _ => unreachable!("the parser only exports SELECT"),
+            },
+            Statement::Dump(path) => dump(out, db, &path),
+            Statement::Backup(path) => backup(out, db, &path),
+            Statement::Restore { path, until } => restore(out, db, &path, until),
+            Statement::Migrate(dir) => {
+                self.migrate(out, dir.as_deref(), user);
+            }
+            Statement::Source { path, on_error } => {
+                self.source(out, &path, on_error, user);
+            }
+
+            Statement::Promote => match self.following.take() {
+                Some(leader) => say!(out, "Promoted: no longer following {}, and taking writes", leader),
+                None => out.failure(&DbError::NotFollowing),
+            },
+
+            // The server takes the connection over for the stream before it gets here
+            Statement::Subscribe(_) => {
+                out.failure(&DbError::Unavailable("SUBSCRIBE is only available over a server connection (rust_db connect)".to_string()))
+            }
+
+            Statement::Help => print_help(out),
+            Statement::Exit => return false,
+        }
+        true
+    }
+
+    // The catalog is read on every statement, so a REVOKE or DROP USER
+    // applies to open connections straight away
+    fn authorize(&self, name: &str, statement: &Statement) -> Result<(), DbError> {
+        let user = self.db.user(name)?.ok_or_else(|| DbError::UserNotFound(name.to_string()))?;
+        let requirement = users::requirement(statement);
+        if !user.allows(&requirement) {
+            return Err(DbError::PermissionDenied(match requirement {
+                Requirement::Table(table, privilege) => format!("{} on '{}' was not granted to '{}'", privilege, table, name),
+                _ => format!("only superusers may run this statement, and '{}' is not one", name),
+            }));
+        }
+        // A cursor reads what its query would
+        if let Statement::Declare { query, .. } = statement {
+            return self.authorize(name, query);
+        }
+        // A subquery reads its tables as a query of its own
+        for query in statement.subqueries() {
+            self.authorize(name, query)?;
+        }
+        if let Statement::ShowGrants(other) = statement && other != name && !user.superuser {
+            return Err(DbError::PermissionDenied("only superusers may see other users' grants".to_string()));
+        }
+        // A join reads every table of its FROM list, explained or run
+        let query = match statement {
+            Statement::Explain { statement: query, .. } => query,
+            statement => statement,
+        };
+        if let Statement::Select { joins, .. } = query
+            && let Some(join) = joins.iter().map(|join| &join.table).find(|table| !user.allows(&Requirement::Table(table, Privilege::Select)))
+        {
+            return Err(DbError::PermissionDenied(format!("{} on '{}' was not granted to '{}'", Privilege::Select, join, name)));
+        }
+        // A WITH reads the tables of each of its queries, but its own results are open
+        if let Statement::With { ctes, query } = statement {
+            // Each query with the results it can see
+            let mut queries: Vec<(&Statement, &[Cte])> = Vec::new();
+            for (i, cte) in ctes.iter().enumerate() {
+                queries.push((&cte.query, &ctes[..i]));
+                if let Some(step) = &cte.step {
+                    queries.push((&step.query, &ctes[..=i]));
+                }
+            }
+            queries.push((query, ctes));
+            for (query, visible) in queries {
+                let Statement::Select { from, joins, .. } = query else { continue };
+                let denied = std::iter::once(from).chain(joins).map(|table| &table.table)
+                    .filter(|table| !visible.iter().any(|cte| cte.name == **table))
+                    .find(|table| !user.allows(&Requirement::Table(table, Privilege::Select)));
+                if let Some(table) = denied {
+                    return Err(DbError::PermissionDenied(format!("{} on '{}' was not granted to '{}'", Privilege::Select, table, name)));
+                }
+            }
+        }
+        // An upsert may change an existing row as well as add one
+        if let Statement::Insert { table, on_conflict: Some(OnConflict { action: ConflictAction::Update(_), .. }), .. } = statement
+            && !user.allows(&Requirement::Table(table, Privilege::Update))
+        {
+            return Err(DbError::PermissionDenied(format!(
+                "{} on '{}' was not granted to '{}', which ON CONFLICT DO UPDATE needs", Privilege::Update, table, name
+            )));
+        }
+        Ok(())
+    }
+
+    /// Applies the migrations in `dir` (`migrations/` by default) that have
+    /// not been applied yet, in version order, recording each one in
+    /// `schema_migrations`. Stops at the first failing statement, leaving that
+    /// migration unrecorded; its earlier statements are not undone. Returns
+    /// whether every pending migration was applied.
+    pub fn migrate(&mut self, out: &mut dyn Output, dir: Option<&str>, user: Option<&str>) -> bool {
+        let dir = Path::new(dir.unwrap_or(migrations::DEFAULT_DIR));
+        let pending = match self.db.pending_migrations(dir) {
+            Ok(pending) => pending,
+            Err(e) => {
+                out.failure(&e);
+                return false;
+            }
+        };
+        if pending.is_empty() {
+            say!(out, "No pending migrations");
+            return true;
+        }
+
+        for migration in &pending {
+            let statements = match migration.statements() {
+                Ok(statements) => statements,
+                Err(e) => {
+                    out.failure(&migration_failed(migration, e.to_string()));
+                    return false;
+                }
+            };
+            let mut quiet = Quiet::default();
+            for (text, statement) in statements {
+                self.execute(&mut quiet, statement, &text, user);
+                if let Some(error) = quiet.error {
+                    out.failure(&migration_failed(migration, error));
+                    return false;
+                }
+            }
+            if let Err(e) = self.db.record_migration(migration) {
+                out.failure(&migration_failed(migration, format!("it ran but could not be recorded: {}", e)));
+                return false;
+            }
+            say!(out, "Applied migration {} ({})", migration.version, migration.name);
+        }
+        say!(out, "{} migration(s) applied", pending.len());
+        true
+    }
+
+    /// Runs the `;`-separated statements of the file at `path` in order, as
+    /// `user`, like `run_script`.
+    pub fn source(&mut self, out: &mut dyn Output, path: &str, on_error: OnError, user: Option<&str>) -> bool {
+        match fs::read_to_string(path) {
+            Ok(script) => self.run_script(out, &script, Some(path), on_error, user),
+            Err(e) => {
+                out.failure(&DbError::ReadFailed { source: format!("'{}'", path), reason: e.to_string() });
+                false
+            }
+        }
+    }
+
+    /// Runs the `;`-separated statements of `script` in order, as `user`.
+    /// Errors name the line the failing statement starts on, and the file
+    /// it came from if any. What the first one does is up to `on_error`.
+    /// Returns whether every statement succeeded.
+    pub fn run_script(&mut self, out: &mut dyn Output, script: &str, path: Option<&str>, on_error: OnError, user: Option<&str>) -> bool {
+        let name = path.map_or_else(|| "Script".to_string(), |path| format!("Script '{}'", path));
+        if on_error == OnError::RollBack {
+            return self.run_transaction(out, script, path, &name, user);
+        }
+        // A typo stops the script before anything runs rather than halfway
+        if on_error == OnError::Stop && !self.check_syntax(out, script, path) {
+            out.failure(&script_failed(&name, "was not run".to_string()));
+            return false;
+        }
+        let mut failed = 0;
+        let mut current = None;
+        for (line, text) in parser::split_script(script) {
+            // The statements starting on one line reach the disk together
+            if current != Some(line) {
+                if !self.end_line(out) {
+                    return false;
+                }
+                self.begin_line();
+                current = Some(line);
+            }
+            let mut located = Located::new(out, path, line);
+            match self.parse(text) {
+                // A script sourcing itself would never end
+                Ok(Statement::Source { .. } | Statement::Exit) => {
+                    located.failure(&DbError::Unavailable("SOURCE and EXIT cannot be used in a script".to_string()));
+                }
+                Ok(statement) => {
+                    self.execute(&mut located, statement, text, user);
+                }
+                Err(e) => located.failure(&e),
+            }
+            if located.failed {
+                failed += 1;
+                if on_error == OnError::Stop {
+                    self.end_line(out);
+                    out.failure(&script_failed(&name, format!("stopped at line {}", line)));
+                    return false;
+                }
+            }
+        }
+        if !self.end_line(out) {
+            return false;
+        }
+        if failed > 0 {
+            out.failure(&script_failed(&name, format!("finished with {} failed statement(s)", failed)));
+        }
+        failed == 0
+    }
+
+    // Reports each statement of `script` that does not parse, not just the
+    // first, with the line it starts on. Returns whether they all parse. One
+    // using a variable not set yet is left to fail when it runs, as the
+    // script may set it first.
+    fn check_syntax(&self, out: &mut dyn Output, script: &str, path: Option<&str>) -> bool {
+        let mut valid = true;
+        for (line, text) in parser::split_script(script) {
+            if let Err(e @ (DbError::Syntax(_) | DbError::SyntaxAt { .. })) = self.parse(text) {
+                Located::new(out, path, line).failure(&e);
+                valid = false;
+            }
+        }
+        valid
+    }
+
+    // Runs `script` in a transaction of its own, committed once every
+    // statement has succeeded and rolled back at the first that fails. The
+    // statements are all parsed first, so a script holding one that cannot
+    // run in a transaction fails before anything is run, with every such
+    // statement reported.
+    fn run_transaction(&mut self, out: &mut dyn Output, script: &str, path: Option<&str>, name: &str, user: Option<&str>) -> bool {
+        let mut statements = Vec::new();
+        let mut valid = true;
+        for (line, text) in parser::split_script(script) {
+            let mut located = Located::new(out, path, line);
+            match self.parse(text) {
+                Ok(Statement::Source { .. } | Statement::Exit) => {
+                    located.failure(&DbError::Unavailable("SOURCE and EXIT cannot be used in a script".to_string()));
+                }
+                // The statements after it were parsed before it could run
+                Ok(Statement::SetVariable { .. }) => {
+                    located.failure(&DbError::Unavailable("A single-transaction script cannot set variables; set them before running it".to_string()));
+                }
+                Ok(Statement::Begin | Statement::Commit | Statement::Rollback) => {
+                    located.failure(&DbError::Unavailable("A single-transaction script cannot begin or end transactions of its own".to_string()));
+                }
+                Ok(statement) if !statement.allowed_in_transaction() => {
+                    located.failure(&DbError::Unavailable("Not allowed in a single-transaction script, as it cannot run inside a transaction".to_string()));
+                }
+                Ok(statement) => statements.push((line, text, statement)),
+                Err(e) => located.failure(&e),
+            }
+            valid &= !located.failed;
+        }
+        if !valid {
+            out.failure(&script_failed(name, "was not run".to_string()));
+            return false;
+        }
+
+        if let Err(e) = self.db.begin() {
+            out.failure(&e);
+            return false;
+        }
+        for (line, text, statement) in statements {
+            let mut located = Located::new(out, path, line);
+            self.execute(&mut located, statement, text, user);
+            if located.failed {
+                match self.db.rollback() {
+                    Ok(count) => {
+                        out.failure(&script_failed(name, format!("stopped at line {} and was rolled back ({} change(s) discarded)", line, count)))
+                    }
+                    Err(e) => out.failure(&e),
+                }
+                return false;
+            }
+        }
+        match self.db.commit() {
+            Ok(count) => {
+                say!(out, "{} committed ({} change(s))", name, count);
+                true
+            }
+            Err(e) => {
+                out.failure(&e);
+                false
+            }
+        }
+    }
+
+    pub fn shutdown(&mut self, out: &mut dyn Output) {
+        shutdown(out, &mut self.db);
+    }
+
+    pub fn rollback(&mut self, out: &mut dyn Output) {
+        rollback(out, &mut self.db);
+    }
+}
+
+fn create_table(out: &mut dyn Output, db: &mut Database, mut table: Table, temp: bool, partition_by: Option<PartitionBy>) {
+    let name = table.name.clone();
+    let name = name.as_str();
+    if catalog::is_system_table(name) {
+        return out.failure(&DbError::SystemTable(name.to_string()));
+    }
+    // Check if table exists
+    if db.table_exists(name) {
+        return out.failure(&DbError::ViewExists(name.to_string()));
+    }
+    match db.view(name) {
+        Ok(None) => {}
+        Ok(Some(_)) => return out.failure(&DbError::ViewExists(name.to_string())),
+        Err(e) => return out.failure(&e),
+    }
+
+    if let Err(e) = table.check_generated(&db.functions()) {
+        return out.failure(&e);
+    }
+    if let Some(by) = partition_by {
+        match Partitioning::new(&table, by) {
+            Ok(partitioning) => table.partitioning = Some(partitioning),
+            Err(e) => return out.failure(&e),
+        }
+    }
+    if let Some(column) = &table.ttl && let Err(e) = ttl::check(&table, column) {
+        return out.failure(&e);
+    }
+    if table.tombstones.is_some() && let Err(e) = tombstones::check(&table) {
+        return out.failure(&e);
+    }
+    if table.timestamps && let Err(e) = timestamps::check(&table) {
+        return out.failure(&e);
+    }
+    if let Err(e) = db.check_defaults(&table) {
+        return out.failure(&e);
+    }
+
+    if temp {
+        db.add_temp_table(table);
+        say!(out, "Temporary table '{}' created", name);
+        return;
+    }
+
+    // The partitions first, so the table is never without them
+    let partitions = table.partitioning.as_ref().map(|partitioning| partitioning.partitions.clone()).unwrap_or_default();
+    for partition in &partitions {
+        if let Err(e) = db.save_table(&partition::empty_partition(&table, &partition.name, table.lsn)) {
+            return out.failure(&e);
+        }
+    }
+    if let Err(e) = db.save_table(&table) {
+        return out.failure(&e);
+    }
+    match table.partitioning {
+        Some(_) => say!(out, "Table '{}' created with {} partition(s)", name, partitions.len()),
+        None => say!(out, "Table '{}' created", name),
+    }
+}
+
+fn create_index(out: &mut dyn Output, db: &mut Database, name: &str, table_name: &str, columns: Vec<String>, kind: IndexKind) {
+    let list = columns.join(", ");
+    let def = IndexDef { name: name.to_string(), columns, kind, unique: false };
+    match db.create_index(table_name, def) {
+        Ok(()) => say!(out, "Index '{}' created on {}({})", name, table_name, list),
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn drop_index(out: &mut dyn Output, db: &mut Database, name: &str, table: Option<String>) {
+    let table = match table {
+        Some(table) => table,
+        None => match db.index_tables(name) {
+            Ok(tables) if tables.is_empty() => return out.failure(&DbError::IndexNotFound(name.to_string())),
+            Ok(mut tables) if tables.len() == 1 => tables.remove(0),
+            Ok(tables) => return out.failure(&DbError::AmbiguousIndex { name: name.to_string(), tables }),
+            Err(e) => return out.failure(&e),
+        },
+    };
+    match db.drop_index(&table, name) {
+        Ok(()) => say!(out, "Index '{}' dropped from '{}'", name, table),
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn show_indexes(out: &mut dyn Output, db: &mut Database, table_name: &str) {
+    let table = match db.definition(table_name) {
+        Ok(table) => table,
+        Err(e) => return out.failure(&e),
+    };
+    let rows = table.index_defs.iter()
+        .map(|def| vec![
+            def.name.clone(),
+            def.columns.join(", "),
+            def.kind.keyword().to_string(),
+            if def.unique { "yes" } else { "no" }.to_string(),
+        ])
+        .collect();
+    out.rows(&["Index", "Columns", "Kind", "Unique"], &["string"; 4], rows);
+}
+
+fn reindex(out: &mut dyn Output, db: &mut Database, table_name: &str) {
+    match db.reindex(table_name) {
+        Ok(count) => say!(out, "Table '{}' reindexed ({} index(es))", table_name, count),
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn refresh_table(out: &mut dyn Output, db: &mut Database, table_name: &str) {
+    match db.refresh_table(table_name) {
+        Ok(()) => say!(out, "Table '{}' read again from its file", table_name),
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn drop_table(out: &mut dyn Output, db: &mut Database, name: &str, cascade: bool) {
+    match db.view(name) {
+        Ok(None) => {}
+        Ok(Some(_)) => return out.failure(&DbError::IsView(name.to_string())),
+        Err(e) => return out.failure(&e),
+    }
+    match db.drop_dependents(name, cascade) {
+        Ok(dependents) => dropped_dependents(out, &dependents),
+        Err(e) => return out.failure(&e),
+    }
+    match db.drop_table(name) {
+        Ok(true) => say!(out, "Table '{}' dropped", name),
+        Ok(false) => out.failure(&db.table_not_found(name)),
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn create_view(out: &mut dyn Output, db: &mut Database, name: &str, table: &str, filter: Vec<Predicate>, materialized: bool) {
+    match db.create_view(name, table, filter, materialized) {
+        Ok(()) if materialized => say!(out, "Materialized view '{}' created", name),
+        Ok(()) => say!(out, "View '{}' created", name),
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn refresh_view(out: &mut dyn Output, db: &mut Database, name: &str) {
+    match db.refresh_view(name) {
+        Ok(rows) => say!(out, "Materialized view '{}' refreshed ({} row(s))", name, rows),
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn drop_view(out: &mut dyn Output, db: &mut Database, name: &str, cascade: bool) {
+    let result = db.view(name)
+        .and_then(|view| view.ok_or_else(|| DbError::ViewNotFound(name.to_string())))
+        .and_then(|_| db.drop_dependents(name, cascade))
+        .and_then(|dependents| db.drop_view(name).map(|()| dependents));
+    match result {
+        Ok(dependents) => {
+            dropped_dependents(out, &dependents);
+            say!(out, "View '{}' dropped", name);
+        }
+        Err(e) => out.failure(&e),
+    }
+}
+
+// Tells what a DROP ... CASCADE dropped ahead of its object
+fn dropped_dependents(out: &mut dyn Output, dependents: &Dependents) {
+    for view in &dependents.views {
+        say!(out, "View '{}' dropped", view);
+    }
+    for (table, trigger) in &dependents.triggers {
+        say!(out, "Trigger '{}' dropped from '{}'", trigger, table);
+    }
+}
+
+fn create_trigger(out: &mut dyn Output, db: &mut Database, table: &str, trigger: Trigger) {
+    let name = trigger.name.clone();
+    match db.create_trigger(table, trigger) {
+        Ok(()) => say!(out, "Trigger '{}' created on '{}'", name, table),
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn drop_trigger(out: &mut dyn Output, db: &mut Database, table: &str, name: &str) {
+    match db.drop_trigger(table, name) {
+        Ok(()) => say!(out, "Trigger '{}' dropped from '{}'", name, table),
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn create_user(out: &mut dyn Output, db: &mut Database, name: &str, password: &str, superuser: bool) {
+    match db.create_user(name, password, superuser) {
+        Ok(()) => say!(out, "User '{}' created", name),
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn drop_user(out: &mut dyn Output, db: &mut Database, name: &str) {
+    match db.drop_user(name) {
+        Ok(()) => say!(out, "User '{}' dropped", name),
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn show_users(out: &mut dyn Output, db: &Database) {
+    match db.users() {
+        Ok(users) => {
+            for user in users {
+                say!(out, "{}", user.name);
+            }
+        }
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn create_token(out: &mut dyn Output, db: &mut Database, name: &str, user: &str) {
+    match db.create_token(name, user) {
+        Ok(token) => {
+            say!(out, "Token '{}' created for '{}'. It is shown only this once:", name, user);
+            say!(out, "{}", token);
+        }
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn drop_token(out: &mut dyn Output, db: &mut Database, name: &str) {
+    match db.drop_token(name) {
+        Ok(()) => say!(out, "Token '{}' dropped", name),
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn show_tokens(out: &mut dyn Output, db: &Database) {
+    match db.users() {
+        Ok(users) => {
+            let rows = users.iter()
+                .flat_map(|user| user.tokens.iter().map(|token| {
+                    vec![token.name.clone(), user.name.clone(), time::format_timestamp(token.created)]
+                }))
+                .collect();
+            out.rows(&["Token", "User", "Created"], &["string"; 3], rows);
+        }
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn privilege_list(privileges: &[Privilege]) -> String {
+    privileges.iter().map(Privilege::to_string).collect::<Vec<_>>().join(", ")
+}
+
+fn grant(out: &mut dyn Output, db: &mut Database, privileges: &[Privilege], table: &str, user: &str) {
+    match db.grant(user, table, privileges) {
+        Ok(()) => say!(out, "Granted {} on '{}' to '{}'", privilege_list(privileges), table, user),
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn revoke(out: &mut dyn Output, db: &mut Database, privileges: &[Privilege], table: &str, user: &str) {
+    match db.revoke(user, table, privileges) {
+        Ok(()) => say!(out, "Revoked {} on '{}' from '{}'", privilege_list(privileges), table, user),
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn show_grants(out: &mut dyn Output, db: &Database, name: &str) {
+    let user = match db.user(name) {
+        Ok(Some(user)) => user,
+        Ok(None) => return out.failure(&DbError::UserNotFound(name.to_string())),
+        Err(e) => return out.failure(&e),
+    };
+    if user.superuser {
+        say!(out, "User '{}' is a superuser and has every privilege on every table", name);
+        return;
+    }
+    let rows = user.grants.iter()
+        .map(|(table, privileges)| vec![table.clone(), privilege_list(privileges)])
+        .collect();
+    out.rows(&["Table", "Privileges"], &["string"; 2], rows);
+}
+
+fn table_names(out: &mut dyn Output, db: &Database) -> Vec<String> {
+    db.table_names().unwrap_or_else(|e| {
+        out.failure(&e);
+        Vec::new()
+    })
+}
+
+fn show_tables(out: &mut dyn Output, db: &mut Database) {
+    let views = db.views().unwrap_or_else(|e| {
+        out.failure(&e);
+        Vec::new()
+    });
+    // Materialized views are listed once, as views, though they are also tables
+    let mut entries: Vec<(String, String)> = table_names(out, db).into_iter()
+        .filter(|name| !views.iter().any(|view| &view.name == name))
+        .map(|name| {
+            let marker = match db.external(&name) {
+                _ if db.is_temp(&name) => " (temp)".to_string(),
+                Ok(Some(external)) => format!(" (external, {})", external.location),
+                _ => String::new(),
+            };
+            (name, marker)
+        })
+        .collect();
+    for view in views {
+        let marker = match &view.materialized {
+            None => " (view)".to_string(),
+            Some(refresh) => {
+                let state = match db.freshness(&view) {
+                    Ok(Freshness::Fresh) => "up to date",
+                    Ok(Freshness::Stale) => "stale",
+                    Ok(Freshness::Broken) => "source missing",
+                    Err(e) => {
+                        out.failure(&e);
+                        "unknown"
+                    }
+                };
+                format!(" (materialized view, refreshed {} ago, {})", age(refresh.at, db.now()), state)
+            }
+        };
+        entries.push((view.name, marker));
+    }
+    entries.sort();
+    for (name, marker) in entries {
+        say!(out, "{}{}", name, marker);
+    }
+}
+
+// How long before `now` a Unix timestamp was, roughly
+fn age(at: u64, now: u64) -> String {
+    let seconds = now.saturating_sub(at);
+    match seconds {
+        0..60 => format!("{}s", seconds),
+        60..3600 => format!("{}m", seconds / 60),
+        3600..86400 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
+}
+
+fn show_table_status(out: &mut dyn Output, db: &mut Database) {
+    let mut result = Vec::new();
+
+    // Temporary tables have no storage footprint to report
+    let stored: Vec<String> = table_names(out, db).into_iter().filter(|name| !db.is_temp(name)).collect();
+    for name in stored {
+        let table = db.count_rows(&name)
+            .and_then(|rows| db.definition(&name).map(|table| (rows, table.index_defs.len(), table.modified)));
+        let ((rows, indexes, modified), stats) = match (table, db.file_stats(&name)) {
+            (Ok(table), Ok(stats)) => (table, stats),
+            (Err(e), _) | (_, Err(e)) => {
+                out.failure(&e);
+                continue;
+            }
+        };
+        let ratio = stats.file_bytes as f64 / stats.raw_bytes.max(1) as f64 * 100.0;
+        result.push(vec![
+            name,
+            rows.to_string(),
+            indexes.to_string(),
+            stats.engine.name().to_string(),
+            stats.codec.name().to_string(),
+            stats.format.to_string(),
+            format!("{} B", stats.raw_bytes),
+            format!("{} B", stats.file_bytes),
+            format!("{:.1}%", ratio),
+            format!("{} B", stats.index_bytes),
+            // Tables saved before changes were timed have no date
+            if modified == 0 { "-".to_string() } else { format!("{} UTC", time::format_timestamp(modified)) },
+        ]);
+    }
+    let columns = ["Name", "Rows", "Indexes", "Engine", "Codec", "Format", "Raw Size", "File Size", "Ratio", "Index Size", "Modified"];
+    let types = ["string", "int", "int", "string", "string", "int", "string", "string", "string", "string", "string"];
+    out.rows(&columns, &types, result);
+}
+
+fn show_create_table(out: &mut dyn Output, db: &mut Database, name: &str) {
+    match db.create_statements(name) {
+        Ok(statements) => {
+            for statement in statements {
+                say!(out, "{};", statement);
+            }
+        }
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn show_sequences(out: &mut dyn Output, db: &mut Database) {
+    let sequences = match db.sequences() {
+        Ok(sequences) => sequences,
+        Err(e) => return out.failure(&e),
+    };
+    let rows = sequences.into_iter()
+        .map(|sequence| vec![
+            sequence.name,
+            match i32::try_from(sequence.next) {
+                Ok(next) => next.to_string(),
+                Err(_) => "none left".to_string(),
+            },
+            sequence.increment.to_string(),
+        ])
+        .collect();
+    out.rows(&["Sequence", "Next Value", "Increment"], &["string", "int", "int"], rows);
+}
+
+fn describe(out: &mut dyn Output, db: &mut Database, name: &str) {
+    let table = match db.definition(name) {
+        Ok(table) => table,
+        Err(e) => return out.failure(&e),
+    };
+    let rows = table.columns.iter()
+        .map(|column| vec![
+            column.clone(),
+            table.fields[column].clone(),
+            if table.primary_key.as_ref() == Some(column) { "yes" } else { "no" }.to_string(),
+            table.sequences.get(column).map(|sequence| format!("NEXTVAL('{}')", sequence)).unwrap_or_default(),
+            table.generated.get(column).map(|expr| expr.to_string()).unwrap_or_default(),
+            table.collation(column).name().to_string(),
+            table.column_comments.get(column).cloned().unwrap_or_default(),
+        ])
+        .collect();
+    out.rows(&["Column", "Type", "Primary Key", "Default", "Generated", "Collation", "Comment"], &["string"; 7], rows);
+}
+
+fn set_compression(out: &mut dyn Output, db: &mut Database, codec_name: &str) {
+    let Some(codec) = Compression::parse(codec_name) else {
+        return out.failure(&DbError::Syntax(format!("Unknown compression '{}'. Use none or gzip.", codec_name)));
+    };
+
+    let result = db.settings().and_then(|mut settings| {
+        settings.compression = codec;
+        db.save_settings(&settings)
+    });
+    if let Err(e) = result {
+        return out.failure(&e);
+    }
+
+    // Re-encode existing files right away so SHOW TABLE STATUS reflects the change
+    let mut rewritten = 0;
+    let stored: Vec<String> = table_names(out, db).into_iter().filter(|name| !db.is_temp(name)).collect();
+    for name in stored {
+        match db.rewrite_table(&name) {
+            Ok(()) => rewritten += 1,
+            Err(e) => out.failure(&e),
+        }
+    }
+    say!(out, "Compression set to {} ({} table(s) rewritten)", codec.name(), rewritten);
+}
+
+
+/// The table's triggers for `event`, if it has any.
+fn triggers_for(table: &Table, event: Event) -> Vec<Trigger> {
+    table.triggers.iter().filter(|t| t.event == event).cloned().collect()
+}
+
+/// Runs the `timing` triggers once for each row. `depth` counts the
+/// triggers already running around this statement.
+fn fire(db: &mut Database, triggers: &[Trigger], timing: Timing, columns: &[String], rows: &[Vec<DataType>], depth: usize) -> Result<(), DbError> {
+    for trigger in triggers.iter().filter(|t| t.timing == timing) {
+        let failed = |reason: String| DbError::TriggerFailed { trigger: trigger.name.clone(), reason };
+        if depth >= MAX_TRIGGER_DEPTH {
+            return Err(failed(format!("triggers nested more than {} deep", MAX_TRIGGER_DEPTH)));
+        }
+        for row in rows {
+            for statement in trigger.statements(columns, row).map_err(|e| failed(e.to_string()))? {
+                let result = match statement {
+                    Statement::Insert { table, values, on_conflict, .. } => {
+                        insert(db, &table, values, on_conflict.as_ref(), depth + 1).map(|_| ())
+                    }
+                    Statement::Delete { table, filter, .. } => delete(db, &table, &filter, depth + 1).map(|_| ()),
+                    _ => unreachable!("trigger statements are checked to be INSERT or DELETE"),
+                };
+                result.map_err(|e| match e {
+                    // Already names the innermost trigger, which is the one to fix
+                    DbError::TriggerFailed { .. } => e,
+                    e => failed(e.to_string()),
+                })?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn row_values(table: &Table, rows: &[usize]) -> Vec<Vec<DataType>> {
+    rows.iter().map(|&i| table.columns.iter().map(|col| table.data[col][i].clone()).collect()).collect()
+}
+
+/// Whole rows of `table` as they read, for triggers to bind.
+fn shown_rows(table: &Table, rows: &[Vec<DataType>]) -> Vec<Vec<DataType>> {
+    rows.iter()
+        .map(|row| table.columns.iter().zip(row).map(|(col, value)| present(&table.fields[col], value.clone())).collect())
+        .collect()
+}
+
+/// What an INSERT did, with the row as written.
+enum Inserted {
+    Row(Vec<DataType>),
+    Updated(Vec<DataType>), // ON CONFLICT DO UPDATE changed the row holding the key instead
+    Skipped,                // ON CONFLICT DO NOTHING
+}
+
+fn insert_row(
+    out: &mut dyn Output,
+    db: &mut Database,
+    table_name: &str,
+    values: Vec<InsertValue>,
+    on_conflict: Option<&OnConflict>,
+    returning: Option<&[Expr]>,
+) {
+    if let Err(e) = check_returning(db, table_name, returning) {
+        return out.failure(&e);
+    }
+    let (row, message) = match insert(db, table_name, values, on_conflict, 0) {
+        Ok(Inserted::Row(row)) => (Some(row), "1 row inserted"),
+        Ok(Inserted::Updated(row)) => (Some(row), "1 row updated"),
+        Ok(Inserted::Skipped) => (None, "0 rows inserted"),
+        Err(e) => return out.failure(&e),
+    };
+    out.affected(usize::from(row.is_some()));
+    match returning {
+        Some(columns) => show_returning(out, db, table_name, row.into_iter().collect(), columns),
+        None => say!(out, "{}", message),
+    }
+}
+
+/// Fails if RETURNING names something the table doesn't have, before the
+/// write rather than after it.
+fn check_returning(db: &mut Database, table_name: &str, returning: Option<&[Expr]>) -> Result<(), DbError> {
+    match returning {
+        Some(columns) => db.returning(table_name, Vec::new(), columns).map(|_| ()),
+        None => Ok(()),
+    }
+}
+
+/// Shows what RETURNING asked for of the rows a write touched.
+fn show_returning(out: &mut dyn Output, db: &mut Database, table_name: &str, rows: Vec<Vec<DataType>>, columns: &[Expr]) {
+    match db.returning(table_name, rows, columns) {
+        Ok(result) => show_rows(out, &result),
+        Err(e) => out.failure(&e),
+    }
+}
+
+/// Inserts one row, with the table's INSERT triggers around it.
+fn insert(db: &mut Database, table_name: &str, values: Vec<InsertValue>, on_conflict: Option<&OnConflict>, depth: usize) -> Result<Inserted, DbError> {
+    db.check_writable(table_name)?;
+    let functions = db.functions();
+    let table = db.load_table(table_name)?;
+
+    // Check if input count matches column count; generated columns take no value
+    let expected = table.input_columns().len();
+    if values.len() != expected {
+        return Err(DbError::ColumnCount { expected, found: values.len() });
+    }
+
+    // Values are drawn from sequences before the row is checked, so an
+    // INSERT that fails leaves a gap
+    let defaults = table.sequences.clone();
+    let values: Vec<String> = values.into_iter()
+        .map(|value| match value {
+            InsertValue::Literal(text) => Ok(text),
+            InsertValue::Nextval(sequence) => db.nextval(&sequence).map(|value| value.to_string()),
+        })
+        .collect::<Result<_, _>>()?;
+    let drawn = defaults.into_iter()
+        .map(|(column, sequence)| Ok((column, db.nextval(&sequence)?)))
+        .collect::<Result<_, DbError>>()?;
+    let now = db.now();
+    let table = db.load_table(table_name)?;
+    let inputs = table.input_columns();
+
+    // Parse each value against its column type
+    let values: Vec<DataType> = inputs.iter()
+        .zip(&values)
+        .map(|(col_name, raw)| parse_value(col_name, &table.fields[*col_name], raw))
+        .collect::<Result<_, _>>()?;
+    let row = table.complete_row(values, &drawn, &functions, now)?;
+    if let Some(on_conflict) = on_conflict
+        && let Some(existing) = table.conflict(&row, &on_conflict.target)?
+    {
+        return match &on_conflict.action {
+            ConflictAction::Nothing => Ok(Inserted::Skipped),
+            ConflictAction::Update(set) => {
+                update_conflicting(db, table_name, existing, &row, set, &on_conflict.target, depth).map(Inserted::Updated)
+            }
+        };
+    }
+    table.check_unique(&row)?;
+
+    let triggers = triggers_for(table, Event::Insert);
+    if triggers.is_empty() {
+        db.log(WalOp::Insert { table: table_name.to_string(), row: row.clone() })?;
+        return Ok(Inserted::Row(row));
+    }
+
+    // The row and everything its triggers do stand or fall together
+    let columns = table.columns.clone();
+    let shown = shown_rows(table, std::slice::from_ref(&row));
+    db.atomically(|db| {
+        fire(db, &triggers, Timing::Before, &columns, &shown, depth)?;
+        // A BEFORE trigger may have taken the row's key in the meantime
+        db.load_table(table_name)?.check_unique(&row)?;
+        db.log(WalOp::Insert { table: table_name.to_string(), row: row.clone() })?;
+        fire(db, &triggers, Timing::After, &columns, &shown, depth)
+    })?;
+    Ok(Inserted::Row(row))
+}
+
+/// Applies ON CONFLICT DO UPDATE's `set` to row `existing`, which holds a
+/// key of `target` that `proposed` repeats, with the table's UPDATE triggers
+/// around it. The row is changed where it is, and only the values that
+/// differ are logged.
+fn update_conflicting(
+    db: &mut Database,
+    table_name: &str,
+    existing: usize,
+    proposed: &[DataType],
+    set: &[(String, SetValue)],
+    target: &[String],
+    depth: usize,
+) -> Result<Vec<DataType>, DbError> {
+    let functions = db.functions();
+    let now = db.now();
+    let table = db.load_table(table_name)?;
+    let old = row_values(table, &[existing]).remove(0);
+    let row = assign(table, &old, set, Some(proposed), &functions, now)?;
+    table.check_unique_except(&row, Some(existing))?;
+
+    let values = changed_values(table, &old, &row);
+    let triggers = triggers_for(table, Event::Update);
+    if triggers.is_empty() {
+        if !values.is_empty() {
+            db.log(WalOp::Update { table: table_name.to_string(), row: existing, values })?;
+        }
+        return Ok(row);
+    }
+
+    // The update and everything its triggers do stand or fall together
+    let columns = table.columns.clone();
+    let shown = shown_updates(table, &[(old, row.clone())]);
+    db.atomically(|db| {
+        fire(db, &triggers, Timing::Before, &columns, &shown, depth)?;
+        // BEFORE triggers may have moved the row, or taken its new key
+        let table = db.load_table(table_name)?;
+        let Some(existing) = table.conflict(proposed, target)? else {
+            let trigger = triggers.iter().find(|t| t.timing == Timing::Before).map_or_else(String::new, |t| t.name.clone());
+            return Err(DbError::TriggerFailed { trigger, reason: "it removed the row being updated".to_string() });
+        };
+        table.check_unique_except(&row, Some(existing))?;
+        if !values.is_empty() {
+            db.log(WalOp::Update { table: table_name.to_string(), row: existing, values })?;
+        }
+        fire(db, &triggers, Timing::After, &columns, &shown, depth)
+    })?;
+    Ok(row)
+}
+
+fn update_rows(
+    out: &mut dyn Output,
+    db: &mut Database,
+    table_name: &str,
+    set: &[(String, SetValue)],
+    filter: &[Predicate],
+    returning: Option<&[Expr]>,
+) {
+    if let Err(e) = check_returning(db, table_name, returning) {
+        return out.failure(&e);
+    }
+    match update(db, table_name, set, filter, 0) {
+        Ok(rows) if rows.is_empty() => out.failure(&DbError::NoMatch(describe_filter(filter))),
+        Ok(rows) => {
+            out.affected(rows.len());
+            match returning {
+                Some(columns) => show_returning(out, db, table_name, rows, columns),
+                None => say!(out, "{} row(s) updated", rows.len()),
+            }
+        }
+        Err(e) => out.failure(&e),
+    }
+}
+
+/// Applies `set` to the matching rows, with the table's UPDATE triggers
+/// around it. Each row is changed where it is, and only the values that
+/// differ are logged. Returns the rows as updated.
+fn update(db: &mut Database, table_name: &str, set: &[(String, SetValue)], filter: &[Predicate], depth: usize) -> Result<Vec<Vec<DataType>>, DbError> {
+    db.check_writable(table_name)?;
+    let functions = db.functions();
+    let now = db.now();
+    let table = db.load_table(table_name)?;
+    if live_matching_rows(table, filter, &functions)?.is_empty() {
+        return Ok(Vec::new());
+    }
+    let triggers = triggers_for(table, Event::Update);
+    let columns = table.columns.clone();
+
+    // Every row, and everything the triggers do, stand or fall together
+    db.atomically(|db| {
+        if !triggers.is_empty() {
+            let table = db.load_table(table_name)?;
+            let updates = live_matching_rows(table, filter, &functions)?.into_iter()
+                .map(|row| {
+                    let old = row_values(table, &[row]).remove(0);
+                    assign(table, &old, set, None, &functions, now).map(|new| (old, new))
+                })
+                .collect::<Result<Vec<_>, DbError>>()?;
+            let shown = shown_updates(table, &updates);
+            fire(db, &triggers, Timing::Before, &columns, &shown, depth)?;
+        }
+
+        // BEFORE triggers may have changed the table, moving or removing rows
+        let rows = live_matching_rows(db.load_table(table_name)?, filter, &functions)?;
+        let mut updates = Vec::with_capacity(rows.len());
+        for row in rows {
+            // Each row is checked against those already updated
+            let table = db.load_table(table_name)?;
+            let old = row_values(table, &[row]).remove(0);
+            let new = assign(table, &old, set, None, &functions, now)?;
+            table.check_unique_except(&new, Some(row))?;
+            let values = changed_values(table, &old, &new);
+            if !values.is_empty() {
+                db.log(WalOp::Update { table: table_name.to_string(), row, values })?;
+            }
+            updates.push((old, new));
+        }
+        let shown = shown_updates(db.load_table(table_name)?, &updates);
+        fire(db, &triggers, Timing::After, &columns, &shown, depth)?;
+        Ok(updates.into_iter().map(|(_, new)| new).collect())
+    })
+}
+
+/// Row `old` of `table` with `set` applied, its generated columns worked
+/// out again and, if anything changed, its timestamps kept. Expressions
+/// read the row as it was. `proposed` is the row an upsert tried to
+/// insert, which `EXCLUDED.<column>` reads.
+fn assign(
+    table: &Table,
+    old: &[DataType],
+    set: &[(String, SetValue)],
+    proposed: Option<&[DataType]>,
+    functions: &Functions,
+    now: u64,
+) -> Result<Vec<DataType>, DbError> {
+    let position = |column: &str| {
+        table.columns.iter().position(|c| c == column).ok_or_else(|| DbError::ColumnNotFound {
+            table: table.name.clone(),
+            column: column.to_string(),
+        })
+    };
+    let mut row = old.to_vec();
+    for (column, value) in set {
+        if table.generated.contains_key(column) {
+            return Err(DbError::GeneratedColumn(column.clone()));
+        }
+        if timestamps::is_timestamp(table, column) {
+            return Err(DbError::InvalidTimestamps(format!("'{}' is kept by the engine and cannot be given a value", column)));
+        }
+        let at = position(column)?;
+        row[at] = match (value, proposed) {
+            (SetValue::Literal(raw), _) => parse_value(column, &table.fields[column], raw)?,
+            (SetValue::Excluded(source), Some(proposed)) => proposed[position(source)?].clone(),
+            (SetValue::Excluded(_), None) => return Err(excluded_outside_upsert()),
+            (SetValue::Expr(expr), _) => {
+                let value = expr.eval_with(&|name| match (name.split_once('.'), proposed) {
+                    (Some((prefix, source)), Some(proposed)) if prefix.eq_ignore_ascii_case("EXCLUDED") => Ok(proposed[position(source)?].clone()),
+                    (Some((prefix, _)), None) if prefix.eq_ignore_ascii_case("EXCLUDED") => Err(excluded_outside_upsert()),
+                    _ => position(name).map(|at| present(&table.fields[name], old[at].clone())),
+                }, functions)?;
+                table.computed(column, expr, &value)?
+            }
+        };
+    }
+    table.generate(&mut row, functions)?;
+    if row != old {
+        timestamps::stamp(table, &mut row, false, now);
+    }
+    Ok(row)
+}
+
+fn excluded_outside_upsert() -> DbError {
+    DbError::Syntax("EXCLUDED is only available in ON CONFLICT DO UPDATE".to_string())
+}
+
+/// The columns whose values differ between `old` and `new`, with the new
+/// values, as an update logs them.
+fn changed_values(table: &Table, old: &[DataType], new: &[DataType]) -> Vec<(String, DataType)> {
+    table.columns.iter().zip(old.iter().zip(new))
+        .filter(|(_, (old, new))| old != new)
+        .map(|(column, (_, new))| (column.clone(), new.clone()))
+        .collect()
+}
+
+/// Rows updated, each as OLD followed by NEW, for UPDATE triggers to bind.
+fn shown_updates(table: &Table, updates: &[(Vec<DataType>, Vec<DataType>)]) -> Vec<Vec<DataType>> {
+    updates.iter().map(|(old, new)| shown_rows(table, &[old.clone(), new.clone()]).concat()).collect()
+}
+
+fn import(out: &mut dyn Output, db: &mut Database, path: &str, table_name: &str, format: &Format) {
+    match db.import(table_name, Path::new(path), format) {
+        Ok(rows) => {
+            out.affected(rows);
+            say!(out, "Imported {} row(s) into '{}'", rows, table_name);
+        }
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn copy(out: &mut dyn Output, db: &mut Database, table_name: &str, format: &Format, data: Option<String>, read_stdin: bool) {
+    let data = match data {
+        Some(data) => data,
+        None if read_stdin => match io::read_to_string(io::stdin()) {
+            Ok(data) => data,
+            Err(e) => return out.failure(&DbError::ReadFailed { source: "standard input".to_string(), reason: e.to_string() }),
+        },
+        None => {
+            let reason = "COPY FROM STDIN only works from the prompt, a pipe, -c or --file; use IMPORT to load a file";
+            return out.failure(&DbError::Unavailable(reason.to_string()));
+        }
+    };
+    match db.load_rows(table_name, &data, format) {
+        Ok(rows) => {
+            out.affected(rows);
+            say!(out, "Loaded {} row(s) into '{}'", rows, table_name);
+        }
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn export(out: &mut dyn Output, result: Result<Rows, DbError>, path: &str, format: &Format) {
+    match result.and_then(|result| formats::export(Path::new(path), &result, format)) {
+        Ok(rows) => say!(out, "Exported {} row(s) to '{}'", rows, path),
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn dump(out: &mut dyn Output, db: &mut Database, path: &str) {
+    match db.dump(Path::new(path)) {
+        Ok(tables) => say!(out, "Dumped {} table(s) to '{}'", tables, path),
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn backup(out: &mut dyn Output, db: &mut Database, path: &str) {
+    match db.backup(Path::new(path)) {
+        Ok(tables) => say!(out, "Backed up {} table(s) to '{}'", tables, path),
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn restore(out: &mut dyn Output, db: &mut Database, path: &str, until: Option<u64>) {
+    match (db.restore_backup(Path::new(path), until), until) {
+        (Ok(tables), None) => say!(out, "Restored {} table(s) from '{}'", tables, path),
+        (Ok(tables), Some(until)) => say!(
+            out, "Restored {} table(s) from '{}' as of {} UTC", tables, path, time::format_timestamp(until)
+        ),
+        (Err(e), _) => out.failure(&e),
+    }
+}
+
+fn set_wal_archive(out: &mut dyn Output, db: &mut Database, dir: Option<String>) {
+    let result = db.settings().and_then(|mut settings| {
+        settings.wal_archive = dir.clone();
+        db.save_settings(&settings)
+    });
+    match (result, dir) {
+        (Ok(()), Some(dir)) => say!(out, "Checkpoints now archive the log to '{}'", dir),
+        (Ok(()), None) => say!(out, "Log archiving is off"),
+        (Err(e), _) => out.failure(&e),
+    }
+}
+
+fn rekey(out: &mut dyn Output, db: &mut Database, passphrase: Option<&str>) {
+    match (db.rekey(passphrase), passphrase) {
+        (Ok(written), Some(_)) => say!(out, "Database encrypted under the new key ({} file(s) rewritten)", written),
+        (Ok(written), None) => say!(out, "Database decrypted ({} file(s) rewritten)", written),
+        (Err(e), _) => out.failure(&e),
+    }
+}
+
+/// Positions of the rows matching every condition in `filter`, in ascending order.
+fn matching_rows(table: &Table, filter: &[Predicate], functions: &Functions) -> Result<Vec<usize>, DbError> {
+    planner::plan(table, filter, functions)?.rows()
+}
+
+/// `matching_rows`, leaving out rows already soft-deleted.
+fn live_matching_rows(table: &Table, filter: &[Predicate], functions: &Functions) -> Result<Vec<usize>, DbError> {
+    let mut rows = matching_rows(table, filter, functions)?;
+    rows.retain(|&row| !table.is_deleted(row));
+    Ok(rows)
+}
+
+fn analyze(out: &mut dyn Output, db: &mut Database, table_name: &str) {
+    match db.analyze(table_name) {
+        Ok(rows) => say!(out, "Table '{}' analyzed ({} row(s))", table_name, rows),
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn show_stats(out: &mut dyn Output, db: &mut Database, table_name: &str) {
+    let table = match db.load_table(table_name) {
+        Ok(table) => table,
+        Err(e) => {
+            out.failure(&e);
+            return;
+        }
+    };
+    let Some(stats) = &table.stats else {
+        say!(out, "Table '{}' has no statistics. Run ANALYZE {} first.", table_name, table_name);
+        return;
+    };
+
+    say!(out, "Table '{}': {} row(s) when analyzed", table_name, stats.rows);
+    let show = |col: &str, value: &Option<DataType>| {
+        value.as_ref().map_or("NULL".to_string(), |value| present(&table.fields[col], value.clone()).to_string())
+    };
+    let result = table.columns.iter()
+        .filter_map(|col| {
+            let column = stats.columns.get(col)?;
+            // A histogram has one bound more than it has buckets
+            let buckets = column.histogram.len().saturating_sub(1);
+            let buckets = if buckets == 0 { "-".to_string() } else { buckets.to_string() };
+            Some(vec![col.clone(), column.distinct.to_string(), show(col, &column.min), show(col, &column.max), buckets])
+        })
+        .collect();
+    out.rows(&["Column", "Distinct", "Min", "Max", "Buckets"], &["string", "int", "string", "string", "int"], result);
+}
+
+fn explain(out: &mut dyn Output, db: &mut Database, table_name: &str, source: &Source, filter: &[Predicate]) {
+    let (table_name, filter) = match source {
+        Source::Current => match db.resolve_view(table_name, filter) {
+            Ok(resolved) => resolved,
+            Err(e) => return out.failure(&e),
+        },
+        _ => (table_name.to_string(), filter.to_vec()),
+    };
+    let functions = db.functions();
+    let snapshot = match source {
+        Source::Current => db.snapshot_for(&table_name, &filter),
+        source => db.snapshot_of(&table_name, source).map(|table| (table, None)),
+    };
+    let plan = snapshot.and_then(|(table, partitions)| {
+        let plan = planner::plan(&table, &filter, &functions)?.to_string();
+        Ok(match partitions {
+            Some(partitions) if partitions.is_empty() => format!("{}\n  Partitions: none", plan),
+            Some(partitions) => format!("{}\n  Partitions: {}", plan, partitions.join(", ")),
+            None => plan,
+        })
+    });
+    match plan {
+        Ok(plan) => say!(out, "{}", plan),
+        Err(e) => out.failure(&e),
+    }
+}
+
+// Runs the query, dropping its rows, and reports each step it took
+fn explain_analyze(out: &mut dyn Output, db: &mut Database, tables: &[TableRef], columns: &[Expr], filter: &[Predicate], order: &Order) {
+    let started = Instant::now();
+    profile::start();
+    let result = db.select_from(tables, columns, filter, order);
+    let steps = profile::finish();
+    let elapsed = started.elapsed();
+    match result {
+        Ok(rows) => {
+            for step in steps {
+                say!(out, "{}", step);
+            }
+            say!(out, "Total: {} row(s) in {:.3} ms", rows.rows.len(), elapsed.as_secs_f64() * 1000.0);
+        }
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn migration_failed(migration: &migrations::Migration, reason: String) -> DbError {
+    DbError::MigrationFailed { version: migration.version, name: migration.name.clone(), reason }
+}
+
+// Comes after the errors of the script's own statements, which say why
+fn script_failed(name: &str, outcome: String) -> DbError {
+    DbError::ScriptFailed { script: name.to_string(), outcome }
+}
+
+fn describe_filter(filter: &[Predicate]) -> String {
+    filter.iter().map(Predicate::to_string).collect::<Vec<_>>().join(" AND ")
+}
+
+fn select_rows(out: &mut dyn Output, db: &mut Database, tables: &[TableRef], columns: &[Expr], filter: &[Predicate], order: &Order) {
+    let result = match db.select_from(tables, columns, filter, order) {
+        Ok(result) => result,
+        Err(e) => {
+            out.failure(&e);
+            return;
+        }
+    };
+    if !filter.is_empty() && result.rows.is_empty() {
+        say!(out, "No row found with {}", describe_filter(filter));
+        return;
+    }
+
+    show_rows(out, &result);
+}
+
+/// Runs the query of a cursor up to where its rows are known, leaving them
+/// to be fetched.
+fn declare(out: &mut dyn Output, db: &mut Database, cursors: &mut HashMap<String, Cursor>, name: String, query: Statement) {
+    if cursors.contains_key(&name) {
+        return out.failure(&DbError::CursorExists(name));
+    }
+    let Statement::Select { from, joins, columns, filter, order } = query else {
+        unreachable!("the parser only declares cursors for SELECT");
+    };
+    match db.cursor(&TableRef::list(from, joins), &columns, &filter, &order) {
+        Ok(cursor) => {
+            say!(out, "Cursor '{}' declared ({} row(s))", name, cursor.remaining());
+            cursors.insert(name, cursor);
+        }
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn fetch(out: &mut dyn Output, cursors: &mut HashMap<String, Cursor>, name: &str, count: Option<usize>) {
+    let Some(cursor) = cursors.get_mut(name) else {
+        return out.failure(&DbError::CursorNotFound(name.to_string()));
+    };
+    match cursor.fetch(count.unwrap_or(usize::MAX)) {
+        Ok(result) => show_rows(out, &result),
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn show_rows(out: &mut dyn Output, result: &Rows) {
+    let columns: Vec<&str> = result.columns.iter().map(String::as_str).collect();
+    let types: Vec<&str> = result.types.iter().map(String::as_str).collect();
+    let rows = result.rows.iter()
+        .map(|row| row.iter().map(DataType::to_string).collect())
+        .collect();
+    out.rows(&columns, &types, rows);
+}
+
+fn delete_rows(out: &mut dyn Output, db: &mut Database, table_name: &str, filter: &[Predicate], returning: Option<&[Expr]>) {
+    if let Err(e) = check_returning(db, table_name, returning) {
+        return out.failure(&e);
+    }
+    match delete(db, table_name, filter, 0) {
+        Ok(old) if old.is_empty() => out.failure(&DbError::NoMatch(describe_filter(filter))),
+        Ok(old) => {
+            out.affected(old.len());
+            match returning {
+                Some(columns) => show_returning(out, db, table_name, old, columns),
+                None => say!(out, "{} row(s) deleted", old.len()),
+            }
+        }
+        Err(e) => out.failure(&e),
+    }
+}
+
+/// Deletes the matching rows, with the table's DELETE triggers around it.
+/// Returns the rows deleted.
+fn delete(db: &mut Database, table_name: &str, filter: &[Predicate], depth: usize) -> Result<Vec<Vec<DataType>>, DbError> {
+    db.check_writable(table_name)?;
+    let functions = db.functions();
+    let table = db.load_table(table_name)?;
+    let rows = live_matching_rows(table, filter, &functions)?;
+    if rows.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let triggers = triggers_for(table, Event::Delete);
+    if triggers.is_empty() {
+        let (old, op) = (row_values(table, &rows), tombstones::delete_op(table, rows));
+        db.log(op)?;
+        return Ok(old);
+    }
+
+    // The rows and everything their triggers do stand or fall together
+    let (columns, before) = (table.columns.clone(), shown_rows(table, &row_values(table, &rows)));
+    db.atomically(|db| {
+        fire(db, &triggers, Timing::Before, &columns, &before, depth)?;
+
+        // BEFORE triggers may have changed the table, moving or removing rows
+        let table = db.load_table(table_name)?;
+        let rows = live_matching_rows(table, filter, &functions)?;
+        let old = row_values(table, &rows);
+        let shown = shown_rows(table, &old);
+        if !rows.is_empty() {
+            let op = tombstones::delete_op(table, rows);
+            db.log(op)?;
+        }
+        fire(db, &triggers, Timing::After, &columns, &shown, depth)?;
+        Ok(old)
+    })
+}
+
+fn count_rows(out: &mut dyn Output, db: &mut Database, table_name: &str) {
+    let functions = db.functions();
+    let count = db.resolve_view(table_name, &[]).and_then(|(base, filter)| {
+        if filter.is_empty() {
+            return db.count_rows(&base);
+        }
+        let table = db.snapshot(&base)?;
+        matching_rows(&table, &filter, &functions).map(|rows| rows.len())
+    });
+    match count {
+        Ok(count) => say!(out, "Table '{}' contains {} row(s).", table_name, count),
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn checkpoint(out: &mut dyn Output, db: &mut Database) -> usize {
+    db.checkpoint().unwrap_or_else(|e| {
+        out.failure(&e);
+        0
+    })
+}
+
+fn vacuum(out: &mut dyn Output, db: &mut Database, table: Option<&str>) {
+    match db.vacuum(table) {
+        Ok(report) => say!(
+            out, "Vacuumed {} table(s): {} bytes reclaimed ({} -> {} bytes)",
+            report.tables, report.reclaimed(), report.bytes_before, report.bytes_after
+        ),
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn begin(out: &mut dyn Output, db: &mut Database) {
+    match db.begin() {
+        Ok(()) => say!(out, "Transaction started"),
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn commit(out: &mut dyn Output, db: &mut Database) {
+    match db.commit() {
+        Ok(count) => say!(out, "Transaction committed ({} change(s))", count),
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn rollback(out: &mut dyn Output, db: &mut Database) {
+    match db.rollback() {
+        Ok(count) => say!(out, "Transaction rolled back ({} change(s) discarded)", count),
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn savepoint(out: &mut dyn Output, db: &mut Database, name: &str) {
+    match db.savepoint(name) {
+        Ok(()) => say!(out, "Savepoint '{}' set", name),
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn rollback_to(out: &mut dyn Output, db: &mut Database, name: &str) {
+    match db.rollback_to(name) {
+        Ok(count) => say!(out, "Rolled back to savepoint '{}' ({} change(s) discarded)", name, count),
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn release(out: &mut dyn Output, db: &mut Database, name: &str) {
+    match db.release(name) {
+        Ok(()) => say!(out, "Savepoint '{}' released", name),
+        Err(e) => out.failure(&e),
+    }
+}
+
+// Leaving with a transaction open discards it, as if the session had crashed
+fn shutdown(out: &mut dyn Output, db: &mut Database) {
+    if db.in_transaction() {
+        rollback(out, db);
+    }
+    if !db.is_read_only() {
+        checkpoint(out, db);
+    }
+}
+
+fn open_database(out: &mut dyn Output, db: &mut Database, dir: &std::path::Path) -> bool {
+    // USE keeps a read-only session read-only, and the passphrase it was given
+    let opened = match db.is_read_only() {
+        true => Database::open_read_only_with_key(dir, db.passphrase()),
+        false => Database::open_dir_with_key(dir, db.passphrase()),
+    };
+    match opened {
+        Ok(opened) => {
+            // Limits come from the command line and config, not the database
+            let limits = db.limits();
+            *db = opened;
+            db.set_limits(limits);
+            run_recovery(out, db);
+            true
+        }
+        Err(e) => {
+            out.failure(&DbError::Io(e));
+            false
+        }
+    }
+}
+
+pub fn run_recovery(out: &mut dyn Output, db: &mut Database) {
+    match recovery::recover(db) {
+        Ok(report) => {
+            for line in report {
+                say!(out, "Recovery: {}", line);
+            }
+        }
+        Err(e) => out.failure(&e),
+    }
+}
+
+// Named databases only exist when running from a data directory
+fn data_root<'a>(out: &mut dyn Output, root: &'a Option<DataRoot>) -> Option<&'a DataRoot> {
+    if root.is_none() {
+        out.failure(&DbError::Unavailable("Multiple databases need a data directory (not a single file or --memory)".to_string()));
+    }
+    root.as_ref()
+}
+
+fn create_database(out: &mut dyn Output, root: &Option<DataRoot>, name: &str) {
+    let Some(root) = data_root(out, root) else { return };
+    match root.create(name) {
+        Ok(()) => say!(out, "Database '{}' created", name),
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn drop_database(out: &mut dyn Output, root: &Option<DataRoot>, current: &str, name: &str) {
+    let Some(root) = data_root(out, root) else { return };
+    if name == current {
+        return out.failure(&DbError::DatabaseInUse(name.to_string()));
+    }
+    match root.drop(name) {
+        Ok(()) => say!(out, "Database '{}' dropped", name),
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn show_databases(out: &mut dyn Output, root: &Option<DataRoot>, current: &str) {
+    let Some(root) = data_root(out, root) else { return };
+    match root.list() {
+        Ok(names) => {
+            for name in names {
+                let marker = if name == current { " (current)" } else { "" };
+                say!(out, "{}{}", name, marker);
+            }
+        }
+        Err(e) => out.failure(&e),
+    }
+}
+
+fn use_database(out: &mut dyn Output, root: &Option<DataRoot>, db: &mut Database, current: &mut String, name: &str) {
+    let Some(root) = data_root(out, root) else { return };
+    if !root.exists(name) {
+        out.failure(&DbError::DatabaseNotFound(name.to_string()));
+        return;
+    }
+    // Reopening would conflict with the lock this session already holds
+    if name == current {
+        say!(out, "Database changed to '{}'", name);
+        return;
+    }
+    let Ok(dir) = root.path_of(name) else { return };
+
+    // Leave the current database with clean table files
+    checkpoint(out, db);
+    if open_database(out, db, &dir) {
+        *current = name.to_string();
+        say!(out, "Database changed to '{}'", name);
+    }
+}
+
+fn print_help(out: &mut dyn Output) {
+    say!(out, "DDL:");
+    say!(out, "  CREATE TABLE <name> <col:type> [PRIMARY KEY] <col:type> [GENERATED AS (<expr>)]...");
+    say!(out, "  CREATE TEMP TABLE <name> <col:type>...");
+    say!(out, "  CREATE INDEX <name> ON <table>(<col>, ...) [USING BTREE|HASH|FULLTEXT]");
+    say!(out, "  DROP INDEX <name> [ON <table>]");
+    say!(out, "  SHOW INDEXES FROM <table>");
+    say!(out, "  REINDEX <table>   (rebuilds the table's indexes and rewrites its index file)");
+    say!(out, "  REFRESH [TABLE] <table>   (reads the table from its file again, after another program changed it)");
+    say!(out, "  CREATE TABLE ... PARTITION BY RANGE (<col>) (PARTITION <name> VALUES LESS THAN (<value>)|MAXVALUE, ...)");
+    say!(out, "  CREATE TABLE ... PARTITION BY KEY (<col>) PARTITIONS <n>");
+    say!(out, "  CREATE EXTERNAL TABLE <name> <col:type>... LOCATION '<file.csv>' [DELIMITER '<char>'] [NO HEADER]");
+    say!(out, "  ALTER TABLE <table> ADD PARTITION <name> VALUES LESS THAN (<value>)|MAXVALUE");
+    say!(out, "  ALTER TABLE <table> DROP PARTITION <name>");
+    say!(out, "  ALTER TABLE <table> SET HISTORY RETENTION <n> SECONDS|MINUTES|HOURS|DAYS|WEEKS|OFF");
+    say!(out, "  CREATE TABLE ... WITH TTL <col>   (rows expire at the time in <col>)");
+    say!(out, "  ALTER TABLE <table> SET TTL <col>|OFF");
+    say!(out, "  ALTER TABLE <table> SET MAX ROWS <n>|OFF");
+    say!(out, "  CREATE TABLE ... WITH SOFT DELETE   (DELETE only marks rows deleted)");
+    say!(out, "  ALTER TABLE <table> SET SOFT DELETE ON|OFF");
+    say!(out, "  CREATE TABLE ... ENGINE = JSON|BINARY   (how the rows are laid out in the table's file)");
+    say!(out, "  ALTER TABLE <table> ENGINE = JSON|BINARY");
+    say!(out, "  DROP TABLE <name> [CASCADE]   (CASCADE drops the views and triggers depending on it too)");
+    say!(out, "  CREATE VIEW <name> AS SELECT * FROM <table> [WHERE ...]");
+    say!(out, "  CREATE MATERIALIZED VIEW <name> AS SELECT ...");
+    say!(out, "  REFRESH MATERIALIZED VIEW <name>");
+    say!(out, "  DROP VIEW <name> [CASCADE]");
+    say!(out, "  CREATE TRIGGER <name> BEFORE|AFTER INSERT|DELETE ON <table> DO '<statement>; ...'");
+    say!(out, "  DROP TRIGGER <name> ON <table>");
+    say!(out, "  SHOW TABLES");
+    say!(out, "  SHOW TABLE STATUS");
+    say!(out, "  SHOW CREATE TABLE <table>|VIEW <view>");
+    say!(out, "  DESCRIBE <table>   (its columns, with their types and comments)");
+    say!(out, "  CREATE SEQUENCE <name> [START WITH <n>] [INCREMENT BY <n>]");
+    say!(out, "  DROP SEQUENCE <name> [CASCADE]");
+    say!(out, "  SHOW SEQUENCES");
+    say!(out, "  ALTER TABLE <table> ALTER COLUMN <col> SET DEFAULT NEXTVAL('<sequence>')|DROP DEFAULT");
+    say!(out, "  COMMENT ON TABLE <table>|COLUMN <table>.<col> IS '<text>'|NULL");
+    say!(out, "  CREATE DATABASE <name>");
+    say!(out, "  DROP DATABASE <name>");
+    say!(out, "  SHOW DATABASES");
+    say!(out, "  USE <name>");
+    say!(out, "  CREATE USER <name> PASSWORD '<password>' [SUPERUSER]");
+    say!(out, "  DROP USER <name>");
+    say!(out, "  SHOW USERS");
+    say!(out, "  CREATE TOKEN <name> FOR <user>   (an access token to log in with instead of the password)");
+    say!(out, "  DROP TOKEN <name>");
+    say!(out, "  SHOW TOKENS");
+    say!(out, "  GRANT SELECT|INSERT|UPDATE|DELETE|ALL, ... ON <table> TO <user>");
+    say!(out, "  REVOKE <privilege>, ... ON <table> FROM <user>");
+    say!(out, "  SHOW GRANTS FOR <user>");
+    say!(out, "  SHOW PROCESSLIST   (on a server: its sessions and what they run)");
+    say!(out, "  KILL <session id>\n");
+
+    say!(out, "DML:");
+    say!(out, "  INSERT INTO <table> VALUES <id> <name>");
+    say!(out, "  INSERT INTO <table> VALUES (<id>, <name>) ON CONFLICT [(<col>)] DO NOTHING|DO UPDATE SET <col> = <value>|EXCLUDED.<col>, ...");
+    say!(out, "  INSERT INTO <table> VALUES (NEXTVAL('<sequence>'), <name>)");
+    say!(out, "  SELECT NEXTVAL('<sequence>')   SELECT SETVAL('<sequence>', <n>)");
+    say!(out, "  SELECT * FROM <table>");
+    say!(out, "  SELECT * FROM <table> WHERE <col> = <value>");
+    say!(out, "  SELECT * FROM <table>, <table> [CROSS JOIN <table>] WHERE <table>.<col> = <table>.<col>");
+    say!(out, "  SELECT * FROM <table> [AS] <alias> JOIN <table> <alias> ON <alias>.<col> = <alias>.<col>");
+    say!(out, "  SELECT * FROM <table> AS OF 'YYYY-MM-DD HH:MM:SS' [[AS] <alias>] ...");
+    say!(out, "  SELECT ... [GROUP BY <expr>, ...] [ORDER BY <expr> [ASC|DESC], ...] [LIMIT <n>]");
+    say!(out, "  SELECT COUNT(*)|COUNT|SUM|AVG|MIN|MAX(<expr>), ... FROM ... [GROUP BY <expr>, ...]");
+    say!(out, "  WITH <name> [(<col>, ...)] AS (SELECT ...), ... SELECT ... FROM <name> ...");
+    say!(out, "  WITH RECURSIVE <name> AS (SELECT ... UNION [ALL] SELECT ... FROM <name> ...) SELECT ...");
+    say!(out, "  SELECT ROW_NUMBER()|RANK()|SUM(<expr>)|AVG(<expr>) OVER ([PARTITION BY <expr>, ...] [ORDER BY <expr>, ...]) FROM ...");
+    say!(out, "  UPDATE <table> SET <col> = <value>, ... WHERE <col> = <value>");
+    say!(out, "  DELETE FROM <table> WHERE <col> = <value>");
+    say!(out, "  UNDELETE FROM <table> [WHERE ...]   PURGE FROM <table> [WHERE ...]");
+    say!(out, "  SELECT * FROM <table> WITH DELETED ...");
+    say!(out, "  INSERT|UPDATE|DELETE ... RETURNING *|<col>, ...");
+    say!(out, "  COUNT <table>");
+    say!(out, "  IMPORT CSV '<file>' INTO <table> [DELIMITER '<char>'|TAB] [NO HEADER]");
+    say!(out, "  IMPORT JSONL '<file>' INTO <table> [IGNORE UNKNOWN]");
+    say!(out, "  COPY <table> FROM STDIN [CSV|JSONL] [<IMPORT options>]");
+    say!(out, "  EXPORT (SELECT ...) TO '<file>' [FORMAT CSV|JSONL] [DELIMITER ...] [NO HEADER]");
+    say!(out, "  EXPORT TABLE <table> TO '<file>' [FORMAT CSV|JSONL]");
+    say!(out, "  EXPLAIN [ANALYZE] SELECT ...");
+    say!(out, "  DECLARE <cursor> CURSOR FOR SELECT ...");
+    say!(out, "  FETCH [<n>|NEXT|ALL] [FROM] <cursor>");
+    say!(out, "  CLOSE <cursor>|ALL");
+    say!(out, "  (WHERE also accepts != <> < <= > >=, MATCH, CONTAINS, = ANY(<array>) and AND; quote strings as 'text')\n");
+
+    say!(out, "Transactions:");
+    say!(out, "  BEGIN");
+    say!(out, "  COMMIT");
+    say!(out, "  ROLLBACK");
+    say!(out, "  SAVEPOINT <name>");
+    say!(out, "  ROLLBACK TO <name>");
+    say!(out, "  RELEASE <name>\n");
+
+    say!(out, "Maintenance:");
+    say!(out, "  CHECKPOINT");
+    say!(out, "  FLUSH");
+    say!(out, "  VACUUM [<table>]");
+    say!(out, "  SET COMPRESSION none|gzip");
+    say!(out, "  ANALYZE <table>");
+    say!(out, "  SHOW STATS <table>");
+    say!(out, "  DUMP DATABASE TO '<file>'");
+    say!(out, "  BACKUP DATABASE TO '<dir>|<file.rdb>'");
+    say!(out, "  RESTORE DATABASE FROM '<dir>|<file.rdb>' [UNTIL 'YYYY-MM-DD HH:MM:SS']");
+    say!(out, "  SET statement_timeout = <ms>   (0 for none)");
+    say!(out, "  SET autocommit = ON | OFF");
+    say!(out, "  SET synchronous = FULL | BATCHED");
+    say!(out, "  SET @<name> = <expr>   (then @<name> in later statements)");
+    say!(out, "  SET WAL ARCHIVE '<dir>'|OFF");
+    say!(out, "  REKEY '<passphrase>'|OFF");
+    say!(out, "  MIGRATE ['<dir>']");
+    say!(out, "  SOURCE '<file>' [CONTINUE ON ERROR | SINGLE TRANSACTION]");
+    say!(out, "  PROMOTE   (on a follower: stop following and take writes)");
+    say!(out, "  SUBSCRIBE TO <table>   (over a connection: stream its committed changes as JSON)");
+}
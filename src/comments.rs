@@ -0,0 +1,36 @@
+//! Comments on tables and their columns, so a schema can document itself:
+//! `COMMENT ON TABLE users IS 'Everyone who can log in'` and `COMMENT ON
+//! COLUMN users.age IS 'In whole years'`, or `IS NULL` to remove one. They
+//! are saved with the table and shown by `DESCRIBE`, `SHOW CREATE TABLE`
+//! and the system catalog.
+
+use crate::catalog;
+use crate::database::Database;
+use crate::error::DbError;
+
+impl Database {
+    /// Sets the comment of `table`, or of its `column` if given. None or an
+    /// empty comment removes it.
+    pub fn set_comment(&mut self, table_name: &str, column: Option<&str>, comment: Option<String>) -> Result<(), DbError> {
+        if catalog::is_system_table(table_name) {
+            return Err(DbError::SystemTable(table_name.to_string()));
+        }
+        let mut table = self.load_table(table_name)?.clone();
+        let comment = comment.filter(|comment| !comment.is_empty());
+        match column {
+            None => table.comment = comment,
+            Some(column) if !table.fields.contains_key(column) => {
+                return Err(DbError::ColumnNotFound { table: table_name.to_string(), column: column.to_string() });
+            }
+            Some(column) => match comment {
+                Some(comment) => {
+                    table.column_comments.insert(column.to_string(), comment);
+                }
+                None => {
+                    table.column_comments.remove(column);
+                }
+            },
+        }
+        self.save_table(&table)
+    }
+}
@@ -0,0 +1,180 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+
+use rust_db::parser::{self, Token};
+use rust_db::Database;
+
+use crate::commands::Engine;
+
+const KEYWORDS: [&str; 195] = [
+    "ADD", "AFTER", "ALL", "ALWAYS", "ANALYZE", "AND", "ANY", "ARCHIVE", "ARRAY", "AS",
+    "ASC", "AUTOCOMMIT", "AVG", "BACKUP", "BATCHED", "BEFORE", "BEGIN", "BETWEEN", "BINARY", "BY",
+    "CASCADE", "CAST", "CHECKPOINT", "CLOSE", "COLLATE", "COLUMN", "COMMENT", "COMMIT", "COMPRESSION", "CONFLICT",
+    "CONTAINS", "CONTINUE", "COPY", "COUNT", "CREATE", "CROSS", "CSV", "CURSOR", "DATABASE", "DATABASES",
+    "DAY", "DAYS", "DECLARE", "DEFAULT", "DELETE", "DELETED", "DELIMITER", "DESC", "DESCRIBE", "DISTANCE",
+    "DO", "DROP", "DUMP", "EACH", "ENGINE", "ENUM", "ERROR", "EXCLUDED", "EXISTS", "EXIT",
+    "EXPLAIN", "EXPORT", "EXTERNAL", "FETCH", "FLUSH", "FOR", "FORMAT", "FROM", "FULL", "GENERATED",
+    "GRANT", "GRANTS", "GROUP", "HASH", "HEADER", "HELP", "HISTORY", "HOUR", "HOURS", "IGNORE",
+    "IMPORT", "IN", "INCREMENT", "INDEX", "INDEXES", "INNER", "INSERT", "INTERVAL", "INTO", "IS",
+    "JOIN", "JSON", "JSONL", "KEY", "KILL", "LESS", "LIMIT", "LOCATION", "MATCH", "MATERIALIZED",
+    "MAX", "MAXVALUE", "MIGRATE", "MIN", "MINUTE", "MINUTES", "NEXT", "NEXTVAL", "NO", "NOCASE",
+    "NOT", "NOTHING", "OF", "OFF", "ON", "ORDER", "OVER", "PARQUET", "PARTITION", "PARTITIONS",
+    "PASSWORD", "PATH", "POINT", "PRIMARY", "PRIVILEGES", "PROCESSLIST", "PROMOTE", "PURGE", "RANGE", "RANK",
+    "RECURSIVE", "REFRESH", "REINDEX", "REKEY", "RELEASE", "RESTORE", "RETENTION", "RETURNING", "REVOKE", "ROLLBACK",
+    "ROW", "ROWS", "ROW_NUMBER", "SAVEPOINT", "SECOND", "SECONDS", "SELECT", "SEQUENCE", "SEQUENCES", "SET",
+    "SETVAL", "SHOW", "SINGLE", "SOFT", "SOURCE", "START", "STATEMENT_TIMEOUT", "STATS", "STATUS", "STDIN",
+    "STORED", "SUBSCRIBE", "SUM", "SUPERUSER", "SYNCHRONOUS", "TAB", "TABLE", "TABLES", "TEMP", "TEMPORARY",
+    "THAN", "TIMESTAMPS", "TO", "TOKEN", "TOKENS", "TRANSACTION", "TRIGGER", "TTL", "UNDELETE", "UNICODE",
+    "UNION", "UNKNOWN", "UNTIL", "UPDATE", "USE", "USER", "USERS", "USING", "VACUUM", "VALUES",
+    "VIEW", "WAL", "WEEK", "WEEKS", "WHERE",
+];
+
+// The keywords a statement can start with
+const STATEMENTS: [&str; 45] = [
+    "ALTER", "ANALYZE", "BACKUP", "BEGIN", "CHECKPOINT", "CLOSE", "COMMENT", "COMMIT", "COPY", "COUNT",
+    "CREATE", "DECLARE", "DELETE", "DESCRIBE", "DROP", "DUMP", "EXIT", "EXPLAIN", "EXPORT", "FETCH",
+    "FLUSH", "GRANT", "HELP", "IMPORT", "INSERT", "KILL", "MIGRATE", "PROMOTE", "PURGE", "REFRESH",
+    "REINDEX", "REKEY", "RELEASE", "RESTORE", "REVOKE", "ROLLBACK", "SAVEPOINT", "SELECT", "SHOW", "SOURCE",
+    "SUBSCRIBE", "UNDELETE", "USE", "VACUUM", "WITH",
+];
+
+// The keywords that can come up in a select list or conditions
+const IN_COLUMNS: [&str; 4] = ["AND", "FROM", "MATCH", "WHERE"];
+
+// Words followed by a table name
+const BEFORE_TABLE: [&str; 8] = ["FROM", "INTO", "TABLE", "ON", "COUNT", "ANALYZE", "STATS", "VACUUM"];
+
+/// Completes the word under the cursor at the prompt: a statement keyword at
+/// the start, a table after FROM, INTO and the like, and a column of the
+/// statement's table in the select list and conditions. Tables and columns
+/// are looked up in the open database at the time Tab is pressed. Without a
+/// database (`connect`) only keywords are offered.
+pub struct Completion {
+    engine: Option<Rc<RefCell<Engine>>>,
+}
+
+impl Completion {
+    pub fn new(engine: Option<Rc<RefCell<Engine>>>) -> Completion {
+        Completion { engine }
+    }
+
+    fn candidates(&self, before: &[Token], line: &[Token], word: &str) -> Vec<String> {
+        let previous = match before.last() {
+            None => return matching(&STATEMENTS, word),
+            Some(Token::Ident(previous)) => previous.to_ascii_uppercase(),
+            Some(_) => String::new(),
+        };
+        let Some(engine) = &self.engine else {
+            return matching(&KEYWORDS, word);
+        };
+        let Ok(mut engine) = engine.try_borrow_mut() else {
+            return Vec::new();
+        };
+        let db = &mut engine.db;
+
+        if BEFORE_TABLE.contains(&previous.as_str()) {
+            let mut names = db.table_names().unwrap_or_default();
+            names.extend(db.views().unwrap_or_default().into_iter().map(|view| view.name));
+            return names.into_iter().filter(|name| starts_with(name, word)).collect();
+        }
+        if in_columns(before) {
+            let columns = match table_of(line) {
+                Some(table) => columns(db, &table),
+                // Nothing to go by before FROM is written, so any table's
+                None => {
+                    let mut all: Vec<String> = db.table_names().unwrap_or_default().iter()
+                        .flat_map(|table| columns(db, table))
+                        .collect();
+                    all.sort();
+                    all.dedup();
+                    all
+                }
+            };
+            let mut found: Vec<String> = columns.into_iter().filter(|col| starts_with(col, word)).collect();
+            found.extend(matching(&IN_COLUMNS, word));
+            return found;
+        }
+        matching(&KEYWORDS, word)
+    }
+}
+
+impl Completer for Completion {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos]
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .map_or(0, |i| i + line[i..].chars().next().map_or(1, char::len_utf8));
+        // Nothing is offered inside a string literal
+        if parser::unterminated(&line[..start]) {
+            return Ok((start, Vec::new()));
+        }
+        let (Ok(before), Ok(tokens)) = (parser::tokenize(&line[..start]), parser::tokenize(line)) else {
+            return Ok((start, Vec::new()));
+        };
+        let mut candidates = self.candidates(&before, &tokens, &line[start..pos]);
+        candidates.dedup();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for Completion {
+    type Hint = String;
+}
+
+impl Highlighter for Completion {}
+
+impl Validator for Completion {}
+
+impl Helper for Completion {}
+
+/// The keywords starting with `word`, in the case it is being typed in.
+fn matching(keywords: &[&str], word: &str) -> Vec<String> {
+    let lower = !word.is_empty() && word.chars().all(|c| !c.is_uppercase());
+    keywords.iter()
+        .filter(|keyword| starts_with(keyword, word))
+        .map(|keyword| if lower { keyword.to_ascii_lowercase() } else { keyword.to_string() })
+        .collect()
+}
+
+fn starts_with(name: &str, word: &str) -> bool {
+    name.len() >= word.len() && name.is_char_boundary(word.len()) && name[..word.len()].eq_ignore_ascii_case(word)
+}
+
+fn is_keyword(token: &Token, keyword: &str) -> bool {
+    matches!(token, Token::Ident(word) if word.eq_ignore_ascii_case(keyword))
+}
+
+/// Whether the cursor is in a select list or a condition: after SELECT and
+/// before FROM, or after WHERE.
+fn in_columns(before: &[Token]) -> bool {
+    let last = before.iter().rposition(|token| {
+        ["SELECT", "FROM", "WHERE", "VALUES", "INTO"].iter().any(|keyword| is_keyword(token, keyword))
+    });
+    last.is_some_and(|i| is_keyword(&before[i], "SELECT") || is_keyword(&before[i], "WHERE"))
+}
+
+/// The table the statement reads from or writes to, wherever it appears
+/// in the line.
+fn table_of(tokens: &[Token]) -> Option<String> {
+    tokens.windows(2).find_map(|pair| match &pair[1] {
+        Token::Ident(name) if is_keyword(&pair[0], "FROM") || is_keyword(&pair[0], "ON") => Some(name.clone()),
+        _ => None,
+    })
+}
+
+/// The columns of a table, or of the table under a view.
+fn columns(db: &mut Database, name: &str) -> Vec<String> {
+    let mut name = name.to_string();
+    // Views may be built on views, but not in a cycle
+    while let Ok(Some(view)) = db.view(&name) {
+        name = view.table;
+    }
+    db.definition(&name).map(|table| table.columns).unwrap_or_default()
+}
@@ -0,0 +1,133 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+/// Read from the working directory at startup if it exists.
+pub const DEFAULT_PATH: &str = "rustdb.toml";
+
+/// The settings of `rustdb.toml`. Every one is optional, and a command-line
+/// flag for the same thing wins over it.
+///
+/// ```toml
+/// data_dir = "/var/lib/rustdb"
+/// format = "vertical"
+/// log_level = "info"
+/// slow_query_ms = 200
+/// slow_query_log = "slow.log"
+/// encryption_key = "correct horse battery staple"
+///
+/// [server]
+/// host = "0.0.0.0"
+/// port = 4000
+/// pg_port = 5432
+/// http_port = 8080
+/// auth = "token"
+/// tls_cert = "server.crt"
+/// tls_key = "server.key"
+///
+/// [server.pg]
+/// auth = "password"
+///
+/// [server.http]
+/// allowed_origins = ["https://dashboard.example.com"]
+///
+/// [wal]
+/// checkpoint_bytes = 16777216
+///
+/// [cache]
+/// tables = 256
+/// results = 100
+///
+/// [query]
+/// max_recursion = 1000
+/// statement_timeout_ms = 5000
+/// max_memory_mb = 512
+///
+/// [quota]
+/// max_database_mb = 10240
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub data_dir: Option<PathBuf>,
+    pub format: Option<String>,
+    pub log_level: Option<String>,
+    pub slow_query_ms: Option<u64>,
+    pub slow_query_log: Option<PathBuf>,
+    pub encryption_key: Option<String>,
+    pub server: ServerConfig,
+    pub wal: WalConfig,
+    pub cache: CacheConfig,
+    pub query: QueryConfig,
+    pub quota: QuotaConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub pg_port: Option<u16>,
+    pub http_port: Option<u16>,
+    pub auth: Option<String>,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    // What differs for one listener from the above
+    pub native: ListenerConfig,
+    pub pg: ListenerConfig,
+    pub http: ListenerConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ListenerConfig {
+    pub auth: Option<String>,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    pub allowed_origins: Vec<String>, // HTTP only: pages that may call it (CORS)
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WalConfig {
+    pub checkpoint_bytes: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    pub tables: Option<usize>,
+    pub results: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueryConfig {
+    pub max_recursion: Option<usize>,
+    pub statement_timeout_ms: Option<u64>,
+    pub max_memory_mb: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuotaConfig {
+    pub max_database_mb: Option<u64>,
+}
+
+/// Reads the config file at `path`, or `rustdb.toml` if there is one when
+/// no path is given. Relative paths inside it are taken from the working
+/// directory, like those given as flags.
+pub fn load(path: Option<&Path>) -> Result<Config, String> {
+    let (path, required) = match path {
+        Some(path) => (path, true),
+        None => (Path::new(DEFAULT_PATH), false),
+    };
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound && !required => return Ok(Config::default()),
+        Err(e) => return Err(format!("Could not read '{}': {}", path.display(), e)),
+    };
+    toml::from_str(&text).map_err(|e| format!("Invalid config file '{}': {}", path.display(), e.message()))
+}
@@ -0,0 +1,165 @@
+use crate::error::DbError;
+use crate::progress;
+use crate::query::Rows;
+use crate::{parse_value, DataType, Table};
+
+/// How a CSV file is laid out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvOptions {
+    /// The first record names the columns, in any order. Without a header
+    /// every record holds all the table's columns, in table order.
+    pub header: bool,
+    pub delimiter: char,
+}
+
+impl Default for CsvOptions {
+    fn default() -> CsvOptions {
+        CsvOptions { header: true, delimiter: ',' }
+    }
+}
+
+/// Splits CSV text into records of fields, following RFC 4180: fields may
+/// be quoted with `"`, a quoted field may hold delimiters, line breaks and
+/// `""` for a quote, and lines end with `\n` or `\r\n`. Blank lines are
+/// skipped. Each record comes with the line it starts on.
+pub fn parse(text: &str, delimiter: char) -> Result<Vec<(usize, Vec<String>)>, DbError> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut blank = true; // Nothing of the current record seen yet
+    let mut line = 1;
+    let mut start = 1;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\n' && c != '\r' {
+            blank = false;
+        }
+        if quoted {
+            match c {
+                '"' if chars.next_if_eq(&'"').is_some() => field.push('"'),
+                '"' => quoted = false,
+                '\n' => {
+                    line += 1;
+                    field.push(c);
+                    progress::advance(1);
+                }
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => quoted = true,
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                if !blank {
+                    record.push(std::mem::take(&mut field));
+                    records.push((start, std::mem::take(&mut record)));
+                }
+                blank = true;
+                line += 1;
+                start = line;
+                progress::advance(1);
+            }
+            c if c == delimiter => record.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(DbError::ImportFailed { line: start, reason: "unterminated quoted field".to_string() });
+    }
+    if !blank {
+        record.push(field);
+        records.push((start, record));
+    }
+    Ok(records)
+}
+
+/// Appends one record to `out`, quoting the fields that hold the delimiter,
+/// a quote or a line break, and ending it with `\r\n` as RFC 4180 asks.
+pub fn write_record<S: AsRef<str>>(out: &mut String, fields: &[S], delimiter: char) {
+    for (i, field) in fields.iter().enumerate() {
+        let field = field.as_ref();
+        if i > 0 {
+            out.push(delimiter);
+        }
+        if field.contains([delimiter, '"', '\r', '\n']) {
+            out.push('"');
+            out.push_str(&field.replace('"', "\"\""));
+            out.push('"');
+        } else {
+            out.push_str(field);
+        }
+    }
+    out.push_str("\r\n");
+}
+
+/// A query result as CSV text, with a header naming the columns unless
+/// `options.header` is off.
+pub fn write(result: &Rows, options: &CsvOptions) -> String {
+    let mut text = String::new();
+    if options.header {
+        write_record(&mut text, &result.columns, options.delimiter);
+    }
+    for row in &result.rows {
+        let fields: Vec<String> = row.iter().map(DataType::to_string).collect();
+        write_record(&mut text, &fields, options.delimiter);
+    }
+    text
+}
+
+/// The rows of CSV text for `table`, each field converted to its column's
+/// type, with the line each row starts on.
+pub fn rows(table: &Table, text: &str, options: &CsvOptions) -> Result<Vec<(usize, Vec<DataType>)>, DbError> {
+    let mut records = parse(text, options.delimiter)?.into_iter();
+
+    // Position in the record of each of the table's columns (none for a
+    // generated column or timestamp, which is set whatever the file says), and how
+    // many fields a record has
+    let (positions, width): (Vec<Option<usize>>, usize) = if options.header {
+        let Some((line, header)) = records.next() else {
+            return Ok(Vec::new());
+        };
+        if let Some(name) = header.iter().find(|name| !table.fields.contains_key(name.as_str())) {
+            return Err(DbError::ImportFailed {
+                line,
+                reason: format!("column '{}' does not exist in table '{}'", name, table.name),
+            });
+        }
+        let positions = table.columns.iter().map(|column| match header.iter().position(|name| name == column) {
+            _ if table.is_automatic(column) => Ok(None),
+            Some(position) => Ok(Some(position)),
+            None => Err(DbError::ImportFailed { line, reason: format!("the header has no column '{}'", column) }),
+        }).collect::<Result<_, _>>()?;
+        (positions, header.len())
+    } else {
+        let mut inputs = 0..;
+        let positions = table.columns.iter()
+            .map(|column| (!table.is_automatic(column)).then(|| inputs.next().unwrap()))
+            .collect();
+        (positions, table.input_columns().len())
+    };
+
+    records.map(|(line, record)| {
+        let failed = |e: DbError| DbError::ImportFailed { line, reason: e.to_string() };
+        if record.len() != width {
+            return Err(failed(DbError::ColumnCount { expected: width, found: record.len() }));
+        }
+        let row = table.columns.iter()
+            .zip(&positions)
+            .map(|(column, &position)| {
+                let Some(position) = position else {
+                    return Ok(DataType::String(String::new()));
+                };
+                let typ = &table.fields[column];
+                // Numbers may be padded, as spreadsheets like to write them
+                let raw = if typ == "string" { record[position].as_str() } else { record[position].trim() };
+                parse_value(column, typ, raw)
+            })
+            .collect::<Result<_, _>>()
+            .map_err(failed)?;
+        Ok((line, row))
+    }).collect()
+}
@@ -0,0 +1,132 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::database::Database;
+use crate::error::DbError;
+use crate::interrupt;
+use crate::parser::{Cte, Statement, Step, TableRef};
+use crate::query::Rows;
+use crate::table::enum_labels;
+use crate::{DataType, Table};
+
+/// The rounds a WITH RECURSIVE may take unless the limits say otherwise.
+pub const MAX_RECURSION: usize = 100;
+
+impl Database {
+    /// Runs a SELECT with the results of `ctes` readable as tables by their
+    /// names, each query seeing the results before it. They hide any table or
+    /// view of the same name, and are gone once the statement is done.
+    pub fn with(&mut self, ctes: &[Cte], query: &Statement) -> Result<Rows, DbError> {
+        let result = self.with_ctes(ctes, query);
+        self.ctes.clear();
+        result
+    }
+
+    fn with_ctes(&mut self, ctes: &[Cte], query: &Statement) -> Result<Rows, DbError> {
+        for cte in ctes {
+            let rows = self.run_select(&cte.query)?;
+            let names = column_names(cte, &rows)?;
+            let mut table = cte_table(cte, &names, &rows.types, rows.rows.clone());
+            if let Some(step) = &cte.step {
+                table = self.recurse(cte, &names, table, rows.rows, step)?;
+            }
+            self.ctes.insert(cte.name.clone(), Arc::new(table));
+        }
+        self.run_select(query)
+    }
+
+    /// The rows of a WITH RECURSIVE: `table`, the first query's, and those
+    /// each round of `step` adds, each round reading only the rows of the
+    /// round before under the WITH's name.
+    fn recurse(&mut self, cte: &Cte, names: &[String], mut table: Table, mut last: Vec<Vec<DataType>>, step: &Step) -> Result<Table, DbError> {
+        let types: Vec<String> = table.columns.iter().map(|col| table.fields[col].clone()).collect();
+        let mut seen: HashSet<Vec<DataType>> = HashSet::new();
+        if !step.all {
+            last.retain(|row| seen.insert(row.clone()));
+            table = cte_table(cte, names, &types, last.clone());
+        }
+        let max_recursion = self.limits().max_recursion;
+        for round in 0.. {
+            interrupt::check()?;
+            self.ctes.insert(cte.name.clone(), Arc::new(cte_table(cte, names, &types, last)));
+            let mut rows = self.run_select(&step.query)?.rows;
+            if let Some(row) = rows.first() && row.len() != names.len() {
+                return Err(DbError::Syntax(format!(
+                    "the UNION of WITH {} gives {} column(s), but its first query gives {}", cte.name, row.len(), names.len()
+                )));
+            }
+            if !step.all {
+                rows.retain(|row| seen.insert(row.clone()));
+            }
+            if rows.is_empty() {
+                break;
+            }
+            if round == max_recursion {
+                return Err(DbError::RecursionLimit { name: cte.name.clone(), rounds: max_recursion });
+            }
+            for row in &rows {
+                for (name, value) in names.iter().zip(row) {
+                    table.data.get_mut(name).unwrap().push(value.clone());
+                }
+            }
+            last = rows;
+        }
+        Ok(table)
+    }
+
+    fn run_select(&mut self, query: &Statement) -> Result<Rows, DbError> {
+        match query {
+            Statement::Select { from, joins, columns, filter, order } => {
+                let tables = TableRef::list(from.clone(), joins.clone());
+                self.select_from(&tables, columns, filter, order)
+            }
+            _ => unreachable!("the parser only accepts SELECT in a WITH"),
+        }
+    }
+}
+
+/// What the columns of a WITH query are called.
+fn column_names(cte: &Cte, rows: &Rows) -> Result<Vec<String>, DbError> {
+    let names: Vec<String> = if cte.columns.is_empty() {
+        rows.columns.iter().map(|heading| unqualified(heading).to_string()).collect()
+    } else if cte.columns.len() == rows.columns.len() {
+        cte.columns.clone()
+    } else {
+        return Err(DbError::Syntax(format!(
+            "WITH {} names {} column(s), but its query gives {}", cte.name, cte.columns.len(), rows.columns.len()
+        )));
+    };
+    if let Some((i, name)) = names.iter().enumerate().find(|(i, name)| names[..*i].contains(name)) {
+        return Err(DbError::Syntax(format!(
+            "WITH {} has two columns named '{}' (column {}); name them with WITH {}(<column>, ...) AS", cte.name, name, i + 1, cte.name
+        )));
+    }
+    Ok(names)
+}
+
+/// `rows` of a WITH query as a table. An enum column comes out as its
+/// labels, so it becomes a `string` one.
+fn cte_table(cte: &Cte, names: &[String], types: &[String], rows: Vec<Vec<DataType>>) -> Table {
+    let schema = names.iter().zip(types)
+        .map(|(name, typ)| (name.clone(), if enum_labels(typ).is_some() { "string".to_string() } else { typ.clone() }))
+        .collect();
+    let mut table = Table::new(&cte.name, schema, None, 0, 0);
+    for row in rows {
+        for (name, value) in names.iter().zip(row) {
+            table.data.get_mut(name).unwrap().push(value);
+        }
+    }
+    table
+}
+
+/// `column` for a heading `<table>.<column>`; any other heading as it is.
+fn unqualified(heading: &str) -> &str {
+    match heading.split_once('.') {
+        Some((table, column)) if [table, column].iter().all(|part| is_name(part)) => column,
+        _ => heading,
+    }
+}
+
+fn is_name(word: &str) -> bool {
+    word.starts_with(|c: char| c.is_alphabetic() || c == '_') && word.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
@@ -0,0 +1,1189 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::{DataType, Table};
+use crate::append::{self, Saved};
+use crate::catalog;
+use crate::cdc::{self, Event, Subscriber};
+use crate::cte;
+use crate::encryption;
+use crate::error::DbError;
+use crate::external::{self, External};
+use crate::functions::Functions;
+use crate::index::{Index, IndexDef, IndexFile, IndexKind};
+use crate::parser::{Predicate, Source};
+use crate::partition::{self, storage_name};
+use crate::stats;
+use crate::time::{Clock, SystemClock};
+use crate::tombstones;
+use crate::ttl;
+use crate::versions::Versions;
+use crate::storage::{self, DbSettings, DirStorage, FileStats, FileStorage, MemoryStorage, ReadOnlyStorage, Stamp, Stamps, Storage};
+use crate::wal::{self, Wal, WalOp};
+
+// Clean tables beyond this many are evicted, least recently used first,
+// unless set otherwise.
+const CACHE_CAPACITY: usize = 64;
+
+/// How much the database keeps in memory, lets the log grow to and lets a
+/// query do. These belong to the process rather than the data, so they are
+/// not saved.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    /// Clean tables cached beyond this many are evicted, least recently used first.
+    pub cache_tables: usize,
+    /// Once the log reaches this many bytes it is folded into the table files.
+    pub checkpoint_bytes: u64,
+    /// A WITH RECURSIVE still adding rows after this many rounds fails.
+    pub max_recursion: usize,
+    /// Inserts, imports and updates that grow rows fail once the database
+    /// would take more bytes than this, if set.
+    pub max_database_bytes: Option<u64>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            cache_tables: CACHE_CAPACITY,
+            checkpoint_bytes: wal::CHECKPOINT_BYTES,
+            max_recursion: cte::MAX_RECURSION,
+            max_database_bytes: None,
+        }
+    }
+}
+
+struct CachedTable {
+    // Shared with any outstanding snapshots; writers go through
+    // `Arc::make_mut`, which copies the table only while a snapshot holds it
+    table: Arc<Table>,
+    dirty: bool,    // Contains logged mutations its saved file does not have yet
+    temp: bool,     // Session-only: never logged or saved
+    last_used: u64,
+    stamp: Option<Stamp>, // Of its file when read or last written, if the storage can tell
+    saved: Option<Saved>, // What its files hold, if it was read from or written to them
+}
+
+/// Mutations made since BEGIN. They are applied to the cached tables right
+/// away but only reach the WAL, as a single record, on COMMIT. The first
+/// change to a table copies it, the original staying in `undo`, so until
+/// COMMIT only the transaction sees its changes; others read the original
+/// through `committed_snapshot`.
+struct Transaction {
+    ops: Vec<WalOp>,
+    events: Vec<Event>, // For subscribers, once committed
+    undo: Undo, // Tables as they were at BEGIN
+    savepoints: Vec<Savepoint>,
+}
+
+// Each touched table as it was before its first change, with its dirty flag
+type Undo = HashMap<String, (Arc<Table>, bool)>;
+
+/// An open transaction set aside by `Database::suspend`, with the tables it
+/// had changed.
+#[must_use]
+#[derive(Default)]
+pub struct Suspended {
+    txn: Option<Transaction>,
+    changed: Vec<(String, Arc<Table>)>,
+}
+
+struct Savepoint {
+    name: String,
+    ops: usize, // Length of `Transaction::ops` when the savepoint was set
+    events: usize, // Likewise of `Transaction::events`
+    undo: Undo, // Tables as they were at the savepoint, for those touched since
+}
+
+/// A storage backend, the write-ahead log protecting it, and a cache of the
+/// tables loaded from it.
+pub struct Database {
+    pub(crate) storage: Box<dyn Storage>,
+    pub(crate) wal: Wal,
+    cache: HashMap<String, CachedTable>,
+    cache_tables: usize,
+    max_recursion: usize,
+    pub(crate) max_database_bytes: Option<u64>,
+    pub(crate) clock_source: Arc<dyn Clock>, // What `now` reads
+    clock: u64, // Bumped on every cache access to order entries for eviction
+    txn: Option<Transaction>,
+    pub(crate) functions: Functions, // Registered by the embedding program, never saved
+    pub(crate) ctes: HashMap<String, Arc<Table>>, // Results of the running statement's WITH, by name
+    pub(crate) subscribers: Vec<Subscriber>,
+    pub(crate) versions: Versions,
+    _lock: Option<File>, // Held for as long as the database is open
+    read_only: bool,
+    pub(crate) passphrase: Option<String>, // What it was opened, or last rekeyed, with; for the databases it opens
+}
+
+impl Database {
+    fn new(storage: Box<dyn Storage>, wal: Wal, lock: Option<File>) -> Database {
+        Database {
+            storage, wal, cache: HashMap::new(), cache_tables: CACHE_CAPACITY, max_recursion: cte::MAX_RECURSION,
+            max_database_bytes: None, clock_source: Arc::new(SystemClock), clock: 0, txn: None, functions: Functions::default(), ctes: HashMap::new(),
+            subscribers: Vec::new(), versions: Versions::default(), _lock: lock, read_only: false, passphrase: None,
+        }
+    }
+
+    // `storage`, sealed if it is encrypted, with its log at `wal_path`;
+    // without `lock` it is opened read-only
+    fn open_sealed(storage: Box<dyn Storage>, wal_path: &Path, lock: Option<File>, passphrase: Option<&str>) -> io::Result<Database> {
+        let read_only = lock.is_none();
+        let (storage, cipher, rekeyed) = encryption::wrap(storage, passphrase)?;
+        let mut wal = match read_only {
+            true => Wal::open_read_only(wal_path, cipher)?,
+            false => Wal::open(wal_path, cipher)?,
+        };
+        // Its records were all in the table files, but it was sealed under the old key
+        if let Some(lsn) = rekeyed {
+            wal.truncate_at(lsn)?;
+        }
+        let mut db = Database::new(storage, wal, lock);
+        db.read_only = read_only;
+        db.passphrase = passphrase.map(str::to_string);
+        Ok(db)
+    }
+
+    /// One JSON file per table inside `dir`, with the log in `dir/wal.log`.
+    /// The directory is created if it does not exist yet. Only one process
+    /// may have it open at a time (`dir/.lock`).
+    pub fn open_dir(dir: &Path) -> io::Result<Database> {
+        Database::open_dir_with_key(dir, None)
+    }
+
+    /// `open_dir` for a database encrypted under `passphrase`, or to be if
+    /// it has no contents yet; see `encryption`.
+    pub fn open_dir_with_key(dir: &Path, passphrase: Option<&str>) -> io::Result<Database> {
+        fs::create_dir_all(dir)?;
+        let lock = storage::lock_exclusive(&dir.join(".lock"))?;
+        Database::open_sealed(Box::new(DirStorage::new(dir)), &dir.join("wal.log"), Some(lock), passphrase)
+    }
+
+    /// The whole database in a single file, with the log beside it in
+    /// `<path>-wal` and the lock in `<path>-lock`.
+    pub fn open_file(path: &Path) -> io::Result<Database> {
+        Database::open_file_with_key(path, None)
+    }
+
+    /// `open_file` for a database encrypted under `passphrase`, or to be.
+    pub fn open_file_with_key(path: &Path, passphrase: Option<&str>) -> io::Result<Database> {
+        let sibling = |suffix: &str| {
+            let mut sibling = path.as_os_str().to_owned();
+            sibling.push(suffix);
+            PathBuf::from(sibling)
+        };
+        // The data file itself is replaced on every save, so it cannot carry the lock
+        let lock = storage::lock_exclusive(&sibling("-lock"))?;
+        Database::open_sealed(Box::new(FileStorage::open(path)?), &sibling("-wal"), Some(lock), passphrase)
+    }
+
+    /// The database at `path`, a data directory or a single file, opened
+    /// only to be read: statements that would change it fail, and nothing in
+    /// the directory or beside the file is ever written, not even the lock
+    /// or the log. Records left in the log by the process writing it are
+    /// replayed in memory as each table is read, and the database is read as
+    /// it stands then; another process may go on writing it meanwhile.
+    pub fn open_read_only(path: &Path) -> io::Result<Database> {
+        Database::open_read_only_with_key(path, None)
+    }
+
+    /// `open_read_only` for a database encrypted under `passphrase`.
+    pub fn open_read_only_with_key(path: &Path, passphrase: Option<&str>) -> io::Result<Database> {
+        let (storage, wal_path): (Box<dyn Storage>, PathBuf) = if path.is_dir() {
+            (Box::new(DirStorage::new(path)), path.join("wal.log"))
+        } else if path.is_file() {
+            let mut wal_path = path.as_os_str().to_owned();
+            wal_path.push("-wal");
+            (Box::new(FileStorage::open(path)?), PathBuf::from(wal_path))
+        } else {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("no database at {}", path.display())));
+        };
+        Database::open_sealed(Box::new(ReadOnlyStorage::new(storage)), &wal_path, None, passphrase)
+    }
+
+    /// The passphrase the database was opened with, or last rekeyed with.
+    pub fn passphrase(&self) -> Option<&str> {
+        self.passphrase.as_deref()
+    }
+
+    /// Whether the database was opened with `open_read_only`.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    // Fails if the database was opened read-only
+    pub(crate) fn check_read_write(&self) -> Result<(), DbError> {
+        match self.read_only {
+            true => Err(DbError::ReadOnly("it was opened read-only".to_string())),
+            false => Ok(()),
+        }
+    }
+
+    /// Tables live only in RAM and vanish when the database is dropped.
+    pub fn open_in_memory() -> Database {
+        Database::new(Box::new(MemoryStorage::default()), Wal::in_memory(), None)
+    }
+
+    /// Tables kept in `storage`, a backend of the caller's, such as
+    /// `browser::LocalStorage` in a web page where there are no files to
+    /// open. The log lives in memory, so every committed change is written
+    /// through to the backend at once by a checkpoint; `set_limits` can
+    /// trade that for fewer writes, at the risk of losing what the page held.
+    pub fn open_with(storage: Box<dyn Storage>) -> Result<Database, DbError> {
+        let mut wal = Wal::in_memory();
+        wal.set_checkpoint_bytes(0);
+        let mut db = Database::new(storage, wal, None);
+        // The log starts after the last change the tables hold
+        for name in db.stored_names()? {
+            let lsn = db.read_table_file(&name)?.lsn;
+            db.wal.advance_to(lsn);
+        }
+        Ok(db)
+    }
+
+    pub fn limits(&self) -> Limits {
+        Limits {
+            cache_tables: self.cache_tables,
+            checkpoint_bytes: self.wal.checkpoint_bytes(),
+            max_recursion: self.max_recursion,
+            max_database_bytes: self.max_database_bytes,
+        }
+    }
+
+    /// Changes the limits for the tables loaded and records logged from now on.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.cache_tables = limits.cache_tables.max(1);
+        self.wal.set_checkpoint_bytes(limits.checkpoint_bytes);
+        self.max_recursion = limits.max_recursion;
+        self.max_database_bytes = limits.max_database_bytes;
+    }
+
+    /// The tables that can be queried, temporary ones included.
+    pub fn table_names(&self) -> Result<Vec<String>, DbError> {
+        let mut names: Vec<String> = self.stored_names()?
+            .into_iter()
+            .filter(|name| !partition::is_partition(name))
+            .chain(self.cache.iter().filter(|(_, c)| c.temp).map(|(name, _)| name.clone()))
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    /// Every table with a file, partitions of partitioned tables included.
+    pub(crate) fn stored_names(&self) -> Result<Vec<String>, DbError> {
+        let mut names: Vec<String> = self.storage.keys()?
+            .into_iter()
+            .filter_map(|key| key.strip_suffix(".json").map(str::to_string))
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    pub fn table_exists(&self, name: &str) -> bool {
+        self.is_temp(name) || self.storage.exists(&storage::table_key(name))
+    }
+
+    pub fn is_temp(&self, name: &str) -> bool {
+        self.cache.get(name).is_some_and(|c| c.temp)
+    }
+
+    /// Drops every stored table from the cache, keeping temporary ones, for
+    /// when the storage underneath has been replaced.
+    pub(crate) fn forget_stored_tables(&mut self) {
+        self.versions.changed_all();
+        self.cache.retain(|_, entry| entry.temp);
+    }
+
+    /// Discards the open transaction, if any, and drops every stored table
+    /// from the cache, to be read again from storage and the log, for when
+    /// a panic may have left a statement half-applied to the cache.
+    pub(crate) fn reload(&mut self) {
+        self.txn = None;
+        self.forget_stored_tables();
+    }
+
+    pub fn add_temp_table(&mut self, table: Table) {
+        self.versions.changed(&table.name);
+        self.insert_cached(table, false, true, None);
+    }
+
+    fn read_blob(&self, name: &str) -> Result<Vec<u8>, DbError> {
+        self.storage.read(&storage::table_key(name))?
+            .ok_or_else(|| self.table_not_found(name))
+    }
+
+    /// Reads the saved table only, bypassing the cache and the WAL: its
+    /// file, and the rows appended beside it.
+    pub fn read_table_file(&self, name: &str) -> Result<Table, DbError> {
+        let mut table = storage::decode_table(name, &self.read_blob(name)?)?;
+        self.read_appended(&mut table)?;
+        Ok(table)
+    }
+
+    // The summary in the header of `name`'s file, if the file has one
+    fn summary(&self, name: &str) -> Result<Option<storage::Summary>, DbError> {
+        match self.storage.read_first_line(&storage::table_key(name))? {
+            Some(header) => storage::decode_summary(name, &header),
+            None => Ok(None),
+        }
+    }
+
+    /// Table `name` without its rows. A table not loaded yet is read from
+    /// the header of its file, so its rows are neither read nor kept.
+    pub fn definition(&mut self, name: &str) -> Result<Table, DbError> {
+        if !self.cache.contains_key(name) && !self.ctes.contains_key(name) && let Some(summary) = self.summary(name)? {
+            return Ok(summary.definition);
+        }
+        Ok(self.snapshot(name)?.schema_only())
+    }
+
+    /// The number of rows of table `name`, as `SELECT` sees them. A table
+    /// not loaded yet whose rows neither expire nor live elsewhere, and with
+    /// no changes in the log, is counted from the header of its file.
+    pub fn count_rows(&mut self, name: &str) -> Result<usize, DbError> {
+        if !self.cache.contains_key(name) && !self.ctes.contains_key(name)
+            && let Some(summary) = self.summary(name)?
+            && summary.definition.ttl.is_none()
+            && summary.definition.partitioning.is_none()
+            && summary.definition.external.is_none()
+            && !self.logged(name)?
+        {
+            self.versions.read(name, false);
+            return Ok(summary.rows + self.appended_rows(name, summary.definition.lsn)?);
+        }
+        Ok(self.snapshot(name)?.row_count())
+    }
+
+    // Whether the log holds changes to `name` not yet saved in its file
+    fn logged(&self, name: &str) -> Result<bool, DbError> {
+        if self.wal.pending() == 0 {
+            return Ok(false);
+        }
+        Ok(self.wal.records()?.iter().any(|record| record.op.tables().contains(&name)))
+    }
+
+    /// Returns the current contents of a table, reading it from storage and
+    /// replaying its pending WAL records only on a cache miss.
+    pub fn load_table(&mut self, name: &str) -> Result<&Table, DbError> {
+        // An external table is read again once its file changes
+        if self.cache.get(name).is_some_and(|entry| entry.table.external.as_ref().is_some_and(External::is_stale)) {
+            self.cache.remove(name);
+        }
+        // And any other once another program writes its file, unless a
+        // transaction has changed it
+        let written = self.is_written_elsewhere(name);
+        if written {
+            self.cache.remove(name);
+            self.versions.changed(name);
+        }
+        if !self.cache.contains_key(name) {
+            // Taken first, so a file written while it is read is read again next time
+            let stamp = self.storage.stamp(&storage::table_key(name));
+            let mut table = storage::decode_table(name, &self.read_blob(name)?)?;
+            if table.external.is_some() {
+                external::read(&mut table)?;
+                self.insert_cached(table, false, false, None);
+            } else if let Some(partitioning) = &table.partitioning {
+                // Its changes are all logged, and saved, as its partitions'
+                let all: Vec<usize> = (0..partitioning.partitions.len()).collect();
+                let table = self.merge(table, &all)?;
+                self.insert_cached(table, false, false, stamp);
+            } else {
+                // The saved indexes may not match rows edited by hand
+                match written {
+                    true => table.rebuild_indexes(),
+                    false => self.load_indexes(&mut table),
+                }
+                let saved = self.read_appended(&mut table)?;
+                let pending = self.wal.replay(&mut table)?;
+                self.insert_cached(table, pending > 0, false, stamp);
+                self.cache.get_mut(name).unwrap().saved = Some(saved);
+            }
+        }
+
+        self.clock += 1;
+        let entry = self.cache.get_mut(name).unwrap();
+        entry.last_used = self.clock;
+        Ok(&entry.table)
+    }
+
+    // Whether the file of cached table `name` has changed since it was read
+    // or written here, and the table is not part of the open transaction
+    fn is_written_elsewhere(&self, name: &str) -> bool {
+        let Some(entry) = self.cache.get(name) else { return false };
+        entry.stamp.is_some()
+            && !self.txn.as_ref().is_some_and(|txn| txn.undo.contains_key(name))
+            && self.storage.stamp(&storage::table_key(name)) != entry.stamp
+    }
+
+    /// Reads table `name` from its file again, with the changes in the log
+    /// not saved in it yet, dropping what is cached of it.
+    pub fn refresh_table(&mut self, name: &str) -> Result<(), DbError> {
+        if catalog::is_system_table(name) {
+            return Err(DbError::SystemTable(name.to_string()));
+        }
+        if self.txn.as_ref().is_some_and(|txn| txn.undo.contains_key(name)) {
+            return Err(DbError::TransactionActive);
+        }
+        match self.cache.get(name) {
+            Some(entry) if entry.temp => return Ok(()),
+            Some(_) => {
+                self.cache.remove(name);
+                self.versions.changed(name);
+            }
+            None => {}
+        }
+        self.load_table(name).map(|_| ())
+    }
+
+    /// A consistent, immutable view of a table as it is now. Later writes
+    /// copy the table instead of changing the snapshot, so a reader holding
+    /// it never sees a half-applied statement or transaction.
+    pub fn snapshot(&mut self, name: &str) -> Result<Arc<Table>, DbError> {
+        if let Some(table) = self.ctes.get(name) {
+            return Ok(Arc::clone(table));
+        }
+        // The system catalog is built afresh for every read
+        if let Some(table) = self.system_table(name)? {
+            self.versions.read(name, true);
+            return Ok(Arc::new(table));
+        }
+        let table = self.load_table(name)?;
+        // Rows expire, and files change, without anything written
+        let volatile = table.ttl.is_some() || table.external.is_some();
+        self.versions.read(name, volatile);
+        Ok(ttl::live(tombstones::visible(Arc::clone(&self.cache[name].table)), self.now()))
+    }
+
+    /// `snapshot` of what a FROM list reads of table `name` as `source`.
+    pub fn snapshot_of(&mut self, name: &str, source: &Source) -> Result<Arc<Table>, DbError> {
+        match source {
+            Source::Current => self.snapshot(name),
+            Source::AsOf(at) => {
+                self.versions.read(name, true);
+                Ok(Arc::new(self.as_of(name, *at)?))
+            }
+            Source::Deleted => {
+                let volatile = self.load_table(name)?.ttl.is_some();
+                self.versions.read(name, volatile);
+                Ok(ttl::live(Arc::clone(&self.cache[name].table), self.now()))
+            }
+            Source::Path(query) => Ok(Arc::new(self.paths(query)?)),
+        }
+    }
+
+    /// `snapshot`, except that a table the open transaction has changed is
+    /// given as it was at BEGIN: what another reader may see of it before
+    /// the transaction commits.
+    pub fn committed_snapshot(&mut self, name: &str) -> Result<Arc<Table>, DbError> {
+        let Some((table, _)) = self.txn.as_ref().and_then(|txn| txn.undo.get(name)) else {
+            return self.snapshot(name);
+        };
+        let table = Arc::clone(table);
+        self.versions.read(name, table.ttl.is_some() || table.external.is_some());
+        Ok(ttl::live(tombstones::visible(table), self.now()))
+    }
+
+    /// `snapshot`, except that of a partitioned table only the partitions a
+    /// query with `filter` may find rows in are read. Also gives the names of
+    /// those partitions, if the table is partitioned.
+    pub fn snapshot_for(&mut self, name: &str, filter: &[Predicate]) -> Result<(Arc<Table>, Option<Vec<String>>), DbError> {
+        let Some(definition) = self.partitioned_definition(name)? else {
+            return Ok((self.snapshot(name)?, None));
+        };
+        self.versions.read(name, false);
+        let partitioning = definition.partitioning.as_ref().expect("a partitioned table");
+        let kept = partitioning.prune(&definition.fields[&partitioning.column], filter);
+        let names = kept.iter().map(|&i| partitioning.partitions[i].name.clone()).collect();
+        let table = match kept.len() == partitioning.partitions.len() {
+            true => self.snapshot(name)?,
+            false => Arc::new(self.merge(definition, &kept)?),
+        };
+        Ok((table, Some(names)))
+    }
+
+    // A partitioned table without its rows, None for any other table
+    fn partitioned_definition(&self, name: &str) -> Result<Option<Table>, DbError> {
+        if let Some(entry) = self.cache.get(name) {
+            return Ok(entry.table.partitioning.is_some().then(|| entry.table.schema_only()));
+        }
+        if self.ctes.contains_key(name) || !self.storage.exists(&storage::table_key(name)) {
+            return Ok(None);
+        }
+        let table = self.read_table_file(name)?;
+        Ok(table.partitioning.is_some().then_some(table))
+    }
+
+    // A partitioned table's definition with the rows of the partitions at
+    // `positions` in its partitioning, in that order
+    fn merge(&mut self, mut table: Table, positions: &[usize]) -> Result<Table, DbError> {
+        let partitioning = table.partitioning.clone().expect("only partitioned tables are merged");
+        for &i in positions {
+            let part = self.load_table(&storage_name(&table.name, &partitioning.partitions[i].name))?;
+            for column in &table.columns {
+                table.data.get_mut(column).unwrap().extend(part.data[column].iter().cloned());
+            }
+            table.stored_at.extend((0..part.row_count()).map(|row| (i, row)));
+        }
+        table.rebuild_indexes();
+        Ok(table)
+    }
+
+    /// Drops a table from the cache, to be read again from storage.
+    pub(crate) fn forget_table(&mut self, name: &str) {
+        self.versions.changed(name);
+        self.cache.remove(name);
+    }
+
+    /// The cached copy of plain table `name` as last committed, as
+    /// `committed_snapshot` reads it, with the stamp its file had when it
+    /// was read or last written here.
+    pub(crate) fn committed_entry(&self, name: &str) -> Option<(Arc<Table>, Option<Stamp>)> {
+        let entry = self.cache.get(name).filter(|_| !self.ctes.contains_key(name))?;
+        let table = match self.txn.as_ref().and_then(|txn| txn.undo.get(name)) {
+            Some((table, _)) => table,
+            None => &entry.table,
+        };
+        Some((Arc::clone(table), entry.stamp))
+    }
+
+    /// The stamps of the storage's blobs, to check cached tables against
+    /// from other threads.
+    pub(crate) fn stamps(&self) -> Option<Stamps> {
+        self.storage.stamps()
+    }
+
+    /// The cached copy of a table, if it is loaded. Unlike `snapshot` this
+    /// needs no exclusive access, but it does not count as a use for eviction.
+    pub fn cached(&self, name: &str) -> Option<Arc<Table>> {
+        self.cache.get(name).map(|entry| Arc::clone(&entry.table))
+    }
+
+    /// The names of the tables in memory.
+    pub(crate) fn cached_names(&self) -> Vec<String> {
+        self.cache.keys().cloned().collect()
+    }
+
+    // Saved entries are only used if they match the table file exactly;
+    // anything else (missing, stale or unreadable) is rebuilt from the rows.
+    fn load_indexes(&self, table: &mut Table) {
+        if table.index_defs.is_empty() {
+            return;
+        }
+        let saved = self.storage.read(&storage::index_key(&table.name)).ok().flatten()
+            .and_then(|bytes| serde_json::from_slice::<IndexFile>(&bytes).ok())
+            .filter(|file| {
+                file.lsn == table.lsn
+                    && file.rows == table.row_count()
+                    && file.indexes.len() == table.index_defs.len()
+                    && file.indexes.iter().zip(&table.index_defs).all(|(i, d)| i.name == d.name)
+            });
+        match saved {
+            Some(file) => table.indexes = file.indexes,
+            None => table.rebuild_indexes(),
+        }
+    }
+
+    fn insert_cached(&mut self, table: Table, dirty: bool, temp: bool, stamp: Option<Stamp>) {
+        if self.cache.len() >= self.cache_tables {
+            let victim = self.cache.iter()
+                .filter(|(_, c)| !c.dirty && !c.temp)
+                .min_by_key(|(_, c)| c.last_used)
+                .map(|(name, _)| name.clone());
+            if let Some(victim) = victim {
+                self.cache.remove(&victim);
+            }
+        }
+
+        self.clock += 1;
+        let entry = CachedTable { table: Arc::new(table), dirty, temp, last_used: self.clock, stamp, saved: None };
+        self.cache.insert(entry.table.name.clone(), entry);
+    }
+
+    pub fn save_table(&mut self, table: &Table) -> Result<(), DbError> {
+        self.versions.changed(&table.name);
+        if let Some(entry) = self.cache.get_mut(&table.name) {
+            entry.table = Arc::new(table.clone());
+            if entry.temp {
+                return Ok(());
+            }
+            entry.dirty = false;
+        }
+        self.write_table(table)
+    }
+
+    fn write_table(&mut self, table: &Table) -> Result<(), DbError> {
+        self.check_read_write()?;
+        // A partitioned table's rows are saved in its partitions, and an
+        // external table's stay in its file
+        let definition;
+        let table = match table.partitioning.is_some() || table.external.is_some() {
+            true => {
+                definition = table.schema_only();
+                &definition
+            }
+            false => table,
+        };
+        let codec = self.settings()?.compression;
+        let bytes = storage::encode_table(table, codec)?;
+        let key = storage::table_key(&table.name);
+        self.storage.write(&key, &bytes)?;
+        if let Some(entry) = self.cache.get_mut(&table.name) {
+            entry.stamp = self.storage.stamp(&key);
+            entry.saved = Some(Saved::file(table));
+        }
+
+        let index_key = storage::index_key(&table.name);
+        if table.index_defs.is_empty() || table.partitioning.is_some() || table.external.is_some() {
+            self.storage.remove(&index_key)?;
+        } else {
+            let file = IndexFile { lsn: table.lsn, rows: table.row_count(), indexes: table.indexes.clone() };
+            let bytes = serde_json::to_vec(&file).map_err(io::Error::from)?;
+            self.storage.write(&index_key, &bytes)?;
+        }
+        // The file holds every row appended beside it
+        self.remove_appended(&table.name)
+    }
+
+    /// Builds a new index over the current rows and saves it with the table,
+    /// and with each of its partitions if it has any.
+    pub fn create_index(&mut self, table_name: &str, def: IndexDef) -> Result<(), DbError> {
+        let mut table = self.load_table(table_name)?.clone();
+        if let Some(column) = def.columns.iter().find(|col| !table.fields.contains_key(*col)) {
+            return Err(DbError::ColumnNotFound { table: table.name, column: column.clone() });
+        }
+        if table.index_defs.iter().any(|d| d.name == def.name) {
+            return Err(DbError::IndexExists(def.name));
+        }
+        if def.kind == IndexKind::FullText
+            && (def.columns.len() != 1 || table.fields[&def.columns[0]] != "string")
+        {
+            return Err(DbError::InvalidIndex("FULLTEXT needs exactly one string column".to_string()));
+        }
+
+        for partition in table.partitioning.iter().flat_map(|partitioning| &partitioning.partitions) {
+            self.create_index(&storage_name(table_name, &partition.name), def.clone())?;
+        }
+        table.indexes.push(Index::build(&def, &table));
+        table.index_defs.push(def);
+        self.save_table(&table)
+    }
+
+    /// Drops the index `name` of a table, and of each of its partitions. The
+    /// index of a primary key is part of the table and stays.
+    pub fn drop_index(&mut self, table_name: &str, name: &str) -> Result<(), DbError> {
+        let mut table = self.load_table(table_name)?.clone();
+        let position = table.index_defs.iter()
+            .position(|def| def.name == name)
+            .ok_or_else(|| DbError::IndexNotFound(name.to_string()))?;
+        if table.index_defs[position].unique {
+            return Err(DbError::InvalidIndex(format!("'{}' enforces the primary key of '{}' and cannot be dropped", name, table.name)));
+        }
+
+        for partition in table.partitioning.iter().flat_map(|partitioning| &partitioning.partitions) {
+            self.drop_index(&storage_name(table_name, &partition.name), name)?;
+        }
+        table.index_defs.remove(position);
+        if position < table.indexes.len() {
+            table.indexes.remove(position);
+        }
+        self.save_table(&table)
+    }
+
+    /// The tables with an index named `name`.
+    pub fn index_tables(&mut self, name: &str) -> Result<Vec<String>, DbError> {
+        let mut tables = Vec::new();
+        for table in self.table_names()? {
+            if self.definition(&table)?.index_defs.iter().any(|def| def.name == name) {
+                tables.push(table);
+            }
+        }
+        Ok(tables)
+    }
+
+    /// Rebuilds every index of a table from its rows and saves them in
+    /// place of its index file, doing the same for each of its partitions.
+    /// Returns the number of indexes rebuilt.
+    pub fn reindex(&mut self, table_name: &str) -> Result<usize, DbError> {
+        if catalog::is_system_table(table_name) {
+            return Err(DbError::SystemTable(table_name.to_string()));
+        }
+        let mut table = self.load_table(table_name)?.clone();
+        for partition in table.partitioning.iter().flat_map(|partitioning| &partitioning.partitions) {
+            self.reindex(&storage_name(table_name, &partition.name))?;
+        }
+        table.rebuild_indexes();
+        self.save_table(&table)?;
+        Ok(table.index_defs.len())
+    }
+
+    /// Re-encodes a stored table with the current settings.
+    pub fn rewrite_table(&mut self, name: &str) -> Result<(), DbError> {
+        let table = self.load_table(name)?.clone();
+        self.save_table(&table)
+    }
+
+    /// Collects fresh statistics for a table and saves them with it. Returns
+    /// the number of rows analyzed.
+    pub fn analyze(&mut self, name: &str) -> Result<usize, DbError> {
+        let mut table = self.load_table(name)?.clone();
+        let stats = stats::analyze(&table);
+        let rows = stats.rows;
+        table.stats = Some(stats);
+        self.save_table(&table)?;
+        Ok(rows)
+    }
+
+    pub fn drop_table(&mut self, name: &str) -> Result<bool, DbError> {
+        self.versions.changed(name);
+        if let Some(entry) = self.cache.remove(name) && entry.temp {
+            return Ok(true);
+        }
+        if let Some(partitioning) = self.read_table_file(name).ok().and_then(|table| table.partitioning) {
+            for partition in partitioning.partitions {
+                self.drop_table(&storage_name(name, &partition.name))?;
+            }
+        }
+        self.remove_appended(name)?;
+        self.storage.remove(&storage::index_key(name))?;
+        let removed = self.storage.remove(&storage::table_key(name))?;
+        self.forget_grants(name)?;
+        Ok(removed)
+    }
+
+    pub fn file_stats(&self, name: &str) -> Result<FileStats, DbError> {
+        let bytes = self.read_blob(name)?;
+        let payload = storage::decode_payload(name, &bytes)?;
+        let index_bytes = self.storage.read(&storage::index_key(name))?.map_or(0, |bytes| bytes.len() as u64);
+        Ok(FileStats {
+            codec: payload.codec,
+            format: payload.format,
+            engine: payload.engine,
+            raw_bytes: payload.body.len() as u64,
+            file_bytes: bytes.len() as u64,
+            index_bytes,
+        })
+    }
+
+    pub fn settings(&self) -> Result<DbSettings, DbError> {
+        match self.storage.read(storage::SETTINGS_KEY)? {
+            Some(bytes) => Ok(serde_json::from_slice(&bytes).map_err(io::Error::from)?),
+            None => Ok(DbSettings::default()),
+        }
+    }
+
+    pub fn save_settings(&mut self, settings: &DbSettings) -> Result<(), DbError> {
+        let bytes = serde_json::to_vec_pretty(settings).map_err(io::Error::from)?;
+        self.storage.write(storage::SETTINGS_KEY, &bytes)?;
+        Ok(())
+    }
+
+    /// LSN a newly created table starts from, so older log records for a
+    /// dropped table of the same name are never replayed into it.
+    pub fn last_lsn(&self) -> u64 {
+        self.wal.last_lsn()
+    }
+
+    /// Durably logs a mutation and applies it to the cached table. The table
+    /// file itself is only rewritten by the next checkpoint.
+    pub fn log(&mut self, op: WalOp) -> Result<(), DbError> {
+        self.check_read_write()?;
+        let name = op.table().expect("only table mutations are logged").to_string();
+        let size = |value: &DataType| serde_json::to_vec(value).map_or(0, |bytes| bytes.len() as u64);
+        match &op {
+            WalOp::Insert { row, .. } => {
+                let bytes = serde_json::to_vec(row).map_or(0, |bytes| bytes.len() as u64);
+                self.check_quota(&name, 1, bytes)?;
+            }
+            // Only what the new values add counts against the size
+            WalOp::Update { row, values, .. } => {
+                let table = self.load_table(&name)?;
+                let (grown, shrunk) = values.iter().fold((0, 0), |(grown, shrunk), (column, value)| {
+                    let old = table.data.get(column).and_then(|values| values.get(*row)).map_or(0, size);
+                    (grown + size(value), shrunk + old)
+                });
+                if grown > shrunk {
+                    self.check_quota(&name, 0, grown - shrunk)?;
+                }
+            }
+            _ => {}
+        }
+        self.versions.changed(&name);
+        let partitioned = self.load_table(&name)?.partitioning.is_some();
+        // Partitions' changes are sent as their table's
+        let events = match self.subscribers.is_empty() || partition::is_partition(&name) || self.is_temp(&name) {
+            true => Vec::new(),
+            false => cdc::capture(&self.cache[&name].table, &op),
+        };
+        if partitioned {
+            // Its cached copy is changed too, so it is kept as it was like any other
+            if let Some(txn) = &mut self.txn {
+                let entry = &self.cache[&name];
+                if let Some(savepoint) = txn.savepoints.last_mut() {
+                    savepoint.undo.entry(name.clone()).or_insert_with(|| (Arc::clone(&entry.table), entry.dirty));
+                }
+                txn.undo.entry(name.clone()).or_insert_with(|| (Arc::clone(&entry.table), entry.dirty));
+            }
+            self.log_partitioned(op)?;
+            match &mut self.txn {
+                Some(txn) => txn.events.extend(events),
+                None => cdc::publish(&mut self.subscribers, self.wal.last_lsn(), events),
+            }
+            return Ok(());
+        }
+
+        let now = self.now();
+        let entry = self.cache.get_mut(&name).unwrap();
+        if let Some(txn) = &mut self.txn {
+            if let Some(savepoint) = txn.savepoints.last_mut() {
+                savepoint.undo.entry(name.clone()).or_insert_with(|| (Arc::clone(&entry.table), entry.dirty));
+            }
+            txn.undo.entry(name).or_insert_with(|| (Arc::clone(&entry.table), entry.dirty));
+            if !entry.temp {
+                txn.ops.push(op.clone());
+                txn.events.extend(events);
+                // Keeps the table from being evicted before COMMIT
+                entry.dirty = true;
+            }
+            Arc::make_mut(&mut entry.table).apply(&op, now);
+            return Ok(());
+        }
+
+        // Temporary tables are never persisted, so there is nothing to make durable
+        if !entry.temp {
+            Arc::make_mut(&mut entry.table).lsn = self.wal.append(op.clone())?;
+            entry.dirty = true;
+        }
+        Arc::make_mut(&mut entry.table).apply(&op, now);
+        cdc::publish(&mut self.subscribers, self.wal.last_lsn(), events);
+
+        if self.wal.needs_checkpoint() {
+            self.checkpoint()?;
+        }
+        Ok(())
+    }
+
+    // Logs a change to a partitioned table as changes to the partitions
+    // holding its rows, then makes it to the cached copy too
+    fn log_partitioned(&mut self, op: WalOp) -> Result<(), DbError> {
+        let name = op.table().unwrap().to_string();
+        let (ops, stored_at) = {
+            let table = Arc::clone(&self.cache[&name].table);
+            let partitioning = table.partitioning.as_ref().unwrap();
+            let part = |i: usize| storage_name(&name, &partitioning.partitions[i].name);
+            let mut stored_at = table.stored_at.clone();
+            let ops = match &op {
+                WalOp::Insert { row, .. } => {
+                    let i = partitioning.route_row(&table, row)?;
+                    stored_at.push((i, self.load_table(&part(i))?.row_count()));
+                    vec![WalOp::Insert { table: part(i), row: row.clone() }]
+                }
+                WalOp::Delete { index, .. } => unstore(&mut stored_at, &[*index]).into_iter()
+                    .map(|(i, rows)| WalOp::DeleteRows { table: part(i), rows })
+                    .collect(),
+                WalOp::DeleteRows { rows, .. } => unstore(&mut stored_at, rows).into_iter()
+                    .map(|(i, rows)| WalOp::DeleteRows { table: part(i), rows })
+                    .collect(),
+                WalOp::Update { row, values, .. } => {
+                    let (i, at) = stored_at[*row];
+                    let mut new: Vec<DataType> = table.columns.iter().map(|col| table.data[col][*row].clone()).collect();
+                    for (column, value) in values {
+                        if let Some(position) = table.columns.iter().position(|col| col == column) {
+                            new[position] = value.clone();
+                        }
+                    }
+                    match partitioning.route_row(&table, &new)? {
+                        j if j == i => vec![WalOp::Update { table: part(i), row: at, values: values.clone() }],
+                        // A new partition value can move the row to another partition
+                        j => {
+                            unstore(&mut stored_at, &[*row]);
+                            stored_at.insert(*row, (j, self.load_table(&part(j))?.row_count()));
+                            vec![WalOp::DeleteRows { table: part(i), rows: vec![at] }, WalOp::Insert { table: part(j), row: new }]
+                        }
+                    }
+                }
+                WalOp::Tombstone { .. } => unreachable!("partitioned tables never soft-delete"),
+                WalOp::Transaction { .. } | WalOp::Checkpoint => unreachable!("only row changes are logged"),
+            };
+            (ops, stored_at)
+        };
+
+        match ops.len() {
+            1 => self.log(ops.into_iter().next().unwrap())?,
+            _ => self.atomically(|db| ops.into_iter().try_for_each(|op| db.log(op)))?,
+        }
+        let now = self.now();
+        if let Some(entry) = self.cache.get_mut(&name) {
+            let table = Arc::make_mut(&mut entry.table);
+            table.apply(&op, now);
+            table.stored_at = stored_at;
+        }
+        Ok(())
+    }
+
+    /// Starts a batch: changes are logged and applied as usual, but only
+    /// reach the disk, all at once, at `end_batch`.
+    pub fn begin_batch(&mut self) {
+        self.wal.defer_sync(true);
+    }
+
+    /// Ends a batch, making its changes durable.
+    pub fn end_batch(&mut self) -> Result<(), DbError> {
+        self.wal.defer_sync(false);
+        Ok(self.wal.sync()?)
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.txn.is_some()
+    }
+
+    /// Sets the open transaction aside, the tables it has changed read as
+    /// they were at BEGIN, so that another session sharing the database can
+    /// read what is committed without waiting for it. Only reads may run
+    /// until `resume` puts the transaction back.
+    pub fn suspend(&mut self) -> Suspended {
+        let Some(txn) = self.txn.take() else { return Suspended::default() };
+        let mut changed = Vec::with_capacity(txn.undo.len());
+        for (name, (committed, _)) in &txn.undo {
+            if let Some(entry) = self.cache.get_mut(name) {
+                changed.push((name.clone(), mem::replace(&mut entry.table, Arc::clone(committed))));
+            }
+        }
+        Suspended { txn: Some(txn), changed }
+    }
+
+    /// Puts back the transaction `suspend` set aside.
+    pub fn resume(&mut self, suspended: Suspended) {
+        let Suspended { txn, changed } = suspended;
+        for (name, table) in changed {
+            match self.cache.get_mut(&name) {
+                Some(entry) => entry.table = table,
+                // Read again from its file meanwhile, which has none of the changes
+                None => self.insert_cached(Arc::unwrap_or_clone(table), true, false, None),
+            }
+        }
+        if txn.is_some() {
+            self.txn = txn;
+        }
+    }
+
+    /// The number of changes made in the open transaction, none if there is
+    /// no transaction.
+    pub fn uncommitted_changes(&self) -> usize {
+        self.txn.as_ref().map_or(0, |txn| txn.ops.len())
+    }
+
+    /// Runs `f` as a unit: all of its changes are kept or, if it fails, none.
+    /// Outside a transaction it gets one of its own; inside one it is undone
+    /// through a savepoint, leaving the rest of the transaction alone.
+    pub fn atomically<T>(&mut self, f: impl FnOnce(&mut Database) -> Result<T, DbError>) -> Result<T, DbError> {
+        // Not a name the parser accepts, so it cannot clash with the user's
+        const SAVEPOINT: &str = " atomically";
+        let implicit = self.txn.is_none();
+        if implicit {
+            self.begin()?;
+        } else {
+            self.savepoint(SAVEPOINT)?;
+        }
+
+        let result = f(self);
+        match (&result, implicit) {
+            (Ok(_), true) => {
+                self.commit()?;
+            }
+            (Ok(_), false) => self.release(SAVEPOINT)?,
+            (Err(_), true) => {
+                self.rollback()?;
+            }
+            (Err(_), false) => {
+                self.rollback_to(SAVEPOINT)?;
+                self.release(SAVEPOINT)?;
+            }
+        }
+        result
+    }
+
+    pub fn begin(&mut self) -> Result<(), DbError> {
+        if self.txn.is_some() {
+            return Err(DbError::TransactionActive);
+        }
+        self.txn = Some(Transaction { ops: Vec::new(), events: Vec::new(), undo: HashMap::new(), savepoints: Vec::new() });
+        Ok(())
+    }
+
+    /// Makes the transaction durable. Returns the number of mutations it held.
+    pub fn commit(&mut self) -> Result<usize, DbError> {
+        let txn = self.txn.take().ok_or(DbError::NoTransaction)?;
+        let count = txn.ops.len();
+        if count == 0 {
+            return Ok(0);
+        }
+
+        let lsn = match self.wal.append(WalOp::Transaction { ops: txn.ops }) {
+            Ok(lsn) => lsn,
+            Err(e) => {
+                self.restore(txn.undo);
+                return Err(e.into());
+            }
+        };
+        for name in txn.undo.into_keys() {
+            if let Some(entry) = self.cache.get_mut(&name) && !entry.temp {
+                Arc::make_mut(&mut entry.table).lsn = lsn;
+            }
+        }
+        cdc::publish(&mut self.subscribers, lsn, txn.events);
+
+        if self.wal.needs_checkpoint() {
+            self.checkpoint()?;
+        }
+        Ok(count)
+    }
+
+    /// Discards the transaction. Returns the number of mutations undone.
+    pub fn rollback(&mut self) -> Result<usize, DbError> {
+        let txn = self.txn.take().ok_or(DbError::NoTransaction)?;
+        self.restore(txn.undo);
+        Ok(txn.ops.len())
+    }
+
+    /// Marks the current point of the transaction. A savepoint with the same
+    /// name as an older one hides it until released or rolled back past.
+    pub fn savepoint(&mut self, name: &str) -> Result<(), DbError> {
+        let txn = self.txn.as_mut().ok_or(DbError::NoTransaction)?;
+        txn.savepoints.push(Savepoint { name: name.to_string(), ops: txn.ops.len(), events: txn.events.len(), undo: HashMap::new() });
+        Ok(())
+    }
+
+    /// Undoes everything done since the savepoint, which stays set. Returns
+    /// the number of mutations undone.
+    pub fn rollback_to(&mut self, name: &str) -> Result<usize, DbError> {
+        let txn = self.txn.as_mut().ok_or(DbError::NoTransaction)?;
+        let position = txn.savepoints.iter().rposition(|sp| sp.name == name)
+            .ok_or_else(|| DbError::SavepointNotFound(name.to_string()))?;
+
+        // Newest first, so older snapshots of the same table win
+        let mut undos: Vec<Undo> = txn.savepoints.drain(position + 1..).rev().map(|sp| sp.undo).collect();
+        let savepoint = &mut txn.savepoints[position];
+        undos.push(std::mem::take(&mut savepoint.undo));
+        let undone = txn.ops.len() - savepoint.ops;
+        txn.ops.truncate(savepoint.ops);
+        txn.events.truncate(savepoint.events);
+
+        for undo in undos {
+            self.restore(undo);
+        }
+        Ok(undone)
+    }
+
+    /// Forgets the savepoint and any set after it, keeping their changes.
+    pub fn release(&mut self, name: &str) -> Result<(), DbError> {
+        let txn = self.txn.as_mut().ok_or(DbError::NoTransaction)?;
+        let position = txn.savepoints.iter().rposition(|sp| sp.name == name)
+            .ok_or_else(|| DbError::SavepointNotFound(name.to_string()))?;
+
+        let released: Vec<Savepoint> = txn.savepoints.drain(position..).collect();
+        // The enclosing savepoint must still be able to undo what they covered
+        if let Some(outer) = txn.savepoints.last_mut() {
+            for savepoint in released {
+                for (table, snapshot) in savepoint.undo {
+                    outer.undo.entry(table).or_insert(snapshot);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn restore(&mut self, undo: Undo) {
+        for (name, (table, dirty)) in undo {
+            self.versions.changed(&name);
+            if let Some(entry) = self.cache.get_mut(&name) {
+                entry.table = table;
+                entry.dirty = dirty;
+            }
+        }
+        // Partitioned tables are read again from their partitions as restored
+        self.cache.retain(|_, entry| entry.table.partitioning.is_none());
+    }
+
+    /// Writes every dirty table (and any table with records left in the log
+    /// from a previous run) to storage, then truncates the log. A table that
+    /// has only had rows inserted since it was last written has them
+    /// appended beside its file instead. Returns the number of tables written.
+    pub fn checkpoint(&mut self) -> Result<usize, DbError> {
+        self.check_read_write()?;
+        // Dirty tables may hold uncommitted rows
+        if self.txn.is_some() {
+            return Err(DbError::TransactionActive);
+        }
+        let mut names: Vec<String> = self.cache.iter()
+            .filter(|(_, c)| c.dirty)
+            .map(|(name, _)| name.clone())
+            .collect();
+        let records = self.wal.records()?;
+        for record in &records {
+            for name in record.op.tables() {
+                if !names.iter().any(|n| n == name) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+
+        let mut written = 0;
+        for name in names {
+            let table = match self.load_table(&name) {
+                Ok(table) => table.clone(),
+                // Records for dropped tables are simply discarded
+                Err(DbError::TableNotFound { .. }) => continue,
+                Err(e) => return Err(e),
+            };
+            let appended = match self.cache.get(&name).and_then(|entry| entry.saved) {
+                Some(saved) => match append::inserts(&name, &records, saved.lsn) {
+                    Some(inserts) => self.append(&table, saved, &inserts)?,
+                    None => None,
+                },
+                None => None,
+            };
+            match appended {
+                Some(saved) => {
+                    let entry = self.cache.get_mut(&name).expect("a table just loaded");
+                    entry.saved = Some(saved);
+                    entry.dirty = false;
+                }
+                None => self.save_table(&table)?,
+            }
+            written += 1;
+        }
+
+        if let Some(dir) = self.settings()?.wal_archive {
+            self.wal.archive(Path::new(&dir))?;
+        }
+        self.wal.truncate()?;
+        Ok(written)
+    }
+}
+
+// Takes `rows`, ascending positions in a partitioned table, out of where its
+// rows are stored, moving up the rows after them in the same partition.
+// Returns the positions taken out of each partition, ascending.
+fn unstore(stored_at: &mut Vec<(usize, usize)>, rows: &[usize]) -> BTreeMap<usize, Vec<usize>> {
+    let mut taken: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for &row in rows {
+        let (partition, at) = stored_at[row];
+        taken.entry(partition).or_default().push(at);
+    }
+    for positions in taken.values_mut() {
+        positions.sort_unstable();
+    }
+    let mut row = 0;
+    stored_at.retain(|_| {
+        row += 1;
+        rows.binary_search(&(row - 1)).is_err()
+    });
+    for (partition, at) in stored_at.iter_mut() {
+        *at -= taken.get(partition).map_or(0, |positions| positions.partition_point(|&p| p < *at));
+    }
+    taken
+}
@@ -0,0 +1,77 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::DbError;
+
+/// Name of the database stored directly in the data directory.
+pub const DEFAULT_DATABASE: &str = "default";
+
+/// The data directory as a namespace of databases: the default database lives
+/// in the directory itself and every named database in a subdirectory.
+pub struct DataRoot {
+    dir: PathBuf,
+}
+
+impl DataRoot {
+    pub fn new(dir: &Path) -> DataRoot {
+        DataRoot { dir: dir.to_path_buf() }
+    }
+
+    pub fn path_of(&self, name: &str) -> Result<PathBuf, DbError> {
+        if name == DEFAULT_DATABASE {
+            return Ok(self.dir.clone());
+        }
+        validate_name(name)?;
+        Ok(self.dir.join(name))
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        self.path_of(name).is_ok_and(|path| path.is_dir())
+    }
+
+    pub fn create(&self, name: &str) -> Result<(), DbError> {
+        if self.exists(name) {
+            return Err(DbError::DatabaseExists(name.to_string()));
+        }
+        fs::create_dir_all(self.path_of(name)?)?;
+        Ok(())
+    }
+
+    pub fn drop(&self, name: &str) -> Result<(), DbError> {
+        if name == DEFAULT_DATABASE {
+            return Err(DbError::InvalidName("the default database cannot be dropped".to_string()));
+        }
+        if !self.exists(name) {
+            return Err(DbError::DatabaseNotFound(name.to_string()));
+        }
+        fs::remove_dir_all(self.path_of(name)?)?;
+        Ok(())
+    }
+
+    pub fn list(&self) -> Result<Vec<String>, DbError> {
+        let mut names = vec![DEFAULT_DATABASE.to_string()];
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Hidden directories hold engine internals such as quarantined files
+            if entry.file_type()?.is_dir() && !name.starts_with('.') {
+                names.push(name);
+            }
+        }
+        names[1..].sort();
+        Ok(names)
+    }
+}
+
+fn validate_name(name: &str) -> Result<(), DbError> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if valid {
+        Ok(())
+    } else {
+        Err(DbError::InvalidName(format!(
+            "'{}' is not a valid database name (use letters, digits, '_' or '-')", name
+        )))
+    }
+}
@@ -0,0 +1,139 @@
+//! What depends on what: views on the tables and views they read, indexes
+//! and triggers on their tables, and triggers on the tables their
+//! statements write to. `DROP TABLE` and `DROP VIEW` refuse to leave a view
+//! reading, or a trigger writing to, something gone, naming what is in the
+//! way; with `CASCADE` they drop it too. Tables have no foreign keys, so no
+//! table depends on another by itself.
+
+use std::collections::BTreeSet;
+
+use crate::database::Database;
+use crate::error::DbError;
+use crate::parser::Statement;
+use crate::triggers::Trigger;
+
+/// One object resting on another.
+pub struct Dependency {
+    pub name: String,
+    pub kind: &'static str, // `view`, `materialized view`, `index` or `trigger`
+    pub table: Option<String>, // The table an index or trigger is on
+    pub on: String,
+}
+
+/// What would have to go before a table or view can be dropped, in an order
+/// it can be dropped in.
+#[derive(Debug, Default)]
+pub struct Dependents {
+    pub views: Vec<String>,              // A view before any it reads
+    pub triggers: Vec<(String, String)>, // Triggers writing to it, by table and name
+}
+
+impl Dependents {
+    pub fn is_empty(&self) -> bool {
+        self.views.is_empty() && self.triggers.is_empty()
+    }
+
+    /// The dependents as an error message lists them.
+    pub fn names(&self) -> Vec<String> {
+        self.views.iter()
+            .map(|view| format!("view '{}'", view))
+            .chain(self.triggers.iter().map(|(table, trigger)| format!("trigger '{}' on '{}'", trigger, table)))
+            .collect()
+    }
+}
+
+impl Trigger {
+    /// The tables the trigger's statements write to.
+    pub fn targets(&self, columns: &[String]) -> BTreeSet<String> {
+        self.statements(columns, &self.placeholder(columns))
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|statement| match statement {
+                Statement::Insert { table, .. } | Statement::Delete { table, .. } => Some(table),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl Database {
+    /// Every dependency between the tables and views of the database.
+    pub fn dependencies(&mut self) -> Result<Vec<Dependency>, DbError> {
+        let mut dependencies: Vec<Dependency> = self.views()?
+            .into_iter()
+            .map(|view| Dependency {
+                kind: if view.materialized.is_some() { "materialized view" } else { "view" },
+                name: view.name,
+                table: None,
+                on: view.table,
+            })
+            .collect();
+        for name in self.table_names()? {
+            let table = self.definition(&name)?;
+            for def in &table.index_defs {
+                dependencies.push(Dependency { name: def.name.clone(), kind: "index", table: Some(name.clone()), on: name.clone() });
+            }
+            for trigger in &table.triggers {
+                let dependency = |on: String| Dependency {
+                    name: trigger.name.clone(),
+                    kind: "trigger",
+                    table: Some(name.clone()),
+                    on,
+                };
+                dependencies.push(dependency(name.clone()));
+                dependencies.extend(trigger.targets(&table.columns).into_iter().filter(|target| *target != name).map(dependency));
+            }
+        }
+        Ok(dependencies)
+    }
+
+    /// The views reading `name`, directly or through other views, and the
+    /// triggers of other tables writing to it or to one of those views.
+    pub fn dependents(&mut self, name: &str) -> Result<Dependents, DbError> {
+        let views = self.views()?;
+        let mut dependents = Dependents::default();
+        let mut pending = vec![name.to_string()];
+        let mut reached = vec![name.to_string()];
+        // Views form no cycles: a view is only created on something that exists
+        while let Some(current) = pending.pop() {
+            for view in views.iter().filter(|view| view.table == current) {
+                reached.push(view.name.clone());
+                pending.push(view.name.clone());
+            }
+        }
+        // Each view is reached after the one it reads, so reversed it comes first
+        dependents.views = reached[1..].iter().rev().cloned().collect();
+
+        for table_name in self.table_names()? {
+            if reached.contains(&table_name) {
+                continue;
+            }
+            let table = self.definition(&table_name)?;
+            for trigger in &table.triggers {
+                if trigger.targets(&table.columns).iter().any(|target| reached.contains(target)) {
+                    dependents.triggers.push((table_name.clone(), trigger.name.clone()));
+                }
+            }
+        }
+        Ok(dependents)
+    }
+
+    /// Clears the way to drop `name`: drops what depends on it if `cascade`,
+    /// and otherwise fails naming it, if anything does.
+    pub fn drop_dependents(&mut self, name: &str, cascade: bool) -> Result<Dependents, DbError> {
+        let dependents = self.dependents(name)?;
+        if dependents.is_empty() {
+            return Ok(dependents);
+        }
+        if !cascade {
+            return Err(DbError::HasDependents { name: name.to_string(), dependents: dependents.names() });
+        }
+        for view in &dependents.views {
+            self.drop_view(view)?;
+        }
+        for (table, trigger) in &dependents.triggers {
+            self.drop_trigger(table, trigger)?;
+        }
+        Ok(dependents)
+    }
+}
@@ -0,0 +1,83 @@
+//! Dictionary encoding of string columns in saved tables. A column whose
+//! values repeat (no more distinct values than half its rows) is saved as
+//! the list of its distinct values and, for each row, the position of its
+//! value in that list, instead of spelling every value out. Tables are
+//! read back to plain values, so queries never see the difference; only
+//! the files get smaller.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::de::Deserializer;
+use serde::ser::{SerializeMap, Serializer};
+use serde::{Deserialize, Serialize};
+
+use crate::table::DataType;
+
+/// Columns with fewer rows than this are saved as they are.
+const MIN_ROWS: usize = 16;
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Column {
+    Dictionary { dictionary: Vec<String>, codes: Vec<u32> },
+    Plain(Vec<DataType>),
+}
+
+/// The distinct values of `values` and the position of each value among
+/// them, if they are all strings and repeat enough to be worth it.
+fn encode(values: &[DataType]) -> Option<(Vec<&str>, Vec<u32>)> {
+    if values.len() < MIN_ROWS {
+        return None;
+    }
+    let mut dictionary = Vec::new();
+    let mut positions: HashMap<&str, u32> = HashMap::new();
+    let mut codes = Vec::with_capacity(values.len());
+    for value in values {
+        let DataType::String(text) = value else { return None };
+        let code = *positions.entry(text).or_insert_with(|| {
+            dictionary.push(text.as_str());
+            dictionary.len() as u32 - 1
+        });
+        if dictionary.len() > values.len() / 2 {
+            return None;
+        }
+        codes.push(code);
+    }
+    Some((dictionary, codes))
+}
+
+/// Writes the columns of a table, dictionary-encoding those that repeat.
+pub fn serialize<S: Serializer>(data: &BTreeMap<String, Vec<DataType>>, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut map = serializer.serialize_map(Some(data.len()))?;
+    for (column, values) in data {
+        match encode(values) {
+            Some((dictionary, codes)) => {
+                #[derive(Serialize)]
+                struct Encoded<'a> {
+                    dictionary: Vec<&'a str>,
+                    codes: Vec<u32>,
+                }
+                map.serialize_entry(column, &Encoded { dictionary, codes })?;
+            }
+            None => map.serialize_entry(column, values)?,
+        }
+    }
+    map.end()
+}
+
+/// Reads the columns of a table, decoding those saved with a dictionary.
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BTreeMap<String, Vec<DataType>>, D::Error> {
+    let columns = BTreeMap::<String, Column>::deserialize(deserializer)?;
+    columns.into_iter()
+        .map(|(name, column)| match column {
+            Column::Plain(values) => Ok((name, values)),
+            Column::Dictionary { dictionary, codes } => {
+                let values = codes.into_iter()
+                    .map(|code| dictionary.get(code as usize).map(|text| DataType::String(text.clone())))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| serde::de::Error::custom(format!("column '{}' has a code outside its dictionary", name)))?;
+                Ok((name, values))
+            }
+        })
+        .collect()
+}
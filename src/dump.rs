@@ -0,0 +1,308 @@
+use std::fs;
+use std::path::Path;
+
+use crate::database::Database;
+use crate::error::DbError;
+use crate::history::{self, History};
+use crate::index::IndexDef;
+use crate::parser::{CmpOp, Predicate};
+use crate::partition::{Partitioning, Scheme};
+use crate::sequences::Sequence;
+use crate::table::{enum_labels, parse_array};
+use crate::triggers::{Timing, Trigger};
+use crate::views::View;
+use crate::{DataType, Table};
+
+/// A value as a literal the parser reads back unchanged.
+pub fn literal(value: &DataType) -> String {
+    match value {
+        DataType::String(s) => format!("'{}'", s.replace('\'', "''")),
+        // In the text form an array column reads
+        DataType::Array(_) => literal(&DataType::String(value.to_string())),
+        other => other.to_string(),
+    }
+}
+
+/// The `CREATE TABLE` statement for a table's columns, defaults, primary
+/// key, TTL, soft delete, timestamps and partitions, or the `CREATE EXTERNAL
+/// TABLE` for an external table.
+pub fn create_table(table: &Table) -> String {
+    create_table_as(table, "TABLE", true)
+}
+
+// Without `filled`, the columns the engine fills, timestamps and those with
+// a DEFAULT, are declared as plain columns, for a dump to give their values
+fn create_table_as(table: &Table, kind: &str, filled: bool) -> String {
+    let kind = if table.external.is_some() { "EXTERNAL TABLE" } else { kind };
+    let mut sql = format!("CREATE {} {}", kind, table.name);
+    for column in &table.columns {
+        let typ = &table.fields[column];
+        match enum_labels(typ) {
+            // Quoted, as a label need not be a word
+            Some(labels) => {
+                let labels: Vec<String> = labels.into_iter().map(|label| literal(&DataType::String(label.to_string()))).collect();
+                sql.push_str(&format!(" {}:enum({})", column, labels.join(", ")));
+            }
+            None => sql.push_str(&format!(" {}:{}", column, typ)),
+        }
+        if !table.collation(column).is_binary() {
+            sql.push_str(&format!(" COLLATE {}", table.collation(column).name()));
+        }
+        if filled && let Some(sequence) = table.sequences.get(column) {
+            sql.push_str(&format!(" DEFAULT {}", nextval(sequence)));
+        }
+        if let Some(expr) = table.generated.get(column) {
+            sql.push_str(&format!(" GENERATED AS ({})", expr));
+        }
+        if table.primary_key.as_ref() == Some(column) {
+            sql.push_str(" PRIMARY KEY");
+        }
+    }
+    if let Some(column) = &table.ttl {
+        sql.push_str(&format!(" WITH TTL {}", column));
+    }
+    if table.tombstones.is_some() {
+        sql.push_str(" WITH SOFT DELETE");
+    }
+    if filled && table.timestamps {
+        sql.push_str(" WITH TIMESTAMPS");
+    }
+    if !table.engine.is_json() {
+        sql.push_str(&format!(" ENGINE = {}", table.engine.name()));
+    }
+    if let Some(partitioning) = &table.partitioning {
+        sql.push_str(&partition_by(partitioning));
+    }
+    if let Some(external) = &table.external {
+        sql.push_str(&format!(" LOCATION {}", literal(&DataType::String(external.location.clone()))));
+        match external.delimiter {
+            ',' => {}
+            '\t' => sql.push_str(" DELIMITER TAB"),
+            delimiter => sql.push_str(&format!(" DELIMITER {}", literal(&DataType::String(delimiter.to_string())))),
+        }
+        if !external.header {
+            sql.push_str(" NO HEADER");
+        }
+    }
+    sql
+}
+
+fn partition_by(partitioning: &Partitioning) -> String {
+    let clause = format!(" PARTITION BY {} ({})", partitioning.scheme.keyword(), partitioning.column);
+    if partitioning.scheme == Scheme::Key {
+        return format!("{} PARTITIONS {}", clause, partitioning.partitions.len());
+    }
+    if partitioning.partitions.is_empty() {
+        return clause;
+    }
+    let partitions: Vec<String> = partitioning.partitions.iter()
+        .map(|partition| match &partition.below {
+            Some(below) => format!("PARTITION {} VALUES LESS THAN ({})", partition.name, literal(below)),
+            None => format!("PARTITION {} VALUES LESS THAN MAXVALUE", partition.name),
+        })
+        .collect();
+    format!("{} ({})", clause, partitions.join(", "))
+}
+
+pub fn create_index(table: &str, def: &IndexDef) -> String {
+    format!("CREATE INDEX {} ON {}({}) USING {}", def.name, table, def.columns.join(", "), def.kind.keyword())
+}
+
+pub fn create_view(view: &View) -> String {
+    let kind = if view.materialized.is_some() { "MATERIALIZED VIEW" } else { "VIEW" };
+    let mut sql = format!("CREATE {} {} AS SELECT * FROM {}", kind, view.name, view.table);
+    if !view.filter.is_empty() {
+        let conditions: Vec<String> = view.filter.iter().map(|p| condition(&view.table, p)).collect();
+        sql.push_str(&format!(" WHERE {}", conditions.join(" AND ")));
+    }
+    sql
+}
+
+pub fn create_trigger(table: &str, trigger: &Trigger) -> String {
+    let timing = match trigger.timing {
+        Timing::Before => "BEFORE",
+        Timing::After => "AFTER",
+    };
+    format!(
+        "CREATE TRIGGER {} {} {} ON {} DO '{}'",
+        trigger.name, timing, trigger.event, table, trigger.body.replace('\'', "''")
+    )
+}
+
+fn nextval(sequence: &str) -> String {
+    format!("NEXTVAL({})", literal(&DataType::String(sequence.to_string())))
+}
+
+/// The statements that recreate a sequence where it is now, so NEXTVAL
+/// gives the same value next.
+pub fn create_sequence(sequence: &Sequence) -> Vec<String> {
+    let create = |start: i64| format!("CREATE SEQUENCE {} START WITH {} INCREMENT BY {}", sequence.name, start, sequence.increment);
+    if i32::try_from(sequence.next).is_ok() {
+        return vec![create(sequence.next)];
+    }
+    // Run out of ints: the last value given is one, and the next one is not
+    let last = sequence.next - i64::from(sequence.increment);
+    vec![create(last), format!("SELECT SETVAL({}, {})", literal(&DataType::String(sequence.name.clone())), last)]
+}
+
+/// The `COMMENT ON` statements for the comments of a table and its columns.
+pub fn comments(table: &Table) -> Vec<String> {
+    let quoted = |comment: &String| literal(&DataType::String(comment.clone()));
+    let on_table = table.comment.iter().map(|comment| format!("COMMENT ON TABLE {} IS {}", table.name, quoted(comment)));
+    let on_columns = table.columns.iter()
+        .filter_map(|column| Some((column, table.column_comments.get(column)?)))
+        .map(|(column, comment)| format!("COMMENT ON COLUMN {}.{} IS {}", table.name, column, quoted(comment)));
+    on_table.chain(on_columns).collect()
+}
+
+pub fn set_max_rows(table: &str, max_rows: usize) -> String {
+    format!("ALTER TABLE {} SET MAX ROWS {}", table, max_rows)
+}
+
+pub fn set_history(table: &str, history: &History) -> String {
+    format!("ALTER TABLE {} SET HISTORY RETENTION {}", table, history::retention_text(history.retention))
+}
+
+// Values are quoted even for numbers, which the parser types by the column;
+// a column on the right must be qualified to be read as one
+fn condition(table: &str, predicate: &Predicate) -> String {
+    let right = match &predicate.against {
+        Some(against) => against
+            .rename_columns(&mut |column| Ok(format!("{}.{}", table, column)))
+            .map_or_else(|_| against.to_string(), |against| against.to_string()),
+        None if matches!(predicate.op, CmpOp::In | CmpOp::NotIn) => {
+            let items = parse_array(&predicate.value).unwrap_or_default();
+            let items: Vec<String> = items.into_iter().map(|item| literal(&DataType::String(item))).collect();
+            format!("({})", items.join(", "))
+        }
+        None => literal(&DataType::String(predicate.value.clone())),
+    };
+    format!("{} {} {}", predicate.left, predicate.op.symbol(), right)
+}
+
+// Generated columns are left to be computed again. Timestamps are kept, as
+// the table only starts keeping them once its rows are in.
+fn insert(table: &Table, row: usize) -> String {
+    let values: Vec<String> = table.columns.iter()
+        .filter(|col| !table.generated.contains_key(*col))
+        .map(|col| literal(&table.value(col, row)))
+        .collect();
+    format!("INSERT INTO {} {}", table.name, values.join(" "))
+}
+
+impl Database {
+    /// The statements that recreate a table or view as it is defined now,
+    /// without its rows: `CREATE TABLE` (or `CREATE TEMPORARY TABLE`, or
+    /// `CREATE VIEW`), then its comments, indexes and triggers.
+    pub fn create_statements(&mut self, name: &str) -> Result<Vec<String>, DbError> {
+        let mut statements = Vec::new();
+        let view = self.view(name)?;
+        if let Some(view) = &view {
+            statements.push(create_view(view));
+            if view.materialized.is_none() {
+                return Ok(statements);
+            }
+        }
+        let kind = if self.is_temp(name) { "TEMPORARY TABLE" } else { "TABLE" };
+        let table = self.load_table(name)?;
+        if view.is_none() {
+            statements.push(create_table_as(table, kind, true));
+        }
+        statements.extend(comments(table));
+        // The primary key's index, the only unique one, comes with CREATE TABLE
+        for def in table.index_defs.iter().filter(|def| !def.unique) {
+            statements.push(create_index(name, def));
+        }
+        for trigger in &table.triggers {
+            statements.push(create_trigger(name, trigger));
+        }
+        if let Some(history) = &table.history {
+            statements.push(set_history(name, history));
+        }
+        if let Some(max_rows) = table.max_rows {
+            statements.push(set_max_rows(name, max_rows));
+        }
+        Ok(statements)
+    }
+
+    /// Writes the database as SQL: the sequences, then a `CREATE TABLE` and
+    /// one `INSERT` per row for every table, then its indexes, triggers,
+    /// defaults and row limit (so that they do not act on the restore), then
+    /// the views. Running the file against an empty database restores it.
+    /// Temporary tables, users and grants are left out. Returns the number of
+    /// tables written.
+    pub fn dump(&mut self, path: &Path) -> Result<usize, DbError> {
+        let views = self.views()?;
+        let mut sql = String::from("-- RustDB dump\n");
+        let sequences = self.sequences()?;
+        if !sequences.is_empty() {
+            sql.push('\n');
+        }
+        for sequence in &sequences {
+            for statement in create_sequence(sequence) {
+                sql.push_str(&format!("{};\n", statement));
+            }
+        }
+        let mut tables = 0;
+        // Materialized views are created with their views, and fill themselves
+        let names: Vec<String> = self.table_names()?.into_iter()
+            .filter(|name| !self.is_temp(name) && !views.iter().any(|view| &view.name == name))
+            .collect();
+        for name in names {
+            // An external table's rows stay in its file, which need not be readable now
+            let definition;
+            let table = match self.external(&name)? {
+                Some(_) => {
+                    definition = self.read_table_file(&name)?;
+                    &definition
+                }
+                None => self.load_table(&name)?,
+            };
+            sql.push_str(&format!("\n{};\n", create_table_as(table, "TABLE", false)));
+            for comment in comments(table) {
+                sql.push_str(&format!("{};\n", comment));
+            }
+            // Soft-deleted rows are gone as far as a restore is concerned
+            for row in (0..table.row_count()).filter(|&row| !table.is_deleted(row)) {
+                sql.push_str(&format!("{};\n", insert(table, row)));
+            }
+            // The primary key's index, the only unique one, comes with CREATE TABLE
+            for def in table.index_defs.iter().filter(|def| !def.unique) {
+                sql.push_str(&format!("{};\n", create_index(&name, def)));
+            }
+            for trigger in &table.triggers {
+                sql.push_str(&format!("{};\n", create_trigger(&name, trigger)));
+            }
+            if table.timestamps {
+                sql.push_str(&format!("ALTER TABLE {} SET TIMESTAMPS ON;\n", name));
+            }
+            for (column, sequence) in &table.sequences {
+                sql.push_str(&format!("ALTER TABLE {} ALTER COLUMN {} SET DEFAULT {};\n", name, column, nextval(sequence)));
+            }
+            // The changes kept are not dumped, only how long to keep new ones
+            if let Some(history) = &table.history {
+                sql.push_str(&format!("{};\n", set_history(&name, history)));
+            }
+            // After the rows, which a limit lowered since may not leave room for
+            if let Some(max_rows) = table.max_rows {
+                sql.push_str(&format!("{};\n", set_max_rows(&name, max_rows)));
+            }
+            tables += 1;
+        }
+
+        if !views.is_empty() {
+            sql.push('\n');
+        }
+        for view in &views {
+            sql.push_str(&format!("{};\n", create_view(view)));
+            if view.materialized.is_some() {
+                for def in self.load_table(&view.name)?.index_defs.iter().filter(|def| !def.unique) {
+                    sql.push_str(&format!("{};\n", create_index(&view.name, def)));
+                }
+            }
+        }
+
+        fs::write(path, sql)?;
+        Ok(tables)
+    }
+}
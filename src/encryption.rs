@@ -0,0 +1,321 @@
+//! Encryption at rest: with a key given, every table, index and settings
+//! file and every log record is sealed with AES-256-GCM before it reaches
+//! disk, so a copied data directory reads as noise without the key. The key
+//! is derived from a passphrase with Argon2 and a salt kept, unencrypted, in
+//! the database's `encryption.header`, along with a value sealed under the
+//! key to tell a wrong passphrase from damaged files. A database is
+//! encrypted when it is created with a passphrase given to the function
+//! opening it, or by `REKEY`; one created without stays readable as it is.
+
+use std::io;
+use std::sync::{Arc, RwLock};
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use argon2::Argon2;
+use argon2::password_hash::rand_core::RngCore;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::{Serialize, Deserialize};
+
+use crate::backup::is_content;
+use crate::database::Database;
+use crate::error::DbError;
+use crate::storage::{Stamp, Stamps, Storage};
+
+/// The blob holding the salt and the check, never itself encrypted.
+pub const HEADER_KEY: &str = "encryption.header";
+
+const CIPHER: &str = "aes-256-gcm";
+// The cipher of the header `REKEY OFF` swaps in, removed once the blobs
+// written again without a key are all in place
+const NO_CIPHER: &str = "none";
+// What `REKEY` writes each blob to, under the new key, before the header
+// swapped in for the staged one makes them the database's
+const STAGED: &str = ".rekey";
+const NONCE_BYTES: usize = 12;
+// Sealed into the header, so opening it proves the key right
+const CHECK: &[u8] = b"rustdb";
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    cipher: String,
+    salt: String,  // Base64
+    check: String, // CHECK sealed under the key, base64
+    // The log's last LSN when `REKEY` wrote the header, for the log to go
+    // on from should a crash keep it from being written under the new key
+    #[serde(default)]
+    lsn: u64,
+}
+
+impl Header {
+    // The header of a database sealed with `cipher`, derived with `salt`,
+    // or of one `REKEY OFF` decrypts if None
+    fn encode(cipher: Option<(&Cipher, &[u8])>, lsn: u64) -> io::Result<Vec<u8>> {
+        let header = match cipher {
+            Some((cipher, salt)) => Header { cipher: CIPHER.to_string(), salt: STANDARD.encode(salt), check: STANDARD.encode(cipher.seal(CHECK)), lsn },
+            None => Header { cipher: NO_CIPHER.to_string(), salt: String::new(), check: String::new(), lsn },
+        };
+        Ok(serde_json::to_vec_pretty(&header)?)
+    }
+}
+
+/// A key, ready to seal and open blobs.
+#[derive(Clone)]
+pub struct Cipher(Aes256Gcm);
+
+/// The key a database's storage and log are sealed with, None if they are
+/// not; shared between them so `REKEY` changes it for both at once.
+pub type SharedCipher = Arc<RwLock<Option<Cipher>>>;
+
+impl Cipher {
+    fn derive(passphrase: &str, salt: &[u8]) -> io::Result<Cipher> {
+        let mut key = [0u8; 32];
+        Argon2::default().hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("could not derive the encryption key: {}", e)))?;
+        Ok(Cipher(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))))
+    }
+
+    /// A key from `passphrase` with a fresh salt, and the header to save
+    /// with the database it seals, whose log has reached `lsn`.
+    pub fn create(passphrase: &str, lsn: u64) -> io::Result<(Cipher, Vec<u8>)> {
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let cipher = Cipher::derive(passphrase, &salt)?;
+        let header = Header::encode(Some((&cipher, &salt)), lsn)?;
+        Ok((cipher, header))
+    }
+
+    /// `plain` encrypted under a fresh nonce, which goes in front of it.
+    pub fn seal(&self, plain: &[u8]) -> Vec<u8> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let mut sealed = nonce.to_vec();
+        sealed.extend(self.0.encrypt(&nonce, plain).expect("AES-GCM encrypts any message that fits in memory"));
+        sealed
+    }
+
+    /// What `seal` was given, failing if `sealed` was made with another key
+    /// or has been changed since.
+    pub fn open(&self, sealed: &[u8]) -> io::Result<Vec<u8>> {
+        let failed = || io::Error::new(io::ErrorKind::InvalidData, "could not decrypt: wrong key, or the file is damaged");
+        if sealed.len() < NONCE_BYTES {
+            return Err(failed());
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_BYTES);
+        self.0.decrypt(Nonce::from_slice(nonce), ciphertext).map_err(|_| failed())
+    }
+}
+
+/// The key `storage` is sealed with, from `passphrase`: None if it is not
+/// encrypted. A database with no contents yet is made encrypted if a
+/// passphrase is given. Fails if it is encrypted and no passphrase, or
+/// another one, is given.
+pub fn unlock(storage: &mut dyn Storage, passphrase: Option<&str>) -> io::Result<Option<Cipher>> {
+    let Some(bytes) = storage.read(HEADER_KEY)? else {
+        let empty = !storage.keys()?.iter().any(|key| is_content(key));
+        return match passphrase {
+            Some(passphrase) if empty => {
+                let (cipher, header) = Cipher::create(passphrase, 0)?;
+                storage.write(HEADER_KEY, &header)?;
+                Ok(Some(cipher))
+            }
+            _ => Ok(None),
+        };
+    };
+    let header: Header = serde_json::from_slice(&bytes)?;
+    let invalid = |reason: &str| io::Error::new(io::ErrorKind::InvalidData, format!("invalid {}: {}", HEADER_KEY, reason));
+    if header.cipher != CIPHER {
+        return Err(invalid(&format!("unknown cipher '{}'", header.cipher)));
+    }
+    let salt = STANDARD.decode(&header.salt).map_err(|_| invalid("salt is not base64"))?;
+    let check = STANDARD.decode(&header.check).map_err(|_| invalid("check is not base64"))?;
+    let passphrase = passphrase.ok_or_else(|| io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        "the database is encrypted; give its key (encryption_key, RUSTDB_ENCRYPTION_KEY or --ask-key)",
+    ))?;
+    let cipher = Cipher::derive(passphrase, &salt)?;
+    match cipher.open(&check) {
+        Ok(opened) if opened == CHECK => Ok(Some(cipher)),
+        _ => Err(io::Error::new(io::ErrorKind::PermissionDenied, "wrong encryption key")),
+    }
+}
+
+/// `storage` behind a `SealedStorage`, with the key `unlock` finds for it
+/// from `passphrase`, which the database's log is to share. Also gives the
+/// LSN of a `REKEY` a crash left for this to finish, if any: the log, still
+/// sealed under the old key, is to be emptied and go on from there.
+pub(crate) fn wrap(mut storage: Box<dyn Storage>, passphrase: Option<&str>) -> io::Result<(Box<dyn Storage>, SharedCipher, Option<u64>)> {
+    let rekeyed = finish_rekey(storage.as_mut())
+        .map_err(|e| io::Error::new(e.kind(), format!("could not finish an interrupted REKEY: {}", e)))?;
+    let cipher = SharedCipher::new(RwLock::new(unlock(storage.as_mut(), passphrase)?));
+    Ok((Box::new(SealedStorage::new(storage, cipher.clone())), cipher, rekeyed))
+}
+
+// Where `REKEY` stages `key`
+fn staged(key: &str) -> String {
+    format!("{}{}", key, STAGED)
+}
+
+// Finishes a `REKEY` cut short: while its staged header is still there it
+// was not swapped in, and the blobs staged are removed, the header last;
+// once it has been, they replace the blobs they were staged for. Returns
+// the LSN of the `REKEY` finished, if it had been swapped in.
+fn finish_rekey(storage: &mut dyn Storage) -> io::Result<Option<u64>> {
+    let staged_keys: Vec<String> = storage.keys()?.into_iter()
+        .filter(|key| key.ends_with(STAGED) && key != &staged(HEADER_KEY))
+        .collect();
+    if storage.exists(&staged(HEADER_KEY)) {
+        for key in staged_keys {
+            storage.remove(&key)?;
+        }
+        storage.remove(&staged(HEADER_KEY))?;
+        return Ok(None);
+    }
+    let header: Option<Header> = match storage.read(HEADER_KEY)? {
+        Some(bytes) => Some(serde_json::from_slice(&bytes)?),
+        None => None,
+    };
+    let decrypted = header.as_ref().is_some_and(|header| header.cipher == NO_CIPHER);
+    if staged_keys.is_empty() && !decrypted {
+        return Ok(None);
+    }
+    for key in staged_keys {
+        storage.rename(&key, key.strip_suffix(STAGED).unwrap())?;
+    }
+    if decrypted {
+        storage.remove(HEADER_KEY)?;
+    }
+    Ok(header.map(|header| header.lsn))
+}
+
+/// Storage that seals every blob written to the storage underneath, and
+/// opens every blob read, while the shared key is set. The header is passed
+/// through as it is.
+pub struct SealedStorage {
+    inner: Box<dyn Storage>,
+    cipher: SharedCipher,
+}
+
+impl SealedStorage {
+    pub fn new(inner: Box<dyn Storage>, cipher: SharedCipher) -> SealedStorage {
+        SealedStorage { inner, cipher }
+    }
+
+    fn cipher(&self, key: &str) -> Option<Cipher> {
+        match key == HEADER_KEY || key == staged(HEADER_KEY) {
+            true => None,
+            false => self.cipher.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone(),
+        }
+    }
+}
+
+impl Storage for SealedStorage {
+    fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        let Some(bytes) = self.inner.read(key)? else { return Ok(None) };
+        match self.cipher(key) {
+            Some(cipher) => cipher.open(&bytes)
+                .map(Some)
+                .map_err(|e| io::Error::new(e.kind(), format!("{} ({})", e, key))),
+            None => Ok(Some(bytes)),
+        }
+    }
+
+    fn write(&mut self, key: &str, bytes: &[u8]) -> io::Result<()> {
+        match self.cipher(key) {
+            Some(cipher) => self.inner.write(key, &cipher.seal(bytes)),
+            None => self.inner.write(key, bytes),
+        }
+    }
+
+    fn remove(&mut self, key: &str) -> io::Result<bool> {
+        self.inner.remove(key)
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> io::Result<()> {
+        self.inner.rename(from, to)
+    }
+
+    fn exists(&self, key: &str) -> bool {
+        self.inner.exists(key)
+    }
+
+    fn stamp(&self, key: &str) -> Option<Stamp> {
+        self.inner.stamp(key)
+    }
+
+    fn stamps(&self) -> Option<Stamps> {
+        self.inner.stamps()
+    }
+
+    fn keys(&self) -> io::Result<Vec<String>> {
+        self.inner.keys()
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        self.inner.size()
+    }
+
+    fn discard_torn_writes(&mut self) -> io::Result<Vec<String>> {
+        self.inner.discard_torn_writes()
+    }
+}
+
+impl Database {
+    /// Seals the database under a key derived from `passphrase`, or leaves
+    /// it unencrypted if None: folds the log into the table files, then
+    /// writes every table, index and settings file again under the new key.
+    /// They are staged beside the old ones and swapped in with the header,
+    /// so a crash part way leaves the database under one key or the other,
+    /// never both. Archived log segments keep the key they were written
+    /// with. Returns the number of files written.
+    pub fn rekey(&mut self, passphrase: Option<&str>) -> Result<usize, DbError> {
+        if self.in_transaction() {
+            return Err(DbError::TransactionActive);
+        }
+        if !self.wal.is_on_disk() {
+            return Err(DbError::Io(io::Error::new(io::ErrorKind::Unsupported, "only a database kept in a directory or file can be encrypted")));
+        }
+        self.checkpoint()?;
+        let mut blobs = Vec::new();
+        for key in self.storage.keys()?.into_iter().filter(|key| is_content(key)) {
+            if let Some(blob) = self.storage.read(&key)? {
+                blobs.push((key, blob));
+            }
+        }
+
+        let lsn = self.wal.last_lsn();
+        let (cipher, header) = match passphrase {
+            Some(passphrase) => {
+                let (cipher, header) = Cipher::create(passphrase, lsn)?;
+                (Some(cipher), header)
+            }
+            None => (None, Header::encode(None, lsn)?),
+        };
+        // Blobs written from now on are sealed under the new key
+        let old = std::mem::replace(&mut *self.wal.cipher.write().unwrap_or_else(|poisoned| poisoned.into_inner()), cipher);
+        if let Err(e) = self.swap_key(&header, &blobs) {
+            *self.wal.cipher.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = old;
+            let _ = finish_rekey(self.storage.as_mut());
+            return Err(e);
+        }
+        // Only the checkpoint marker is left in the log, written again under
+        // the new key before the staged blobs are put in place; should a
+        // crash come between, opening the database empties the log again
+        self.wal.truncate()?;
+        finish_rekey(self.storage.as_mut())?;
+        self.passphrase = passphrase.map(str::to_string);
+        Ok(blobs.len())
+    }
+
+    // Stages the header first, so the blobs staged after it are removed if
+    // a crash comes before the header is swapped in, then the blobs, then
+    // swaps the header in
+    fn swap_key(&mut self, header: &[u8], blobs: &[(String, Vec<u8>)]) -> Result<(), DbError> {
+        self.storage.write(&staged(HEADER_KEY), header)?;
+        for (key, blob) in blobs {
+            self.storage.write(&staged(key), blob)?;
+        }
+        self.storage.rename(&staged(HEADER_KEY), HEADER_KEY)?;
+        Ok(())
+    }
+}
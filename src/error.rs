@@ -0,0 +1,310 @@
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum DbError {
+    Io(io::Error),
+    TableNotFound { name: String, suggestion: Option<String> }, // A table or view of a close name
+    CorruptTable { table: String, reason: String },
+    DatabaseNotFound(String),
+    DatabaseExists(String),
+    DatabaseInUse(String),
+    InvalidName(String),
+    Syntax(String),
+    // Where in the statement's text parsing stopped: the byte offset of the
+    // token it was at, and that token as written, None at the end of input
+    SyntaxAt { reason: String, offset: usize, token: Option<String> },
+    ColumnNotFound { table: String, column: String },
+    AmbiguousColumn(String),
+    ColumnCount { expected: usize, found: usize },
+    TypeMismatch { column: String, expected: String, value: String },
+    GeneratedColumn(String),
+    IndexExists(String),
+    IndexNotFound(String),
+    AmbiguousIndex { name: String, tables: Vec<String> },
+    InvalidIndex(String),
+    DuplicateKey { index: String, value: String },
+    TransactionActive,
+    NoTransaction,
+    SavepointNotFound(String),
+    UserExists(String),
+    UserNotFound(String),
+    TokenExists(String),
+    TokenNotFound(String),
+    VariableNotFound(String),
+    SequenceExists(String),
+    SequenceNotFound(String),
+    SequenceExhausted(String),
+    PermissionDenied(String),
+    ViewExists(String),
+    ViewNotFound(String),
+    IsView(String), // A view named where a table must be
+    NotMaterialized(String),
+    ReadOnlyView(String),
+    TriggerExists(String),
+    TriggerNotFound(String),
+    TriggerFailed { trigger: String, reason: String },
+    CursorExists(String),
+    CursorNotFound(String),
+    PartitionExists(String),
+    PartitionNotFound { table: String, partition: String },
+    NotPartitioned(String),
+    SessionNotFound(u64),
+    InvalidPartition(String),
+    InvalidTtl(String),
+    InvalidDefinition(Vec<String>), // Every problem found
+    InvalidSoftDelete(String),
+    InvalidTimestamps(String),
+    NoPartition { table: String, value: String },
+    ReadOnly(String), // Why the database takes no writes
+    NotFollowing,
+    ExternalTable(String),
+    NoHistory(String),
+    HistoryUnavailable { table: String, since: u64 }, // The earliest time it can be read as of
+    HasDependents { name: String, dependents: Vec<String> },
+    FunctionNotFound(String),
+    FunctionFailed { function: String, reason: String },
+    InvalidExpression(String),
+    InvalidMigration(String),
+    MigrationFailed { version: u64, name: String, reason: String },
+    ReadFailed { source: String, reason: String }, // A file, quoted, or standard input
+    ScriptFailed { script: String, outcome: String },
+    NoMatch(String), // The condition no row met
+    Unavailable(String), // Why the statement cannot run here
+    ExternalFile { table: String, location: String, reason: String },
+    NewerFormat { table: String, format: u32 },
+    ImportFailed { line: usize, reason: String },
+    ExportFailed(String),
+    BackupFailed(String),
+    SystemTable(String),
+    RecursionLimit { name: String, rounds: usize },
+    Interrupted,
+    Timeout(u64), // The milliseconds allowed
+    MemoryLimit(usize), // The bytes allowed
+    QuotaExceeded(String), // What is full
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DbError::Io(e) => write!(f, "I/O error: {}", e),
+            DbError::TableNotFound { name, suggestion: None } => write!(f, "Table '{}' does not exist", name),
+            DbError::TableNotFound { name, suggestion: Some(suggestion) } => {
+                write!(f, "Table '{}' does not exist; did you mean '{}'?", name, suggestion)
+            }
+            DbError::CorruptTable { table, reason } => {
+                write!(f, "Table '{}' is corrupt: {}", table, reason)
+            }
+            DbError::DatabaseNotFound(name) => write!(f, "Database '{}' does not exist", name),
+            DbError::DatabaseExists(name) => write!(f, "Database '{}' already exists", name),
+            DbError::DatabaseInUse(name) => write!(f, "Database '{}' is in use; USE another database first", name),
+            DbError::InvalidName(reason) => write!(f, "Invalid name: {}", reason),
+            DbError::Syntax(reason) => write!(f, "Syntax error: {}", reason),
+            DbError::SyntaxAt { reason, offset, .. } => write!(f, "Syntax error at byte {}: {}", offset, reason),
+            DbError::ColumnNotFound { table, column } => {
+                write!(f, "Column '{}' does not exist in table '{}'", column, table)
+            }
+            DbError::GeneratedColumn(name) => {
+                write!(f, "Column '{}' is generated from the others and cannot be given a value", name)
+            }
+            DbError::AmbiguousColumn(name) => {
+                write!(f, "Column '{}' is in more than one table of the query; qualify it as <table>.{}", name, name)
+            }
+            DbError::ColumnCount { expected, found } => {
+                write!(f, "Column count mismatch: expected {} value(s), got {}", expected, found)
+            }
+            DbError::TypeMismatch { column, expected, value } => {
+                write!(f, "Value '{}' is not a valid {} for column '{}'", value, expected, column)
+            }
+            DbError::IndexExists(name) => write!(f, "Index '{}' already exists", name),
+            DbError::IndexNotFound(name) => write!(f, "Index '{}' does not exist", name),
+            DbError::AmbiguousIndex { name, tables } => {
+                write!(f, "Index '{}' is on several tables ({}); use DROP INDEX {} ON <table>", name, tables.join(", "), name)
+            }
+            DbError::InvalidIndex(reason) => write!(f, "Invalid index: {}", reason),
+            DbError::DuplicateKey { index, value } => {
+                write!(f, "Duplicate value '{}' violates unique index '{}'", value, index)
+            }
+            DbError::TransactionActive => {
+                write!(f, "Not allowed inside a transaction; COMMIT or ROLLBACK first")
+            }
+            DbError::NoTransaction => write!(f, "No transaction is in progress"),
+            DbError::SavepointNotFound(name) => write!(f, "Savepoint '{}' does not exist", name),
+            DbError::UserExists(name) => write!(f, "User '{}' already exists", name),
+            DbError::UserNotFound(name) => write!(f, "User '{}' does not exist", name),
+            DbError::TokenExists(name) => write!(f, "Token '{}' already exists", name),
+            DbError::TokenNotFound(name) => write!(f, "Token '{}' does not exist", name),
+            DbError::VariableNotFound(name) => write!(f, "Variable '@{}' is not set", name),
+            DbError::SequenceExists(name) => write!(f, "Sequence '{}' already exists", name),
+            DbError::SequenceNotFound(name) => write!(f, "Sequence '{}' does not exist", name),
+            DbError::SequenceExhausted(name) => write!(f, "Sequence '{}' has no int values left", name),
+            DbError::PermissionDenied(reason) => write!(f, "Permission denied: {}", reason),
+            DbError::ViewExists(name) => write!(f, "Table or view '{}' already exists", name),
+            DbError::ViewNotFound(name) => write!(f, "View '{}' does not exist", name),
+            DbError::IsView(name) => write!(f, "'{}' is a view; use DROP VIEW {}", name, name),
+            DbError::NotMaterialized(name) => write!(f, "View '{}' is not materialized", name),
+            DbError::ReadOnlyView(name) => write!(f, "View '{}' is read-only", name),
+            DbError::TriggerExists(name) => write!(f, "Trigger '{}' already exists", name),
+            DbError::TriggerNotFound(name) => write!(f, "Trigger '{}' does not exist", name),
+            DbError::TriggerFailed { trigger, reason } => write!(f, "Trigger '{}' failed: {}", trigger, reason),
+            DbError::CursorExists(name) => write!(f, "Cursor '{}' already exists", name),
+            DbError::CursorNotFound(name) => write!(f, "Cursor '{}' does not exist", name),
+            DbError::PartitionExists(name) => write!(f, "Partition '{}' already exists", name),
+            DbError::PartitionNotFound { table, partition } => {
+                write!(f, "Partition '{}' does not exist in table '{}'", partition, table)
+            }
+            DbError::NotPartitioned(name) => write!(f, "Table '{}' is not partitioned", name),
+            DbError::SessionNotFound(id) => write!(f, "Session {} does not exist", id),
+            DbError::InvalidPartition(reason) => write!(f, "Invalid partitioning: {}", reason),
+            DbError::InvalidTtl(reason) => write!(f, "Invalid TTL: {}", reason),
+            DbError::InvalidDefinition(problems) => write!(f, "Invalid table definition: {}", problems.join("; ")),
+            DbError::InvalidSoftDelete(reason) => write!(f, "Invalid soft delete: {}", reason),
+            DbError::InvalidTimestamps(reason) => write!(f, "Invalid timestamps: {}", reason),
+            DbError::NoPartition { table, value } => write!(f, "No partition of table '{}' takes rows with {}", table, value),
+            DbError::ReadOnly(reason) => write!(f, "Database is read-only: {}", reason),
+            DbError::NotFollowing => write!(f, "This server is not following a leader"),
+            DbError::ExternalTable(name) => write!(f, "Table '{}' is external and read-only; change its file instead", name),
+            DbError::NoHistory(name) => {
+                write!(f, "Table '{}' keeps no history; ALTER TABLE {} SET HISTORY RETENTION starts keeping it", name, name)
+            }
+            DbError::HistoryUnavailable { table, since } => {
+                write!(f, "The history of table '{}' only goes back to {} UTC", table, crate::time::format_timestamp(*since))
+            }
+            DbError::HasDependents { name, dependents } => {
+                write!(f, "Cannot drop '{}' as {} depend(s) on it; add CASCADE to drop them too", name, dependents.join(", "))
+            }
+            DbError::FunctionNotFound(name) => write!(f, "Function '{}' does not exist", name),
+            DbError::FunctionFailed { function, reason } => write!(f, "Function '{}' failed: {}", function, reason),
+            DbError::InvalidExpression(reason) => write!(f, "Invalid expression {}", reason),
+            DbError::InvalidMigration(reason) => write!(f, "Invalid migration: {}", reason),
+            DbError::MigrationFailed { version, name, reason } => write!(f, "Migration {} ({}) failed: {}", version, name, reason),
+            DbError::ReadFailed { source, reason } => write!(f, "Could not read {}: {}", source, reason),
+            DbError::ScriptFailed { script, outcome } => write!(f, "{} {}", script, outcome),
+            DbError::NoMatch(condition) => write!(f, "No row found with {}", condition),
+            DbError::Unavailable(reason) => write!(f, "{}", reason),
+            DbError::ExternalFile { table, location, reason } => {
+                write!(f, "Could not read '{}' for external table '{}': {}", location, table, reason)
+            }
+            DbError::NewerFormat { table, format } => write!(
+                f, "Table '{}' was saved in format {} by a newer release; this one reads formats up to {}",
+                table, format, crate::storage::FORMAT_VERSION
+            ),
+            DbError::ImportFailed { line, reason } => write!(f, "Import failed at line {}: {}", line, reason),
+            DbError::ExportFailed(reason) => write!(f, "Export failed: {}", reason),
+            DbError::BackupFailed(reason) => write!(f, "Backup failed: {}", reason),
+            DbError::SystemTable(name) => write!(f, "'{}' belongs to the system catalog and cannot be changed", name),
+            DbError::Interrupted => write!(f, "Statement cancelled"),
+            DbError::MemoryLimit(bytes) => {
+                write!(f, "Query needs more than the {} MiB of memory allowed (max_memory_mb)", bytes / (1024 * 1024))
+            }
+            DbError::Timeout(ms) => write!(f, "Statement cancelled after running longer than statement_timeout ({} ms)", ms),
+            DbError::RecursionLimit { name, rounds } => {
+                write!(f, "WITH RECURSIVE {} was still adding rows after {} rounds; raise max_recursion if it should go deeper", name, rounds)
+            }
+            DbError::QuotaExceeded(what) => write!(f, "Quota exceeded: {}", what),
+        }
+    }
+}
+
+impl DbError {
+    /// A code naming the kind of error, which stays the same from release to
+    /// release while the wording of messages may change. The first digit is
+    /// the group: 1 the statement itself, 2 a name that does or does not
+    /// exist, 3 the state of the data, 4 permissions, 5 limits and
+    /// cancellation, 6 files.
+    pub fn code(&self) -> &'static str {
+        match self {
+            DbError::Syntax(_) | DbError::SyntaxAt { .. } => "E1001",
+            DbError::TypeMismatch { .. } => "E1002",
+            DbError::ColumnNotFound { .. } => "E1003",
+            DbError::AmbiguousColumn(_) => "E1004",
+            DbError::ColumnCount { .. } => "E1005",
+            DbError::GeneratedColumn(_) => "E1006",
+            DbError::InvalidExpression(_) => "E1007",
+            DbError::InvalidName(_) => "E1008",
+            DbError::InvalidIndex(_) => "E1009",
+            DbError::FunctionFailed { .. } => "E1010",
+            DbError::InvalidPartition(_) => "E1011",
+            DbError::InvalidTtl(_) => "E1012",
+            DbError::InvalidDefinition(_) => "E1013",
+            DbError::InvalidSoftDelete(_) => "E1014",
+            DbError::InvalidTimestamps(_) => "E1015",
+            DbError::TableNotFound { .. } => "E2001",
+            DbError::DatabaseNotFound(_) => "E2002",
+            DbError::DatabaseExists(_) => "E2003",
+            DbError::IndexExists(_) => "E2004",
+            DbError::SavepointNotFound(_) => "E2005",
+            DbError::UserExists(_) => "E2006",
+            DbError::UserNotFound(_) => "E2007",
+            DbError::ViewExists(_) => "E2008",
+            DbError::ViewNotFound(_) => "E2009",
+            DbError::TriggerExists(_) => "E2010",
+            DbError::TriggerNotFound(_) => "E2011",
+            DbError::CursorExists(_) => "E2012",
+            DbError::CursorNotFound(_) => "E2013",
+            DbError::FunctionNotFound(_) => "E2014",
+            DbError::PartitionExists(_) => "E2015",
+            DbError::PartitionNotFound { .. } => "E2016",
+            DbError::NotPartitioned(_) => "E2017",
+            DbError::SessionNotFound(_) => "E2018",
+            DbError::IndexNotFound(_) => "E2019",
+            DbError::TokenExists(_) => "E2020",
+            DbError::TokenNotFound(_) => "E2021",
+            DbError::VariableNotFound(_) => "E2022",
+            DbError::SequenceExists(_) => "E2023",
+            DbError::SequenceNotFound(_) => "E2024",
+            DbError::AmbiguousIndex { .. } => "E2025",
+            DbError::IsView(_) => "E2026",
+            DbError::DuplicateKey { .. } => "E3001",
+            DbError::TransactionActive => "E3002",
+            DbError::NoTransaction => "E3003",
+            DbError::NotMaterialized(_) => "E3004",
+            DbError::ReadOnlyView(_) => "E3005",
+            DbError::SystemTable(_) => "E3006",
+            DbError::TriggerFailed { .. } => "E3007",
+            DbError::NoPartition { .. } => "E3008",
+            DbError::ReadOnly(_) => "E3009",
+            DbError::NotFollowing => "E3010",
+            DbError::ExternalTable(_) => "E3011",
+            DbError::NoHistory(_) => "E3012",
+            DbError::HistoryUnavailable { .. } => "E3013",
+            DbError::HasDependents { .. } => "E3014",
+            DbError::SequenceExhausted(_) => "E3015",
+            DbError::Unavailable(_) => "E3016",
+            DbError::ScriptFailed { .. } => "E3017",
+            DbError::NoMatch(_) => "E3018",
+            DbError::DatabaseInUse(_) => "E3019",
+            DbError::PermissionDenied(_) => "E4001",
+            DbError::Interrupted => "E5001",
+            DbError::Timeout(_) => "E5002",
+            DbError::MemoryLimit(_) => "E5003",
+            DbError::RecursionLimit { .. } => "E5004",
+            DbError::QuotaExceeded(_) => "E5005",
+            DbError::Io(_) => "E6001",
+            DbError::CorruptTable { .. } => "E6002",
+            DbError::ImportFailed { .. } => "E6003",
+            DbError::ExportFailed(_) => "E6004",
+            DbError::BackupFailed(_) => "E6005",
+            DbError::InvalidMigration(_) => "E6006",
+            DbError::ExternalFile { .. } => "E6007",
+            DbError::NewerFormat { .. } => "E6008",
+            DbError::MigrationFailed { .. } => "E6009",
+            DbError::ReadFailed { .. } => "E6010",
+        }
+    }
+
+    /// For a syntax error, the byte offset in the statement's text where
+    /// parsing stopped.
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            DbError::SyntaxAt { offset, .. } => Some(*offset),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DbError {
+    fn from(e: io::Error) -> Self {
+        DbError::Io(e)
+    }
+}
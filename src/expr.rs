@@ -0,0 +1,316 @@
+use std::fmt;
+
+use serde::{Serialize, Deserialize};
+
+use crate::aggregate::Aggregate;
+use crate::error::DbError;
+use crate::functions::Functions;
+use crate::parser;
+use crate::table;
+use crate::time;
+use crate::window::Window;
+use crate::{DataType, Table};
+
+/// A value computed for each row: a column, a literal, a function call, a
+/// conversion to another type, arithmetic on two of them, a window
+/// function over the rows a query matched or an aggregate over a group of
+/// them. Saved (in view definitions) as the text it was parsed from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum Expr {
+    Column(String),
+    Literal(DataType),
+    Call { function: String, args: Vec<Expr> },
+    Binary { op: BinaryOp, left: Box<Expr>, right: Box<Expr> },
+    Cast { expr: Box<Expr>, to: String }, // `to` is `int`, `float` or `string`
+    Window(Box<Window>),
+    Aggregate(Box<Aggregate>),
+}
+
+/// The types a value can be `CAST` to, as columns name them.
+pub const CAST_TYPES: [&str; 3] = ["int", "float", "string"];
+
+/// `value` as a `to`. Text is read as a number the way an `INSERT` reads it,
+/// ignoring surrounding whitespace; a float becomes an int by dropping its
+/// fraction, and must be in range. Anything else converts as it prints.
+pub fn cast(value: &DataType, to: &str) -> Result<DataType, String> {
+    let fail = || format!("cannot convert '{}' to {}", value, to);
+    match (value, to) {
+        (DataType::Integer32(i), "float") => Ok(DataType::Float32(*i as f32)),
+        (DataType::Float32(f), "int") => {
+            let whole = f.trunc();
+            if !whole.is_finite() || whole < i32::MIN as f32 || whole >= i32::MAX as f32 {
+                return Err(fail());
+            }
+            Ok(DataType::Integer32(whole as i32))
+        }
+        (DataType::String(s), "int") => s.trim().parse().map(DataType::Integer32).map_err(|_| fail()),
+        (DataType::String(s), "float") => s.trim().parse().map(DataType::Float32).map_err(|_| fail()),
+        (value, "string") => Ok(DataType::String(value.to_string())),
+        (value, table::POINT) => table::point_of(value).and_then(|(lat, lon)| table::point(lat, lon)).ok_or_else(fail),
+        (value, _) => Ok(value.clone()),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl BinaryOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+        }
+    }
+
+    /// How tightly the operator binds: `*` and `/` before `+` and `-`.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Add | BinaryOp::Sub => 1,
+            BinaryOp::Mul | BinaryOp::Div => 2,
+        }
+    }
+
+    /// Numbers add up as numbers, an int result staying an int (so an int
+    /// divided by an int drops the remainder). A number added to or taken
+    /// from a timestamp (`YYYY-MM-DD[ HH:MM[:SS]]` text) counts seconds and
+    /// gives a timestamp; one timestamp taken from another gives the seconds
+    /// between them.
+    pub fn apply(&self, left: &DataType, right: &DataType) -> Result<DataType, String> {
+        let sign = match self {
+            BinaryOp::Add => 1,
+            BinaryOp::Sub => -1,
+            BinaryOp::Mul | BinaryOp::Div => return self.multiply(left, right),
+        };
+        let timestamp = |text: &str| time::parse_timestamp(text).map(|at| at as i64);
+        match (left, right) {
+            (DataType::Integer32(a), DataType::Integer32(b)) => a.checked_add(sign * b)
+                .map(DataType::Integer32)
+                .ok_or_else(|| "result is too large for an int".to_string()),
+            (DataType::Integer32(_) | DataType::Float32(_), DataType::Integer32(_) | DataType::Float32(_)) => {
+                Ok(DataType::Float32(float(left) + sign as f32 * float(right)))
+            }
+            (DataType::String(a), DataType::Integer32(seconds)) if timestamp(a).is_some() => {
+                let at = timestamp(a).unwrap() + i64::from(sign * seconds);
+                u64::try_from(at)
+                    .map(|at| DataType::String(time::format_timestamp(at)))
+                    .map_err(|_| "timestamps before 1970 are not supported".to_string())
+            }
+            (DataType::String(a), DataType::String(b)) if *self == BinaryOp::Sub => match (timestamp(a), timestamp(b)) {
+                (Some(a), Some(b)) => i32::try_from(a - b)
+                    .map(DataType::Integer32)
+                    .map_err(|_| "result is too large for an int".to_string()),
+                _ => Err(format!("cannot take '{}' from '{}'", b, a)),
+            },
+            _ => Err(format!("cannot compute '{}' {} '{}'", left, self.symbol(), right)),
+        }
+    }
+}
+
+impl BinaryOp {
+    fn multiply(&self, left: &DataType, right: &DataType) -> Result<DataType, String> {
+        let overflow = || "result is too large for an int".to_string();
+        match (self, left, right) {
+            (BinaryOp::Div, _, DataType::Integer32(0)) => Err("division by zero".to_string()),
+            (BinaryOp::Mul, DataType::Integer32(a), DataType::Integer32(b)) => a.checked_mul(*b).map(DataType::Integer32).ok_or_else(overflow),
+            (BinaryOp::Div, DataType::Integer32(a), DataType::Integer32(b)) => a.checked_div(*b).map(DataType::Integer32).ok_or_else(overflow),
+            (_, DataType::Integer32(_) | DataType::Float32(_), DataType::Integer32(_) | DataType::Float32(_)) => {
+                Ok(DataType::Float32(if *self == BinaryOp::Mul { float(left) * float(right) } else { float(left) / float(right) }))
+            }
+            _ => Err(format!("cannot compute '{}' {} '{}'", left, self.symbol(), right)),
+        }
+    }
+}
+
+fn float(value: &DataType) -> f32 {
+    match value {
+        DataType::Integer32(i) => *i as f32,
+        DataType::Float32(f) => *f,
+        DataType::String(_) | DataType::Array(_) => f32::NAN,
+    }
+}
+
+impl Expr {
+    /// The column name, if the expression is nothing more than a column.
+    pub fn column(&self) -> Option<&str> {
+        match self {
+            Expr::Column(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// The same expression with every column name replaced by what `rename`
+    /// gives for it.
+    pub fn rename_columns(&self, rename: &mut impl FnMut(&str) -> Result<String, DbError>) -> Result<Expr, DbError> {
+        Ok(match self {
+            Expr::Column(name) => Expr::Column(rename(name)?),
+            Expr::Literal(value) => Expr::Literal(value.clone()),
+            Expr::Call { function, args } => Expr::Call {
+                function: function.clone(),
+                args: args.iter().map(|arg| arg.rename_columns(rename)).collect::<Result<_, _>>()?,
+            },
+            Expr::Binary { op, left, right } => Expr::Binary {
+                op: *op,
+                left: Box::new(left.rename_columns(rename)?),
+                right: Box::new(right.rename_columns(rename)?),
+            },
+            Expr::Cast { expr, to } => Expr::Cast { expr: Box::new(expr.rename_columns(rename)?), to: to.clone() },
+            Expr::Window(window) => Expr::Window(Box::new(window.rename_columns(rename)?)),
+            Expr::Aggregate(aggregate) => Expr::Aggregate(Box::new(aggregate.rename_columns(rename)?)),
+        })
+    }
+
+    /// Fails if the expression names a column the table does not have or a
+    /// function that is not registered, or uses a window function or an
+    /// aggregate the table has no values for (see `window::with_windows` and
+    /// `aggregate::grouped`).
+    pub fn check(&self, table: &Table, functions: &Functions) -> Result<(), DbError> {
+        match self {
+            Expr::Column(name) if !table.fields.contains_key(name) => {
+                Err(DbError::ColumnNotFound { table: table.name.clone(), column: name.clone() })
+            }
+            Expr::Window(_) if !table.fields.contains_key(&self.to_string()) => Err(DbError::InvalidExpression(
+                format!("{}: window functions can only be used in the columns and ORDER BY of a SELECT", self),
+            )),
+            Expr::Aggregate(_) if !table.fields.contains_key(&self.to_string()) => Err(DbError::InvalidExpression(
+                format!("{}: aggregate functions can only be used in the columns and ORDER BY of a SELECT", self),
+            )),
+            Expr::Column(_) | Expr::Literal(_) | Expr::Window(_) | Expr::Aggregate(_) => Ok(()),
+            Expr::Call { function, args } => {
+                if functions.get(function).is_none() {
+                    return Err(DbError::FunctionNotFound(function.clone()));
+                }
+                args.iter().try_for_each(|arg| arg.check(table, functions))
+            }
+            Expr::Binary { left, right, .. } => {
+                left.check(table, functions)?;
+                right.check(table, functions)
+            }
+            Expr::Cast { expr, .. } => expr.check(table, functions),
+        }
+    }
+
+    /// The expression's value in row `row` of `table`.
+    pub fn eval(&self, table: &Table, row: usize, functions: &Functions) -> Result<DataType, DbError> {
+        self.eval_with(&|name| table.data.contains_key(name)
+            .then(|| table.value(name, row))
+            .ok_or_else(|| DbError::ColumnNotFound { table: table.name.clone(), column: name.to_string() }),
+            functions,
+        )
+    }
+
+    /// The expression's value, with each column's value given by `column`.
+    pub fn eval_with(&self, column: &dyn Fn(&str) -> Result<DataType, DbError>, functions: &Functions) -> Result<DataType, DbError> {
+        match self {
+            Expr::Column(name) => column(name),
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Call { function, args } => {
+                let f = functions.get(function).ok_or_else(|| DbError::FunctionNotFound(function.clone()))?;
+                let args = args.iter()
+                    .map(|arg| arg.eval_with(column, functions))
+                    .collect::<Result<Vec<_>, _>>()?;
+                f(&args).map_err(|reason| DbError::FunctionFailed { function: function.clone(), reason })
+            }
+            Expr::Binary { op, left, right } => {
+                let (a, b) = (left.eval_with(column, functions)?, right.eval_with(column, functions)?);
+                op.apply(&a, &b).map_err(|reason| DbError::InvalidExpression(format!("{}: {}", self, reason)))
+            }
+            Expr::Cast { expr, to } => cast(&expr.eval_with(column, functions)?, to)
+                .map_err(|reason| DbError::InvalidExpression(format!("{}: {}", self, reason))),
+            // Computed beforehand for the whole query, as a column named after it
+            Expr::Window(_) | Expr::Aggregate(_) => column(&self.to_string()),
+        }
+    }
+
+    /// Every column the expression reads.
+    pub fn columns(&self) -> Vec<&str> {
+        match self {
+            Expr::Column(name) => vec![name],
+            Expr::Literal(_) => Vec::new(),
+            Expr::Call { args, .. } => args.iter().flat_map(Expr::columns).collect(),
+            Expr::Binary { left, right, .. } => [left.columns(), right.columns()].concat(),
+            Expr::Cast { expr, .. } => expr.columns(),
+            Expr::Window(window) => window.exprs().flat_map(Expr::columns).collect(),
+            Expr::Aggregate(aggregate) => aggregate.arg.iter().flat_map(Expr::columns).collect(),
+        }
+    }
+
+    /// Every window function the expression uses.
+    pub fn windows(&self) -> Vec<&Window> {
+        match self {
+            Expr::Column(_) | Expr::Literal(_) | Expr::Aggregate(_) => Vec::new(),
+            Expr::Call { args, .. } => args.iter().flat_map(Expr::windows).collect(),
+            Expr::Binary { left, right, .. } => [left.windows(), right.windows()].concat(),
+            Expr::Cast { expr, .. } => expr.windows(),
+            Expr::Window(window) => vec![window],
+        }
+    }
+
+    /// Every aggregate the expression uses, those a window reads included.
+    pub fn aggregates(&self) -> Vec<&Aggregate> {
+        match self {
+            Expr::Column(_) | Expr::Literal(_) => Vec::new(),
+            Expr::Call { args, .. } => args.iter().flat_map(Expr::aggregates).collect(),
+            Expr::Binary { left, right, .. } => [left.aggregates(), right.aggregates()].concat(),
+            Expr::Cast { expr, .. } => expr.aggregates(),
+            Expr::Window(window) => window.exprs().flat_map(Expr::aggregates).collect(),
+            Expr::Aggregate(aggregate) => vec![aggregate],
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Column(name) => write!(f, "{}", name),
+            Expr::Literal(DataType::String(s)) => write!(f, "'{}'", s.replace('\'', "''")),
+            Expr::Literal(value) => write!(f, "{}", value),
+            Expr::Call { function, args } => {
+                let args: Vec<String> = args.iter().map(Expr::to_string).collect();
+                write!(f, "{}({})", function, args.join(", "))
+            }
+            // Left to right, `*` and `/` first: parentheses go around a looser
+            // operation on the left, and one no tighter on the right
+            Expr::Binary { op, left, right } => {
+                let grouped = |side: &Expr, loose: bool| match side {
+                    Expr::Binary { .. } if loose => format!("({})", side),
+                    _ => side.to_string(),
+                };
+                let precedence = |side: &Expr| match side {
+                    Expr::Binary { op, .. } => op.precedence(),
+                    _ => u8::MAX,
+                };
+                write!(
+                    f, "{} {} {}",
+                    grouped(left, precedence(left) < op.precedence()),
+                    op.symbol(),
+                    grouped(right, precedence(right) <= op.precedence()),
+                )
+            }
+            Expr::Cast { expr, to } => write!(f, "CAST({} AS {})", expr, to),
+            Expr::Window(window) => write!(f, "{}", window),
+            Expr::Aggregate(aggregate) => write!(f, "{}", aggregate),
+        }
+    }
+}
+
+impl From<Expr> for String {
+    fn from(expr: Expr) -> String {
+        expr.to_string()
+    }
+}
+
+impl TryFrom<String> for Expr {
+    type Error = DbError;
+
+    fn try_from(text: String) -> Result<Expr, DbError> {
+        parser::parse_expr(&text)
+    }
+}
@@ -0,0 +1,102 @@
+//! External tables: a table whose rows are a CSV file somewhere on disk,
+//! queried where it lies instead of being imported. Only the definition is
+//! stored with the database, the columns and where the file is. The file
+//! is read when the table is first queried and again whenever it has
+//! changed since, so it can be rewritten by whatever produces it. Nothing
+//! writes to it through the database.
+
+use std::fs;
+use std::time::SystemTime;
+
+use serde::{Serialize, Deserialize};
+
+use crate::catalog;
+use crate::csv::{self, CsvOptions};
+use crate::database::Database;
+use crate::error::DbError;
+use crate::Table;
+
+/// Where an external table's rows come from, saved with the table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct External {
+    pub location: String, // As given, relative to where the server runs
+    pub header: bool,
+    pub delimiter: char,
+    // The modification time and size of the file when it was read
+    #[serde(skip)]
+    read: Option<(SystemTime, u64)>,
+}
+
+impl External {
+    pub fn new(location: &str, options: &CsvOptions) -> External {
+        External { location: location.to_string(), header: options.header, delimiter: options.delimiter, read: None }
+    }
+
+    pub fn options(&self) -> CsvOptions {
+        CsvOptions { header: self.header, delimiter: self.delimiter }
+    }
+
+    /// Whether the file has changed, or gone, since it was read.
+    pub fn is_stale(&self) -> bool {
+        self.read.is_none() || stamp(&self.location) != self.read
+    }
+}
+
+fn stamp(location: &str) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(location).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Fills the definition of an external table with the rows of its file,
+/// and builds its indexes over them.
+pub(crate) fn read(table: &mut Table) -> Result<(), DbError> {
+    let external = table.external.as_ref().expect("an external table");
+    let failed = |reason: String| DbError::ExternalFile { table: table.name.clone(), location: external.location.clone(), reason };
+    // Taken first, so a file written while it is read is read again next time
+    let read = stamp(&external.location);
+    let text = fs::read_to_string(&external.location).map_err(|e| failed(e.to_string()))?;
+    let rows = csv::rows(table, &text, &external.options()).map_err(|e| match e {
+        DbError::ImportFailed { line, reason } => failed(format!("line {}: {}", line, reason)),
+        e => failed(e.to_string()),
+    })?;
+
+    let mut filled = table.schema_only();
+    for (_, row) in rows {
+        for (column, value) in filled.columns.iter().zip(row) {
+            filled.data.get_mut(column).unwrap().push(value);
+        }
+    }
+    filled.rebuild_indexes();
+    filled.external.as_mut().unwrap().read = read;
+    *table = filled;
+    Ok(())
+}
+
+impl Database {
+    /// Saves the definition of an external table, `table` with no rows,
+    /// after reading the file once to check it fits the columns. Returns the
+    /// number of rows in it.
+    pub fn create_external_table(&mut self, mut table: Table, external: External) -> Result<usize, DbError> {
+        if catalog::is_system_table(&table.name) {
+            return Err(DbError::SystemTable(table.name));
+        }
+        if self.table_exists(&table.name) || self.view(&table.name)?.is_some() {
+            return Err(DbError::ViewExists(table.name));
+        }
+        table.external = Some(external);
+        read(&mut table)?;
+        self.save_table(&table)?;
+        Ok(table.row_count())
+    }
+
+    /// Where the rows of table `name` come from, if it is an external table.
+    pub fn external(&self, name: &str) -> Result<Option<External>, DbError> {
+        if let Some(table) = self.cached(name) {
+            return Ok(table.external.clone());
+        }
+        if !self.table_exists(name) {
+            return Ok(None);
+        }
+        Ok(self.read_table_file(name)?.external)
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::csv::{self, CsvOptions};
+use crate::database::Database;
+use crate::error::DbError;
+use crate::jsonl::{self, JsonlOptions};
+use crate::parquet;
+use crate::progress;
+use crate::query::Rows;
+use crate::index::{IndexDef, Key};
+use crate::timestamps;
+use crate::DataType;
+
+/// A file format IMPORT reads and EXPORT writes, with its options.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Format {
+    Csv(CsvOptions),
+    Jsonl(JsonlOptions),
+    Parquet, // Export only
+}
+
+/// Writes a query result to a file. Returns the number of rows written.
+pub fn export(path: &Path, result: &Rows, format: &Format) -> Result<usize, DbError> {
+    let text = match format {
+        Format::Csv(options) => csv::write(result, options),
+        Format::Jsonl(_) => jsonl::write(result),
+        Format::Parquet => {
+            parquet::write(path, result)?;
+            return Ok(result.rows.len());
+        }
+    };
+    fs::write(path, text)?;
+    Ok(result.rows.len())
+}
+
+impl Database {
+    /// Appends the rows of a file to a table, converting each value to its
+    /// column's type. Either every row is imported or, if any of them is
+    /// invalid, none; the table file is written once, at the end. Triggers do
+    /// not fire. Returns the number of rows imported.
+    pub fn import(&mut self, table_name: &str, path: &Path, format: &Format) -> Result<usize, DbError> {
+        self.check_writable(table_name)?;
+        let text = fs::read_to_string(path)?;
+        self.load_rows(table_name, &text, format)
+    }
+
+    /// Appends the rows in `text` to a table, as `import` does for a file.
+    /// Built for large loads: the rows are appended column by column, unique
+    /// keys are checked against the table and a set of the batch's own, and
+    /// the indexes are rebuilt once at the end.
+    pub fn load_rows(&mut self, table_name: &str, text: &str, format: &Format) -> Result<usize, DbError> {
+        self.check_writable(table_name)?;
+        let functions = self.functions();
+        let mut table = self.load_table(table_name)?.clone();
+        let _running = progress::start(|| format!("Importing into '{}'", table_name), Some(text.lines().count()), "lines");
+        let mut rows = match format {
+            Format::Csv(options) => csv::rows(&table, text, options)?,
+            Format::Jsonl(options) => jsonl::rows(&table, text, options)?,
+            Format::Parquet => return Err(DbError::Syntax("PARQUET files can only be exported".to_string())),
+        };
+        self.check_quota(table_name, rows.len(), text.len() as u64)?;
+        // Drawn at once, before the rows are checked, as for an INSERT
+        for (column, sequence) in &table.sequences {
+            let position = table.columns.iter().position(|c| c == column).expect("a sequence's column is a column");
+            let values = self.nextvals(sequence, rows.len())?;
+            for ((_, row), value) in rows.iter_mut().zip(values) {
+                row[position] = DataType::Integer32(value);
+            }
+        }
+        if !table.generated.is_empty() {
+            for (line, row) in &mut rows {
+                table.generate(row, &functions).map_err(|e| DbError::ImportFailed { line: *line, reason: e.to_string() })?;
+            }
+        }
+        for (_, row) in &mut rows {
+            timestamps::stamp(&table, row, true, self.now());
+        }
+
+        let unique: Vec<&IndexDef> = table.index_defs.iter().filter(|def| def.unique).collect();
+        let mut seen: Vec<HashSet<Key>> = vec![HashSet::new(); unique.len()];
+        for (line, row) in &rows {
+            let failed = |e: DbError| DbError::ImportFailed { line: *line, reason: e.to_string() };
+            table.check_unique(row).map_err(failed)?;
+            for (def, seen) in unique.iter().zip(&mut seen) {
+                if !seen.insert(table.row_key(&def.columns, row)) {
+                    return Err(failed(table.duplicate(def, row)));
+                }
+            }
+        }
+
+        let loaded = rows.len();
+        let now = self.now();
+        let before = table.row_count();
+        if let Some(history) = &mut table.history {
+            history.appended(before, now);
+        }
+        for column in &table.columns {
+            table.data.get_mut(column).unwrap().reserve(loaded);
+        }
+        for (_, row) in rows {
+            for (column, value) in table.columns.iter().zip(row) {
+                table.data.get_mut(column).unwrap().push(value);
+            }
+        }
+        table.rebuild_indexes();
+        table.modified = now;
+        self.save_table(&table)?;
+        Ok(loaded)
+    }
+}
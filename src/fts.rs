@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+
+use crate::index::{Index, IndexDef, IndexKind};
+use crate::{DataType, Table};
+
+/// Splits text into lowercase words; anything that is not a letter or digit
+/// separates them.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Whether `value` contains every word of `query`.
+pub fn matches(value: &DataType, query: &str) -> bool {
+    let words = tokenize(&value.to_string());
+    tokenize(query).iter().all(|term| words.contains(term))
+}
+
+pub fn fulltext_index<'a>(table: &'a Table, column: &str) -> Option<(&'a IndexDef, &'a Index)> {
+    table.index_defs.iter()
+        .zip(&table.indexes)
+        .find(|(def, _)| def.kind == IndexKind::FullText && def.columns[0] == column)
+}
+
+/// Rows containing every word of `query` according to a full-text index, in
+/// ascending order.
+pub fn search(index: &Index, query: &str, row_count: usize) -> Vec<usize> {
+    let mut terms = tokenize(query).into_iter();
+    let Some(first) = terms.next() else {
+        return (0..row_count).collect();
+    };
+
+    let mut rows = index.postings(&first);
+    for term in terms {
+        let postings = index.postings(&term);
+        rows.retain(|row| postings.binary_search(row).is_ok());
+    }
+    rows
+}
+
+/// Orders `rows` by TF-IDF relevance to `query`, best first. Ties keep their
+/// original order.
+pub fn rank(table: &Table, column: &str, query: &str, rows: &mut [usize]) {
+    let terms = tokenize(query);
+    let total = table.row_count() as f64;
+    let index = fulltext_index(table, column).map(|(_, index)| index);
+
+    // Rarer words say more about a row than common ones
+    let idf: HashMap<&String, f64> = terms.iter()
+        .map(|term| {
+            let df = match index {
+                Some(index) => index.postings(term).len(),
+                None => table.data[column].iter()
+                    .filter(|value| tokenize(&value.to_string()).contains(term))
+                    .count(),
+            };
+            (term, (1.0 + total / df.max(1) as f64).ln())
+        })
+        .collect();
+
+    let score = |row: usize| -> f64 {
+        let words = tokenize(&table.data[column][row].to_string());
+        terms.iter()
+            .map(|term| words.iter().filter(|w| *w == term).count() as f64 / words.len().max(1) as f64 * idf[term])
+            .sum()
+    };
+    let mut scored: Vec<(usize, f64)> = rows.iter().map(|&row| (row, score(row))).collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    for (slot, (row, _)) in rows.iter_mut().zip(scored) {
+        *slot = row;
+    }
+}
@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::builtins;
+use crate::database::Database;
+use crate::random::{Rng, SystemRng};
+use crate::time::{Clock, SystemClock};
+use crate::DataType;
+
+/// A scalar function callable from SQL. It gets the values of its arguments
+/// and returns the result, or why there is none.
+pub type Function = Arc<dyn Fn(&[DataType]) -> Result<DataType, String> + Send + Sync>;
+
+/// The functions a database can call, by lowercase name. Cheap to clone, so
+/// a query can hold on to them while it borrows a table.
+#[derive(Clone)]
+pub struct Functions {
+    functions: Arc<HashMap<String, Function>>,
+}
+
+// Starts with the built-in functions
+impl Default for Functions {
+    fn default() -> Functions {
+        let (clock, rng): (Arc<dyn Clock>, Arc<dyn Rng>) = (Arc::new(SystemClock), Arc::new(SystemRng));
+        let functions = builtins::all(&clock, &rng).into_iter().map(|(name, f)| (name.to_string(), f)).collect();
+        Functions { functions: Arc::new(functions) }
+    }
+}
+
+impl Functions {
+    pub fn get(&self, name: &str) -> Option<&Function> {
+        self.functions.get(&name.to_ascii_lowercase())
+    }
+
+    // Replaces function `name`, given in lowercase, or adds it
+    fn insert(&mut self, name: &str, f: Function) {
+        Arc::make_mut(&mut self.functions).insert(name.to_string(), f);
+    }
+}
+
+impl Database {
+    /// Makes `f` callable as `name(...)` in SELECT lists and WHERE clauses,
+    /// replacing any function of the same name. Names are case-insensitive.
+    /// Functions live only as long as this `Database`; register them again
+    /// after opening it.
+    pub fn register_function<F>(&mut self, name: &str, f: F)
+    where
+        F: Fn(&[DataType]) -> Result<DataType, String> + Send + Sync + 'static,
+    {
+        self.functions.insert(&name.to_ascii_lowercase(), Arc::new(f));
+    }
+
+    /// Reads the time from `clock` from now on, for `NOW()` (replacing any
+    /// function registered under that name), expiring rows, audit
+    /// timestamps, history and the log, so tests can set the time queries see.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.functions.insert("now", builtins::now(Arc::clone(&clock)));
+        self.wal.set_clock(Arc::clone(&clock));
+        self.clock_source = clock;
+    }
+
+    /// Draws the numbers of `RANDOM()` and `UUID()` from `rng` from now on,
+    /// replacing any functions registered under those names.
+    pub fn set_rng(&mut self, rng: Arc<dyn Rng>) {
+        self.functions.insert("random", builtins::random(Arc::clone(&rng)));
+        self.functions.insert("uuid", builtins::uuid(rng));
+    }
+
+    /// Seconds since the Unix epoch, by the database's clock.
+    pub fn now(&self) -> u64 {
+        self.clock_source.now()
+    }
+
+    pub fn functions(&self) -> Functions {
+        self.functions.clone()
+    }
+}
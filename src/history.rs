@@ -0,0 +1,163 @@
+//! Table history, for `SELECT ... FROM <table> AS OF '<time>'`. A table set
+//! to keep history saves, with every change made to it, what undoes that
+//! change: the rows a delete removed, the values an update replaced, the
+//! number of rows before an insert. Reading the table as of a time starts
+//! from its rows now and undoes its changes since, newest first. Changes
+//! older than the table's retention are dropped as new ones come in, so how
+//! far back it can be read stays bounded.
+
+use serde::{Serialize, Deserialize};
+
+use crate::catalog;
+use crate::database::Database;
+use crate::error::DbError;
+use crate::wal::WalOp;
+use crate::{DataType, Table};
+
+/// A table's saved changes, kept in its file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct History {
+    pub retention: u64, // Seconds a change is kept for
+    pub since: u64,     // The earliest time the table can be read as of
+    changes: Vec<Change>, // Oldest first
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Change {
+    at: u64,
+    undo: Undo,
+}
+
+/// What puts a table back as it was before one change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum Undo {
+    Truncate(usize),                                      // Rows the table had before an insert
+    Restore(Vec<(usize, Vec<DataType>)>),                 // Deleted rows and their positions, ascending
+    Revert { row: usize, values: Vec<(String, DataType)> }, // Values an update replaced
+    Mark { rows: Vec<usize>, deleted: bool },             // Rows soft-deleted or brought back, and what they were
+}
+
+impl History {
+    /// An empty history, from `now` on.
+    pub fn new(retention: u64, now: u64) -> History {
+        History { retention, since: now, changes: Vec::new() }
+    }
+
+    /// Saves what undoes `op`, made to `table` at `at`, before it is made.
+    pub(crate) fn record(&mut self, table: &Table, op: &WalOp, at: u64) {
+        let undo = match op {
+            WalOp::Insert { .. } => Undo::Truncate(table.row_count()),
+            WalOp::Delete { index, .. } => Undo::Restore(vec![(*index, row(table, *index))]),
+            WalOp::DeleteRows { rows, .. } => Undo::Restore(rows.iter().map(|&i| (i, row(table, i))).collect()),
+            WalOp::Update { row, values, .. } => Undo::Revert {
+                row: *row,
+                values: values.iter()
+                    .filter(|(column, _)| table.data.contains_key(column))
+                    .map(|(column, _)| (column.clone(), table.data[column][*row].clone()))
+                    .collect(),
+            },
+            WalOp::Tombstone { rows, deleted, .. } => Undo::Mark { rows: rows.clone(), deleted: !deleted },
+            // A transaction's changes are each recorded as they are made
+            WalOp::Transaction { .. } | WalOp::Checkpoint => return,
+        };
+        self.push(at, undo);
+    }
+
+    /// Saves that rows were appended to a table of `before` rows at `at`,
+    /// as an import does without logging them one by one.
+    pub(crate) fn appended(&mut self, before: usize, at: u64) {
+        self.push(at, Undo::Truncate(before));
+    }
+
+    fn push(&mut self, at: u64, undo: Undo) {
+        self.changes.push(Change { at, undo });
+        let expired = self.changes.partition_point(|change| change.at < at.saturating_sub(self.retention));
+        if expired > 0 {
+            self.since = self.changes[expired - 1].at;
+            self.changes.drain(..expired);
+        }
+    }
+
+    /// `table`, which keeps this history, as it was at `at`.
+    fn undo_since(&self, table: &Table, at: u64) -> Table {
+        let mut past = table.schema_only();
+        past.history = None;
+        past.data = table.data.clone();
+        past.tombstones = table.tombstones.clone();
+        for change in self.changes.iter().rev().take_while(|change| change.at > at) {
+            match &change.undo {
+                Undo::Truncate(rows) => {
+                    past.data.values_mut().for_each(|data| data.truncate(*rows));
+                    past.tombstones.iter_mut().for_each(|tombstones| tombstones.truncate(*rows));
+                }
+                Undo::Restore(rows) => {
+                    for (position, row) in rows {
+                        if let Some(tombstones) = past.tombstones.as_mut().filter(|tombstones| *position <= tombstones.len()) {
+                            tombstones.insert(*position, false);
+                        }
+                        for (column, value) in past.columns.iter().zip(row) {
+                            past.data.get_mut(column).unwrap().insert(*position, value.clone());
+                        }
+                    }
+                }
+                Undo::Revert { row, values } => {
+                    for (column, value) in values {
+                        past.data.get_mut(column).unwrap()[*row] = value.clone();
+                    }
+                }
+                Undo::Mark { rows, deleted } => past.mark_deleted(rows, *deleted),
+            }
+        }
+        past.rebuild_indexes();
+        past
+    }
+}
+
+fn row(table: &Table, row: usize) -> Vec<DataType> {
+    table.columns.iter().map(|column| table.data[column][row].clone()).collect()
+}
+
+/// `seconds` in the largest unit that counts it whole, as
+/// `SET HISTORY RETENTION` takes it: `30 DAYS`, `90 MINUTES`.
+pub fn retention_text(seconds: u64) -> String {
+    let units = [(7 * 86400, "WEEK"), (86400, "DAY"), (3600, "HOUR"), (60, "MINUTE"), (1, "SECOND")];
+    let (size, unit) = units.into_iter().find(|(size, _)| seconds > 0 && seconds.is_multiple_of(*size)).unwrap_or((1, "SECOND"));
+    let n = seconds / size;
+    format!("{} {}{}", n, unit, if n == 1 { "" } else { "S" })
+}
+
+impl Database {
+    /// Starts keeping `table`'s changes for `retention` seconds, or changes
+    /// how long they are kept; None stops keeping them and drops those kept.
+    pub fn set_history_retention(&mut self, table_name: &str, retention: Option<u64>) -> Result<(), DbError> {
+        if catalog::is_system_table(table_name) {
+            return Err(DbError::SystemTable(table_name.to_string()));
+        }
+        let mut table = self.load_table(table_name)?.clone();
+        if table.external.is_some() {
+            return Err(DbError::ExternalTable(table.name));
+        }
+        if table.partitioning.is_some() {
+            return Err(DbError::InvalidPartition("history is only kept for tables that are not partitioned".to_string()));
+        }
+        table.history = match (table.history, retention) {
+            (_, None) => None,
+            (Some(mut history), Some(retention)) => {
+                history.retention = retention;
+                Some(history)
+            }
+            (None, Some(retention)) => Some(History::new(retention, self.now())),
+        };
+        self.save_table(&table)
+    }
+
+    /// Table `name` as it was at `at`, from the history it keeps.
+    pub fn as_of(&mut self, name: &str, at: u64) -> Result<Table, DbError> {
+        let table = self.load_table(name)?;
+        let history = table.history.as_ref().ok_or_else(|| DbError::NoHistory(name.to_string()))?;
+        if at < history.since {
+            return Err(DbError::HistoryUnavailable { table: name.to_string(), since: history.since });
+        }
+        Ok(history.undo_since(table, at))
+    }
+}
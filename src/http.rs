@@ -0,0 +1,301 @@
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server, SslConfig};
+
+use rust_db::parser::{self, Statement};
+use rust_db::users::{Privilege, Requirement, User};
+use rust_db::DbError;
+
+use crate::commands::Output;
+use crate::server::{self, Auth, Security, Shared};
+use crate::sessions;
+use crate::tls::Tls;
+
+// Names the protocol in SHOW PROCESSLIST
+const PROTOCOL: &str = "http";
+
+// Larger query bodies are refused rather than read
+const MAX_BODY: u64 = 16 * 1024 * 1024;
+
+// Threads answering requests; any more wait for one of them
+const WORKERS: usize = 8;
+
+// The content types a page on another site can POST without the browser
+// asking the server first, so a query sent as one may be forged
+const SIMPLE_TYPES: [&str; 3] = ["application/x-www-form-urlencoded", "multipart/form-data", "text/plain"];
+
+/// Listens on `addr`, for HTTPS if given a certificate.
+pub fn bind(addr: &str, tls: Option<&Tls>) -> io::Result<Server> {
+    match tls {
+        Some(tls) => {
+            let config = SslConfig { certificate: tls.certificate.clone(), private_key: tls.private_key.clone() };
+            Server::https(addr, config).map_err(io::Error::other)
+        }
+        None => Server::http(addr).map_err(io::Error::other),
+    }
+}
+
+/// Answers `POST /query` and `GET /tables` on `WORKERS` threads, accepting
+/// the logins `security` allows and calls from the pages on the origins it
+/// allows.
+pub fn accept(server: Server, shared: Arc<Mutex<Shared>>, security: Security) {
+    let server = Arc::new(server);
+    let origins: Arc<[String]> = security.origins.into();
+    let workers: Vec<_> = (0..WORKERS)
+        .map(|_| {
+            let (server, shared, origins) = (Arc::clone(&server), Arc::clone(&shared), Arc::clone(&origins));
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    respond(&shared, request, security.auth, &origins);
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        let _ = worker.join();
+    }
+}
+
+fn respond(shared: &Mutex<Shared>, mut request: Request, auth: Auth, origins: &[String]) {
+    let path = request.url().split('?').next().unwrap_or_default().to_string();
+    let (status, body) = match (request.method(), path.as_str(), login(shared, &request, auth)) {
+        // CORS preflight, answered in full only for the origins allowed
+        (Method::Options, "/query" | "/tables", _) => (204, Value::Null),
+        (_, _, Login::Refused) => (401, json!({ "error": "Authentication required" })),
+        (Method::Post, "/query", _) if forgeable(&request) => (415, json!({
+            "error": "Send the statements with a Content-Type such as application/sql, or with an access token",
+        })),
+        (Method::Post, "/query", login) => query(shared, &mut request, &login),
+        (Method::Get, "/tables", login) => (200, tables(shared, login.user())),
+        (_, "/query" | "/tables", _) => (405, json!({ "error": "Method not allowed" })),
+        _ => (404, json!({ "error": "Not found. Use POST /query or GET /tables" })),
+    };
+
+    let body = if body.is_null() { String::new() } else { body.to_string() };
+    let mut response = Response::from_string(body)
+        .with_status_code(status)
+        .with_header(header("Content-Type", "application/json"));
+    if let Some(origin) = header_value(&request, "Origin").filter(|origin| origins.iter().any(|o| o == "*" || o == origin)) {
+        response.add_header(header("Access-Control-Allow-Origin", origin));
+        response.add_header(header("Vary", "Origin"));
+        response.add_header(header("Access-Control-Allow-Methods", "GET, POST, OPTIONS"));
+        response.add_header(header("Access-Control-Allow-Headers", "Content-Type, Authorization"));
+    }
+    if status == 401 {
+        if auth.allows_password() {
+            response.add_header(header("WWW-Authenticate", "Basic realm=\"RustDB\""));
+        }
+        if auth.allows_token() {
+            response.add_header(header("WWW-Authenticate", "Bearer realm=\"RustDB\""));
+        }
+    }
+    if let Err(e) = request.respond(response) {
+        println!("Error: Could not send HTTP response: {}", e);
+    }
+}
+
+enum Login {
+    Open, // The database has no users, so there is nothing to check
+    User(User),
+    Refused,
+}
+
+impl Login {
+    fn user(&self) -> Option<&str> {
+        match self {
+            Login::User(user) => Some(&user.name),
+            Login::Open | Login::Refused => None,
+        }
+    }
+}
+
+/// Checks HTTP Basic credentials or a Bearer access token, as `auth`
+/// allows, once the database has users at all.
+fn login(shared: &Mutex<Shared>, request: &Request, auth: Auth) -> Login {
+    if !server::auth_required(shared) {
+        return Login::Open;
+    }
+    let authorization = header_value(request, "Authorization");
+    if let Some(token) = authorization.and_then(|value| value.strip_prefix("Bearer ")).filter(|_| auth.allows_token()) {
+        return server::authenticate_token(shared, token.trim()).map_or(Login::Refused, Login::User);
+    }
+    let credentials = authorization
+        .and_then(|value| value.strip_prefix("Basic "))
+        .filter(|_| auth.allows_password())
+        .and_then(|encoded| STANDARD.decode(encoded.trim()).ok())
+        .and_then(|decoded| String::from_utf8(decoded).ok());
+    match credentials.as_deref().and_then(|c| c.split_once(':')) {
+        Some((user, password)) => server::authenticate(shared, user, password).map_or(Login::Refused, Login::User),
+        None => Login::Refused,
+    }
+}
+
+/// Whether a query could have come from a form or a plain `fetch` on a page
+/// of another site, which the browser sends without asking: one with a
+/// simple content type, or none, and no access token, which pages cannot
+/// attach without asking. Browsers add saved Basic credentials on their own.
+fn forgeable(request: &Request) -> bool {
+    if header_value(request, "Authorization").is_some_and(|value| value.starts_with("Bearer ")) {
+        return false;
+    }
+    match header_value(request, "Content-Type") {
+        Some(value) => {
+            let essence = value.split(';').next().unwrap_or_default().trim();
+            SIMPLE_TYPES.iter().any(|simple| essence.eq_ignore_ascii_case(simple))
+        }
+        None => true,
+    }
+}
+
+fn header_value<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request.headers().iter().find(|h| h.field.equiv(name)).map(|h| h.value.as_str())
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("header names and values are ASCII")
+}
+
+/// The outcome of one statement of a query.
+#[derive(Default, Serialize)]
+struct StatementResult {
+    statement: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    columns: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rows: Option<Vec<Vec<String>>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    messages: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    // For an error from the database: its code and, for a syntax error, the
+    // byte offset in `statement` and the token parsing stopped at
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    offset: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+}
+
+impl Output for StatementResult {
+    fn line(&mut self, text: &str) {
+        self.messages.push(text.to_string());
+    }
+
+    fn error(&mut self, message: &str) {
+        self.error.get_or_insert_with(|| message.to_string());
+    }
+
+    fn failure(&mut self, e: &DbError) {
+        if self.error.is_some() {
+            return;
+        }
+        self.error = Some(e.to_string());
+        self.code = Some(e.code());
+        if let DbError::SyntaxAt { offset, token, .. } = e {
+            self.offset = Some(*offset);
+            self.token = token.clone();
+        }
+    }
+
+    fn rows(&mut self, columns: &[&str], _types: &[&str], rows: Vec<Vec<String>>) {
+        self.columns = Some(columns.iter().map(|col| col.to_string()).collect());
+        self.rows.get_or_insert_default().extend(rows);
+    }
+}
+
+/// Runs the statements in the body in order, stopping at the first error.
+/// The request is its own session: a transaction it leaves open is rolled back.
+fn query(shared: &Mutex<Shared>, request: &mut Request, login: &Login) -> (u16, Value) {
+    let mut sql = String::new();
+    if let Err(e) = request.as_reader().take(MAX_BODY + 1).read_to_string(&mut sql) {
+        return (400, json!({ "error": format!("Could not read the request body: {}", e) }));
+    }
+    if sql.len() as u64 > MAX_BODY {
+        return (413, json!({ "error": "Query is too large" }));
+    }
+
+    let id = server::connection_id();
+    let peer = request.remote_addr().map_or("unknown".to_string(), |addr| addr.to_string());
+    sessions::open(id, PROTOCOL, &peer, None);
+    if let Login::User(account) = login {
+        sessions::login(id, &account.name, account.superuser);
+    }
+    let user = login.user();
+    let mut results = Vec::new();
+    let mut failed = false;
+    for text in parser::split_statements(&sql) {
+        // Trimmed, so a syntax error's offset counts from the start of `statement`
+        let text = text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        let mut result = StatementResult { statement: text.to_string(), ..Default::default() };
+        match parser::parse(text) {
+            Ok(Statement::Exit) => break,
+            Ok(statement) => server::execute(shared, id, user, statement, text, &mut result),
+            Err(e) => result.failure(&e),
+        }
+        failed = result.error.is_some();
+        results.push(result);
+        if failed {
+            break;
+        }
+    }
+
+    if server::in_transaction(shared, id) {
+        let mut result = StatementResult { statement: "ROLLBACK".to_string(), ..Default::default() };
+        server::execute(shared, id, user, Statement::Rollback, "ROLLBACK", &mut result);
+        results.push(result);
+    }
+    sessions::close(id);
+    (if failed { 400 } else { 200 }, json!({ "results": results }))
+}
+
+/// Every table with its columns and row count.
+// The tables `user` may SELECT from (every table if no one has to log in),
+// their grants read afresh as for a statement
+fn tables(shared: &Mutex<Shared>, user: Option<&str>) -> Value {
+    let mut shared = server::lock(shared);
+    let db = &mut shared.engine.db;
+    let user = match user.map(|name| db.user(name).and_then(|user| user.ok_or_else(|| DbError::UserNotFound(name.to_string())))) {
+        Some(Ok(user)) => Some(user),
+        Some(Err(e)) => return json!({ "error": e.to_string() }),
+        None => None,
+    };
+    let names = match db.table_names() {
+        Ok(names) => names,
+        Err(e) => return json!({ "error": e.to_string() }),
+    };
+
+    let mut tables = Vec::new();
+    for name in names {
+        if user.as_ref().is_some_and(|user| !user.allows(&Requirement::Table(&name, Privilege::Select))) {
+            continue;
+        }
+        let table = match db.snapshot(&name) {
+            Ok(table) => table,
+            Err(e) => {
+                tables.push(json!({ "name": name, "error": e.to_string() }));
+                continue;
+            }
+        };
+        let columns: Vec<Value> = table.columns.iter()
+            .map(|col| json!({ "name": col, "type": table.fields[col] }))
+            .collect();
+        tables.push(json!({
+            "name": name,
+            "columns": columns,
+            "primary_key": table.primary_key,
+            "rows": table.row_count(),
+            "temp": db.is_temp(&name),
+        }));
+    }
+    json!({ "tables": tables })
+}
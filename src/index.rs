@@ -0,0 +1,268 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+
+use serde::{Serialize, Deserialize, Deserializer};
+
+use crate::fts;
+use crate::parser::CmpOp;
+use crate::progress;
+use crate::{DataType, Table};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum IndexKind {
+    /// Ordered; answers equality and range comparisons.
+    #[default]
+    BTree,
+    /// Unordered; answers equality only, in constant time.
+    Hash,
+    /// Inverted index from words to rows; answers MATCH on one string column.
+    FullText,
+}
+
+impl IndexKind {
+    pub fn parse(name: &str) -> Option<IndexKind> {
+        match name.to_ascii_lowercase().as_str() {
+            "btree" => Some(IndexKind::BTree),
+            "hash" => Some(IndexKind::Hash),
+            "fulltext" => Some(IndexKind::FullText),
+            _ => None,
+        }
+    }
+
+    /// The name `USING` takes for this kind.
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            IndexKind::BTree => "BTREE",
+            IndexKind::Hash => "HASH",
+            IndexKind::FullText => "FULLTEXT",
+        }
+    }
+}
+
+/// Persisted description of an index; the entries are rebuilt or loaded separately.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexDef {
+    pub name: String,
+    // Single-column indexes used to be saved as `"column": "<name>"`
+    #[serde(alias = "column", deserialize_with = "one_or_many")]
+    pub columns: Vec<String>,
+    #[serde(default)]
+    pub kind: IndexKind,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub unique: bool,
+}
+
+fn one_or_many<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match OneOrMany::deserialize(d)? {
+        OneOrMany::One(column) => vec![column],
+        OneOrMany::Many(columns) => columns,
+    })
+}
+
+/// The indexed columns' values for one row, in index column order.
+pub type Key = Vec<DataType>;
+
+/// Map from key to the positions of the rows holding it. B-tree keys sort
+/// column by column, so rows sharing a leading prefix of the key are adjacent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "SavedIndex", into = "SavedIndex")]
+pub struct Index {
+    pub name: String,
+    entries: Entries,
+}
+
+#[derive(Debug, Clone)]
+enum Entries {
+    BTree(BTreeMap<Key, Vec<usize>>),
+    Hash(HashMap<Key, Vec<usize>>),
+    FullText(HashMap<String, Vec<usize>>), // A row appears once per occurrence of the word
+}
+
+impl Index {
+    pub fn build(def: &IndexDef, table: &Table) -> Index {
+        let entries = match def.kind {
+            IndexKind::BTree => Entries::BTree(BTreeMap::new()),
+            IndexKind::Hash => Entries::Hash(HashMap::new()),
+            IndexKind::FullText => Entries::FullText(HashMap::new()),
+        };
+        let mut index = Index { name: def.name.clone(), entries };
+        let _running = progress::start(|| format!("Building index '{}'", def.name), Some(table.row_count()), "rows");
+        for row in 0..table.row_count() {
+            index.insert(table.key(&def.columns, row), row);
+            progress::advance(1);
+        }
+        index
+    }
+
+    pub fn kind(&self) -> IndexKind {
+        match self.entries {
+            Entries::BTree(_) => IndexKind::BTree,
+            Entries::Hash(_) => IndexKind::Hash,
+            Entries::FullText(_) => IndexKind::FullText,
+        }
+    }
+
+    /// Adds `row` under `key`, keeping each key's rows in ascending order.
+    pub fn insert(&mut self, key: Key, row: usize) {
+        let add = |rows: &mut Vec<usize>| rows.insert(rows.partition_point(|&r| r <= row), row);
+        match &mut self.entries {
+            Entries::BTree(map) => add(map.entry(key).or_default()),
+            Entries::Hash(map) => add(map.entry(key).or_default()),
+            Entries::FullText(map) => {
+                for word in fts::tokenize(&key[0].to_string()) {
+                    add(map.entry(word).or_default());
+                }
+            }
+        }
+    }
+
+    /// Takes `row` out from under `key`, as `insert` put it there.
+    pub fn remove(&mut self, key: &[DataType], row: usize) {
+        // Whether no rows are left under the key
+        let take = |rows: &mut Vec<usize>| {
+            rows.retain(|&r| r != row);
+            rows.is_empty()
+        };
+        match &mut self.entries {
+            Entries::BTree(map) => {
+                if map.get_mut(key).is_some_and(take) {
+                    map.remove(key);
+                }
+            }
+            Entries::Hash(map) => {
+                if map.get_mut(key).is_some_and(take) {
+                    map.remove(key);
+                }
+            }
+            Entries::FullText(map) => {
+                for word in fts::tokenize(&key[0].to_string()) {
+                    if map.get_mut(&word).is_some_and(take) {
+                        map.remove(&word);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Distinct rows containing `word`, in ascending order (full-text only).
+    pub fn postings(&self, word: &str) -> Vec<usize> {
+        let Entries::FullText(map) = &self.entries else { return Vec::new() };
+        let mut rows = map.get(word).cloned().unwrap_or_default();
+        rows.dedup();
+        rows
+    }
+
+    pub fn contains(&self, key: &[DataType]) -> bool {
+        match &self.entries {
+            Entries::BTree(map) => map.contains_key(key),
+            Entries::Hash(map) => map.contains_key(key),
+            Entries::FullText(_) => false,
+        }
+    }
+
+    /// Positions of rows whose key starts with `prefix` and whose next key
+    /// column satisfies every bound in `range` (at most one from below and
+    /// one from above), in ascending order. Hash indexes only answer a
+    /// prefix covering the whole key with no range.
+    pub fn lookup(&self, prefix: &[DataType], range: &[(CmpOp, DataType)]) -> Vec<usize> {
+        let map = match &self.entries {
+            Entries::Hash(map) => return map.get(prefix).cloned().unwrap_or_default(),
+            Entries::FullText(_) => return Vec::new(),
+            Entries::BTree(map) => map,
+        };
+
+        let k = prefix.len();
+        let mut start = prefix.to_vec();
+        if let Some((_, value)) = range.iter().find(|(op, _)| matches!(op, CmpOp::Eq | CmpOp::Gt | CmpOp::GtEq)) {
+            start.push(value.clone());
+        }
+
+        let mut rows = Vec::new();
+        for (key, positions) in map.range(start..) {
+            if key[..k] != *prefix {
+                break;
+            }
+            if let Some((op, _)) = range.iter().find(|(op, value)| !op.test(key[k].cmp(value))) {
+                // `>` starts at keys equal to the value; everything else is past the end
+                if *op == CmpOp::Gt {
+                    continue;
+                }
+                break;
+            }
+            rows.extend(positions);
+        }
+        rows.sort_unstable();
+        rows
+    }
+}
+
+// JSON object keys must be strings, so entries are stored as [key, rows] pairs.
+#[derive(Serialize, Deserialize)]
+struct SavedIndex {
+    name: String,
+    #[serde(default)]
+    kind: IndexKind,
+    #[serde(default)]
+    entries: Vec<(Key, Vec<usize>)>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    words: Vec<(String, Vec<usize>)>,
+}
+
+impl From<SavedIndex> for Index {
+    fn from(saved: SavedIndex) -> Index {
+        let pairs = saved.entries.into_iter();
+        let entries = match saved.kind {
+            IndexKind::BTree => Entries::BTree(pairs.collect()),
+            IndexKind::Hash => Entries::Hash(pairs.collect()),
+            IndexKind::FullText => Entries::FullText(saved.words.into_iter().collect()),
+        };
+        Index { name: saved.name, entries }
+    }
+}
+
+impl From<Index> for SavedIndex {
+    fn from(index: Index) -> SavedIndex {
+        let kind = index.kind();
+        // Sorted, so an index saved twice over the same rows gives the same file
+        let (entries, words) = match index.entries {
+            Entries::BTree(map) => (map.into_iter().collect(), Vec::new()),
+            Entries::Hash(map) => (sorted(map), Vec::new()),
+            Entries::FullText(map) => (Vec::new(), sorted(map)),
+        };
+        SavedIndex { name: index.name, kind, entries, words }
+    }
+}
+
+fn sorted<K: Ord, V>(map: HashMap<K, V>) -> Vec<(K, V)> {
+    let mut pairs: Vec<(K, V)> = map.into_iter().collect();
+    pairs.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+    pairs
+}
+
+// Consistent with `Ord for DataType`: floats compare by `total_cmp`, which is
+// equality of bit patterns.
+impl Hash for DataType {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            DataType::String(s) => s.hash(state),
+            DataType::Integer32(i) => i.hash(state),
+            DataType::Float32(f) => f.to_bits().hash(state),
+            DataType::Array(items) => items.hash(state),
+        }
+    }
+}
+
+/// Index entries as saved next to a table. They are only trusted when they
+/// were written for exactly the table state being loaded.
+#[derive(Serialize, Deserialize)]
+pub struct IndexFile {
+    pub lsn: u64,
+    pub rows: usize,
+    pub indexes: Vec<Index>,
+}
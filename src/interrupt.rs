@@ -0,0 +1,59 @@
+//! Stopping a running statement from outside it, as Ctrl-C at the prompt
+//! does, or once it runs past a deadline. The loops over rows in queries
+//! call `check`, so a long scan or a runaway join ends at the next row
+//! rather than running to completion. Queries only read until they are
+//! done, so nothing is left half written.
+
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::error::DbError;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// The deadline in nanoseconds since `START`, 0 for none, and the time
+// allowed that it was set from, for the error
+static DEADLINE: AtomicU64 = AtomicU64::new(0);
+static ALLOWED_MS: AtomicU64 = AtomicU64::new(0);
+static START: OnceLock<Instant> = OnceLock::new();
+
+/// Asks the running statement to stop. Safe to call from a signal handler.
+pub fn interrupt() {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+/// Forgets any request to stop, before the next statement starts.
+pub fn clear() {
+    INTERRUPTED.store(false, Ordering::Relaxed);
+}
+
+/// Gives the statement about to run `allowed` to finish in, or as long as
+/// it takes if None.
+pub fn set_timeout(allowed: Option<Duration>) {
+    match allowed {
+        Some(allowed) => {
+            let deadline = elapsed() + allowed.as_nanos() as u64;
+            ALLOWED_MS.store(allowed.as_millis() as u64, Ordering::Relaxed);
+            DEADLINE.store(deadline.max(1), Ordering::Relaxed);
+        }
+        None => DEADLINE.store(0, Ordering::Relaxed),
+    }
+}
+
+/// Fails once the running statement has been asked to stop or has run out
+/// of time.
+pub(crate) fn check() -> Result<(), DbError> {
+    if INTERRUPTED.load(Ordering::Relaxed) {
+        return Err(DbError::Interrupted);
+    }
+    let deadline = DEADLINE.load(Ordering::Relaxed);
+    if deadline != 0 && elapsed() >= deadline {
+        return Err(DbError::Timeout(ALLOWED_MS.load(Ordering::Relaxed)));
+    }
+    Ok(())
+}
+
+fn elapsed() -> u64 {
+    START.get_or_init(Instant::now).elapsed().as_nanos() as u64
+}
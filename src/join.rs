@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Arc;
+
+use crate::aggregate::{self, Grouped};
+use crate::budget;
+use crate::catalog;
+use crate::database::Database;
+use crate::error::DbError;
+use crate::expr::Expr;
+use crate::parser::{self, CmpOp, Order, Predicate, SortKey, TableRef};
+use crate::planner;
+use crate::profile;
+use crate::window::with_windows;
+use crate::query::{sort, Cursor, Rows};
+use crate::index::Key;
+use crate::interrupt;
+use crate::{DataType, Table};
+
+/// One table of a FROM list.
+struct Source {
+    name: String,           // Its alias, or else its name; it qualifies the columns
+    table: Arc<Table>,      // The table itself, for a view the one underneath
+    filter: Vec<Predicate>, // The view's conditions and those on this table alone
+}
+
+/// `left = right` between columns of two tables, by position in the FROM list.
+struct JoinKey {
+    left: (usize, String),
+    right: (usize, String),
+}
+
+/// A SELECT over several tables, ready to run: each table's own conditions
+/// go to its access path, equalities between two tables become hash joins,
+/// and whatever is left is checked on the joined rows.
+struct Join {
+    sources: Vec<Source>,
+    keys: Vec<JoinKey>,
+    columns: Vec<Expr>,       // Qualified, as `<table>.<column>`
+    residual: Vec<Predicate>, // Qualified likewise
+}
+
+impl Database {
+    /// Runs a SELECT over the tables of a FROM list: every combination of
+    /// their rows matching `filter`, tables joined in order. Columns are
+    /// named `<table>.<column>` (by alias, for a table that has one), but a
+    /// column of only one of the tables may go unqualified. A single table
+    /// is the same as `select`.
+    pub fn select_from(&mut self, tables: &[TableRef], columns: &[Expr], filter: &[Predicate], order: &Order) -> Result<Rows, DbError> {
+        self.cursor(tables, columns, filter, order)?.fetch(usize::MAX)
+    }
+
+    /// `select_from`, with the rows left to be fetched.
+    pub fn cursor(&mut self, tables: &[TableRef], columns: &[Expr], filter: &[Predicate], order: &Order) -> Result<Cursor, DbError> {
+        if let [table] = tables {
+            let columns = columns.iter().map(|col| unqualify(table.name(), col)).collect::<Result<Vec<_>, _>>()?;
+            let order = Order {
+                group: order.group.iter().map(|expr| unqualify(table.name(), expr)).collect::<Result<_, _>>()?,
+                by: order.by.iter()
+                    .map(|key| Ok(SortKey { expr: unqualify(table.name(), &key.expr)?, ..key.clone() }))
+                    .collect::<Result<_, DbError>>()?,
+                ..order.clone()
+            };
+            return self.select_cursor(&table.table, &table.source, &columns, &unqualify_filter(table.name(), filter)?, &order);
+        }
+
+        let join = self.join(tables, columns, filter)?;
+        let functions = self.functions();
+        let mut combinations: Vec<Vec<usize>> = Vec::new();
+        for (i, source) in join.sources.iter().enumerate() {
+            let rows = planner::plan(&source.table, &source.filter, &functions)?.rows()?;
+            if i == 0 {
+                combinations = rows.into_iter().map(|row| vec![row]).collect();
+                continue;
+            }
+
+            let began = profile::begin();
+            let keys: Vec<&JoinKey> = join.keys.iter().filter(|key| key.right.0 == i).collect();
+            if keys.is_empty() {
+                // No equality to go by: every row with every combination so far
+                let mut joined = Vec::new();
+                for combination in &combinations {
+                    interrupt::check()?;
+                    budget::charge(budget::size_of_rows(rows.len(), i + 1))?;
+                    joined.extend(rows.iter().map(|&row| [combination.as_slice(), &[row]].concat()));
+                }
+                combinations = joined;
+                profile::record(began, || format!("Nested loop with {}", source.name), None, combinations.len(), None);
+                continue;
+            }
+
+            let right: Vec<String> = keys.iter().map(|key| key.right.1.clone()).collect();
+            let mut matching: HashMap<Key, Vec<usize>> = HashMap::new();
+            for row in rows {
+                matching.entry(source.table.key(&right, row)).or_default().push(row);
+            }
+            let mut joined = Vec::new();
+            for combination in &combinations {
+                interrupt::check()?;
+                // Compared as the column joined to compares its own values
+                let key: Key = keys.iter()
+                    .map(|key| {
+                        let value = &join.sources[key.left.0].table.data[&key.left.1][combination[key.left.0]];
+                        source.table.collation(&key.right.1).key(value).into_owned()
+                    })
+                    .collect();
+                let rows = matching.get(&key).map_or(&[][..], Vec::as_slice);
+                budget::charge(budget::size_of_rows(rows.len(), i + 1))?;
+                joined.extend(rows.iter().map(|&row| [combination.as_slice(), &[row]].concat()));
+            }
+            combinations = joined;
+            profile::record(began, || format!("Hash join with {} on {}", source.name, join.describe_keys(&keys)), None, combinations.len(), None);
+        }
+
+        let joined = Arc::new(join.joined_table(&combinations)?);
+        let order = Order {
+            group: order.group.iter().map(|expr| join.qualify(expr)).collect::<Result<_, _>>()?,
+            by: order.by.iter()
+                .map(|key| Ok(SortKey { expr: join.qualify(&key.expr)?, ..key.clone() }))
+                .collect::<Result<_, DbError>>()?,
+            ..order.clone()
+        };
+        let qualified = if join.columns.is_empty() {
+            joined.columns.iter().cloned().map(Expr::Column).collect()
+        } else {
+            join.columns
+        };
+        let rows = planner::plan(&joined, &join.residual, &functions)?.rows()?;
+        let Grouped { table: joined, mut rows, columns: qualified, order } = aggregate::grouped(joined, rows, qualified, order, &functions)?;
+        let exprs: Vec<&Expr> = qualified.iter().chain(order.by.iter().map(|key| &key.expr)).collect();
+        let joined = with_windows(&joined, &rows, &exprs, &functions)?;
+        for column in &qualified {
+            column.check(&joined, &functions)?;
+        }
+        sort(&joined, &mut rows, &order, &functions)?;
+        let cursor = Cursor::new(joined, rows, qualified, functions);
+        // Headings as written; only `*` spells out every table
+        if columns.is_empty() {
+            Ok(cursor)
+        } else {
+            Ok(cursor.headed(columns.iter().map(Expr::to_string).collect()))
+        }
+    }
+
+    /// How `select_from` would run a SELECT over several tables, one line
+    /// per step.
+    pub fn explain_join(&mut self, tables: &[TableRef], filter: &[Predicate]) -> Result<String, DbError> {
+        let join = self.join(tables, &[], filter)?;
+        let functions = self.functions();
+        let mut text = String::new();
+        for (i, source) in join.sources.iter().enumerate() {
+            let mut plan = planner::plan(&source.table, &source.filter, &functions)?.to_string().replace('\n', "\n  ");
+            // A table joined to itself is told apart by its aliases
+            if source.name != source.table.name {
+                let table = format!("on {}", source.table.name);
+                plan = plan.replacen(&table, &format!("{} AS {}", table, source.name), 1);
+            }
+            let keys: Vec<&JoinKey> = join.keys.iter().filter(|key| key.right.0 == i).collect();
+            let _ = match (i, keys.is_empty()) {
+                (0, _) => writeln!(text, "{}", plan),
+                (_, true) => writeln!(text, "Nested loop with {}\n  {}", source.name, plan),
+                (_, false) => writeln!(text, "Hash join with {} on {}\n  {}", source.name, join.describe_keys(&keys), plan),
+            };
+        }
+        if !join.residual.is_empty() {
+            let residual: Vec<String> = join.residual.iter().map(Predicate::to_string).collect();
+            let _ = writeln!(text, "Filter on joined rows: {}", residual.join(" AND "));
+        }
+        Ok(text.trim_end().to_string())
+    }
+
+    fn join(&mut self, tables: &[TableRef], columns: &[Expr], filter: &[Predicate]) -> Result<Join, DbError> {
+        let mut sources: Vec<Source> = Vec::new();
+        for table in tables {
+            let name = table.name();
+            if sources.iter().any(|source| source.name == name) {
+                return Err(DbError::Syntax(format!(
+                    "'{}' appears more than once in FROM; give each use of a table its own alias", name
+                )));
+            }
+            let (base, filter) = match table.source {
+                parser::Source::Current => self.resolve_view(&table.table, &[])?,
+                _ => (table.table.clone(), Vec::new()),
+            };
+            sources.push(Source { name: name.to_string(), table: self.snapshot_of(&base, &table.source)?, filter });
+        }
+
+        let columns = columns.iter()
+            .map(|col| col.rename_columns(&mut |name| resolve(&sources, name).map(|(i, col)| qualified(&sources[i], &col))))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut keys = Vec::new();
+        let mut residual = Vec::new();
+        for predicate in filter {
+            // The tables the condition reads, in FROM order
+            let mut used = Vec::new();
+            let mut note = |name: &str| {
+                let (i, col) = resolve(&sources, name)?;
+                if !used.contains(&i) {
+                    used.push(i);
+                }
+                Ok((i, col))
+            };
+            let left = predicate.left.rename_columns(&mut |name| note(name).map(|(_, col)| col))?;
+            let against = predicate.against.as_ref()
+                .map(|against| against.rename_columns(&mut |name| note(name).map(|(_, col)| col)))
+                .transpose()?;
+            used.sort_unstable();
+
+            match (used.as_slice(), &predicate.left, &predicate.against) {
+                // On one table alone: checked while reading it, through an index if one fits
+                ([i], ..) => sources[*i].filter.push(Predicate { left, against, ..predicate.clone() }),
+                ([_, _], Expr::Column(left), Some(Expr::Column(right))) if predicate.op == CmpOp::Eq => {
+                    let (left, right) = (resolve(&sources, left)?, resolve(&sources, right)?);
+                    let (left, right) = if left.0 < right.0 { (left, right) } else { (right, left) };
+                    keys.push(JoinKey { left, right });
+                }
+                _ => {
+                    let mut qualify = |name: &str| resolve(&sources, name).map(|(i, col)| qualified(&sources[i], &col));
+                    residual.push(Predicate {
+                        left: predicate.left.rename_columns(&mut qualify)?,
+                        against: predicate.against.as_ref().map(|against| against.rename_columns(&mut qualify)).transpose()?,
+                        ..predicate.clone()
+                    });
+                }
+            }
+        }
+        Ok(Join { sources, keys, columns, residual })
+    }
+}
+
+impl Join {
+    /// `expr` with each column named `<table>.<column>`.
+    fn qualify(&self, expr: &Expr) -> Result<Expr, DbError> {
+        expr.rename_columns(&mut |name| resolve(&self.sources, name).map(|(i, col)| qualified(&self.sources[i], &col)))
+    }
+
+    /// `keys` as conditions, as EXPLAIN gives them.
+    fn describe_keys(&self, keys: &[&JoinKey]) -> String {
+        let conditions: Vec<String> = keys.iter()
+            .map(|key| {
+                let (left, right) = (&self.sources[key.left.0].name, &self.sources[key.right.0].name);
+                format!("{}.{} = {}.{}", left, key.left.1, right, key.right.1)
+            })
+            .collect();
+        conditions.join(" AND ")
+    }
+
+    /// The joined rows as one table, its columns named `<table>.<column>`.
+    fn joined_table(&self, combinations: &[Vec<usize>]) -> Result<Table, DbError> {
+        let schema = self.sources.iter()
+            .flat_map(|source| source.table.columns.iter().map(|col| (qualified(source, col), source.table.fields[col].clone())))
+            .collect();
+        let names: Vec<&str> = self.sources.iter().map(|source| source.name.as_str()).collect();
+        let mut joined = Table::new(&names.join(", "), schema, None, 0, 0);
+
+        for (i, source) in self.sources.iter().enumerate() {
+            for col in &source.table.columns {
+                let values = &source.table.data[col];
+                let values: Vec<DataType> = combinations.iter().map(|combination| values[combination[i]].clone()).collect();
+                budget::charge(budget::size_of(&values))?;
+                joined.data.insert(qualified(source, col), values);
+            }
+        }
+        Ok(joined)
+    }
+}
+
+fn qualified(source: &Source, column: &str) -> String {
+    format!("{}.{}", source.name, column)
+}
+
+/// The table of the FROM list a column belongs to, and its plain name there.
+fn resolve(sources: &[Source], name: &str) -> Result<(usize, String), DbError> {
+    if let Some((table, column)) = name.split_once('.') {
+        let Some(i) = sources.iter().position(|source| source.name == table) else {
+            let names: Vec<String> = sources.iter().map(|source| source.name.clone()).collect();
+            return Err(DbError::TableNotFound {
+                name: format!("{} (not in the FROM list)", table),
+                suggestion: catalog::closest(table, &names),
+            });
+        };
+        if !sources[i].table.fields.contains_key(column) {
+            return Err(DbError::ColumnNotFound { table: table.to_string(), column: column.to_string() });
+        }
+        return Ok((i, column.to_string()));
+    }
+
+    let mut owners = sources.iter().enumerate().filter(|(_, source)| source.table.fields.contains_key(name));
+    match (owners.next(), owners.next()) {
+        (Some((i, _)), None) => Ok((i, name.to_string())),
+        (Some(_), Some(_)) => Err(DbError::AmbiguousColumn(name.to_string())),
+        (None, _) => {
+            let names: Vec<&str> = sources.iter().map(|source| source.name.as_str()).collect();
+            Err(DbError::ColumnNotFound { table: names.join(", "), column: name.to_string() })
+        }
+    }
+}
+
+/// `expr` with columns qualified by `table` written plainly, for a query on
+/// that table alone.
+pub fn unqualify(table: &str, expr: &Expr) -> Result<Expr, DbError> {
+    let prefix = format!("{}.", table);
+    expr.rename_columns(&mut |name| Ok(name.strip_prefix(&prefix).unwrap_or(name).to_string()))
+}
+
+/// The conditions of `filter` with columns qualified by `table` written plainly.
+pub fn unqualify_filter(table: &str, filter: &[Predicate]) -> Result<Vec<Predicate>, DbError> {
+    filter.iter()
+        .map(|predicate| Ok(Predicate {
+            left: unqualify(table, &predicate.left)?,
+            against: predicate.against.as_ref().map(|against| unqualify(table, against)).transpose()?,
+            ..predicate.clone()
+        }))
+        .collect()
+}
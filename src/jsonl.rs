@@ -0,0 +1,86 @@
+use serde_json::{Map, Value};
+
+use crate::error::DbError;
+use crate::progress;
+use crate::query::Rows;
+use crate::table::{array_literal, element_type};
+use crate::{parse_value, DataType, Table};
+
+/// How JSON Lines are matched to a table's columns.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JsonlOptions {
+    /// Skip keys that are not columns instead of failing.
+    pub ignore_unknown: bool,
+}
+
+/// A query result as JSON Lines: one object per row, keys in column order.
+pub fn write(result: &Rows) -> String {
+    let mut text = String::new();
+    for row in &result.rows {
+        let fields: Vec<String> = result.columns.iter().zip(row)
+            .map(|(column, value)| format!("{}:{}", Value::from(column.as_str()), json(value)))
+            .collect();
+        text.push('{');
+        text.push_str(&fields.join(","));
+        text.push_str("}\n");
+    }
+    text
+}
+
+pub(crate) fn json(value: &DataType) -> String {
+    match value {
+        DataType::String(s) => Value::from(s.as_str()).to_string(),
+        DataType::Integer32(i) => i.to_string(),
+        // Written as the shortest text that reads back as the same f32
+        DataType::Float32(f) if f.is_finite() => f.to_string(),
+        DataType::Float32(_) => "null".to_string(),
+        DataType::Array(items) => format!("[{}]", items.iter().map(json).collect::<Vec<_>>().join(",")),
+    }
+}
+
+/// The rows of JSON Lines text for `table`, with the line each came from.
+/// Every line is an object holding each column once; blank lines are
+/// skipped.
+pub fn rows(table: &Table, text: &str, options: &JsonlOptions) -> Result<Vec<(usize, Vec<DataType>)>, DbError> {
+    let mut rows = Vec::new();
+    for (i, text) in text.lines().enumerate() {
+        let line = i + 1;
+        progress::advance(1);
+        if text.trim().is_empty() {
+            continue;
+        }
+        let failed = |reason: String| DbError::ImportFailed { line, reason };
+        let object: Map<String, Value> = match serde_json::from_str(text) {
+            Ok(Value::Object(object)) => object,
+            Ok(_) => return Err(failed("expected a JSON object".to_string())),
+            Err(e) => return Err(failed(e.to_string())),
+        };
+        if !options.ignore_unknown
+            && let Some(key) = object.keys().find(|key| !table.fields.contains_key(key.as_str()))
+        {
+            return Err(failed(format!("column '{}' does not exist in table '{}'", key, table.name)));
+        }
+
+        let row = table.columns.iter().map(|column| {
+            // Computed once the row is read, whatever the file says
+            if table.is_automatic(column) {
+                return Ok(DataType::String(String::new()));
+            }
+            let raw = match object.get(column) {
+                Some(Value::String(s)) => s.clone(),
+                Some(value @ (Value::Number(_) | Value::Bool(_))) => value.to_string(),
+                Some(Value::Array(items)) if element_type(&table.fields[column]).is_some() => {
+                    array_literal(items.iter().map(|item| match item {
+                        Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    }))
+                }
+                Some(_) => return Err(failed(format!("'{}' must be a string, number or boolean", column))),
+                None => return Err(failed(format!("missing column '{}'", column))),
+            };
+            parse_value(column, &table.fields[column], &raw).map_err(|e| failed(e.to_string()))
+        }).collect::<Result<_, _>>()?;
+        rows.push((line, row));
+    }
+    Ok(rows)
+}
@@ -0,0 +1,75 @@
+//! The RustDB engine: storage, write-ahead log, tables, indexes and the SQL
+//! parser and planner. The `rust_db` binary is a REPL on top of it.
+
+pub mod aggregate;
+pub mod append;
+pub mod async_db;
+pub mod backup;
+#[cfg(target_arch = "wasm32")]
+pub mod browser;
+pub mod budget;
+pub mod builtins;
+pub mod catalog;
+pub mod cdc;
+pub mod collation;
+pub mod comments;
+pub mod csv;
+pub mod cte;
+pub mod database;
+pub mod databases;
+pub mod dependencies;
+pub mod dictionary;
+pub mod dump;
+pub mod encryption;
+pub mod engines;
+pub mod error;
+pub mod expr;
+pub mod external;
+pub mod formats;
+pub mod fts;
+pub mod functions;
+pub mod history;
+pub mod index;
+pub mod interrupt;
+pub mod join;
+pub mod jsonl;
+pub mod migrations;
+pub mod parquet;
+pub mod parser;
+pub mod partition;
+pub mod paths;
+pub mod planner;
+pub mod profile;
+pub mod progress;
+pub mod protocol;
+pub mod query;
+pub mod quota;
+pub mod random;
+pub mod recovery;
+pub mod replication;
+pub mod sequences;
+pub mod shared;
+pub mod stats;
+pub mod storage;
+pub mod subquery;
+pub mod table;
+pub mod time;
+pub mod timestamps;
+pub mod tombstones;
+pub mod triggers;
+pub mod ttl;
+pub mod users;
+pub mod variables;
+pub mod vectorized;
+pub mod versions;
+pub mod vacuum;
+pub mod views;
+pub mod wal;
+pub mod window;
+
+pub use async_db::AsyncDatabase;
+pub use database::Database;
+pub use error::DbError;
+pub use query::Rows;
+pub use shared::SharedDatabase;
+pub use table::{parse_value, DataType, Table};
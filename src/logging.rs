@@ -0,0 +1,66 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+use rust_db::time;
+
+use crate::commands::SLOW_QUERY_LOG;
+
+/// Writes log records to stderr, one line each with the time and level.
+/// Slow statements go to their own file instead, if one is given, whatever
+/// the level.
+struct Logger {
+    level: LevelFilter,
+    slow: Option<Mutex<File>>,
+}
+
+impl Logger {
+    fn is_slow(&self, metadata: &Metadata) -> bool {
+        self.slow.is_some() && metadata.target() == SLOW_QUERY_LOG
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level || self.is_slow(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format!(
+            "{} UTC {} {}: {}",
+            time::format_timestamp(time::now()), record.level(), record.target(), record.args()
+        );
+        match &self.slow {
+            Some(file) if self.is_slow(record.metadata()) => {
+                let mut file = file.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                let _ = writeln!(file, "{}", line);
+            }
+            _ => eprintln!("{}", line),
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.slow {
+            let _ = file.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).flush();
+        }
+    }
+}
+
+/// Installs the logger, showing records at `level` and above, and
+/// appending slow statements to `slow_log` if given.
+pub fn init(level: LevelFilter, slow_log: Option<&Path>) -> io::Result<()> {
+    let slow = slow_log
+        .map(|path| OpenOptions::new().create(true).append(true).open(path))
+        .transpose()?
+        .map(Mutex::new);
+    let max = if slow.is_some() { level.max(LevelFilter::Warn) } else { level };
+    log::set_boxed_logger(Box::new(Logger { level, slow })).map_err(io::Error::other)?;
+    log::set_max_level(max);
+    Ok(())
+}
@@ -1,335 +1,164 @@
-use std::collections::HashMap;
-use std::io::{self, Write};
-use std::fs::{self};
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal};
+
+use rust_db::databases::{DataRoot, DEFAULT_DATABASE};
+use rust_db::parser::OnError;
+use rust_db::Database;
+
+mod bench;
+mod cli;
+mod client;
+mod commands;
+mod completion;
+mod config;
+mod http;
+mod logging;
+mod pgwire;
+mod repl;
+mod result_cache;
+mod server;
+mod sessions;
+mod tls;
+
+use cli::{Command, Location, Mode};
+use client::Credentials;
+use commands::Engine;
+use repl::{RowFormat, Stdout};
+use result_cache::ResultCache;
 
-use serde::{Serialize, Deserialize};
-
-#[macro_use] extern crate prettytable;
-use prettytable::{Table as PTable, Row, Cell};
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-enum DataType {
-    String(String),
-    Integer32(i32),
-    Float32(f32),
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Table {
-    name: String,
-    fields: HashMap<String, String>, // Schema: "age" -> "int"
-    columns: Vec<String>,            // KEEPS ORDER: ["id", "name", "age"]
-    data: HashMap<String, Vec<DataType>>, 
-}
-
-impl std::fmt::Display for DataType {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match self {
-            DataType::String(s) => write!(f, "{}", s),
-            DataType::Integer32(i) => write!(f, "{}", i),
-            DataType::Float32(fl) => write!(f, "{}", fl),
+fn main() {
+    let options = match cli::parse(env::args().skip(1)) {
+        Ok(Command::Open(options)) => *options,
+        Ok(Command::Connect { addr, user, token, tls_ca }) => {
+            let login = match Credentials::read(user.as_deref(), token) {
+                Ok(login) => login,
+                Err(e) => {
+                    eprintln!("Error: Could not read the credentials: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            if !client::run(&addr, login, tls_ca.as_deref()) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        Err(e) => {
+            eprintln!("Error: {}\n{}", e, cli::USAGE);
+            std::process::exit(2);
         }
-    }
-}
-
-
-fn tokenize(input: &str) -> Vec<&str> {
-    input.trim().split_whitespace().collect()
-}
-
-
-fn create_table(name: &str, cols: Vec<(&str, &str)>) {
-    let path = format!("data/{}.json", name);
-    
-    // Check if file exists
-    if std::path::Path::new(&path).exists() {
-        println!("Error: Table '{}' already exists!", name);
-        return;
-    }   
-
-    let mut fields: HashMap<String, String> = HashMap::new();
-    let mut data: HashMap<String, Vec<DataType>> = HashMap::new();
-    let mut columns: Vec<String> = Vec::new(); // Store order
-
-    for (col, data_type) in cols {
-        fields.insert(col.to_string(), data_type.to_string());
-        columns.push(col.to_string());
-        data.insert(col.to_string(), Vec::new());
-    }
-
-    let table = Table {
-        name: name.to_string(),
-        fields,
-        columns,
-        data,
     };
 
-    save_table(&table);
-    println!("Table '{}' created", name);
-}
-
-
-fn drop_table(name: &str) {
-    let path = format!("data/{}.json", name);
-    if std::fs::remove_file(path).is_ok() {
-        println!("Table '{}' dropped", name);
-    }
-    else {
-        println!("Table '{}' does not exists!", name);
+    if let Err(e) = logging::init(options.log_level, options.slow_query_log.as_deref()) {
+        eprintln!("Error: Could not open the slow-query log: {}", e);
+        std::process::exit(2);
     }
-}
 
-fn show_tables() {
-    if let Ok(entries) = fs::read_dir("data") {
-        for e in entries {
-            let path = e.unwrap().path();
-            if path.extension().unwrap_or_default() == "json" {
-                println!("{}", path.file_stem().unwrap().to_str().unwrap());
+    let key = match options.ask_key {
+        true => match rpassword::prompt_password("Encryption key: ") {
+            Ok(key) => Some(key),
+            Err(e) => {
+                eprintln!("Error: Could not read the encryption key: {}", e);
+                std::process::exit(1);
             }
-        }
-    }
-}
-
-
-fn insert_row(table_name: &str, values: Vec<&str>) {
-    let mut table = load_table(table_name);
-
-    // Check if input count matches column count
-    if values.len() != table.columns.len() {
-        println!("Error: Column count mismatch.");
-        return;
-    }
-
-    // Iterate the columns
-    for (i, col_name) in table.columns.iter().enumerate() {
-        let target_type = table.fields.get(col_name).unwrap();
-        let val = parse_value(target_type, values[i]);
-        
-        table.data.get_mut(col_name).unwrap().push(val);
-    }
-
-    save_table(&table);
-    println!("1 row inserted");
-}
-
-fn select_all(table_name: &str) {
-    let table = load_table(table_name);
-    
-    let mut p_table = PTable::new();
-    // Print Header
-    let header_cells: Vec<Cell> = table.columns.iter()
-        .map(|col| Cell::new(col).style_spec("bFg"))
-        .collect();
-    p_table.add_row(Row::new(header_cells));
-
-    // Get row count from the first column
-    let row_count = if let Some(first_col) = table.columns.first() {
-        table.data.get(first_col).unwrap().len()
-    } else { 
-        0 
+        },
+        false => options.encryption_key.clone(),
     };
 
-    // Print Rows
-    for i in 0..row_count {
-        let mut row_cells = Vec::new();
-        for col in &table.columns {
-            // Get the value at index 'i' for this column
-            let val = &table.data[col][i]; 
-            row_cells.push(Cell::new(&val.to_string()));
+    let opened = match &options.location {
+        Location::Dir(path) | Location::File(path) if options.read_only => Database::open_read_only_with_key(path, key.as_deref()),
+        Location::Dir(dir) => Database::open_dir_with_key(dir, key.as_deref()),
+        Location::File(path) => Database::open_file_with_key(path, key.as_deref()),
+        Location::Memory => Ok(Database::open_in_memory()),
+    };
+    let mut db = match opened {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("Error: Could not open database: {}", e);
+            std::process::exit(1);
         }
-        
-        p_table.add_row(Row::new(row_cells));
-    }
-    p_table.printstd();
-}
-
+    };
+    db.set_limits(options.limits);
+    commands::run_recovery(&mut Stdout::default(), &mut db);
 
-fn select_where(table_name: &str, col_name: &str, target_id: i32) {
-    let table = load_table(table_name);
-    
-    // Get the column to search
-    if let Some(column_data) = table.data.get(col_name) {
-        // Find the index where the data matches our target
-        let mut found_index = None;
-        for (i, data) in column_data.iter().enumerate() {
-            if let DataType::Integer32(val) = data {
-                if *val == target_id {
-                    found_index = Some(i);
-                    break;
+    let root = match &options.location {
+        Location::Dir(dir) => Some(DataRoot::new(dir)),
+        Location::File(_) | Location::Memory => None,
+    };
+    let mut engine = Engine::new(db, root, DEFAULT_DATABASE);
+    engine.slow_query = options.slow_query;
+    engine.statement_timeout = options.statement_timeout;
+    engine.query_memory = options.query_memory;
+    engine.results = options.result_cache.map(ResultCache::new);
+    engine.read_stdin = matches!(options.mode, Mode::Script { .. } | Mode::Command { .. });
+
+    // A server's operations run for its clients, which would not see it
+    if !matches!(options.mode, Mode::Serve { .. }) {
+        repl::show_progress();
+    }
+    match &options.mode {
+        Mode::Serve { addr, pg_addr, http_addr, listeners, follow, user, token, tls_ca } => {
+            let leader = match follow {
+                Some(follow) => match Credentials::read(user.as_deref(), *token) {
+                    Ok(login) => Some(server::Leader { addr: follow.clone(), login, tls_ca: tls_ca.clone() }),
+                    Err(e) => {
+                        eprintln!("Error: Could not read the credentials: {}", e);
+                        std::process::exit(1);
+                    }
+                },
+                None => None,
+            };
+            let listeners = match listeners.load() {
+                Ok(listeners) => listeners,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
                 }
+            };
+            if let Err(e) = server::serve(engine, addr, pg_addr.as_deref(), http_addr.as_deref(), listeners, leader) {
+                eprintln!("Error: Could not serve on {}: {}", addr, e);
+                std::process::exit(1);
             }
         }
-
-        // If found, print that index for ALL columns
-        match found_index {
-            Some(i) => {
-                let mut p_table = PTable::new();
-
-            // Create Header
-            let header: Vec<Cell> = table.columns.iter()
-                .map(|c| Cell::new(c).style_spec("bFg"))
-                .collect();
-            p_table.add_row(Row::new(header));
-
-            // Add the Found Row
-            let mut row_cells = Vec::new();
-                for col in &table.columns {
-                    let val = &table.data[col][i];
-                    row_cells.push(Cell::new(&val.to_string()));
-                }
-            p_table.add_row(Row::new(row_cells));
-            p_table.printstd();
-            },
-            None => println!("No row found with {} = {}", col_name, target_id),
+        Mode::Migrate { dir } => {
+            let mut out = Stdout::default();
+            let applied = engine.migrate(&mut out, dir.as_deref(), None);
+            finish(engine, out, applied);
         }
-    } else {
-        println!("Column {} not found", col_name);
-    }
-}
-
-fn delete_row(table_name: &str, col_name: &str, target_id: i32) {
-    let mut table = load_table(table_name);
-    if let Some(column_data) = table.data.get(col_name) {
-        // Find the index 
-        let mut found_index = None;
-            for (i, data) in column_data.iter().enumerate() {
-                if let DataType::Integer32(val) = data {
-                    if *val == target_id {
-                        found_index = Some(i);
-                        break;
-                    }
-                }
+        Mode::Bench { workload, scratch } => {
+            let mut out = Stdout::batch(options.format.or(Some(RowFormat::Table)));
+            let succeeded = bench::run(&mut engine, &mut out, workload);
+            engine.shutdown(&mut out);
+            if *scratch && let Location::Dir(dir) = &options.location {
+                let _ = fs::remove_dir_all(dir);
+            }
+            if !succeeded {
+                std::process::exit(1);
             }
-
-        // If found, remove that index from ALL columns
-        match found_index {
-            Some(i) => {
-                for col in &table.columns {
-                    if let Some(data_vec) = table.data.get_mut(col) {
-                        data_vec.remove(i);
-                    }
-                }
-                save_table(&table);
-                println!("Row deleted successfully.");
-            },
-            None => println!("Error: No row found with {} = {}", col_name, target_id),
         }
-    } else {
-        println!("Column {} not found", col_name);
-    }
-}
-
-fn count_rows (table_name: &str){
-    let table = load_table(table_name);
-    let row_count = if let Some(first_col) = table.columns.first() {
-        table.data.get(first_col).unwrap().len()
-    } else { 
-        0 
-    };
-    println!("Table '{}' contains {} row(s).", table_name, row_count);
-}
-
-fn print_help() {
-    println!("DDL:");
-    println!("  CREATE TABLE <name>");
-    println!("  DROP TABLE <name>");
-    println!("  SHOW TABLES\n");
-
-    println!("DML:");
-    println!("  INSERT INTO <table> VALUES <id> <name>");
-    println!("  SELECT * FROM <table>");
-    println!("  SELECT * FROM <table> WHERE id = <id>");
-}
-
-fn save_table(table: &Table) {
-    let file = std::fs::File::create(format!("data/{}.json", table.name)).unwrap();
-    serde_json::to_writer_pretty(file, table).unwrap();
-}
-
-fn load_table(name: &str) -> Table {
-    let file = std::fs::File::open(format!("data/{}.json", name)).unwrap();
-    serde_json::from_reader(file).unwrap()
-}
-
-fn parse_value(typ: &str, raw: &str) -> DataType {
-    match typ {
-        "int" => DataType::Integer32(raw.parse().unwrap()),
-        "float" => DataType::Float32(raw.parse().unwrap()),
-        _ => DataType::String(raw.to_string()),
+        Mode::Script { file } => {
+            let mut out = Stdout::batch(options.format);
+            let succeeded = engine.source(&mut out, file, options.on_error, None);
+            finish(engine, out, succeeded);
+        }
+        Mode::Command { sql } => {
+            let mut out = Stdout::batch(options.format);
+            let succeeded = engine.run_script(&mut out, sql, None, options.on_error, None);
+            finish(engine, out, succeeded);
+        }
+        Mode::Repl if !io::stdin().is_terminal() => {
+            let mut out = Stdout::batch(options.format);
+            let succeeded = repl::pipe(&mut engine, &mut out, options.on_error == OnError::Continue);
+            finish(engine, out, succeeded);
+        }
+        Mode::Repl => repl::run(engine, options.format),
     }
 }
 
-
-fn main() {
-    loop {
-        print!("dbms> ");
-        io::stdout().flush().unwrap();
-
-        let mut input: String = String::new();
-        io::stdin().read_line(&mut input).unwrap();
-        let t: Vec<&str> = tokenize(&input);
-        
-    
-        match t.as_slice() {
-            ["CREATE", "TABLE", table, rest @ ..] => {
-                let mut cols = Vec::new();
-                let mut syntax_error = false;
-
-                for c in rest {
-                    let parts: Vec<&str> = c.split(':').collect();
-                    
-                    // Ensure we have exactly [name, type]
-                    if parts.len() == 2 {
-                        cols.push((parts[0], parts[1]));
-                    } else {
-                        println!("Syntax Error: Column '{}' format is invalid. Use name:type", c);
-                        syntax_error = true;
-                        break;
-                    }
-                }
-
-                // Only create the table if there were no errors
-                if !syntax_error {
-                    create_table(table, cols);
-                }
-            }
-
-            // SHOW TABLES
-            ["SHOW", "TABLES"] => show_tables(),
-            ["DROP", "TABLE", table] => drop_table(table),
-
-            ["INSERT", "INTO", table, values @ ..] => {
-                insert_row(table, values.to_vec());
-            }
-            ["SELECT", "*", "FROM", table] => {
-                select_all(table);
-            }
-
-            // SELECT * FROM users WHERE id = 1
-            ["SELECT", "*", "FROM", table, "WHERE", col, "=", val] => {
-                if let Ok(id) = val.parse::<i32>() {
-                    select_where(table, col, id);
-                } else {
-                    println!("Only integer search supported currently.");
-                }
-            }
-
-            ["DELETE", "FROM", table, "WHERE", col, "=", val] => {
-                if let Ok(id) = val.parse::<i32>() {
-                    delete_row(table, col, id);
-                } else {
-                    println!("Error: ID must be an integer.");
-                }
-            }
-            
-            ["COUNT", table] => {
-                count_rows(table);
-            }
-
-            ["HELP"] => print_help(),
-            ["EXIT"] => break,
-
-            _ => println!("Invalid command"),
-        }
+/// Ends a non-interactive run, with exit status 1 if a statement failed.
+fn finish(mut engine: Engine, mut out: Stdout, succeeded: bool) {
+    engine.shutdown(&mut out);
+    if !succeeded {
+        std::process::exit(1);
     }
 }
@@ -0,0 +1,113 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::database::Database;
+use crate::error::DbError;
+use crate::parser::{self, Statement};
+use crate::wal::WalOp;
+use crate::{DataType, Table};
+
+/// Where `MIGRATE` looks for migration files unless told otherwise.
+pub const DEFAULT_DIR: &str = "migrations";
+
+/// The table recording which migrations have been applied.
+pub const MIGRATIONS_TABLE: &str = "schema_migrations";
+
+/// One SQL file of a migrations directory, named `<version>_<name>.sql`
+/// (`001_create_users.sql`). Migrations run in version order.
+#[derive(Debug, Clone)]
+pub struct Migration {
+    pub version: u64,
+    pub name: String,
+    pub path: PathBuf,
+}
+
+impl Migration {
+    /// The file's `;`-separated statements and their text, all parsed before
+    /// any of them runs.
+    pub fn statements(&self) -> Result<Vec<(String, Statement)>, DbError> {
+        let sql = fs::read_to_string(&self.path)?;
+        let mut statements = Vec::new();
+        for text in parser::split_statements(&sql) {
+            if parser::tokenize(text)?.is_empty() {
+                continue;
+            }
+            let statement = parser::parse(text)?;
+            if matches!(statement, Statement::Migrate(_) | Statement::Use(_) | Statement::Exit) {
+                return Err(DbError::Syntax("MIGRATE, USE and EXIT cannot be used in a migration".to_string()));
+            }
+            statements.push((text.trim().to_string(), statement));
+        }
+        Ok(statements)
+    }
+}
+
+/// The migrations in `dir`, oldest first. Files not ending in `.sql` are
+/// ignored; a `.sql` file without a version, or two files with the same
+/// version, is an error.
+pub fn load(dir: &Path) -> Result<Vec<Migration>, DbError> {
+    if !dir.is_dir() {
+        return Err(DbError::InvalidMigration(format!("directory '{}' does not exist", dir.display())));
+    }
+    let mut migrations: Vec<Migration> = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(stem) = path.file_name().and_then(|name| name.to_str()).and_then(|name| name.strip_suffix(".sql")) else {
+            continue;
+        };
+        let digits = stem.find(|c: char| !c.is_ascii_digit()).unwrap_or(stem.len());
+        let version: u64 = stem[..digits].parse().map_err(|_| DbError::InvalidMigration(
+            format!("'{}' does not start with a version number", path.display())
+        ))?;
+        let name = stem[digits..].trim_start_matches(['_', '-']).to_string();
+        migrations.push(Migration { version, name, path });
+    }
+
+    migrations.sort_by_key(|m| m.version);
+    if let Some(pair) = migrations.windows(2).find(|pair| pair[0].version == pair[1].version) {
+        return Err(DbError::InvalidMigration(format!(
+            "'{}' and '{}' have the same version", pair[0].path.display(), pair[1].path.display()
+        )));
+    }
+    Ok(migrations)
+}
+
+impl Database {
+    /// The versions of the migrations applied so far, creating the
+    /// `schema_migrations` table the first time.
+    pub fn applied_migrations(&mut self) -> Result<Vec<u64>, DbError> {
+        if !self.table_exists(MIGRATIONS_TABLE) {
+            let columns = vec![
+                ("version".to_string(), "int".to_string()),
+                ("name".to_string(), "string".to_string()),
+                ("applied_at".to_string(), "int".to_string()),
+            ];
+            let table = Table::new(MIGRATIONS_TABLE, columns, Some("version".to_string()), self.last_lsn(), self.now());
+            self.save_table(&table)?;
+        }
+        let table = self.load_table(MIGRATIONS_TABLE)?;
+        Ok(table.data["version"].iter().filter_map(|value| match value {
+            DataType::Integer32(version) => u64::try_from(*version).ok(),
+            _ => None,
+        }).collect())
+    }
+
+    /// The migrations in `dir` that have not been applied yet, oldest first.
+    pub fn pending_migrations(&mut self, dir: &Path) -> Result<Vec<Migration>, DbError> {
+        let applied = self.applied_migrations()?;
+        Ok(load(dir)?.into_iter().filter(|m| !applied.contains(&m.version)).collect())
+    }
+
+    /// Notes in `schema_migrations` that `migration` has been applied.
+    pub fn record_migration(&mut self, migration: &Migration) -> Result<(), DbError> {
+        let applied_at = self.now();
+        let row = vec![
+            DataType::Integer32(migration.version as i32),
+            DataType::String(migration.name.clone()),
+            DataType::Integer32(applied_at as i32),
+        ];
+        self.applied_migrations()?;
+        self.load_table(MIGRATIONS_TABLE)?.check_unique(&row)?;
+        self.log(WalOp::Insert { table: MIGRATIONS_TABLE.to_string(), row })
+    }
+}
@@ -0,0 +1,55 @@
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use ::parquet::arrow::ArrowWriter;
+use ::parquet::basic::Compression;
+use ::parquet::file::properties::WriterProperties;
+use arrow_array::{ArrayRef, Float32Array, Int32Array, RecordBatch, StringArray};
+use arrow_schema::{ArrowError, DataType as ArrowType, Field, Schema};
+
+use crate::error::DbError;
+use crate::query::Rows;
+use crate::DataType;
+
+/// The Arrow type a column type is written as.
+pub fn arrow_type(typ: &str) -> ArrowType {
+    match typ {
+        "int" => ArrowType::Int32,
+        "float" => ArrowType::Float32,
+        _ => ArrowType::Utf8,
+    }
+}
+
+/// Writes a query result to a Snappy-compressed Parquet file. Columns are
+/// never null.
+pub fn write(path: &Path, result: &Rows) -> Result<(), DbError> {
+    let fields: Vec<Field> = result.columns.iter().zip(&result.types)
+        .map(|(name, typ)| Field::new(name, arrow_type(typ), false))
+        .collect();
+    let schema = Arc::new(Schema::new(fields));
+
+    let columns: Vec<ArrayRef> = result.types.iter().enumerate().map(|(i, typ)| {
+        let values = result.rows.iter().map(move |row| &row[i]);
+        let array: ArrayRef = match arrow_type(typ) {
+            ArrowType::Int32 => Arc::new(values.map(|value| match value {
+                DataType::Integer32(n) => Some(*n),
+                _ => None,
+            }).collect::<Int32Array>()),
+            ArrowType::Float32 => Arc::new(values.map(|value| match value {
+                DataType::Float32(n) => Some(*n),
+                _ => None,
+            }).collect::<Float32Array>()),
+            _ => Arc::new(values.map(|value| Some(value.to_string())).collect::<StringArray>()),
+        };
+        array
+    }).collect();
+
+    let failed = |e: &dyn std::fmt::Display| DbError::ExportFailed(e.to_string());
+    let batch = RecordBatch::try_new(Arc::clone(&schema), columns).map_err(|e: ArrowError| failed(&e))?;
+    let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+    let mut writer = ArrowWriter::try_new(File::create(path)?, schema, Some(properties)).map_err(|e| failed(&e))?;
+    writer.write(&batch).map_err(|e| failed(&e))?;
+    writer.close().map_err(|e| failed(&e))?;
+    Ok(())
+}
@@ -0,0 +1,2313 @@
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::aggregate::{Aggregate, AggregateFunction};
+use crate::csv::CsvOptions;
+use crate::collation::Collation;
+use crate::engines::Engine;
+use crate::error::DbError;
+use crate::expr::{BinaryOp, Expr, CAST_TYPES};
+use crate::formats::Format;
+use crate::index::IndexKind;
+use crate::join;
+use crate::jsonl::JsonlOptions;
+use crate::partition::{PartitionBy, Scheme};
+use crate::paths::PathQuery;
+use crate::time;
+use crate::triggers::{Event, Timing};
+use crate::users::Privilege;
+use crate::table::{self, array_literal};
+use crate::wal::Synchronous;
+use crate::window::{Window, WindowFunction};
+use crate::DataType;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Ident(String),
+    Number(String),
+    Str(String), // Single-quoted, with '' unescaped
+    Symbol(&'static str),
+}
+
+/// Words a statement reads as keywords where a name could also go, so no
+/// table or column may be called by one.
+pub const RESERVED: [&str; 39] = [
+    "ALL", "AND", "ANY", "ARRAY", "AS", "ASC", "BETWEEN", "BY", "CAST", "CONFLICT", "CONTAINS", "CREATE", "CROSS", "DELETE",
+    "DESC", "DO", "DROP", "EXCLUDED", "FROM", "GENERATED", "IN", "INNER", "INSERT", "INTERVAL", "INTO", "JOIN",
+    "LIMIT", "MATCH", "ON", "ORDER", "OVER", "PARTITION", "PRIMARY", "RETURNING", "SELECT", "SET", "UNION",
+    "VALUES", "WHERE",
+];
+
+/// Whether `word` is one of the `RESERVED` keywords, in any case.
+pub fn is_reserved(word: &str) -> bool {
+    RESERVED.iter().any(|keyword| word.eq_ignore_ascii_case(keyword))
+}
+
+// Words that may follow a table name in FROM, so are never taken for an alias
+const CLAUSE_KEYWORDS: [&str; 10] = ["WHERE", "CROSS", "JOIN", "INNER", "ON", "AS", "GROUP", "ORDER", "LIMIT", "UNION"];
+
+const SYMBOLS: [&str; 20] = [
+    "<=", ">=", "!=", "<>", "(", ")", ",", ";", ":", "*", "=", "<", ">", "-", ".", "+", "/", "[", "]", "@",
+];
+
+pub fn tokenize(input: &str) -> Result<Vec<Token>, DbError> {
+    tokenize_spans(input).map(|(tokens, _)| tokens)
+}
+
+/// The tokens of `input`, with the bytes of `input` each was read from.
+fn tokenize_spans(input: &str) -> Result<(Vec<Token>, Vec<Range<usize>>), DbError> {
+    let mut tokens = Vec::new();
+    let mut spans = Vec::new();
+    let chars: Vec<char> = input.chars().collect();
+    let offsets: Vec<usize> = input.char_indices().map(|(offset, _)| offset).chain([input.len()]).collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '-' && chars.get(i + 1) == Some(&'-') {
+            // A comment, up to the end of the line
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            tokens.push(Token::Number(chars[start..i].iter().collect()));
+        } else if c == '\'' {
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => {
+                        return Err(DbError::SyntaxAt {
+                            reason: "unterminated string literal".to_string(),
+                            offset: offsets[start],
+                            token: Some(input[offsets[start]..].to_string()),
+                        });
+                    }
+                    Some('\'') if chars.get(i + 1) == Some(&'\'') => {
+                        text.push('\'');
+                        i += 2;
+                    }
+                    Some('\'') => {
+                        i += 1;
+                        break;
+                    }
+                    Some(ch) => {
+                        text.push(*ch);
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push(Token::Str(text));
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let symbol = SYMBOLS.iter()
+                .find(|s| rest.starts_with(**s))
+                .ok_or_else(|| DbError::SyntaxAt {
+                    reason: format!("unexpected character '{}'", c),
+                    offset: offsets[start],
+                    token: Some(c.to_string()),
+                })?;
+            tokens.push(Token::Symbol(symbol));
+            i += symbol.len();
+        }
+        if tokens.len() > spans.len() {
+            spans.push(offsets[start]..offsets[i]);
+        }
+    }
+    Ok((tokens, spans))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CmpOp {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    Match,    // Full-text: the value holds every word of the query
+    Contains, // The array holds the value as one of its elements
+    In,       // The value is an array literal holding the value compared
+    NotIn,
+}
+
+impl CmpOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            CmpOp::Eq => "=",
+            CmpOp::NotEq => "!=",
+            CmpOp::Lt => "<",
+            CmpOp::LtEq => "<=",
+            CmpOp::Gt => ">",
+            CmpOp::GtEq => ">=",
+            CmpOp::Match => "MATCH",
+            CmpOp::Contains => "CONTAINS",
+            CmpOp::In => "IN",
+            CmpOp::NotIn => "NOT IN",
+        }
+    }
+
+    /// Whether a value comparing `ord` to the target satisfies the operator.
+    pub fn test(&self, ord: Ordering) -> bool {
+        match self {
+            CmpOp::Eq => ord == Ordering::Equal,
+            CmpOp::NotEq => ord != Ordering::Equal,
+            CmpOp::Lt => ord == Ordering::Less,
+            CmpOp::LtEq => ord != Ordering::Greater,
+            CmpOp::Gt => ord == Ordering::Greater,
+            CmpOp::GtEq => ord != Ordering::Less,
+            CmpOp::Match | CmpOp::Contains | CmpOp::In | CmpOp::NotIn => false,
+        }
+    }
+}
+
+/// `expr op value`, with the value kept as written until it can be typed
+/// against the column (or the result of the expression). Compared with an
+/// expression instead (a column written `<table>.<column>`, a function
+/// call, arithmetic), the value is empty and the expression is `against`.
+/// `[NOT] IN` or `[NOT] EXISTS` a subquery has the subquery until it is run
+/// and its values become the list.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Predicate {
+    #[serde(alias = "column")]
+    pub left: Expr,
+    pub op: CmpOp,
+    pub value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub against: Option<Expr>,
+    #[serde(skip)]
+    pub subquery: Option<Subquery>,
+}
+
+/// A SELECT in a condition.
+#[derive(Debug, Clone)]
+pub enum Subquery {
+    In(Box<Statement>),     // Gives the values of its one column
+    Exists(Box<Statement>), // Gives rows or none
+}
+
+impl Subquery {
+    pub fn query(&self) -> &Statement {
+        match self {
+            Subquery::In(query) | Subquery::Exists(query) => query,
+        }
+    }
+}
+
+impl Predicate {
+    /// The column compared with a value, unless the left side is more than a
+    /// column or the right side is an expression.
+    pub fn column(&self) -> Option<&str> {
+        if self.against.is_some() { None } else { self.left.column() }
+    }
+}
+
+/// A table in a FROM list, what it is read as, and the alias its columns
+/// are qualified with there, if it has one.
+#[derive(Debug, Clone)]
+pub struct TableRef {
+    pub table: String, // For a path query, the table of edges
+    pub alias: Option<String>,
+    pub source: Source,
+}
+
+/// What a FROM list reads of a table.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Source {
+    #[default]
+    Current,         // Its rows as they are now
+    AsOf(u64),       // Its rows as they were at that time, for `AS OF`
+    Deleted,         // Its rows soft-deleted or not, for `WITH DELETED`
+    Path(PathQuery), // The ways along its edges, for `PATH(...)`
+}
+
+impl TableRef {
+    /// `table` as it is now, without an alias.
+    pub fn current(table: String) -> TableRef {
+        TableRef { table, alias: None, source: Source::Current }
+    }
+
+    /// The FROM list of a SELECT, in order.
+    pub fn list(from: TableRef, joins: Vec<TableRef>) -> Vec<TableRef> {
+        std::iter::once(from).chain(joins).collect()
+    }
+
+    /// What the table's columns are qualified with: its alias, or else its name.
+    pub fn name(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.table)
+    }
+}
+
+/// `<name> [(<column>, ...)] AS (SELECT ...)` in a WITH: a query whose
+/// result the statement reads as a table. Without column names, each column
+/// is named as it was selected, less any table qualifying it.
+#[derive(Debug, Clone)]
+pub struct Cte {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub query: Statement,
+    pub step: Option<Step>,
+}
+
+/// `UNION [ALL] SELECT ...` after the query of a WITH RECURSIVE: run again
+/// and again on the rows the last round added, reading them under the
+/// WITH's name, until it gives no new rows.
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub query: Box<Statement>,
+    pub all: bool, // UNION ALL keeps rows seen before; UNION drops them
+}
+
+/// `GROUP BY <expr>, ... ORDER BY <expr> [ASC|DESC], ... LIMIT <n>`: the
+/// groups a SELECT puts its rows in for aggregates (see `aggregate`), the
+/// order it gives its rows in, by the first key and then the next among
+/// equals, and how many of them at most. Without keys, rows come in table
+/// order (or by relevance for a MATCH).
+#[derive(Debug, Clone, Default)]
+pub struct Order {
+    pub group: Vec<Expr>,
+    pub by: Vec<SortKey>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortKey {
+    pub expr: Expr,
+    pub descending: bool,
+}
+
+/// What a script does when one of its statements fails.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OnError {
+    #[default]
+    Stop,     // The statements before it stay applied
+    Continue, // The rest still run
+    RollBack, // The whole script runs in one transaction, which is rolled back
+}
+
+/// One of the values of an INSERT.
+#[derive(Debug, Clone)]
+pub enum InsertValue {
+    Literal(String),
+    Nextval(String), // `NEXTVAL('<sequence>')`, drawn as the row is inserted
+}
+
+/// What an INSERT does instead when its row repeats a unique key. With no
+/// target columns any unique index counts, the primary key included.
+#[derive(Debug, Clone)]
+pub struct OnConflict {
+    pub target: Vec<String>,
+    pub action: ConflictAction,
+}
+
+#[derive(Debug, Clone)]
+pub enum ConflictAction {
+    Nothing,
+    Update(Vec<(String, SetValue)>), // Applied to the row already holding the key
+}
+
+#[derive(Debug, Clone)]
+pub enum SetValue {
+    Literal(String),
+    Excluded(String), // `EXCLUDED.<column>`: the value the INSERT tried to write
+    Expr(Expr),       // Worked out from the row's values before the update
+}
+
+impl Statement {
+    /// Whether the statement may run while a transaction is open. Everything
+    /// else writes table files directly and would leak uncommitted rows.
+    pub fn allowed_in_transaction(&self) -> bool {
+        matches!(
+            self,
+            Statement::Insert { .. }
+                | Statement::Select { .. }
+                | Statement::With { .. }
+                | Statement::Nextval(_)
+                | Statement::Setval { .. }
+                | Statement::Delete { .. }
+                | Statement::Update { .. }
+                | Statement::Undelete { .. }
+                | Statement::Purge { .. }
+                | Statement::Count(_)
+                | Statement::Explain { .. }
+                | Statement::Declare { .. }
+                | Statement::Fetch { .. }
+                | Statement::Close(_)
+                | Statement::Export { .. }
+                | Statement::ShowTables
+                | Statement::ShowSequences
+                | Statement::ShowDatabases
+                | Statement::ShowStats(_)
+                | Statement::ShowIndexes(_)
+                | Statement::ShowCreateTable(_)
+                | Statement::Describe(_)
+                | Statement::RefreshTable(_)
+                | Statement::ShowUsers
+                | Statement::ShowTokens
+                | Statement::ShowGrants(_)
+                | Statement::ShowProcesslist
+                | Statement::Kill(_)
+                | Statement::Source { .. }
+                | Statement::Help
+                | Statement::Commit
+                | Statement::Rollback
+                | Statement::Savepoint(_)
+                | Statement::RollbackTo(_)
+                | Statement::Release(_)
+                | Statement::SetStatementTimeout(_)
+                | Statement::SetAutocommit(_)
+                | Statement::SetSynchronous(_)
+                | Statement::SetVariable { .. }
+                | Statement::Exit
+        )
+    }
+
+    /// Whether the statement leaves the database as it is, and so may run on
+    /// a follower. A script's statements are checked one by one as it runs.
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Statement::Select { .. }
+                | Statement::With { .. }
+                | Statement::Count(_)
+                | Statement::Explain { .. }
+                | Statement::Declare { .. }
+                | Statement::Fetch { .. }
+                | Statement::Close(_)
+                | Statement::Export { .. }
+                | Statement::Dump(_)
+                | Statement::Backup(_)
+                | Statement::ShowTables
+                | Statement::ShowTableStatus
+                | Statement::ShowSequences
+                | Statement::ShowCreateTable(_)
+                | Statement::Describe(_)
+                | Statement::RefreshTable(_)
+                | Statement::ShowDatabases
+                | Statement::ShowStats(_)
+                | Statement::ShowIndexes(_)
+                | Statement::ShowUsers
+                | Statement::ShowTokens
+                | Statement::ShowGrants(_)
+                | Statement::ShowProcesslist
+                | Statement::Kill(_)
+                | Statement::Use(_)
+                | Statement::Begin
+                | Statement::Commit
+                | Statement::Rollback
+                | Statement::Savepoint(_)
+                | Statement::RollbackTo(_)
+                | Statement::Release(_)
+                | Statement::SetStatementTimeout(_)
+                | Statement::SetAutocommit(_)
+                | Statement::SetSynchronous(_)
+                | Statement::SetVariable { .. }
+                | Statement::Source { .. }
+                | Statement::Promote
+                | Statement::Subscribe(_)
+                | Statement::Help
+                | Statement::Exit
+        )
+    }
+}
+
+impl fmt::Display for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.against, &self.subquery) {
+            (_, Some(Subquery::Exists(_))) if self.op == CmpOp::NotIn => write!(f, "NOT EXISTS (SELECT ...)"),
+            (_, Some(Subquery::Exists(_))) => write!(f, "EXISTS (SELECT ...)"),
+            (_, Some(Subquery::In(_))) => write!(f, "{} {} (SELECT ...)", self.left, self.op.symbol()),
+            (Some(against), None) => write!(f, "{} {} {}", self.left, self.op.symbol(), against),
+            // The list as written, in parentheses rather than an array's braces
+            (None, None) if matches!(self.op, CmpOp::In | CmpOp::NotIn) => {
+                let items = self.value.strip_prefix('{').and_then(|value| value.strip_suffix('}')).unwrap_or(&self.value);
+                write!(f, "{} {} ({})", self.left, self.op.symbol(), items)
+            }
+            (None, None) => write!(f, "{} {} {}", self.left, self.op.symbol(), self.value),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Statement {
+    CreateTable {
+        name: String,
+        columns: Vec<(String, String)>,
+        generated: Vec<(String, Expr)>,
+        primary_key: Option<String>,
+        temp: bool,
+        partition_by: Option<PartitionBy>,
+        ttl: Option<String>, // The column holding when each row expires
+        soft_delete: bool,   // Whether DELETE only marks rows deleted
+        timestamps: bool,    // Whether the engine keeps created_at and updated_at
+        engine: Engine,
+        collations: Vec<(String, Collation)>, // Of the string columns declared with COLLATE
+        sequences: Vec<(String, String)>,     // Of the columns declared DEFAULT NEXTVAL('<sequence>')
+    },
+    // The rows stay in a CSV file at `location`, read at query time
+    CreateExternalTable { name: String, columns: Vec<(String, String)>, location: String, options: CsvOptions },
+    DropTable { name: String, cascade: bool }, // CASCADE drops what depends on it first
+    // A range partition for values below `below`, every value left if None
+    AddPartition { table: String, name: String, below: Option<String> },
+    DropPartition { table: String, name: String },
+    // How long, in seconds, the table keeps its changes for AS OF; None stops keeping them
+    SetHistoryRetention { table: String, retention: Option<u64> },
+    SetTtl { table: String, column: Option<String> }, // None stops rows expiring
+    SetSoftDelete { table: String, on: bool },
+    SetTimestamps { table: String, on: bool },
+    SetMaxRows { table: String, max_rows: Option<usize> }, // None lifts the limit
+    SetEngine { table: String, engine: Engine }, // Rewrites the table's file
+    // The sequence a column takes its DEFAULT from; None drops the DEFAULT
+    SetDefault { table: String, column: String, sequence: Option<String> },
+    // Of the table, or of one of its columns; None removes it
+    Comment { table: String, column: Option<String>, text: Option<String> },
+    ShowTables,
+    ShowTableStatus,
+    ShowSequences,
+    ShowCreateTable(String), // A view's name gives its CREATE VIEW
+    Describe(String),
+    CreateSequence { name: String, start: i32, increment: i32 },
+    DropSequence { name: String, cascade: bool }, // CASCADE drops the DEFAULTs taken from it first
+    Nextval(String),                        // `SELECT NEXTVAL('<sequence>')`
+    Setval { sequence: String, value: i32 }, // `SELECT SETVAL('<sequence>', <value>)`
+    CreateDatabase(String),
+    DropDatabase(String),
+    ShowDatabases,
+    Use(String),
+    CreateUser { name: String, password: String, superuser: bool },
+    DropUser(String),
+    ShowUsers,
+    CreateToken { name: String, user: String },
+    DropToken(String),
+    ShowTokens,
+    Grant { privileges: Vec<Privilege>, table: String, user: String },
+    Revoke { privileges: Vec<Privilege>, table: String, user: String },
+    ShowGrants(String),
+    ShowProcesslist,
+    Kill(u64), // A session id, as SHOW PROCESSLIST gives it
+    CreateIndex { name: String, table: String, columns: Vec<String>, kind: IndexKind },
+    DropIndex { name: String, table: Option<String> }, // Without a table, the one table with such an index
+    ShowIndexes(String),
+    Reindex(String),
+    CreateView { name: String, table: String, filter: Vec<Predicate>, materialized: bool },
+    DropView { name: String, cascade: bool },
+    RefreshView(String),
+    RefreshTable(String),
+    CreateTrigger { name: String, table: String, timing: Timing, event: Event, body: String },
+    DropTrigger { name: String, table: String },
+    // `returning` lists what to give back of the row written (every column
+    // if empty), as RETURNING asks
+    Insert { table: String, values: Vec<InsertValue>, on_conflict: Option<OnConflict>, returning: Option<Vec<Expr>> },
+    // Filters are ANDed together; an empty list matches every row. No
+    // columns means `SELECT *`. `joins` are the tables after the first in
+    // the FROM list, each combined with every row of those before it
+    Select { from: TableRef, joins: Vec<TableRef>, columns: Vec<Expr>, filter: Vec<Predicate>, order: Order },
+    // `query` is a SELECT, reading the results of `ctes` as tables
+    With { ctes: Vec<Cte>, query: Box<Statement> },
+    Delete { table: String, filter: Vec<Predicate>, returning: Option<Vec<Expr>> },
+    // A `set` value is a literal or an expression; EXCLUDED is only for upserts
+    Update { table: String, set: Vec<(String, SetValue)>, filter: Vec<Predicate>, returning: Option<Vec<Expr>> },
+    // Soft-deleted rows matching `filter` (every one if empty), brought back or removed for good
+    Undelete { table: String, filter: Vec<Predicate> },
+    Purge { table: String, filter: Vec<Predicate> },
+    Count(String),
+    // With `analyze`, the query runs and each step reports what it did
+    Explain { statement: Box<Statement>, analyze: bool },
+    // `query` is a SELECT, whose rows FETCH then gives a batch at a time
+    Declare { name: String, query: Box<Statement> },
+    Fetch { name: String, count: Option<usize> }, // Every row left if None
+    Close(Option<String>),                        // Every cursor if None
+    Analyze(String),
+    ShowStats(String),
+    Begin,
+    Commit,
+    Rollback,
+    Savepoint(String),
+    RollbackTo(String),
+    Release(String),
+    Checkpoint,
+    Flush,
+    Vacuum(Option<String>), // Every table if None
+    SetCompression(String),
+    Migrate(Option<String>), // The migrations directory, if not the default
+    Import { path: String, table: String, format: Format },
+    // The rows come from standard input, which the front end reads into `data`
+    Copy { table: String, format: Format, data: Option<String> },
+    // Always a SELECT; `EXPORT TABLE t` is `SELECT * FROM t`
+    Export { query: Box<Statement>, path: String, format: Format },
+    Dump(String),
+    Backup(String),
+    // Up to a point in time (seconds since the Unix epoch) using the archived log, if set
+    Restore { path: String, until: Option<u64> },
+    SetWalArchive(Option<String>), // None turns archiving off
+    Rekey(Option<String>),         // The new passphrase; None stores the database unencrypted
+    SetStatementTimeout(u64),      // In milliseconds; 0 turns the timeout off
+    SetAutocommit(bool),           // When off, a change opens a transaction that waits for COMMIT
+    SetSynchronous(Synchronous),   // Whether changes are fsynced per statement or per input line
+    SetVariable { name: String, value: Expr }, // A session variable, `@name` in the statements that follow
+    // Runs the statements of a file
+    Source { path: String, on_error: OnError },
+    Promote, // Stops following the leader and takes writes
+    // Streams the table's committed changes over the connection, which then takes no statements
+    Subscribe(String),
+    Help,
+    Exit,
+}
+
+/// Parses one statement. A syntax error says where in `input` it was found.
+pub fn parse(input: &str) -> Result<Statement, DbError> {
+    let (tokens, spans) = tokenize_spans(input)?;
+    let mut parser = Parser::new(tokens);
+    parser.whole_statement().map_err(|e| parser.locate(e, input, &spans))
+}
+
+/// Parses a single expression, such as a saved view condition.
+pub fn parse_expr(input: &str) -> Result<Expr, DbError> {
+    let (tokens, spans) = tokenize_spans(input)?;
+    let mut parser = Parser::new(tokens);
+    let expr = parser.expr().map_err(|e| parser.locate(e, input, &spans))?;
+    if let Some(token) = parser.peek() {
+        let e = DbError::Syntax(format!("unexpected {} after expression", describe(token)));
+        return Err(parser.locate(e, input, &spans));
+    }
+    Ok(expr)
+}
+
+/// Parses tokens that did not come straight from text, so a syntax error
+/// cannot say where it is.
+pub fn parse_tokens(tokens: Vec<Token>) -> Result<Statement, DbError> {
+    Parser::new(tokens).whole_statement()
+}
+
+/// Whether `input` ends inside a string literal, so the statement goes on
+/// past the end of the line.
+pub fn unterminated(input: &str) -> bool {
+    let mut chars = input.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => quoted = !quoted,
+            '-' if !quoted && chars.next_if_eq(&'-').is_some() => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            }
+            _ => {}
+        }
+    }
+    quoted
+}
+
+/// Splits a query on the semicolons that are not inside string literals.
+pub fn split_statements(query: &str) -> Vec<&str> {
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    for (i, c) in query.char_indices() {
+        match c {
+            '\'' => quoted = !quoted,
+            ';' if !quoted => {
+                statements.push(&query[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    statements.push(&query[start..]);
+    statements
+}
+
+/// The statements of a `;`-separated script with the line each one starts
+/// on, counting from 1. Empty statements and comments are left out.
+pub fn split_script(script: &str) -> Vec<(usize, &str)> {
+    let mut line = 1;
+    let mut statements = Vec::new();
+    for text in split_statements(script) {
+        // Blank lines and comments before the statement itself
+        let skipped = text.lines()
+            .take_while(|l| l.trim().is_empty() || l.trim_start().starts_with("--"))
+            .count();
+        if skipped < text.lines().count() {
+            statements.push((line + skipped, text));
+        }
+        line += text.matches('\n').count();
+    }
+    statements
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Ident(s) | Token::Number(s) => format!("'{}'", s),
+        Token::Str(s) => format!("string '{}'", s),
+        Token::Symbol(s) => format!("'{}'", s),
+    }
+}
+
+/// Seconds in a SECOND, MINUTE, HOUR, DAY or WEEK, any of them plural.
+fn seconds_in(unit: &str) -> Result<i32, DbError> {
+    match unit.to_ascii_uppercase().trim_end_matches('S') {
+        "SECOND" => Ok(1),
+        "MINUTE" => Ok(60),
+        "HOUR" => Ok(3600),
+        "DAY" => Ok(86400),
+        "WEEK" => Ok(7 * 86400),
+        _ => Err(DbError::Syntax(format!("unknown unit '{}'. Use SECOND, MINUTE, HOUR, DAY or WEEK", unit))),
+    }
+}
+
+// Parentheses, operators, subqueries and arrays nested deeper than this are
+// refused rather than overflowing the stack
+const MAX_DEPTH: usize = 256;
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    // The furthest token looked at, where a syntax error is reported
+    reached: Cell<usize>,
+    depth: usize, // Of what is being parsed, in `nested` calls
+}
+
+impl Parser {
+    fn new(tokens: Vec<Token>) -> Parser {
+        Parser { tokens, pos: 0, reached: Cell::new(0), depth: 0 }
+    }
+
+    /// Parses with `parse` one level further in.
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Parser) -> Result<T, DbError>) -> Result<T, DbError> {
+        self.deeper()?;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn deeper(&mut self) -> Result<(), DbError> {
+        if self.depth == MAX_DEPTH {
+            return Err(DbError::Syntax(format!("nested more than {} levels deep", MAX_DEPTH)));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.reached.set(self.reached.get().max(self.pos));
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// A statement and nothing after it but a `;`.
+    fn whole_statement(&mut self) -> Result<Statement, DbError> {
+        let statement = self.statement()?;
+        self.symbol(";");
+        if let Some(token) = self.peek() {
+            return Err(DbError::Syntax(format!("unexpected {} after statement", describe(token))));
+        }
+        Ok(statement)
+    }
+
+    /// `e`, if a syntax error, with the place in `input` parsing got to;
+    /// `spans` are where the tokens came from.
+    fn locate(&self, e: DbError, input: &str, spans: &[Range<usize>]) -> DbError {
+        let DbError::Syntax(reason) = e else { return e };
+        match spans.get(self.reached.get()) {
+            Some(span) => DbError::SyntaxAt { reason, offset: span.start, token: Some(input[span.clone()].to_string()) },
+            None => DbError::SyntaxAt { reason, offset: input.len(), token: None },
+        }
+    }
+
+    fn error(&self, expected: &str) -> DbError {
+        match self.peek() {
+            Some(token) => DbError::Syntax(format!("expected {}, found {}", expected, describe(token))),
+            None => DbError::Syntax(format!("expected {}, found end of input", expected)),
+        }
+    }
+
+    /// Consumes the keyword if it is next (case-insensitive).
+    fn at_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    // Whether the token at `pos` is `keyword`
+    fn keyword_at(&self, pos: usize, keyword: &str) -> bool {
+        matches!(self.tokens.get(pos), Some(Token::Ident(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found = self.at_keyword(keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), DbError> {
+        if self.keyword(keyword) { Ok(()) } else { Err(self.error(keyword)) }
+    }
+
+    fn at_symbol(&self, symbol: &str) -> bool {
+        matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol)
+    }
+
+    fn symbol(&mut self, symbol: &str) -> bool {
+        if self.at_symbol(symbol) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), DbError> {
+        if self.symbol(symbol) { Ok(()) } else { Err(self.error(&format!("'{}'", symbol))) }
+    }
+
+    fn ident(&mut self) -> Result<String, DbError> {
+        match self.peek() {
+            Some(Token::Ident(name)) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(self.error("a name")),
+        }
+    }
+
+    /// A literal value: number (optionally negative), quoted string or bare word.
+    fn value(&mut self) -> Result<String, DbError> {
+        // An array, `[<value>, ...]` or `ARRAY[...]`, in the text form a column reads
+        if let Some(Token::Ident(word)) = self.peek()
+            && word.eq_ignore_ascii_case("ARRAY")
+            && self.tokens.get(self.pos + 1) == Some(&Token::Symbol("["))
+        {
+            self.pos += 1;
+        }
+        if self.symbol("[") {
+            let mut items = Vec::new();
+            if !self.symbol("]") {
+                items.push(self.nested(Parser::value)?);
+                while self.symbol(",") {
+                    items.push(self.nested(Parser::value)?);
+                }
+                self.expect_symbol("]")?;
+            }
+            return Ok(array_literal(items));
+        }
+        let negative = self.symbol("-");
+        match self.next() {
+            Some(Token::Number(n)) if negative => Ok(format!("-{}", n)),
+            Some(Token::Number(n) | Token::Str(n) | Token::Ident(n)) if !negative => Ok(n),
+            _ => {
+                self.pos -= 1;
+                Err(self.error("a value"))
+            }
+        }
+    }
+
+    fn at_end(&self) -> bool {
+        matches!(self.peek(), None | Some(Token::Symbol(";")))
+    }
+
+    fn statement(&mut self) -> Result<Statement, DbError> {
+        if self.keyword("CREATE") {
+            self.create()
+        } else if self.keyword("DROP") {
+            if self.keyword("TABLE") {
+                let name = self.ident()?;
+                Ok(Statement::DropTable { name, cascade: self.keyword("CASCADE") })
+            } else if self.keyword("DATABASE") {
+                Ok(Statement::DropDatabase(self.ident()?))
+            } else if self.keyword("USER") {
+                Ok(Statement::DropUser(self.ident()?))
+            } else if self.keyword("TOKEN") {
+                Ok(Statement::DropToken(self.ident()?))
+            } else if self.keyword("VIEW") {
+                let name = self.ident()?;
+                Ok(Statement::DropView { name, cascade: self.keyword("CASCADE") })
+            } else if self.keyword("MATERIALIZED") {
+                self.expect_keyword("VIEW")?;
+                let name = self.ident()?;
+                Ok(Statement::DropView { name, cascade: self.keyword("CASCADE") })
+            } else if self.keyword("TRIGGER") {
+                let name = self.ident()?;
+                self.expect_keyword("ON")?;
+                Ok(Statement::DropTrigger { name, table: self.ident()? })
+            } else if self.keyword("SEQUENCE") {
+                let name = self.ident()?;
+                Ok(Statement::DropSequence { name, cascade: self.keyword("CASCADE") })
+            } else if self.keyword("INDEX") {
+                let name = self.ident()?;
+                let table = match self.keyword("ON") {
+                    true => Some(self.ident()?),
+                    false => None,
+                };
+                Ok(Statement::DropIndex { name, table })
+            } else {
+                Err(self.error("TABLE, DATABASE, USER, TOKEN, VIEW, TRIGGER, SEQUENCE or INDEX"))
+            }
+        } else if self.keyword("ALTER") {
+            self.expect_keyword("TABLE")?;
+            let table = self.ident()?;
+            if self.keyword("ADD") {
+                let (name, below) = self.range_partition()?;
+                return Ok(Statement::AddPartition { table, name, below });
+            }
+            if self.keyword("ENGINE") {
+                return Ok(Statement::SetEngine { table, engine: self.engine()? });
+            }
+            if self.keyword("ALTER") {
+                self.keyword("COLUMN");
+                let column = self.ident()?;
+                if self.keyword("DROP") {
+                    self.expect_keyword("DEFAULT")?;
+                    return Ok(Statement::SetDefault { table, column, sequence: None });
+                }
+                self.expect_keyword("SET")?;
+                self.expect_keyword("DEFAULT")?;
+                return Ok(Statement::SetDefault { table, column, sequence: Some(self.default()?) });
+            }
+            if self.keyword("SET") {
+                if self.keyword("SOFT") {
+                    self.expect_keyword("DELETE")?;
+                    let on = self.keyword("ON");
+                    if !on {
+                        self.expect_keyword("OFF")?;
+                    }
+                    return Ok(Statement::SetSoftDelete { table, on });
+                }
+                if self.keyword("TIMESTAMPS") {
+                    let on = self.keyword("ON");
+                    if !on {
+                        self.expect_keyword("OFF")?;
+                    }
+                    return Ok(Statement::SetTimestamps { table, on });
+                }
+                if self.keyword("MAX") {
+                    self.expect_keyword("ROWS")?;
+                    if self.keyword("OFF") {
+                        return Ok(Statement::SetMaxRows { table, max_rows: None });
+                    }
+                    let n = self.value()?;
+                    let max_rows = n.parse().map_err(|_| DbError::Syntax(format!("MAX ROWS takes a whole number, not '{}'", n)))?;
+                    return Ok(Statement::SetMaxRows { table, max_rows: Some(max_rows) });
+                }
+                if self.keyword("TTL") {
+                    if self.keyword("OFF") {
+                        return Ok(Statement::SetTtl { table, column: None });
+                    }
+                    return Ok(Statement::SetTtl { table, column: Some(self.ident()?) });
+                }
+                self.expect_keyword("HISTORY")?;
+                self.expect_keyword("RETENTION")?;
+                if self.keyword("OFF") {
+                    return Ok(Statement::SetHistoryRetention { table, retention: None });
+                }
+                let n: u64 = self.value()?.parse().map_err(|_| DbError::Syntax("a retention is a whole number".to_string()))?;
+                let unit = self.ident()?;
+                let retention = n.checked_mul(seconds_in(&unit)? as u64)
+                    .ok_or_else(|| DbError::Syntax(format!("a retention of {} {} is too long", n, unit)))?;
+                return Ok(Statement::SetHistoryRetention { table, retention: Some(retention) });
+            }
+            self.expect_keyword("DROP")?;
+            self.expect_keyword("PARTITION")?;
+            Ok(Statement::DropPartition { table, name: self.ident()? })
+        } else if self.keyword("SHOW") {
+            if self.keyword("TABLES") {
+                Ok(Statement::ShowTables)
+            } else if self.keyword("TABLE") {
+                self.expect_keyword("STATUS")?;
+                Ok(Statement::ShowTableStatus)
+            } else if self.keyword("SEQUENCES") {
+                Ok(Statement::ShowSequences)
+            } else if self.keyword("CREATE") {
+                if !self.keyword("VIEW") {
+                    self.expect_keyword("TABLE")?;
+                }
+                Ok(Statement::ShowCreateTable(self.ident()?))
+            } else if self.keyword("DATABASES") {
+                Ok(Statement::ShowDatabases)
+            } else if self.keyword("STATS") {
+                Ok(Statement::ShowStats(self.ident()?))
+            } else if self.keyword("INDEXES") || self.keyword("INDEX") {
+                if !self.keyword("IN") {
+                    self.expect_keyword("FROM")?;
+                }
+                Ok(Statement::ShowIndexes(self.ident()?))
+            } else if self.keyword("USERS") {
+                Ok(Statement::ShowUsers)
+            } else if self.keyword("TOKENS") {
+                Ok(Statement::ShowTokens)
+            } else if self.keyword("GRANTS") {
+                self.expect_keyword("FOR")?;
+                Ok(Statement::ShowGrants(self.ident()?))
+            } else if self.keyword("PROCESSLIST") {
+                Ok(Statement::ShowProcesslist)
+            } else {
+                Err(self.error("TABLES, TABLE STATUS, SEQUENCES, CREATE TABLE, DATABASES, STATS, USERS, GRANTS or PROCESSLIST"))
+            }
+        } else if self.keyword("GRANT") {
+            let (privileges, table) = self.privileges()?;
+            self.expect_keyword("TO")?;
+            Ok(Statement::Grant { privileges, table, user: self.ident()? })
+        } else if self.keyword("REVOKE") {
+            let (privileges, table) = self.privileges()?;
+            self.expect_keyword("FROM")?;
+            Ok(Statement::Revoke { privileges, table, user: self.ident()? })
+        } else if self.keyword("USE") {
+            Ok(Statement::Use(self.ident()?))
+        } else if self.keyword("INSERT") {
+            self.insert()
+        } else if self.keyword("SELECT") {
+            if let Some(sequence) = self.nextval()? {
+                return Ok(Statement::Nextval(sequence));
+            }
+            if self.call("SETVAL") {
+                let sequence = self.sequence_name()?;
+                self.expect_symbol(",")?;
+                let value = self.integer("SETVAL's value")?;
+                self.expect_symbol(")")?;
+                return Ok(Statement::Setval { sequence, value });
+            }
+            self.select()
+        } else if self.keyword("WITH") {
+            self.with()
+        } else if self.keyword("DELETE") {
+            self.expect_keyword("FROM")?;
+            let table = self.ident()?;
+            self.expect_keyword("WHERE")?;
+            let filter = join::unqualify_filter(&table, &self.conditions()?)?;
+            Ok(Statement::Delete { table, filter, returning: self.returning()? })
+        } else if self.keyword("UPDATE") {
+            self.update()
+        } else if self.keyword("UNDELETE") {
+            let (table, filter) = self.deleted_rows()?;
+            Ok(Statement::Undelete { table, filter })
+        } else if self.keyword("PURGE") {
+            let (table, filter) = self.deleted_rows()?;
+            Ok(Statement::Purge { table, filter })
+        } else if self.keyword("EXPLAIN") {
+            let analyze = self.keyword("ANALYZE");
+            let statement = self.nested(Parser::statement)?;
+            if analyze && !matches!(statement, Statement::Select { .. }) {
+                // Running a DELETE to time it would delete the rows
+                return Err(DbError::Syntax("EXPLAIN ANALYZE only supports SELECT".to_string()));
+            }
+            if !matches!(statement, Statement::Select { .. } | Statement::Delete { .. }) {
+                return Err(DbError::Syntax("EXPLAIN only supports SELECT and DELETE".to_string()));
+            }
+            Ok(Statement::Explain { statement: Box::new(statement), analyze })
+        } else if self.keyword("DECLARE") {
+            let name = self.ident()?;
+            self.expect_keyword("CURSOR")?;
+            self.expect_keyword("FOR")?;
+            let query = self.nested(Parser::statement)?;
+            if !matches!(query, Statement::Select { .. }) {
+                return Err(DbError::Syntax("a cursor is declared FOR a SELECT".to_string()));
+            }
+            Ok(Statement::Declare { name, query: Box::new(query) })
+        } else if self.keyword("FETCH") {
+            self.fetch()
+        } else if self.keyword("CLOSE") {
+            if self.keyword("ALL") {
+                return Ok(Statement::Close(None));
+            }
+            Ok(Statement::Close(Some(self.ident()?)))
+        } else if self.keyword("ANALYZE") {
+            Ok(Statement::Analyze(self.ident()?))
+        } else if self.keyword("COMMENT") {
+            self.expect_keyword("ON")?;
+            let (table, column) = if self.keyword("COLUMN") {
+                let table = self.ident()?;
+                self.expect_symbol(".")?;
+                (table, Some(self.ident()?))
+            } else {
+                self.expect_keyword("TABLE")?;
+                (self.ident()?, None)
+            };
+            self.expect_keyword("IS")?;
+            let text = match self.keyword("NULL") {
+                true => None,
+                false => Some(self.string()?),
+            };
+            Ok(Statement::Comment { table, column, text })
+        } else if self.keyword("DESCRIBE") {
+            Ok(Statement::Describe(self.ident()?))
+        } else if self.keyword("REINDEX") {
+            self.keyword("TABLE");
+            Ok(Statement::Reindex(self.ident()?))
+        } else if self.keyword("COUNT") {
+            Ok(Statement::Count(self.ident()?))
+        } else if self.keyword("BEGIN") || self.keyword("START") {
+            self.keyword("TRANSACTION");
+            Ok(Statement::Begin)
+        } else if self.keyword("COMMIT") {
+            Ok(Statement::Commit)
+        } else if self.keyword("ROLLBACK") {
+            if self.keyword("TO") {
+                self.keyword("SAVEPOINT");
+                return Ok(Statement::RollbackTo(self.ident()?));
+            }
+            Ok(Statement::Rollback)
+        } else if self.keyword("SAVEPOINT") {
+            Ok(Statement::Savepoint(self.ident()?))
+        } else if self.keyword("RELEASE") {
+            self.keyword("SAVEPOINT");
+            Ok(Statement::Release(self.ident()?))
+        } else if self.keyword("REFRESH") {
+            if !self.keyword("MATERIALIZED") {
+                self.keyword("TABLE");
+                return Ok(Statement::RefreshTable(self.ident()?));
+            }
+            self.expect_keyword("VIEW")?;
+            Ok(Statement::RefreshView(self.ident()?))
+        } else if self.keyword("CHECKPOINT") {
+            Ok(Statement::Checkpoint)
+        } else if self.keyword("PROMOTE") {
+            Ok(Statement::Promote)
+        } else if self.keyword("KILL") {
+            let id = self.value()?;
+            id.parse().map(Statement::Kill).map_err(|_| DbError::Syntax(format!("KILL takes a session id, not '{}'", id)))
+        } else if self.keyword("SUBSCRIBE") {
+            self.expect_keyword("TO")?;
+            Ok(Statement::Subscribe(self.ident()?))
+        } else if self.keyword("FLUSH") {
+            Ok(Statement::Flush)
+        } else if self.keyword("VACUUM") {
+            if self.at_end() {
+                return Ok(Statement::Vacuum(None));
+            }
+            Ok(Statement::Vacuum(Some(self.ident()?)))
+        } else if self.keyword("SET") {
+            if self.symbol("@") {
+                let name = self.ident()?;
+                if !self.symbol("=") {
+                    self.expect_keyword("TO")?;
+                }
+                return Ok(Statement::SetVariable { name, value: self.expr()? });
+            }
+            if self.keyword("STATEMENT_TIMEOUT") {
+                if !self.symbol("=") {
+                    self.expect_keyword("TO")?;
+                }
+                let ms = self.value()?;
+                return ms.parse().map(Statement::SetStatementTimeout).map_err(|_| {
+                    DbError::Syntax(format!("statement_timeout is a number of milliseconds, not '{}'", ms))
+                });
+            }
+            if self.keyword("AUTOCOMMIT") {
+                if !self.symbol("=") {
+                    self.expect_keyword("TO")?;
+                }
+                let on = self.keyword("ON");
+                if !on {
+                    self.expect_keyword("OFF")?;
+                }
+                return Ok(Statement::SetAutocommit(on));
+            }
+            if self.keyword("SYNCHRONOUS") {
+                if !self.symbol("=") {
+                    self.expect_keyword("TO")?;
+                }
+                let name = self.ident()?;
+                return Synchronous::parse(&name).map(Statement::SetSynchronous).ok_or_else(|| {
+                    DbError::Syntax(format!("synchronous is full or batched, not '{}'", name))
+                });
+            }
+            if self.keyword("WAL") {
+                self.expect_keyword("ARCHIVE")?;
+                if self.keyword("OFF") {
+                    return Ok(Statement::SetWalArchive(None));
+                }
+                return Ok(Statement::SetWalArchive(Some(self.string()?)));
+            }
+            self.expect_keyword("COMPRESSION")?;
+            Ok(Statement::SetCompression(self.ident()?))
+        } else if self.keyword("REKEY") {
+            if self.keyword("OFF") {
+                return Ok(Statement::Rekey(None));
+            }
+            Ok(Statement::Rekey(Some(self.string()?)))
+        } else if self.keyword("IMPORT") {
+            self.import()
+        } else if self.keyword("COPY") {
+            self.copy()
+        } else if self.keyword("EXPORT") {
+            self.export()
+        } else if self.keyword("DUMP") {
+            self.expect_keyword("DATABASE")?;
+            self.expect_keyword("TO")?;
+            Ok(Statement::Dump(self.string()?))
+        } else if self.keyword("BACKUP") {
+            self.expect_keyword("DATABASE")?;
+            self.expect_keyword("TO")?;
+            Ok(Statement::Backup(self.string()?))
+        } else if self.keyword("RESTORE") {
+            self.expect_keyword("DATABASE")?;
+            self.expect_keyword("FROM")?;
+            let path = self.string()?;
+            if !self.keyword("UNTIL") {
+                return Ok(Statement::Restore { path, until: None });
+            }
+            let text = self.string()?;
+            let until = time::parse_timestamp(&text).ok_or_else(|| {
+                DbError::Syntax(format!("'{}' is not a time; use 'YYYY-MM-DD HH:MM:SS' (UTC)", text))
+            })?;
+            Ok(Statement::Restore { path, until: Some(until) })
+        } else if self.keyword("MIGRATE") {
+            if self.at_end() {
+                return Ok(Statement::Migrate(None));
+            }
+            Ok(Statement::Migrate(Some(self.string()?)))
+        } else if self.keyword("SOURCE") {
+            let path = self.string()?;
+            let on_error = if self.keyword("CONTINUE") {
+                self.expect_keyword("ON")?;
+                self.expect_keyword("ERROR")?;
+                OnError::Continue
+            } else if self.keyword("SINGLE") {
+                self.expect_keyword("TRANSACTION")?;
+                OnError::RollBack
+            } else {
+                OnError::Stop
+            };
+            Ok(Statement::Source { path, on_error })
+        } else if self.keyword("HELP") {
+            Ok(Statement::Help)
+        } else if self.keyword("EXIT") {
+            Ok(Statement::Exit)
+        } else {
+            Err(self.error("a command"))
+        }
+    }
+
+    fn create(&mut self) -> Result<Statement, DbError> {
+        if self.keyword("DATABASE") {
+            return Ok(Statement::CreateDatabase(self.ident()?));
+        }
+        if self.keyword("USER") {
+            let name = self.ident()?;
+            self.expect_keyword("PASSWORD")?;
+            let password = self.value()?;
+            let superuser = self.keyword("SUPERUSER");
+            return Ok(Statement::CreateUser { name, password, superuser });
+        }
+        if self.keyword("TOKEN") {
+            let name = self.ident()?;
+            self.expect_keyword("FOR")?;
+            return Ok(Statement::CreateToken { name, user: self.ident()? });
+        }
+        let materialized = self.keyword("MATERIALIZED");
+        if materialized || self.keyword("VIEW") {
+            if materialized {
+                self.expect_keyword("VIEW")?;
+            }
+            let name = self.ident()?;
+            self.expect_keyword("AS")?;
+            self.expect_keyword("SELECT")?;
+            let Statement::Select { from, joins, columns, filter, order } = self.select()? else {
+                unreachable!("select() only returns SELECT");
+            };
+            if !columns.is_empty() {
+                return Err(DbError::Syntax("a view must SELECT *".to_string()));
+            }
+            if !order.group.is_empty() || !order.by.is_empty() || order.limit.is_some() {
+                return Err(DbError::Syntax("a view cannot have GROUP BY, ORDER BY or LIMIT".to_string()));
+            }
+            if !joins.is_empty() {
+                return Err(DbError::Syntax("a view must SELECT from a single table".to_string()));
+            }
+            if from.source != Source::Current {
+                return Err(DbError::Syntax("a view must read its table as it is now, not AS OF, WITH DELETED or PATH".to_string()));
+            }
+            return Ok(Statement::CreateView { name, table: from.table, filter, materialized });
+        }
+        if self.keyword("TRIGGER") {
+            return self.trigger();
+        }
+        if self.keyword("INDEX") {
+            let name = self.ident()?;
+            self.expect_keyword("ON")?;
+            let table = self.ident()?;
+            self.expect_symbol("(")?;
+            let mut columns = vec![self.ident()?];
+            while self.symbol(",") {
+                columns.push(self.ident()?);
+            }
+            self.expect_symbol(")")?;
+            let mut kind = IndexKind::default();
+            if self.keyword("USING") {
+                let method = self.ident()?;
+                kind = IndexKind::parse(&method).ok_or_else(|| {
+                    DbError::Syntax(format!("unknown index method '{}'. Use BTREE or HASH", method))
+                })?;
+            }
+            return Ok(Statement::CreateIndex { name, table, columns, kind });
+        }
+
+        if self.keyword("EXTERNAL") {
+            return self.external_table();
+        }
+        if self.keyword("SEQUENCE") {
+            let name = self.ident()?;
+            let (mut start, mut increment) = (1, 1);
+            loop {
+                if self.keyword("START") {
+                    self.keyword("WITH");
+                    start = self.integer("START")?;
+                } else if self.keyword("INCREMENT") {
+                    self.keyword("BY");
+                    increment = self.integer("INCREMENT")?;
+                    if increment == 0 {
+                        return Err(DbError::Syntax("a sequence's INCREMENT cannot be 0".to_string()));
+                    }
+                } else {
+                    return Ok(Statement::CreateSequence { name, start, increment });
+                }
+            }
+        }
+        let temp = self.keyword("TEMP") || self.keyword("TEMPORARY");
+        self.expect_keyword("TABLE")?;
+        let name = self.ident()?;
+        let mut columns = Vec::new();
+        let mut generated = Vec::new();
+        let mut primary_key = None;
+        let mut collations = Vec::new();
+        let mut sequences = Vec::new();
+        while !self.at_end() && !self.at_partition_by() && !self.at_with_option() && !self.at_engine() {
+            let column = self.ident()?;
+            if !self.symbol(":") {
+                return Err(DbError::Syntax(format!(
+                    "column '{}' format is invalid. Use name:type", column
+                )));
+            }
+            columns.push((column.clone(), self.column_type()?));
+
+            if self.keyword("COLLATE") {
+                let name = self.ident()?;
+                let collation = Collation::parse(&name).ok_or_else(|| {
+                    DbError::Syntax(format!("unknown collation '{}'. Use binary, nocase or unicode", name))
+                })?;
+                if columns.last().is_some_and(|(_, typ)| typ != "string") {
+                    return Err(DbError::Syntax(format!("only a string column can have a COLLATE, and '{}' is not one", column)));
+                }
+                collations.push((column.clone(), collation));
+            }
+            if self.keyword("DEFAULT") {
+                sequences.push((column.clone(), self.default()?));
+            }
+            // `GENERATED [ALWAYS] AS (<expr>) [STORED]`, computed and stored on every write
+            if self.keyword("GENERATED") {
+                self.keyword("ALWAYS");
+                self.expect_keyword("AS")?;
+                self.expect_symbol("(")?;
+                generated.push((column.clone(), self.expr()?));
+                self.expect_symbol(")")?;
+                if self.keyword("VIRTUAL") {
+                    return Err(DbError::Syntax("generated columns are always STORED".to_string()));
+                }
+                self.keyword("STORED");
+            }
+            if self.keyword("PRIMARY") {
+                self.expect_keyword("KEY")?;
+                if primary_key.is_some() {
+                    return Err(DbError::Syntax("a table can only have one PRIMARY KEY".to_string()));
+                }
+                primary_key = Some(column);
+            }
+        }
+        table::check_definition(&name, &columns)?;
+        let (mut ttl, mut soft_delete, mut timestamps) = (None, false, false);
+        while self.keyword("WITH") {
+            if self.keyword("SOFT") {
+                self.expect_keyword("DELETE")?;
+                soft_delete = true;
+            } else if self.keyword("TIMESTAMPS") {
+                timestamps = true;
+            } else {
+                self.expect_keyword("TTL")?;
+                ttl = Some(self.ident()?);
+            }
+        }
+        let engine = match self.keyword("ENGINE") {
+            true => self.engine()?,
+            false => Engine::default(),
+        };
+        let partition_by = match self.keyword("PARTITION") {
+            true if temp => return Err(DbError::Syntax("a temporary table cannot be partitioned".to_string())),
+            true => Some(self.partition_by()?),
+            false => None,
+        };
+        Ok(Statement::CreateTable { name, columns, generated, primary_key, temp, partition_by, ttl, soft_delete, timestamps, engine, collations, sequences })
+    }
+
+    /// After DEFAULT: `NEXTVAL('<sequence>')`, the one DEFAULT there is.
+    fn default(&mut self) -> Result<String, DbError> {
+        self.nextval()?.ok_or_else(|| self.error("NEXTVAL('<sequence>')"))
+    }
+
+    /// After ENGINE: `[=] <engine>`.
+    fn engine(&mut self) -> Result<Engine, DbError> {
+        self.symbol("=");
+        let name = self.ident()?;
+        Engine::parse(&name).ok_or_else(|| {
+            DbError::Syntax(format!("unknown engine '{}'. Use {}", name, Engine::NAMES.join(" or ")))
+        })
+    }
+
+    /// After EXTERNAL: `TABLE <name> [(]<col>:<type>[,] ...[)] LOCATION '<file>'
+    /// [DELIMITER '<char>' | DELIMITER TAB] [HEADER | NO HEADER]`.
+    fn external_table(&mut self) -> Result<Statement, DbError> {
+        self.expect_keyword("TABLE")?;
+        let name = self.ident()?;
+        let parenthesized = self.symbol("(");
+        let mut columns = Vec::new();
+        while !(self.at_end() || self.at_keyword("LOCATION") || parenthesized && self.at_symbol(")")) {
+            let column = self.ident()?;
+            if !self.symbol(":") {
+                return Err(DbError::Syntax(format!("column '{}' format is invalid. Use name:type", column)));
+            }
+            columns.push((column, self.column_type()?));
+            if parenthesized && !self.at_symbol(")") {
+                self.expect_symbol(",")?;
+            }
+        }
+        if parenthesized {
+            self.expect_symbol(")")?;
+        }
+        table::check_definition(&name, &columns)?;
+        self.expect_keyword("LOCATION")?;
+        let location = self.string()?;
+        match self.format_options(None)? {
+            Format::Csv(options) => Ok(Statement::CreateExternalTable { name, columns, location, options }),
+            _ => Err(DbError::Syntax("an external table reads a CSV file".to_string())),
+        }
+    }
+
+    /// After UNDELETE or PURGE: `FROM <table> [WHERE <condition> [AND ...]]`.
+    fn deleted_rows(&mut self) -> Result<(String, Vec<Predicate>), DbError> {
+        self.expect_keyword("FROM")?;
+        let table = self.ident()?;
+        let filter = match self.keyword("WHERE") {
+            true => join::unqualify_filter(&table, &self.conditions()?)?,
+            false => Vec::new(),
+        };
+        Ok((table, filter))
+    }
+
+    // `WITH TTL`, `WITH SOFT DELETE` or `WITH TIMESTAMPS`, rather than a column named with
+    fn at_with_option(&self) -> bool {
+        self.at_keyword("WITH")
+            && matches!(self.tokens.get(self.pos + 1), Some(Token::Ident(word)) if ["TTL", "SOFT", "TIMESTAMPS"].iter().any(|option| word.eq_ignore_ascii_case(option)))
+    }
+
+    // `ENGINE =` or `ENGINE <engine>`, rather than a column named engine
+    fn at_engine(&self) -> bool {
+        self.at_keyword("ENGINE") && !matches!(self.tokens.get(self.pos + 1), Some(Token::Symbol(":")))
+    }
+
+    // `PARTITION BY`, rather than a column named partition
+    fn at_partition_by(&self) -> bool {
+        self.at_keyword("PARTITION")
+            && matches!(self.tokens.get(self.pos + 1), Some(Token::Ident(word)) if word.eq_ignore_ascii_case("BY"))
+    }
+
+    /// After PARTITION: `BY RANGE (<col>) [(<range partition>, ...)]` or
+    /// `BY KEY (<col>) PARTITIONS <n>`, whose partitions are named p0, p1, ...
+    fn partition_by(&mut self) -> Result<PartitionBy, DbError> {
+        self.expect_keyword("BY")?;
+        let scheme = if self.keyword("RANGE") {
+            Scheme::Range
+        } else if self.keyword("KEY") || self.keyword("HASH") {
+            Scheme::Key
+        } else {
+            return Err(self.error("RANGE or KEY"));
+        };
+        self.expect_symbol("(")?;
+        let column = self.ident()?;
+        self.expect_symbol(")")?;
+
+        let mut partitions = Vec::new();
+        if scheme == Scheme::Key {
+            self.expect_keyword("PARTITIONS")?;
+            let count = self.value()?;
+            let count: usize = count.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                DbError::Syntax(format!("PARTITIONS is a number of partitions above 0, not '{}'", count))
+            })?;
+            partitions = (0..count).map(|i| (format!("p{}", i), None)).collect();
+        } else if self.symbol("(") {
+            partitions.push(self.range_partition()?);
+            while self.symbol(",") {
+                partitions.push(self.range_partition()?);
+            }
+            self.expect_symbol(")")?;
+        }
+        Ok(PartitionBy { column, scheme, partitions })
+    }
+
+    /// `PARTITION <name> VALUES LESS THAN (<value>)`, or `... THAN MAXVALUE`
+    /// for every value above the partitions before it.
+    fn range_partition(&mut self) -> Result<(String, Option<String>), DbError> {
+        self.expect_keyword("PARTITION")?;
+        let name = self.ident()?;
+        self.expect_keyword("VALUES")?;
+        self.expect_keyword("LESS")?;
+        self.expect_keyword("THAN")?;
+        if self.keyword("MAXVALUE") {
+            return Ok((name, None));
+        }
+        self.expect_symbol("(")?;
+        let below = self.value()?;
+        self.expect_symbol(")")?;
+        Ok((name, Some(below)))
+    }
+
+    /// A column's type: a name, `<name>[]` for an array of int, float or
+    /// string, `point`, or `enum(<label>, ...)` for one of a fixed set of labels, kept
+    /// as `enum(<label>,...)`.
+    fn column_type(&mut self) -> Result<String, DbError> {
+        let name = self.ident()?;
+        if self.symbol("[") {
+            self.expect_symbol("]")?;
+            return match CAST_TYPES.iter().find(|typ| name.eq_ignore_ascii_case(typ)) {
+                Some(typ) => Ok(format!("{}[]", typ)),
+                None => Err(DbError::Syntax(format!("arrays hold int, float or string, not '{}'", name))),
+            };
+        }
+        if !name.eq_ignore_ascii_case("enum") {
+            // `INT` is int; an unknown type is kept as written, for the definition check to report
+            return Ok(CAST_TYPES.iter().chain([&table::POINT])
+                .find(|typ| name.eq_ignore_ascii_case(typ))
+                .map_or(name, |typ| typ.to_string()));
+        }
+        self.expect_symbol("(")?;
+        let mut labels: Vec<String> = Vec::new();
+        loop {
+            let label = match self.next() {
+                Some(Token::Ident(label) | Token::Str(label)) => label,
+                _ => {
+                    self.pos -= 1;
+                    return Err(self.error("an enum label"));
+                }
+            };
+            if label.is_empty() || label.contains([',', '(', ')']) {
+                return Err(DbError::Syntax(format!("enum label '{}' must not be empty or hold commas or parentheses", label)));
+            }
+            if labels.contains(&label) {
+                return Err(DbError::Syntax(format!("enum label '{}' is listed twice", label)));
+            }
+            labels.push(label);
+            if !self.symbol(",") {
+                break;
+            }
+        }
+        self.expect_symbol(")")?;
+        Ok(format!("enum({})", labels.join(",")))
+    }
+
+    /// `<name> BEFORE|AFTER INSERT|UPDATE|DELETE ON <table> [FOR EACH ROW] DO '<statements>'`
+    fn trigger(&mut self) -> Result<Statement, DbError> {
+        let name = self.ident()?;
+        let timing = if self.keyword("BEFORE") {
+            Timing::Before
+        } else if self.keyword("AFTER") {
+            Timing::After
+        } else {
+            return Err(self.error("BEFORE or AFTER"));
+        };
+        let event = if self.keyword("INSERT") {
+            Event::Insert
+        } else if self.keyword("UPDATE") {
+            Event::Update
+        } else if self.keyword("DELETE") {
+            Event::Delete
+        } else {
+            return Err(self.error("INSERT, UPDATE or DELETE"));
+        };
+        self.expect_keyword("ON")?;
+        let table = self.ident()?;
+        if self.keyword("FOR") {
+            self.expect_keyword("EACH")?;
+            self.expect_keyword("ROW")?;
+        }
+        self.expect_keyword("DO")?;
+        match self.next() {
+            Some(Token::Str(body)) => Ok(Statement::CreateTrigger { name, table, timing, event, body }),
+            _ => {
+                self.pos -= 1;
+                Err(self.error("the trigger's statements in quotes"))
+            }
+        }
+    }
+
+    fn insert_value(&mut self) -> Result<InsertValue, DbError> {
+        match self.nextval()? {
+            Some(sequence) => Ok(InsertValue::Nextval(sequence)),
+            None => self.value().map(InsertValue::Literal),
+        }
+    }
+
+    /// `NEXTVAL('<sequence>')`, if it comes next: the sequence's name.
+    fn nextval(&mut self) -> Result<Option<String>, DbError> {
+        if !self.call("NEXTVAL") {
+            return Ok(None);
+        }
+        let sequence = self.sequence_name()?;
+        self.expect_symbol(")")?;
+        Ok(Some(sequence))
+    }
+
+    // `<function>(`, taken if it comes next
+    fn call(&mut self, function: &str) -> bool {
+        let found = self.at_keyword(function) && self.tokens.get(self.pos + 1) == Some(&Token::Symbol("("));
+        if found {
+            self.pos += 2;
+        }
+        found
+    }
+
+    // A sequence's name, quoted as NEXTVAL and SETVAL take it, or bare
+    fn sequence_name(&mut self) -> Result<String, DbError> {
+        match self.peek() {
+            Some(Token::Str(_)) => self.string(),
+            _ => self.ident(),
+        }
+    }
+
+    fn integer(&mut self, what: &str) -> Result<i32, DbError> {
+        let value = self.value()?;
+        value.parse().map_err(|_| DbError::Syntax(format!("{} must be an int, not '{}'", what, value)))
+    }
+
+    /// `INSERT INTO <table> [VALUES] <values> [ON CONFLICT [(<columns>)] DO NOTHING|DO UPDATE SET ...]
+    /// [RETURNING ...]`, with the values space-separated or as `(<value>, ...)`
+    fn insert(&mut self) -> Result<Statement, DbError> {
+        self.expect_keyword("INTO")?;
+        let table = self.ident()?;
+        self.keyword("VALUES");
+        let mut values = Vec::new();
+        if self.symbol("(") {
+            values.push(self.insert_value()?);
+            while self.symbol(",") {
+                values.push(self.insert_value()?);
+            }
+            self.expect_symbol(")")?;
+        } else {
+            while !self.at_end() && !self.at_keyword("ON") && !self.at_keyword("RETURNING") {
+                values.push(self.insert_value()?);
+            }
+        }
+
+        let mut on_conflict = None;
+        if self.keyword("ON") {
+            self.expect_keyword("CONFLICT")?;
+            let mut target = Vec::new();
+            if self.symbol("(") {
+                target.push(self.ident()?);
+                while self.symbol(",") {
+                    target.push(self.ident()?);
+                }
+                self.expect_symbol(")")?;
+            }
+            self.expect_keyword("DO")?;
+            let action = if self.keyword("NOTHING") {
+                ConflictAction::Nothing
+            } else if self.keyword("UPDATE") {
+                ConflictAction::Update(self.assignments()?)
+            } else {
+                return Err(self.error("NOTHING or UPDATE"));
+            };
+            on_conflict = Some(OnConflict { target, action });
+        }
+        Ok(Statement::Insert { table, values, on_conflict, returning: self.returning()? })
+    }
+
+    /// `RETURNING *` or `RETURNING <expr>, ...`, if present.
+    fn returning(&mut self) -> Result<Option<Vec<Expr>>, DbError> {
+        if self.keyword("RETURNING") { self.columns().map(Some) } else { Ok(None) }
+    }
+
+    /// `UPDATE <table> SET <column> = <value>, ... WHERE <conditions> [RETURNING ...]`
+    fn update(&mut self) -> Result<Statement, DbError> {
+        let table = self.ident()?;
+        let set = self.assignments()?;
+        if set.iter().any(|(_, value)| matches!(value, SetValue::Excluded(_))) {
+            return Err(DbError::Syntax("EXCLUDED is only available in ON CONFLICT DO UPDATE".to_string()));
+        }
+        self.expect_keyword("WHERE")?;
+        let filter = join::unqualify_filter(&table, &self.conditions()?)?;
+        Ok(Statement::Update { table, set, filter, returning: self.returning()? })
+    }
+
+    /// `SET <assignment>, ...`
+    fn assignments(&mut self) -> Result<Vec<(String, SetValue)>, DbError> {
+        self.expect_keyword("SET")?;
+        let mut set = vec![self.assignment()?];
+        while self.symbol(",") {
+            set.push(self.assignment()?);
+        }
+        Ok(set)
+    }
+
+    /// `<column> = <value>`, `<column> = EXCLUDED.<column>` or `<column> =
+    /// <expr>`. A value standing alone, bare words included, is read as
+    /// before, so `SET a = b` sets the text `b`; `SET a = (b)` copies column b.
+    fn assignment(&mut self) -> Result<(String, SetValue), DbError> {
+        let column = self.ident()?;
+        self.expect_symbol("=")?;
+        if self.at_keyword("EXCLUDED")
+            && self.tokens.get(self.pos + 1) == Some(&Token::Symbol("."))
+            && matches!(self.tokens.get(self.pos + 3), None | Some(Token::Symbol("," | ";") | Token::Ident(_)))
+        {
+            self.pos += 2;
+            return Ok((column, SetValue::Excluded(self.ident()?)));
+        }
+        let start = self.pos;
+        if let Ok(value) = self.value()
+            && matches!(self.peek(), None | Some(Token::Symbol("," | ";") | Token::Ident(_)))
+        {
+            return Ok((column, SetValue::Literal(value)));
+        }
+        self.pos = start;
+        Ok((column, SetValue::Expr(self.expr()?)))
+    }
+
+    /// `IMPORT [CSV|JSONL] '<file>' INTO <table> [<options>]`
+    fn import(&mut self) -> Result<Statement, DbError> {
+        let named = self.format_name();
+        let path = self.string()?;
+        self.expect_keyword("INTO")?;
+        let table = self.ident()?;
+        let format = self.format_options(named)?;
+        if format == Format::Parquet {
+            return Err(DbError::Syntax("PARQUET files can only be exported".to_string()));
+        }
+        Ok(Statement::Import { path, table, format })
+    }
+
+    /// `COPY <table> FROM STDIN [CSV|JSONL] [<options>]`
+    fn copy(&mut self) -> Result<Statement, DbError> {
+        let table = self.ident()?;
+        self.expect_keyword("FROM")?;
+        self.expect_keyword("STDIN")?;
+        let named = self.format_name();
+        let format = self.format_options(named)?;
+        if format == Format::Parquet {
+            return Err(DbError::Syntax("PARQUET files can only be exported".to_string()));
+        }
+        Ok(Statement::Copy { table, format, data: None })
+    }
+
+    /// `EXPORT (SELECT ...) TO '<file>' [<options>]` or `EXPORT TABLE <table> TO ...`
+    fn export(&mut self) -> Result<Statement, DbError> {
+        let query = if self.keyword("TABLE") {
+            Statement::Select {
+                from: TableRef::current(self.ident()?),
+                joins: Vec::new(),
+                columns: Vec::new(),
+                filter: Vec::new(),
+                order: Order::default(),
+            }
+        } else {
+            self.expect_symbol("(")?;
+            self.expect_keyword("SELECT")?;
+            let query = self.select()?;
+            self.expect_symbol(")")?;
+            query
+        };
+        self.expect_keyword("TO")?;
+        let path = self.string()?;
+        Ok(Statement::Export { query: Box::new(query), path, format: self.format_options(None)? })
+    }
+
+    fn format_name(&mut self) -> Option<&'static str> {
+        ["CSV", "JSONL", "PARQUET"].into_iter().find(|name| self.keyword(name))
+    }
+
+    /// `[FORMAT CSV|JSONL|PARQUET] [DELIMITER '<char>' | DELIMITER TAB] [HEADER | NO HEADER] [IGNORE UNKNOWN]`,
+    /// in any order. The delimiter and header apply to CSV, the default
+    /// format; IGNORE UNKNOWN (keys that are not columns) to JSONL.
+    fn format_options(&mut self, mut name: Option<&'static str>) -> Result<Format, DbError> {
+        let mut csv = CsvOptions::default();
+        let mut jsonl = JsonlOptions::default();
+        let mut csv_only = None;
+        while !self.at_end() {
+            if self.keyword("FORMAT") {
+                name = Some(self.format_name().ok_or_else(|| self.error("CSV, JSONL or PARQUET"))?);
+            } else if self.keyword("DELIMITER") {
+                csv_only = Some("DELIMITER");
+                csv.delimiter = if self.keyword("TAB") {
+                    '\t'
+                } else {
+                    let text = self.string()?;
+                    let mut chars = text.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(c), None) if c != '"' && c != '\n' && c != '\r' => c,
+                        _ => return Err(DbError::Syntax(format!("invalid delimiter '{}'. Use a single character", text))),
+                    }
+                };
+            } else if self.keyword("HEADER") {
+                csv_only = Some("HEADER");
+                csv.header = true;
+            } else if self.keyword("NO") {
+                self.expect_keyword("HEADER")?;
+                csv_only = Some("NO HEADER");
+                csv.header = false;
+            } else if self.keyword("IGNORE") {
+                self.expect_keyword("UNKNOWN")?;
+                jsonl.ignore_unknown = true;
+            } else {
+                return Err(self.error("FORMAT, DELIMITER, HEADER, NO HEADER or IGNORE UNKNOWN"));
+            }
+        }
+
+        if let Some(option) = csv_only && name.is_some_and(|name| name != "CSV") {
+            return Err(DbError::Syntax(format!("{} only applies to CSV", option)));
+        }
+        if jsonl.ignore_unknown && name != Some("JSONL") {
+            return Err(DbError::Syntax("IGNORE UNKNOWN only applies to JSONL".to_string()));
+        }
+        Ok(match name {
+            Some("JSONL") => Format::Jsonl(jsonl),
+            Some("PARQUET") => Format::Parquet,
+            _ => Format::Csv(csv),
+        })
+    }
+
+    fn string(&mut self) -> Result<String, DbError> {
+        match self.peek() {
+            Some(Token::Str(text)) => {
+                let text = text.clone();
+                self.pos += 1;
+                Ok(text)
+            }
+            _ => Err(self.error("a quoted string")),
+        }
+    }
+
+    /// `<privilege>, ... ON [TABLE] <table>`, where ALL [PRIVILEGES] stands for every privilege.
+    fn privileges(&mut self) -> Result<(Vec<Privilege>, String), DbError> {
+        let privileges = if self.keyword("ALL") {
+            self.keyword("PRIVILEGES");
+            Privilege::ALL.to_vec()
+        } else {
+            let mut privileges = Vec::new();
+            loop {
+                let name = self.ident()?;
+                let privilege = Privilege::parse(&name).ok_or_else(|| {
+                    DbError::Syntax(format!("unknown privilege '{}'. Use SELECT, INSERT, UPDATE, DELETE or ALL", name))
+                })?;
+                privileges.push(privilege);
+                if !self.symbol(",") {
+                    break;
+                }
+            }
+            privileges
+        };
+        self.expect_keyword("ON")?;
+        self.keyword("TABLE");
+        Ok((privileges, self.ident()?))
+    }
+
+    /// The rest of a SELECT: `*|<expr>, ... FROM <table> [[AS] <alias>]`, then
+    /// any number of `, <table> [<alias>]`, `CROSS JOIN <table> [<alias>]` or
+    /// `[INNER] JOIN <table> [<alias>] ON <conditions>`, then `[WHERE <conditions>]`.
+    fn select(&mut self) -> Result<Statement, DbError> {
+        let mut columns = self.columns()?;
+        self.expect_keyword("FROM")?;
+        let from = self.table_ref()?;
+        let mut joins = Vec::new();
+        // An inner join's ON conditions are WHERE conditions by another name
+        let mut on = Vec::new();
+        loop {
+            let inner = self.keyword("INNER");
+            if inner || self.at_keyword("JOIN") {
+                self.expect_keyword("JOIN")?;
+                joins.push(self.table_ref()?);
+                self.expect_keyword("ON")?;
+                on.extend(self.conditions()?);
+                continue;
+            }
+            if self.keyword("CROSS") {
+                self.expect_keyword("JOIN")?;
+            } else if !self.symbol(",") {
+                break;
+            }
+            joins.push(self.table_ref()?);
+        }
+        if self.keyword("WHERE") {
+            on.extend(self.conditions()?);
+        }
+        let mut filter = on;
+        let mut order = self.order()?;
+        // On a single table, `<table>.<column>` is just the column
+        if joins.is_empty() {
+            let name = from.name();
+            columns = columns.iter().map(|col| join::unqualify(name, col)).collect::<Result<_, _>>()?;
+            filter = join::unqualify_filter(name, &filter)?;
+            for expr in &mut order.group {
+                *expr = join::unqualify(name, expr)?;
+            }
+            for key in &mut order.by {
+                key.expr = join::unqualify(name, &key.expr)?;
+            }
+        }
+        Ok(Statement::Select { from, joins, columns, filter, order })
+    }
+
+    /// The rest of `WITH [RECURSIVE] <name> [(<column>, ...)] AS (SELECT ...
+    /// [UNION [ALL] SELECT ...]), ... SELECT ...`.
+    fn with(&mut self) -> Result<Statement, DbError> {
+        let recursive = self.keyword("RECURSIVE");
+        let mut ctes: Vec<Cte> = Vec::new();
+        loop {
+            let name = self.ident()?;
+            if ctes.iter().any(|cte| cte.name == name) {
+                return Err(DbError::Syntax(format!("WITH names '{}' twice", name)));
+            }
+            let mut columns = Vec::new();
+            if self.symbol("(") {
+                columns.push(self.ident()?);
+                while self.symbol(",") {
+                    columns.push(self.ident()?);
+                }
+                self.expect_symbol(")")?;
+            }
+            self.expect_keyword("AS")?;
+            self.expect_symbol("(")?;
+            self.expect_keyword("SELECT")?;
+            let query = self.select()?;
+            let mut step = None;
+            if self.keyword("UNION") {
+                if !recursive {
+                    return Err(DbError::Syntax("UNION is only supported in WITH RECURSIVE".to_string()));
+                }
+                let all = self.keyword("ALL");
+                self.expect_keyword("SELECT")?;
+                step = Some(Step { query: Box::new(self.select()?), all });
+            }
+            self.expect_symbol(")")?;
+            ctes.push(Cte { name, columns, query, step });
+            if !self.symbol(",") {
+                break;
+            }
+        }
+        self.expect_keyword("SELECT")?;
+        Ok(Statement::With { ctes, query: Box::new(self.select()?) })
+    }
+
+    /// `FETCH [<n> | NEXT | ALL] [FROM | IN] <cursor>`; one row if no count is given.
+    fn fetch(&mut self) -> Result<Statement, DbError> {
+        let count = if self.keyword("ALL") {
+            None
+        } else if let Some(Token::Number(count)) = self.peek() {
+            let count = count.clone();
+            self.pos += 1;
+            Some(count.parse().map_err(|_| {
+                DbError::Syntax(format!("FETCH takes a number of rows, not '{}'", count))
+            })?)
+        } else {
+            self.keyword("NEXT");
+            Some(1)
+        };
+        if !self.keyword("FROM") {
+            self.keyword("IN");
+        }
+        Ok(Statement::Fetch { name: self.ident()?, count })
+    }
+
+    /// `[GROUP BY <expr>, ...] [ORDER BY <expr> [ASC|DESC], ...] [LIMIT <n>]`
+    fn order(&mut self) -> Result<Order, DbError> {
+        let mut order = Order::default();
+        if self.keyword("GROUP") {
+            self.expect_keyword("BY")?;
+            order.group.push(self.expr()?);
+            while self.symbol(",") {
+                order.group.push(self.expr()?);
+            }
+        }
+        if self.keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            order.by = self.sort_keys()?;
+        }
+        if self.keyword("LIMIT") {
+            let limit = self.value()?;
+            order.limit = Some(limit.parse().map_err(|_| {
+                DbError::Syntax(format!("LIMIT takes a number of rows, not '{}'", limit))
+            })?);
+        }
+        Ok(order)
+    }
+
+    /// `<expr> [ASC|DESC], ...`
+    fn sort_keys(&mut self) -> Result<Vec<SortKey>, DbError> {
+        let mut keys = Vec::new();
+        loop {
+            let expr = self.expr()?;
+            let descending = self.keyword("DESC");
+            if !descending {
+                self.keyword("ASC");
+            }
+            keys.push(SortKey { expr, descending });
+            if !self.symbol(",") {
+                return Ok(keys);
+            }
+        }
+    }
+
+    /// `[AS] <alias>` after a table name, if there is one.
+    /// A table of a FROM list: `<table> [AS OF '<time>' | WITH DELETED]
+    /// [[AS] <alias>]`. A table read as of a time or with its soft-deleted
+    /// rows goes by its own name unless given an alias.
+    fn table_ref(&mut self) -> Result<TableRef, DbError> {
+        let table = self.ident()?;
+        if table.eq_ignore_ascii_case("PATH") && self.symbol("(") {
+            let query = self.path_query()?;
+            let alias = self.alias()?.unwrap_or_else(|| "path".to_string());
+            return Ok(TableRef { table: query.table.clone(), alias: Some(alias), source: Source::Path(query) });
+        }
+        let with_deleted = matches!(self.tokens.get(self.pos + 1), Some(Token::Ident(word)) if word.eq_ignore_ascii_case("DELETED"));
+        if with_deleted && self.keyword("WITH") {
+            self.expect_keyword("DELETED")?;
+            let alias = self.alias()?.unwrap_or_else(|| table.clone());
+            return Ok(TableRef { table, alias: Some(alias), source: Source::Deleted });
+        }
+        let as_of = matches!(self.tokens.get(self.pos + 1), Some(Token::Ident(word)) if word.eq_ignore_ascii_case("OF"));
+        if !(as_of && self.keyword("AS")) {
+            return Ok(TableRef { alias: self.alias()?, ..TableRef::current(table) });
+        }
+        self.expect_keyword("OF")?;
+        let text = self.string()?;
+        let at = time::parse_timestamp(&text).ok_or_else(|| {
+            DbError::Syntax(format!("invalid time '{}' after AS OF. Use 'YYYY-MM-DD HH:MM:SS' (UTC)", text))
+        })?;
+        let alias = self.alias()?.unwrap_or_else(|| table.clone());
+        Ok(TableRef { table, alias: Some(alias), source: Source::AsOf(at) })
+    }
+
+    /// `<table>, <from column>, <to column>, <start> [, <end>])`, after `PATH(`.
+    fn path_query(&mut self) -> Result<PathQuery, DbError> {
+        let table = self.ident()?;
+        self.expect_symbol(",")?;
+        let from = self.ident()?;
+        self.expect_symbol(",")?;
+        let to = self.ident()?;
+        self.expect_symbol(",")?;
+        let start = self.value()?;
+        let end = if self.symbol(",") { Some(self.value()?) } else { None };
+        self.expect_symbol(")")?;
+        Ok(PathQuery { table, from, to, start, end })
+    }
+
+    fn alias(&mut self) -> Result<Option<String>, DbError> {
+        if self.keyword("AS") {
+            return self.ident().map(Some);
+        }
+        match self.peek() {
+            Some(Token::Ident(word)) if !CLAUSE_KEYWORDS.iter().any(|k| word.eq_ignore_ascii_case(k)) => self.ident().map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// `*`, which is no columns, or `<expr>, ...`.
+    fn columns(&mut self) -> Result<Vec<Expr>, DbError> {
+        let mut columns = Vec::new();
+        if !self.symbol("*") {
+            columns.push(self.expr()?);
+            while self.symbol(",") {
+                columns.push(self.expr()?);
+            }
+        }
+        Ok(columns)
+    }
+
+    fn expr(&mut self) -> Result<Expr, DbError> {
+        self.nested(Parser::sum)
+    }
+
+    /// Products added to or taken from each other, left to right.
+    fn sum(&mut self) -> Result<Expr, DbError> {
+        self.operations(Parser::product, |symbol| match symbol {
+            "+" => Some(BinaryOp::Add),
+            "-" => Some(BinaryOp::Sub),
+            _ => None,
+        })
+    }
+
+    /// Terms multiplied or divided, left to right.
+    fn product(&mut self) -> Result<Expr, DbError> {
+        self.operations(Parser::term, |symbol| match symbol {
+            "*" => Some(BinaryOp::Mul),
+            "/" => Some(BinaryOp::Div),
+            _ => None,
+        })
+    }
+
+    // `operand`s joined left to right by the operators `op` knows. Each
+    // operator holds those before it, so it is a level further in.
+    fn operations(&mut self, operand: fn(&mut Parser) -> Result<Expr, DbError>, op: fn(&str) -> Option<BinaryOp>) -> Result<Expr, DbError> {
+        let depth = self.depth;
+        let mut expr = operand(self);
+        while let Ok(left) = expr {
+            let Some(op) = (match self.peek() {
+                Some(Token::Symbol(symbol)) => op(symbol),
+                _ => None,
+            }) else {
+                expr = Ok(left);
+                break;
+            };
+            self.pos += 1;
+            expr = self.deeper()
+                .and_then(|()| operand(self))
+                .map(|right| Expr::Binary { op, left: Box::new(left), right: Box::new(right) });
+        }
+        self.depth = depth;
+        expr
+    }
+
+    /// A column, a literal, `<function>(<expr>, ...)`, `CAST(<expr> AS <type>)`,
+    /// `INTERVAL <n> <unit>`, `(<expr>)`, a window function,
+    /// `<function>(<expr>, ...) OVER (...)`, or an aggregate, `COUNT(*)` or
+    /// `COUNT|SUM|AVG|MIN|MAX(<expr>)`.
+    fn term(&mut self) -> Result<Expr, DbError> {
+        let negative = self.symbol("-");
+        match self.next() {
+            Some(Token::Number(n)) => {
+                let n = if negative { format!("-{}", n) } else { n };
+                let value = if n.contains('.') {
+                    n.parse().map(DataType::Float32).ok()
+                } else {
+                    n.parse().map(DataType::Integer32).ok()
+                };
+                value.map(Expr::Literal).ok_or_else(|| DbError::Syntax(format!("invalid number '{}'", n)))
+            }
+            Some(Token::Str(s)) if !negative => Ok(Expr::Literal(DataType::String(s))),
+            Some(Token::Symbol("(")) if !negative => {
+                let expr = self.expr()?;
+                self.expect_symbol(")")?;
+                Ok(expr)
+            }
+            Some(Token::Ident(name)) if !negative && name.eq_ignore_ascii_case("INTERVAL") && matches!(self.peek(), Some(Token::Number(_))) => {
+                self.interval()
+            }
+            Some(Token::Ident(name)) if !negative => {
+                if self.symbol(".") {
+                    return Ok(Expr::Column(format!("{}.{}", name, self.ident()?)));
+                }
+                if !self.symbol("(") {
+                    return Ok(Expr::Column(name));
+                }
+                if name.eq_ignore_ascii_case("CAST") {
+                    return self.cast();
+                }
+                if name.eq_ignore_ascii_case("COUNT") && self.symbol("*") {
+                    self.expect_symbol(")")?;
+                    return Ok(Expr::Aggregate(Box::new(Aggregate { function: AggregateFunction::Count, arg: None })));
+                }
+                let mut args = Vec::new();
+                if !self.symbol(")") {
+                    args.push(self.expr()?);
+                    while self.symbol(",") {
+                        args.push(self.expr()?);
+                    }
+                    self.expect_symbol(")")?;
+                }
+                if self.keyword("OVER") {
+                    return self.over(name, args);
+                }
+                if let Some(function) = AggregateFunction::parse(&name) {
+                    let [arg] = <[Expr; 1]>::try_from(args).map_err(|args| {
+                        DbError::Syntax(format!("{} takes 1 argument, got {}", function.name(), args.len()))
+                    })?;
+                    return Ok(Expr::Aggregate(Box::new(Aggregate { function, arg: Some(arg) })));
+                }
+                Ok(Expr::Call { function: name, args })
+            }
+            _ => {
+                self.pos -= 1;
+                Err(self.error("a column, value or function call"))
+            }
+        }
+    }
+
+    /// The rest of `CAST(<expr> AS <type>)`, after the parenthesis.
+    fn cast(&mut self) -> Result<Expr, DbError> {
+        let expr = self.expr()?;
+        self.expect_keyword("AS")?;
+        let to = self.ident()?.to_ascii_lowercase();
+        if !CAST_TYPES.contains(&to.as_str()) {
+            return Err(DbError::Syntax(format!("cannot CAST to '{}'. Use int, float or string", to)));
+        }
+        self.expect_symbol(")")?;
+        Ok(Expr::Cast { expr: Box::new(expr), to })
+    }
+
+    /// The rest of `<function>(<args>) OVER ([PARTITION BY <expr>, ...]
+    /// [ORDER BY <expr> [ASC|DESC], ...])`, after OVER.
+    fn over(&mut self, name: String, args: Vec<Expr>) -> Result<Expr, DbError> {
+        let Some(function) = WindowFunction::parse(&name) else {
+            return Err(DbError::Syntax(format!("'{}' is not a window function. Use ROW_NUMBER, RANK, SUM or AVG", name)));
+        };
+        if args.len() != function.arity() {
+            return Err(DbError::Syntax(format!("{} takes {} argument(s), got {}", function.name(), function.arity(), args.len())));
+        }
+        self.expect_symbol("(")?;
+        let mut partition = Vec::new();
+        if self.keyword("PARTITION") {
+            self.expect_keyword("BY")?;
+            partition.push(self.expr()?);
+            while self.symbol(",") {
+                partition.push(self.expr()?);
+            }
+        }
+        let mut order = Vec::new();
+        if self.keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            order = self.sort_keys()?;
+        }
+        self.expect_symbol(")")?;
+        Ok(Expr::Window(Box::new(Window { function, arg: args.into_iter().next(), partition, order })))
+    }
+
+    /// The rest of `INTERVAL <n> SECOND|MINUTE|HOUR|DAY|WEEK`: that many
+    /// seconds, which is what a timestamp counts in.
+    fn interval(&mut self) -> Result<Expr, DbError> {
+        let n: i32 = self.value()?.parse().map_err(|_| DbError::Syntax("an INTERVAL is a whole number".to_string()))?;
+        let unit = self.ident()?;
+        n.checked_mul(seconds_in(&unit)?)
+            .map(|seconds| Expr::Literal(DataType::Integer32(seconds)))
+            .ok_or_else(|| DbError::Syntax(format!("INTERVAL {} {} is too long", n, unit)))
+    }
+
+    /// Whether the right side of a condition is an expression rather than a
+    /// value. A bare word is a string, so only what cannot be a value counts:
+    /// a qualified column, a function call, an INTERVAL, something in
+    /// parentheses, or a value with something added to it.
+    fn expression_follows(&self) -> bool {
+        match (self.peek(), self.tokens.get(self.pos + 1)) {
+            (Some(Token::Ident(_)), Some(Token::Symbol("." | "("))) => true,
+            (Some(Token::Ident(word)), Some(Token::Number(_))) => word.eq_ignore_ascii_case("INTERVAL"),
+            (Some(Token::Number(_) | Token::Str(_)), Some(Token::Symbol("+" | "-" | "*" | "/"))) => true,
+            (Some(Token::Symbol("(")), _) => true,
+            _ => false,
+        }
+    }
+
+    fn conditions(&mut self) -> Result<Vec<Predicate>, DbError> {
+        let mut conditions = Vec::new();
+        loop {
+            // `[NOT] EXISTS (SELECT ...)`, run before the statement
+            let negated = self.at_keyword("NOT") && self.keyword_at(self.pos + 1, "EXISTS");
+            if negated || self.at_keyword("EXISTS") && self.tokens.get(self.pos + 1) == Some(&Token::Symbol("(")) {
+                self.pos += if negated { 2 } else { 1 };
+                self.expect_symbol("(")?;
+                self.expect_keyword("SELECT")?;
+                let query = self.nested(Parser::select)?;
+                self.expect_symbol(")")?;
+                conditions.push(Predicate {
+                    left: Expr::Literal(DataType::Integer32(1)),
+                    op: if negated { CmpOp::NotIn } else { CmpOp::In },
+                    value: String::new(),
+                    against: None,
+                    subquery: Some(Subquery::Exists(Box::new(query))),
+                });
+                if !self.keyword("AND") {
+                    return Ok(conditions);
+                }
+                continue;
+            }
+            let left = self.expr()?;
+            // `<expr> BETWEEN <low> AND <high>` is `<expr> >= <low> AND <expr> <= <high>`
+            if self.keyword("BETWEEN") {
+                let low = self.value()?;
+                self.expect_keyword("AND")?;
+                conditions.push(Predicate { left: left.clone(), op: CmpOp::GtEq, value: low, against: None, subquery: None });
+                conditions.push(Predicate { left, op: CmpOp::LtEq, value: self.value()?, against: None, subquery: None });
+            } else if self.at_keyword("IN") || self.at_keyword("NOT") && self.keyword_at(self.pos + 1, "IN") {
+                conditions.push(self.in_list(left)?);
+            } else {
+                conditions.push(self.predicate(left)?);
+            }
+            if !self.keyword("AND") {
+                return Ok(conditions);
+            }
+        }
+    }
+
+    /// The rest of `<expr> [NOT] IN (<value>, ...)` or `<expr> [NOT] IN
+    /// (SELECT <column> ...)`.
+    fn in_list(&mut self, left: Expr) -> Result<Predicate, DbError> {
+        let op = if self.keyword("NOT") { CmpOp::NotIn } else { CmpOp::In };
+        self.expect_keyword("IN")?;
+        self.expect_symbol("(")?;
+        if self.keyword("SELECT") {
+            let query = self.nested(Parser::select)?;
+            self.expect_symbol(")")?;
+            return Ok(Predicate { left, op, value: String::new(), against: None, subquery: Some(Subquery::In(Box::new(query))) });
+        }
+        let mut values = vec![self.value()?];
+        while self.symbol(",") {
+            values.push(self.value()?);
+        }
+        self.expect_symbol(")")?;
+        Ok(Predicate { left, op, value: array_literal(values), against: None, subquery: None })
+    }
+
+    /// The rest of a condition on `left`.
+    fn predicate(&mut self, left: Expr) -> Result<Predicate, DbError> {
+        if self.keyword("MATCH") {
+            return Ok(Predicate { left, op: CmpOp::Match, value: self.value()?, against: None, subquery: None });
+        }
+        if self.keyword("CONTAINS") {
+            return Ok(Predicate { left, op: CmpOp::Contains, value: self.value()?, against: None, subquery: None });
+        }
+        let op = match self.next() {
+            Some(Token::Symbol("=")) => CmpOp::Eq,
+            Some(Token::Symbol("!=" | "<>")) => CmpOp::NotEq,
+            Some(Token::Symbol("<")) => CmpOp::Lt,
+            Some(Token::Symbol("<=")) => CmpOp::LtEq,
+            Some(Token::Symbol(">")) => CmpOp::Gt,
+            Some(Token::Symbol(">=")) => CmpOp::GtEq,
+            _ => {
+                self.pos -= 1;
+                return Err(self.error("a comparison operator"));
+            }
+        };
+        // `<value> = ANY(<array>)` is `<array> CONTAINS <value>`
+        if let (Some(Token::Ident(word)), Some(Token::Symbol("("))) = (self.peek(), self.tokens.get(self.pos + 1))
+            && word.eq_ignore_ascii_case("ANY")
+        {
+            let Expr::Literal(value) = left else {
+                return Err(DbError::Syntax("ANY(...) must be compared with a value".to_string()));
+            };
+            if op != CmpOp::Eq {
+                return Err(DbError::Syntax("only = ANY(...) is supported".to_string()));
+            }
+            self.pos += 2;
+            let array = self.expr()?;
+            self.expect_symbol(")")?;
+            return Ok(Predicate { left: array, op: CmpOp::Contains, value: value.to_string(), against: None, subquery: None });
+        }
+        if self.expression_follows() {
+            return Ok(Predicate { left, op, value: String::new(), against: Some(self.expr()?), subquery: None });
+        }
+        Ok(Predicate { left, op, value: self.value()?, against: None, subquery: None })
+    }
+}
@@ -0,0 +1,218 @@
+//! Partitioned tables: `PARTITION BY RANGE (<col>)` splits the rows by
+//! ranges of a column's values, `PARTITION BY KEY (<col>)` by a hash of
+//! them. Each partition is stored as a table of its own, named
+//! `<table>#<partition>`, which the parser never reads, so it has a file and
+//! log records of its own. The table's own file keeps only its definition,
+//! and it reads as the rows of its partitions one after the other. A query
+//! whose WHERE rules out a partition does not read it, and dropping a
+//! partition deletes its file.
+
+use serde::{Serialize, Deserialize};
+
+use crate::database::Database;
+use crate::error::DbError;
+use crate::parser::{CmpOp, Predicate};
+use crate::table::element_type;
+use crate::{parse_value, DataType, Table};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Scheme {
+    Range, // A partition holds the values below its bound and not below the one before's
+    Key,   // Rows are spread over a fixed number of partitions by a hash of the value
+}
+
+impl Scheme {
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            Scheme::Range => "RANGE",
+            Scheme::Key => "KEY",
+        }
+    }
+}
+
+/// How a table's rows are split, saved with the table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Partitioning {
+    pub column: String,
+    pub scheme: Scheme,
+    pub partitions: Vec<Partition>, // Of a range, in ascending order of bounds
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Partition {
+    pub name: String,
+    // The value every row of a range partition is below; None for
+    // MAXVALUE, and for key partitions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub below: Option<DataType>,
+}
+
+/// A PARTITION BY clause as written: each partition's name and bound, which
+/// is only read against the column's type once the table is created.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartitionBy {
+    pub column: String,
+    pub scheme: Scheme,
+    pub partitions: Vec<(String, Option<String>)>,
+}
+
+/// The name a partition is stored under.
+pub fn storage_name(table: &str, partition: &str) -> String {
+    format!("{}#{}", table, partition)
+}
+
+/// Whether a stored table is a partition of another.
+pub fn is_partition(name: &str) -> bool {
+    name.contains('#')
+}
+
+impl Partitioning {
+    /// The partitioning `by` describes, for `table`.
+    pub fn new(table: &Table, by: PartitionBy) -> Result<Partitioning, DbError> {
+        let typ = table.fields.get(&by.column)
+            .ok_or_else(|| DbError::ColumnNotFound { table: table.name.clone(), column: by.column.clone() })?;
+        if element_type(typ).is_some() {
+            return Err(DbError::InvalidPartition(format!("cannot partition by '{}', an array column", by.column)));
+        }
+        let mut partitioning = Partitioning { column: by.column, scheme: by.scheme, partitions: Vec::new() };
+        for (name, below) in by.partitions {
+            partitioning.push(typ, name, below)?;
+        }
+        Ok(partitioning)
+    }
+
+    /// Adds a range partition above the others, for values below `below`
+    /// (every value left if None). `typ` is the column's type.
+    pub fn add(&mut self, typ: &str, name: String, below: Option<String>) -> Result<(), DbError> {
+        if self.scheme == Scheme::Key {
+            return Err(DbError::InvalidPartition("a KEY partitioned table keeps the partitions it was created with".to_string()));
+        }
+        self.push(typ, name, below)
+    }
+
+    fn push(&mut self, typ: &str, name: String, below: Option<String>) -> Result<(), DbError> {
+        if self.partitions.iter().any(|p| p.name == name) {
+            return Err(DbError::PartitionExists(name));
+        }
+        let below = match (self.scheme, below) {
+            (Scheme::Key, _) | (Scheme::Range, None) => None,
+            (Scheme::Range, Some(text)) => Some(parse_value(&self.column, typ, &text)?),
+        };
+        if self.scheme == Scheme::Range && let Some(last) = self.partitions.last() {
+            match (&last.below, &below) {
+                (None, _) => {
+                    return Err(DbError::InvalidPartition(format!(
+                        "partition '{}' already holds every value above the others (MAXVALUE)", last.name
+                    )));
+                }
+                (Some(last), Some(below)) if below <= last => {
+                    return Err(DbError::InvalidPartition(format!(
+                        "bounds must go up from one partition to the next, and '{}' is not above '{}'", below, last
+                    )));
+                }
+                _ => {}
+            }
+        }
+        self.partitions.push(Partition { name, below });
+        Ok(())
+    }
+
+    /// The position of the partition a row with `value` belongs in, if any
+    /// takes it.
+    pub fn route(&self, value: &DataType) -> Option<usize> {
+        match self.scheme {
+            Scheme::Range => self.partitions.iter().position(|p| p.below.as_ref().is_none_or(|below| value < below)),
+            Scheme::Key if self.partitions.is_empty() => None,
+            Scheme::Key => Some((hash(value) % self.partitions.len() as u64) as usize),
+        }
+    }
+
+    /// `route` for a whole row of `table`, failing if no partition takes it.
+    pub fn route_row(&self, table: &Table, row: &[DataType]) -> Result<usize, DbError> {
+        let position = table.columns.iter().position(|col| *col == self.column).expect("the partition column exists");
+        self.route(&row[position]).ok_or_else(|| DbError::NoPartition {
+            table: table.name.clone(),
+            value: format!("{} = {}", self.column, row[position]),
+        })
+    }
+
+    /// Positions of the partitions that may hold rows matching every
+    /// condition of `filter`. Only comparisons of the partition column with
+    /// a value rule any out. `typ` is the column's type.
+    pub fn prune(&self, typ: &str, filter: &[Predicate]) -> Vec<usize> {
+        let mut kept = vec![true; self.partitions.len()];
+        for predicate in filter.iter().filter(|p| p.column() == Some(self.column.as_str())) {
+            // A value of the wrong type fails when the query is planned
+            let Ok(value) = parse_value(&self.column, typ, &predicate.value) else { continue };
+            for (i, kept) in kept.iter_mut().enumerate() {
+                *kept &= self.may_hold(i, predicate.op, &value);
+            }
+        }
+        (0..self.partitions.len()).filter(|&i| kept[i]).collect()
+    }
+
+    fn may_hold(&self, i: usize, op: CmpOp, value: &DataType) -> bool {
+        if self.scheme == Scheme::Key {
+            return op != CmpOp::Eq || self.route(value) == Some(i);
+        }
+        let low = i.checked_sub(1).and_then(|before| self.partitions[before].below.as_ref()); // Inclusive
+        let high = self.partitions[i].below.as_ref(); // Exclusive
+        match op {
+            CmpOp::Eq => low.is_none_or(|low| low <= value) && high.is_none_or(|high| value < high),
+            CmpOp::Lt => low.is_none_or(|low| low < value),
+            CmpOp::LtEq => low.is_none_or(|low| low <= value),
+            CmpOp::Gt | CmpOp::GtEq => high.is_none_or(|high| value < high),
+            CmpOp::NotEq | CmpOp::Match | CmpOp::Contains | CmpOp::In | CmpOp::NotIn => true,
+        }
+    }
+}
+
+// FNV-1a of the value as text, so a value goes to the same partition from
+// run to run and release to release
+fn hash(value: &DataType) -> u64 {
+    value.to_string().bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3))
+}
+
+/// An empty partition of `table`, with its columns and indexes. `lsn`
+/// should be the WAL's last LSN, as for a new table.
+pub fn empty_partition(table: &Table, partition: &str, lsn: u64) -> Table {
+    let mut part = table.schema_only();
+    part.name = storage_name(&table.name, partition);
+    part.partitioning = None;
+    part.stats = None;
+    part.triggers.clear();
+    part.lsn = lsn;
+    part
+}
+
+impl Database {
+    /// Adds a range partition to `table` above its others.
+    pub fn add_partition(&mut self, table_name: &str, name: &str, below: Option<String>) -> Result<(), DbError> {
+        let mut table = self.load_table(table_name)?.clone();
+        let typ = table.partitioning.as_ref()
+            .map(|partitioning| table.fields[&partitioning.column].clone())
+            .ok_or_else(|| DbError::NotPartitioned(table_name.to_string()))?;
+        table.partitioning.as_mut().unwrap().add(&typ, name.to_string(), below)?;
+        self.save_table(&empty_partition(&table, name, self.last_lsn()))?;
+        self.save_table(&table)
+    }
+
+    /// Drops a range partition of `table`, deleting its file and the rows in
+    /// it. New rows with values it held go to the partition above it, if
+    /// there is one.
+    pub fn drop_partition(&mut self, table_name: &str, name: &str) -> Result<(), DbError> {
+        let mut definition = self.load_table(table_name)?.schema_only();
+        let partitioning = definition.partitioning.as_mut()
+            .ok_or_else(|| DbError::NotPartitioned(table_name.to_string()))?;
+        if partitioning.scheme == Scheme::Key {
+            return Err(DbError::InvalidPartition("a KEY partitioned table keeps the partitions it was created with".to_string()));
+        }
+        let position = partitioning.partitions.iter().position(|p| p.name == name)
+            .ok_or_else(|| DbError::PartitionNotFound { table: table_name.to_string(), partition: name.to_string() })?;
+        partitioning.partitions.remove(position);
+        self.drop_table(&storage_name(table_name, name))?;
+        // The table is read again from the partitions left
+        self.forget_table(table_name);
+        self.save_table(&definition)
+    }
+}
@@ -0,0 +1,94 @@
+//! Path queries over a table of edges, such as one of `parent_id` and
+//! `child_id` pairs: `FROM PATH(<table>, <from column>, <to column>, <start>
+//! [, <end>])` reads as a table of the shortest ways from `start` along the
+//! edges, one row per node reached with its `source`, `target`, number of
+//! `hops` and the `path` of nodes in between, so whether and how two rows are
+//! connected needs no WITH RECURSIVE. Without an end, every node reachable
+//! from `start` is listed, nearest first; with one, only the way to it, or
+//! no row if there is none. The start itself is listed too, with 0 hops, and
+//! a walk round a cycle stops where it began.
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::database::Database;
+use crate::error::DbError;
+use crate::interrupt;
+use crate::table::parse_value;
+use crate::{DataType, Table};
+
+/// A path query, its values as written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathQuery {
+    pub table: String,
+    pub from: String,
+    pub to: String,
+    pub start: String,
+    pub end: Option<String>,
+}
+
+impl Database {
+    /// The rows of `query`: a breadth-first walk of its table's edges.
+    pub fn paths(&mut self, query: &PathQuery) -> Result<Table, DbError> {
+        let edges = self.snapshot(&query.table)?;
+        let column = |name: &str| {
+            edges.fields.get(name).ok_or_else(|| DbError::ColumnNotFound { table: query.table.clone(), column: name.to_string() })
+        };
+        let typ = column(&query.from)?;
+        if column(&query.to)? != typ {
+            return Err(DbError::TypeMismatch { column: query.to.clone(), expected: typ.clone(), value: edges.fields[&query.to].clone() });
+        }
+        if typ.ends_with("[]") {
+            return Err(DbError::TypeMismatch { column: query.from.clone(), expected: "int, float or string".to_string(), value: typ.clone() });
+        }
+        let start = parse_value(&query.from, typ, &query.start)?;
+        let end = query.end.as_deref().map(|end| parse_value(&query.to, typ, end)).transpose()?;
+
+        let mut next: HashMap<&DataType, Vec<&DataType>> = HashMap::new();
+        for (from, to) in edges.data[&query.from].iter().zip(&edges.data[&query.to]) {
+            next.entry(from).or_default().push(to);
+        }
+        // Each node reached, with the node it was first reached from
+        let mut reached: Vec<(&DataType, Option<usize>)> = vec![(&start, None)];
+        let mut seen: HashMap<&DataType, usize> = HashMap::from([(&start, 0)]);
+        let mut queue = VecDeque::from([0]);
+        while end.as_ref().is_none_or(|end| !seen.contains_key(end))
+            && let Some(i) = queue.pop_front()
+        {
+            interrupt::check()?;
+            for &node in next.get(reached[i].0).into_iter().flatten() {
+                if !seen.contains_key(node) {
+                    seen.insert(node, reached.len());
+                    queue.push_back(reached.len());
+                    reached.push((node, Some(i)));
+                }
+            }
+        }
+
+        let element = match typ.as_str() {
+            "int" | "float" => typ.as_str(),
+            _ => "string",
+        };
+        let schema = [("source", element), ("target", element), ("hops", "int"), ("path", &format!("{}[]", element))]
+            .map(|(name, typ)| (name.to_string(), typ.to_string()))
+            .to_vec();
+        let mut table = Table::new("path", schema, None, 0, 0);
+        let targets: Vec<usize> = match &end {
+            Some(end) => seen.get(end).copied().into_iter().collect(),
+            None => (0..reached.len()).collect(),
+        };
+        for target in targets {
+            let mut path = vec![reached[target].0.clone()];
+            let mut at = target;
+            while let Some(from) = reached[at].1 {
+                path.push(reached[from].0.clone());
+                at = from;
+            }
+            path.reverse();
+            let row = [start.clone(), reached[target].0.clone(), DataType::Integer32(path.len() as i32 - 1), DataType::Array(path)];
+            for (column, value) in ["source", "target", "hops", "path"].into_iter().zip(row) {
+                table.data.get_mut(column).unwrap().push(value);
+            }
+        }
+        Ok(table)
+    }
+}
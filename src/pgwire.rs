@@ -0,0 +1,459 @@
+use std::collections::HashMap;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::TcpStream;
+use std::sync::Mutex;
+
+use rust_db::parser::{self, Statement};
+use rust_db::DbError;
+
+use crate::commands::Output;
+use crate::server::{self, Security, Shared};
+use crate::sessions;
+use crate::tls::{self, Connection, Tls};
+
+// Startup packet codes: protocol 3.0 and the encryption/cancel requests
+const PROTOCOL_V3: u32 = 196608;
+const SSL_REQUEST: u32 = 80877103;
+const GSSENC_REQUEST: u32 = 80877104;
+const CANCEL_REQUEST: u32 = 80877102;
+
+const MAX_MESSAGE: usize = 64 * 1024 * 1024;
+
+/// Names the protocol in SHOW PROCESSLIST.
+pub const PROTOCOL: &str = "postgres";
+
+// Every column is sent as `text`; clients convert as they would any string
+const TEXT_OID: i32 = 25;
+
+/// Serves one PostgreSQL client using the simple-query protocol: each
+/// statement of a query runs as if typed at the REPL.
+pub fn handle(shared: &Mutex<Shared>, id: u64, stream: TcpStream, security: &Security) -> io::Result<()> {
+    let Some((stream, params)) = startup(stream, security.tls.as_deref())? else {
+        return Ok(());
+    };
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    let mut user = None;
+    if server::auth_required(shared) {
+        if !login(shared, id, security, &params, &mut reader, &mut writer)? {
+            return Ok(());
+        }
+        user = params.get("user").cloned();
+    }
+    let mut messages = Vec::new();
+    message(&mut messages, b'R', &0i32.to_be_bytes());
+    for (name, value) in [
+        ("server_version", "14.0"),
+        ("server_encoding", "UTF8"),
+        ("client_encoding", "UTF8"),
+        ("DateStyle", "ISO, MDY"),
+        ("integer_datetimes", "on"),
+        ("standard_conforming_strings", "on"),
+    ] {
+        message(&mut messages, b'S', &[cstring(name), cstring(value)].concat());
+    }
+    message(&mut messages, b'K', &[(id as i32).to_be_bytes(), 0i32.to_be_bytes()].concat());
+    ready(&mut messages, shared, id);
+    writer.write_all(&messages)?;
+    writer.flush()?;
+
+    // After an extended-protocol message fails, the rest up to Sync is ignored
+    let mut skipping = false;
+    while let Some((tag, body)) = read_message(&mut reader)? {
+        let mut messages = Vec::new();
+        match tag {
+            b'Q' => {
+                let query = String::from_utf8_lossy(body.strip_suffix(&[0]).unwrap_or(&body)).into_owned();
+                if !simple_query(shared, id, user.as_deref(), &query, &mut messages) {
+                    writer.write_all(&messages)?;
+                    break;
+                }
+                ready(&mut messages, shared, id);
+            }
+            b'X' => break,
+            b'S' => {
+                skipping = false;
+                ready(&mut messages, shared, id);
+            }
+            b'P' | b'B' | b'D' | b'E' | b'C' | b'H' | b'F' => {
+                if !skipping {
+                    error(&mut messages, "0A000", "Only the simple query protocol is supported");
+                    skipping = true;
+                }
+            }
+            _ => {
+                error(&mut messages, "08P01", &format!("Unexpected message '{}'", tag as char));
+                ready(&mut messages, shared, id);
+            }
+        }
+        writer.write_all(&messages)?;
+        writer.flush()?;
+    }
+    writer.flush()
+}
+
+/// Asks for the password in clear text (encrypted only if the listener has
+/// TLS) and checks it against the user named in the startup packet. Where
+/// the listener accepts tokens, one of the user's access tokens does as the
+/// password.
+fn login(
+    shared: &Mutex<Shared>,
+    id: u64,
+    security: &Security,
+    params: &HashMap<String, String>,
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+) -> io::Result<bool> {
+    let mut messages = Vec::new();
+    message(&mut messages, b'R', &3i32.to_be_bytes());
+    writer.write_all(&messages)?;
+    writer.flush()?;
+
+    let password = match read_message(reader)? {
+        Some((b'p', body)) => String::from_utf8_lossy(body.strip_suffix(&[0]).unwrap_or(&body)).into_owned(),
+        _ => return Ok(false),
+    };
+    let user = params.get("user").map_or("", String::as_str);
+    let account = security.auth.allows_token()
+        .then(|| server::authenticate_token(shared, &password).filter(|account| account.name == user))
+        .flatten()
+        .or_else(|| security.auth.allows_password().then(|| server::authenticate(shared, user, &password)).flatten());
+    if let Some(account) = account {
+        sessions::login(id, user, account.superuser);
+        return Ok(true);
+    }
+    let mut messages = Vec::new();
+    error(&mut messages, "28P01", &format!("password authentication failed for user \"{}\"", user));
+    writer.write_all(&messages)?;
+    writer.flush()?;
+    Ok(false)
+}
+
+/// Reads startup packets until the protocol 3.0 one and returns the
+/// connection, encrypted if the client asked for TLS and the listener has
+/// it, with the packet's parameters. A listener with TLS refuses clients
+/// that do not ask for it. Returns `None` if the client only wanted to cancel
+/// a query, or was refused.
+fn startup(socket: TcpStream, tls: Option<&Tls>) -> io::Result<Option<(Connection, HashMap<String, String>)>> {
+    let mut stream = Connection::plain(socket.try_clone()?);
+    let mut encrypted = false;
+    loop {
+        let mut len = [0u8; 4];
+        stream.read_exact(&mut len)?;
+        let len = u32::from_be_bytes(len) as usize;
+        if !(8..=MAX_MESSAGE).contains(&len) {
+            return Err(invalid(format!("bad startup packet length {}", len)));
+        }
+        let mut body = vec![0u8; len - 4];
+        stream.read_exact(&mut body)?;
+
+        match u32::from_be_bytes([body[0], body[1], body[2], body[3]]) {
+            // The handshake follows straight after the answer
+            SSL_REQUEST if tls.is_some() && !encrypted => {
+                stream.write_all(b"S")?;
+                stream = tls::accept(socket.try_clone()?, tls)?;
+                encrypted = true;
+            }
+            SSL_REQUEST | GSSENC_REQUEST => stream.write_all(b"N")?,
+            CANCEL_REQUEST => return Ok(None),
+            PROTOCOL_V3 if tls.is_some() && !encrypted => {
+                let mut messages = Vec::new();
+                error(&mut messages, "28000", "This server requires SSL; connect with sslmode=require");
+                stream.write_all(&messages)?;
+                return Ok(None);
+            }
+            // Pairs of NUL-terminated names and values, ending with an empty name
+            PROTOCOL_V3 => {
+                let mut fields = body[4..].split(|&b| b == 0).map(|field| String::from_utf8_lossy(field).into_owned());
+                let mut params = HashMap::new();
+                while let (Some(name), Some(value)) = (fields.next(), fields.next()) {
+                    if name.is_empty() {
+                        break;
+                    }
+                    params.insert(name, value);
+                }
+                return Ok(Some((stream, params)));
+            }
+            code => return Err(invalid(format!("unsupported protocol version {}", code))),
+        }
+    }
+}
+
+fn read_message(reader: &mut impl Read) -> io::Result<Option<(u8, Vec<u8>)>> {
+    let mut tag = [0u8; 1];
+    match reader.read_exact(&mut tag) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if !(4..=MAX_MESSAGE).contains(&len) {
+        return Err(invalid(format!("bad message length {}", len)));
+    }
+    let mut body = vec![0u8; len - 4];
+    reader.read_exact(&mut body)?;
+    Ok(Some((tag[0], body)))
+}
+
+/// Runs each statement of `query` in turn, stopping at the first error.
+/// Returns false if the client asked to EXIT.
+fn simple_query(shared: &Mutex<Shared>, id: u64, user: Option<&str>, query: &str, messages: &mut Vec<u8>) -> bool {
+    let statements: Vec<&str> = parser::split_statements(query).into_iter().filter(|s| !s.trim().is_empty()).collect();
+    if statements.is_empty() {
+        message(messages, b'I', &[]);
+    }
+
+    for text in statements {
+        let statement = match parser::parse(text) {
+            Ok(statement) => statement,
+            // Drivers set session parameters on connect; there are none to set
+            Err(_) if starts_with_keyword(text, "SET") => {
+                message(messages, b'C', &cstring("SET"));
+                continue;
+            }
+            Err(e) => {
+                // PostgreSQL counts the position in characters from 1, across the whole query
+                let start = text.as_ptr() as usize - query.as_ptr() as usize;
+                let position = e.offset().map(|offset| query[..start + offset].chars().count() + 1);
+                notice_or_error(messages, b'E', "ERROR", "42601", &format!("[{}] {}", e.code(), e), position);
+                break;
+            }
+        };
+        if matches!(statement, Statement::Exit) {
+            return false;
+        }
+
+        let tag = command_tag(&statement);
+        let mut out = PgOutput { messages, rows: None, failed: false };
+        server::execute(shared, id, user, statement, text, &mut out);
+        if out.failed {
+            break;
+        }
+        let tag = match (out.rows, tag) {
+            // Rows from RETURNING are counted in the write's own tag
+            (Some(rows), "INSERT 0 1") => format!("INSERT 0 {}", rows),
+            (Some(rows), "DELETE") => format!("DELETE {}", rows),
+            (Some(rows), "UPDATE") => format!("UPDATE {}", rows),
+            (Some(rows), "FETCH") => format!("FETCH {}", rows),
+            (Some(rows), _) => format!("SELECT {}", rows),
+            (None, tag) => tag.to_string(),
+        };
+        message(messages, b'C', &cstring(&tag));
+    }
+    true
+}
+
+fn starts_with_keyword(text: &str, keyword: &str) -> bool {
+    text.split_whitespace().next().is_some_and(|word| word.eq_ignore_ascii_case(keyword))
+}
+
+fn command_tag(statement: &Statement) -> &'static str {
+    match statement {
+        Statement::CreateTable { .. } | Statement::CreateExternalTable { .. } => "CREATE TABLE",
+        Statement::CreateIndex { .. } => "CREATE INDEX",
+        Statement::DropIndex { .. } => "DROP INDEX",
+        Statement::Reindex(_) => "REINDEX",
+        Statement::DropTable { .. } => "DROP TABLE",
+        Statement::Comment { .. } => "COMMENT",
+        Statement::CreateSequence { .. } => "CREATE SEQUENCE",
+        Statement::DropSequence { .. } => "DROP SEQUENCE",
+        Statement::AddPartition { .. } | Statement::DropPartition { .. } | Statement::SetHistoryRetention { .. } | Statement::SetTtl { .. }
+        | Statement::SetSoftDelete { .. }
+        | Statement::SetTimestamps { .. }
+        | Statement::SetMaxRows { .. }
+        | Statement::SetDefault { .. }
+        | Statement::SetEngine { .. } => "ALTER TABLE",
+        Statement::CreateView { materialized: false, .. } => "CREATE VIEW",
+        Statement::CreateView { materialized: true, .. } => "CREATE MATERIALIZED VIEW",
+        Statement::DropView { .. } => "DROP VIEW",
+        Statement::RefreshView(_) => "REFRESH MATERIALIZED VIEW",
+        Statement::RefreshTable(_) => "REFRESH",
+        Statement::CreateTrigger { .. } => "CREATE TRIGGER",
+        Statement::DropTrigger { .. } => "DROP TRIGGER",
+        Statement::CreateDatabase(_) => "CREATE DATABASE",
+        Statement::DropDatabase(_) => "DROP DATABASE",
+        Statement::Insert { .. } => "INSERT 0 1",
+        Statement::Select { .. } | Statement::With { .. } | Statement::Nextval(_) | Statement::Setval { .. } => "SELECT 0",
+        Statement::Delete { .. } => "DELETE",
+        Statement::Update { .. } => "UPDATE",
+        Statement::Undelete { .. } => "UNDELETE",
+        Statement::Purge { .. } => "PURGE",
+        Statement::Declare { .. } => "DECLARE CURSOR",
+        Statement::Fetch { .. } => "FETCH",
+        Statement::Close(_) => "CLOSE CURSOR",
+        Statement::Begin => "BEGIN",
+        Statement::Commit => "COMMIT",
+        Statement::Rollback | Statement::RollbackTo(_) => "ROLLBACK",
+        Statement::Savepoint(_) => "SAVEPOINT",
+        Statement::Release(_) => "RELEASE",
+        Statement::Analyze(_) => "ANALYZE",
+        Statement::Migrate(_) => "MIGRATE",
+        Statement::Import { .. } | Statement::Export { .. } | Statement::Copy { .. } => "COPY",
+        Statement::Dump(_) => "DUMP",
+        Statement::Backup(_) => "BACKUP",
+        Statement::Restore { .. } => "RESTORE",
+        Statement::Rekey(_) => "REKEY",
+        Statement::Source { .. } => "SOURCE",
+        Statement::Promote => "PROMOTE",
+        Statement::Subscribe(_) => "SUBSCRIBE",
+        Statement::Kill(_) => "KILL",
+        Statement::Checkpoint | Statement::Flush => "CHECKPOINT",
+        Statement::Vacuum(_) => "VACUUM",
+        Statement::SetCompression(_) | Statement::SetWalArchive(_) | Statement::SetStatementTimeout(_) | Statement::SetAutocommit(_) | Statement::SetSynchronous(_) | Statement::SetVariable { .. } | Statement::Use(_) => "SET",
+        Statement::CreateUser { .. } => "CREATE ROLE",
+        Statement::DropUser(_) => "DROP ROLE",
+        Statement::CreateToken { .. } => "CREATE TOKEN",
+        Statement::DropToken(_) => "DROP TOKEN",
+        Statement::Grant { .. } => "GRANT",
+        Statement::Revoke { .. } => "REVOKE",
+        Statement::ShowTables
+        | Statement::ShowTableStatus
+        | Statement::ShowSequences
+        | Statement::ShowDatabases
+        | Statement::ShowStats(_)
+        | Statement::ShowIndexes(_)
+        | Statement::ShowCreateTable(_)
+        | Statement::Describe(_)
+        | Statement::ShowUsers
+        | Statement::ShowTokens
+        | Statement::ShowGrants(_)
+        | Statement::ShowProcesslist
+        | Statement::Count(_)
+        | Statement::Explain { .. }
+        | Statement::Help
+        | Statement::Exit => "SHOW",
+    }
+}
+
+/// Result sets become RowDescription/DataRow messages and every other line
+/// of output a notice. Only the first error is reported; PostgreSQL ends a
+/// statement at its first error, so anything after it is dropped.
+struct PgOutput<'a> {
+    messages: &'a mut Vec<u8>,
+    rows: Option<usize>,
+    failed: bool,
+}
+
+impl Output for PgOutput<'_> {
+    fn line(&mut self, text: &str) {
+        if !self.failed {
+            notice(self.messages, text);
+        }
+    }
+
+    fn error(&mut self, message: &str) {
+        if !self.failed {
+            error(self.messages, "XX000", message);
+            self.failed = true;
+        }
+    }
+
+    fn failure(&mut self, e: &DbError) {
+        if !self.failed {
+            error(self.messages, sqlstate(e), &format!("[{}] {}", e.code(), e));
+            self.failed = true;
+        }
+    }
+
+    fn rows(&mut self, columns: &[&str], _types: &[&str], rows: Vec<Vec<String>>) {
+        if self.failed {
+            return;
+        }
+        let mut description = (columns.len() as i16).to_be_bytes().to_vec();
+        for column in columns {
+            description.extend(cstring(column));
+            description.extend(0i32.to_be_bytes()); // Table OID
+            description.extend(0i16.to_be_bytes()); // Column number
+            description.extend(TEXT_OID.to_be_bytes());
+            description.extend((-1i16).to_be_bytes()); // Variable length
+            description.extend((-1i32).to_be_bytes()); // Type modifier
+            description.extend(0i16.to_be_bytes()); // Text format
+        }
+        message(self.messages, b'T', &description);
+
+        *self.rows.get_or_insert(0) += rows.len();
+        for row in rows {
+            let mut data = (row.len() as i16).to_be_bytes().to_vec();
+            for value in row {
+                data.extend((value.len() as i32).to_be_bytes());
+                data.extend(value.as_bytes());
+            }
+            message(self.messages, b'D', &data);
+        }
+    }
+}
+
+fn ready(messages: &mut Vec<u8>, shared: &Mutex<Shared>, id: u64) {
+    let status = if server::in_transaction(shared, id) { b'T' } else { b'I' };
+    message(messages, b'Z', &[status]);
+}
+
+/// The SQLSTATE drivers know an error by, for the errors that have one of
+/// their own.
+fn sqlstate(e: &DbError) -> &'static str {
+    match e {
+        DbError::Syntax(_) | DbError::SyntaxAt { .. } => "42601",
+        DbError::TypeMismatch { .. } => "22P02",
+        DbError::ColumnNotFound { .. } => "42703",
+        DbError::AmbiguousColumn(_) => "42702",
+        DbError::TableNotFound { .. } | DbError::ViewNotFound(_) => "42P01",
+        DbError::FunctionNotFound(_) => "42883",
+        DbError::CursorNotFound(_) => "34000",
+        DbError::SessionNotFound(_) | DbError::IndexNotFound(_) | DbError::TokenNotFound(_) | DbError::VariableNotFound(_) | DbError::SequenceNotFound(_) => "42704",
+        DbError::InvalidDefinition(_) => "42P16",
+        DbError::DuplicateKey { .. } => "23505",
+        DbError::NoPartition { .. } => "23514",
+        DbError::PermissionDenied(_) => "42501",
+        DbError::Interrupted | DbError::Timeout(_) => "57014",
+        DbError::MemoryLimit(_) => "53200",
+        DbError::QuotaExceeded(_) => "53100",
+        DbError::ReadOnly(_) => "25006",
+        DbError::HasDependents { .. } => "2BP01",
+        DbError::SequenceExhausted(_) => "2200H",
+        DbError::Unavailable(_) => "0A000",
+        DbError::NoMatch(_) => "02000",
+        DbError::DatabaseInUse(_) => "55006",
+        DbError::IsView(_) => "42809",
+        DbError::ReadFailed { .. } => "58030",
+        _ => "XX000",
+    }
+}
+
+fn error(messages: &mut Vec<u8>, code: &str, text: &str) {
+    notice_or_error(messages, b'E', "ERROR", code, text, None);
+}
+
+fn notice(messages: &mut Vec<u8>, text: &str) {
+    notice_or_error(messages, b'N', "NOTICE", "00000", text, None);
+}
+
+fn notice_or_error(messages: &mut Vec<u8>, tag: u8, severity: &str, code: &str, text: &str, position: Option<usize>) {
+    let position = position.map(|position| position.to_string());
+    let mut body = Vec::new();
+    let fields = [(b'S', severity), (b'V', severity), (b'C', code), (b'M', text)];
+    for (field, value) in fields.into_iter().chain(position.as_deref().map(|position| (b'P', position))) {
+        body.push(field);
+        body.extend(cstring(value));
+    }
+    body.push(0);
+    message(messages, tag, &body);
+}
+
+fn message(messages: &mut Vec<u8>, tag: u8, body: &[u8]) {
+    messages.push(tag);
+    messages.extend((body.len() as u32 + 4).to_be_bytes());
+    messages.extend(body);
+}
+
+fn cstring(text: &str) -> Vec<u8> {
+    let mut bytes = text.as_bytes().to_vec();
+    bytes.push(0);
+    bytes
+}
+
+fn invalid(reason: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
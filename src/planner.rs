@@ -0,0 +1,311 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::budget;
+use crate::collation::Collation;
+use crate::error::DbError;
+use crate::fts;
+use crate::functions::Functions;
+use crate::interrupt;
+use crate::profile;
+use crate::stats;
+use crate::index::{Index, IndexDef, IndexKind, Key};
+use crate::parser::{CmpOp, Predicate};
+use crate::table::element_type;
+use crate::vectorized;
+use crate::{parse_value, DataType, Table};
+
+/// How the candidate rows of a filtered statement are found.
+pub enum Access<'a> {
+    FullScan,
+    /// Rows whose key starts with `prefix`, all fixed by `=` conditions.
+    IndexLookup { def: &'a IndexDef, index: &'a Index, prefix: Key },
+    /// Rows whose key starts with `prefix` and whose next key column is
+    /// within `range`: a bound from below, from above or both.
+    IndexRange { def: &'a IndexDef, index: &'a Index, prefix: Key, range: Vec<(CmpOp, DataType)> },
+    FullText { def: &'a IndexDef, index: &'a Index, query: String },
+}
+
+/// The chosen access path, plus every condition of the filter with its value
+/// typed against the column. All conditions are re-checked on the candidates.
+pub struct Plan<'a> {
+    table: &'a Table,
+    access: Access<'a>,
+    conditions: Vec<(Predicate, DataType)>,
+    used: Vec<usize>, // Conditions the access path already guarantees
+    selectivity: f64, // Estimated fraction of rows the access path reads
+    functions: Functions,
+}
+
+// An access path and the positions of the conditions it guarantees
+type Choice<'a> = (Access<'a>, Vec<usize>);
+
+/// Once ANALYZE expects an index to find more than this fraction of the
+/// rows, a full scan is cheaper than going through it.
+const INDEX_SCAN_LIMIT: f64 = 0.25;
+
+pub fn plan<'a>(table: &'a Table, filter: &[Predicate], functions: &Functions) -> Result<Plan<'a>, DbError> {
+    let mut conditions = Vec::new();
+    for predicate in filter {
+        if predicate.subquery.is_some() {
+            return Err(DbError::Syntax("a subquery can only be used in the WHERE of a SELECT or DELETE".to_string()));
+        }
+        predicate.left.check(table, functions)?;
+        if let Some(against) = &predicate.against {
+            against.check(table, functions)?;
+        }
+        let value = match (predicate.op, predicate.column()) {
+            // A MATCH query is a list of words, whatever the column type. The
+            // type of an expression is only known once it has a value, row by row
+            (CmpOp::Match, _) | (_, None) => DataType::String(predicate.value.clone()),
+            (CmpOp::Contains, Some(column)) => match element_type(&table.fields[column]) {
+                Some(element) => parse_value(column, element, &predicate.value)?,
+                None => return Err(DbError::Syntax(format!("CONTAINS needs an array column, and '{}' is not one", column))),
+            },
+            (CmpOp::In | CmpOp::NotIn, Some(column)) => {
+                list(column, &table.fields[column], &predicate.value, table.collation(column))?
+            }
+            (_, Some(column)) => {
+                let value = parse_value(column, &table.fields[column], &predicate.value)?;
+                table.collation(column).key(&value).into_owned()
+            }
+        };
+        conditions.push((predicate.clone(), value));
+    }
+
+    let (access, used) = full_text(table, &conditions)
+        .or_else(|| best_index(table, &conditions).filter(|(_, used)| !too_broad(table, &conditions, used)))
+        .unwrap_or((Access::FullScan, Vec::new()));
+    let selectivity = estimate(table, &conditions, &used);
+    Ok(Plan { table, access, conditions, used, selectivity, functions: functions.clone() })
+}
+
+/// Estimated fraction of rows satisfying all of `used`, treating the
+/// conditions as independent but for bounds from below and above on the
+/// same column, which are estimated together as a range.
+fn estimate(table: &Table, conditions: &[(Predicate, DataType)], used: &[usize]) -> f64 {
+    let stats = table.stats.as_ref();
+    let mut fraction = 1.0;
+    let mut ranges: BTreeMap<&str, (Option<&DataType>, Option<&DataType>)> = BTreeMap::new();
+    for &i in used {
+        let (p, value) = &conditions[i];
+        let column = p.column().expect("access paths only use conditions on columns");
+        match p.op {
+            CmpOp::Gt | CmpOp::GtEq => ranges.entry(column).or_default().0 = Some(value),
+            CmpOp::Lt | CmpOp::LtEq => ranges.entry(column).or_default().1 = Some(value),
+            op => fraction *= stats::selectivity(stats, column, op, value),
+        }
+    }
+    for (column, (lower, upper)) in ranges {
+        fraction *= stats::range_selectivity(stats, column, lower, upper);
+    }
+    fraction
+}
+
+// Whether ANALYZE expects the access path using `used` to read so many rows
+// that a full scan would be quicker. Without statistics an index is always used
+fn too_broad(table: &Table, conditions: &[(Predicate, DataType)], used: &[usize]) -> bool {
+    table.stats.is_some() && estimate(table, conditions, used) > INDEX_SCAN_LIMIT
+}
+
+fn full_text<'a>(table: &'a Table, conditions: &[(Predicate, DataType)]) -> Option<Choice<'a>> {
+    conditions.iter()
+        .enumerate()
+        .filter(|(_, (p, _))| p.op == CmpOp::Match)
+        .find_map(|(i, (p, _))| {
+            let (def, index) = fts::fulltext_index(table, p.column()?)?;
+            Some((Access::FullText { def, index, query: p.value.clone() }, vec![i]))
+        })
+}
+
+/// Picks the index expected to read the fewest rows, using the statistics
+/// from ANALYZE when there are any, and preferring a hash index on a tie.
+fn best_index<'a>(table: &'a Table, conditions: &[(Predicate, DataType)]) -> Option<Choice<'a>> {
+    let condition = |column: &str, op: CmpOp| {
+        conditions.iter().position(|(p, _)| p.column() == Some(column) && p.op == op)
+    };
+
+    let mut best: Option<((f64, bool), Choice)> = None;
+    for (def, index) in table.index_defs.iter().zip(&table.indexes) {
+        let mut prefix = Vec::new();
+        let mut range = Vec::new();
+        let mut used = Vec::new();
+        for column in &def.columns {
+            if let Some(i) = condition(column, CmpOp::Eq) {
+                prefix.push(conditions[i].1.clone());
+                used.push(i);
+                continue;
+            }
+            if def.kind == IndexKind::BTree {
+                // At most one bound from below and one from above
+                for ops in [[CmpOp::Gt, CmpOp::GtEq], [CmpOp::Lt, CmpOp::LtEq]] {
+                    if let Some((op, i)) = ops.into_iter().find_map(|op| condition(column, op).map(|i| (op, i))) {
+                        range.push((op, conditions[i].1.clone()));
+                        used.push(i);
+                    }
+                }
+            }
+            break;
+        }
+
+        let usable = match def.kind {
+            IndexKind::BTree => !prefix.is_empty() || !range.is_empty(),
+            IndexKind::Hash => prefix.len() == def.columns.len(),
+            IndexKind::FullText => false,
+        };
+        let cost = (estimate(table, conditions, &used), def.kind != IndexKind::Hash);
+        if usable && best.as_ref().is_none_or(|(best_cost, _)| cost < *best_cost) {
+            let access = match range.is_empty() {
+                false => Access::IndexRange { def, index, prefix, range },
+                true => Access::IndexLookup { def, index, prefix },
+            };
+            best = Some((cost, (access, used)));
+        }
+    }
+    best.map(|(_, access)| access)
+}
+
+impl Plan<'_> {
+    /// Positions of the rows matching every condition, in ascending order.
+    pub fn rows(&self) -> Result<Vec<usize>, DbError> {
+        let began = profile::begin();
+        let (candidates, checked) = match &self.access {
+            Access::FullScan => self.scan(),
+            Access::IndexLookup { index, prefix, .. } => (index.lookup(prefix, &[]), Vec::new()),
+            Access::IndexRange { index, prefix, range, .. } => (index.lookup(prefix, range), Vec::new()),
+            Access::FullText { index, query, .. } => (fts::search(index, query, self.table.row_count()), Vec::new()),
+        };
+        let mut rows = Vec::new();
+        for &row in &candidates {
+            interrupt::check()?;
+            if self.matches(row, &checked)? {
+                rows.push(row);
+            }
+        }
+        budget::charge(budget::size_of_rows(1, rows.len()))?;
+        // A full scan looks at every row, those its column filters drop included
+        let (read, index) = match &self.access {
+            Access::FullScan => (self.table.row_count(), None),
+            Access::IndexLookup { def, .. } | Access::IndexRange { def, .. } | Access::FullText { def, .. } => {
+                (candidates.len(), Some(def.name.as_str()))
+            }
+        };
+        profile::record(began, || self.to_string(), Some(read), rows.len(), index);
+        Ok(rows)
+    }
+
+    /// Every row, less those failing a comparison of a numeric column with a
+    /// value, which are tested a whole column at a time. Returns the rows
+    /// left and the positions of the conditions already tested.
+    fn scan(&self) -> (Vec<usize>, Vec<usize>) {
+        let mut keep = vec![true; self.table.row_count()];
+        let mut checked = Vec::new();
+        for (i, (p, target)) in self.conditions.iter().enumerate() {
+            let Some(column) = p.column() else { continue };
+            if let Some(mask) = vectorized::compare(&self.table.data[column], p.op, target) {
+                keep.iter_mut().zip(mask).for_each(|(keep, holds)| *keep &= holds);
+                checked.push(i);
+            }
+        }
+        let rows = keep.iter().enumerate().filter(|(_, keep)| **keep).map(|(row, _)| row).collect();
+        (rows, checked)
+    }
+
+    /// Whether `row` meets every condition but those at `checked`.
+    fn matches(&self, row: usize, checked: &[usize]) -> Result<bool, DbError> {
+        for (i, (p, target)) in self.conditions.iter().enumerate() {
+            if checked.contains(&i) {
+                continue;
+            }
+            let satisfied = match (p.column(), &p.against) {
+                (Some(column), _) => {
+                    let value = &self.table.data[column][row];
+                    holds(&self.table.collation(column).key(value), p.op, target)
+                }
+                (None, Some(against)) => {
+                    let value = p.left.eval(self.table, row, &self.functions)?;
+                    holds(&value, p.op, &against.eval(self.table, row, &self.functions)?)
+                }
+                (None, None) => {
+                    let value = p.left.eval(self.table, row, &self.functions)?;
+                    let name = p.left.to_string();
+                    let target = match (p.op, element_type(value.type_name())) {
+                        (CmpOp::Match, _) => target.clone(),
+                        (CmpOp::In | CmpOp::NotIn, _) => list(&name, value.type_name(), &p.value, Collation::Binary)?,
+                        (CmpOp::Contains, Some(element)) => parse_value(&name, element, &p.value)?,
+                        (CmpOp::Contains, None) => {
+                            return Err(DbError::InvalidExpression(format!("{}: CONTAINS needs an array, not '{}'", name, value)));
+                        }
+                        _ => parse_value(&name, value.type_name(), &p.value)?,
+                    };
+                    holds(&value, p.op, &target)
+                }
+            };
+            if !satisfied {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// The values of an `IN` list compared with values of type `typ` under
+/// `collation`, as it compares them and sorted for looking them up.
+fn list(name: &str, typ: &str, raw: &str, collation: Collation) -> Result<DataType, DbError> {
+    let DataType::Array(items) = parse_value(name, &format!("{}[]", typ), raw)? else {
+        unreachable!("an array type parses to an array")
+    };
+    let mut items: Vec<DataType> = items.iter().map(|item| collation.key(item).into_owned()).collect();
+    items.sort();
+    items.dedup();
+    Ok(DataType::Array(items))
+}
+
+fn holds(value: &DataType, op: CmpOp, target: &DataType) -> bool {
+    match op {
+        CmpOp::Match => fts::matches(value, &target.to_string()),
+        CmpOp::Contains => matches!(value, DataType::Array(items) if items.contains(target)),
+        CmpOp::In => matches!(target, DataType::Array(items) if items.binary_search(value).is_ok()),
+        CmpOp::NotIn => matches!(target, DataType::Array(items) if items.binary_search(value).is_err()),
+        _ => op.test(value.cmp(target)),
+    }
+}
+
+fn join(predicates: &[&Predicate]) -> String {
+    predicates.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(" AND ")
+}
+
+impl fmt::Display for Plan<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let used: Vec<&Predicate> = self.used.iter().map(|&i| &self.conditions[i].0).collect();
+        let table = &self.table.name;
+        match &self.access {
+            Access::FullScan => write!(f, "Full scan on {} ({} rows)", table, self.table.row_count())?,
+            Access::IndexLookup { def, .. } => {
+                write!(f, "Index lookup on {} using {} ({})", table, def.name, join(&used))?
+            }
+            Access::IndexRange { def, .. } => {
+                write!(f, "Index range scan on {} using {} ({})", table, def.name, join(&used))?
+            }
+            Access::FullText { def, .. } => {
+                write!(f, "Full-text search on {} using {} ({})", table, def.name, join(&used))?
+            }
+        }
+
+        // Estimates are only worth showing once real statistics exist
+        if let (Some(stats), false) = (&self.table.stats, self.used.is_empty()) {
+            let rows = (self.selectivity * stats.rows as f64).round();
+            write!(f, " [estimated {} of {} rows]", rows, stats.rows)?;
+        }
+
+        let residual: Vec<&Predicate> = self.conditions.iter()
+            .enumerate()
+            .filter(|(i, _)| !self.used.contains(i))
+            .map(|(_, (p, _))| p)
+            .collect();
+        if !residual.is_empty() {
+            write!(f, "\n  Filter: {}", join(&residual))?;
+        }
+        Ok(())
+    }
+}
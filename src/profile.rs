@@ -0,0 +1,67 @@
+//! What EXPLAIN ANALYZE reports: the steps a query took as it ran, each
+//! with the rows it gave and the time it took. Recording is off unless a
+//! profile was started, so queries run as usual only pay for a check per
+//! step, not per row.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+static RECORDING: AtomicBool = AtomicBool::new(false);
+static STEPS: Mutex<Vec<Step>> = Mutex::new(Vec::new());
+
+/// One step of a query, in the order the steps ran.
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub name: String,          // As EXPLAIN words it, with any lines under it
+    pub read: Option<usize>,   // Rows looked at, for a step finding rows
+    pub rows: usize,           // Rows it gave the next step
+    pub index: Option<String>, // The index it went through, for a step finding rows
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}\n  Actual: ", self.name)?;
+        if let Some(read) = self.read {
+            write!(f, "{} row(s) read, ", read)?;
+        }
+        write!(f, "{} row(s) out, {:.3} ms", self.rows, self.elapsed.as_secs_f64() * 1000.0)?;
+        match (&self.index, self.read) {
+            (Some(index), _) => write!(f, ", index {} used", index),
+            (None, Some(_)) => write!(f, ", no index used"),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// Starts recording the steps of the query about to run.
+pub fn start() {
+    steps().clear();
+    RECORDING.store(true, Ordering::Relaxed);
+}
+
+/// Stops recording, returning the steps taken since `start`.
+pub fn finish() -> Vec<Step> {
+    RECORDING.store(false, Ordering::Relaxed);
+    std::mem::take(&mut *steps())
+}
+
+/// When the step about to run starts, if a profile is being recorded.
+pub(crate) fn begin() -> Option<Instant> {
+    RECORDING.load(Ordering::Relaxed).then(Instant::now)
+}
+
+/// Records a step that started at `began`, as `begin` gave it; does nothing
+/// if that was None. `name` is only worded then.
+pub(crate) fn record(began: Option<Instant>, name: impl FnOnce() -> String, read: Option<usize>, rows: usize, index: Option<&str>) {
+    if let Some(began) = began {
+        let elapsed = began.elapsed();
+        steps().push(Step { name: name(), read, rows, index: index.map(str::to_string), elapsed });
+    }
+}
+
+fn steps() -> MutexGuard<'static, Vec<Step>> {
+    STEPS.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
@@ -0,0 +1,141 @@
+//! Progress of long operations: IMPORT and COPY reading their rows, index
+//! builds (those of a large DELETE too) and VACUUM rewriting tables. Each
+//! `start`s an operation and `advance`s it as it goes, the way the loops
+//! over rows call `interrupt::check`. Once an operation has run for a second, the
+//! reporter set with `set_reporter` (the prompt's progress bar) hears how
+//! far it has got a few times a second, and once more when it ends. An
+//! operation started inside another, such as the index builds that end an
+//! IMPORT, is reported in its place until it ends. Without a reporter,
+//! nothing is kept.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// How far an operation has got, as given to the reporter.
+#[derive(Debug)]
+pub struct Progress<'a> {
+    pub operation: &'a str,
+    pub done: u64,
+    pub total: Option<u64>, // None if not known beforehand
+    pub unit: &'static str,
+    pub elapsed: Duration,
+    pub finished: bool,
+}
+
+pub type Reporter = Box<dyn Fn(&Progress) + Send>;
+
+// Operations run this long before they are reported
+const DELAY: Duration = Duration::from_secs(1);
+// And are then reported at most this often
+const INTERVAL: Duration = Duration::from_millis(200);
+
+static REPORTER: Mutex<Option<Reporter>> = Mutex::new(None);
+static REPORTING: AtomicBool = AtomicBool::new(false);
+// The running operations, innermost last
+static OPERATIONS: Mutex<Vec<Operation>> = Mutex::new(Vec::new());
+// How far the innermost operation has got, and how far it gets before the
+// time is looked at again
+static DONE: AtomicU64 = AtomicU64::new(0);
+static NEXT_CHECK: AtomicU64 = AtomicU64::new(u64::MAX);
+
+struct Operation {
+    name: String,
+    total: Option<u64>,
+    unit: &'static str,
+    step: u64, // Units between looks at the time
+    started: Instant,
+    reported: Option<Instant>,
+    outer_done: u64, // DONE of the operation this one runs inside
+}
+
+/// Sends the progress of long operations to `reporter`, or nowhere if None.
+pub fn set_reporter(reporter: Option<Reporter>) {
+    REPORTING.store(reporter.is_some(), Ordering::Relaxed);
+    *REPORTER.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = reporter;
+}
+
+/// An operation that has been started, which ends when this is dropped.
+#[must_use]
+pub(crate) struct Running {
+    active: bool,
+}
+
+/// Starts an operation of `total` `unit`s, if known, named by `name`.
+pub(crate) fn start(name: impl FnOnce() -> String, total: Option<usize>, unit: &'static str) -> Running {
+    if !REPORTING.load(Ordering::Relaxed) {
+        return Running { active: false };
+    }
+    let total = total.map(|total| total as u64);
+    let step = total.map_or(1000, |total| (total / 1000).clamp(1, 1000));
+    let operation = Operation {
+        name: name(),
+        total,
+        unit,
+        step,
+        started: Instant::now(),
+        reported: None,
+        outer_done: DONE.swap(0, Ordering::Relaxed),
+    };
+    operations().push(operation);
+    NEXT_CHECK.store(step, Ordering::Relaxed);
+    Running { active: true }
+}
+
+/// Counts `n` more units done by the innermost operation.
+pub(crate) fn advance(n: usize) {
+    if !REPORTING.load(Ordering::Relaxed) {
+        return;
+    }
+    let done = DONE.fetch_add(n as u64, Ordering::Relaxed) + n as u64;
+    if done < NEXT_CHECK.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut operations = operations();
+    let Some(operation) = operations.last_mut() else { return };
+    NEXT_CHECK.store(done + operation.step, Ordering::Relaxed);
+    let now = Instant::now();
+    let due = match operation.reported {
+        Some(reported) => now - reported >= INTERVAL,
+        None => now - operation.started >= DELAY,
+    };
+    if due {
+        operation.reported = Some(now);
+        report(operation, done, false);
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        if !self.active {
+            return;
+        }
+        let mut operations = operations();
+        let Some(operation) = operations.pop() else { return };
+        let done = DONE.swap(operation.outer_done, Ordering::Relaxed);
+        if operation.reported.is_some() {
+            report(&operation, done, true);
+        }
+        // The outer operation's time is looked at again on its next step
+        let next = operations.last().map_or(u64::MAX, |outer| operation.outer_done + outer.step);
+        NEXT_CHECK.store(next, Ordering::Relaxed);
+    }
+}
+
+fn report(operation: &Operation, done: u64, finished: bool) {
+    let progress = Progress {
+        operation: &operation.name,
+        done: operation.total.map_or(done, |total| done.min(total)),
+        total: operation.total,
+        unit: operation.unit,
+        elapsed: operation.started.elapsed(),
+        finished,
+    };
+    if let Some(reporter) = &*REPORTER.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) {
+        reporter(&progress);
+    }
+}
+
+fn operations() -> MutexGuard<'static, Vec<Operation>> {
+    OPERATIONS.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
@@ -0,0 +1,101 @@
+use std::io::{self, Read, Write};
+
+// Larger frames are refused rather than allocated
+const MAX_FRAME: u32 = 64 * 1024 * 1024;
+
+/// One message of the client/server protocol. On the wire each frame is a
+/// 4-byte big-endian length followed by that many bytes: a tag byte and the
+/// UTF-8 payload.
+///
+/// The client sends a `Query` per statement; the server answers with any
+/// number of `Output` and `Error` frames, then `Done`. A `Login` is answered
+/// the same way, with an `Error` if the credentials are refused, and so is a
+/// `Token`, which logs in with an access token instead.
+///
+/// A follower sends `Replicate` instead of queries; the leader answers with
+/// a `Change` for each change to its storage and log, for as long as the
+/// connection lasts.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Frame {
+    Login { user: String, password: String }, // Sent as `user\0password`
+    Token(String),
+    Query(String),
+    Output(String), // A line of text or a whole rendered table
+    Error(String),
+    Done,
+    Replicate,
+    Change(String), // A replication::Change as JSON
+}
+
+impl Frame {
+    fn tag(&self) -> u8 {
+        match self {
+            Frame::Login { .. } => b'L',
+            Frame::Token(_) => b'T',
+            Frame::Query(_) => b'Q',
+            Frame::Output(_) => b'O',
+            Frame::Error(_) => b'E',
+            Frame::Done => b'Z',
+            Frame::Replicate => b'R',
+            Frame::Change(_) => b'C',
+        }
+    }
+}
+
+pub fn write_frame(w: &mut impl Write, frame: &Frame) -> io::Result<()> {
+    let login;
+    let payload = match frame {
+        Frame::Login { user, password } => {
+            login = format!("{}\0{}", user, password);
+            login.as_bytes()
+        }
+        Frame::Token(text) | Frame::Query(text) | Frame::Output(text) | Frame::Error(text) | Frame::Change(text) => {
+            text.as_bytes()
+        }
+        Frame::Done | Frame::Replicate => &[],
+    };
+    let len = u32::try_from(payload.len() + 1)
+        .ok()
+        .filter(|len| *len <= MAX_FRAME)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "frame too large"))?;
+    w.write_all(&len.to_be_bytes())?;
+    w.write_all(&[frame.tag()])?;
+    w.write_all(payload)
+}
+
+/// Reads the next frame, or `None` if the peer closed the connection
+/// between frames.
+pub fn read_frame(r: &mut impl Read) -> io::Result<Option<Frame>> {
+    let mut len = [0u8; 4];
+    match r.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u32::from_be_bytes(len);
+    if len == 0 || len > MAX_FRAME {
+        return Err(invalid(format!("bad frame length {}", len)));
+    }
+
+    let mut body = vec![0u8; len as usize];
+    r.read_exact(&mut body)?;
+    let text = String::from_utf8(body.split_off(1)).map_err(|_| invalid("frame is not UTF-8".to_string()))?;
+    match body[0] {
+        b'L' => {
+            let (user, password) = text.split_once('\0').ok_or_else(|| invalid("malformed login".to_string()))?;
+            Ok(Some(Frame::Login { user: user.to_string(), password: password.to_string() }))
+        }
+        b'T' => Ok(Some(Frame::Token(text))),
+        b'Q' => Ok(Some(Frame::Query(text))),
+        b'O' => Ok(Some(Frame::Output(text))),
+        b'E' => Ok(Some(Frame::Error(text))),
+        b'Z' => Ok(Some(Frame::Done)),
+        b'R' => Ok(Some(Frame::Replicate)),
+        b'C' => Ok(Some(Frame::Change(text))),
+        tag => Err(invalid(format!("unknown frame tag {}", tag))),
+    }
+}
+
+fn invalid(reason: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
@@ -0,0 +1,249 @@
+use std::sync::Arc;
+
+use crate::aggregate::{self, Grouped};
+use crate::budget;
+use crate::database::Database;
+use crate::error::DbError;
+use crate::expr::Expr;
+use crate::fts;
+use crate::interrupt;
+use crate::functions::Functions;
+use crate::parser::{self, CmpOp, Order, Predicate, SortKey, Source, Statement, TableRef};
+use crate::planner;
+use crate::profile;
+use crate::window::with_windows;
+use crate::{DataType, Table};
+
+/// The result of a SELECT: the column headings and the rows, in order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rows {
+    pub columns: Vec<String>,
+    pub types: Vec<String>, // Of each column, as in a schema
+    pub rows: Vec<Vec<DataType>>,
+}
+
+/// A SELECT that has found its rows but not yet computed their values,
+/// which `fetch` does a batch at a time. It reads a snapshot, so later
+/// writes do not change what it gives.
+pub struct Cursor {
+    table: Arc<Table>,
+    rows: Vec<usize>, // Positions in `table`, in order
+    columns: Vec<Expr>,
+    headings: Vec<String>,
+    functions: Functions,
+    next: usize, // Index into `rows` of the next one to fetch
+}
+
+impl Cursor {
+    pub(crate) fn new(table: Arc<Table>, rows: Vec<usize>, columns: Vec<Expr>, functions: Functions) -> Cursor {
+        let headings = columns.iter().map(Expr::to_string).collect();
+        Cursor { table, rows, columns, headings, functions, next: 0 }
+    }
+
+    /// Headings to give the rows in place of the columns as written.
+    pub(crate) fn headed(self, headings: Vec<String>) -> Cursor {
+        Cursor { headings, ..self }
+    }
+
+    /// The next `count` rows, or as many as are left.
+    pub fn fetch(&mut self, count: usize) -> Result<Rows, DbError> {
+        let began = profile::begin();
+        let end = self.next.saturating_add(count).min(self.rows.len());
+        let mut rows = project(&self.table, self.rows[self.next..end].to_vec(), &self.columns, &self.functions)?;
+        profile::record(began, || format!("Output {}", self.headings.join(", ")), None, rows.rows.len(), None);
+        rows.columns = self.headings.clone();
+        self.next = end;
+        Ok(rows)
+    }
+
+    /// How many rows are yet to be fetched.
+    pub fn remaining(&self) -> usize {
+        self.rows.len() - self.next
+    }
+}
+
+impl Database {
+    /// Runs a SELECT on a table or view: the rows matching every condition
+    /// of `filter`, with the values of `columns` (every column if empty), in
+    /// `order`.
+    pub fn select(&mut self, table: &str, columns: &[Expr], filter: &[Predicate], order: &Order) -> Result<Rows, DbError> {
+        self.select_cursor(table, &Source::Current, columns, filter, order)?.fetch(usize::MAX)
+    }
+
+    /// `select`, with the rows left to be fetched, of `table` read as `source`.
+    pub fn select_cursor(&mut self, table: &str, source: &Source, columns: &[Expr], filter: &[Predicate], order: &Order) -> Result<Cursor, DbError> {
+        // A view is its table with the view's conditions added to the query's
+        let (name, filter) = match source {
+            Source::Current => self.resolve_view(table, filter)?,
+            _ => (table.to_string(), filter.to_vec()),
+        };
+        // `SELECT COUNT(*) FROM <table>` is answered without reading the rows
+        if *source == Source::Current
+            && let [count @ Expr::Aggregate(aggregate)] = columns
+            && aggregate.counts_rows()
+            && filter.is_empty()
+            && order.group.is_empty()
+            && order.by.is_empty()
+            && order.limit.is_none_or(|limit| limit > 0)
+        {
+            let rows = self.count_rows(&name)?;
+            let mut counted = Table::new(&name, vec![(count.to_string(), "int".to_string())], None, 0, 0);
+            counted.data.insert(count.to_string(), vec![DataType::Integer32(aggregate::count(rows)?)]);
+            return Ok(Cursor::new(Arc::new(counted), vec![0], columns.to_vec(), self.functions()));
+        }
+        // Output is produced from a snapshot, never from a table being
+        // written, and of a partitioned table only the partitions it may match
+        let began = profile::begin();
+        let (table, partitions) = match source {
+            Source::Current => self.snapshot_for(&name, &filter)?,
+            source => (self.snapshot_of(&name, source)?, None),
+        };
+        if let Some(partitions) = partitions {
+            let read = if partitions.is_empty() { "none".to_string() } else { partitions.join(", ") };
+            profile::record(began, || format!("Partitions of {}: {}", name, read), None, table.row_count(), None);
+        }
+        let functions = self.functions();
+
+        let columns = if columns.is_empty() {
+            table.columns.iter().cloned().map(Expr::Column).collect()
+        } else {
+            columns.to_vec()
+        };
+
+        let mut rows = planner::plan(&table, &filter, &functions)?.rows()?;
+        // Full-text results come back most relevant first
+        if let Some(search) = filter.iter().find(|p| p.op == CmpOp::Match)
+            && let Some(column) = search.column()
+        {
+            fts::rank(&table, column, &search.value, &mut rows);
+        }
+        // Aggregates go over every matching row, and window functions see
+        // every group, before any LIMIT
+        let Grouped { table, mut rows, columns, order } = aggregate::grouped(table, rows, columns, order.clone(), &functions)?;
+        let exprs: Vec<&Expr> = columns.iter().chain(order.by.iter().map(|key| &key.expr)).collect();
+        let table = with_windows(&table, &rows, &exprs, &functions)?;
+        for column in &columns {
+            column.check(&table, &functions)?;
+        }
+        sort(&table, &mut rows, &order, &functions)?;
+        Ok(Cursor::new(table, rows, columns, functions))
+    }
+
+    /// `columns` (every column if empty) of `rows`, whole rows of `table`
+    /// that a write just added, changed or removed, as RETURNING gives them.
+    pub fn returning(&mut self, table: &str, rows: Vec<Vec<DataType>>, columns: &[Expr]) -> Result<Rows, DbError> {
+        let functions = self.functions();
+        let table = self.load_table(table)?;
+        let schema = table.columns.iter().map(|col| (col.clone(), table.fields[col].clone())).collect();
+        let mut written = Table::new(&table.name, schema, None, 0, 0);
+        let count = rows.len();
+        for row in rows {
+            for (column, value) in written.columns.iter().zip(row) {
+                written.data.get_mut(column).unwrap().push(value);
+            }
+        }
+
+        let columns = if columns.is_empty() {
+            written.columns.iter().cloned().map(Expr::Column).collect()
+        } else {
+            columns.to_vec()
+        };
+        for column in &columns {
+            column.check(&written, &functions)?;
+        }
+        project(&written, (0..count).collect(), &columns, &functions)
+    }
+
+    /// Parses and runs one SELECT statement.
+    pub fn query(&mut self, sql: &str) -> Result<Rows, DbError> {
+        match parser::parse(sql)? {
+            Statement::Select { from, joins, columns, filter, order } => {
+                self.select_from(&TableRef::list(from, joins), &columns, &filter, &order)
+            }
+            Statement::With { ctes, query } => self.with(&ctes, &query),
+            _ => Err(DbError::Syntax("only SELECT statements return rows".to_string())),
+        }
+    }
+}
+
+/// Puts `rows` of `table` in `order` and keeps as many as its limit allows.
+/// Rows with equal keys keep the order they came in.
+pub(crate) fn sort(table: &Table, rows: &mut Vec<usize>, order: &Order, functions: &Functions) -> Result<(), DbError> {
+    let began = profile::begin();
+    if !order.by.is_empty() {
+        for key in &order.by {
+            key.expr.check(table, functions)?;
+        }
+        let mut keyed = rows.iter()
+            .map(|&row| {
+                interrupt::check()?;
+                let keys = order.by.iter().map(|key| sort_value(table, &key.expr, row, functions)).collect::<Result<Vec<_>, _>>()?;
+                budget::charge(budget::size_of(&keys))?;
+                Ok((keys, row))
+            })
+            .collect::<Result<Vec<_>, DbError>>()?;
+        keyed.sort_by(|(a, _), (b, _)| compare(&order.by, a, b));
+        *rows = keyed.into_iter().map(|(_, row)| row).collect();
+    }
+    if let Some(limit) = order.limit {
+        rows.truncate(limit);
+    }
+    if !order.by.is_empty() || order.limit.is_some() {
+        profile::record(began, || describe_order(order), None, rows.len(), None);
+    }
+    Ok(())
+}
+
+fn describe_order(order: &Order) -> String {
+    let keys: Vec<String> = order.by.iter()
+        .map(|key| if key.descending { format!("{} DESC", key.expr) } else { key.expr.to_string() })
+        .collect();
+    match (keys.is_empty(), order.limit) {
+        (false, Some(limit)) => format!("Sort by {} and keep {}", keys.join(", "), limit),
+        (false, None) => format!("Sort by {}", keys.join(", ")),
+        (true, limit) => format!("Keep {}", limit.unwrap_or_default()),
+    }
+}
+
+/// What `expr` sorts by in `row`. A column sorts by what is stored, so an
+/// enum in declaration order, under its collation.
+pub(crate) fn sort_value(table: &Table, expr: &Expr, row: usize, functions: &Functions) -> Result<DataType, DbError> {
+    match expr.column() {
+        Some(column) => Ok(table.collation(column).key(&table.data[column][row]).into_owned()),
+        None => expr.eval(table, row, functions),
+    }
+}
+
+/// How two rows' values for `keys` compare, the first unequal key deciding.
+pub(crate) fn compare(keys: &[SortKey], a: &[DataType], b: &[DataType]) -> std::cmp::Ordering {
+    keys.iter().zip(a.iter().zip(b))
+        .map(|(key, (a, b))| if key.descending { b.cmp(a) } else { a.cmp(b) })
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(std::cmp::Ordering::Equal)
+}
+
+/// The values of `columns` for each of `rows`, positions in `table`.
+pub(crate) fn project(table: &Table, rows: Vec<usize>, columns: &[Expr], functions: &Functions) -> Result<Rows, DbError> {
+    let rows: Vec<Vec<DataType>> = rows.into_iter()
+        .map(|row| {
+            interrupt::check()?;
+            let values = columns.iter().map(|col| col.eval(table, row, functions)).collect::<Result<Vec<_>, _>>()?;
+            budget::charge(budget::size_of(&values))?;
+            Ok(values)
+        })
+        .collect::<Result<_, DbError>>()?;
+    let types = columns.iter().enumerate().map(|(i, col)| match col {
+        Expr::Column(name) => table.fields[name].clone(),
+        Expr::Literal(value) => value.type_name().to_string(),
+        Expr::Cast { to, .. } => to.clone(),
+        Expr::Aggregate(_) => table.fields[&col.to_string()].clone(),
+        // Whatever the function or arithmetic gave, if it always gave the
+        // same type; text otherwise
+        Expr::Call { .. } | Expr::Binary { .. } | Expr::Window(_) => {
+            let mut names = rows.iter().map(|row| row[i].type_name());
+            let first = names.next().unwrap_or("string");
+            if names.all(|name| name == first) { first } else { "string" }.to_string()
+        }
+    }).collect();
+    Ok(Rows { columns: columns.iter().map(Expr::to_string).collect(), types, rows })
+}
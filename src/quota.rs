@@ -0,0 +1,59 @@
+//! Quotas, so a runaway writer cannot fill the disk: `max_database_mb` in
+//! the config file caps the bytes the whole database takes (the data
+//! directory with its log, or the database file), and `ALTER TABLE ... SET
+//! MAX ROWS <n>` the rows of one table. An INSERT or IMPORT that would go
+//! past either, or an UPDATE that would make the rows it changes take more
+//! room than the size allows, fails with `E5005` before anything is written. Deleting rows
+//! (and, for the size, a VACUUM after) or raising the limit makes room
+//! again; nothing else is held back, so that stays possible.
+
+use crate::catalog;
+use crate::database::Database;
+use crate::error::DbError;
+
+impl Database {
+    /// Bytes the database takes where it is stored.
+    pub fn database_size(&self) -> Result<u64, DbError> {
+        Ok(self.storage.size()?)
+    }
+
+    /// Checks that `rows` rows of about `bytes` bytes can be added to table
+    /// `name` without going past its row limit or the database's size limit.
+    pub(crate) fn check_quota(&mut self, name: &str, rows: usize, bytes: u64) -> Result<(), DbError> {
+        if self.is_temp(name) {
+            return Ok(());
+        }
+        let table = self.load_table(name)?;
+        if let Some(max_rows) = table.max_rows
+            && rows > 0
+            && table.row_count() + rows > max_rows
+        {
+            return Err(DbError::QuotaExceeded(format!(
+                "table '{}' holds {} row(s), and may hold {} (MAX ROWS)", name, table.row_count(), max_rows
+            )));
+        }
+        if let Some(limit) = self.max_database_bytes {
+            let size = self.database_size()?;
+            if size.saturating_add(bytes) > limit {
+                return Err(DbError::QuotaExceeded(format!(
+                    "the database takes {} byte(s), and may take {} (max_database_mb)", size, limit
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes inserts into `table` fail once it holds `max_rows` rows, or
+    /// lifts the limit if None. Rows it holds beyond a new limit are kept.
+    pub fn set_max_rows(&mut self, table_name: &str, max_rows: Option<usize>) -> Result<(), DbError> {
+        if catalog::is_system_table(table_name) {
+            return Err(DbError::SystemTable(table_name.to_string()));
+        }
+        let mut table = self.load_table(table_name)?.clone();
+        if table.external.is_some() {
+            return Err(DbError::ExternalTable(table.name));
+        }
+        table.max_rows = max_rows;
+        self.save_table(&table)
+    }
+}
@@ -0,0 +1,80 @@
+//! Where a database draws random numbers, for `RANDOM()` and `UUID()`: a
+//! generator seeded by the clock unless `Database::set_rng` gives another,
+//! such as a `SeededRng`, whose numbers come out the same on every run.
+//! Neither is fit for secrets; access tokens and keys draw from the system's
+//! secure source instead.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::time;
+
+pub trait Rng: Send + Sync {
+    /// The next 64 random bits.
+    fn next_u64(&self) -> u64;
+}
+
+/// A xorshift64* generator shared by the process, seeded by the clock on first use.
+pub struct SystemRng;
+
+static STATE: AtomicU64 = AtomicU64::new(0);
+
+impl Rng for SystemRng {
+    fn next_u64(&self) -> u64 {
+        let step = |x: u64| {
+            let seed = match x {
+                0 => time::epoch_nanos() as u64 | 1,
+                x => x,
+            };
+            xorshift(seed)
+        };
+        let previous = STATE.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |x| Some(step(x))).unwrap_or_default();
+        scramble(step(previous))
+    }
+}
+
+/// A xorshift64* generator giving the same numbers for the same seed.
+pub struct SeededRng {
+    state: AtomicU64,
+}
+
+impl SeededRng {
+    pub fn new(seed: u64) -> SeededRng {
+        // Zero would stay zero
+        SeededRng { state: AtomicU64::new(seed.max(1)) }
+    }
+}
+
+impl Rng for SeededRng {
+    fn next_u64(&self) -> u64 {
+        let previous = self.state.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |x| Some(xorshift(x))).unwrap_or_default();
+        scramble(xorshift(previous))
+    }
+}
+
+// The high bits of a state are poor after a small seed; multiplying them in
+// (xorshift64*) fixes that
+fn scramble(x: u64) -> u64 {
+    x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+}
+
+fn xorshift(mut x: u64) -> u64 {
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^ (x << 17)
+}
+
+/// A number from 0 up to but not including 1.
+pub fn float(rng: &dyn Rng) -> f32 {
+    // The top 24 bits, as many as an f32 holds exactly
+    (rng.next_u64() >> 40) as f32 / (1u32 << 24) as f32
+}
+
+/// A random (version 4) UUID, written `xxxxxxxx-xxxx-4xxx-yxxx-xxxxxxxxxxxx`.
+pub fn uuid(rng: &dyn Rng) -> String {
+    let high = rng.next_u64() & !0xf000 | 0x4000; // Version 4
+    let low = rng.next_u64() & !(0b11 << 62) | (0b10 << 62); // RFC 4122 variant
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        high >> 32, (high >> 16) & 0xffff, high & 0xffff, low >> 48, low & 0xffff_ffff_ffff
+    )
+}
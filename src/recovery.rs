@@ -0,0 +1,93 @@
+use crate::database::Database;
+use crate::error::DbError;
+use crate::storage;
+use crate::wal::{WalOp, WalRecord};
+
+const QUARANTINE_PREFIX: &str = ".quarantine/";
+
+/// Brings the database back to a consistent state after an unclean shutdown.
+/// Returns a human-readable line for every repair made. A database opened
+/// read-only is left as it is, for its owner to recover.
+pub fn recover(db: &mut Database) -> Result<Vec<String>, DbError> {
+    let mut report = Vec::new();
+    if db.is_read_only() {
+        return Ok(report);
+    }
+
+    for path in db.storage.discard_torn_writes()? {
+        report.push(format!("Discarded torn write {}", path));
+    }
+
+    let discarded = db.wal.repair()?;
+    if discarded > 0 {
+        report.push(format!("Discarded {} byte(s) of incomplete WAL record", discarded));
+    }
+
+    let records: Vec<WalRecord> = db.wal.records()?.into_iter()
+        .filter(|record| !matches!(record.op, WalOp::Checkpoint))
+        .collect();
+    let mut quarantined = Vec::new();
+    for name in db.stored_names()? {
+        if let Err(DbError::CorruptTable { reason, .. }) = db.read_table_file(&name) {
+            let n = quarantine(db, &name)?;
+            report.push(format!(
+                "Quarantined unreadable table '{}' as {} ({})", name, target(&storage::table_key(&name), n), reason
+            ));
+            // The checkpoint below would drop the table's records with the
+            // log, so they are kept beside the file they belong to
+            let kept: Vec<WalRecord> = records.iter()
+                .filter(|record| record.op.tables().contains(&name.as_str()))
+                .cloned()
+                .collect();
+            if !kept.is_empty() {
+                let key = target(&format!("{}.wal", name), n);
+                let bytes = db.wal.encode(&kept)?;
+                db.storage.write(&key, &bytes)?;
+                report.push(format!("Kept {} WAL record(s) of '{}' in {}", kept.len(), name, key));
+            }
+            quarantined.push(name);
+        }
+    }
+
+    // Records that only touch quarantined tables are not replayed
+    let replayed = records.iter()
+        .filter(|record| record.op.tables().iter().any(|table| !quarantined.iter().any(|name| name == table)))
+        .count();
+    if db.wal.pending() > 0 {
+        db.checkpoint()?;
+    }
+    if replayed > 0 {
+        report.push(format!("Replayed {} WAL record(s) into table files", replayed));
+    }
+    Ok(report)
+}
+
+// Moves the file of table `name`, its saved indexes and the rows appended
+// beside it into the quarantine. Returns the number they are kept under, 0
+// for the first.
+fn quarantine(db: &mut Database, name: &str) -> Result<usize, DbError> {
+    let (key, index_key) = (storage::table_key(name), storage::index_key(name));
+    let mut n = 0;
+    while db.storage.exists(&target(&key, n)) {
+        n += 1;
+    }
+    db.storage.rename(&key, &target(&key, n))?;
+    if db.storage.exists(&index_key) {
+        db.storage.rename(&index_key, &target(&index_key, n))?;
+    }
+    let mut blob = 0;
+    while db.storage.exists(&storage::append_key(name, blob)) {
+        let key = storage::append_key(name, blob);
+        db.storage.rename(&key, &target(&key, n))?;
+        blob += 1;
+    }
+    Ok(n)
+}
+
+// Where `key` is kept in the quarantine under number `n`
+fn target(key: &str, n: usize) -> String {
+    match n {
+        0 => format!("{}{}", QUARANTINE_PREFIX, key),
+        n => format!("{}{}.{}", QUARANTINE_PREFIX, key, n),
+    }
+}
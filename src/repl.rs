@@ -0,0 +1,567 @@
+use std::cell::RefCell;
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::{CompletionType, Config, Editor};
+use serde_json::Value;
+use terminal_size::{terminal_size, Height};
+
+use rust_db::csv;
+use rust_db::dump;
+use rust_db::interrupt;
+use rust_db::parser::{self, Statement};
+use rust_db::progress::{self, Progress};
+use rust_db::variables::{self, Variables};
+use rust_db::DbError;
+
+use crate::commands::{render, Engine, Located, Output};
+use crate::completion::Completion;
+
+// Used when $PAGER is not set: quit at once if the text fits after all,
+// and scroll wide tables sideways instead of wrapping them
+const DEFAULT_PAGER: &str = "less -FSX";
+
+// In the home directory, shared by the local prompt and `connect`
+const HISTORY_FILE: &str = ".rustdb_history";
+
+// Ends the rows typed or piped after COPY ... FROM STDIN, as in psql
+const END_OF_ROWS: &str = "\\.";
+
+// Characters in a progress bar
+const PROGRESS_WIDTH: usize = 30;
+
+/// How the prompt prints result sets, chosen with `\format`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum RowFormat {
+    #[default]
+    Table,
+    Csv,
+    Json,     // An array of objects, every value a string
+    Vertical, // One line per column, for wide rows
+}
+
+impl RowFormat {
+    pub fn parse(name: &str) -> Option<RowFormat> {
+        match name.to_ascii_lowercase().as_str() {
+            "table" => Some(RowFormat::Table),
+            "csv" => Some(RowFormat::Csv),
+            "json" => Some(RowFormat::Json),
+            "vertical" => Some(RowFormat::Vertical),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RowFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RowFormat::Table => write!(f, "table"),
+            RowFormat::Csv => write!(f, "csv"),
+            RowFormat::Json => write!(f, "json"),
+            RowFormat::Vertical => write!(f, "vertical"),
+        }
+    }
+}
+
+/// The terminal. Result sets taller than the window go through a pager,
+/// unless it is turned off with `\pager off` or output is not a terminal.
+/// After `\o <file>` result sets are written to the file instead.
+pub struct Stdout {
+    pub format: RowFormat,
+    pub pager: bool,
+    pub stderr: bool,                 // Errors go to stderr rather than among the results
+    pub timing: bool,                 // Whether `\timing` is on
+    pub output: Option<(String, File)>, // The file `\o` named, and its path
+}
+
+impl Default for Stdout {
+    fn default() -> Stdout {
+        Stdout { format: RowFormat::default(), pager: true, stderr: false, timing: false, output: None }
+    }
+}
+
+impl Stdout {
+    /// For scripts and pipes: CSV unless `format` says otherwise, no
+    /// pager, and errors kept apart from the results.
+    pub fn batch(format: Option<RowFormat>) -> Stdout {
+        Stdout { format: format.unwrap_or(RowFormat::Csv), pager: false, stderr: true, timing: false, output: None }
+    }
+}
+
+impl Output for Stdout {
+    fn line(&mut self, text: &str) {
+        println!("{}", text);
+    }
+
+    fn error(&mut self, message: &str) {
+        if self.stderr {
+            eprintln!("Error: {}", message);
+        } else {
+            println!("Error: {}", message);
+        }
+    }
+
+    fn rows(&mut self, columns: &[&str], types: &[&str], rows: Vec<Vec<String>>) {
+        let text = match self.format {
+            RowFormat::Table => {
+                let table = render(columns, types, rows);
+                let text = table.to_string();
+                if !self.write_out(&text) && !self.page(&text) {
+                    // Printed again so the header keeps its colors
+                    table.printstd();
+                }
+                return;
+            }
+            RowFormat::Csv => {
+                let mut text = String::new();
+                csv::write_record(&mut text, columns, ',');
+                for row in &rows {
+                    csv::write_record(&mut text, row, ',');
+                }
+                text.replace("\r\n", "\n")
+            }
+            RowFormat::Json => {
+                let objects: Vec<String> = rows.into_iter()
+                    .map(|row| {
+                        // Written by hand to keep the keys in column order
+                        let fields: Vec<String> = columns.iter().zip(row)
+                            .map(|(col, value)| format!("{}: {}", Value::from(*col), Value::from(value)))
+                            .collect();
+                        format!("  {{{}}}", fields.join(", "))
+                    })
+                    .collect();
+                if objects.is_empty() {
+                    "[]\n".to_string()
+                } else {
+                    format!("[\n{}\n]\n", objects.join(",\n"))
+                }
+            }
+            RowFormat::Vertical => {
+                let width = columns.iter().map(|col| col.chars().count()).max().unwrap_or(0);
+                let mut text = String::new();
+                for (i, row) in rows.iter().enumerate() {
+                    text.push_str(&format!("-[ RECORD {} ]-\n", i + 1));
+                    for (col, value) in columns.iter().zip(row) {
+                        text.push_str(&format!("{:width$} | {}\n", col, value, width = width));
+                    }
+                }
+                if rows.is_empty() {
+                    text.push_str("(0 rows)\n");
+                }
+                text
+            }
+        };
+        if !self.write_out(&text) && !self.page(&text) {
+            print!("{}", text);
+        }
+    }
+}
+
+impl Stdout {
+    /// Prints how long a statement took, if `\timing` is on. Executing
+    /// includes printing the results.
+    fn time(&mut self, parse: Duration, total: Duration) {
+        if self.timing {
+            let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+            self.line(&format!(
+                "Time: {:.3} ms (parse {:.3} ms, execute {:.3} ms)", ms(total), ms(parse), ms(total - parse)
+            ));
+        }
+    }
+
+    /// Writes `text` to the file `\o` named, if there is one. Returns false
+    /// if it is left for the caller to print.
+    fn write_out(&mut self, text: &str) -> bool {
+        let Some((path, file)) = &mut self.output else {
+            return false;
+        };
+        if let Err(e) = file.write_all(text.as_bytes()) {
+            let message = format!("Could not write to '{}': {}", path, e);
+            self.error(&message);
+        }
+        true
+    }
+
+    /// Shows `text` through $PAGER if it is too tall for the terminal.
+    /// Returns false if it is left for the caller to print.
+    fn page(&self, text: &str) -> bool {
+        if !self.pager || !io::stdout().is_terminal() {
+            return false;
+        }
+        let Some((_, Height(height))) = terminal_size() else {
+            return false;
+        };
+        // One line is kept for the prompt that follows
+        if text.lines().count() < usize::from(height) {
+            return false;
+        }
+
+        let pager = env::var("PAGER").ok().filter(|pager| !pager.trim().is_empty());
+        let command = pager.as_deref().unwrap_or(DEFAULT_PAGER);
+        let mut words = command.split_whitespace();
+        let Some(program) = words.next() else {
+            return false;
+        };
+        let Ok(mut child) = Command::new(program).args(words).stdin(Stdio::piped()).spawn() else {
+            return false;
+        };
+        // The pager may be quit before reading everything, closing the pipe
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = stdin.write_all(text.as_bytes());
+        }
+        let _ = child.wait();
+        true
+    }
+}
+
+/// Reads lines with editing, and keeps the history in `~/.rustdb_history`
+/// across sessions. Up and down recall earlier lines, Ctrl-R searches them,
+/// and Tab completes names from `engine` if there is one.
+pub struct LineEditor {
+    editor: Editor<Completion, DefaultHistory>,
+    history: Option<PathBuf>,
+}
+
+impl LineEditor {
+    pub fn new(engine: Option<Rc<RefCell<Engine>>>) -> rustyline::Result<LineEditor> {
+        let config = Config::builder().completion_type(CompletionType::List).build();
+        let mut editor = Editor::with_config(config)?;
+        editor.set_helper(Some(Completion::new(engine)));
+        let history = env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE));
+        if let Some(path) = &history {
+            // Missing on first use
+            let _ = editor.load_history(path);
+        }
+        Ok(LineEditor { editor, history })
+    }
+
+    /// Reads one line without its newline, or None at end of input. Ctrl-C
+    /// discards what was typed and gives an empty line.
+    pub fn read_line(&mut self, prompt: &str) -> rustyline::Result<Option<String>> {
+        match self.editor.readline(prompt) {
+            Ok(line) => Ok(Some(line)),
+            Err(ReadlineError::Interrupted) => Ok(Some(String::new())),
+            Err(ReadlineError::Eof) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Adds an entry to the history, saving it right away so that it
+    /// survives a crash and other sessions do not overwrite it.
+    pub fn remember(&mut self, entry: &str) {
+        if entry.trim().is_empty() || !self.editor.add_history_entry(entry).unwrap_or(false) {
+            return;
+        }
+        if let Some(path) = &self.history {
+            let _ = self.editor.append_history(path);
+        }
+    }
+}
+
+/// The interactive prompt on the local database. Lines starting with `\`
+/// change settings of the session instead of running a statement.
+pub fn run(engine: Engine, format: Option<RowFormat>) {
+    let mut out = Stdout { format: format.unwrap_or_default(), ..Stdout::default() };
+    // Shared with the completion, which reads the catalog between statements
+    let engine = Rc::new(RefCell::new(engine));
+    let mut editor = match LineEditor::new(Some(Rc::clone(&engine))) {
+        Ok(editor) => editor,
+        Err(e) => {
+            out.error(&format!("Could not start the prompt: {}", e));
+            engine.borrow_mut().shutdown(&mut out);
+            return;
+        }
+    };
+    // Ctrl-C while a statement runs cancels it; at the prompt the editor
+    // sees it first and just drops the line
+    if let Err(e) = ctrlc::set_handler(interrupt::interrupt) {
+        out.error(&format!("Could not catch Ctrl-C: {}", e));
+    }
+    // Set once leaving has been refused for uncommitted changes, so asking
+    // again leaves all the same
+    let mut warned = false;
+    loop {
+        let prompt = match engine.borrow().db.in_transaction() {
+            true => "dbms*> ",
+            false => "dbms> ",
+        };
+        // End of input behaves like EXIT so piped sessions still checkpoint
+        let mut input = match editor.read_line(prompt) {
+            Ok(Some(line)) => line,
+            Ok(None) => {
+                if !warned && warn_uncommitted(&mut out, &engine.borrow()) {
+                    warned = true;
+                    continue;
+                }
+                engine.borrow_mut().shutdown(&mut out);
+                break;
+            }
+            Err(e) => {
+                out.error(&format!("Could not read input: {}", e));
+                engine.borrow_mut().shutdown(&mut out);
+                break;
+            }
+        };
+        if let Some(command) = input.trim().strip_prefix('\\') {
+            editor.remember(&input);
+            meta_command(&mut out, &mut engine.borrow_mut().variables, command);
+            continue;
+        }
+        // A quoted string may run over several lines
+        while parser::unterminated(&input) {
+            match editor.read_line("    -> ") {
+                Ok(Some(line)) => {
+                    input.push('\n');
+                    input.push_str(&line);
+                }
+                Ok(None) | Err(_) => break,
+            }
+        }
+        editor.remember(&input);
+
+        // The changes of a line's statements reach the disk together
+        engine.borrow_mut().begin_line();
+        let mut leave = false;
+        for text in statements(&input) {
+            let mut started = Instant::now();
+            let parsed = engine.borrow().parse(text);
+            let statement = match parsed {
+                Ok(Statement::Copy { table, format, data: None }) => {
+                    let parsed = started.elapsed();
+                    out.line(&format!("Enter the rows, then {} on a line of its own", END_OF_ROWS));
+                    let data = read_rows(|| editor.read_line(">> ").ok().flatten());
+                    // Typing the rows does not count towards the statement's time
+                    started = Instant::now() - parsed;
+                    Statement::Copy { table, format, data: Some(data) }
+                }
+                Ok(statement) => statement,
+                Err(e) => {
+                    out.failure(&e);
+                    continue;
+                }
+            };
+            let parsed = started.elapsed();
+            if matches!(statement, Statement::Exit) && !warned && warn_uncommitted(&mut out, &engine.borrow()) {
+                warned = true;
+                break;
+            }
+            warned = false;
+            interrupt::clear();
+            if !engine.borrow_mut().execute(&mut out, statement, text, None) {
+                leave = true;
+                break;
+            }
+            out.time(parsed, started.elapsed());
+        }
+        engine.borrow_mut().end_line(&mut out);
+        if leave {
+            engine.borrow_mut().shutdown(&mut out);
+            break;
+        }
+    }
+}
+
+// The `;`-separated statements of a line, leaving out blank ones and
+// comments, as found in scripts and dumps
+fn statements(input: &str) -> Vec<&str> {
+    parser::split_statements(input)
+        .into_iter()
+        .filter(|text| !parser::tokenize(text).is_ok_and(|tokens| tokens.is_empty()))
+        .collect()
+}
+
+// Warns that leaving would discard the changes of the open transaction, if
+// it has any. Returns whether it did.
+fn warn_uncommitted(out: &mut Stdout, engine: &Engine) -> bool {
+    let changes = engine.db.uncommitted_changes();
+    if changes == 0 {
+        return false;
+    }
+    out.line(&format!(
+        "Warning: The open transaction has {} uncommitted change(s); COMMIT or ROLLBACK it, or leave again to discard them", changes
+    ));
+    true
+}
+
+/// Shows how far operations that run over a second have got (an IMPORT, an
+/// index build, a VACUUM) on a line of the terminal, as a bar
+/// when their size is known and a count otherwise, cleared when they end.
+/// Nothing is shown unless stderr is a terminal.
+pub fn show_progress() {
+    if !io::stderr().is_terminal() {
+        return;
+    }
+    progress::set_reporter(Some(Box::new(|progress| {
+        let mut err = io::stderr().lock();
+        let _ = match progress.finished {
+            true => write!(err, "\r\x1b[K"),
+            false => write!(err, "\r\x1b[K{}", progress_line(progress)),
+        };
+        let _ = err.flush();
+    })));
+}
+
+fn progress_line(progress: &Progress) -> String {
+    let Progress { operation, done, total, unit, elapsed, .. } = progress;
+    let Some(total) = total else {
+        return format!("{}: {} {} ({}s)", operation, done, unit, elapsed.as_secs());
+    };
+    let fraction = if *total == 0 { 1.0 } else { *done as f64 / *total as f64 };
+    let filled = (fraction * PROGRESS_WIDTH as f64) as usize;
+    format!(
+        "{} [{}{}] {:>3}% {}/{} {} ({}s)",
+        operation, "#".repeat(filled), "-".repeat(PROGRESS_WIDTH - filled), (fraction * 100.0) as u32, done, total, unit, elapsed.as_secs()
+    )
+}
+
+/// Runs statements piped to stdin, read as the prompt reads them: a line
+/// at a time, or more where a quoted string runs on, each holding one
+/// statement or several separated by `;`. Errors name the line. The
+/// first one stops the run unless `continue_on_error` is set, and EXIT
+/// stops it early. Returns whether every statement succeeded.
+pub fn pipe(engine: &mut Engine, out: &mut Stdout, continue_on_error: bool) -> bool {
+    let mut lines = io::stdin().lines().enumerate();
+    let mut failed = false;
+    while let Some((i, line)) = lines.next() {
+        let mut input = match line {
+            Ok(line) => line,
+            Err(e) => {
+                out.error(&format!("Could not read input: {}", e));
+                return false;
+            }
+        };
+        if let Some(command) = input.trim().strip_prefix('\\') {
+            meta_command(out, &mut engine.variables, command);
+            continue;
+        }
+        while parser::unterminated(&input) && let Some((_, Ok(line))) = lines.next() {
+            input.push('\n');
+            input.push_str(&line);
+        }
+
+        engine.begin_line();
+        let mut leave = false;
+        for text in statements(&input) {
+            let started = Instant::now();
+            let mut located = Located::new(out, None, i + 1);
+            let mut parsed = None;
+            match engine.parse(text) {
+                Ok(mut statement) => {
+                    parsed = Some(started.elapsed());
+                    if let Statement::Copy { data: data @ None, .. } = &mut statement {
+                        *data = Some(read_rows(|| lines.next().and_then(|(_, line)| line.ok())));
+                    }
+                    leave = !engine.execute(&mut located, statement, text, None);
+                }
+                Err(e) => located.failure(&e),
+            }
+            if located.failed {
+                failed = true;
+                if !continue_on_error {
+                    engine.end_line(out);
+                    return false;
+                }
+            }
+            if leave {
+                break;
+            }
+            if let Some(parsed) = parsed {
+                out.time(parsed, started.elapsed());
+            }
+        }
+        if !engine.end_line(out) {
+            return false;
+        }
+        if leave {
+            break;
+        }
+    }
+    !failed
+}
+
+/// The rows of a `COPY ... FROM STDIN`, one per line up to a line holding
+/// only `\.` or the end of input.
+fn read_rows(mut next_line: impl FnMut() -> Option<String>) -> String {
+    let mut data = String::new();
+    while let Some(line) = next_line() {
+        if line.trim_end() == END_OF_ROWS {
+            break;
+        }
+        data.push_str(&line);
+        data.push('\n');
+    }
+    data
+}
+
+fn meta_command(out: &mut Stdout, variables: &mut Variables, command: &str) {
+    let mut words = command.split_whitespace();
+    match (words.next(), words.next()) {
+        (Some("set"), None) if variables.is_empty() => out.line("No variables are set"),
+        (Some("set"), None) => {
+            for (name, value) in variables.iter() {
+                out.line(&format!("@{} = {}", name, dump::literal(value)));
+            }
+        }
+        (Some("set"), Some(_)) => {
+            // The value is the rest of the line, spaces and all
+            let rest = command.trim()["set".len()..].trim_start();
+            let (name, value) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            let name = name.trim_start_matches('@');
+            if !variables::is_name(name) {
+                out.error(&format!("Invalid variable name '{}'; use letters, digits and _", name));
+                return;
+            }
+            let value = variables::parse(value.trim());
+            out.line(&format!("@{} = {}", name, dump::literal(&value)));
+            variables.insert(name.to_string(), value);
+        }
+        (Some("unset"), Some(name)) => match variables.remove(name.trim_start_matches('@')) {
+            Some(_) => out.line(&format!("@{} unset", name.trim_start_matches('@'))),
+            None => out.failure(&DbError::VariableNotFound(name.trim_start_matches('@').to_string())),
+        },
+        (Some("format"), None) => out.line(&format!("Output format is {}", out.format)),
+        (Some("format"), Some(name)) => match RowFormat::parse(name) {
+            Some(format) => {
+                out.format = format;
+                out.line(&format!("Output format is {}", format));
+            }
+            None => out.error(&format!("Unknown output format '{}'. Use table, csv, json or vertical", name)),
+        },
+        (Some("pager"), None) => out.line(&format!("Pager is {}", if out.pager { "on" } else { "off" })),
+        (Some("pager"), Some(setting @ ("on" | "off"))) => {
+            out.pager = setting == "on";
+            out.line(&format!("Pager is {}", setting));
+        }
+        (Some("pager"), Some(setting)) => out.error(&format!("Unknown pager setting '{}'. Use on or off", setting)),
+        (Some("timing"), None) => {
+            out.timing = !out.timing;
+            out.line(&format!("Timing is {}", if out.timing { "on" } else { "off" }));
+        }
+        (Some("timing"), Some(setting @ ("on" | "off"))) => {
+            out.timing = setting == "on";
+            out.line(&format!("Timing is {}", setting));
+        }
+        (Some("timing"), Some(setting)) => out.error(&format!("Unknown timing setting '{}'. Use on or off", setting)),
+        (Some("o"), None) => {
+            // Closing the file flushes it
+            out.output = None;
+            out.line("Results go to the terminal");
+        }
+        (Some("o"), Some(path)) => match File::create(path) {
+            Ok(file) => {
+                out.output = Some((path.to_string(), file));
+                out.line(&format!("Results go to '{}'", path));
+            }
+            Err(e) => out.error(&format!("Could not open '{}': {}", path, e)),
+        },
+        _ => out.error(&format!("Unknown command '\\{}'. Try \\format, \\o, \\pager, \\set, \\timing or \\unset", command)),
+    }
+}
@@ -0,0 +1,188 @@
+//! Leader-follower replication. A follower asks its leader for the log; the
+//! leader answers with its whole contents, as blobs and the records of its
+//! log, then with every change it makes from then on: blobs written, removed
+//! or renamed, records logged and the log truncated. The follower makes the
+//! same changes to its own storage and log, so it reads the same tables and
+//! can take over, once promoted, from where the leader left off.
+
+use std::io;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+use serde::{Serialize, Deserialize};
+
+use crate::backup::is_content;
+use crate::database::Database;
+use crate::error::DbError;
+use crate::partition;
+use crate::storage::{MemoryStorage, Stamp, Stamps, Storage};
+use crate::wal::{WalOp, WalRecord};
+
+/// One change to a database's storage or log, as its followers are sent it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Change {
+    Reset, // Removes every table, index and setting, before a copy of the leader's
+    Write { key: String, bytes: Vec<u8> },
+    Remove { key: String },
+    Rename { from: String, to: String },
+    Record(WalRecord),
+    Truncate { lsn: u64 }, // The log is emptied, going on from `lsn`
+}
+
+/// The followers of a database, each listening on a channel of its own.
+#[derive(Clone, Default)]
+pub struct Feed(Arc<Mutex<Vec<Sender<Change>>>>);
+
+impl Feed {
+    /// Sends `change` to every follower, forgetting those that went away.
+    pub fn publish(&self, change: Change) {
+        let mut followers = self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        followers.retain(|follower| follower.send(change.clone()).is_ok());
+    }
+
+    fn subscribe(&self) -> Receiver<Change> {
+        let (sender, receiver) = mpsc::channel();
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).push(sender);
+        receiver
+    }
+}
+
+// Storage that tells the followers of every table, index and setting written
+struct Published {
+    inner: Box<dyn Storage>,
+    feed: Feed,
+}
+
+impl Storage for Published {
+    fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        self.inner.read(key)
+    }
+
+    fn read_first_line(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        self.inner.read_first_line(key)
+    }
+
+    fn write(&mut self, key: &str, bytes: &[u8]) -> io::Result<()> {
+        self.inner.write(key, bytes)?;
+        if is_content(key) {
+            self.feed.publish(Change::Write { key: key.to_string(), bytes: bytes.to_vec() });
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &str) -> io::Result<bool> {
+        let removed = self.inner.remove(key)?;
+        if removed && is_content(key) {
+            self.feed.publish(Change::Remove { key: key.to_string() });
+        }
+        Ok(removed)
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> io::Result<()> {
+        self.inner.rename(from, to)?;
+        match (is_content(from), is_content(to)) {
+            (true, true) => self.feed.publish(Change::Rename { from: from.to_string(), to: to.to_string() }),
+            (true, false) => self.feed.publish(Change::Remove { key: from.to_string() }),
+            (false, true) => {
+                let bytes = self.inner.read(to)?.unwrap_or_default();
+                self.feed.publish(Change::Write { key: to.to_string(), bytes });
+            }
+            (false, false) => {}
+        }
+        Ok(())
+    }
+
+    fn exists(&self, key: &str) -> bool {
+        self.inner.exists(key)
+    }
+
+    fn stamp(&self, key: &str) -> Option<Stamp> {
+        self.inner.stamp(key)
+    }
+
+    fn stamps(&self) -> Option<Stamps> {
+        self.inner.stamps()
+    }
+
+    fn keys(&self) -> io::Result<Vec<String>> {
+        self.inner.keys()
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        self.inner.size()
+    }
+
+    fn discard_torn_writes(&mut self) -> io::Result<Vec<String>> {
+        self.inner.discard_torn_writes()
+    }
+}
+
+impl Database {
+    /// Starts a new follower off: the changes that turn an empty database
+    /// into a copy of this one, and a channel of every change made after
+    /// them, which closes once the database is dropped.
+    pub fn replicate(&mut self) -> Result<(Vec<Change>, Receiver<Change>), DbError> {
+        let feed = match &self.wal.feed {
+            Some(feed) => feed.clone(),
+            None => {
+                let feed = Feed::default();
+                let inner = std::mem::replace(&mut self.storage, Box::new(MemoryStorage::default()));
+                self.storage = Box::new(Published { inner, feed: feed.clone() });
+                self.wal.feed = Some(feed.clone());
+                feed
+            }
+        };
+
+        let mut base = vec![Change::Reset];
+        for key in self.storage.keys()?.into_iter().filter(|key| is_content(key)) {
+            if let Some(bytes) = self.storage.read(&key)? {
+                base.push(Change::Write { key, bytes });
+            }
+        }
+        // Committed changes the table files do not have yet; a transaction
+        // still open is sent once it commits
+        let records: Vec<WalRecord> = self.wal.records()?.into_iter()
+            .filter(|record| !matches!(record.op, WalOp::Checkpoint))
+            .collect();
+        let lsn = records.first().map_or(self.last_lsn(), |record| record.lsn - 1);
+        base.push(Change::Truncate { lsn });
+        base.extend(records.into_iter().map(Change::Record));
+        Ok((base, feed.subscribe()))
+    }
+
+    /// Makes a change sent by the leader this database follows.
+    pub fn apply_change(&mut self, change: Change) -> Result<(), DbError> {
+        match change {
+            Change::Reset => {
+                for key in self.storage.keys()?.into_iter().filter(|key| is_content(key)) {
+                    self.storage.remove(&key)?;
+                }
+                self.forget_stored_tables();
+            }
+            Change::Write { key, bytes } => {
+                self.storage.write(&key, &bytes)?;
+                self.forget_stored_tables();
+            }
+            Change::Remove { key } => {
+                self.storage.remove(&key)?;
+                self.forget_stored_tables();
+            }
+            Change::Rename { from, to } => {
+                self.storage.rename(&from, &to)?;
+                self.forget_stored_tables();
+            }
+            Change::Record(record) => {
+                // The tables it changes are read again, with it replayed
+                for name in record.op.tables() {
+                    self.forget_table(name);
+                    if partition::is_partition(name) {
+                        self.forget_table(name.split('#').next().unwrap());
+                    }
+                }
+                self.wal.append_record(record)?;
+            }
+            Change::Truncate { lsn } => self.wal.truncate_at(lsn)?,
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+//! The result cache, off unless `[cache] results` is set: what a SELECT
+//! printed is kept by the statement's text, spacing aside, and printed
+//! again when the same statement comes back, until one of the tables or
+//! views it read changes. A query reading the
+//! system catalog, an external table, a table whose rows expire or a table
+//! as of a time, or calling NOW() or RANDOM(), is never kept, as its result
+//! can change with nothing written.
+
+use std::collections::{BTreeSet, HashMap};
+
+use rust_db::builtins;
+use rust_db::parser::{self, Statement, Token};
+use rust_db::versions::Reads;
+use rust_db::{Database, DbError};
+
+use crate::commands::Output;
+
+/// Results kept, by normalized statement text.
+pub struct ResultCache {
+    capacity: usize,
+    entries: HashMap<String, Entry>,
+    clock: u64, // Bumped on every hit, to order entries for eviction
+}
+
+struct Entry {
+    output: Vec<Shown>,
+    reads: BTreeSet<String>,
+    at: u64, // The database's version when the result was worked out
+    last_used: u64,
+}
+
+/// One thing a statement printed.
+#[derive(Clone)]
+pub enum Shown {
+    Line(String),
+    Rows(Vec<String>, Vec<String>, Vec<Vec<String>>), // Columns, their types and the rows
+}
+
+impl ResultCache {
+    /// A cache keeping up to `capacity` results, the least recently used
+    /// going first.
+    pub fn new(capacity: usize) -> ResultCache {
+        ResultCache { capacity: capacity.max(1), entries: HashMap::new(), clock: 0 }
+    }
+
+    /// What `key` printed, if kept and nothing it read has changed since.
+    pub fn get(&mut self, key: &str, db: &Database) -> Option<Vec<Shown>> {
+        let entry = self.entries.get_mut(key)?;
+        if db.changed_since(&entry.reads, entry.at) {
+            self.entries.remove(key);
+            return None;
+        }
+        self.clock += 1;
+        entry.last_used = self.clock;
+        Some(entry.output.clone())
+    }
+
+    /// Keeps what `key` printed, worked out at version `at` from `reads`,
+    /// unless it read something that changes by itself.
+    pub fn put(&mut self, key: String, output: Vec<Shown>, reads: Reads, at: u64) {
+        if reads.volatile {
+            return;
+        }
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            let victim = self.entries.iter().min_by_key(|(_, entry)| entry.last_used).map(|(key, _)| key.clone());
+            if let Some(victim) = victim {
+                self.entries.remove(&victim);
+            }
+        }
+        self.clock += 1;
+        self.entries.insert(key, Entry { output, reads: reads.names, at, last_used: self.clock });
+    }
+
+    /// Forgets every result, for when the database underneath is replaced.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// The key `statement`, parsed from `text`, is kept under, None if its
+/// result is not to be kept: only SELECT and WITH queries calling no
+/// function whose result changes by itself are.
+pub fn key(statement: &Statement, text: &str) -> Option<String> {
+    if !matches!(statement, Statement::Select { .. } | Statement::With { .. }) {
+        return None;
+    }
+    let tokens = parser::tokenize(text).ok()?;
+    let volatile = tokens.windows(2).any(|pair| match pair {
+        [Token::Ident(name), Token::Symbol("(")] => builtins::VOLATILE.contains(&name.to_lowercase().as_str()),
+        _ => false,
+    });
+    if volatile {
+        return None;
+    }
+    let words: Vec<String> = tokens.iter()
+        .filter(|token| !matches!(token, Token::Symbol(";")))
+        .map(|token| match token {
+            Token::Ident(word) | Token::Number(word) => word.clone(),
+            Token::Str(text) => format!("'{}'", text.replace('\'', "''")),
+            Token::Symbol(symbol) => symbol.to_string(),
+        })
+        .collect();
+    Some(words.join(" "))
+}
+
+/// Output that passes everything on, keeping a copy of it to cache.
+pub struct Recording<'a> {
+    out: &'a mut dyn Output,
+    pub shown: Vec<Shown>,
+    pub failed: bool,
+}
+
+impl<'a> Recording<'a> {
+    pub fn new(out: &'a mut dyn Output) -> Recording<'a> {
+        Recording { out, shown: Vec::new(), failed: false }
+    }
+}
+
+impl Output for Recording<'_> {
+    fn line(&mut self, text: &str) {
+        self.shown.push(Shown::Line(text.to_string()));
+        self.out.line(text);
+    }
+
+    fn error(&mut self, message: &str) {
+        self.failed = true;
+        self.out.error(message);
+    }
+
+    fn failure(&mut self, error: &DbError) {
+        self.failed = true;
+        self.out.failure(error);
+    }
+
+    fn rows(&mut self, columns: &[&str], types: &[&str], rows: Vec<Vec<String>>) {
+        let owned = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        self.shown.push(Shown::Rows(owned(columns), owned(types), rows.clone()));
+        self.out.rows(columns, types, rows);
+    }
+
+    fn affected(&mut self, rows: usize) {
+        self.out.affected(rows);
+    }
+}
+
+/// Prints a cached result again.
+pub fn replay(out: &mut dyn Output, shown: Vec<Shown>) {
+    for item in shown {
+        match item {
+            Shown::Line(text) => out.line(&text),
+            Shown::Rows(columns, types, rows) => {
+                let columns: Vec<&str> = columns.iter().map(String::as_str).collect();
+                let types: Vec<&str> = types.iter().map(String::as_str).collect();
+                out.rows(&columns, &types, rows)
+            }
+        }
+    }
+}
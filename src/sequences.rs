@@ -0,0 +1,165 @@
+//! Sequences: counters stored beside the tables rather than in one, for IDs
+//! shared by several tables. `CREATE SEQUENCE ids [START [WITH] n]
+//! [INCREMENT [BY] n]` makes one; `NEXTVAL('ids')` draws its next value,
+//! as an INSERT value, or as the `DEFAULT` of an int column that is then
+//! filled on every insert and import; `SETVAL('ids', n)` sets it. A value
+//! drawn is never handed out again, even if the statement that drew it fails
+//! or its transaction rolls back, so there may be gaps.
+
+use serde::{Deserialize, Serialize};
+
+use crate::catalog;
+use crate::database::Database;
+use crate::error::DbError;
+use crate::storage;
+use crate::timestamps;
+use crate::Table;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sequence {
+    pub name: String,
+    pub next: i64, // The value NEXTVAL gives next, past the ints once they run out
+    pub increment: i32,
+}
+
+/// The sequence catalog, stored beside the tables.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Catalog {
+    sequences: Vec<Sequence>,
+}
+
+impl Database {
+    pub fn sequences(&self) -> Result<Vec<Sequence>, DbError> {
+        Ok(self.read_sequences()?.sequences)
+    }
+
+    pub fn create_sequence(&mut self, name: &str, start: i32, increment: i32) -> Result<(), DbError> {
+        let mut catalog = self.read_sequences()?;
+        if catalog.sequences.iter().any(|sequence| sequence.name == name) {
+            return Err(DbError::SequenceExists(name.to_string()));
+        }
+        catalog.sequences.push(Sequence { name: name.to_string(), next: start.into(), increment });
+        self.write_sequences(&catalog)
+    }
+
+    /// Drops a sequence. Fails while columns take their DEFAULT from it,
+    /// unless `cascade`, which drops those DEFAULTs first.
+    pub fn drop_sequence(&mut self, name: &str, cascade: bool) -> Result<(), DbError> {
+        let mut catalog = self.read_sequences()?;
+        let position = catalog.sequences.iter().position(|sequence| sequence.name == name)
+            .ok_or_else(|| DbError::SequenceNotFound(name.to_string()))?;
+        let mut columns = Vec::new();
+        for table in self.table_names()? {
+            for (column, sequence) in self.definition(&table)?.sequences {
+                if sequence == name {
+                    columns.push((table.clone(), column));
+                }
+            }
+        }
+        if !cascade && !columns.is_empty() {
+            let dependents = columns.iter().map(|(table, column)| format!("the DEFAULT of '{}.{}'", table, column)).collect();
+            return Err(DbError::HasDependents { name: name.to_string(), dependents });
+        }
+        for (table, column) in columns {
+            self.set_default(&table, &column, None)?;
+        }
+        catalog.sequences.remove(position);
+        self.write_sequences(&catalog)
+    }
+
+    pub fn nextval(&mut self, name: &str) -> Result<i32, DbError> {
+        Ok(self.nextvals(name, 1)?[0])
+    }
+
+    /// The next `count` values of sequence `name`, drawn at once.
+    pub fn nextvals(&mut self, name: &str, count: usize) -> Result<Vec<i32>, DbError> {
+        let mut catalog = self.read_sequences()?;
+        let sequence = find(&mut catalog, name)?;
+        let mut values = Vec::with_capacity(count);
+        let mut next = sequence.next;
+        for _ in 0..count {
+            values.push(i32::try_from(next).map_err(|_| DbError::SequenceExhausted(name.to_string()))?);
+            next += i64::from(sequence.increment);
+        }
+        sequence.next = next;
+        self.write_sequences(&catalog)?;
+        Ok(values)
+    }
+
+    /// Makes `value` the last value sequence `name` gave, so NEXTVAL goes on
+    /// from it.
+    pub fn setval(&mut self, name: &str, value: i32) -> Result<(), DbError> {
+        let mut catalog = self.read_sequences()?;
+        let sequence = find(&mut catalog, name)?;
+        sequence.next = i64::from(value) + i64::from(sequence.increment);
+        self.write_sequences(&catalog)
+    }
+
+    /// Fills `column` of `table_name` from sequence `name` on every insert
+    /// from now on, or stops if None.
+    pub fn set_default(&mut self, table_name: &str, column: &str, sequence: Option<String>) -> Result<(), DbError> {
+        if catalog::is_system_table(table_name) {
+            return Err(DbError::SystemTable(table_name.to_string()));
+        }
+        let mut table = self.load_table(table_name)?.clone();
+        if !table.fields.contains_key(column) {
+            return Err(DbError::ColumnNotFound { table: table_name.to_string(), column: column.to_string() });
+        }
+        match sequence {
+            Some(sequence) => {
+                table.sequences.insert(column.to_string(), sequence);
+                self.check_defaults(&table)?;
+            }
+            None => {
+                table.sequences.remove(column);
+            }
+        }
+        self.save_table(&table)
+    }
+
+    /// Checks that each column of `table` with a sequence as its DEFAULT is
+    /// an int the engine does not fill otherwise, and that the sequence exists.
+    pub fn check_defaults(&self, table: &Table) -> Result<(), DbError> {
+        if table.external.is_some() && !table.sequences.is_empty() {
+            return Err(DbError::ExternalTable(table.name.clone()));
+        }
+        let sequences = self.sequences()?;
+        for (column, sequence) in &table.sequences {
+            let invalid = |reason: &str| DbError::InvalidDefinition(vec![format!("column '{}' {}", column, reason)]);
+            if table.fields[column] != "int" {
+                return Err(invalid("must be an int to take its DEFAULT from a sequence"));
+            }
+            if table.generated.contains_key(column) || timestamps::is_timestamp(table, column) {
+                return Err(invalid("is filled by the engine, so cannot have a DEFAULT"));
+            }
+            if !sequences.iter().any(|s| &s.name == sequence) {
+                return Err(DbError::SequenceNotFound(sequence.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    fn read_sequences(&self) -> Result<Catalog, DbError> {
+        match self.storage.read(storage::SEQUENCES_KEY)? {
+            Some(bytes) => serde_json::from_slice(&bytes).map_err(|e| DbError::CorruptTable {
+                table: storage::SEQUENCES_KEY.to_string(),
+                reason: e.to_string(),
+            }),
+            None => Ok(Catalog::default()),
+        }
+    }
+
+    fn write_sequences(&mut self, catalog: &Catalog) -> Result<(), DbError> {
+        self.check_read_write()?;
+        self.versions.changed(storage::SEQUENCES_KEY);
+        let bytes = serde_json::to_vec_pretty(catalog).map_err(std::io::Error::from)?;
+        self.storage.write(storage::SEQUENCES_KEY, &bytes)?;
+        Ok(())
+    }
+}
+
+fn find<'a>(catalog: &'a mut Catalog, name: &str) -> Result<&'a mut Sequence, DbError> {
+    catalog.sequences.iter_mut()
+        .find(|sequence| sequence.name == name)
+        .ok_or_else(|| DbError::SequenceNotFound(name.to_string()))
+}
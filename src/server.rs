@@ -0,0 +1,602 @@
+use std::io::{self, BufReader, BufWriter, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rust_db::DbError;
+use rust_db::cdc;
+use rust_db::parser::{self, Statement};
+use rust_db::protocol::{self, Frame};
+use rust_db::replication::Change;
+use rust_db::users::{self, User};
+
+use crate::client::Credentials;
+use crate::commands::{render, shown, Engine, Output};
+use crate::http;
+use crate::pgwire;
+use crate::sessions;
+use crate::tls::{self, Tls};
+
+const TABLE_CHUNK_LINES: usize = 1000;
+
+// How long a follower waits to connect again after losing its leader
+const FOLLOW_RETRY: Duration = Duration::from_secs(5);
+
+// How often expired rows are deleted from the tables in memory
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+// A transaction left this long without a statement is rolled back, and its
+// connection closed, once another connection runs one
+const IDLE_TRANSACTION_TIMEOUT: Duration = Duration::from_secs(60);
+
+// Connection ids are unique across both listeners
+static NEXT_CONNECTION: AtomicU64 = AtomicU64::new(1);
+
+// The user catalog as last read, so a client can log in (and KILL a
+// runaway statement) while a statement holds the engine. Forgotten whenever
+// a statement may have changed it.
+static USERS: Mutex<Option<Vec<User>>> = Mutex::new(None);
+
+/// The engine shared by every connection. Statements run one at a time.
+pub struct Shared {
+    pub engine: Engine,
+    // The connection whose transaction is open, and when its last statement
+    // ended. Everyone else reads what is committed, and waits to write.
+    owner: Option<u64>,
+    idle_since: Instant,
+}
+
+impl Shared {
+    // Rolls back a transaction whose connection has gone quiet, and closes
+    // the connection, so it does not go on as if the transaction were open
+    fn end_idle_transaction(&mut self) {
+        let Some(owner) = self.owner.filter(|_| self.idle_since.elapsed() >= IDLE_TRANSACTION_TIMEOUT) else { return };
+        println!("Connection {}: rolling back its transaction, idle for {} s", owner, self.idle_since.elapsed().as_secs());
+        self.engine.rollback(&mut Frames(Vec::new()));
+        self.owner = None;
+        let _ = sessions::kill(owner, None, None);
+    }
+}
+
+/// Collects the frames of one statement's results. They are sent once the
+/// engine is unlocked, so a slow client never holds up the others.
+struct Frames(Vec<Frame>);
+
+impl Output for Frames {
+    fn line(&mut self, text: &str) {
+        self.0.push(Frame::Output(text.to_string()));
+    }
+
+    fn error(&mut self, message: &str) {
+        self.0.push(Frame::Error(message.to_string()));
+    }
+
+    // Large results go out in several frames, which the client prints as they arrive
+    fn rows(&mut self, columns: &[&str], types: &[&str], rows: Vec<Vec<String>>) {
+        let text = render(columns, types, rows).to_string();
+        let lines: Vec<&str> = text.lines().collect();
+        for chunk in lines.chunks(TABLE_CHUNK_LINES) {
+            self.0.push(Frame::Output(chunk.join("\n")));
+        }
+    }
+}
+
+pub fn connection_id() -> u64 {
+    NEXT_CONNECTION.fetch_add(1, Ordering::Relaxed)
+}
+
+type Handler = fn(&Mutex<Shared>, u64, TcpStream, &Security) -> io::Result<()>;
+
+// Names each protocol in SHOW PROCESSLIST
+const NATIVE: &str = "native";
+
+/// The logins a listener accepts once the database has users: a user's
+/// password, one of their access tokens, or either.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Auth {
+    #[default]
+    Any,
+    Password,
+    Token,
+}
+
+impl Auth {
+    pub fn parse(name: &str) -> Option<Auth> {
+        match name.to_ascii_lowercase().as_str() {
+            "any" => Some(Auth::Any),
+            "password" => Some(Auth::Password),
+            "token" => Some(Auth::Token),
+            _ => None,
+        }
+    }
+
+    pub fn allows_password(&self) -> bool {
+        *self != Auth::Token
+    }
+
+    pub fn allows_token(&self) -> bool {
+        *self != Auth::Password
+    }
+}
+
+/// How one listener authenticates its clients, the certificate it
+/// encrypts their connections with, if any, and for HTTP the origins of
+/// the pages allowed to call it from a browser (CORS), none by default.
+#[derive(Debug, Clone, Default)]
+pub struct Security {
+    pub auth: Auth,
+    pub tls: Option<Arc<Tls>>,
+    pub origins: Vec<String>,
+}
+
+impl Security {
+    // Added to the line announcing the listener
+    fn describe(&self) -> String {
+        let auth = match self.auth {
+            Auth::Any => "",
+            Auth::Password => ", passwords only",
+            Auth::Token => ", tokens only",
+        };
+        format!("{}{}", if self.tls.is_some() { " over TLS" } else { "" }, auth)
+    }
+}
+
+/// The security of each listener.
+#[derive(Debug, Default)]
+pub struct Listeners {
+    pub native: Security,
+    pub pg: Security,
+    pub http: Security,
+}
+
+/// The server a follower replicates, the credentials it logs in with there
+/// if it must, and the certificates to trust if the leader's listener
+/// encrypts.
+pub struct Leader {
+    pub addr: String,
+    pub login: Option<Credentials>,
+    pub tls_ca: Option<PathBuf>,
+}
+
+/// Serves the native protocol on `addr` and, if given, the PostgreSQL
+/// protocol on `pg_addr` and HTTP on `http_addr`, all against the same engine,
+/// each secured as `listeners` says. With a `leader`, the server follows it,
+/// serving reads of what it replicates from there until PROMOTE.
+pub fn serve(
+    mut engine: Engine,
+    addr: &str,
+    pg_addr: Option<&str>,
+    http_addr: Option<&str>,
+    listeners: Listeners,
+    leader: Option<Leader>,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    let pg_listener = pg_addr.map(TcpListener::bind).transpose()?;
+    let http_server = http_addr.map(|addr| http::bind(addr, listeners.http.tls.as_deref())).transpose()?;
+    engine.following = leader.as_ref().map(|leader| leader.addr.clone());
+    let shared = Arc::new(Mutex::new(Shared { engine, owner: None, idle_since: Instant::now() }));
+    if !auth_required(&shared) {
+        println!("Warning: No users exist, so connections are not authenticated. CREATE USER to require a login.");
+    }
+
+    if let Some(leader) = leader {
+        let shared = Arc::clone(&shared);
+        thread::spawn(move || follow(&shared, &leader));
+    }
+
+    {
+        let shared = Arc::clone(&shared);
+        thread::spawn(move || sweep(&shared));
+    }
+
+    let Listeners { native, pg, http } = listeners;
+    if let Some(http_server) = http_server {
+        println!("Listening for HTTP requests on {}{}", http_server.server_addr(), http.describe());
+        let shared = Arc::clone(&shared);
+        thread::spawn(move || http::accept(http_server, shared, http));
+    }
+    if let Some(pg_listener) = pg_listener {
+        println!("Listening for PostgreSQL clients on {}{}", pg_listener.local_addr()?, pg.describe());
+        let shared = Arc::clone(&shared);
+        thread::spawn(move || accept(pg_listener, shared, pgwire::PROTOCOL, pgwire::handle, pg));
+    }
+    println!("Listening on {}{}", listener.local_addr()?, native.describe());
+    accept(listener, shared, NATIVE, handle, native);
+    Ok(())
+}
+
+// Deletes expired rows every SWEEP_INTERVAL, skipping a round while a
+// connection's transaction is open
+fn sweep(shared: &Mutex<Shared>) {
+    loop {
+        thread::sleep(SWEEP_INTERVAL);
+        let mut shared = lock(shared);
+        shared.end_idle_transaction();
+        if shared.owner.is_some() {
+            continue;
+        }
+        match shared.engine.sweep_expired() {
+            Ok(0) => {}
+            Ok(purged) => println!("Deleted {} expired row(s)", purged),
+            Err(e) => println!("Error: Could not delete expired rows: {}", e),
+        }
+    }
+}
+
+fn accept(listener: TcpListener, shared: Arc<Mutex<Shared>>, protocol: &'static str, handler: Handler, security: Security) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                println!("Error: Could not accept connection: {}", e);
+                continue;
+            }
+        };
+        let id = connection_id();
+        let shared = Arc::clone(&shared);
+        let security = security.clone();
+        thread::spawn(move || {
+            let peer = stream.peer_addr().map_or("unknown".to_string(), |addr| addr.to_string());
+            println!("Connection {} from {}", id, peer);
+            sessions::open(id, protocol, &peer, stream.try_clone().ok());
+            if let Err(e) = handler(&shared, id, stream, &security) {
+                println!("Error: Connection {}: {}", id, e);
+            }
+            sessions::close(id);
+            release(&shared, id);
+            println!("Connection {} closed", id);
+        });
+    }
+}
+
+fn handle(shared: &Mutex<Shared>, id: u64, stream: TcpStream, security: &Security) -> io::Result<()> {
+    let stream = tls::accept(stream, security.tls.as_deref())?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    // Decided once: a connection made before the first user existed stays open
+    let open = !auth_required(shared);
+    let mut user: Option<String> = None;
+    while let Some(frame) = protocol::read_frame(&mut reader)? {
+        let mut out = Frames(Vec::new());
+        let mut keep_going = true;
+        match frame {
+            Frame::Login { .. } if !security.auth.allows_password() => {
+                out.error("This server only accepts access tokens; connect with --token");
+                keep_going = false;
+            }
+            Frame::Token(_) if !security.auth.allows_token() => {
+                out.error("This server only accepts passwords; connect with --user <name>");
+                keep_going = false;
+            }
+            Frame::Login { user: name, password } => {
+                if let Some(account) = authenticate(shared, &name, &password) {
+                    sessions::login(id, &name, account.superuser);
+                    user = Some(name);
+                } else {
+                    out.error("Authentication failed");
+                    keep_going = false;
+                }
+            }
+            Frame::Token(token) => {
+                if let Some(account) = authenticate_token(shared, &token) {
+                    sessions::login(id, &account.name, account.superuser);
+                    user = Some(account.name);
+                } else {
+                    out.error("Authentication failed");
+                    keep_going = false;
+                }
+            }
+            Frame::Query(_) if user.is_none() && !open => {
+                out.error(login_hint(security.auth));
+            }
+            Frame::Query(input) => match parser::parse(&input) {
+                Ok(Statement::Subscribe(table)) => {
+                    let subscribed = lock(shared).engine.subscribe(&table, user.as_deref());
+                    match subscribed {
+                        // The connection is the subscriber's from here on
+                        Ok(events) => {
+                            println!("Connection {} subscribed to '{}'", id, table);
+                            return send_events(&mut writer, &table, events);
+                        }
+                        Err(e) => out.failure(&e),
+                    }
+                }
+                _ => keep_going = run(shared, id, user.as_deref(), &input, &mut out),
+            },
+            Frame::Replicate if user.is_none() && !open => {
+                out.error(login_hint(security.auth));
+            }
+            Frame::Replicate => match replicate(shared, user.as_deref()) {
+                // The connection is the follower's from here on
+                Ok((base, changes)) => {
+                    println!("Connection {} is following", id);
+                    return send_changes(&mut writer, base, changes);
+                }
+                Err(e) => out.failure(&e),
+            },
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "expected a query")),
+        }
+        for frame in out.0.iter().chain([&Frame::Done]) {
+            protocol::write_frame(&mut writer, frame)?;
+        }
+        writer.flush()?;
+        if !keep_going {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn login_hint(auth: Auth) -> &'static str {
+    match auth {
+        Auth::Any => "Authentication required; connect with --user <name> or --token",
+        Auth::Password => "Authentication required; connect with --user <name>",
+        Auth::Token => "Authentication required; connect with --token",
+    }
+}
+
+fn run(shared: &Mutex<Shared>, id: u64, user: Option<&str>, input: &str, out: &mut Frames) -> bool {
+    if input.trim().is_empty() {
+        return true;
+    }
+    let statement = match parser::parse(input) {
+        Ok(statement) => statement,
+        Err(e) => {
+            out.failure(&e);
+            return true;
+        }
+    };
+    if matches!(statement, Statement::Exit) {
+        return false;
+    }
+    execute(shared, id, user, statement, input, out);
+    true
+}
+
+/// Runs a statement, parsed from `text`, for connection `id`, logged in as
+/// `user` if the server requires a login. EXIT is up to the caller.
+pub fn execute(shared: &Mutex<Shared>, id: u64, user: Option<&str>, statement: Statement, text: &str, out: &mut dyn Output) {
+    // Every connection shares the one open database
+    if matches!(statement, Statement::Use(_)) {
+        out.error("USE is not available over a connection; start the server on that database instead");
+        return;
+    }
+    // The engine's setting would hold for every connection
+    if matches!(statement, Statement::SetAutocommit(_)) {
+        out.error("SET autocommit is not available over a connection; use BEGIN and COMMIT instead");
+        return;
+    }
+    // Each request is a line of its own, synced once it has run
+    if matches!(statement, Statement::SetSynchronous(_)) {
+        out.error("SET synchronous is not available over a connection; each statement is synced before it returns");
+        return;
+    }
+    // The statement was parsed without them, and the engine's would be every connection's
+    if matches!(statement, Statement::SetVariable { .. }) {
+        out.error("Variables are not available over a connection; put the values in the statements instead");
+        return;
+    }
+    // Without waiting for the engine, which a runaway statement may hold
+    match statement {
+        Statement::ShowProcesslist => return sessions::show(out, Some(id), user),
+        Statement::Kill(target) => {
+            match sessions::kill(target, Some(id), user) {
+                Ok(()) => out.line(&format!("Session {} killed", target)),
+                Err(e) => out.failure(&e),
+            }
+            return;
+        }
+        _ => {}
+    }
+
+    sessions::waiting(id, &shown(&statement, text));
+    let mut shared = lock(shared);
+    if shared.owner.is_some_and(|owner| owner != id) {
+        shared.end_idle_transaction();
+    }
+    let beside = shared.owner.is_some_and(|owner| owner != id);
+    if beside && !reads_tables(&statement) {
+        sessions::finished(id);
+        out.error("Another connection has a transaction open; try again once it ends");
+        return;
+    }
+    // A session killed while it waited runs nothing
+    match sessions::running(id) {
+        Ok(()) => {
+            if !statement.is_read_only() {
+                forget_users();
+            }
+            match beside {
+                true => shared.engine.execute_committed(out, statement, text, user),
+                false => shared.engine.execute(out, statement, text, user),
+            };
+        }
+        Err(e) => out.failure(&e),
+    }
+    sessions::finished(id);
+    if !beside {
+        shared.owner = shared.engine.db.in_transaction().then_some(id);
+        shared.idle_since = Instant::now();
+    }
+}
+
+// What a connection may run beside another's open transaction, reading what
+// has been committed: statements that only read tables and the catalog
+fn reads_tables(statement: &Statement) -> bool {
+    matches!(
+        statement,
+        Statement::Select { .. }
+            | Statement::With { .. }
+            | Statement::Count(_)
+            | Statement::Explain { .. }
+            | Statement::ShowTables
+            | Statement::ShowTableStatus
+            | Statement::ShowSequences
+            | Statement::ShowCreateTable(_)
+            | Statement::Describe(_)
+            | Statement::ShowStats(_)
+            | Statement::ShowIndexes(_)
+            | Statement::ShowUsers
+            | Statement::ShowTokens
+            | Statement::ShowGrants(_)
+    )
+}
+
+// The changes that bring a new follower up to date and the channel of those
+// after them. Only a superuser may replicate, since it reads everything.
+fn replicate(shared: &Mutex<Shared>, user: Option<&str>) -> Result<(Vec<Change>, Receiver<Change>), DbError> {
+    let mut shared = lock(shared);
+    if let Some(user) = user && !matches!(shared.engine.db.user(user), Ok(Some(user)) if user.superuser) {
+        return Err(DbError::PermissionDenied("replication needs a superuser".to_string()));
+    }
+    shared.engine.db.replicate()
+}
+
+fn send_changes(writer: &mut impl Write, base: Vec<Change>, changes: Receiver<Change>) -> io::Result<()> {
+    for change in base {
+        protocol::write_frame(writer, &Frame::Change(serde_json::to_string(&change)?))?;
+    }
+    writer.flush()?;
+    for change in changes {
+        protocol::write_frame(writer, &Frame::Change(serde_json::to_string(&change)?))?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+// Each event goes out as a line of JSON, as soon as it is committed. The
+// statement never ends, so no `Done` follows.
+fn send_events(writer: &mut impl Write, table: &str, events: Receiver<cdc::Event>) -> io::Result<()> {
+    protocol::write_frame(writer, &Frame::Output(format!("Subscribed to changes of '{}'", table)))?;
+    writer.flush()?;
+    for event in events {
+        protocol::write_frame(writer, &Frame::Output(event.to_json()))?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+// Replicates `leader` for as long as this server follows it, connecting
+// again whenever the connection is lost. Each new connection starts from a
+// fresh copy of the leader's contents.
+fn follow(shared: &Mutex<Shared>, leader: &Leader) {
+    loop {
+        if let Err(e) = follow_once(shared, leader) {
+            println!("Error: Replication from {}: {}", leader.addr, e);
+        }
+        if lock(shared).engine.following.is_none() {
+            println!("Stopped following {}", leader.addr);
+            return;
+        }
+        thread::sleep(FOLLOW_RETRY);
+    }
+}
+
+fn follow_once(shared: &Mutex<Shared>, leader: &Leader) -> io::Result<()> {
+    let stream = tls::connect(&leader.addr, leader.tls_ca.as_deref())?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    if let Some(login) = &leader.login {
+        protocol::write_frame(&mut writer, &login.frame())?;
+    }
+    protocol::write_frame(&mut writer, &Frame::Replicate)?;
+    writer.flush()?;
+
+    while let Some(frame) = protocol::read_frame(&mut reader)? {
+        match frame {
+            Frame::Change(json) => {
+                let change: Change = serde_json::from_str(&json)?;
+                // The first change of each connection starts the copy over
+                if matches!(change, Change::Reset) {
+                    println!("Following {}", leader.addr);
+                }
+                let mut shared = lock(shared);
+                if shared.engine.following.is_none() {
+                    return Ok(());
+                }
+                forget_users();
+                shared.engine.db.apply_change(change).map_err(|e| io::Error::other(e.to_string()))?;
+            }
+            Frame::Error(message) => return Err(io::Error::other(message)),
+            // The answer to the login
+            Frame::Done => {}
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "unexpected message from the leader")),
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the leader closed the connection"))
+}
+
+/// Whether the database has any users, and so requires a login. An unreadable
+/// user catalog counts as having some.
+pub fn auth_required(shared: &Mutex<Shared>) -> bool {
+    !matches!(users(shared), Ok(users) if users.is_empty())
+}
+
+/// Checks a login against the user catalog, returning the user if it
+/// succeeds. The deliberately slow password check runs without holding the
+/// engine.
+pub fn authenticate(shared: &Mutex<Shared>, name: &str, password: &str) -> Option<User> {
+    let users = users(shared).ok()?;
+    users.into_iter().find(|user| user.name == name).filter(|user| user.verify(password))
+}
+
+/// Checks an access token against the user catalog, returning its user if
+/// it is valid. Like a password, it is checked without holding the engine.
+pub fn authenticate_token(shared: &Mutex<Shared>, token: &str) -> Option<User> {
+    let users = self::users(shared).ok()?;
+    users::token_owner(&users, token).filter(|user| user.verify_token(token)).cloned()
+}
+
+// The user catalog, read again if the engine is free and otherwise as last
+// read, unless it may have changed since
+fn users(shared: &Mutex<Shared>) -> Result<Vec<User>, DbError> {
+    let read = |shared: MutexGuard<Shared>| {
+        let users = shared.engine.db.users()?;
+        *lock_users() = Some(users.clone());
+        Ok(users)
+    };
+    match shared.try_lock() {
+        Ok(shared) => read(shared),
+        Err(TryLockError::Poisoned(poisoned)) => read(poisoned.into_inner()),
+        Err(TryLockError::WouldBlock) => {
+            // Let go of the last read before waiting for the engine, whose holder may be storing one
+            let last = lock_users().clone();
+            match last {
+                Some(users) => Ok(users),
+                None => read(lock(shared)),
+            }
+        }
+    }
+}
+
+fn forget_users() {
+    *lock_users() = None;
+}
+
+fn lock_users() -> MutexGuard<'static, Option<Vec<User>>> {
+    USERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Whether connection `id` has a transaction open.
+pub fn in_transaction(shared: &Mutex<Shared>, id: u64) -> bool {
+    lock(shared).owner == Some(id)
+}
+
+// A connection that goes away mid-transaction has its changes discarded
+pub fn release(shared: &Mutex<Shared>, id: u64) {
+    let mut shared = lock(shared);
+    if shared.owner == Some(id) {
+        shared.engine.rollback(&mut Frames(Vec::new()));
+        shared.owner = None;
+    }
+}
+
+// A statement that panicked in one connection should not take the others down
+pub fn lock(shared: &Mutex<Shared>) -> MutexGuard<'_, Shared> {
+    shared.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
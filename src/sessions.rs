@@ -0,0 +1,151 @@
+//! The sessions a server has open: each connection over the native or
+//! PostgreSQL protocol, and each HTTP request while it runs. SHOW
+//! PROCESSLIST lists them with what they are running, and KILL cancels a
+//! session's statement and closes its connection. The registry is kept
+//! apart from the engine, so KILL works while a runaway statement holds it.
+
+use std::collections::BTreeMap;
+use std::net::{Shutdown, TcpStream};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Instant;
+
+use rust_db::interrupt;
+use rust_db::DbError;
+
+use crate::commands::Output;
+
+static SESSIONS: Mutex<BTreeMap<u64, Session>> = Mutex::new(BTreeMap::new());
+
+struct Session {
+    protocol: &'static str,
+    peer: String,
+    user: Option<String>,
+    superuser: bool,
+    opened: Instant,
+    statement: Option<Statement>,
+    killed: bool,
+    // Shut down by KILL, so a client waiting on the connection is let go too
+    stream: Option<TcpStream>,
+}
+
+struct Statement {
+    text: String,
+    since: Instant,
+    running: bool, // Or still waiting for the engine
+}
+
+/// Registers session `id`, connected over `protocol` from `peer`.
+pub fn open(id: u64, protocol: &'static str, peer: &str, stream: Option<TcpStream>) {
+    let session = Session {
+        protocol, peer: peer.to_string(), user: None, superuser: false, opened: Instant::now(), statement: None, killed: false, stream,
+    };
+    sessions().insert(id, session);
+}
+
+pub fn close(id: u64) {
+    sessions().remove(&id);
+}
+
+/// Records who session `id` logged in as.
+pub fn login(id: u64, user: &str, superuser: bool) {
+    if let Some(session) = sessions().get_mut(&id) {
+        session.user = Some(user.to_string());
+        session.superuser = superuser;
+    }
+}
+
+/// Marks session `id` as waiting for the engine to run `text`.
+pub fn waiting(id: u64, text: &str) {
+    if let Some(session) = sessions().get_mut(&id) {
+        session.statement = Some(Statement { text: text.to_string(), since: Instant::now(), running: false });
+    }
+}
+
+/// Marks the statement of session `id` as running, which the caller does
+/// once it holds the engine. Fails if the session was killed meanwhile.
+pub fn running(id: u64) -> Result<(), DbError> {
+    let mut sessions = sessions();
+    let Some(session) = sessions.get_mut(&id) else { return Ok(()) };
+    if session.killed {
+        return Err(DbError::Interrupted);
+    }
+    // A KILL meant for the statement before this one must not cancel it
+    interrupt::clear();
+    if let Some(statement) = &mut session.statement {
+        statement.running = true;
+    }
+    Ok(())
+}
+
+/// Marks session `id` as idle, before the engine is released.
+pub fn finished(id: u64) {
+    if let Some(session) = sessions().get_mut(&id) {
+        session.statement = None;
+    }
+}
+
+/// Kills session `target` for session `by`, logged in as `user` (None if
+/// the server requires no login): cancels its statement if one is running
+/// and closes its connection. Only superusers may kill other users'
+/// sessions.
+pub fn kill(target: u64, by: Option<u64>, user: Option<&str>) -> Result<(), DbError> {
+    let mut sessions = sessions();
+    let allowed = match user {
+        None => true,
+        Some(user) => {
+            by.and_then(|by| sessions.get(&by)).is_some_and(|caller| caller.superuser)
+                || sessions.get(&target).is_some_and(|session| session.user.as_deref() == Some(user))
+        }
+    };
+    let session = sessions.get_mut(&target).ok_or(DbError::SessionNotFound(target))?;
+    if !allowed {
+        return Err(DbError::PermissionDenied(format!("session {} belongs to another user", target)));
+    }
+    session.killed = true;
+    if session.statement.as_ref().is_some_and(|statement| statement.running) {
+        interrupt::interrupt();
+    }
+    if let Some(stream) = &session.stream {
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+    Ok(())
+}
+
+/// SHOW PROCESSLIST for session `by`, logged in as `user`: every session,
+/// oldest first, for a superuser or if the server requires no login, and
+/// otherwise only those of `user`.
+pub fn show(out: &mut dyn Output, by: Option<u64>, user: Option<&str>) {
+    let columns = ["id", "user", "protocol", "client", "connected s", "state", "time ms", "statement"];
+    let sessions = sessions();
+    let everyone = user.is_none() || by.and_then(|by| sessions.get(&by)).is_some_and(|caller| caller.superuser);
+    let rows = sessions.iter()
+        .filter(|(_, session)| everyone || session.user.as_deref() == user)
+        .map(|(id, session)| {
+            let (state, ms, text) = match &session.statement {
+                Some(statement) => (
+                    if statement.running { "running" } else { "waiting" },
+                    format!("{:.0}", statement.since.elapsed().as_secs_f64() * 1000.0),
+                    statement.text.clone(),
+                ),
+                None => ("idle", String::new(), String::new()),
+            };
+            vec![
+                id.to_string(),
+                session.user.clone().unwrap_or_default(),
+                session.protocol.to_string(),
+                session.peer.clone(),
+                session.opened.elapsed().as_secs().to_string(),
+                if session.killed { "killed" } else { state }.to_string(),
+                ms,
+                text,
+            ]
+        })
+        .collect();
+    drop(sessions);
+    let types = ["int", "string", "string", "string", "int", "string", "int", "string"];
+    out.rows(&columns, &types, rows);
+}
+
+fn sessions() -> MutexGuard<'static, BTreeMap<u64, Session>> {
+    SESSIONS.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
@@ -0,0 +1,176 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::database::Database;
+use crate::error::DbError;
+use crate::external::External;
+use crate::storage::{self, Stamp, Stamps};
+use crate::time::Clock;
+use crate::tombstones;
+use crate::ttl;
+use crate::versions::Recorder;
+use crate::Table;
+
+/// A `Database` that can be shared between threads, e.g. behind an `Arc`.
+///
+/// Each table read is published in a slot with a lock of its own, found
+/// through a catalog that is locked only to look a slot up. A reader takes
+/// a snapshot of the table from its slot and runs without any lock; unless
+/// the table has yet to be read, or its file has changed, it never takes
+/// the database's lock, so it waits neither for other readers nor for a
+/// writer of other tables. Writes take the database exclusively, as they
+/// would serialize on the WAL anyway, and empty the slots of the tables
+/// they changed before they finish. A table the database's open
+/// transaction has changed is served as it was at BEGIN until it commits.
+pub struct SharedDatabase {
+    db: Mutex<Database>,
+    catalog: RwLock<Catalog>,
+}
+
+// The tables published, and what it takes to serve them without the database
+struct Catalog {
+    slots: HashMap<String, Arc<RwLock<Option<Published>>>>,
+    probe: Probe,
+}
+
+// A table's cached copy, and the stamp its file had when it was read or written
+#[derive(Clone)]
+struct Published {
+    table: Arc<Table>,
+    stamp: Option<Stamp>,
+}
+
+// The clock, the storage's stamps and where reads are recorded, taken from
+// the database after each write in case it changed them
+#[derive(Clone)]
+struct Probe {
+    clock: Arc<dyn Clock>,
+    stamps: Option<Stamps>,
+    reads: Recorder,
+}
+
+impl Probe {
+    fn of(db: &Database) -> Probe {
+        Probe { clock: Arc::clone(&db.clock_source), stamps: db.stamps(), reads: db.versions.reads.clone() }
+    }
+
+    // What `Database::snapshot` would give from `published`, unless its file
+    // has changed since, the same checks as `Database::load_table` makes
+    fn current(&self, name: &str, published: &Published) -> Option<Arc<Table>> {
+        let table = &published.table;
+        if table.external.as_ref().is_some_and(External::is_stale) {
+            return None;
+        }
+        if let (Some(stamp), Some(stamps)) = (published.stamp, &self.stamps) && stamps(&storage::table_key(name)) != Some(stamp) {
+            return None;
+        }
+        self.reads.read(name, table.ttl.is_some() || table.external.is_some());
+        Some(ttl::live(tombstones::visible(Arc::clone(table)), self.clock.now()))
+    }
+}
+
+impl SharedDatabase {
+    pub fn new(db: Database) -> SharedDatabase {
+        let catalog = Catalog { slots: HashMap::new(), probe: Probe::of(&db) };
+        SharedDatabase { db: Mutex::new(db), catalog: RwLock::new(catalog) }
+    }
+
+    /// A consistent view of a table, loading it into the cache if needed.
+    pub fn snapshot(&self, name: &str) -> Result<Arc<Table>, DbError> {
+        let (slot, probe) = {
+            let catalog = self.catalog();
+            (catalog.slots.get(name).cloned(), catalog.probe.clone())
+        };
+        let published = slot.and_then(|slot| read(&slot).clone());
+        if let Some(table) = published.and_then(|published| probe.current(name, &published)) {
+            return Ok(table);
+        }
+
+        // Not read yet, or read again: under the database's lock, which also
+        // keeps writers from emptying the slot before it is filled
+        let mut db = self.lock();
+        let table = db.committed_snapshot(name)?;
+        if let Some((cached, stamp)) = db.committed_entry(name) {
+            let published = Some(Published { table: cached, stamp });
+            let slot = Arc::clone(self.catalog_mut().slots.entry(name.to_string()).or_default());
+            *write(&slot) = published;
+        }
+        Ok(table)
+    }
+
+    /// Runs `f` on a snapshot of the table without holding any lock.
+    pub fn read<R>(&self, name: &str, f: impl FnOnce(&Table) -> R) -> Result<R, DbError> {
+        let table = self.snapshot(name)?;
+        Ok(f(&table))
+    }
+
+    /// Runs `f` with exclusive access, for anything that changes the database.
+    pub fn write<R>(&self, f: impl FnOnce(&mut Database) -> R) -> R {
+        let mut db = self.lock();
+        let before = db.version();
+        let result = f(&mut db);
+        self.unpublish(&db, before);
+        result
+    }
+
+    pub fn into_inner(self) -> Database {
+        self.db.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // Empties the slots of the tables changed since the database's version
+    // read `before`, so no reader is given their copies from before
+    fn unpublish(&self, db: &Database, before: u64) {
+        let mut catalog = self.catalog_mut();
+        catalog.slots.retain(|name, slot| {
+            let changed = db.changed_since(&BTreeSet::from([name.clone()]), before);
+            if changed {
+                *write(slot) = None;
+            }
+            !changed
+        });
+        catalog.probe = Probe::of(db);
+    }
+
+    // A panic in another thread may leave a statement half-applied to the
+    // cache, but the WAL still holds only complete records: carry on with
+    // the tables read again from storage and the log, any transaction the
+    // panic left open discarded, and nothing published from before, as the
+    // panic skipped `unpublish`
+    fn lock(&self) -> MutexGuard<'_, Database> {
+        self.db.lock().unwrap_or_else(|poisoned| {
+            let mut catalog = self.catalog_mut();
+            for slot in catalog.slots.values() {
+                *write(slot) = None;
+            }
+            catalog.slots.clear();
+            drop(catalog);
+            self.db.clear_poison();
+            let mut db = poisoned.into_inner();
+            db.reload();
+            db
+        })
+    }
+
+    fn catalog(&self) -> RwLockReadGuard<'_, Catalog> {
+        self.catalog.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn catalog_mut(&self) -> RwLockWriteGuard<'_, Catalog> {
+        self.catalog.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn read(slot: &RwLock<Option<Published>>) -> RwLockReadGuard<'_, Option<Published>> {
+    slot.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn write(slot: &RwLock<Option<Published>>) -> RwLockWriteGuard<'_, Option<Published>> {
+    slot.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Fails to compile if a field of `Database` stops being thread-safe
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Database>();
+    assert_send_sync::<SharedDatabase>();
+};
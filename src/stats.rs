@@ -0,0 +1,148 @@
+use std::collections::{BTreeMap, HashSet};
+
+use serde::{Serialize, Deserialize};
+
+use crate::parser::CmpOp;
+use crate::{DataType, Table};
+
+// Guesses used for columns that have not been analyzed
+const DEFAULT_EQ_SELECTIVITY: f64 = 0.1;
+const DEFAULT_RANGE_SELECTIVITY: f64 = 1.0 / 3.0;
+
+/// Most buckets in a column's histogram.
+const HISTOGRAM_BUCKETS: usize = 32;
+
+/// Statistics collected by ANALYZE. They describe the table as it was then
+/// and are only refreshed by running ANALYZE again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableStats {
+    pub rows: usize,
+    pub columns: BTreeMap<String, ColumnStats>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColumnStats {
+    pub distinct: usize,
+    pub min: Option<DataType>,
+    pub max: Option<DataType>,
+    /// For a numeric column, the bounds of an equi-depth histogram: bucket
+    /// `i` runs from `histogram[i]` to `histogram[i + 1]` and holds as many
+    /// rows as every other bucket.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub histogram: Vec<f64>,
+}
+
+pub fn analyze(table: &Table) -> TableStats {
+    let columns = table.columns.iter()
+        .map(|col| {
+            let values = &table.data[col];
+            let stats = ColumnStats {
+                distinct: values.iter().collect::<HashSet<_>>().len(),
+                min: values.iter().min().cloned(),
+                max: values.iter().max().cloned(),
+                histogram: histogram(values),
+            };
+            (col.clone(), stats)
+        })
+        .collect();
+    TableStats { rows: table.row_count(), columns }
+}
+
+/// The bounds of an equi-depth histogram of `values`, none unless they are
+/// all numbers.
+fn histogram(values: &[DataType]) -> Vec<f64> {
+    let Some(mut numbers) = values.iter().map(number).collect::<Option<Vec<f64>>>() else {
+        return Vec::new();
+    };
+    if numbers.is_empty() {
+        return Vec::new();
+    }
+    numbers.sort_by(f64::total_cmp);
+    let buckets = HISTOGRAM_BUCKETS.min(numbers.len());
+    (0..=buckets).map(|i| numbers[i * (numbers.len() - 1) / buckets]).collect()
+}
+
+/// Estimated fraction of rows satisfying `column op value`.
+pub fn selectivity(stats: Option<&TableStats>, column: &str, op: CmpOp, value: &DataType) -> f64 {
+    let Some(col) = stats.and_then(|s| s.columns.get(column)) else {
+        return match op {
+            CmpOp::Eq => DEFAULT_EQ_SELECTIVITY,
+            _ => DEFAULT_RANGE_SELECTIVITY,
+        };
+    };
+    match op {
+        CmpOp::Eq => 1.0 / col.distinct.max(1) as f64,
+        CmpOp::NotEq => 1.0 - 1.0 / col.distinct.max(1) as f64,
+        CmpOp::Lt | CmpOp::LtEq | CmpOp::Gt | CmpOp::GtEq => {
+            match (fraction_below(col, value), op) {
+                (Some(below), CmpOp::Lt | CmpOp::LtEq) => below,
+                (Some(below), _) => 1.0 - below,
+                (None, _) => DEFAULT_RANGE_SELECTIVITY,
+            }
+        }
+        CmpOp::In | CmpOp::NotIn => {
+            let listed = match value {
+                DataType::Array(items) => items.len(),
+                _ => 1,
+            };
+            let fraction = (listed as f64 / col.distinct.max(1) as f64).min(1.0);
+            if op == CmpOp::In { fraction } else { 1.0 - fraction }
+        }
+        CmpOp::Match | CmpOp::Contains => DEFAULT_EQ_SELECTIVITY,
+    }
+}
+
+/// Estimated fraction of rows whose `column` lies between `lower` and
+/// `upper`, each a `>`/`>=` or `<`/`<=` bound, or unbounded if None.
+pub fn range_selectivity(
+    stats: Option<&TableStats>,
+    column: &str,
+    lower: Option<&DataType>,
+    upper: Option<&DataType>,
+) -> f64 {
+    let col = stats.and_then(|s| s.columns.get(column));
+    let below = |value: &DataType| col.and_then(|col| fraction_below(col, value));
+    match (lower.map(below), upper.map(below)) {
+        (Some(Some(lower)), Some(Some(upper))) => (upper - lower).max(0.0),
+        // Otherwise each bound on its own, one that cannot be estimated getting the usual guess
+        (lower, upper) => {
+            let above_lower = lower.map_or(1.0, |below| below.map_or(DEFAULT_RANGE_SELECTIVITY, |below| 1.0 - below));
+            let below_upper = upper.map_or(1.0, |below| below.unwrap_or(DEFAULT_RANGE_SELECTIVITY));
+            above_lower * below_upper
+        }
+    }
+}
+
+// Interpolates within the histogram's bucket holding `value`, or between
+// min and max without one; only numbers can be interpolated.
+fn fraction_below(col: &ColumnStats, value: &DataType) -> Option<f64> {
+    let value = number(value)?;
+    if col.histogram.len() >= 2 {
+        let bounds = &col.histogram;
+        let buckets = (bounds.len() - 1) as f64;
+        if value <= bounds[0] {
+            return Some(0.0);
+        }
+        // The first bucket ending at or above the value
+        let i = bounds[1..].partition_point(|&bound| bound < value);
+        if i == bounds.len() - 1 {
+            return Some(1.0);
+        }
+        let (start, end) = (bounds[i], bounds[i + 1]);
+        let within = if end > start { (value - start) / (end - start) } else { 1.0 };
+        return Some((i as f64 + within) / buckets);
+    }
+    let (min, max) = (number(col.min.as_ref()?)?, number(col.max.as_ref()?)?);
+    if max <= min {
+        return Some(if value > min { 1.0 } else { 0.0 });
+    }
+    Some(((value - min) / (max - min)).clamp(0.0, 1.0))
+}
+
+fn number(value: &DataType) -> Option<f64> {
+    match value {
+        DataType::Integer32(i) => Some(*i as f64),
+        DataType::Float32(f) => Some(*f as f64),
+        DataType::String(_) | DataType::Array(_) => None,
+    }
+}
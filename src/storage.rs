@@ -0,0 +1,709 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD;
+use flate2::Compression as GzLevel;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Serialize, Deserialize};
+
+use crate::Table;
+use crate::engines::{self, Engine};
+use crate::error::DbError;
+
+/// When a blob was last modified, and its size in bytes.
+pub type Stamp = (SystemTime, u64);
+
+/// Gives the stamp of a blob by key, without borrowing the backend, for
+/// threads that check a table is current without locking the database.
+pub type Stamps = Arc<dyn Fn(&str) -> Option<Stamp> + Send + Sync>;
+
+/// Where a database keeps its named blobs (table files, settings).
+/// Keys containing a `/` live in a sub-namespace and are not listed by `keys`.
+pub trait Storage: Send + Sync {
+    fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>>;
+    /// The first line of a blob, without its newline. Backends that can
+    /// should stop reading there.
+    fn read_first_line(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.read(key)?.map(|mut bytes| {
+            if let Some(newline) = bytes.iter().position(|b| *b == b'\n') {
+                bytes.truncate(newline);
+            }
+            bytes
+        }))
+    }
+    /// Must replace the blob atomically.
+    fn write(&mut self, key: &str, bytes: &[u8]) -> io::Result<()>;
+    fn remove(&mut self, key: &str) -> io::Result<bool>;
+    fn rename(&mut self, from: &str, to: &str) -> io::Result<()>;
+    fn exists(&self, key: &str) -> bool;
+    /// When a blob last changed and its size, to notice it being written by
+    /// another program. Backends that cannot tell give None.
+    fn stamp(&self, _key: &str) -> Option<Stamp> {
+        None
+    }
+    /// `stamp` as a function of its own, for backends that can tell.
+    fn stamps(&self) -> Option<Stamps> {
+        None
+    }
+    fn keys(&self) -> io::Result<Vec<String>>;
+    /// Bytes taken by everything kept, sub-namespaces included, for quotas.
+    fn size(&self) -> io::Result<u64>;
+    /// Deletes leftovers of writes interrupted by a crash, returning what was removed.
+    fn discard_torn_writes(&mut self) -> io::Result<Vec<String>>;
+}
+
+/// One file per blob inside a directory.
+pub struct DirStorage {
+    dir: PathBuf,
+}
+
+impl DirStorage {
+    pub fn new(dir: &Path) -> DirStorage {
+        DirStorage { dir: dir.to_path_buf() }
+    }
+}
+
+impl Storage for DirStorage {
+    fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        match fs::read(self.dir.join(key)) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn read_first_line(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        let file = match File::open(self.dir.join(key)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut line = Vec::new();
+        BufReader::new(file).read_until(b'\n', &mut line)?;
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+        Ok(Some(line))
+    }
+
+    fn write(&mut self, key: &str, bytes: &[u8]) -> io::Result<()> {
+        write_atomic(&self.dir.join(key), bytes)
+    }
+
+    fn remove(&mut self, key: &str) -> io::Result<bool> {
+        match fs::remove_file(self.dir.join(key)) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> io::Result<()> {
+        let target = self.dir.join(to);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(self.dir.join(from), target)
+    }
+
+    fn exists(&self, key: &str) -> bool {
+        self.dir.join(key).exists()
+    }
+
+    fn stamp(&self, key: &str) -> Option<Stamp> {
+        file_stamp(&self.dir.join(key))
+    }
+
+    fn stamps(&self) -> Option<Stamps> {
+        let dir = self.dir.clone();
+        Some(Arc::new(move |key| file_stamp(&dir.join(key))))
+    }
+
+    fn keys(&self) -> io::Result<Vec<String>> {
+        let mut keys = Vec::new();
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(keys),
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                keys.push(entry.file_name().to_string_lossy().to_string());
+            }
+        }
+        Ok(keys)
+    }
+
+    // The whole directory, the log and sub-namespaces included
+    fn size(&self) -> io::Result<u64> {
+        dir_size(&self.dir)
+    }
+
+    fn discard_torn_writes(&mut self) -> io::Result<Vec<String>> {
+        let mut discarded = Vec::new();
+        for key in self.keys()? {
+            // A temp file only survives if we died before its rename, so the
+            // original next to it is still the last complete version
+            if key.ends_with(".tmp") {
+                fs::remove_file(self.dir.join(&key))?;
+                discarded.push(self.dir.join(key).display().to_string());
+            }
+        }
+        Ok(discarded)
+    }
+}
+
+// Single-file layout: magic, u64 LE directory length, JSON directory, blob data
+const RDB_MAGIC: &[u8; 4] = b"RDB1";
+
+#[derive(Serialize, Deserialize)]
+struct DirEntry {
+    key: String,
+    offset: u64, // Relative to the start of the data section
+    len: u64,
+}
+
+/// Every blob packed into one `.rdb` file with an internal directory. The whole
+/// file is rewritten atomically on each write.
+pub struct FileStorage {
+    path: PathBuf,
+    blobs: BTreeMap<String, Vec<u8>>,
+}
+
+impl FileStorage {
+    pub fn open(path: &Path) -> io::Result<FileStorage> {
+        let mut storage = FileStorage { path: path.to_path_buf(), blobs: BTreeMap::new() };
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(storage),
+            Err(e) => return Err(e),
+        };
+
+        let invalid = |reason: &str| io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is not a valid database file: {}", path.display(), reason),
+        );
+        let rest = bytes.strip_prefix(RDB_MAGIC.as_slice()).ok_or_else(|| invalid("bad magic"))?;
+        if rest.len() < 8 {
+            return Err(invalid("truncated directory"));
+        }
+        let data_start = usize::try_from(u64::from_le_bytes(rest[..8].try_into().unwrap())).ok()
+            .and_then(|dir_len| dir_len.checked_add(8))
+            .filter(|&end| end <= rest.len())
+            .ok_or_else(|| invalid("truncated directory"))?;
+        let directory: Vec<DirEntry> = serde_json::from_slice(&rest[8..data_start])
+            .map_err(|_| invalid("unreadable directory"))?;
+
+        let data = &rest[data_start..];
+        for entry in directory {
+            let blob = usize::try_from(entry.offset).ok()
+                .zip(usize::try_from(entry.len).ok())
+                .and_then(|(start, len)| data.get(start..start.checked_add(len)?))
+                .ok_or_else(|| invalid("blob out of bounds"))?;
+            storage.blobs.insert(entry.key, blob.to_vec());
+        }
+        Ok(storage)
+    }
+
+    // Writes the blobs with `changes` made to them, a blob for each key or
+    // None to remove it, and only once that is on disk makes them in memory,
+    // so a failed write leaves both as they were
+    fn commit(&mut self, changes: Vec<(String, Option<Vec<u8>>)>) -> io::Result<()> {
+        let mut blobs: BTreeMap<&str, &[u8]> = self.blobs.iter().map(|(key, blob)| (key.as_str(), blob.as_slice())).collect();
+        for (key, blob) in &changes {
+            match blob {
+                Some(blob) => blobs.insert(key, blob),
+                None => blobs.remove(key.as_str()),
+            };
+        }
+        let mut directory = Vec::new();
+        let mut data = Vec::new();
+        for (key, blob) in blobs {
+            directory.push(DirEntry { key: key.to_string(), offset: data.len() as u64, len: blob.len() as u64 });
+            data.extend_from_slice(blob);
+        }
+        let dir_bytes = serde_json::to_vec(&directory)?;
+
+        let mut bytes = RDB_MAGIC.to_vec();
+        bytes.extend((dir_bytes.len() as u64).to_le_bytes());
+        bytes.extend(dir_bytes);
+        bytes.extend(data);
+        write_atomic(&self.path, &bytes)?;
+
+        for (key, blob) in changes {
+            match blob {
+                Some(blob) => self.blobs.insert(key, blob),
+                None => self.blobs.remove(&key),
+            };
+        }
+        Ok(())
+    }
+}
+
+impl Storage for FileStorage {
+    fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.blobs.get(key).cloned())
+    }
+
+    fn write(&mut self, key: &str, bytes: &[u8]) -> io::Result<()> {
+        self.commit(vec![(key.to_string(), Some(bytes.to_vec()))])
+    }
+
+    fn remove(&mut self, key: &str) -> io::Result<bool> {
+        if !self.blobs.contains_key(key) {
+            return Ok(false);
+        }
+        self.commit(vec![(key.to_string(), None)])?;
+        Ok(true)
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> io::Result<()> {
+        let blob = self.blobs.get(from)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, from.to_string()))?;
+        self.commit(vec![(from.to_string(), None), (to.to_string(), Some(blob.clone()))])
+    }
+
+    fn exists(&self, key: &str) -> bool {
+        self.blobs.contains_key(key)
+    }
+
+    fn keys(&self) -> io::Result<Vec<String>> {
+        Ok(self.blobs.keys().filter(|k| !k.contains('/')).cloned().collect())
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        match fs::metadata(&self.path) {
+            Ok(metadata) => Ok(metadata.len()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn discard_torn_writes(&mut self) -> io::Result<Vec<String>> {
+        let tmp_path = tmp_path(&self.path);
+        if !tmp_path.exists() {
+            return Ok(Vec::new());
+        }
+        fs::remove_file(&tmp_path)?;
+        Ok(vec![tmp_path.display().to_string()])
+    }
+}
+
+/// Blobs held in RAM only; nothing touches disk.
+#[derive(Default)]
+pub struct MemoryStorage {
+    blobs: BTreeMap<String, Vec<u8>>,
+}
+
+impl Storage for MemoryStorage {
+    fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.blobs.get(key).cloned())
+    }
+
+    fn write(&mut self, key: &str, bytes: &[u8]) -> io::Result<()> {
+        self.blobs.insert(key.to_string(), bytes.to_vec());
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &str) -> io::Result<bool> {
+        Ok(self.blobs.remove(key).is_some())
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> io::Result<()> {
+        let blob = self.blobs.remove(from)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, from.to_string()))?;
+        self.blobs.insert(to.to_string(), blob);
+        Ok(())
+    }
+
+    fn exists(&self, key: &str) -> bool {
+        self.blobs.contains_key(key)
+    }
+
+    fn keys(&self) -> io::Result<Vec<String>> {
+        Ok(self.blobs.keys().filter(|k| !k.contains('/')).cloned().collect())
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.blobs.values().map(|bytes| bytes.len() as u64).sum())
+    }
+
+    fn discard_torn_writes(&mut self) -> io::Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Another storage, read but never written: every write fails, so nothing
+/// can reach the files underneath by any path.
+pub struct ReadOnlyStorage {
+    inner: Box<dyn Storage>,
+}
+
+impl ReadOnlyStorage {
+    pub fn new(inner: Box<dyn Storage>) -> ReadOnlyStorage {
+        ReadOnlyStorage { inner }
+    }
+}
+
+fn file_stamp(path: &Path) -> Option<Stamp> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+fn read_only() -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, "the database is open read-only")
+}
+
+impl Storage for ReadOnlyStorage {
+    fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        self.inner.read(key)
+    }
+
+    fn read_first_line(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        self.inner.read_first_line(key)
+    }
+
+    fn write(&mut self, _key: &str, _bytes: &[u8]) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn remove(&mut self, _key: &str) -> io::Result<bool> {
+        Err(read_only())
+    }
+
+    fn rename(&mut self, _from: &str, _to: &str) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn exists(&self, key: &str) -> bool {
+        self.inner.exists(key)
+    }
+
+    fn stamp(&self, key: &str) -> Option<Stamp> {
+        self.inner.stamp(key)
+    }
+
+    fn stamps(&self) -> Option<Stamps> {
+        self.inner.stamps()
+    }
+
+    fn keys(&self) -> io::Result<Vec<String>> {
+        self.inner.keys()
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        self.inner.size()
+    }
+
+    // Leftovers are left for the next process opening the database to write
+    fn discard_torn_writes(&mut self) -> io::Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+}
+
+fn dir_size(dir: &Path) -> io::Result<u64> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    let mut size = 0;
+    for entry in entries {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else if file_type.is_file() {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    PathBuf::from(tmp)
+}
+
+/// Replaces `path` with `bytes` so that readers see either the old file or the
+/// new one, never a torn mix: write to `<path>.tmp`, fsync, then rename over.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let tmp_path = tmp_path(path);
+    let mut tmp = File::create(&tmp_path)?;
+    tmp.write_all(bytes)?;
+    tmp.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+
+    // Persist the rename itself
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Takes an exclusive advisory lock on `path` (created if missing), held
+/// until the returned file is dropped. Fails right away if another process
+/// holds it.
+pub fn lock_exclusive(path: &Path) -> io::Result<File> {
+    let file = OpenOptions::new().create(true).truncate(false).write(true).open(path)?;
+    match file.try_lock() {
+        Ok(()) => Ok(file),
+        Err(TryLockError::WouldBlock) => Err(io::Error::new(
+            io::ErrorKind::WouldBlock,
+            format!("database is locked by another process ({})", path.display()),
+        )),
+        Err(TryLockError::Error(e)) => Err(e),
+    }
+}
+
+pub const SETTINGS_KEY: &str = "database.conf";
+pub const USERS_KEY: &str = "users.conf";
+pub const VIEWS_KEY: &str = "views.conf";
+pub const SEQUENCES_KEY: &str = "sequences.conf";
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+}
+
+impl Compression {
+    pub fn parse(name: &str) -> Option<Compression> {
+        match name.to_lowercase().as_str() {
+            "none" => Some(Compression::None),
+            "gzip" => Some(Compression::Gzip),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Gzip => "gzip",
+        }
+    }
+}
+
+/// Settings stored with the database rather than the process.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DbSettings {
+    #[serde(default)]
+    pub compression: Compression,
+    // Where checkpoints copy the log before emptying it, if anywhere
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wal_archive: Option<String>,
+}
+
+pub fn table_key(name: &str) -> String {
+    format!("{}.json", name)
+}
+
+/// Key of the saved index entries for a table.
+pub fn index_key(name: &str) -> String {
+    format!("{}.idx", name)
+}
+
+/// Key of the `n`th blob of rows appended beside a table's file.
+pub fn append_key(name: &str, n: usize) -> String {
+    format!("{}.{}.append", name, n)
+}
+
+/// The table an `append_key` is of, if `key` is one.
+pub fn appended_table(key: &str) -> Option<&str> {
+    key.strip_suffix(".append")?.rsplit_once('.').map(|(name, _)| name)
+}
+
+// Table blobs start with a header line such as `#rustdb crc32=1a2b3c4d codec=gzip format=2 rows=3 schema=eyJu...`.
+// The checksum covers the payload exactly as stored, i.e. after compression.
+// `rows` and `schema` (the table without its rows, as base64 JSON) let the
+// header alone answer questions about the table; files from before they
+// were added lack them.
+const HEADER_PREFIX: &str = "#rustdb ";
+
+/// The layout of the table files this release writes. Files from before
+/// the header carried a format are format 1.
+/// - 1: the first layout.
+/// - 2: repeating string columns saved dictionary-encoded.
+/// - 3: the table's engine, and the `binary` engine's layout.
+pub const FORMAT_VERSION: u32 = 3;
+
+/// Brings the JSON of a table saved in `format` up to `FORMAT_VERSION`, one
+/// format at a time, so that older files read like new ones.
+fn upgrade(format: u32, table: serde_json::Value) -> serde_json::Value {
+    (format..FORMAT_VERSION).fold(table, |table, from| match from {
+        // Columns saved plain are still read as they are
+        1 => table,
+        // Tables without an engine are JSON, as they were
+        2 => table,
+        _ => unreachable!("every older format has a step up"),
+    })
+}
+
+pub fn encode_table(table: &Table, codec: Compression) -> io::Result<Vec<u8>> {
+    let body = match table.engine {
+        Engine::Json => serde_json::to_vec_pretty(table)?,
+        Engine::Binary => engines::encode(table)?,
+    };
+    let payload = match codec {
+        Compression::None => body,
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), GzLevel::default());
+            encoder.write_all(&body)?;
+            encoder.finish()?
+        }
+    };
+
+    let mut header = format!("{}crc32={:08x}", HEADER_PREFIX, crc32fast::hash(&payload));
+    if codec != Compression::None {
+        header.push_str(&format!(" codec={}", codec.name()));
+    }
+    if !table.engine.is_json() {
+        header.push_str(&format!(" engine={}", table.engine.name()));
+    }
+    header.push_str(&format!(" format={}", FORMAT_VERSION));
+    let deleted = (0..table.row_count()).filter(|&row| table.is_deleted(row)).count();
+    let schema = STANDARD.encode(serde_json::to_vec(&table.schema_only())?);
+    header.push_str(&format!(" rows={} schema={}\n", table.row_count() - deleted, schema));
+
+    let mut bytes = header.into_bytes();
+    bytes.extend(payload);
+    Ok(bytes)
+}
+
+/// A table blob, verified and decompressed.
+pub struct Payload {
+    pub codec: Compression,
+    pub format: u32,
+    pub engine: Engine,
+    pub body: Vec<u8>, // Laid out as `engine` says
+}
+
+/// Verifies and decompresses a table blob.
+pub fn decode_payload(name: &str, bytes: &[u8]) -> Result<Payload, DbError> {
+    let corrupt = |reason: String| DbError::CorruptTable { table: name.to_string(), reason };
+
+    // Files written before checksums were introduced have no header
+    let Some(rest) = bytes.strip_prefix(HEADER_PREFIX.as_bytes()) else {
+        return Ok(Payload { codec: Compression::None, format: 1, engine: Engine::Json, body: bytes.to_vec() });
+    };
+
+    let newline = rest.iter().position(|b| *b == b'\n')
+        .ok_or_else(|| corrupt("truncated header".to_string()))?;
+    let header = std::str::from_utf8(&rest[..newline])
+        .map_err(|_| corrupt("malformed header".to_string()))?;
+    let payload = &rest[newline + 1..];
+
+    let mut expected = None;
+    let mut codec = Compression::None;
+    let mut format = 1;
+    let mut engine = Engine::Json;
+    for field in header.split_whitespace() {
+        match field.split_once('=') {
+            Some(("crc32", hex)) => expected = u32::from_str_radix(hex, 16).ok(),
+            Some(("codec", name)) => {
+                codec = Compression::parse(name)
+                    .ok_or_else(|| corrupt(format!("unknown codec '{}'", name)))?;
+            }
+            Some(("engine", engine_name)) => {
+                engine = Engine::parse(engine_name)
+                    .ok_or_else(|| corrupt(format!("unknown engine '{}'", engine_name)))?;
+            }
+            Some(("format", number)) => {
+                format = number.parse().map_err(|_| corrupt(format!("malformed format '{}'", number)))?;
+            }
+            _ => {}
+        }
+    }
+
+    let expected = expected.ok_or_else(|| corrupt("malformed checksum header".to_string()))?;
+    let actual = crc32fast::hash(payload);
+    if actual != expected {
+        return Err(corrupt(format!(
+            "checksum mismatch (expected {:08x}, found {:08x})", expected, actual
+        )));
+    }
+
+    let body = match codec {
+        Compression::None => payload.to_vec(),
+        Compression::Gzip => {
+            let mut body = Vec::new();
+            GzDecoder::new(payload).read_to_end(&mut body)
+                .map_err(|e| corrupt(format!("decompression failed: {}", e)))?;
+            body
+        }
+    };
+    if format > FORMAT_VERSION {
+        return Err(DbError::NewerFormat { table: name.to_string(), format });
+    }
+    Ok(Payload { codec, format, engine, body })
+}
+
+/// Reads a table blob, upgrading it from the format it was saved in.
+pub fn decode_table(name: &str, bytes: &[u8]) -> Result<Table, DbError> {
+    let Payload { format, engine, body, .. } = decode_payload(name, bytes)?;
+    let corrupt = |reason: String| DbError::CorruptTable {
+        table: name.to_string(),
+        reason: format!("{} (saved in format {})", reason, format),
+    };
+    // Only formats from 3 on have engines other than JSON
+    if engine == Engine::Binary {
+        return engines::decode(&body).map_err(corrupt);
+    }
+    if format == FORMAT_VERSION {
+        return serde_json::from_slice(&body).map_err(|e| corrupt(e.to_string()));
+    }
+    let table = serde_json::from_slice(&body).map_err(|e| corrupt(e.to_string()))?;
+    serde_json::from_value(upgrade(format, table)).map_err(|e| corrupt(e.to_string()))
+}
+
+/// What a table file's header tells about the table without reading its rows.
+pub struct Summary {
+    pub rows: usize,       // Rows saved, soft-deleted ones aside
+    pub definition: Table, // The table without its rows
+}
+
+/// The summary in the header line of a table blob, if it has one. Only a
+/// header that cannot be read as one fails: the caller can read the whole
+/// table instead of one with no summary.
+pub fn decode_summary(name: &str, header: &[u8]) -> Result<Option<Summary>, DbError> {
+    let corrupt = |reason: String| DbError::CorruptTable { table: name.to_string(), reason };
+    let Some(header) = header.strip_prefix(HEADER_PREFIX.as_bytes()) else {
+        return Ok(None);
+    };
+    let header = std::str::from_utf8(header).map_err(|_| corrupt("malformed header".to_string()))?;
+    let mut rows = None;
+    let mut schema = None;
+    for field in header.split_whitespace() {
+        match field.split_once('=') {
+            Some(("rows", number)) => rows = Some(number.parse().map_err(|_| corrupt(format!("malformed row count '{}'", number)))?),
+            Some(("schema", encoded)) => schema = Some(encoded),
+            _ => {}
+        }
+    }
+    let (Some(rows), Some(schema)) = (rows, schema) else {
+        return Ok(None);
+    };
+    let json = STANDARD.decode(schema).map_err(|_| corrupt("schema in the header is not base64".to_string()))?;
+    let mut definition: Table = serde_json::from_slice(&json)
+        .map_err(|e| corrupt(format!("unreadable schema in the header: {}", e)))?;
+    definition.rebuild_indexes();
+    Ok(Some(Summary { rows, definition }))
+}
+
+pub struct FileStats {
+    pub codec: Compression,
+    pub format: u32,
+    pub engine: Engine,
+    pub raw_bytes: u64,  // Size of the uncompressed body
+    pub file_bytes: u64, // Size as stored, including the header
+    pub index_bytes: u64, // Size of the saved index entries, if any
+}
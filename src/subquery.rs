@@ -0,0 +1,159 @@
+//! Subqueries in a WHERE: `<expr> [NOT] IN (SELECT <column> ...)` and
+//! `[NOT] EXISTS (SELECT ...)`. Each runs once, before the statement, and
+//! its rows become an `IN` list. An EXISTS may refer to the outer query
+//! through one `=` between an outer and an inner column, which makes it
+//! `<outer column> [NOT] IN (SELECT <inner column> ...)` over the other
+//! conditions: a semi-join, or with NOT an anti-join. Values are never
+//! NULL, so NOT IN keeps every row that no value of the subquery equals,
+//! and all rows if it gives none.
+
+use crate::database::Database;
+use crate::error::DbError;
+use crate::expr::Expr;
+use crate::join;
+use crate::parser::{CmpOp, Order, Predicate, Statement, Subquery, TableRef};
+use crate::table::array_literal;
+use crate::DataType;
+
+impl Statement {
+    /// The subqueries in the conditions of the statement, or of the query
+    /// it runs.
+    pub fn subqueries(&self) -> Vec<&Statement> {
+        match self {
+            Statement::Select { filter, .. }
+            | Statement::Delete { filter, .. }
+            | Statement::Update { filter, .. }
+            | Statement::Undelete { filter, .. }
+            | Statement::Purge { filter, .. } => filter.iter()
+                .filter_map(|predicate| predicate.subquery.as_ref())
+                .map(Subquery::query)
+                .flat_map(|query| std::iter::once(query).chain(query.subqueries()))
+                .collect(),
+            Statement::Explain { statement: query, .. } | Statement::Declare { query, .. } | Statement::Export { query, .. } => {
+                query.subqueries()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Database {
+    /// `statement` with every subquery in its conditions run and replaced
+    /// by the list of its values.
+    pub fn resolve_subqueries(&mut self, statement: Statement) -> Result<Statement, DbError> {
+        if statement.subqueries().is_empty() {
+            return Ok(statement);
+        }
+        Ok(match statement {
+            Statement::Select { from, joins, columns, filter, order } => {
+                let outer = TableRef::list(from.clone(), joins.clone());
+                let filter = self.resolve_filter(&outer, filter)?;
+                Statement::Select { from, joins, columns, filter, order }
+            }
+            Statement::Delete { table, filter, returning } => {
+                let filter = self.resolve_filter(&[TableRef::current(table.clone())], filter)?;
+                Statement::Delete { table, filter, returning }
+            }
+            Statement::Update { table, set, filter, returning } => {
+                let filter = self.resolve_filter(&[TableRef::current(table.clone())], filter)?;
+                Statement::Update { table, set, filter, returning }
+            }
+            Statement::Undelete { table, filter } => {
+                let filter = self.resolve_filter(&[TableRef::current(table.clone())], filter)?;
+                Statement::Undelete { table, filter }
+            }
+            Statement::Purge { table, filter } => {
+                let filter = self.resolve_filter(&[TableRef::current(table.clone())], filter)?;
+                Statement::Purge { table, filter }
+            }
+            Statement::Explain { statement, analyze } => {
+                Statement::Explain { statement: Box::new(self.resolve_subqueries(*statement)?), analyze }
+            }
+            Statement::Declare { name, query } => Statement::Declare { name, query: Box::new(self.resolve_subqueries(*query)?) },
+            Statement::Export { query, path, format } => {
+                Statement::Export { query: Box::new(self.resolve_subqueries(*query)?), path, format }
+            }
+            statement => statement,
+        })
+    }
+
+    // `filter` of a query reading `outer`, its subqueries run
+    fn resolve_filter(&mut self, outer: &[TableRef], filter: Vec<Predicate>) -> Result<Vec<Predicate>, DbError> {
+        filter.into_iter()
+            .map(|predicate| {
+                let Some(subquery) = predicate.subquery else { return Ok(predicate) };
+                let (left, query) = match subquery {
+                    Subquery::In(query) => (predicate.left, *query),
+                    Subquery::Exists(query) => correlate(outer, *query)?,
+                };
+                let Statement::Select { from, joins, columns, filter, order } = self.resolve_subqueries(query)? else {
+                    unreachable!("the parser only accepts a SELECT as a subquery")
+                };
+                let rows = self.select_from(&TableRef::list(from, joins), &columns, &filter, &order)?;
+                if rows.columns.len() != 1 {
+                    return Err(DbError::Syntax(format!("a subquery after IN gives one column, not {}", rows.columns.len())));
+                }
+                // A query on one table names its columns plainly
+                let left = match outer {
+                    [table] => join::unqualify(table.name(), &left)?,
+                    _ => left,
+                };
+                let mut values: Vec<DataType> = rows.rows.into_iter().map(|mut row| row.remove(0)).collect();
+                values.sort();
+                values.dedup();
+                let value = array_literal(values.iter().map(DataType::to_string));
+                Ok(Predicate { left, op: predicate.op, value, against: None, subquery: None })
+            })
+            .collect()
+    }
+}
+
+/// What an EXISTS asks as an IN: the outer column it is correlated on and
+/// the query giving the inner column's values under the other conditions,
+/// or, uncorrelated, 1 and a query giving 1 if it has any row.
+fn correlate(outer: &[TableRef], query: Statement) -> Result<(Expr, Statement), DbError> {
+    let Statement::Select { from, joins, filter, .. } = query else {
+        unreachable!("the parser only accepts a SELECT as a subquery")
+    };
+    let inner = TableRef::list(from.clone(), joins.clone());
+    // A column qualified by a table of the outer query that the subquery does not read itself
+    let is_outer = |name: &str| name.split_once('.').is_some_and(|(qualifier, _)| {
+        outer.iter().any(|table| table.name() == qualifier) && !inner.iter().any(|table| table.name() == qualifier)
+    });
+    let mut correlation = None;
+    let mut rest = Vec::new();
+    for predicate in filter {
+        let refers_out = |expr: &Expr| expr.columns().into_iter().any(is_outer);
+        if !refers_out(&predicate.left) && !predicate.against.as_ref().is_some_and(refers_out) {
+            rest.push(predicate);
+            continue;
+        }
+        let pair = match (&predicate.left, &predicate.against) {
+            (Expr::Column(left), Some(Expr::Column(right))) if predicate.op == CmpOp::Eq => match (is_outer(left), is_outer(right)) {
+                (true, false) => Some((left.clone(), right.clone())),
+                (false, true) => Some((right.clone(), left.clone())),
+                _ => None,
+            },
+            _ => None,
+        };
+        match pair {
+            Some(pair) if correlation.is_none() => correlation = Some(pair),
+            _ => {
+                return Err(DbError::Syntax(format!(
+                    "EXISTS can refer to the outer query through one '=' between an outer and an inner column, not '{}'", predicate
+                )));
+            }
+        }
+    }
+    Ok(match correlation {
+        Some((outer_column, inner_column)) => (
+            Expr::Column(outer_column),
+            Statement::Select { from, joins, columns: vec![Expr::Column(inner_column)], filter: rest, order: Order::default() },
+        ),
+        None => {
+            let one = Expr::Literal(DataType::Integer32(1));
+            let order = Order { limit: Some(1), ..Order::default() };
+            (one.clone(), Statement::Select { from, joins, columns: vec![one], filter: rest, order })
+        }
+    })
+}
@@ -0,0 +1,670 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use serde::{Serialize, Deserialize};
+
+use crate::collation::Collation;
+use crate::engines::Engine;
+use crate::error::DbError;
+use crate::expr::{cast, Expr, CAST_TYPES};
+use crate::external::External;
+use crate::functions::Functions;
+use crate::history::History;
+use crate::index::{Index, IndexDef, IndexKind, Key};
+use crate::parser;
+use crate::partition::Partitioning;
+use crate::stats::TableStats;
+use crate::timestamps;
+use crate::triggers::Trigger;
+use crate::wal::WalOp;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DataType {
+    String(String),
+    Integer32(i32),
+    Float32(f32),
+    Array(Vec<DataType>), // Of a `<type>[]` column, all of that type
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Table {
+    pub name: String,
+    pub fields: BTreeMap<String, String>, // Schema: "age" -> "int"
+    pub columns: Vec<String>,            // KEEPS ORDER: ["id", "name", "age"]
+    #[serde(with = "crate::dictionary")]
+    pub data: BTreeMap<String, Vec<DataType>>, // Repeating strings saved dictionary-encoded
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub generated: BTreeMap<String, Expr>, // Computed columns: "total" -> price * quantity
+    #[serde(default)]
+    pub lsn: u64,                        // Last WAL record contained in the saved file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub primary_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<TableStats>,       // Collected by ANALYZE
+    #[serde(default, rename = "indexes", skip_serializing_if = "Vec::is_empty")]
+    pub index_defs: Vec<IndexDef>,
+    #[serde(skip)]
+    pub indexes: Vec<Index>,             // Entries for index_defs, in the same order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub triggers: Vec<Trigger>,
+    #[serde(default)]
+    pub modified: u64,                   // Creation or last change, in seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partitioning: Option<Partitioning>,
+    #[serde(skip)]
+    pub stored_at: Vec<(usize, usize)>,  // Of a partitioned table, the partition and position in it of each row
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub external: Option<External>,      // Where the rows are read from, if not stored with the table
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history: Option<History>,        // Changes kept for AS OF, if set to keep them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<String>,             // The column holding when each row expires, if rows do
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tombstones: Option<Vec<bool>>,   // Whether each row is soft-deleted, if DELETE only marks rows
+    #[serde(default, skip_serializing_if = "Engine::is_json")]
+    pub engine: Engine,                  // How the rows are laid out in the table's file
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub collations: BTreeMap<String, Collation>, // Of string columns not compared as plain bytes
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub timestamps: bool,                // Whether the engine keeps created_at and updated_at
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sequences: BTreeMap<String, String>, // Columns whose DEFAULT is a sequence's next value: "id" -> "ids"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub column_comments: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_rows: Option<usize>,         // Rows it may hold before inserts fail, if limited
+}
+
+impl Table {
+    /// An empty table, made at `now`. `lsn` should be the WAL's last LSN, so
+    /// older log records for a dropped table of the same name are never
+    /// replayed into it.
+    pub fn new(name: &str, cols: Vec<(String, String)>, primary_key: Option<String>, lsn: u64, now: u64) -> Table {
+        // The primary key is enforced and looked up through an index maintained like any other
+        let index_defs: Vec<IndexDef> = primary_key.iter()
+            .map(|column| IndexDef {
+                name: format!("{}_pkey", name),
+                columns: vec![column.clone()],
+                kind: IndexKind::BTree,
+                unique: true,
+            })
+            .collect();
+
+        let mut fields: BTreeMap<String, String> = BTreeMap::new();
+        let mut data: BTreeMap<String, Vec<DataType>> = BTreeMap::new();
+        let mut columns: Vec<String> = Vec::new(); // Store order
+
+        for (col, data_type) in cols {
+            fields.insert(col.clone(), data_type);
+            columns.push(col.clone());
+            data.insert(col, Vec::new());
+        }
+
+        let mut table = Table {
+            name: name.to_string(),
+            fields,
+            columns,
+            data,
+            generated: BTreeMap::new(),
+            lsn,
+            primary_key,
+            stats: None,
+            index_defs,
+            indexes: Vec::new(),
+            triggers: Vec::new(),
+            modified: now,
+            partitioning: None,
+            stored_at: Vec::new(),
+            external: None,
+            history: None,
+            ttl: None,
+            tombstones: None,
+            engine: Engine::default(),
+            collations: BTreeMap::new(),
+            timestamps: false,
+            sequences: BTreeMap::new(),
+            comment: None,
+            column_comments: BTreeMap::new(),
+            max_rows: None,
+        };
+        table.rebuild_indexes();
+        table
+    }
+
+    /// The table with no rows: the same columns, indexes, triggers and
+    /// everything else that is saved with it.
+    pub fn schema_only(&self) -> Table {
+        let mut table = Table {
+            name: self.name.clone(),
+            fields: self.fields.clone(),
+            columns: self.columns.clone(),
+            data: self.columns.iter().map(|col| (col.clone(), Vec::new())).collect(),
+            generated: self.generated.clone(),
+            lsn: self.lsn,
+            primary_key: self.primary_key.clone(),
+            stats: self.stats.clone(),
+            index_defs: self.index_defs.clone(),
+            indexes: Vec::new(),
+            triggers: self.triggers.clone(),
+            modified: self.modified,
+            partitioning: self.partitioning.clone(),
+            stored_at: Vec::new(),
+            external: self.external.clone(),
+            // Without rows there is nothing to undo, and the table is as it
+            // has been since its last change
+            history: self.history.as_ref().map(|history| History::new(history.retention, self.modified)),
+            ttl: self.ttl.clone(),
+            tombstones: self.tombstones.as_ref().map(|_| Vec::new()),
+            engine: self.engine,
+            collations: self.collations.clone(),
+            timestamps: self.timestamps,
+            sequences: self.sequences.clone(),
+            comment: self.comment.clone(),
+            column_comments: self.column_comments.clone(),
+            max_rows: self.max_rows,
+        };
+        table.rebuild_indexes();
+        table
+    }
+
+    /// A copy of the table without `rows`, which must be in ascending order.
+    pub(crate) fn without_rows(&self, rows: &[usize]) -> Table {
+        let mut kept = self.schema_only();
+        for column in &self.columns {
+            let mut left_out = rows.iter().peekable();
+            let values = self.data[column].iter().enumerate().filter(|(row, _)| left_out.next_if_eq(&row).is_none());
+            kept.data.insert(column.clone(), values.map(|(_, value)| value.clone()).collect());
+        }
+        kept.rebuild_indexes();
+        kept
+    }
+
+    /// The columns an INSERT gives values for: all but the generated ones
+    /// and the timestamps the engine keeps.
+    pub fn input_columns(&self) -> Vec<&String> {
+        self.columns.iter().filter(|col| !self.is_automatic(col)).collect()
+    }
+
+    /// Whether the engine fills `column` rather than the statement writing
+    /// the row: a generated column, an audit timestamp or a column whose
+    /// DEFAULT is a sequence.
+    pub fn is_automatic(&self, column: &str) -> bool {
+        self.generated.contains_key(column) || timestamps::is_timestamp(self, column) || self.sequences.contains_key(column)
+    }
+
+    /// A whole row from `values`, one for each input column in order, and
+    /// `drawn`, the values of the columns filled from sequences, with the
+    /// generated columns computed from them and the timestamps set to `now`.
+    pub fn complete_row(
+        &self,
+        values: Vec<DataType>,
+        drawn: &BTreeMap<String, i32>,
+        functions: &Functions,
+        now: u64,
+    ) -> Result<Vec<DataType>, DbError> {
+        let mut values = values.into_iter();
+        let mut row: Vec<DataType> = self.columns.iter()
+            .map(|col| match drawn.get(col) {
+                Some(&value) => DataType::Integer32(value),
+                None if self.is_automatic(col) => DataType::String(String::new()),
+                None => values.next().expect("a value for every input column"),
+            })
+            .collect();
+        self.generate(&mut row, functions)?;
+        timestamps::stamp(self, &mut row, true, now);
+        Ok(row)
+    }
+
+    /// Computes the generated columns of a whole row from its other values,
+    /// converted to the column's type.
+    pub fn generate(&self, row: &mut [DataType], functions: &Functions) -> Result<(), DbError> {
+        for (i, col) in self.columns.iter().enumerate() {
+            let Some(expr) = self.generated.get(col) else {
+                continue;
+            };
+            let value = expr.eval_with(&|name| match self.columns.iter().position(|c| c == name) {
+                Some(position) => Ok(present(&self.fields[name], row[position].clone())),
+                None => Err(DbError::ColumnNotFound { table: self.name.clone(), column: name.to_string() }),
+            }, functions)?;
+            row[i] = self.computed(col, expr, &value)?;
+        }
+        Ok(())
+    }
+
+    /// `value`, worked out by `expr`, as column `col` holds it.
+    pub fn computed(&self, col: &str, expr: &Expr, value: &DataType) -> Result<DataType, DbError> {
+        match enum_labels(&self.fields[col]) {
+            Some(_) => parse_value(col, &self.fields[col], &value.to_string()),
+            None => cast(value, &self.fields[col])
+                .map_err(|reason| DbError::InvalidExpression(format!("{} for '{}': {}", expr, col, reason))),
+        }
+    }
+
+    /// Fails unless each generated column's expression reads only columns
+    /// of the table that are not generated themselves, and calls only
+    /// functions that exist.
+    pub fn check_generated(&self, functions: &Functions) -> Result<(), DbError> {
+        for (col, expr) in &self.generated {
+            expr.check(self, functions)?;
+            if let Some(other) = expr.columns().into_iter().find(|name| self.generated.contains_key(*name)) {
+                return Err(DbError::Syntax(format!(
+                    "generated column '{}' cannot read '{}', which is generated too", col, other
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// The value of `column` in row `row`, as it reads (see `present`).
+    pub fn value(&self, column: &str, row: usize) -> DataType {
+        present(&self.fields[column], self.data[column][row].clone())
+    }
+
+    pub fn row_count(&self) -> usize {
+        self.columns.first().map_or(0, |col| self.data[col].len())
+    }
+
+    /// Makes a logged change, `at` being when it was logged.
+    pub fn apply(&mut self, op: &WalOp, at: u64) {
+        if let Some(mut history) = self.history.take() {
+            history.record(self, op, at);
+            self.history = Some(history);
+        }
+        match op {
+            WalOp::Insert { row, .. } => {
+                let position = self.row_count();
+                for (col, val) in self.columns.iter().zip(row) {
+                    self.data.get_mut(col).unwrap().push(val.clone());
+                }
+                let keys: Vec<Key> = self.index_defs.iter()
+                    .map(|def| self.key(&def.columns, position))
+                    .collect();
+                for (index, key) in self.indexes.iter_mut().zip(keys) {
+                    index.insert(key, position);
+                }
+            }
+            WalOp::Delete { index, .. } => self.remove_rows(&[*index]),
+            WalOp::DeleteRows { rows, .. } => self.remove_rows(rows),
+            WalOp::Update { row, values, .. } => self.update_row(*row, values),
+            WalOp::Tombstone { rows, deleted, .. } => self.mark_deleted(rows, *deleted),
+            WalOp::Transaction { ops } => {
+                for op in ops {
+                    if op.table() == Some(self.name.as_str()) {
+                        self.apply(op, at);
+                    }
+                }
+            }
+            WalOp::Checkpoint => {}
+        }
+        self.modified = at;
+    }
+
+    // Only the indexes on a changed column have their entry for the row
+    // moved; nothing else is touched
+    fn update_row(&mut self, row: usize, values: &[(String, DataType)]) {
+        let touched: Vec<usize> = (0..self.index_defs.len())
+            .filter(|&i| self.index_defs[i].columns.iter().any(|col| values.iter().any(|(changed, _)| changed == col)))
+            .collect();
+        for &i in &touched {
+            let key = self.key(&self.index_defs[i].columns, row);
+            self.indexes[i].remove(&key, row);
+        }
+        for (column, value) in values {
+            if let Some(data) = self.data.get_mut(column) {
+                data[row] = value.clone();
+            }
+        }
+        for &i in &touched {
+            let key = self.key(&self.index_defs[i].columns, row);
+            self.indexes[i].insert(key, row);
+        }
+    }
+
+    // `rows` must be in ascending order
+    fn remove_rows(&mut self, rows: &[usize]) {
+        self.drop_tombstones(rows);
+        // One pass over each column, however many rows go
+        let mut removed = vec![false; self.row_count()];
+        for &row in rows {
+            removed[row] = true;
+        }
+        for col in &self.columns {
+            let mut row = 0;
+            self.data.get_mut(col).unwrap().retain(|_| {
+                row += 1;
+                !removed[row - 1]
+            });
+        }
+        // Positions after each removed row shift, so the entries are rebuilt
+        self.rebuild_indexes();
+    }
+
+    pub fn rebuild_indexes(&mut self) {
+        self.indexes = self.index_defs.iter().map(|def| Index::build(def, self)).collect();
+    }
+
+    /// How the values of `column` compare.
+    pub fn collation(&self, column: &str) -> Collation {
+        self.collations.get(column).copied().unwrap_or_default()
+    }
+
+    /// Values of `columns` in row `row`, as their collations compare them.
+    pub fn key(&self, columns: &[String], row: usize) -> Key {
+        columns.iter().map(|col| self.collation(col).key(&self.data[col][row]).into_owned()).collect()
+    }
+
+    /// Fails if `row` would repeat a key held by a unique index.
+    pub fn check_unique(&self, row: &[DataType]) -> Result<(), DbError> {
+        self.check_unique_except(row, None)
+    }
+
+    /// Like `check_unique`, but a key held only by row `except`, which `row`
+    /// is about to replace, is no conflict.
+    pub fn check_unique_except(&self, row: &[DataType], except: Option<usize>) -> Result<(), DbError> {
+        for (def, index) in self.index_defs.iter().zip(&self.indexes) {
+            if !def.unique {
+                continue;
+            }
+            let key = self.row_key(&def.columns, row);
+            let taken = match except {
+                None => index.contains(&key),
+                Some(except) => index.lookup(&key, &[]).iter().any(|&r| r != except),
+            };
+            if taken {
+                return Err(self.duplicate(def, row));
+            }
+        }
+        Ok(())
+    }
+
+    /// The row already holding a key that `row` repeats, in a unique index
+    /// on exactly the `target` columns, or in any unique index if there are
+    /// none. Fails if no unique index is on the target columns.
+    pub fn conflict(&self, row: &[DataType], target: &[String]) -> Result<Option<usize>, DbError> {
+        let mut found = false;
+        for (def, index) in self.index_defs.iter().zip(&self.indexes) {
+            let on_target = target.is_empty()
+                || (def.columns.len() == target.len() && target.iter().all(|col| def.columns.contains(col)));
+            if !def.unique || !on_target {
+                continue;
+            }
+            found = true;
+            if let Some(&existing) = index.lookup(&self.row_key(&def.columns, row), &[]).first() {
+                return Ok(Some(existing));
+            }
+        }
+        if !found && !target.is_empty() {
+            return Err(DbError::InvalidIndex(format!(
+                "'{}' has no primary key or unique index on ({})", self.name, target.join(", ")
+            )));
+        }
+        Ok(None)
+    }
+
+    /// Values of `columns` in `row`, a full row of this table, as their
+    /// collations compare them.
+    pub(crate) fn row_key(&self, columns: &[String], row: &[DataType]) -> Key {
+        columns.iter().map(|col| self.collation(col).key(self.row_value(col, row)).into_owned()).collect()
+    }
+
+    fn row_value<'a>(&self, column: &str, row: &'a [DataType]) -> &'a DataType {
+        &row[self.columns.iter().position(|c| c == column).unwrap()]
+    }
+
+    /// The error for `row` repeating a key of the unique index `def`, naming
+    /// the values as they are written.
+    pub(crate) fn duplicate(&self, def: &IndexDef, row: &[DataType]) -> DbError {
+        let value = def.columns.iter().map(|col| self.row_value(col, row).to_string()).collect::<Vec<_>>().join(", ");
+        DbError::DuplicateKey { index: def.name.clone(), value }
+    }
+}
+
+impl std::fmt::Display for DataType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DataType::String(s) => write!(f, "{}", s),
+            DataType::Integer32(i) => write!(f, "{}", i),
+            DataType::Float32(fl) => write!(f, "{}", fl),
+            DataType::Array(items) => write!(f, "{}", array_literal(items.iter().map(DataType::to_string))),
+        }
+    }
+}
+
+// Values of one column always share a variant; across variants the order is
+// arbitrary but total, which is all an index key needs.
+impl Ord for DataType {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (DataType::String(a), DataType::String(b)) => a.cmp(b),
+            (DataType::Integer32(a), DataType::Integer32(b)) => a.cmp(b),
+            (DataType::Float32(a), DataType::Float32(b)) => compare_floats(*a, *b),
+            (DataType::Array(a), DataType::Array(b)) => a.cmp(b),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+/// How two floats compare wherever values do: by `total_cmp`, so -0.0 comes
+/// before 0.0 and NaN after every number, equal only to itself, which keeps
+/// the order total for indexes and sorting.
+pub fn compare_floats(a: f32, b: f32) -> Ordering {
+    a.total_cmp(&b)
+}
+
+impl PartialOrd for DataType {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for DataType {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for DataType {}
+
+impl DataType {
+    /// The column type holding values like this one, as in a schema.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            DataType::String(_) => "string",
+            DataType::Integer32(_) => "int",
+            DataType::Float32(_) => "float",
+            DataType::Array(items) => match items.first() {
+                Some(DataType::Integer32(_)) => "int[]",
+                Some(DataType::Float32(_)) => "float[]",
+                _ => "string[]",
+            },
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            DataType::Integer32(_) => 0,
+            DataType::Float32(_) => 1,
+            DataType::String(_) => 2,
+            DataType::Array(_) => 3,
+        }
+    }
+}
+
+pub fn parse_value(column: &str, typ: &str, raw: &str) -> Result<DataType, DbError> {
+    let mismatch = || DbError::TypeMismatch {
+        column: column.to_string(),
+        expected: typ.to_string(),
+        value: raw.to_string(),
+    };
+    if let Some(element) = element_type(typ) {
+        let items = parse_array(raw).ok_or_else(mismatch)?;
+        return items.iter()
+            .map(|item| parse_value(column, element, item))
+            .collect::<Result<_, _>>()
+            .map(DataType::Array);
+    }
+    if let Some(labels) = enum_labels(typ) {
+        return labels.iter()
+            .position(|label| *label == raw)
+            .map(|i| DataType::Integer32(i as i32))
+            .ok_or_else(mismatch);
+    }
+    match typ {
+        "int" => raw.parse().map(DataType::Integer32).map_err(|_| mismatch()),
+        "float" => raw.parse().map(DataType::Float32).map_err(|_| mismatch()),
+        POINT => point_of(&DataType::String(raw.to_string())).and_then(|(lat, lon)| point(lat, lon)).ok_or_else(mismatch),
+        _ => Ok(DataType::String(raw.to_string())),
+    }
+}
+
+/// The type of the elements of an array column type like `string[]`, or
+/// None for any other type.
+pub fn element_type(typ: &str) -> Option<&str> {
+    typ.strip_suffix("[]")
+}
+
+/// Elements written as an array literal, `{a,b,"c d"}`. An element that is
+/// empty or holds a space, comma, brace, quote or backslash is quoted, with
+/// a backslash before each quote or backslash in it.
+pub fn array_literal(items: impl IntoIterator<Item = String>) -> String {
+    let items: Vec<String> = items.into_iter()
+        .map(|item| {
+            if !item.is_empty() && !item.contains([' ', ',', '{', '}', '"', '\\']) {
+                return item;
+            }
+            format!("\"{}\"", item.replace('\\', "\\\\").replace('"', "\\\""))
+        })
+        .collect();
+    format!("{{{}}}", items.join(","))
+}
+
+/// The elements of an array literal as `array_literal` writes it, or None
+/// if `text` is not one. Unquoted elements are trimmed.
+pub(crate) fn parse_array(text: &str) -> Option<Vec<String>> {
+    let inner = text.trim().strip_prefix('{')?.strip_suffix('}')?;
+    let mut items = Vec::new();
+    if inner.trim().is_empty() {
+        return Some(items);
+    }
+    let mut chars = inner.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let mut item = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            loop {
+                match chars.next()? {
+                    '"' => break,
+                    '\\' => item.push(chars.next()?),
+                    c => item.push(c),
+                }
+            }
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        } else {
+            while let Some(c) = chars.next_if(|c| *c != ',') {
+                if matches!(c, '{' | '}' | '"') {
+                    return None;
+                }
+                item.push(c);
+            }
+            item = item.trim().to_string();
+        }
+        items.push(item);
+        match chars.next() {
+            Some(',') => continue,
+            None => return Some(items),
+            Some(_) => return None,
+        }
+    }
+}
+
+/// The column type of a place on Earth, stored as an array of its latitude
+/// and longitude in degrees, `{<lat>,<lon>}`.
+pub const POINT: &str = "point";
+
+/// The point at `lat`, `lon`, or None if either is out of range.
+pub fn point(lat: f32, lon: f32) -> Option<DataType> {
+    if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+        return None;
+    }
+    Some(DataType::Array(vec![DataType::Float32(lat), DataType::Float32(lon)]))
+}
+
+/// The latitude and longitude of a point, or of text written
+/// `<lat>,<lon>`, bare or in braces or parentheses. None for anything else.
+pub fn point_of(value: &DataType) -> Option<(f32, f32)> {
+    let number = |value: &DataType| match value {
+        DataType::Integer32(i) => Some(*i as f32),
+        DataType::Float32(f) => Some(*f),
+        _ => None,
+    };
+    match value {
+        DataType::Array(items) => match &items[..] {
+            [lat, lon] => Some((number(lat)?, number(lon)?)),
+            _ => None,
+        },
+        DataType::String(text) => {
+            let text = text.trim();
+            let inner = ["{}", "()"].iter()
+                .find_map(|pair| text.strip_prefix(&pair[..1])?.strip_suffix(&pair[1..]))
+                .unwrap_or(text);
+            let (lat, lon) = inner.split_once(',')?;
+            Some((lat.trim().parse().ok()?, lon.trim().parse().ok()?))
+        }
+        _ => None,
+    }
+}
+
+/// The labels of an `enum(<label>,...)` column type, in the order they were
+/// declared, or None for any other type.
+pub fn enum_labels(typ: &str) -> Option<Vec<&str>> {
+    typ.strip_prefix("enum(")?.strip_suffix(')').map(|labels| labels.split(',').collect())
+}
+
+/// Checks the name and columns of a table about to be created: every name
+/// given and not a reserved keyword, no column twice, and every type one
+/// there is. Reports every problem found at once.
+pub fn check_definition(name: &str, columns: &[(String, String)]) -> Result<(), DbError> {
+    let mut problems = Vec::new();
+    let mut check_name = |kind: &str, name: &str| {
+        if name.is_empty() {
+            problems.push(format!("{} name is empty", kind));
+        } else if parser::is_reserved(name) {
+            problems.push(format!("{} name '{}' is a reserved keyword", kind, name));
+        }
+    };
+    check_name("table", name);
+    for (column, _) in columns {
+        check_name("column", column);
+    }
+    if columns.is_empty() {
+        problems.push("a table needs at least one column".to_string());
+    }
+    for (i, (column, typ)) in columns.iter().enumerate() {
+        if columns[..i].iter().any(|(earlier, _)| earlier == column) {
+            problems.push(format!("column '{}' is defined more than once", column));
+        }
+        let scalar = element_type(typ).unwrap_or(typ);
+        if !CAST_TYPES.contains(&scalar) && typ != POINT && enum_labels(typ).is_none() {
+            problems.push(format!("column '{}' has unknown type '{}' (use int, float, string, point, enum(...) or an array)", column, typ));
+        }
+    }
+    match problems.is_empty() {
+        true => Ok(()),
+        false => Err(DbError::InvalidDefinition(problems)),
+    }
+}
+
+/// A stored value as it reads. An enum column stores the position of each
+/// value's label, so that it is small and sorts in declaration order, and
+/// reads as the label; every other value reads as stored.
+pub fn present(typ: &str, value: DataType) -> DataType {
+    match (enum_labels(typ), &value) {
+        (Some(labels), DataType::Integer32(i)) => match labels.get(*i as usize) {
+            Some(label) => DataType::String(label.to_string()),
+            None => value,
+        },
+        _ => value,
+    }
+}
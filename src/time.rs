@@ -0,0 +1,153 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Seconds since the Unix epoch.
+pub fn now() -> u64 {
+    (epoch_nanos() / 1_000_000_000) as u64
+}
+
+/// Nanoseconds since the Unix epoch, from the operating system's clock.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn epoch_nanos() -> u128 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos())
+}
+
+/// Nanoseconds since the Unix epoch, from the browser's clock, which has
+/// no system time to read and only tells milliseconds.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn epoch_nanos() -> u128 {
+    js_sys::Date::now().max(0.0) as u128 * 1_000_000
+}
+
+/// Where a database reads the time: for `NOW()`, expiring rows, audit
+/// timestamps, the history `AS OF` reads and the times in the log. The
+/// system's clock unless `Database::set_clock` gives another.
+pub trait Clock: Send + Sync {
+    /// Seconds since the Unix epoch.
+    fn now(&self) -> u64;
+}
+
+/// The system's clock.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        now()
+    }
+}
+
+/// A clock that stands still until it is set or moved on, for tests of
+/// queries that depend on the time. Share it through an `Arc` to move it
+/// while a database reads it.
+pub struct ManualClock {
+    at: AtomicU64,
+}
+
+impl ManualClock {
+    pub fn new(at: u64) -> ManualClock {
+        ManualClock { at: AtomicU64::new(at) }
+    }
+
+    pub fn set(&self, at: u64) {
+        self.at.store(at, Ordering::Relaxed);
+    }
+
+    pub fn advance(&self, seconds: u64) {
+        self.at.fetch_add(seconds, Ordering::Relaxed);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> u64 {
+        self.at.load(Ordering::Relaxed)
+    }
+}
+
+/// Reads `YYYY-MM-DD`, `YYYY-MM-DD HH:MM` or `YYYY-MM-DD HH:MM:SS` (a `T`
+/// may stand for the space) as a UTC time in seconds since the Unix epoch.
+pub fn parse_timestamp(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let (date, time) = match text.split_once([' ', 'T']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (text, None),
+    };
+
+    let mut fields = date.split('-');
+    let year: i64 = fields.next()?.parse().ok()?;
+    let month: u32 = fields.next()?.parse().ok()?;
+    let day: u32 = fields.next()?.parse().ok()?;
+    if fields.next().is_some() || !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+
+    let mut seconds = 0;
+    if let Some(time) = time {
+        let parts: Vec<&str> = time.split(':').collect();
+        if !(2..=3).contains(&parts.len()) {
+            return None;
+        }
+        let hour: u64 = parts[0].parse().ok().filter(|h| *h < 24)?;
+        let minute: u64 = parts[1].parse().ok().filter(|m| *m < 60)?;
+        let second: u64 = match parts.get(2) {
+            Some(s) => s.parse().ok().filter(|s| *s < 60)?,
+            None => 0,
+        };
+        seconds = hour * 3600 + minute * 60 + second;
+    }
+
+    let days = days_from_civil(year, month, day);
+    u64::try_from(days * 86400).ok().map(|d| d + seconds)
+}
+
+/// Writes a Unix timestamp as `YYYY-MM-DD HH:MM:SS` in UTC.
+pub fn format_timestamp(at: u64) -> String {
+    let (year, month, day) = date_of(at);
+    let seconds = at % 86400;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60
+    )
+}
+
+/// The year, month and day of a Unix timestamp, in UTC.
+pub fn date_of(at: u64) -> (i64, u32, u32) {
+    civil_from_days((at / 86400) as i64)
+}
+
+fn is_leap(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Days since 1970-01-01 of a date in the proleptic Gregorian calendar, after
+// Howard Hinnant's `days_from_civil`
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
@@ -0,0 +1,84 @@
+//! Audit timestamps, for `CREATE TABLE ... WITH TIMESTAMPS`: the engine
+//! keeps two columns of the table, `created_at` with when each row was
+//! inserted and `updated_at` with when it last changed, and no statement
+//! can set them. Each holds a time as `YYYY-MM-DD HH:MM:SS` (UTC) if it is a
+//! string column, the default, or a number of seconds since the Unix epoch
+//! if it is an int.
+
+use crate::catalog;
+use crate::database::Database;
+use crate::error::DbError;
+use crate::time;
+use crate::{DataType, Table};
+
+pub const CREATED_AT: &str = "created_at";
+pub const UPDATED_AT: &str = "updated_at";
+
+/// Whether the engine keeps `column` of `table` as an audit timestamp.
+pub fn is_timestamp(table: &Table, column: &str) -> bool {
+    table.timestamps && (column == CREATED_AT || column == UPDATED_AT)
+}
+
+/// Adds the timestamp columns not already declared to a new table's, as strings.
+pub fn add_columns(columns: &mut Vec<(String, String)>) {
+    for name in [CREATED_AT, UPDATED_AT] {
+        if !columns.iter().any(|(column, _)| column == name) {
+            columns.push((name.to_string(), "string".to_string()));
+        }
+    }
+}
+
+/// Checks that `table` has both timestamp columns, of a type that holds a
+/// time, for the engine to keep.
+pub fn check(table: &Table) -> Result<(), DbError> {
+    if table.external.is_some() {
+        return Err(DbError::ExternalTable(table.name.clone()));
+    }
+    for column in [CREATED_AT, UPDATED_AT] {
+        match table.fields.get(column).map(String::as_str) {
+            None => return Err(DbError::ColumnNotFound { table: table.name.clone(), column: column.to_string() }),
+            Some("int" | "string") => {}
+            Some(typ) => {
+                return Err(DbError::InvalidTimestamps(format!(
+                    "column '{}' is {}; it must be a string or an int of seconds since the Unix epoch", column, typ
+                )));
+            }
+        }
+        if table.generated.contains_key(column) {
+            return Err(DbError::InvalidTimestamps(format!("column '{}' is generated", column)));
+        }
+    }
+    Ok(())
+}
+
+/// Sets the timestamps of `row`, a whole row of `table`, to `now`: both for
+/// a row being inserted, and only `updated_at` otherwise.
+pub fn stamp(table: &Table, row: &mut [DataType], inserted: bool, now: u64) {
+    if !table.timestamps {
+        return;
+    }
+    for (i, column) in table.columns.iter().enumerate() {
+        if column == UPDATED_AT || inserted && column == CREATED_AT {
+            row[i] = match table.fields[column].as_str() {
+                "int" => DataType::Integer32(i32::try_from(now).unwrap_or(i32::MAX)),
+                _ => DataType::String(time::format_timestamp(now)),
+            };
+        }
+    }
+}
+
+impl Database {
+    /// Makes the engine keep the timestamp columns of `table`, or stops it.
+    /// The columns and their values stay either way.
+    pub fn set_timestamps(&mut self, table_name: &str, on: bool) -> Result<(), DbError> {
+        if catalog::is_system_table(table_name) {
+            return Err(DbError::SystemTable(table_name.to_string()));
+        }
+        let mut table = self.load_table(table_name)?.clone();
+        if on {
+            check(&table)?;
+        }
+        table.timestamps = on;
+        self.save_table(&table)
+    }
+}
@@ -0,0 +1,136 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use rustls::{Certificate, ClientConfig, ClientConnection, PrivateKey, RootCertStore, ServerConfig, ServerConnection, ServerName, StreamOwned};
+
+/// The certificate and private key a listener encrypts its connections
+/// with, both as read from their PEM files.
+pub struct Tls {
+    config: Arc<ServerConfig>,
+    pub certificate: Vec<u8>,
+    pub private_key: Vec<u8>,
+}
+
+impl fmt::Debug for Tls {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Tls { .. }")
+    }
+}
+
+impl Tls {
+    /// Reads a certificate chain and the private key for it (PKCS#8 or RSA)
+    /// from PEM files.
+    pub fn load(certificate_path: &Path, key_path: &Path) -> Result<Tls, String> {
+        let read = |path: &Path| fs::read(path).map_err(|e| format!("Could not read '{}': {}", path.display(), e));
+        let certificate = read(certificate_path)?;
+        let private_key = read(key_path)?;
+
+        let chain = rustls_pemfile::certs(&mut &certificate[..]).unwrap_or_default();
+        if chain.is_empty() {
+            return Err(format!("No certificate found in '{}'", certificate_path.display()));
+        }
+        let key = rustls_pemfile::pkcs8_private_keys(&mut &private_key[..])
+            .ok()
+            .filter(|keys| !keys.is_empty())
+            .or_else(|| rustls_pemfile::rsa_private_keys(&mut &private_key[..]).ok())
+            .and_then(|keys| keys.into_iter().next())
+            .ok_or_else(|| format!("No PKCS#8 or RSA private key found in '{}'", key_path.display()))?;
+        let config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(chain.into_iter().map(Certificate).collect(), PrivateKey(key))
+            .map_err(|e| format!("Invalid certificate or key: {}", e))?;
+        Ok(Tls { config: Arc::new(config), certificate, private_key })
+    }
+}
+
+trait Stream: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Stream for T {}
+
+/// A connection that is either plain TCP or encrypted over it. Clones share
+/// the one TLS session, so a reader and a writer can each hold one as they
+/// would a cloned `TcpStream`, as long as they take turns.
+pub struct Connection {
+    socket: TcpStream,
+    tls: Option<Arc<Mutex<dyn Stream>>>,
+}
+
+impl Connection {
+    pub fn plain(socket: TcpStream) -> Connection {
+        Connection { socket, tls: None }
+    }
+
+    pub fn try_clone(&self) -> io::Result<Connection> {
+        Ok(Connection { socket: self.socket.try_clone()?, tls: self.tls.clone() })
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.peer_addr()
+    }
+
+    fn lock(tls: &Arc<Mutex<dyn Stream>>) -> MutexGuard<'_, dyn Stream + 'static> {
+        tls.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &self.tls {
+            Some(tls) => Connection::lock(tls).read(buf),
+            None => self.socket.read(buf),
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &self.tls {
+            Some(tls) => Connection::lock(tls).write(buf),
+            None => self.socket.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &self.tls {
+            Some(tls) => Connection::lock(tls).flush(),
+            None => self.socket.flush(),
+        }
+    }
+}
+
+/// The server's end of a connection a client opened, encrypted with `tls`
+/// if given. The handshake happens on the first read or write.
+pub fn accept(socket: TcpStream, tls: Option<&Tls>) -> io::Result<Connection> {
+    let Some(tls) = tls else { return Ok(Connection::plain(socket)) };
+    let session = ServerConnection::new(Arc::clone(&tls.config)).map_err(io::Error::other)?;
+    let stream: Arc<Mutex<dyn Stream>> = Arc::new(Mutex::new(StreamOwned::new(session, socket.try_clone()?)));
+    Ok(Connection { socket, tls: Some(stream) })
+}
+
+/// Connects to the server at `addr`, over TLS if given the PEM file of the
+/// certificates to trust. The server's certificate must then name the host
+/// of `addr`.
+pub fn connect(addr: &str, trusted: Option<&Path>) -> io::Result<Connection> {
+    let socket = TcpStream::connect(addr)?;
+    let Some(trusted) = trusted else { return Ok(Connection::plain(socket)) };
+    let invalid = |reason: String| io::Error::new(io::ErrorKind::InvalidInput, reason);
+
+    let mut roots = RootCertStore::empty();
+    for certificate in rustls_pemfile::certs(&mut &fs::read(trusted)?[..])? {
+        roots.add(&Certificate(certificate)).map_err(|e| invalid(format!("'{}': {}", trusted.display(), e)))?;
+    }
+    if roots.is_empty() {
+        return Err(invalid(format!("No certificate found in '{}'", trusted.display())));
+    }
+    let config = ClientConfig::builder().with_safe_defaults().with_root_certificates(roots).with_no_client_auth();
+    let host = addr.rsplit_once(':').map_or(addr, |(host, _)| host).trim_start_matches('[').trim_end_matches(']');
+    let name = ServerName::try_from(host).map_err(|_| invalid(format!("'{}' is not a host name a certificate can name", host)))?;
+    let session = ClientConnection::new(Arc::new(config), name).map_err(io::Error::other)?;
+    let stream: Arc<Mutex<dyn Stream>> = Arc::new(Mutex::new(StreamOwned::new(session, socket.try_clone()?)));
+    Ok(Connection { socket, tls: Some(stream) })
+}
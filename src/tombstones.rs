@@ -0,0 +1,132 @@
+//! Soft delete, for `CREATE TABLE ... WITH SOFT DELETE`. A `DELETE` on such
+//! a table marks its rows with a tombstone instead of removing them: reads
+//! leave them out, `FROM <table> WITH DELETED` reads them too, `UNDELETE`
+//! brings them back and `PURGE` removes them for good.
+
+use std::sync::Arc;
+
+use crate::catalog;
+use crate::database::Database;
+use crate::error::DbError;
+use crate::parser::Predicate;
+use crate::planner;
+use crate::wal::WalOp;
+use crate::Table;
+
+impl Table {
+    /// Whether row `row` has been soft-deleted.
+    pub fn is_deleted(&self, row: usize) -> bool {
+        self.tombstones.as_ref().is_some_and(|tombstones| tombstones.get(row) == Some(&true))
+    }
+
+    /// Marks `rows` deleted, or no longer deleted.
+    pub(crate) fn mark_deleted(&mut self, rows: &[usize], deleted: bool) {
+        let Some(tombstones) = &mut self.tombstones else { return };
+        for &row in rows {
+            // Rows past the end of the list have never been deleted
+            if tombstones.len() <= row {
+                tombstones.resize(row + 1, false);
+            }
+            tombstones[row] = deleted;
+        }
+    }
+
+    /// Forgets the tombstones of `rows`, ascending, as they are removed.
+    pub(crate) fn drop_tombstones(&mut self, rows: &[usize]) {
+        let Some(tombstones) = &mut self.tombstones else { return };
+        for &row in rows.iter().rev() {
+            if row < tombstones.len() {
+                tombstones.remove(row);
+            }
+        }
+    }
+}
+
+/// `table` without its soft-deleted rows: the same table if it has none.
+pub(crate) fn visible(table: Arc<Table>) -> Arc<Table> {
+    let deleted: Vec<usize> = (0..table.row_count()).filter(|&row| table.is_deleted(row)).collect();
+    if deleted.is_empty() {
+        return table;
+    }
+    Arc::new(table.without_rows(&deleted))
+}
+
+/// What deleting `rows` of `table` logs: tombstones if the table soft-deletes.
+pub fn delete_op(table: &Table, rows: Vec<usize>) -> WalOp {
+    match table.tombstones {
+        Some(_) => WalOp::Tombstone { table: table.name.clone(), rows, deleted: true },
+        None => WalOp::DeleteRows { table: table.name.clone(), rows },
+    }
+}
+
+impl Database {
+    /// Makes `DELETE` on `table` soft or, if `on` is false, hard again. Soft
+    /// delete cannot be turned off while there are soft-deleted rows.
+    pub fn set_soft_delete(&mut self, table_name: &str, on: bool) -> Result<(), DbError> {
+        if catalog::is_system_table(table_name) {
+            return Err(DbError::SystemTable(table_name.to_string()));
+        }
+        let mut table = self.load_table(table_name)?.clone();
+        if on {
+            check(&table)?;
+        }
+        match (on, &table.tombstones) {
+            (true, Some(_)) | (false, None) => return Ok(()),
+            (true, None) => table.tombstones = Some(Vec::new()),
+            (false, Some(tombstones)) => {
+                let deleted = tombstones.iter().filter(|&&deleted| deleted).count();
+                if deleted > 0 {
+                    return Err(DbError::InvalidSoftDelete(format!(
+                        "table '{}' has {} soft-deleted row(s); UNDELETE or PURGE them first", table_name, deleted
+                    )));
+                }
+                table.tombstones = None;
+            }
+        }
+        self.save_table(&table)
+    }
+
+    /// Brings back the soft-deleted rows of `table` matching `filter`.
+    /// Returns the number brought back.
+    pub fn undelete(&mut self, table_name: &str, filter: &[Predicate]) -> Result<usize, DbError> {
+        let rows = self.deleted_rows(table_name, filter)?;
+        if !rows.is_empty() {
+            self.log(WalOp::Tombstone { table: table_name.to_string(), rows: rows.clone(), deleted: false })?;
+        }
+        Ok(rows.len())
+    }
+
+    /// Removes the soft-deleted rows of `table` matching `filter` for good,
+    /// without firing its triggers. Returns the number removed.
+    pub fn purge_deleted(&mut self, table_name: &str, filter: &[Predicate]) -> Result<usize, DbError> {
+        let rows = self.deleted_rows(table_name, filter)?;
+        if !rows.is_empty() {
+            self.log(WalOp::DeleteRows { table: table_name.to_string(), rows: rows.clone() })?;
+        }
+        Ok(rows.len())
+    }
+
+    // The soft-deleted rows of a table that soft-deletes, matching `filter`
+    fn deleted_rows(&mut self, table_name: &str, filter: &[Predicate]) -> Result<Vec<usize>, DbError> {
+        self.check_writable(table_name)?;
+        let functions = self.functions();
+        let table = self.load_table(table_name)?;
+        if table.tombstones.is_none() {
+            return Err(DbError::InvalidSoftDelete(format!("table '{}' does not soft-delete its rows", table_name)));
+        }
+        let mut rows = planner::plan(table, filter, &functions)?.rows()?;
+        rows.retain(|&row| table.is_deleted(row));
+        Ok(rows)
+    }
+}
+
+/// Checks that `table` can soft-delete its rows.
+pub fn check(table: &Table) -> Result<(), DbError> {
+    if table.external.is_some() {
+        return Err(DbError::ExternalTable(table.name.clone()));
+    }
+    if table.partitioning.is_some() {
+        return Err(DbError::InvalidSoftDelete("only tables that are not partitioned soft-delete their rows".to_string()));
+    }
+    Ok(())
+}
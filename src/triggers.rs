@@ -0,0 +1,138 @@
+use std::fmt;
+
+use serde::{Serialize, Deserialize};
+
+use crate::database::Database;
+use crate::error::DbError;
+use crate::parser::{self, Statement, Token};
+use crate::DataType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Timing {
+    Before,
+    After,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Event {
+    Insert,
+    Update, // Only ON CONFLICT DO UPDATE changes a row in place
+    Delete,
+}
+
+impl Event {
+    /// How the trigger's statements refer to the row: NEW for an inserted
+    /// row, OLD for a deleted one, and both for an updated one, OLD as it
+    /// was and NEW as it becomes.
+    pub fn row_names(&self) -> &'static [&'static str] {
+        match self {
+            Event::Insert => &["NEW"],
+            Event::Update => &["OLD", "NEW"],
+            Event::Delete => &["OLD"],
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::Insert => write!(f, "INSERT"),
+            Event::Update => write!(f, "UPDATE"),
+            Event::Delete => write!(f, "DELETE"),
+        }
+    }
+}
+
+/// Statements run for every row a table's INSERT, UPDATE or DELETE touches. Saved
+/// with the table, like its index definitions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Trigger {
+    pub name: String,
+    pub timing: Timing,
+    pub event: Event,
+    pub body: String, // `;`-separated INSERT and DELETE statements
+}
+
+impl Trigger {
+    /// The trigger's statements for one row, with every `NEW.<column>` (or
+    /// `OLD.<column>`) replaced by that column's value. `row` holds the
+    /// values of each of the event's `row_names` in turn.
+    pub fn statements(&self, columns: &[String], row: &[DataType]) -> Result<Vec<Statement>, DbError> {
+        let mut statements = Vec::new();
+        for text in parser::split_statements(&self.body) {
+            let tokens = parser::tokenize(text)?;
+            if tokens.is_empty() {
+                continue;
+            }
+            let statement = parser::parse_tokens(self.bind(tokens, columns, row)?)?;
+            if !matches!(statement, Statement::Insert { .. } | Statement::Delete { .. }) {
+                return Err(DbError::Syntax("trigger statements must be INSERT or DELETE".to_string()));
+            }
+            statements.push(statement);
+        }
+        Ok(statements)
+    }
+
+    fn bind(&self, tokens: Vec<Token>, columns: &[String], row: &[DataType]) -> Result<Vec<Token>, DbError> {
+        let mut bound = Vec::with_capacity(tokens.len());
+        let mut tokens = tokens.into_iter().peekable();
+        while let Some(token) = tokens.next() {
+            let qualifier = match &token {
+                Token::Ident(word) if word.eq_ignore_ascii_case("NEW") || word.eq_ignore_ascii_case("OLD") => word.to_ascii_uppercase(),
+                _ => {
+                    bound.push(token);
+                    continue;
+                }
+            };
+            if tokens.next_if_eq(&Token::Symbol(".")).is_none() {
+                bound.push(token);
+                continue;
+            }
+            let names = self.event.row_names();
+            let Some(which) = names.iter().position(|name| *name == qualifier) else {
+                return Err(DbError::Syntax(format!(
+                    "{} is not available to {} triggers, only {}", qualifier, self.event, names.join(" and ")
+                )));
+            };
+            let Some(Token::Ident(column)) = tokens.next() else {
+                return Err(DbError::Syntax(format!("expected a column after {}.", qualifier)));
+            };
+            let position = columns.iter().position(|col| *col == column).ok_or_else(|| DbError::Syntax(
+                format!("{}.{} is not a column of the table", qualifier, column)
+            ))?;
+            bound.push(Token::Str(row[which * columns.len() + position].to_string()));
+        }
+        Ok(bound)
+    }
+
+    /// A row of empty values to bind, for checking the statements parse.
+    pub(crate) fn placeholder(&self, columns: &[String]) -> Vec<DataType> {
+        vec![DataType::String(String::new()); columns.len() * self.event.row_names().len()]
+    }
+}
+
+impl Database {
+    /// Adds a trigger to a table after checking that its statements parse.
+    pub fn create_trigger(&mut self, table_name: &str, trigger: Trigger) -> Result<(), DbError> {
+        // Views never see a write to fire on
+        self.check_writable(table_name)?;
+        let mut table = self.load_table(table_name)?.clone();
+        if table.triggers.iter().any(|t| t.name == trigger.name) {
+            return Err(DbError::TriggerExists(trigger.name));
+        }
+        trigger.statements(&table.columns, &trigger.placeholder(&table.columns))?;
+
+        table.triggers.push(trigger);
+        self.save_table(&table)
+    }
+
+    pub fn drop_trigger(&mut self, table_name: &str, name: &str) -> Result<(), DbError> {
+        let mut table = self.load_table(table_name)?.clone();
+        let before = table.triggers.len();
+        table.triggers.retain(|t| t.name != name);
+        if table.triggers.len() == before {
+            return Err(DbError::TriggerNotFound(name.to_string()));
+        }
+        self.save_table(&table)
+    }
+}
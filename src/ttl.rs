@@ -0,0 +1,100 @@
+//! Row expiry, for `CREATE TABLE ... WITH TTL <column>`. The column holds
+//! when each row expires: a time as `YYYY-MM-DD HH:MM:SS` (UTC), or a
+//! number of seconds since the Unix epoch. Reads leave out rows whose time
+//! has passed from the moment it passes; they are deleted for good the next
+//! time their table is written to, and by the server's sweep of the tables
+//! in memory.
+
+use std::sync::Arc;
+
+use crate::catalog;
+use crate::database::Database;
+use crate::error::DbError;
+use crate::time;
+use crate::wal::WalOp;
+use crate::{DataType, Table};
+
+/// When a row whose TTL column holds `value` expires, if ever: a value that
+/// is not a time never does.
+pub fn expiry(value: &DataType) -> Option<u64> {
+    match value {
+        DataType::Integer32(seconds) => u64::try_from(*seconds).ok(),
+        DataType::String(text) => time::parse_timestamp(text),
+        _ => None,
+    }
+}
+
+/// The positions of the rows of `table` expired by `now`, in ascending order.
+pub(crate) fn expired_rows(table: &Table, now: u64) -> Vec<usize> {
+    let Some(column) = &table.ttl else { return Vec::new() };
+    table.data[column].iter()
+        .enumerate()
+        .filter(|(_, value)| expiry(value).is_some_and(|at| at <= now))
+        .map(|(row, _)| row)
+        .collect()
+}
+
+/// `table` without the rows expired by `now`: the same table if it has none.
+pub(crate) fn live(table: Arc<Table>, now: u64) -> Arc<Table> {
+    let expired = expired_rows(&table, now);
+    if expired.is_empty() {
+        return table;
+    }
+    Arc::new(table.without_rows(&expired))
+}
+
+/// Checks that rows of `table` can expire by `column`.
+pub fn check(table: &Table, column: &str) -> Result<(), DbError> {
+    let invalid = |reason: String| Err(DbError::InvalidTtl(reason));
+    if table.external.is_some() {
+        return Err(DbError::ExternalTable(table.name.clone()));
+    }
+    if table.partitioning.is_some() {
+        return invalid("rows only expire in tables that are not partitioned".to_string());
+    }
+    match table.fields.get(column).map(String::as_str) {
+        None => Err(DbError::ColumnNotFound { table: table.name.clone(), column: column.to_string() }),
+        Some("int" | "string") => Ok(()),
+        Some(typ) => invalid(format!("column '{}' is {}; it must be a string holding a time, or an int of seconds since the Unix epoch", column, typ)),
+    }
+}
+
+impl Database {
+    /// Makes the rows of `table` expire at the time in `column`, or stops
+    /// them expiring if None.
+    pub fn set_ttl(&mut self, table_name: &str, column: Option<String>) -> Result<(), DbError> {
+        if catalog::is_system_table(table_name) {
+            return Err(DbError::SystemTable(table_name.to_string()));
+        }
+        let mut table = self.load_table(table_name)?.clone();
+        if let Some(column) = &column {
+            check(&table, column)?;
+        }
+        table.ttl = column;
+        self.save_table(&table)
+    }
+
+    /// Deletes the expired rows of `table`, without firing its triggers.
+    /// Returns the number deleted.
+    pub fn purge_expired(&mut self, table_name: &str) -> Result<usize, DbError> {
+        let now = self.now();
+        let rows = expired_rows(self.load_table(table_name)?, now);
+        if !rows.is_empty() {
+            self.log(WalOp::DeleteRows { table: table_name.to_string(), rows: rows.clone() })?;
+        }
+        Ok(rows.len())
+    }
+
+    /// `purge_expired` for every table in memory whose rows expire. Returns
+    /// the number of rows deleted.
+    pub fn sweep_expired(&mut self) -> Result<usize, DbError> {
+        let names: Vec<String> = self.cached_names().into_iter()
+            .filter(|name| self.cached(name).is_some_and(|table| table.ttl.is_some()))
+            .collect();
+        let mut purged = 0;
+        for name in names {
+            purged += self.purge_expired(&name)?;
+        }
+        Ok(purged)
+    }
+}
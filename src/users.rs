@@ -0,0 +1,356 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine as _;
+use serde::{Serialize, Deserialize};
+
+use crate::database::Database;
+use crate::error::DbError;
+use crate::parser::Statement;
+use crate::storage;
+
+// Random bytes in the secret part of an access token
+const TOKEN_BYTES: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Privilege {
+    Select,
+    Insert,
+    Update,
+    Delete,
+}
+
+impl Privilege {
+    pub const ALL: [Privilege; 4] = [Privilege::Select, Privilege::Insert, Privilege::Update, Privilege::Delete];
+
+    pub fn parse(name: &str) -> Option<Privilege> {
+        match name.to_ascii_uppercase().as_str() {
+            "SELECT" => Some(Privilege::Select),
+            "INSERT" => Some(Privilege::Insert),
+            "UPDATE" => Some(Privilege::Update),
+            "DELETE" => Some(Privilege::Delete),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Privilege {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Privilege::Select => "SELECT",
+            Privilege::Insert => "INSERT",
+            Privilege::Update => "UPDATE",
+            Privilege::Delete => "DELETE",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// What a statement needs from the user running it.
+pub enum Requirement<'a> {
+    Nothing,
+    Table(&'a str, Privilege),
+    Superuser,
+}
+
+/// Table statements need the matching privilege on their table. Everything
+/// that changes the schema, the users or the database as a whole is reserved
+/// for superusers; the rest (listing tables, transactions, HELP) is open.
+pub fn requirement(statement: &Statement) -> Requirement<'_> {
+    match statement {
+        // A table read as of a time, with its deleted rows or as a graph
+        // needs the same as the table now
+        Statement::Select { from, .. } => Requirement::Table(&from.table, Privilege::Select),
+        Statement::Count(table)
+        | Statement::ShowStats(table)
+        | Statement::ShowIndexes(table)
+        | Statement::ShowCreateTable(table)
+        | Statement::Describe(table)
+        | Statement::Analyze(table)
+        | Statement::Reindex(table)
+        | Statement::RefreshTable(table)
+        | Statement::Subscribe(table) => Requirement::Table(table, Privilege::Select),
+        Statement::Explain { statement: inner, .. } => match requirement(inner) {
+            Requirement::Table(table, _) => Requirement::Table(table, Privilege::Select),
+            other => other,
+        },
+        Statement::Declare { query, .. } => requirement(query),
+        Statement::Insert { table, .. } | Statement::Copy { table, .. } => Requirement::Table(table, Privilege::Insert),
+        Statement::Update { table, .. } => Requirement::Table(table, Privilege::Update),
+        Statement::Delete { table, .. } | Statement::Undelete { table, .. } | Statement::Purge { table, .. } => {
+            Requirement::Table(table, Privilege::Delete)
+        }
+        Statement::CreateTable { .. }
+        | Statement::DropTable { .. }
+        | Statement::AddPartition { .. }
+        | Statement::SetHistoryRetention { .. }
+        | Statement::SetTtl { .. }
+        | Statement::SetSoftDelete { .. }
+        | Statement::SetTimestamps { .. }
+        | Statement::SetMaxRows { .. }
+        | Statement::SetEngine { .. }
+        | Statement::Comment { .. }
+        | Statement::SetDefault { .. }
+        | Statement::CreateSequence { .. }
+        | Statement::DropSequence { .. }
+        | Statement::Setval { .. }
+        | Statement::DropPartition { .. }
+        | Statement::CreateIndex { .. }
+        | Statement::DropIndex { .. }
+        | Statement::CreateView { .. }
+        | Statement::DropView { .. }
+        | Statement::RefreshView(_)
+        | Statement::CreateTrigger { .. }
+        | Statement::DropTrigger { .. }
+        | Statement::CreateDatabase(_)
+        | Statement::DropDatabase(_)
+        | Statement::CreateUser { .. }
+        | Statement::DropUser(_)
+        | Statement::ShowUsers
+        | Statement::CreateToken { .. }
+        | Statement::DropToken(_)
+        | Statement::ShowTokens
+        | Statement::Grant { .. }
+        | Statement::Revoke { .. }
+        | Statement::SetCompression(_)
+        | Statement::Migrate(_)
+        // Read or write files on the server, which only superusers may do
+        | Statement::CreateExternalTable { .. }
+        | Statement::Import { .. }
+        | Statement::Export { .. }
+        | Statement::Dump(_)
+        | Statement::Backup(_)
+        | Statement::Restore { .. }
+        | Statement::SetWalArchive(_)
+        | Statement::Rekey(_)
+        // Applies to every connection
+        | Statement::SetStatementTimeout(_)
+        | Statement::SetAutocommit(_)
+        | Statement::SetSynchronous(_)
+        | Statement::Source { .. }
+        | Statement::Promote
+        // Rewrite files and the log for every table, or a whole table
+        | Statement::Checkpoint
+        | Statement::Flush
+        | Statement::Vacuum(_) => Requirement::Superuser,
+        // Anyone may see their own grants, and their own sessions and kill them
+        Statement::ShowGrants(_) | Statement::ShowProcesslist | Statement::Kill(_) => Requirement::Nothing,
+        // SELECT on each table its queries read, which the caller checks
+        Statement::With { .. } => Requirement::Nothing,
+        // Privileges were checked when the cursor was declared
+        Statement::Fetch { .. } | Statement::Close(_) => Requirement::Nothing,
+        // Drawing a number changes nothing anyone else relies on
+        Statement::Nextval(_) => Requirement::Nothing,
+        Statement::ShowTables
+        | Statement::ShowTableStatus
+        | Statement::ShowSequences
+        | Statement::ShowDatabases
+        | Statement::Use(_)
+        | Statement::Begin
+        | Statement::Commit
+        | Statement::Rollback
+        | Statement::Savepoint(_)
+        | Statement::RollbackTo(_)
+        | Statement::Release(_)
+        | Statement::SetVariable { .. }
+        | Statement::Help
+        | Statement::Exit => Requirement::Nothing,
+    }
+}
+
+/// An account that may connect to the server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct User {
+    pub name: String,
+    // Argon2 hash in PHC string form, salt included
+    password_hash: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub superuser: bool,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub grants: BTreeMap<String, Vec<Privilege>>, // Table name to its privileges, sorted
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tokens: Vec<Token>,
+}
+
+/// An access token a user can log in with instead of the password. The
+/// token itself is `<name>.<secret>`; only a hash of the secret is kept.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Token {
+    pub name: String,
+    secret_hash: String, // Argon2, like a password
+    pub created: u64,    // Seconds since the Unix epoch
+}
+
+impl User {
+    pub fn new(name: &str, password: &str, superuser: bool) -> User {
+        User { name: name.to_string(), password_hash: hash(password), superuser, grants: BTreeMap::new(), tokens: Vec::new() }
+    }
+
+    pub fn allows(&self, requirement: &Requirement) -> bool {
+        match requirement {
+            Requirement::Nothing => true,
+            _ if self.superuser => true,
+            Requirement::Table(table, privilege) => {
+                self.grants.get(*table).is_some_and(|privileges| privileges.contains(privilege))
+            }
+            Requirement::Superuser => false,
+        }
+    }
+
+    pub fn verify(&self, password: &str) -> bool {
+        verify(&self.password_hash, password)
+    }
+
+    /// Whether `token` is one of the user's access tokens.
+    pub fn verify_token(&self, token: &str) -> bool {
+        let Some((name, secret)) = token.split_once('.') else { return false };
+        self.tokens.iter().any(|token| token.name == name && verify(&token.secret_hash, secret))
+    }
+}
+
+fn hash(secret: &str) -> String {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(secret.as_bytes(), &salt)
+        .expect("Argon2 with default parameters accepts any password")
+        .to_string()
+}
+
+fn verify(hash: &str, secret: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| Argon2::default().verify_password(secret.as_bytes(), &hash).is_ok())
+}
+
+/// The user an access token claims to be for, by the token's name, if any
+/// user has a token of that name. The token still has to be verified.
+pub fn token_owner<'a>(users: &'a [User], token: &str) -> Option<&'a User> {
+    let (name, _) = token.split_once('.')?;
+    users.iter().find(|user| user.tokens.iter().any(|token| token.name == name))
+}
+
+/// The user catalog, stored beside the tables.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Catalog {
+    users: Vec<User>,
+}
+
+impl Database {
+    pub fn users(&self) -> Result<Vec<User>, DbError> {
+        Ok(self.read_catalog()?.users)
+    }
+
+    pub fn user(&self, name: &str) -> Result<Option<User>, DbError> {
+        Ok(self.users()?.into_iter().find(|user| user.name == name))
+    }
+
+    pub fn create_user(&mut self, name: &str, password: &str, superuser: bool) -> Result<(), DbError> {
+        let mut catalog = self.read_catalog()?;
+        if catalog.users.iter().any(|user| user.name == name) {
+            return Err(DbError::UserExists(name.to_string()));
+        }
+        catalog.users.push(User::new(name, password, superuser));
+        self.write_catalog(&catalog)
+    }
+
+    pub fn grant(&mut self, user: &str, table: &str, privileges: &[Privilege]) -> Result<(), DbError> {
+        if !self.table_exists(table) && self.view(table)?.is_none() {
+            return Err(self.table_not_found(table));
+        }
+        self.update_user(user, |user| {
+            let granted = user.grants.entry(table.to_string()).or_default();
+            granted.extend(privileges);
+            granted.sort();
+            granted.dedup();
+        })
+    }
+
+    pub fn revoke(&mut self, user: &str, table: &str, privileges: &[Privilege]) -> Result<(), DbError> {
+        self.update_user(user, |user| {
+            if let Some(granted) = user.grants.get_mut(table) {
+                granted.retain(|privilege| !privileges.contains(privilege));
+                if granted.is_empty() {
+                    user.grants.remove(table);
+                }
+            }
+        })
+    }
+
+    /// Drops every grant on a table or view that no longer exists, so a new
+    /// one of the same name starts with none.
+    pub(crate) fn forget_grants(&mut self, table: &str) -> Result<(), DbError> {
+        let mut catalog = self.read_catalog()?;
+        if catalog.users.iter().all(|user| !user.grants.contains_key(table)) {
+            return Ok(());
+        }
+        for user in &mut catalog.users {
+            user.grants.remove(table);
+        }
+        self.write_catalog(&catalog)
+    }
+
+    fn update_user(&mut self, name: &str, change: impl FnOnce(&mut User)) -> Result<(), DbError> {
+        let mut catalog = self.read_catalog()?;
+        let user = catalog.users.iter_mut()
+            .find(|user| user.name == name)
+            .ok_or_else(|| DbError::UserNotFound(name.to_string()))?;
+        change(user);
+        self.write_catalog(&catalog)
+    }
+
+    /// Gives `user` a new access token called `name`, returning the token.
+    /// It cannot be read back later.
+    pub fn create_token(&mut self, name: &str, user: &str) -> Result<String, DbError> {
+        if name.contains('.') {
+            return Err(DbError::InvalidName(format!("a token name cannot contain '.', as in '{}'", name)));
+        }
+        if self.users()?.iter().any(|user| user.tokens.iter().any(|token| token.name == name)) {
+            return Err(DbError::TokenExists(name.to_string()));
+        }
+        let mut bytes = [0u8; TOKEN_BYTES];
+        OsRng.fill_bytes(&mut bytes);
+        let secret = URL_SAFE_NO_PAD.encode(bytes);
+        let created = self.now();
+        self.update_user(user, |user| {
+            user.tokens.push(Token { name: name.to_string(), secret_hash: hash(&secret), created });
+        })?;
+        Ok(format!("{}.{}", name, secret))
+    }
+
+    pub fn drop_token(&mut self, name: &str) -> Result<(), DbError> {
+        let mut catalog = self.read_catalog()?;
+        let user = catalog.users.iter_mut()
+            .find(|user| user.tokens.iter().any(|token| token.name == name))
+            .ok_or_else(|| DbError::TokenNotFound(name.to_string()))?;
+        user.tokens.retain(|token| token.name != name);
+        self.write_catalog(&catalog)
+    }
+
+    pub fn drop_user(&mut self, name: &str) -> Result<(), DbError> {
+        let mut catalog = self.read_catalog()?;
+        let before = catalog.users.len();
+        catalog.users.retain(|user| user.name != name);
+        if catalog.users.len() == before {
+            return Err(DbError::UserNotFound(name.to_string()));
+        }
+        self.write_catalog(&catalog)
+    }
+
+    fn read_catalog(&self) -> Result<Catalog, DbError> {
+        match self.storage.read(storage::USERS_KEY)? {
+            Some(bytes) => serde_json::from_slice(&bytes).map_err(|e| DbError::CorruptTable {
+                table: storage::USERS_KEY.to_string(),
+                reason: e.to_string(),
+            }),
+            None => Ok(Catalog::default()),
+        }
+    }
+
+    fn write_catalog(&mut self, catalog: &Catalog) -> Result<(), DbError> {
+        let bytes = serde_json::to_vec_pretty(catalog).map_err(std::io::Error::from)?;
+        self.storage.write(storage::USERS_KEY, &bytes)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,84 @@
+use crate::database::Database;
+use crate::error::DbError;
+use crate::progress;
+use crate::storage;
+
+/// What `VACUUM` did.
+#[derive(Debug)]
+pub struct VacuumReport {
+    pub tables: usize,
+    pub bytes_before: u64, // Table and index files vacuumed, rows appended beside them, plus the log
+    pub bytes_after: u64,
+}
+
+impl VacuumReport {
+    pub fn reclaimed(&self) -> u64 {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
+}
+
+impl Database {
+    /// Folds the log into the table files and truncates it, then rewrites
+    /// `table` (every stored table if None) with its indexes rebuilt from the
+    /// rows. Vacuuming everything also deletes index files and appended rows
+    /// left behind by dropped tables, and temporary files of interrupted writes.
+    pub fn vacuum(&mut self, table: Option<&str>) -> Result<VacuumReport, DbError> {
+        if self.in_transaction() {
+            return Err(DbError::TransactionActive);
+        }
+        let names = match table {
+            Some(name) if !self.table_exists(name) => return Err(self.table_not_found(name)),
+            Some(name) => vec![name.to_string()],
+            None => self.stored_names()?,
+        };
+        let names: Vec<String> = names.into_iter().filter(|name| !self.is_temp(name)).collect();
+
+        let orphans: Vec<String> = match table {
+            Some(_) => Vec::new(),
+            None => self.storage.keys()?.into_iter()
+                .filter(|key| {
+                    key.strip_suffix(".idx").or_else(|| storage::appended_table(key))
+                        .is_some_and(|name| !names.iter().any(|n| n == name))
+                })
+                .collect(),
+        };
+        let bytes_before = self.stored_bytes(&names)? + self.stored_bytes_of(&orphans)?;
+
+        self.checkpoint()?;
+        let _running = progress::start(|| "Vacuuming".to_string(), Some(names.len()), "tables");
+        for name in &names {
+            let mut table = self.load_table(name)?.clone();
+            table.rebuild_indexes();
+            self.save_table(&table)?;
+            progress::advance(1);
+        }
+        for key in &orphans {
+            self.storage.remove(key)?;
+        }
+        if table.is_none() {
+            self.storage.discard_torn_writes()?;
+        }
+
+        Ok(VacuumReport { tables: names.len(), bytes_before, bytes_after: self.stored_bytes(&names)? })
+    }
+
+    // The table and index files of `names` and the rows appended beside
+    // them, plus the log
+    fn stored_bytes(&self, names: &[String]) -> Result<u64, DbError> {
+        let appended = self.storage.keys()?.into_iter()
+            .filter(|key| storage::appended_table(key).is_some_and(|name| names.iter().any(|n| n == name)));
+        let keys: Vec<String> = names.iter()
+            .flat_map(|name| [storage::table_key(name), storage::index_key(name)])
+            .chain(appended)
+            .collect();
+        Ok(self.stored_bytes_of(&keys)? + self.wal.size())
+    }
+
+    fn stored_bytes_of(&self, keys: &[String]) -> Result<u64, DbError> {
+        let mut bytes = 0;
+        for key in keys {
+            bytes += self.storage.read(key)?.map_or(0, |blob| blob.len() as u64);
+        }
+        Ok(bytes)
+    }
+}
@@ -0,0 +1,81 @@
+//! Session variables, for parameterized scripts: `SET @start = 100` (or
+//! `\set start 100` at the prompt) and then `SELECT * FROM t WHERE id >
+//! @start`. Each `@name` in a statement, outside string literals and
+//! comments, is replaced by its variable's value as a literal before the
+//! statement is parsed, so a variable can stand wherever a value can.
+
+use std::collections::BTreeMap;
+
+use crate::dump::literal;
+use crate::error::DbError;
+use crate::parser::{self, Token};
+use crate::DataType;
+
+/// The variables of a session, by name.
+pub type Variables = BTreeMap<String, DataType>;
+
+/// `text` with each `@name` replaced by the literal of its variable's value.
+/// The `@name` a `SET` assigns to is left as it is.
+pub fn substitute(text: &str, variables: &Variables) -> Result<String, DbError> {
+    if !text.contains('@') {
+        return Ok(text.to_string());
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => quoted = !quoted,
+            '-' if !quoted && chars.peek() == Some(&'-') => {
+                out.push(c);
+                while let Some(c) = chars.next_if(|&c| c != '\n') {
+                    out.push(c);
+                }
+                continue;
+            }
+            '@' if !quoted && chars.peek().is_some_and(|&c| is_name_char(c)) && !assigns(&out) => {
+                let mut name = String::new();
+                while let Some(c) = chars.next_if(|&c| is_name_char(c)) {
+                    name.push(c);
+                }
+                let value = variables.get(&name).ok_or(DbError::VariableNotFound(name))?;
+                out.push_str(&literal(value));
+                continue;
+            }
+            _ => {}
+        }
+        out.push(c);
+    }
+    Ok(out)
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Whether `name` can be written after an `@`.
+pub fn is_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(is_name_char)
+}
+
+// Whether `before`, the statement up to an `@name`, is a SET assigning to it
+fn assigns(before: &str) -> bool {
+    parser::tokenize(before).is_ok_and(|tokens| matches!(&tokens[..], [Token::Ident(word)] if word.eq_ignore_ascii_case("SET")))
+}
+
+/// The value `\set` gives a variable from what follows its name: an int or
+/// a float if it reads as one, the text of a quoted string, or else the text
+/// as it is.
+pub fn parse(text: &str) -> DataType {
+    if let Ok(i) = text.parse() {
+        DataType::Integer32(i)
+    } else if let Ok(f) = text.parse::<f32>()
+        && text.chars().all(|c| c.is_ascii_digit() || matches!(c, '.' | '-' | '+' | 'e' | 'E'))
+    {
+        DataType::Float32(f)
+    } else if text.len() >= 2 && text.starts_with('\'') && text.ends_with('\'') {
+        DataType::String(text[1..text.len() - 1].replace("''", "'"))
+    } else {
+        DataType::String(text.to_string())
+    }
+}
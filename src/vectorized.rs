@@ -0,0 +1,59 @@
+//! Work on whole columns at once. A numeric column's values are checked to
+//! be of one type as they are read, in place, and the comparison or sum then
+//! runs as a single loop over the column rather than going through a
+//! condition or expression for every row. Columns holding anything else are
+//! left to the row-by-row path, whose comparison this one shares.
+
+use std::cmp::Ordering;
+
+use crate::parser::CmpOp;
+use crate::table::{self, DataType};
+
+/// For each value of a numeric column, whether it compares to `target` as
+/// `op` asks, as the row-by-row path would find. None if the column is not
+/// numeric, `target` is not of its type, or `op` is not a plain comparison.
+pub(crate) fn compare(values: &[DataType], op: CmpOp, target: &DataType) -> Option<Vec<bool>> {
+    match target {
+        DataType::Integer32(target) => test(values, op, |value| match value {
+            DataType::Integer32(i) => Some(i.cmp(target)),
+            _ => None,
+        }),
+        DataType::Float32(target) => test(values, op, |value| match value {
+            DataType::Float32(f) => Some(table::compare_floats(*f, *target)),
+            _ => None,
+        }),
+        _ => None,
+    }
+}
+
+fn test(values: &[DataType], op: CmpOp, cmp: impl Fn(&DataType) -> Option<Ordering>) -> Option<Vec<bool>> {
+    if matches!(op, CmpOp::Match | CmpOp::Contains | CmpOp::In | CmpOp::NotIn) {
+        return None;
+    }
+    values.iter().map(|value| cmp(value).map(|ord| op.test(ord))).collect()
+}
+
+/// The sum of a numeric column over `rows`, added in their order: an int
+/// for ints, or an error if that does not fit one, and a float for floats.
+/// None if the column is not numeric.
+pub(crate) fn sum(values: &[DataType], rows: &[usize]) -> Option<Result<DataType, String>> {
+    match rows.first().map(|&row| &values[row]) {
+        None | Some(DataType::Integer32(_)) => {
+            let mut total: i64 = 0;
+            for &row in rows {
+                let DataType::Integer32(i) = values[row] else { return None };
+                total += i64::from(i);
+            }
+            Some(i32::try_from(total).map(DataType::Integer32).map_err(|_| "result is too large for an int".to_string()))
+        }
+        Some(DataType::Float32(_)) => {
+            let mut total = 0.0;
+            for &row in rows {
+                let DataType::Float32(f) = values[row] else { return None };
+                total += f;
+            }
+            Some(Ok(DataType::Float32(total)))
+        }
+        _ => None,
+    }
+}
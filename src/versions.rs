@@ -0,0 +1,86 @@
+//! When each table last changed, and which tables a query read, so a result
+//! worked out from them can be served again until one of them changes. Every
+//! change moves a clock forward and stamps the table with it; a result taken
+//! at some reading of the clock is still good if none of what it read has a
+//! later stamp.
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::database::Database;
+
+/// What a query read.
+#[derive(Debug, Default)]
+pub struct Reads {
+    pub names: BTreeSet<String>, // Tables and views, by the name stored under
+    pub volatile: bool,          // Whether it read something that changes without being written
+}
+
+#[derive(Default)]
+pub(crate) struct Versions {
+    clock: u64,
+    changed: HashMap<String, u64>,
+    everything: u64, // When every table was last read again from storage
+    pub(crate) reads: Recorder,
+}
+
+/// Where what the query running reads is recorded, if it is. Shared with
+/// the readers of a `SharedDatabase`, which record without the database.
+#[derive(Clone, Default)]
+pub(crate) struct Recorder(Arc<Mutex<Option<Reads>>>); // Being recorded, if set
+
+impl Recorder {
+    /// Records that `name` was read, if reads are recorded.
+    pub(crate) fn read(&self, name: &str, volatile: bool) {
+        if let Some(reads) = self.lock().as_mut() {
+            reads.names.insert(name.to_string());
+            reads.volatile |= volatile;
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<Reads>> {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Versions {
+    /// Notes that table `name` has changed; a partition's change is its table's.
+    pub(crate) fn changed(&mut self, name: &str) {
+        self.clock += 1;
+        let table = name.split('#').next().unwrap_or(name);
+        self.changed.insert(table.to_string(), self.clock);
+    }
+
+    /// Notes that anything may have changed.
+    pub(crate) fn changed_all(&mut self) {
+        self.clock += 1;
+        self.everything = self.clock;
+    }
+
+    /// Records that the query running read `name`, if reads are recorded.
+    pub(crate) fn read(&self, name: &str, volatile: bool) {
+        self.reads.read(name, volatile);
+    }
+}
+
+impl Database {
+    /// Starts recording what is read, up to `take_reads`.
+    pub fn record_reads(&self) {
+        *self.versions.reads.lock() = Some(Reads::default());
+    }
+
+    /// Stops recording, returning what was read since `record_reads`.
+    pub fn take_reads(&self) -> Reads {
+        self.versions.reads.lock().take().unwrap_or_default()
+    }
+
+    /// The clock changes are stamped with, as it reads now.
+    pub fn version(&self) -> u64 {
+        self.versions.clock
+    }
+
+    /// Whether any of `names` has changed since the clock read `at`.
+    pub fn changed_since(&self, names: &BTreeSet<String>, at: u64) -> bool {
+        self.versions.everything > at || names.iter().any(|name| self.versions.changed.get(name).is_some_and(|&changed| changed > at))
+    }
+}
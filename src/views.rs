@@ -0,0 +1,199 @@
+use serde::{Serialize, Deserialize};
+
+use crate::catalog;
+use crate::database::Database;
+use crate::error::DbError;
+use crate::index::IndexDef;
+use crate::parser::Predicate;
+use crate::planner;
+use crate::storage;
+use crate::Table;
+
+/// A named `SELECT * FROM <table> WHERE ...`, expanded whenever it is
+/// queried. A materialized view instead keeps its rows in a table of the same
+/// name, computed when it is created or refreshed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct View {
+    pub name: String,
+    pub table: String, // A table or another view
+    pub filter: Vec<Predicate>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub materialized: Option<Refresh>,
+}
+
+/// When a materialized view's rows were last computed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Refresh {
+    pub at: u64,  // Seconds since the Unix epoch
+    pub lsn: u64, // LSN of the table underneath at the time
+}
+
+/// Whether a materialized view still matches the table it was computed from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Freshness {
+    Fresh,
+    Stale,
+    Broken, // The table underneath is gone
+}
+
+/// The view catalog, stored beside the tables.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Catalog {
+    views: Vec<View>,
+}
+
+impl Database {
+    pub fn views(&self) -> Result<Vec<View>, DbError> {
+        Ok(self.read_views()?.views)
+    }
+
+    pub fn view(&self, name: &str) -> Result<Option<View>, DbError> {
+        Ok(self.views()?.into_iter().find(|view| view.name == name))
+    }
+
+    /// Saves a view after checking that what it selects from exists and has
+    /// the columns its conditions name. A materialized view also gets its
+    /// table, filled right away.
+    pub fn create_view(&mut self, name: &str, table: &str, filter: Vec<Predicate>, materialized: bool) -> Result<(), DbError> {
+        if catalog::is_system_table(name) {
+            return Err(DbError::SystemTable(name.to_string()));
+        }
+        let mut catalog = self.read_views()?;
+        if self.table_exists(name) || catalog.views.iter().any(|view| view.name == name) {
+            return Err(DbError::ViewExists(name.to_string()));
+        }
+        // A subquery runs once, and a view is read again and again
+        if filter.iter().any(|p| p.subquery.is_some()) {
+            return Err(DbError::Syntax("a view's conditions cannot hold a subquery".to_string()));
+        }
+        let (base, conditions) = self.resolve_view(table, &filter)?;
+        let functions = self.functions();
+        let base = self.load_table(&base)?;
+        for p in &conditions {
+            p.left.check(base, &functions)?;
+        }
+
+        let mut view = View { name: name.to_string(), table: table.to_string(), filter, materialized: None };
+        if materialized {
+            view.materialized = Some(self.materialize(&view, Vec::new())?);
+        }
+        catalog.views.push(view);
+        self.write_views(&catalog)
+    }
+
+    /// Recomputes the rows of a materialized view. Returns how many it holds.
+    pub fn refresh_view(&mut self, name: &str) -> Result<usize, DbError> {
+        let mut catalog = self.read_views()?;
+        let view = catalog.views.iter_mut()
+            .find(|view| view.name == name)
+            .ok_or_else(|| DbError::ViewNotFound(name.to_string()))?;
+        if view.materialized.is_none() {
+            return Err(DbError::NotMaterialized(name.to_string()));
+        }
+
+        // Indexes created on the view survive the refresh
+        let index_defs = self.load_table(name).map(|table| table.index_defs.clone()).unwrap_or_default();
+        let refresh = self.materialize(view, index_defs)?;
+        view.materialized = Some(refresh);
+        self.write_views(&catalog)?;
+        Ok(self.load_table(name)?.row_count())
+    }
+
+    // Writes the view's current rows to its table
+    fn materialize(&mut self, view: &View, index_defs: Vec<IndexDef>) -> Result<Refresh, DbError> {
+        let (base, filter) = self.resolve_view(&view.table, &view.filter)?;
+        let source = self.snapshot(&base)?;
+        let rows = planner::plan(&source, &filter, &self.functions())?.rows()?;
+
+        let columns = source.columns.iter().map(|col| (col.clone(), source.fields[col].clone())).collect();
+        let mut table = Table::new(&view.name, columns, None, self.last_lsn(), self.now());
+        for col in &source.columns {
+            table.data.insert(col.clone(), rows.iter().map(|&i| source.data[col][i].clone()).collect());
+        }
+        table.index_defs = index_defs;
+        table.rebuild_indexes();
+        self.save_table(&table)?;
+
+        let at = self.now();
+        Ok(Refresh { at, lsn: source.lsn })
+    }
+
+    /// Compares a materialized view's last refresh with its table underneath.
+    pub fn freshness(&mut self, view: &View) -> Result<Freshness, DbError> {
+        let Some(refresh) = &view.materialized else { return Ok(Freshness::Fresh) };
+        let (base, _) = self.resolve_view(&view.table, &[])?;
+        match self.load_table(&base) {
+            Ok(table) if table.lsn == refresh.lsn => Ok(Freshness::Fresh),
+            Ok(_) => Ok(Freshness::Stale),
+            Err(DbError::TableNotFound { .. }) => Ok(Freshness::Broken),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Deletes a view, and the table holding its rows if it is materialized.
+    pub fn drop_view(&mut self, name: &str) -> Result<(), DbError> {
+        let mut catalog = self.read_views()?;
+        let position = catalog.views.iter()
+            .position(|view| view.name == name)
+            .ok_or_else(|| DbError::ViewNotFound(name.to_string()))?;
+        let view = catalog.views.remove(position);
+        self.write_views(&catalog)?;
+        if view.materialized.is_some() {
+            self.drop_table(name)?;
+        }
+        self.forget_grants(name)
+    }
+
+    /// Fails for a view: plain views have no rows of their own, and the rows of
+    /// a materialized view only change on REFRESH.
+    pub fn check_writable(&self, name: &str) -> Result<(), DbError> {
+        if catalog::is_system_table(name) {
+            return Err(DbError::SystemTable(name.to_string()));
+        }
+        if self.view(name)?.is_some() {
+            return Err(DbError::ReadOnlyView(name.to_string()));
+        }
+        match self.external(name)? {
+            Some(_) => Err(DbError::ExternalTable(name.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Follows `name` through any views to the table underneath, with their
+    /// conditions ahead of `filter`. A table comes back unchanged. There are
+    /// no cycles to guard against: a view can only be created on top of
+    /// something that already resolves to a table.
+    pub fn resolve_view(&self, name: &str, filter: &[Predicate]) -> Result<(String, Vec<Predicate>), DbError> {
+        // A WITH result hides a view of the same name
+        if self.ctes.contains_key(name) {
+            return Ok((name.to_string(), filter.to_vec()));
+        }
+        self.versions.read(storage::VIEWS_KEY, false);
+        let views = self.views()?;
+        let mut table = name;
+        let mut conditions = filter.to_vec();
+        // A materialized view is read like the table it is
+        while let Some(view) = views.iter().find(|view| view.name == table && view.materialized.is_none()) {
+            conditions.splice(0..0, view.filter.iter().cloned());
+            table = &view.table;
+        }
+        Ok((table.to_string(), conditions))
+    }
+
+    fn read_views(&self) -> Result<Catalog, DbError> {
+        match self.storage.read(storage::VIEWS_KEY)? {
+            Some(bytes) => serde_json::from_slice(&bytes).map_err(|e| DbError::CorruptTable {
+                table: storage::VIEWS_KEY.to_string(),
+                reason: e.to_string(),
+            }),
+            None => Ok(Catalog::default()),
+        }
+    }
+
+    fn write_views(&mut self, catalog: &Catalog) -> Result<(), DbError> {
+        self.versions.changed(storage::VIEWS_KEY);
+        let bytes = serde_json::to_vec_pretty(catalog).map_err(std::io::Error::from)?;
+        self.storage.write(storage::VIEWS_KEY, &bytes)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,389 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::{Serialize, Deserialize};
+
+use crate::encryption::{Cipher, SharedCipher};
+use crate::replication::{Change, Feed};
+use crate::storage;
+use crate::time::{Clock, SystemClock};
+use crate::{DataType, Table};
+
+// Once the log reaches this size (unless set otherwise) it is folded into
+// the table files, rows only inserted appended beside them (see `append`).
+pub(crate) const CHECKPOINT_BYTES: u64 = 4 * 1024 * 1024;
+
+const SEGMENT_EXTENSION: &str = "wal";
+
+/// When logged changes are fsynced, chosen with `SET synchronous`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Synchronous {
+    Full, // Before each statement returns
+    #[default]
+    Batched, // Once for all the statements of an input line
+}
+
+impl Synchronous {
+    pub fn parse(name: &str) -> Option<Synchronous> {
+        match name.to_ascii_lowercase().as_str() {
+            "full" => Some(Synchronous::Full),
+            "batched" => Some(Synchronous::Batched),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Synchronous::Full => "full",
+            Synchronous::Batched => "batched",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum WalOp {
+    Insert { table: String, row: Vec<DataType> },
+    Delete { table: String, index: usize },
+    DeleteRows { table: String, rows: Vec<usize> }, // Ascending positions
+    // New values for some columns of the row at position `row`, which stays
+    // where it is; columns left out keep theirs
+    Update { table: String, row: usize, values: Vec<(String, DataType)> },
+    // Rows of a table that soft-deletes, marked deleted or brought back; ascending positions
+    Tombstone { table: String, rows: Vec<usize>, deleted: bool },
+    // A committed transaction, logged as one record so it is replayed entirely or not at all
+    Transaction { ops: Vec<WalOp> },
+    Checkpoint,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalRecord {
+    pub lsn: u64,
+    pub op: WalOp,
+    #[serde(default)]
+    pub at: u64, // When it was logged, in seconds since the Unix epoch; 0 in older logs
+}
+
+impl WalOp {
+    pub fn table(&self) -> Option<&str> {
+        match self {
+            WalOp::Insert { table, .. }
+            | WalOp::Delete { table, .. }
+            | WalOp::DeleteRows { table, .. }
+            | WalOp::Update { table, .. }
+            | WalOp::Tombstone { table, .. } => Some(table),
+            WalOp::Transaction { .. } | WalOp::Checkpoint => None,
+        }
+    }
+
+    /// Every table the record mutates.
+    pub fn tables(&self) -> Vec<&str> {
+        match self {
+            WalOp::Transaction { ops } => ops.iter().flat_map(WalOp::tables).collect(),
+            op => op.table().into_iter().collect(),
+        }
+    }
+}
+
+pub struct Wal {
+    path: Option<PathBuf>, // None keeps the log in `buffer` for in-memory databases
+    buffer: Vec<u8>,
+    next_lsn: u64,
+    pending: u64, // Mutations logged since the last checkpoint
+    size: u64,    // Bytes in the log
+    checkpoint_bytes: u64,
+    deferred: bool, // Whether appends leave the fsync to `sync`
+    unsynced: bool, // Whether records were appended since the last fsync
+    discarded: usize, // Bytes of a torn record `open` cut off, until `repair` reports them
+    pub(crate) feed: Option<Feed>, // Followers, once any has asked for the log
+    pub(crate) cipher: SharedCipher, // What records are sealed with, if anything
+    clock: Arc<dyn Clock>, // Gives the time of each record
+}
+
+impl Wal {
+    /// The log at `path`, its records sealed with `cipher` while that is
+    /// set. A torn record a crash left at its end is cut off, so records
+    /// appended from now on are not lost behind it.
+    pub fn open(path: &Path, cipher: SharedCipher) -> io::Result<Wal> {
+        let mut wal = Wal::open_read_only(path, cipher)?;
+        wal.discarded = wal.repair()?;
+        Ok(wal)
+    }
+
+    /// The log at `path`, as `open` gives it, but never written: a torn
+    /// record at its end is left there, and skipped.
+    pub fn open_read_only(path: &Path, cipher: SharedCipher) -> io::Result<Wal> {
+        let mut wal = Wal::in_memory();
+        wal.path = Some(path.to_path_buf());
+        wal.cipher = cipher;
+        let bytes = wal.read_log()?;
+        let (records, valid_len) = parse_records(&bytes, wal.key().as_ref());
+        wal.size = valid_len as u64;
+        wal.next_lsn = records.last().map(|r| r.lsn + 1).unwrap_or(1);
+        wal.pending = records.iter()
+            .filter(|r| !matches!(r.op, WalOp::Checkpoint))
+            .count() as u64;
+        Ok(wal)
+    }
+
+    pub fn in_memory() -> Wal {
+        Wal {
+            path: None, buffer: Vec::new(), next_lsn: 1, pending: 0, size: 0, checkpoint_bytes: CHECKPOINT_BYTES, deferred: false,
+            unsynced: false, discarded: 0, feed: None, cipher: SharedCipher::default(), clock: Arc::new(SystemClock),
+        }
+    }
+
+    pub(crate) fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Whether the log is kept in a file, rather than for an in-memory database.
+    pub fn is_on_disk(&self) -> bool {
+        self.path.is_some()
+    }
+
+    /// LSN of the most recent record; tables created now start from here.
+    pub fn last_lsn(&self) -> u64 {
+        self.next_lsn - 1
+    }
+
+    pub fn pending(&self) -> u64 {
+        self.pending
+    }
+
+    /// Appends `op` to the log and fsyncs it, unless that is deferred to
+    /// `sync`. Only once this returns may the caller apply the mutation to a
+    /// table.
+    pub fn append(&mut self, op: WalOp) -> io::Result<u64> {
+        let record = WalRecord { lsn: self.next_lsn, op, at: self.clock.now() };
+        self.append_record(record)
+    }
+
+    /// Appends a record logged elsewhere, keeping its LSN and time, as a
+    /// follower does with its leader's.
+    pub fn append_record(&mut self, record: WalRecord) -> io::Result<u64> {
+        let line = encode_line(&record, self.key().as_ref())?;
+
+        match &self.path {
+            Some(path) => {
+                let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                file.write_all(line.as_bytes())?;
+                if self.deferred {
+                    self.unsynced = true;
+                } else {
+                    file.sync_data()?;
+                }
+            }
+            None => self.buffer.extend(line.as_bytes()),
+        }
+
+        self.next_lsn = record.lsn + 1;
+        self.pending += 1;
+        self.size += line.len() as u64;
+        let lsn = record.lsn;
+        if let Some(feed) = &self.feed {
+            feed.publish(Change::Record(record));
+        }
+        Ok(lsn)
+    }
+
+    /// Makes appends written but not fsynced until `sync`, so a batch of
+    /// them reaches the disk with one fsync; or, if `deferred` is false,
+    /// fsynced one by one again.
+    pub fn defer_sync(&mut self, deferred: bool) {
+        self.deferred = deferred;
+    }
+
+    /// Fsyncs the records appended since the last fsync, if any.
+    pub fn sync(&mut self) -> io::Result<()> {
+        if let (true, Some(path)) = (self.unsynced, &self.path) {
+            OpenOptions::new().append(true).open(path)?.sync_data()?;
+        }
+        self.unsynced = false;
+        Ok(())
+    }
+
+    /// Makes the next record follow `lsn` if the log is behind it, as when
+    /// tables written elsewhere are brought in.
+    pub fn advance_to(&mut self, lsn: u64) {
+        self.next_lsn = self.next_lsn.max(lsn + 1);
+    }
+
+    /// Bytes in the log.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn needs_checkpoint(&self) -> bool {
+        self.size >= self.checkpoint_bytes
+    }
+
+    pub fn checkpoint_bytes(&self) -> u64 {
+        self.checkpoint_bytes
+    }
+
+    pub fn set_checkpoint_bytes(&mut self, bytes: u64) {
+        self.checkpoint_bytes = bytes;
+    }
+
+    pub fn records(&self) -> io::Result<Vec<WalRecord>> {
+        Ok(parse_records(&self.read_log()?, self.key().as_ref()).0)
+    }
+
+    /// Applies every logged mutation for `table` that its file does not contain
+    /// yet. Returns how many records were applied.
+    pub fn replay(&self, table: &mut Table) -> io::Result<usize> {
+        let mut applied = 0;
+        for record in self.records()? {
+            if record.lsn > table.lsn && record.op.tables().contains(&table.name.as_str()) {
+                table.apply(&record.op, record.at);
+                table.lsn = record.lsn;
+                applied += 1;
+            }
+        }
+        Ok(applied)
+    }
+
+    /// Empties the log once every table file contains its records.
+    pub fn truncate(&mut self) -> io::Result<()> {
+        // Keep the LSN sequence going across truncation
+        let marker = WalRecord { lsn: self.last_lsn(), op: WalOp::Checkpoint, at: self.clock.now() };
+        let line = encode_line(&marker, self.key().as_ref())?;
+        self.write_log(line.as_bytes())?;
+        self.pending = 0;
+        self.size = line.len() as u64;
+        if let Some(feed) = &self.feed {
+            feed.publish(Change::Truncate { lsn: marker.lsn });
+        }
+        Ok(())
+    }
+
+    /// Empties the log, going on from `lsn` whether that is ahead of the
+    /// log or behind it.
+    pub fn truncate_at(&mut self, lsn: u64) -> io::Result<()> {
+        self.next_lsn = lsn + 1;
+        self.truncate()
+    }
+
+    /// Copies the records logged since the last truncation into a segment
+    /// file in `dir`, named after the first and last LSN it holds, for
+    /// point-in-time recovery. Returns its path, or None if there was
+    /// nothing to archive.
+    pub fn archive(&self, dir: &Path) -> io::Result<Option<PathBuf>> {
+        let records: Vec<WalRecord> = self.records()?.into_iter()
+            .filter(|record| !matches!(record.op, WalOp::Checkpoint))
+            .collect();
+        let (Some(first), Some(last)) = (records.first(), records.last()) else {
+            return Ok(None);
+        };
+        let bytes = self.encode(&records)?;
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!("{:020}-{:020}.{}", first.lsn, last.lsn, SEGMENT_EXTENSION));
+        storage::write_atomic(&path, &bytes)?;
+        Ok(Some(path))
+    }
+
+    /// `records` as lines of a log, sealed like this one's.
+    pub(crate) fn encode(&self, records: &[WalRecord]) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        for record in records {
+            bytes.extend(encode_line(record, self.key().as_ref())?.as_bytes());
+        }
+        Ok(bytes)
+    }
+
+    /// Cuts off a torn record left by a crash mid-append. Returns the number
+    /// of bytes discarded, with those `open` cut off and not reported yet.
+    pub fn repair(&mut self) -> io::Result<usize> {
+        let discarded = std::mem::take(&mut self.discarded);
+        let bytes = self.read_log()?;
+        let (_, valid_len) = parse_records(&bytes, self.key().as_ref());
+        if valid_len == bytes.len() {
+            return Ok(discarded);
+        }
+        self.write_log(&bytes[..valid_len])?;
+        self.size = valid_len as u64;
+        Ok(discarded + bytes.len() - valid_len)
+    }
+
+    pub(crate) fn key(&self) -> Option<Cipher> {
+        self.cipher.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    fn read_log(&self) -> io::Result<Vec<u8>> {
+        let Some(path) = &self.path else { return Ok(self.buffer.clone()) };
+        match fs::read(path) {
+            Ok(bytes) => Ok(bytes),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn write_log(&mut self, bytes: &[u8]) -> io::Result<()> {
+        match &self.path {
+            Some(path) => storage::write_atomic(path, bytes),
+            None => {
+                self.buffer = bytes.to_vec();
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The records of every segment archived in `dir`, in LSN order. Those
+/// sealed need `cipher` to be read.
+pub fn archived_records(dir: &Path, cipher: Option<&Cipher>) -> io::Result<Vec<WalRecord>> {
+    let mut records = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == SEGMENT_EXTENSION) {
+            records.extend(parse_records(&fs::read(&path)?, cipher).0);
+        }
+    }
+    records.sort_by_key(|record| record.lsn);
+    records.dedup_by_key(|record| record.lsn);
+    Ok(records)
+}
+
+// A record as a line of the log: its JSON, or with a key, the JSON sealed
+// and in base64
+fn encode_line(record: &WalRecord, cipher: Option<&Cipher>) -> io::Result<String> {
+    let json = serde_json::to_string(record)?;
+    let mut line = match cipher {
+        Some(cipher) => STANDARD.encode(cipher.seal(json.as_bytes())),
+        None => json,
+    };
+    line.push('\n');
+    Ok(line)
+}
+
+// With a key, only records sealed under it are read, so none can be slipped
+// into the log without it; `REKEY` writes the log again under the new key
+fn decode_line(line: &[u8], cipher: Option<&Cipher>) -> Option<WalRecord> {
+    let line = line.strip_suffix(b"\n")?;
+    match cipher {
+        Some(cipher) => serde_json::from_slice(&cipher.open(&STANDARD.decode(line).ok()?).ok()?).ok(),
+        None => serde_json::from_slice(line).ok(),
+    }
+}
+
+// Returns the complete records and the length of the prefix they occupy.
+fn parse_records(bytes: &[u8], cipher: Option<&Cipher>) -> (Vec<WalRecord>, usize) {
+    let mut records = Vec::new();
+    let mut offset = 0;
+    for line in bytes.split_inclusive(|b| *b == b'\n') {
+        // A torn final line means the process died mid-append; that mutation was never applied
+        if !line.ends_with(b"\n") {
+            break;
+        }
+        match decode_line(line, cipher) {
+            Some(record) => records.push(record),
+            None => break,
+        }
+        offset += line.len();
+    }
+    (records, offset)
+}
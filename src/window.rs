@@ -0,0 +1,208 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::budget;
+use crate::error::DbError;
+use crate::expr::{BinaryOp, Expr};
+use crate::functions::Functions;
+use crate::interrupt;
+use crate::parser::SortKey;
+use crate::profile;
+use crate::query::{compare, sort_value};
+use crate::vectorized;
+use crate::{DataType, Table};
+
+/// What a window computes for each row of its partition.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WindowFunction {
+    RowNumber, // 1, 2, 3, ... in order
+    Rank,      // The row number of the first row with the same ORDER BY values
+    Sum,       // Running total, up to and including rows with the same ORDER BY values
+    Avg,       // Running average, likewise
+}
+
+impl WindowFunction {
+    pub fn parse(name: &str) -> Option<WindowFunction> {
+        match name.to_ascii_uppercase().as_str() {
+            "ROW_NUMBER" => Some(WindowFunction::RowNumber),
+            "RANK" => Some(WindowFunction::Rank),
+            "SUM" => Some(WindowFunction::Sum),
+            "AVG" => Some(WindowFunction::Avg),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WindowFunction::RowNumber => "ROW_NUMBER",
+            WindowFunction::Rank => "RANK",
+            WindowFunction::Sum => "SUM",
+            WindowFunction::Avg => "AVG",
+        }
+    }
+
+    /// How many arguments it takes: the value to add up, for SUM and AVG.
+    pub fn arity(&self) -> usize {
+        match self {
+            WindowFunction::RowNumber | WindowFunction::Rank => 0,
+            WindowFunction::Sum | WindowFunction::Avg => 1,
+        }
+    }
+}
+
+/// `<function>(<arg>) OVER (PARTITION BY <expr>, ... ORDER BY <expr>, ...)`:
+/// a value for each row computed from the rows sharing its PARTITION BY
+/// values (all rows, if there are none), taken in ORDER BY order. Without an
+/// ORDER BY every row of a partition is level with the others, so SUM and
+/// AVG give the partition's total and average.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Window {
+    pub function: WindowFunction,
+    pub arg: Option<Expr>,
+    pub partition: Vec<Expr>,
+    pub order: Vec<SortKey>,
+}
+
+impl Window {
+    /// The expressions the window reads.
+    pub fn exprs(&self) -> impl Iterator<Item = &Expr> {
+        self.arg.iter().chain(&self.partition).chain(self.order.iter().map(|key| &key.expr))
+    }
+
+    /// The same window with every column name replaced by what `rename` gives for it.
+    pub fn rename_columns(&self, rename: &mut impl FnMut(&str) -> Result<String, DbError>) -> Result<Window, DbError> {
+        Ok(Window {
+            function: self.function,
+            arg: self.arg.as_ref().map(|arg| arg.rename_columns(rename)).transpose()?,
+            partition: self.partition.iter().map(|expr| expr.rename_columns(rename)).collect::<Result<_, _>>()?,
+            order: self.order.iter()
+                .map(|key| Ok(SortKey { expr: key.expr.rename_columns(rename)?, descending: key.descending }))
+                .collect::<Result<_, DbError>>()?,
+        })
+    }
+
+    /// The window's value for each of `rows`, positions in `table`.
+    fn compute(&self, table: &Table, rows: &[usize], functions: &Functions) -> Result<Vec<(usize, DataType)>, DbError> {
+        let fail = |reason: String| DbError::InvalidExpression(format!("{}: {}", self, reason));
+        let mut keyed = rows.iter()
+            .map(|&row| {
+                interrupt::check()?;
+                let partition = self.partition.iter().map(|expr| sort_value(table, expr, row, functions)).collect::<Result<Vec<_>, _>>()?;
+                let order = self.order.iter().map(|key| sort_value(table, &key.expr, row, functions)).collect::<Result<Vec<_>, _>>()?;
+                budget::charge(budget::size_of(&partition) + budget::size_of(&order))?;
+                Ok((partition, order, row))
+            })
+            .collect::<Result<Vec<_>, DbError>>()?;
+        keyed.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| compare(&self.order, &a.1, &b.1)));
+
+        let mut values = Vec::with_capacity(keyed.len());
+        for partition in keyed.chunk_by(|a, b| a.0 == b.0) {
+            let (mut seen, mut total) = (0, None);
+            for peers in partition.chunk_by(|a, b| a.1 == b.1) {
+                // A running total takes in every row level with the current one
+                let column = match &self.arg {
+                    Some(Expr::Column(column)) => table.data.get(column),
+                    _ => None,
+                };
+                let rows: Vec<usize> = peers.iter().map(|&(_, _, row)| row).collect();
+                if let Some(sum) = column.and_then(|values| vectorized::sum(values, &rows)) {
+                    let sum = sum.map_err(fail)?;
+                    total = Some(match total {
+                        Some(total) => BinaryOp::Add.apply(&total, &sum).map_err(fail)?,
+                        None => sum,
+                    });
+                } else if let Some(arg) = &self.arg {
+                    for &(_, _, row) in peers {
+                        let value = arg.eval(table, row, functions)?;
+                        if !matches!(value, DataType::Integer32(_) | DataType::Float32(_)) {
+                            return Err(fail(format!("{} adds up numbers, not '{}'", self.function.name(), value)));
+                        }
+                        total = Some(match total {
+                            Some(total) => BinaryOp::Add.apply(&total, &value).map_err(fail)?,
+                            None => value,
+                        });
+                    }
+                }
+                let counted = seen + peers.len();
+                for (i, &(_, _, row)) in peers.iter().enumerate() {
+                    let value = match (self.function, &total) {
+                        (WindowFunction::RowNumber, _) => DataType::Integer32((seen + i + 1) as i32),
+                        (WindowFunction::Rank, _) => DataType::Integer32((seen + 1) as i32),
+                        (WindowFunction::Sum, Some(total)) => total.clone(),
+                        (WindowFunction::Avg, Some(DataType::Integer32(total))) => DataType::Float32(*total as f32 / counted as f32),
+                        (WindowFunction::Avg, Some(DataType::Float32(total))) => DataType::Float32(total / counted as f32),
+                        _ => unreachable!("SUM and AVG have a numeric total"),
+                    };
+                    values.push((row, value));
+                }
+                seen = counted;
+            }
+        }
+        Ok(values)
+    }
+}
+
+impl fmt::Display for Window {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let arg = self.arg.as_ref().map(Expr::to_string).unwrap_or_default();
+        let mut clauses = Vec::new();
+        if !self.partition.is_empty() {
+            let partition: Vec<String> = self.partition.iter().map(Expr::to_string).collect();
+            clauses.push(format!("PARTITION BY {}", partition.join(", ")));
+        }
+        if !self.order.is_empty() {
+            let order: Vec<String> = self.order.iter()
+                .map(|key| if key.descending { format!("{} DESC", key.expr) } else { key.expr.to_string() })
+                .collect();
+            clauses.push(format!("ORDER BY {}", order.join(", ")));
+        }
+        write!(f, "{}({}) OVER ({})", self.function.name(), arg, clauses.join(" "))
+    }
+}
+
+/// `table` with a column for each window function in `exprs`, named as the
+/// function is written and filled in for `rows`, the rows a query matched.
+/// `table` itself if they use none.
+pub(crate) fn with_windows(table: &Arc<Table>, rows: &[usize], exprs: &[&Expr], functions: &Functions) -> Result<Arc<Table>, DbError> {
+    let windows: Vec<&Window> = exprs.iter().flat_map(|expr| expr.windows()).collect();
+    if windows.is_empty() {
+        return Ok(Arc::clone(table));
+    }
+    let began = profile::begin();
+    let names: Vec<String> = windows.iter().map(|window| window.to_string()).collect();
+
+    let schema = table.columns.iter().map(|col| (col.clone(), table.fields[col].clone())).collect();
+    let mut widened = Table::new(&table.name, schema, None, 0, 0);
+    budget::charge(table.data.values().map(|values| budget::size_of(values)).sum())?;
+    widened.data = table.data.clone();
+    for window in windows {
+        let name = window.to_string();
+        if widened.fields.contains_key(&name) {
+            continue;
+        }
+        for expr in window.exprs() {
+            expr.check(table, functions)?;
+        }
+        let values = window.compute(table, rows, functions)?;
+        let typ = match window.function {
+            WindowFunction::RowNumber | WindowFunction::Rank => "int",
+            WindowFunction::Avg => "float",
+            WindowFunction::Sum if values.iter().all(|(_, value)| matches!(value, DataType::Integer32(_))) => "int",
+            WindowFunction::Sum => "float",
+        };
+        // Rows the query did not match are never read
+        let mut column = vec![DataType::Integer32(0); table.row_count()];
+        budget::charge(budget::size_of(&column))?;
+        for (row, value) in values {
+            column[row] = match value {
+                DataType::Integer32(i) if typ == "float" => DataType::Float32(i as f32),
+                value => value,
+            };
+        }
+        widened.fields.insert(name.clone(), typ.to_string());
+        widened.columns.push(name.clone());
+        widened.data.insert(name, column);
+    }
+    profile::record(began, || format!("Window {}", names.join(", ")), None, rows.len(), None);
+    Ok(Arc::new(widened))
+}
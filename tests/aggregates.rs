@@ -0,0 +1,76 @@
+mod common;
+
+use std::fs;
+
+use rust_db::{DataType, Database};
+
+use common::{create_table, insert, int, string, TempDir};
+
+// Staff 1 and 3 in department a, and 2 in b
+fn staff(db: &mut Database) {
+    create_table(db, "staff", &[("id", "int"), ("dept", "string"), ("pay", "int")]);
+    insert(db, "staff", vec![int(1), string("a"), int(10)]);
+    insert(db, "staff", vec![int(2), string("b"), int(7)]);
+    insert(db, "staff", vec![int(3), string("a"), int(4)]);
+}
+
+#[test]
+fn aggregates_give_one_row_per_group() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    staff(&mut db);
+
+    let rows = db.query("SELECT dept, COUNT(*), SUM(pay), MIN(pay), MAX(id) FROM staff GROUP BY dept ORDER BY COUNT(*) DESC").unwrap();
+    assert_eq!(rows.columns, ["dept", "COUNT(*)", "SUM(pay)", "MIN(pay)", "MAX(id)"]);
+    assert_eq!(rows.types, ["string", "int", "int", "int", "int"]);
+    assert_eq!(rows.rows, vec![
+        vec![string("a"), int(2), int(14), int(4), int(3)],
+        vec![string("b"), int(1), int(7), int(7), int(2)],
+    ]);
+
+    let rows = db.query("SELECT COUNT(*), AVG(pay) FROM staff WHERE pay > 5").unwrap();
+    assert_eq!(rows.rows, vec![vec![int(2), DataType::Float32(8.5)]]);
+}
+
+#[test]
+fn a_column_neither_grouped_nor_aggregated_is_refused() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    staff(&mut db);
+
+    assert!(db.query("SELECT id, COUNT(*) FROM staff").is_err());
+    assert!(db.query("SELECT * FROM staff GROUP BY dept").is_err());
+    assert!(db.query("SELECT COUNT(SUM(pay)) FROM staff").is_err());
+    // An expression grouped by may be selected as it is written
+    let rows = db.query("SELECT UPPER(dept), COUNT(id) FROM staff GROUP BY UPPER(dept)").unwrap();
+    assert_eq!(rows.rows, vec![vec![string("A"), int(2)], vec![string("B"), int(1)]]);
+}
+
+#[test]
+fn aggregates_of_no_rows_follow_from_values_never_being_null() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    staff(&mut db);
+
+    let rows = db.query("SELECT COUNT(*), SUM(pay) FROM staff WHERE id > 9").unwrap();
+    assert_eq!(rows.rows, vec![vec![int(0), int(0)]]);
+    assert!(db.query("SELECT MAX(pay) FROM staff WHERE id > 9").unwrap().rows.is_empty());
+}
+
+#[test]
+fn count_of_a_whole_table_is_read_from_its_file_header() {
+    let dir = TempDir::new();
+    {
+        let mut db = Database::open_dir(dir.path()).unwrap();
+        staff(&mut db);
+        db.checkpoint().unwrap();
+    }
+    // The header is outside the checksum, so a count it claims is believed
+    let path = dir.path().join("staff.json");
+    let saved = fs::read_to_string(&path).unwrap();
+    fs::write(&path, saved.replacen(" rows=3 ", " rows=7 ", 1)).unwrap();
+
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    assert_eq!(db.query("SELECT COUNT(*) FROM staff").unwrap().rows, vec![vec![int(7)]]);
+    assert_eq!(db.query("SELECT COUNT(*) FROM staff WHERE id > 0").unwrap().rows, vec![vec![int(3)]]);
+}
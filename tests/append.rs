@@ -0,0 +1,110 @@
+mod common;
+
+use std::fs;
+
+use rust_db::index::{IndexDef, IndexKind};
+use rust_db::wal::WalOp;
+use rust_db::{recovery, Database};
+
+use common::{create_table, insert, int, rows, string, TempDir};
+
+// Table t of `n` rows, written whole
+fn table_of(db: &mut Database, n: i32) {
+    create_table(db, "t", &[("id", "int"), ("name", "string")]);
+    let def = IndexDef { name: "by_id".to_string(), columns: vec!["id".to_string()], kind: IndexKind::default(), unique: false };
+    db.create_index("t", def).unwrap();
+    for id in 0..n {
+        insert(db, "t", vec![int(id), string("old")]);
+    }
+    db.checkpoint().unwrap();
+}
+
+#[test]
+fn rows_inserted_are_appended_beside_the_file_and_read_back() {
+    let dir = TempDir::new();
+    let file = {
+        let mut db = Database::open_dir(dir.path()).unwrap();
+        table_of(&mut db, 20);
+        let file = fs::read(dir.path().join("t.json")).unwrap();
+        insert(&mut db, "t", vec![int(20), string("new")]);
+        db.begin().unwrap();
+        insert(&mut db, "t", vec![int(21), string("new")]);
+        db.commit().unwrap();
+        db.checkpoint().unwrap();
+        file
+    };
+    assert_eq!(fs::read(dir.path().join("t.json")).unwrap(), file);
+    assert!(dir.path().join("t.0.append").exists());
+
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    recovery::recover(&mut db).unwrap();
+    assert_eq!(db.query("SELECT COUNT(*) FROM t").unwrap().rows, vec![vec![int(22)]]);
+    assert_eq!(rows(&mut db, "t").len(), 22);
+    assert_eq!(db.query("SELECT name FROM t WHERE id = 21").unwrap().rows, vec![vec![string("new")]]);
+}
+
+#[test]
+fn any_other_change_or_enough_rows_appended_writes_the_table_whole() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    table_of(&mut db, 20);
+    insert(&mut db, "t", vec![int(20), string("new")]);
+    db.checkpoint().unwrap();
+    assert!(dir.path().join("t.0.append").exists());
+
+    db.log(WalOp::Delete { table: "t".to_string(), index: 0 }).unwrap();
+    db.checkpoint().unwrap();
+    assert!(!dir.path().join("t.0.append").exists());
+
+    // More than a quarter as many rows again as the file holds are folded into it
+    for id in 21..27 {
+        insert(&mut db, "t", vec![int(id), string("new")]);
+    }
+    db.checkpoint().unwrap();
+    assert!(!dir.path().join("t.0.append").exists());
+    drop(db);
+
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    let ids: Vec<_> = rows(&mut db, "t").into_iter().map(|row| row[0].clone()).collect();
+    assert_eq!(ids, (1..27).map(int).collect::<Vec<_>>());
+}
+
+#[test]
+fn rows_appended_before_the_file_was_last_written_are_not_read_twice() {
+    let dir = TempDir::new();
+    {
+        let mut db = Database::open_dir(dir.path()).unwrap();
+        table_of(&mut db, 20);
+        insert(&mut db, "t", vec![int(20), string("new")]);
+        db.checkpoint().unwrap();
+        let appended = fs::read(dir.path().join("t.0.append")).unwrap();
+        db.log(WalOp::Delete { table: "t".to_string(), index: 0 }).unwrap();
+        db.checkpoint().unwrap();
+        // What a crash leaves between writing the file and removing the blob
+        fs::write(dir.path().join("t.0.append"), appended).unwrap();
+    }
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    assert_eq!(rows(&mut db, "t").len(), 20);
+    assert_eq!(db.query("SELECT COUNT(*) FROM t").unwrap().rows, vec![vec![int(20)]]);
+
+    // The next rows go after it, and the file written whole takes both away
+    insert(&mut db, "t", vec![int(21), string("new")]);
+    db.checkpoint().unwrap();
+    assert!(dir.path().join("t.1.append").exists());
+    db.vacuum(None).unwrap();
+    assert!(!dir.path().join("t.0.append").exists() && !dir.path().join("t.1.append").exists());
+    assert_eq!(rows(&mut db, "t").len(), 21);
+}
+
+#[test]
+fn appended_rows_are_sealed_and_go_with_their_table() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir_with_key(dir.path(), Some("secret")).unwrap();
+    table_of(&mut db, 20);
+    insert(&mut db, "t", vec![int(20), string("plaintext-marker")]);
+    db.checkpoint().unwrap();
+    assert!(!fs::read_to_string(dir.path().join("t.0.append")).unwrap_or_default().contains("plaintext-marker"));
+
+    db.drop_table("t").unwrap();
+    assert!(!dir.path().join("t.0.append").exists());
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use std::future::Future;
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake};
+use std::thread::{self, Thread};
+
+use rust_db::AsyncDatabase;
+
+use common::{create_table, insert, int, TempDir};
+
+// Wakes the thread waiting on a future
+struct Unpark(Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+// Waits on `future` with no runtime, parking the thread between polls
+fn block_on<T>(future: impl Future<Output = T>) -> T {
+    let waker = Arc::new(Unpark(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[test]
+fn calls_run_in_the_order_they_were_made_and_need_no_runtime() {
+    let dir = TempDir::new();
+    let db = block_on(AsyncDatabase::open_dir(dir.path())).unwrap();
+    let created = db.execute(|db| {
+        create_table(db, "t", &[("id", "int")]);
+        Ok(())
+    });
+    let inserted = db.execute(|db| {
+        insert(db, "t", vec![int(1)]);
+        insert(db, "t", vec![int(2)]);
+        Ok(())
+    });
+    let counted = db.query("SELECT COUNT(*) FROM t");
+    // Awaited last, the query still runs after the writes sent before it
+    assert_eq!(block_on(counted).unwrap().rows, vec![vec![int(2)]]);
+    block_on(created).unwrap();
+    block_on(inserted).unwrap();
+    assert!(block_on(db.query("SELECT * FROM missing")).is_err());
+}
+
+#[test]
+fn a_panicking_call_fails_alone() {
+    let dir = TempDir::new();
+    let db = block_on(AsyncDatabase::open_dir(dir.path())).unwrap();
+    let failed = db.execute(|_| -> Result<(), _> { panic!("boom") });
+    assert!(block_on(failed).unwrap_err().to_string().contains("a statement panicked"));
+
+    db.execute(|db| {
+        create_table(db, "t", &[("id", "int")]);
+        Ok(())
+    });
+    assert!(block_on(db.query("SELECT * FROM t")).unwrap().rows.is_empty());
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use std::sync::Arc;
+
+use rust_db::time::{Clock, ManualClock};
+use rust_db::{Database, DbError, Table};
+
+use common::{create_table, insert, int, rows, TempDir};
+
+#[test]
+fn a_backup_is_a_database_of_its_own_holding_every_committed_change() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(&dir.path().join("data")).unwrap();
+    create_table(&mut db, "users", &[("id", "int")]);
+    insert(&mut db, "users", vec![int(1)]);
+    insert(&mut db, "users", vec![int(2)]);
+
+    assert_eq!(db.backup(&dir.path().join("copy")).unwrap(), 1);
+    assert_eq!(db.backup(&dir.path().join("copy.rdb")).unwrap(), 1);
+    assert!(matches!(db.backup(&dir.path().join("copy")), Err(DbError::BackupFailed(_))));
+    for copy in ["copy", "copy.rdb"] {
+        let path = dir.path().join(copy);
+        let mut backup = if copy.ends_with(".rdb") { Database::open_file(&path) } else { Database::open_dir(&path) }.unwrap();
+        assert_eq!(rows(&mut backup, "users"), vec![vec![int(1)], vec![int(2)]]);
+    }
+}
+
+#[test]
+fn restoring_a_backup_replaces_every_table_but_the_temporary_ones() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(&dir.path().join("data")).unwrap();
+    create_table(&mut db, "users", &[("id", "int")]);
+    insert(&mut db, "users", vec![int(1)]);
+    db.backup(&dir.path().join("monday")).unwrap();
+
+    insert(&mut db, "users", vec![int(2)]);
+    create_table(&mut db, "orders", &[("id", "int")]);
+    let scratch = Table::new("scratch", vec![("id".to_string(), "int".to_string())], None, db.last_lsn(), db.now());
+    db.add_temp_table(scratch);
+    assert_eq!(db.restore_backup(&dir.path().join("monday"), None).unwrap(), 1);
+
+    assert_eq!(rows(&mut db, "users"), vec![vec![int(1)]]);
+    assert!(!db.table_exists("orders"));
+    assert!(db.table_exists("scratch"));
+    // And it lasts
+    drop(db);
+    let mut db = Database::open_dir(&dir.path().join("data")).unwrap();
+    assert_eq!(rows(&mut db, "users"), vec![vec![int(1)]]);
+    assert!(matches!(db.restore_backup(&dir.path().join("tuesday"), None), Err(DbError::BackupFailed(_))));
+}
+
+#[test]
+fn a_restore_replays_the_archived_log_up_to_a_time() {
+    let dir = TempDir::new();
+    let clock = Arc::new(ManualClock::new(1_700_000_000));
+    let mut db = Database::open_dir(&dir.path().join("data")).unwrap();
+    db.set_clock(Arc::clone(&clock) as Arc<dyn Clock>);
+    create_table(&mut db, "users", &[("id", "int")]);
+    insert(&mut db, "users", vec![int(1)]);
+    db.backup(&dir.path().join("monday")).unwrap();
+    assert!(matches!(db.restore_backup(&dir.path().join("monday"), Some(1_700_000_100)), Err(DbError::BackupFailed(_))));
+
+    let mut settings = db.settings().unwrap();
+    settings.wal_archive = Some(dir.path().join("archive").display().to_string());
+    db.save_settings(&settings).unwrap();
+    for id in 2..=4 {
+        clock.advance(100);
+        insert(&mut db, "users", vec![int(id)]);
+        db.checkpoint().unwrap();
+    }
+
+    // Rows 2 and 3 went in 100 and 200 seconds after the backup, and 4 after 300
+    db.restore_backup(&dir.path().join("monday"), Some(1_700_000_250)).unwrap();
+    assert_eq!(rows(&mut db, "users"), vec![vec![int(1)], vec![int(2)], vec![int(3)]]);
+    // The settings are the backup's too
+    assert!(db.settings().unwrap().wal_archive.is_none());
+}
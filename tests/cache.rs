@@ -0,0 +1,49 @@
+mod common;
+
+use std::fs;
+use std::io::Write;
+use std::process::Stdio;
+
+use common::{cli, TempDir};
+
+// Half a million combinations of the rows of t, far longer than a millisecond
+const SLOW: &str = "SELECT COUNT(*) FROM t a, t b WHERE a.id < b.id";
+
+// Table t of ids 0 to 999, with results kept
+fn thousand_rows(dir: &TempDir) {
+    let rows: String = (0..1000).map(|id| format!("{}\n", id)).collect();
+    let mut load = cli(dir.path()).args(["-c", "CREATE TABLE t id:int; COPY t FROM STDIN CSV NO HEADER"]).stdin(Stdio::piped()).stdout(Stdio::null()).spawn().unwrap();
+    load.stdin.take().unwrap().write_all(rows.as_bytes()).unwrap();
+    assert!(load.wait().unwrap().success());
+    fs::write(dir.path().join("rustdb.toml"), "[cache]\nresults = 10\n").unwrap();
+}
+
+// Runs `script`, carrying on past errors. Returns what it printed
+fn run(dir: &TempDir, script: &str) -> String {
+    let output = cli(dir.path()).args(["--continue-on-error", "-c", script]).output().unwrap();
+    String::from_utf8(output.stdout).unwrap() + &String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn a_query_coming_back_is_answered_from_the_cache_whatever_its_spacing() {
+    let dir = TempDir::new();
+    thousand_rows(&dir);
+    // A result served from the cache is not worked out again, so it beats a timeout the query itself could not
+    let output = run(&dir, &format!("{}; SET statement_timeout = 1; SELECT COUNT(*)   FROM t a, t b WHERE a.id<b.id; \
+        SELECT COUNT(*) FROM t a, t b WHERE a.id <= b.id", SLOW));
+    assert!(output.starts_with("COUNT(*)\n499500\nStatements running longer than 1 ms are cancelled\nCOUNT(*)\n499500\n"), "{}", output);
+    assert!(output.contains("[E5002]"), "{}", output);
+}
+
+#[test]
+fn a_kept_result_is_dropped_once_a_table_it_read_changes() {
+    let dir = TempDir::new();
+    thousand_rows(&dir);
+    let output = run(&dir, &format!("CREATE TABLE other id:int; {0}; SET statement_timeout = 1; INSERT INTO other VALUES (1); {0}; \
+        INSERT INTO t VALUES (1000); {0}", SLOW));
+    assert!(output.contains("1 row inserted\nCOUNT(*)\n499500\n1 row inserted\nError: Line 1: [E5002]"), "{}", output);
+
+    // Nor is a query calling a function whose result changes by itself kept
+    let output = run(&dir, &format!("{0} AND RANDOM() < 2; SET statement_timeout = 1; {0} AND RANDOM() < 2", SLOW));
+    assert!(output.contains("[E5002]"), "{}", output);
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use rust_db::catalog;
+use rust_db::index::{IndexDef, IndexKind};
+use rust_db::parser::{self, Statement};
+use rust_db::wal::WalOp;
+use rust_db::{Database, DbError, Table};
+
+use common::{create_table, insert, int, rows, string, TempDir};
+
+fn users(db: &mut Database) {
+    let schema = vec![("id".to_string(), "int".to_string()), ("name".to_string(), "string".to_string())];
+    let table = Table::new("users", schema, Some("id".to_string()), db.last_lsn(), db.now());
+    db.save_table(&table).unwrap();
+    insert(db, "users", vec![int(1), string("ann")]);
+    insert(db, "users", vec![int(2), string("bob")]);
+}
+
+#[test]
+fn the_catalog_tables_describe_tables_views_columns_and_indexes() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    users(&mut db);
+    create_table(&mut db, "log", &[("id", "int")]);
+    let def = IndexDef { name: "idx_name".to_string(), columns: vec!["name".to_string()], kind: IndexKind::Hash, unique: false };
+    db.create_index("users", def).unwrap();
+    let Statement::Select { filter, .. } = parser::parse("SELECT * FROM users WHERE id > 1").unwrap() else {
+        unreachable!("a SELECT parses as one");
+    };
+    db.create_view("later", "users", filter, false).unwrap();
+
+    let tables = db.query("SELECT name, kind, rows, columns, indexes FROM __tables ORDER BY name").unwrap();
+    assert_eq!(tables.rows, vec![
+        vec![string("later"), string("view"), int(1), int(2), int(0)],
+        vec![string("log"), string("table"), int(0), int(1), int(0)],
+        vec![string("users"), string("table"), int(2), int(2), int(2)],
+    ]);
+    let columns = db.query("SELECT table_name, name, type, position, primary_key FROM __columns WHERE table_name = 'users'").unwrap();
+    assert_eq!(columns.rows, vec![
+        vec![string("users"), string("id"), string("int"), int(1), string("yes")],
+        vec![string("users"), string("name"), string("string"), int(2), string("no")],
+    ]);
+    let indexes = db.query("SELECT name, columns, kind, unique FROM __indexes WHERE table_name = 'users' ORDER BY name").unwrap();
+    assert_eq!(indexes.rows, vec![
+        vec![string("idx_name"), string("name"), string("HASH"), string("no")],
+        vec![string("users_pkey"), string("id"), string("BTREE"), string("yes")],
+    ]);
+    assert_eq!(db.query("SELECT COUNT(*) FROM __columns WHERE type = 'string'").unwrap().rows, vec![vec![int(1)]]);
+}
+
+#[test]
+fn the_catalog_tables_are_read_only_and_their_names_taken() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    users(&mut db);
+    assert!(matches!(db.check_writable("__tables"), Err(DbError::SystemTable(_))));
+    let Statement::Select { filter, .. } = parser::parse("SELECT * FROM users WHERE id > 1").unwrap() else {
+        unreachable!("a SELECT parses as one");
+    };
+    assert!(matches!(db.create_view("__columns", "users", filter, false), Err(DbError::SystemTable(_))));
+}
+
+#[test]
+fn a_missing_table_is_an_error_naming_the_closest_table_or_view() {
+    let mut db = Database::open_in_memory();
+    users(&mut db);
+    let Statement::Select { filter, .. } = parser::parse("SELECT * FROM users").unwrap() else {
+        unreachable!("a SELECT parses as one");
+    };
+    db.create_view("everyone", "users", filter, false).unwrap();
+
+    let error = db.query("SELECT * FROM USER").unwrap_err();
+    assert!(matches!(&error, DbError::TableNotFound { name, suggestion: Some(close) } if name == "USER" && close == "users"));
+    assert_eq!(error.to_string(), "Table 'USER' does not exist; did you mean 'users'?");
+    let error = db.log(WalOp::Insert { table: "everyon".to_string(), row: vec![int(3)] }).unwrap_err();
+    assert_eq!(error.to_string(), "Table 'everyon' does not exist; did you mean 'everyone'?");
+    // Nothing is suggested when no name is close
+    assert_eq!(db.query("SELECT * FROM orders").unwrap_err().to_string(), "Table 'orders' does not exist");
+    assert_eq!(rows(&mut db, "users").len(), 2);
+
+    let names = ["users".to_string(), "orders".to_string()];
+    assert_eq!(catalog::closest("order", &names).as_deref(), Some("orders"));
+    assert_eq!(catalog::closest("usrs", &names).as_deref(), Some("users"));
+    assert_eq!(catalog::closest("items", &names), None);
+}
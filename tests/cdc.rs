@@ -0,0 +1,55 @@
+mod common;
+
+use rust_db::cdc::EventKind;
+use rust_db::wal::WalOp;
+use rust_db::Database;
+
+use common::{create_table, insert, int, string, TempDir};
+
+#[test]
+fn subscribers_get_each_committed_change_to_their_table() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    create_table(&mut db, "t", &[("id", "int"), ("name", "string")]);
+    create_table(&mut db, "other", &[("id", "int")]);
+    let changes = db.subscribe(Some("t")).unwrap();
+    let everything = db.subscribe(None).unwrap();
+
+    insert(&mut db, "t", vec![int(1), string("a")]);
+    insert(&mut db, "other", vec![int(9)]);
+    db.log(WalOp::Update { table: "t".to_string(), row: 0, values: vec![("name".to_string(), string("b"))] }).unwrap();
+    db.log(WalOp::Delete { table: "t".to_string(), index: 0 }).unwrap();
+
+    let events: Vec<_> = changes.try_iter().collect();
+    assert_eq!(events.iter().map(|event| event.kind).collect::<Vec<_>>(), [EventKind::Insert, EventKind::Update, EventKind::Delete]);
+    assert_eq!(events[1].old, Some(vec![("id".to_string(), int(1)), ("name".to_string(), string("a"))]));
+    assert_eq!(events[1].new, Some(vec![("id".to_string(), int(1)), ("name".to_string(), string("b"))]));
+    assert_eq!(events[1].to_json(), format!(
+        r#"{{"lsn":{},"table":"t","op":"update","old":{{"id":1,"name":"a"}},"new":{{"id":1,"name":"b"}}}}"#, events[1].lsn
+    ));
+    assert_eq!(everything.try_iter().map(|event| event.table).collect::<Vec<_>>(), ["t", "other", "t", "t"]);
+}
+
+#[test]
+fn a_transactions_changes_are_sent_on_commit_and_dropped_on_rollback() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    create_table(&mut db, "t", &[("id", "int")]);
+    let changes = db.subscribe(Some("t")).unwrap();
+
+    db.begin().unwrap();
+    insert(&mut db, "t", vec![int(1)]);
+    db.rollback().unwrap();
+    db.begin().unwrap();
+    insert(&mut db, "t", vec![int(2)]);
+    insert(&mut db, "t", vec![int(3)]);
+    assert!(changes.try_recv().is_err());
+    db.commit().unwrap();
+
+    let events: Vec<_> = changes.try_iter().collect();
+    assert_eq!(events.iter().map(|event| event.new.clone().unwrap()[0].1.clone()).collect::<Vec<_>>(), [int(2), int(3)]);
+    assert_eq!(events[0].lsn, events[1].lsn);
+    // Dropping the receiver ends the subscription
+    drop(changes);
+    insert(&mut db, "t", vec![int(4)]);
+}
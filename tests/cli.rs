@@ -0,0 +1,259 @@
+mod common;
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::Stdio;
+
+use common::{cli, TempDir};
+
+// Creates a table with the client run in `dir` with `args`, and says whether it worked
+fn create(dir: &Path, args: &[&str]) -> bool {
+    cli(dir).args(args).args(["-c", "CREATE TABLE t id:int"]).status().unwrap().success()
+}
+
+#[test]
+fn the_data_directory_comes_from_the_flag_the_environment_or_the_config_file() {
+    let dir = TempDir::new();
+    assert!(create(dir.path(), &["--data-dir", "flag"]));
+    assert!(dir.path().join("flag/t.json").exists());
+
+    let mut command = cli(dir.path());
+    command.env("RUSTDB_DATA_DIR", "env").args(["-c", "CREATE TABLE t id:int"]);
+    assert!(command.status().unwrap().success());
+    assert!(dir.path().join("env/t.json").exists());
+
+    fs::write(dir.path().join("rustdb.toml"), "data_dir = \"config\"\n").unwrap();
+    assert!(create(dir.path(), &[]));
+    assert!(dir.path().join("config/t.json").exists());
+    // The flag wins over the config file
+    assert!(create(dir.path(), &["--data-dir", "flag2"]));
+    assert!(dir.path().join("flag2/t.json").exists());
+}
+
+#[test]
+fn without_a_data_directory_the_default_is_used() {
+    let dir = TempDir::new();
+    assert!(create(dir.path(), &[]));
+    assert!(dir.path().join("data/t.json").exists());
+}
+
+#[test]
+fn an_in_memory_session_writes_nothing() {
+    let dir = TempDir::new();
+    assert!(create(dir.path(), &["--memory"]));
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    assert!(!create(dir.path(), &["--memory", "--data-dir", "data"]));
+}
+
+#[test]
+fn explain_shows_how_a_select_reads_its_table() {
+    let dir = TempDir::new();
+    let script = "CREATE TABLE u id:int age:int; INSERT INTO u VALUES (1, 20); CREATE INDEX idx_age ON u(age); \
+                  EXPLAIN SELECT * FROM u WHERE age = 20 AND id > 0; EXPLAIN SELECT * FROM u WHERE id = 1";
+    let output = cli(dir.path()).args(["--memory", "-c", script]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Index lookup on u using idx_age (age = 20)\n  Filter: id > 0\n"), "{}", stdout);
+    assert!(stdout.ends_with("Full scan on u (1 rows)\n  Filter: id = 1\n"), "{}", stdout);
+}
+
+#[test]
+fn explain_analyze_runs_the_select_and_reports_each_step() {
+    let dir = TempDir::new();
+    let script = "CREATE TABLE u id:int age:int; INSERT INTO u VALUES (1, 40); INSERT INTO u VALUES (2, 20); \
+                  INSERT INTO u VALUES (3, 50); CREATE INDEX idx_age ON u(age); \
+                  EXPLAIN ANALYZE SELECT * FROM u WHERE age > 30 ORDER BY id";
+    let output = cli(dir.path()).args(["--memory", "-c", script]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    // Times vary, so they are masked
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let report: Vec<String> = stdout.lines().skip(5)
+        .map(|line| line.split(' ').map(|word| if word.parse::<f64>().is_ok() && word.contains('.') { "_" } else { word }).collect::<Vec<_>>().join(" "))
+        .collect();
+    assert_eq!(report, [
+        "Index range scan on u using idx_age (age > 30)",
+        "  Actual: 2 row(s) read, 2 row(s) out, _ ms, index idx_age used",
+        "Sort by id",
+        "  Actual: 2 row(s) out, _ ms",
+        "Output id, age",
+        "  Actual: 2 row(s) out, _ ms",
+        "Total: 2 row(s) in _ ms",
+    ]);
+    // Without an index it reads every row, those filtered out included
+    let output = cli(dir.path()).args(["--memory", "-c", "CREATE TABLE v id:int; INSERT INTO v VALUES (1); EXPLAIN ANALYZE SELECT * FROM v WHERE id = 2"]).output().unwrap();
+    assert!(String::from_utf8(output.stdout).unwrap().contains("1 row(s) read, 0 row(s) out, "));
+}
+
+#[test]
+fn a_one_shot_run_exits_with_status_1_if_anything_failed() {
+    let dir = TempDir::new();
+    let run = |args: &[&str], sql: &str| cli(dir.path()).args(args).args(["--format", "csv", "-c", sql]).output().unwrap();
+
+    let output = run(&["--memory"], "CREATE TABLE t id:int; SELECT * FROM t");
+    assert_eq!(output.status.code(), Some(0));
+    let output = run(&["--memory"], "CREATE TABLE t id:int; SELECT * FROM missing; SELECT COUNT(*) FROM t");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!String::from_utf8(output.stdout).unwrap().contains("COUNT(*)"));
+    // The rest still runs, but the run has failed all the same
+    let output = run(&["--memory", "--continue-on-error"], "CREATE TABLE t id:int; SELECT * FROM missing; SELECT COUNT(*) FROM t");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stdout).unwrap().ends_with("COUNT(*)\n0\n"));
+
+    fs::write(dir.path().join("not-a-dir"), "").unwrap();
+    let output = run(&["--data-dir", "not-a-dir"], "SHOW TABLES");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with("Error: Could not open database"));
+}
+
+#[test]
+fn result_sets_are_printed_in_the_format_asked_for() {
+    let dir = TempDir::new();
+    let script = "CREATE TABLE u id:int name:string; INSERT INTO u VALUES (1, 'a,b'); INSERT INTO u VALUES (22, 'c'); SELECT * FROM u";
+    let print = |format: &str| {
+        let output = cli(dir.path()).args(["--memory", "--format", format, "-c", script]).output().unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        stdout.split_once("1 row inserted\n1 row inserted\n").unwrap().1.to_string()
+    };
+    assert_eq!(print("table"), "+----+------+\n| id | name |\n+----+------+\n|  1 | a,b  |\n| 22 | c    |\n+----+------+\n");
+    assert_eq!(print("csv"), "id,name\n1,\"a,b\"\n22,c\n");
+    assert_eq!(print("json"), "[\n  {\"id\": \"1\", \"name\": \"a,b\"},\n  {\"id\": \"22\", \"name\": \"c\"}\n]\n");
+    assert_eq!(print("vertical"), "-[ RECORD 1 ]-\nid   | 1\nname | a,b\n-[ RECORD 2 ]-\nid   | 22\nname | c\n");
+}
+
+#[test]
+fn the_format_is_switched_from_the_prompt() {
+    let dir = TempDir::new();
+    let mut child = cli(dir.path()).arg("--memory").stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+    child.stdin.take().unwrap().write_all(b"CREATE TABLE u id:int\nINSERT INTO u VALUES (1)\n\\format json\nSELECT * FROM u\n\\format\n").unwrap();
+    let stdout = String::from_utf8(child.wait_with_output().unwrap().stdout).unwrap();
+    assert!(stdout.ends_with("Output format is json\n[\n  {\"id\": \"1\"}\n]\nOutput format is json\n"), "{}", stdout);
+}
+
+#[test]
+fn result_sets_are_sent_to_a_file_until_output_is_switched_back() {
+    let dir = TempDir::new();
+    fs::write(dir.path().join("out.txt"), "left over").unwrap();
+    let mut child = cli(dir.path()).arg("--memory").stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+    child.stdin.take().unwrap().write_all(b"CREATE TABLE u id:int\nINSERT INTO u VALUES (1)\n\\format table\n\\o out.txt\n\
+        SELECT * FROM u\nINSERT INTO u VALUES (2)\nSELECT COUNT(*) FROM u\n\\o\nSELECT COUNT(*) FROM u\n").unwrap();
+    let stdout = String::from_utf8(child.wait_with_output().unwrap().stdout).unwrap();
+    // Other messages still show
+    assert!(stdout.ends_with("Results go to 'out.txt'\n1 row inserted\nResults go to the terminal\n+----------+\n| COUNT(*) |\n+----------+\n|        2 |\n+----------+\n"), "{}", stdout);
+    assert_eq!(fs::read_to_string(dir.path().join("out.txt")).unwrap(),
+        "+----+\n| id |\n+----+\n|  1 |\n+----+\n+----------+\n| COUNT(*) |\n+----------+\n|        2 |\n+----------+\n");
+}
+
+#[test]
+fn output_that_is_not_a_terminal_is_never_paged() {
+    let dir = TempDir::new();
+    let mut child = cli(dir.path()).arg("--memory").env("PAGER", "touch paged")
+        .stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().unwrap();
+    let mut input = String::from("\\pager\n\\pager on\n\\pager maybe\nCREATE TABLE u id:int\n");
+    input += &(0..200).map(|id| format!("INSERT INTO u VALUES ({})\n", id)).collect::<String>();
+    input += "SELECT * FROM u\n";
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("Pager is off\nPager is on\n"), "{}", stdout);
+    assert!(String::from_utf8(output.stderr).unwrap().contains("Unknown pager setting 'maybe'. Use on or off"));
+    assert!(stdout.ends_with("198\n199\n"));
+    assert!(!dir.path().join("paged").exists());
+}
+
+#[test]
+fn timing_shows_how_long_each_statement_took_to_parse_and_execute() {
+    let dir = TempDir::new();
+    let mut child = cli(dir.path()).arg("--memory").stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+    child.stdin.take().unwrap().write_all(b"CREATE TABLE u id:int\n\\timing\nSELECT * FROM u\n\\timing off\nSELECT * FROM u\n").unwrap();
+    let stdout = String::from_utf8(child.wait_with_output().unwrap().stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[..3], ["Table 'u' created", "Timing is on", "id"], "{}", stdout);
+    let (total, parts) = lines[3].strip_prefix("Time: ").unwrap().split_once(" ms (parse ").unwrap();
+    let (parse, execute) = parts.strip_suffix(" ms)").unwrap().split_once(" ms, execute ").unwrap();
+    let [total, parse, execute] = [total, parse, execute].map(|ms| ms.parse::<f64>().unwrap());
+    assert!((parse + execute - total).abs() < 0.01, "{}", lines[3]);
+    assert_eq!(lines[4..], ["Timing is off", "id"]);
+}
+
+#[test]
+fn a_table_is_as_wide_as_its_values_up_to_a_limit_with_numbers_aligned_right() {
+    let dir = TempDir::new();
+    let script = format!("CREATE TABLE u id:int name:string score:float; INSERT INTO u VALUES (1, '日本語', 1.5); \
+                          INSERT INTO u VALUES (1000, '{}', 22.25); SELECT * FROM u", "x".repeat(50));
+    let output = cli(dir.path()).args(["--memory", "--format", "table", "-c", &script]).output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let border = format!("+------+{}+-------+", "-".repeat(42));
+    let lines: Vec<&str> = stdout.lines().skip(3).collect();
+    assert_eq!(lines, [
+        border.as_str(),
+        &format!("|   id | name{} | score |", " ".repeat(36)),
+        &border,
+        // Each of these characters takes two columns
+        &format!("|    1 | 日本語{} |   1.5 |", " ".repeat(34)),
+        &format!("| 1000 | {}… | 22.25 |", "x".repeat(39)),
+        &border,
+    ]);
+}
+
+#[test]
+fn settings_come_from_the_config_file_unless_a_flag_gives_them() {
+    let dir = TempDir::new();
+    fs::write(dir.path().join("other.toml"), "format = \"json\"\n\n[query]\nmax_recursion = 3\n").unwrap();
+    let script = "CREATE TABLE u id:int; INSERT INTO u VALUES (1); SELECT * FROM u";
+    let select = |args: &[&str]| {
+        let output = cli(dir.path()).args(["--memory", "--config", "other.toml"]).args(args).args(["-c", script]).output().unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    };
+    assert!(select(&[]).ends_with("[\n  {\"id\": \"1\"}\n]\n"));
+    assert!(select(&["--format", "csv"]).ends_with("id\n1\n"));
+
+    let recursion = "CREATE TABLE u id:int; INSERT INTO u VALUES (1); \
+                     WITH RECURSIVE n(i) AS (SELECT id FROM u UNION ALL SELECT i + 1 FROM n WHERE i < 5) SELECT COUNT(*) FROM n";
+    let output = cli(dir.path()).args(["--memory", "--config", "other.toml", "-c", recursion]).output().unwrap();
+    assert!(String::from_utf8(output.stderr).unwrap().contains("[E5004] WITH RECURSIVE n was still adding rows after 3 rounds"));
+}
+
+#[test]
+fn a_config_file_that_cannot_be_read_or_holds_an_unknown_setting_is_refused() {
+    let dir = TempDir::new();
+    let refusal = || {
+        let output = cli(dir.path()).args(["--memory", "-c", "CREATE TABLE u id:int"]).output().unwrap();
+        assert_eq!(output.status.code(), Some(2));
+        String::from_utf8(output.stderr).unwrap()
+    };
+    fs::write(dir.path().join("rustdb.toml"), "colour = \"red\"\n").unwrap();
+    assert!(refusal().starts_with("Error: Invalid config file 'rustdb.toml': unknown field `colour`"));
+    fs::write(dir.path().join("rustdb.toml"), "[query]\nmax_recursion = \"lots\"\n").unwrap();
+    assert!(refusal().starts_with("Error: Invalid config file 'rustdb.toml': invalid type"));
+
+    let output = cli(dir.path()).args(["--config", "missing.toml", "-c", "CREATE TABLE u id:int"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(!dir.path().join("data").exists());
+}
+
+#[test]
+fn bench_runs_the_mix_of_statements_asked_for_and_reports_each_kind() {
+    let dir = TempDir::new();
+    let bench = |seed: &str| {
+        let output = cli(dir.path()).args(["bench", "--rows", "200", "--ops", "100", "--mix", "insert=1,lookup=1,scan=0", "--seed", seed, "--memory", "--format", "csv"])
+            .output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        // Kinds and counts, the timings aside
+        String::from_utf8(output.stdout).unwrap().lines().map(|line| line.split(',').take(2).collect::<Vec<_>>().join(",")).collect::<Vec<_>>()
+    };
+    let report = bench("3");
+    assert_eq!(report[..2], ["operation,count", "load (insert),200"]);
+    assert_eq!(report.len(), 4);
+    let counts: usize = report[2..].iter().map(|line| line.split_once(',').unwrap().1.parse::<usize>().unwrap()).sum();
+    assert_eq!(counts, 100);
+    assert_eq!(bench("3"), report);
+
+    let output = cli(dir.path()).args(["bench", "--mix", "foo=1", "--memory"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+
+    // On disk, it leaves nothing behind
+    assert!(cli(dir.path()).args(["bench", "--rows", "10", "--ops", "10"]).output().unwrap().status.success());
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+}
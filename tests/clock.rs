@@ -0,0 +1,85 @@
+mod common;
+
+use std::sync::Arc;
+
+use rust_db::random::SeededRng;
+use rust_db::time::{Clock, ManualClock};
+use rust_db::{DataType, Database};
+
+use common::{create_table, insert, int, string};
+
+const START: u64 = 1_700_000_000; // 2023-11-14 22:13:20 UTC
+
+fn database_at(clock: &Arc<ManualClock>, seed: u64) -> Database {
+    let mut db = Database::open_in_memory();
+    db.set_clock(Arc::clone(clock) as Arc<dyn Clock>);
+    db.set_rng(Arc::new(SeededRng::new(seed)));
+    db
+}
+
+fn values(db: &mut Database, sql: &str) -> Vec<DataType> {
+    db.query(sql).unwrap().rows.into_iter().flatten().collect()
+}
+
+#[test]
+fn now_reads_the_clock_set() {
+    let clock = Arc::new(ManualClock::new(START));
+    let mut db = database_at(&clock, 1);
+    create_table(&mut db, "t", &[("id", "int")]);
+    insert(&mut db, "t", vec![int(1)]);
+
+    assert_eq!(values(&mut db, "SELECT NOW() FROM t"), vec![string("2023-11-14 22:13:20")]);
+    clock.advance(86400);
+    assert_eq!(values(&mut db, "SELECT NOW() FROM t"), vec![string("2023-11-15 22:13:20")]);
+    assert_eq!(db.now(), START + 86400);
+}
+
+#[test]
+fn rows_expire_by_the_clock_set() {
+    let clock = Arc::new(ManualClock::new(START));
+    let mut db = database_at(&clock, 1);
+    create_table(&mut db, "t", &[("id", "int"), ("expires", "int")]);
+    db.set_ttl("t", Some("expires".to_string())).unwrap();
+    insert(&mut db, "t", vec![int(1), int(START as i32 + 60)]);
+    insert(&mut db, "t", vec![int(2), int(START as i32 + 3600)]);
+
+    assert_eq!(values(&mut db, "SELECT id FROM t"), vec![int(1), int(2)]);
+    clock.advance(60);
+    assert_eq!(values(&mut db, "SELECT id FROM t"), vec![int(2)]);
+    assert_eq!(db.purge_expired("t").unwrap(), 1);
+    clock.set(START);
+    assert_eq!(values(&mut db, "SELECT id FROM t"), vec![int(2)]);
+}
+
+#[test]
+fn tables_are_stamped_by_the_clock_set() {
+    let clock = Arc::new(ManualClock::new(START));
+    let mut db = database_at(&clock, 1);
+    create_table(&mut db, "t", &[("id", "int")]);
+    assert_eq!(db.definition("t").unwrap().modified, START);
+    clock.advance(10);
+    insert(&mut db, "t", vec![int(1)]);
+    assert_eq!(db.definition("t").unwrap().modified, START + 10);
+}
+
+#[test]
+fn random_and_uuid_follow_the_seed() {
+    let clock = Arc::new(ManualClock::new(START));
+    let mut db = database_at(&clock, 42);
+    create_table(&mut db, "t", &[("id", "int")]);
+    insert(&mut db, "t", vec![int(1)]);
+    insert(&mut db, "t", vec![int(2)]);
+
+    let sql = "SELECT RANDOM(), UUID() FROM t";
+    let drawn = values(&mut db, sql);
+    assert_eq!(drawn, vec![
+        DataType::Float32(0.033623338),
+        string("95bc77bf-ee2d-42a3-8e46-e7f7169ba4d2"),
+        DataType::Float32(0.18630672),
+        string("c77658a3-cbfe-48ba-950f-6161944cf2f7"),
+    ]);
+    // Drawn again, the numbers go on; from the same seed, they start over
+    assert_ne!(values(&mut db, sql), drawn);
+    db.set_rng(Arc::new(SeededRng::new(42)));
+    assert_eq!(values(&mut db, sql), drawn);
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use rust_db::collation::Collation;
+use rust_db::{recovery, DataType, Database, DbError, Table};
+
+use common::{insert, int, string, TempDir};
+
+// Users whose names differ in case, compared as `collation` says
+fn users(db: &mut Database, collation: Collation) {
+    let schema = vec![("id".to_string(), "int".to_string()), ("name".to_string(), "string".to_string())];
+    let mut table = Table::new("users", schema, None, db.last_lsn(), db.now());
+    table.collations.insert("name".to_string(), collation);
+    db.save_table(&table).unwrap();
+    for (id, name) in [(1, "bob"), (2, "Alice"), (3, "alice"), (4, "Bob"), (5, "carol")] {
+        insert(db, "users", vec![int(id), string(name)]);
+    }
+}
+
+fn ids(db: &mut Database, sql: &str) -> Vec<i32> {
+    db.query(sql).unwrap().rows.into_iter().map(|row| match row[0] {
+        DataType::Integer32(id) => id,
+        ref other => panic!("not an id: {:?}", other),
+    }).collect()
+}
+
+#[test]
+fn nocase_compares_sorts_and_groups_ignoring_case() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    users(&mut db, Collation::NoCase);
+
+    assert_eq!(ids(&mut db, "SELECT id FROM users WHERE name = 'ALICE' ORDER BY id"), [2, 3]);
+    assert_eq!(ids(&mut db, "SELECT id FROM users WHERE name IN ('BOB', 'Carol') ORDER BY id"), [1, 4, 5]);
+    assert_eq!(ids(&mut db, "SELECT id FROM users ORDER BY name, id"), [2, 3, 1, 4, 5]);
+    // Values are returned as stored, each group as its first row has it
+    let rows = db.query("SELECT name, COUNT(*) FROM users GROUP BY name ORDER BY name").unwrap().rows;
+    assert_eq!(rows, vec![
+        vec![string("Alice"), int(2)],
+        vec![string("bob"), int(2)],
+        vec![string("carol"), int(1)],
+    ]);
+}
+
+#[test]
+fn binary_tells_case_apart() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    users(&mut db, Collation::Binary);
+
+    assert_eq!(ids(&mut db, "SELECT id FROM users WHERE name = 'alice'"), [3]);
+    assert_eq!(ids(&mut db, "SELECT id FROM users ORDER BY name"), [2, 4, 3, 1, 5]);
+    assert_eq!(db.query("SELECT name FROM users GROUP BY name").unwrap().rows.len(), 5);
+}
+
+#[test]
+fn a_nocase_primary_key_refuses_values_differing_only_in_case() {
+    let dir = TempDir::new();
+    {
+        let mut db = Database::open_dir(dir.path()).unwrap();
+        let schema = vec![("name".to_string(), "string".to_string())];
+        let mut table = Table::new("users", schema, Some("name".to_string()), db.last_lsn(), db.now());
+        table.collations.insert("name".to_string(), Collation::NoCase);
+        db.save_table(&table).unwrap();
+        insert(&mut db, "users", vec![string("Alice")]);
+        db.checkpoint().unwrap();
+    }
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    recovery::recover(&mut db).unwrap();
+    let users = db.snapshot("users").unwrap();
+    assert_eq!(users.collation("name"), Collation::NoCase);
+    assert!(matches!(users.check_unique(&[string("ALICE")]), Err(DbError::DuplicateKey { .. })));
+    assert!(users.check_unique(&[string("Alicia")]).is_ok());
+    assert_eq!(db.query("SELECT name FROM users WHERE name = 'alice'").unwrap().rows, vec![vec![string("Alice")]]);
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use std::path::Path;
+
+use common::{cli, TempDir};
+
+// Runs `script` with the client in `dir`. Returns what it printed, and whether it all worked
+fn run(dir: &Path, script: &str) -> (String, bool) {
+    let output = cli(dir).args(["-c", script]).output().unwrap();
+    (String::from_utf8(output.stdout).unwrap() + &String::from_utf8(output.stderr).unwrap(), output.status.success())
+}
+
+#[test]
+fn a_generated_column_is_worked_out_on_every_insert_and_update() {
+    let dir = TempDir::new();
+    let (output, ok) = run(dir.path(), "CREATE TABLE items id:int price:float qty:int total:float GENERATED AS (price * qty); \
+        INSERT INTO items VALUES (1, 2.5, 4); INSERT INTO items VALUES (2, 1.5, 2); UPDATE items SET qty = 2 WHERE id = 1; \
+        SELECT * FROM items WHERE total > 4 ORDER BY id");
+    assert!(ok, "{}", output);
+    assert!(output.ends_with("id,price,qty,total\n1,2.5,2,5\n"), "{}", output);
+
+    let (output, ok) = run(dir.path(), "UPDATE items SET total = 1 WHERE id = 1");
+    assert!(!ok && output.contains("[E1006] Column 'total' is generated from the others and cannot be given a value"), "{}", output);
+    let (output, ok) = run(dir.path(), "INSERT INTO items VALUES (3, 1.0, 1, 9.0)");
+    assert!(!ok && output.contains("[E1005]"), "{}", output);
+    let (output, ok) = run(dir.path(), "CREATE TABLE bad a:int b:int GENERATED AS (a + 1) c:int GENERATED AS (b + 1)");
+    assert!(!ok && output.contains("generated column 'c' cannot read 'b', which is generated too"), "{}", output);
+}
+
+#[test]
+fn an_enum_takes_only_its_labels_and_sorts_in_their_order() {
+    let dir = TempDir::new();
+    let (output, ok) = run(dir.path(), "CREATE TABLE tickets id:int status:enum(open, pending, 'on hold', closed); \
+        INSERT INTO tickets VALUES (1, 'closed'); INSERT INTO tickets VALUES (2, 'open'); INSERT INTO tickets VALUES (3, 'on hold'); \
+        SELECT * FROM tickets ORDER BY status; SELECT id FROM tickets WHERE status < 'closed' ORDER BY id; \
+        SELECT UPPER(status) FROM tickets WHERE id = 3");
+    assert!(ok, "{}", output);
+    assert!(output.ends_with("id,status\n2,open\n3,on hold\n1,closed\nid\n2\n3\nUPPER(status)\nON HOLD\n"), "{}", output);
+
+    let (output, ok) = run(dir.path(), "INSERT INTO tickets VALUES (4, 'done')");
+    assert!(!ok && output.contains("[E1002] Value 'done' is not a valid enum(open,pending,on hold,closed)"), "{}", output);
+}
+
+#[test]
+fn an_array_holds_values_of_its_type_and_is_searched_for_one() {
+    let dir = TempDir::new();
+    let (output, ok) = run(dir.path(), "CREATE TABLE posts id:int tags:string[] scores:int[]; \
+        INSERT INTO posts 1 ['rust', 'db'] [1, 2]; INSERT INTO posts VALUES (2, ARRAY['c d', 'x,y'], '{3}'); \
+        INSERT INTO posts VALUES (3, '{}', '{}'); SELECT * FROM posts ORDER BY id; \
+        SELECT id FROM posts WHERE tags CONTAINS 'rust'; SELECT id FROM posts WHERE 3 = ANY(scores)");
+    assert!(ok, "{}", output);
+    assert!(output.ends_with("id,tags,scores\n1,\"{rust,db}\",\"{1,2}\"\n2,\"{\"\"c d\"\",\"\"x,y\"\"}\",{3}\n3,{},{}\nid\n1\nid\n2\n"), "{}", output);
+
+    let (output, ok) = run(dir.path(), "INSERT INTO posts VALUES (4, ['a'], ['b'])");
+    assert!(!ok && output.contains("[E1002] Value 'b' is not a valid int for column 'scores'"), "{}", output);
+}
+
+#[test]
+fn points_are_checked_on_the_way_in_and_measured_in_kilometres() {
+    let dir = TempDir::new();
+    let (output, ok) = run(dir.path(), "CREATE TABLE stores id:int loc:point; INSERT INTO stores VALUES (1, '52.52,13.405'); \
+        INSERT INTO stores VALUES (2, '(48.8566, 2.3522)'); INSERT INTO stores VALUES (3, [40.7128, -74.006]); \
+        SELECT id, loc FROM stores ORDER BY DISTANCE(loc, POINT(52.5, 13.4)) LIMIT 2; \
+        SELECT ROUND(DISTANCE(loc, '48.8566,2.3522')) FROM stores WHERE id = 1");
+    assert!(ok, "{}", output);
+    assert!(output.ends_with("id,loc\n1,\"{52.52,13.405}\"\n2,\"{48.8566,2.3522}\"\n\"ROUND(DISTANCE(loc, '48.8566,2.3522'))\"\n877\n"), "{}", output);
+
+    for value in ["'91,0'", "'0,181'", "'here'"] {
+        let (output, ok) = run(dir.path(), &format!("INSERT INTO stores VALUES (4, {})", value));
+        assert!(!ok && output.contains("[E1002]") && output.contains("is not a valid point for column 'loc'"), "{}", output);
+    }
+}
@@ -0,0 +1,32 @@
+mod common;
+
+use std::path::Path;
+
+use common::{cli, TempDir};
+
+// Runs `script` with the client in `dir`, carrying on past errors. Returns what it printed
+fn run(dir: &Path, script: &str) -> String {
+    let output = cli(dir).args(["--continue-on-error", "-c", script]).output().unwrap();
+    String::from_utf8(output.stdout).unwrap() + &String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn comments_document_a_table_and_its_columns_wherever_its_schema_shows() {
+    let dir = TempDir::new();
+    let output = run(dir.path(), "CREATE TABLE users id:int PRIMARY KEY name:string; COMMENT ON TABLE users IS 'People who can log in'; \
+        COMMENT ON COLUMN users.name IS 'Shown to others, it''s public'; COMMENT ON COLUMN users.nope IS 'x'");
+    assert!(output.contains("Comment on table 'users' set\nComment on column 'users.name' set\n"), "{}", output);
+    assert!(output.contains("[E1003] Column 'nope' does not exist in table 'users'"), "{}", output);
+
+    // Saved with the table
+    let output = run(dir.path(), "DESCRIBE users; SHOW CREATE TABLE users; SELECT name, comment FROM __tables; SELECT name, comment FROM __columns");
+    assert_eq!(output, "Column,Type,Primary Key,Default,Generated,Collation,Comment\n\
+        id,int,yes,,,binary,\nname,string,no,,,binary,\"Shown to others, it's public\"\n\
+        CREATE TABLE users id:int PRIMARY KEY name:string;\nCOMMENT ON TABLE users IS 'People who can log in';\n\
+        COMMENT ON COLUMN users.name IS 'Shown to others, it''s public';\n\
+        name,comment\nusers,People who can log in\n\
+        name,comment\nid,\nname,\"Shown to others, it's public\"\n");
+
+    let output = run(dir.path(), "COMMENT ON TABLE users IS NULL; COMMENT ON COLUMN users.name IS ''; SHOW CREATE TABLE users");
+    assert_eq!(output, "Comment on table 'users' removed\nComment on column 'users.name' removed\nCREATE TABLE users id:int PRIMARY KEY name:string;\n");
+}
@@ -0,0 +1,72 @@
+//! Helpers shared by the integration tests.
+
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rust_db::table::Table;
+use rust_db::wal::WalOp;
+use rust_db::{DataType, Database};
+
+/// A directory of its own under the system's temp directory, removed when
+/// dropped.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new() -> TempDir {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let name = format!("rust_db-test-{}-{}", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed));
+        let path = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// The command-line client, run from `dir` so that no config file,
+/// data directory or environment of the caller's is picked up.
+pub fn cli(dir: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_rust_db"));
+    command.current_dir(dir).env_remove("RUSTDB_DATA_DIR").env_remove("RUSTDB_ENCRYPTION_KEY");
+    command
+}
+
+/// Creates table `name` with `columns`, given as `(name, type)`.
+pub fn create_table(db: &mut Database, name: &str, columns: &[(&str, &str)]) {
+    let schema = columns.iter().map(|(column, typ)| (column.to_string(), typ.to_string())).collect();
+    let table = Table::new(name, schema, None, db.last_lsn(), db.now());
+    db.save_table(&table).unwrap();
+}
+
+/// Logs the insert of `row` into table `name`.
+pub fn insert(db: &mut Database, name: &str, row: Vec<DataType>) {
+    db.log(WalOp::Insert { table: name.to_string(), row }).unwrap();
+}
+
+/// Every row of `name`, in order.
+pub fn rows(db: &mut Database, name: &str) -> Vec<Vec<DataType>> {
+    db.query(&format!("SELECT * FROM {}", name)).unwrap().rows
+}
+
+pub fn int(i: i32) -> DataType {
+    DataType::Integer32(i)
+}
+
+pub fn string(s: &str) -> DataType {
+    DataType::String(s.to_string())
+}
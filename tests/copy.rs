@@ -0,0 +1,39 @@
+mod common;
+
+use std::fs;
+use std::io::Write;
+use std::process::{Output, Stdio};
+
+use common::{cli, TempDir};
+
+// Runs `args` with `input` on stdin
+fn with_input(dir: &TempDir, args: &[&str], input: &str) -> Output {
+    let mut child = cli(dir.path()).args(args).stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn copy_loads_stdin_in_one_batch_written_straight_to_the_table_file() {
+    let dir = TempDir::new();
+    let rows: String = (1..=20_000).map(|id| format!("{},name {}\n", id, id)).collect();
+    let output = with_input(&dir, &["-c", "CREATE TABLE u id:int PRIMARY KEY name:string; COPY u FROM STDIN CSV"], &format!("id,name\n{}", rows));
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8(output.stdout).unwrap().ends_with("Loaded 20000 row(s) into 'u'\n"));
+    // The rows are not logged one by one
+    assert!(fs::metadata(dir.path().join("data/wal.log")).unwrap().len() < 100);
+
+    // At the prompt the rows follow the statement
+    let output = with_input(&dir, &[], "COPY u FROM STDIN CSV NO HEADER\n20001,x\n20002,y\n\\.\nSELECT COUNT(*) FROM u WHERE id > 19999\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "Loaded 2 row(s) into 'u'\nCOUNT(*)\n3\n");
+}
+
+#[test]
+fn copy_loads_nothing_if_any_row_is_refused() {
+    let dir = TempDir::new();
+    let output = with_input(&dir, &["-c", "CREATE TABLE u id:int PRIMARY KEY; COPY u FROM STDIN CSV NO HEADER"], "1\n2\n1\n");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("[E6003] Import failed at line 3: Duplicate value '1'"));
+    let output = cli(dir.path()).args(["-c", "SELECT COUNT(*) FROM u"]).output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "COUNT(*)\n0\n");
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use common::{cli, TempDir};
+
+// Runs `script` with the client in `dir`. Returns what it printed, and whether it all worked
+fn run(dir: &Path, script: &str) -> (String, bool) {
+    let output = cli(dir).args(["-c", script]).output().unwrap();
+    (String::from_utf8(output.stdout).unwrap() + &String::from_utf8(output.stderr).unwrap(), output.status.success())
+}
+
+#[test]
+fn a_csv_file_is_imported_by_its_header_or_in_table_order() {
+    let dir = TempDir::new();
+    fs::write(dir.path().join("named.csv"), "name;id\n\"Smith; \"\"Ann\"\"\";1\nbob;2\n").unwrap();
+    fs::write(dir.path().join("plain.csv"), "3,carol\n").unwrap();
+    let (output, ok) = run(dir.path(), "CREATE TABLE users id:int name:string; \
+        IMPORT CSV 'named.csv' INTO users DELIMITER ';'; IMPORT CSV 'plain.csv' INTO users NO HEADER; \
+        SELECT * FROM users ORDER BY id");
+    assert!(ok, "{}", output);
+    assert!(output.contains("Imported 2 row(s) into 'users'\n"), "{}", output);
+    assert!(output.ends_with("id,name\n1,\"Smith; \"\"Ann\"\"\"\n2,bob\n3,carol\n"), "{}", output);
+}
+
+#[test]
+fn a_csv_file_with_an_invalid_row_imports_nothing() {
+    let dir = TempDir::new();
+    fs::write(dir.path().join("bad.csv"), "id,name\n1,ann\nfoo,bob\n").unwrap();
+    let (output, ok) = run(dir.path(), "CREATE TABLE users id:int name:string; IMPORT CSV 'bad.csv' INTO users");
+    assert!(!ok);
+    assert!(output.contains("[E6003] Import failed at line 3: Value 'foo' is not a valid int for column 'id'"), "{}", output);
+    assert!(run(dir.path(), "SELECT COUNT(*) FROM users").0.ends_with("COUNT(*)\n0\n"));
+
+    fs::write(dir.path().join("bad.csv"), "id,age\n1,20\n").unwrap();
+    assert!(!run(dir.path(), "IMPORT CSV 'bad.csv' INTO users").1);
+}
+
+#[test]
+fn a_query_or_a_table_is_exported_with_fields_quoted_as_needed() {
+    let dir = TempDir::new();
+    let (output, ok) = run(dir.path(), "CREATE TABLE users id:int name:string; \
+        INSERT INTO users VALUES (1, 'Smith, Ann'); INSERT INTO users VALUES (2, 'bob'); \
+        EXPORT (SELECT name, id FROM users WHERE id = 1) TO 'one.csv'; \
+        EXPORT TABLE users TO 'all.csv' DELIMITER ';' NO HEADER");
+    assert!(ok, "{}", output);
+    assert!(output.contains("Exported 1 row(s) to 'one.csv'\n"), "{}", output);
+    assert_eq!(fs::read_to_string(dir.path().join("one.csv")).unwrap(), "name,id\r\n\"Smith, Ann\",1\r\n");
+    assert_eq!(fs::read_to_string(dir.path().join("all.csv")).unwrap(), "1;Smith, Ann\r\n2;bob\r\n");
+
+    // What is exported imports back as it was
+    let (output, ok) = run(dir.path(), "DELETE FROM users WHERE id = 1; IMPORT CSV 'one.csv' INTO users; SELECT * FROM users ORDER BY id");
+    assert!(ok, "{}", output);
+    assert!(output.ends_with("id,name\n1,\"Smith, Ann\"\n2,bob\n"), "{}", output);
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use rust_db::Database;
+
+use common::{create_table, insert, int, string, TempDir};
+
+// The boss (1), ann (2) and bob (3) under them, and eve (4) under ann
+fn staff(db: &mut Database) {
+    create_table(db, "staff", &[("id", "int"), ("name", "string"), ("boss", "int")]);
+    insert(db, "staff", vec![int(1), string("boss"), int(0)]);
+    insert(db, "staff", vec![int(2), string("ann"), int(1)]);
+    insert(db, "staff", vec![int(3), string("bob"), int(1)]);
+    insert(db, "staff", vec![int(4), string("eve"), int(2)]);
+}
+
+#[test]
+fn a_with_query_is_read_as_a_table_by_those_after_it() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    staff(&mut db);
+
+    let rows = db.query("WITH bosses (who) AS (SELECT boss FROM staff WHERE boss > 0), \
+                         named AS (SELECT staff.name FROM staff JOIN bosses ON staff.id = bosses.who) \
+                         SELECT name FROM named ORDER BY name").unwrap();
+    assert_eq!(rows.columns, ["name"]);
+    assert_eq!(rows.rows, vec![vec![string("ann")], vec![string("boss")], vec![string("boss")]]);
+    // A name hides the table of the same name
+    let rows = db.query("WITH staff AS (SELECT id FROM staff WHERE id > 3) SELECT * FROM staff").unwrap();
+    assert_eq!(rows.rows, vec![vec![int(4)]]);
+    assert!(db.query("WITH a AS (SELECT id FROM staff) SELECT name FROM a").is_err());
+}
+
+#[test]
+fn a_recursive_with_query_walks_a_hierarchy_until_it_adds_no_rows() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    staff(&mut db);
+
+    let rows = db.query("WITH RECURSIVE org(id, name, depth) AS (SELECT id, name, 0 FROM staff WHERE boss = 0 \
+                         UNION ALL SELECT staff.id, staff.name, org.depth + 1 FROM staff JOIN org ON staff.boss = org.id) \
+                         SELECT name, depth FROM org ORDER BY depth, name").unwrap();
+    assert_eq!(rows.rows, vec![
+        vec![string("boss"), int(0)],
+        vec![string("ann"), int(1)],
+        vec![string("bob"), int(1)],
+        vec![string("eve"), int(2)],
+    ]);
+
+    // Round a cycle, UNION stops once no new rows are found, and UNION ALL at the limit
+    insert(&mut db, "staff", vec![int(1), string("boss"), int(4)]);
+    let walk = |all: &str| format!("WITH RECURSIVE up(id) AS (SELECT id FROM staff WHERE id = 4 \
+                                    UNION {} SELECT staff.boss FROM staff JOIN up ON staff.id = up.id) SELECT COUNT(*) FROM up", all);
+    assert_eq!(db.query(&walk("")).unwrap().rows, vec![vec![int(4)]]);
+    assert!(db.query(&walk("ALL")).unwrap_err().to_string().contains("still adding rows after 100 rounds"));
+}
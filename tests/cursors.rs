@@ -0,0 +1,16 @@
+mod common;
+
+use common::{cli, TempDir};
+
+#[test]
+fn a_cursor_pages_through_rows_as_they_were_when_it_was_declared() {
+    let dir = TempDir::new();
+    let script = "CREATE TABLE e id:int; INSERT INTO e VALUES (3); INSERT INTO e VALUES (1); INSERT INTO e VALUES (2); INSERT INTO e VALUES (4); \
+                  DECLARE page CURSOR FOR SELECT * FROM e ORDER BY id; INSERT INTO e VALUES (0); \
+                  FETCH 2 FROM page; FETCH NEXT FROM page; FETCH ALL FROM page; FETCH 5 FROM page; CLOSE page; FETCH page";
+    let output = cli(dir.path()).args(["--memory", "-c", script]).output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let fetched = stdout.split_once("Cursor 'page' declared (4 row(s))\n1 row inserted\n").unwrap().1;
+    assert_eq!(fetched, "id\n1\n2\nid\n3\nid\n4\nid\nCursor 'page' closed\n");
+    assert!(String::from_utf8(output.stderr).unwrap().contains("[E2013] Cursor 'page' does not exist"));
+}
@@ -0,0 +1,36 @@
+mod common;
+
+use rust_db::databases::{DataRoot, DEFAULT_DATABASE};
+use rust_db::DbError;
+
+use common::{cli, TempDir};
+
+#[test]
+fn named_databases_live_in_directories_beside_the_default_one() {
+    let dir = TempDir::new();
+    let root = DataRoot::new(dir.path());
+    assert_eq!(root.path_of(DEFAULT_DATABASE).unwrap(), dir.path());
+    root.create("shop").unwrap();
+    root.create("archive").unwrap();
+    assert!(dir.path().join("shop").is_dir());
+    assert_eq!(root.list().unwrap(), ["default", "archive", "shop"]);
+
+    assert!(matches!(root.create("shop"), Err(DbError::DatabaseExists(_))));
+    assert!(matches!(root.create("../elsewhere"), Err(DbError::InvalidName(_))));
+    assert!(matches!(root.drop(DEFAULT_DATABASE), Err(DbError::InvalidName(_))));
+    root.drop("archive").unwrap();
+    assert!(matches!(root.drop("archive"), Err(DbError::DatabaseNotFound(_))));
+    assert_eq!(root.list().unwrap(), ["default", "shop"]);
+}
+
+#[test]
+fn use_switches_between_databases_with_tables_of_their_own() {
+    let dir = TempDir::new();
+    let script = "CREATE TABLE t id:int; CREATE DATABASE shop; USE shop; CREATE TABLE t id:int; INSERT INTO t VALUES (5); \
+                  USE default; SELECT COUNT(*) FROM t; USE shop; SELECT id FROM t";
+    let output = cli(dir.path()).args(["--data-dir", "data", "--format", "csv", "-c", script]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("COUNT(*)\n0\n") && stdout.ends_with("id\n5\n"), "{}", stdout);
+    assert!(dir.path().join("data/t.json").exists() && dir.path().join("data/shop/t.json").exists());
+}
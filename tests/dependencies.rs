@@ -0,0 +1,39 @@
+mod common;
+
+use std::path::Path;
+
+use common::{cli, TempDir};
+
+// Runs `script` with the client in `dir`, carrying on past errors. Returns what it printed
+fn run(dir: &Path, script: &str) -> String {
+    let output = cli(dir).args(["--continue-on-error", "-c", script]).output().unwrap();
+    String::from_utf8(output.stdout).unwrap() + &String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn a_table_read_by_views_is_only_dropped_with_them() {
+    let dir = TempDir::new();
+    let output = run(dir.path(), "CREATE TABLE users id:int age:int; CREATE INDEX by_age ON users(age); \
+        CREATE VIEW adults AS SELECT * FROM users WHERE age >= 18; CREATE VIEW old AS SELECT * FROM adults WHERE age > 60; \
+        SELECT * FROM __dependencies ORDER BY name; DROP TABLE users; DROP VIEW adults");
+    assert!(output.contains("name,kind,table_name,depends_on\nadults,view,,users\nby_age,index,users,users\nold,view,,adults\n"), "{}", output);
+    assert!(output.contains("[E3014] Cannot drop 'users' as view 'old', view 'adults' depend(s) on it; add CASCADE to drop them too"), "{}", output);
+    assert!(output.contains("[E3014] Cannot drop 'adults' as view 'old' depend(s) on it"), "{}", output);
+
+    // The views reading views go first
+    let output = run(dir.path(), "DROP TABLE users CASCADE; SELECT COUNT(*) FROM __dependencies");
+    assert_eq!(output, "View 'old' dropped\nView 'adults' dropped\nTable 'users' dropped\nCOUNT(*)\n0\n");
+}
+
+#[test]
+fn a_table_written_by_another_tables_trigger_is_only_dropped_with_it() {
+    let dir = TempDir::new();
+    let output = run(dir.path(), "CREATE TABLE users id:int; CREATE TABLE orders id:int; \
+        CREATE TRIGGER welcome AFTER INSERT ON orders DO 'INSERT INTO users VALUES (NEW.id)'; \
+        SELECT * FROM __dependencies; DROP TABLE users");
+    assert!(output.contains("welcome,trigger,orders,orders\nwelcome,trigger,orders,users\n"), "{}", output);
+    assert!(output.contains("[E3014] Cannot drop 'users' as trigger 'welcome' on 'orders' depend(s) on it"), "{}", output);
+
+    let output = run(dir.path(), "DROP TABLE users CASCADE; INSERT INTO orders VALUES (1)");
+    assert_eq!(output, "Trigger 'welcome' dropped from 'orders'\nTable 'users' dropped\n1 row inserted\n");
+}
@@ -0,0 +1,33 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use common::{cli, TempDir};
+
+// Runs `script` with the client in `dir` on data directory `data`. Returns what it printed
+fn run(dir: &Path, data: &str, script: &str) -> String {
+    let output = cli(dir).args(["--data-dir", data, "-c", script]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn a_dump_run_against_an_empty_database_restores_it() {
+    let dir = TempDir::new();
+    run(dir.path(), "old", "CREATE TABLE users id:int PRIMARY KEY name:string tags:string[]; \
+        INSERT INTO users VALUES (1, 'O''Brien', ['a', 'b c']); CREATE INDEX idx_name ON users(name); \
+        CREATE TABLE log id:int; CREATE TRIGGER added AFTER INSERT ON users DO 'INSERT INTO log VALUES (NEW.id)'; \
+        CREATE VIEW named AS SELECT * FROM users WHERE id > 0; DUMP DATABASE TO 'backup.sql'");
+    let dump = fs::read_to_string(dir.path().join("backup.sql")).unwrap();
+    assert!(dump.starts_with("-- RustDB dump\n"));
+    // The trigger is created after the rows, so restoring them fires nothing
+    assert!(dump.find("INSERT INTO users").unwrap() < dump.find("CREATE TRIGGER").unwrap());
+
+    let output = cli(dir.path()).args(["--data-dir", "new", "--file", "backup.sql"]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = run(dir.path(), "new", "INSERT INTO users VALUES (2, 'x', ['y']); SELECT * FROM named ORDER BY id; \
+        SELECT * FROM log; SHOW INDEXES FROM users");
+    assert!(stdout.ends_with("id,name,tags\n1,O'Brien,\"{a,\"\"b c\"\"}\"\n2,x,{y}\nid\n2\n\
+        Index,Columns,Kind,Unique\nusers_pkey,id,BTREE,yes\nidx_name,name,BTREE,no\n"), "{}", stdout);
+}
@@ -0,0 +1,112 @@
+mod common;
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+
+use rust_db::{recovery, Database};
+
+use common::{create_table, insert, int, rows, string, TempDir};
+
+#[test]
+fn an_encrypted_database_recovers_and_keeps_its_log_sealed() {
+    let dir = TempDir::new();
+    {
+        let mut db = Database::open_dir(dir.path()).unwrap();
+        create_table(&mut db, "t", &[("id", "int"), ("name", "string")]);
+        db.rekey(Some("secret")).unwrap();
+        insert(&mut db, "t", vec![int(1), string("plaintext-marker")]);
+    }
+    let log = fs::read_to_string(dir.path().join("wal.log")).unwrap();
+    assert!(!log.contains("plaintext-marker"));
+    assert!(!fs::read_to_string(dir.path().join("t.json")).unwrap_or_default().contains("\"name\""));
+
+    // Without the passphrase the database does not open
+    assert!(Database::open_dir(dir.path()).is_err());
+
+    let mut db = Database::open_dir_with_key(dir.path(), Some("secret")).unwrap();
+    recovery::recover(&mut db).unwrap();
+    assert_eq!(rows(&mut db, "t"), vec![vec![int(1), string("plaintext-marker")]]);
+}
+
+#[test]
+fn databases_with_different_keys_are_open_side_by_side() {
+    let (a, b) = (TempDir::new(), TempDir::new());
+    let mut first = Database::open_dir_with_key(a.path(), Some("first")).unwrap();
+    let mut second = Database::open_dir_with_key(b.path(), Some("second")).unwrap();
+    create_table(&mut first, "t", &[("id", "int")]);
+    create_table(&mut second, "t", &[("id", "int")]);
+    insert(&mut first, "t", vec![int(1)]);
+    insert(&mut second, "t", vec![int(2)]);
+    drop((first, second));
+
+    assert!(Database::open_dir_with_key(a.path(), Some("second")).is_err());
+    let mut first = Database::open_dir_with_key(a.path(), Some("first")).unwrap();
+    let mut second = Database::open_dir_with_key(b.path(), Some("second")).unwrap();
+    assert_eq!(rows(&mut first, "t"), vec![vec![int(1)]]);
+    assert_eq!(rows(&mut second, "t"), vec![vec![int(2)]]);
+}
+
+#[test]
+fn a_plain_record_slipped_into_a_sealed_log_is_not_read() {
+    let dir = TempDir::new();
+    {
+        let mut db = Database::open_dir_with_key(dir.path(), Some("secret")).unwrap();
+        create_table(&mut db, "t", &[("id", "int")]);
+        db.checkpoint().unwrap();
+    }
+    let forged = "{\"lsn\":99,\"op\":{\"Insert\":{\"table\":\"t\",\"row\":[{\"Integer32\":666}]}},\"at\":0}\n";
+    OpenOptions::new().append(true).open(dir.path().join("wal.log")).unwrap().write_all(forged.as_bytes()).unwrap();
+
+    let mut db = Database::open_dir_with_key(dir.path(), Some("secret")).unwrap();
+    recovery::recover(&mut db).unwrap();
+    assert!(rows(&mut db, "t").is_empty());
+}
+
+#[test]
+fn a_rekey_cut_short_before_its_header_is_swapped_in_is_undone() {
+    let dir = TempDir::new();
+    {
+        let mut db = Database::open_dir_with_key(dir.path(), Some("old")).unwrap();
+        create_table(&mut db, "t", &[("id", "int")]);
+        insert(&mut db, "t", vec![int(1)]);
+        db.checkpoint().unwrap();
+    }
+    // What a crash leaves while the new key's blobs are being staged
+    fs::write(dir.path().join("encryption.header.rekey"), "{}").unwrap();
+    fs::write(dir.path().join("t.json.rekey"), "sealed under the new key").unwrap();
+
+    let mut db = Database::open_dir_with_key(dir.path(), Some("old")).unwrap();
+    assert_eq!(rows(&mut db, "t"), vec![vec![int(1)]]);
+    assert!(!dir.path().join("t.json.rekey").exists());
+    assert!(!dir.path().join("encryption.header.rekey").exists());
+}
+
+#[test]
+fn a_rekey_cut_short_after_its_header_is_swapped_in_is_finished() {
+    let dir = TempDir::new();
+    let (old_table, old_log) = {
+        let mut db = Database::open_dir_with_key(dir.path(), Some("old")).unwrap();
+        create_table(&mut db, "t", &[("id", "int")]);
+        insert(&mut db, "t", vec![int(1)]);
+        db.checkpoint().unwrap();
+        let saved = (fs::read(dir.path().join("t.json")).unwrap(), fs::read(dir.path().join("wal.log")).unwrap());
+        db.rekey(Some("new")).unwrap();
+        saved
+    };
+    // What a crash leaves once the header is swapped in, before the staged
+    // blobs replace the old ones and the log is written under the new key
+    fs::rename(dir.path().join("t.json"), dir.path().join("t.json.rekey")).unwrap();
+    fs::write(dir.path().join("t.json"), old_table).unwrap();
+    fs::write(dir.path().join("wal.log"), old_log).unwrap();
+
+    {
+        let mut db = Database::open_dir_with_key(dir.path(), Some("new")).unwrap();
+        assert_eq!(rows(&mut db, "t"), vec![vec![int(1)]]);
+        insert(&mut db, "t", vec![int(2)]);
+    }
+    // The log went on from the LSN it had, so the row logged since is replayed
+    let mut db = Database::open_dir_with_key(dir.path(), Some("new")).unwrap();
+    recovery::recover(&mut db).unwrap();
+    assert_eq!(rows(&mut db, "t"), vec![vec![int(1)], vec![int(2)]]);
+    assert!(!dir.path().join("t.json.rekey").exists());
+}
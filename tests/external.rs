@@ -0,0 +1,54 @@
+mod common;
+
+use std::fs;
+
+use rust_db::csv::CsvOptions;
+use rust_db::external::External;
+use rust_db::{recovery, Database, DbError, Table};
+
+use common::{int, string, TempDir};
+
+// External table `logs` over `logs.csv` in `dir`, read with a header
+fn logs(db: &mut Database, dir: &TempDir) -> Result<usize, DbError> {
+    let schema = vec![("id".to_string(), "int".to_string()), ("level".to_string(), "string".to_string())];
+    let table = Table::new("logs", schema, None, db.last_lsn(), db.now());
+    let location = dir.path().join("logs.csv").to_string_lossy().to_string();
+    db.create_external_table(table, External::new(&location, &CsvOptions { header: true, delimiter: ',' }))
+}
+
+#[test]
+fn an_external_table_reads_its_file_again_once_it_changes() {
+    let dir = TempDir::new();
+    fs::write(dir.path().join("logs.csv"), "level,id\ninfo,1\nwarn,2\n").unwrap();
+    let mut db = Database::open_dir(&dir.path().join("data")).unwrap();
+    assert_eq!(logs(&mut db, &dir).unwrap(), 2);
+    assert_eq!(db.query("SELECT id FROM logs WHERE level = 'warn'").unwrap().rows, vec![vec![int(2)]]);
+
+    fs::write(dir.path().join("logs.csv"), "level,id\ninfo,1\nwarn,2\nwarn,3\n").unwrap();
+    assert_eq!(db.query("SELECT COUNT(*) FROM logs WHERE level = 'warn'").unwrap().rows, vec![vec![int(2)]]);
+    // Only the definition is saved, so it is read from the file when opened again
+    drop(db);
+    let mut db = Database::open_dir(&dir.path().join("data")).unwrap();
+    recovery::recover(&mut db).unwrap();
+    assert!(fs::read_to_string(dir.path().join("data/logs.json")).is_ok_and(|saved| !saved.contains("warn")));
+    assert_eq!(db.query("SELECT level FROM logs WHERE id = 3").unwrap().rows, vec![vec![string("warn")]]);
+    assert!(db.external("logs").unwrap().is_some());
+}
+
+#[test]
+fn an_external_table_is_read_only_and_its_file_must_fit_its_columns() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(&dir.path().join("data")).unwrap();
+    assert!(matches!(logs(&mut db, &dir), Err(DbError::ExternalFile { .. })));
+    fs::write(dir.path().join("logs.csv"), "level,id\ninfo,one\n").unwrap();
+    assert!(matches!(logs(&mut db, &dir), Err(DbError::ExternalFile { reason, .. }) if reason.starts_with("line 2")));
+
+    fs::write(dir.path().join("logs.csv"), "level,id\ninfo,1\n").unwrap();
+    logs(&mut db, &dir).unwrap();
+    assert!(matches!(db.check_writable("logs"), Err(DbError::ExternalTable(_))));
+    fs::write(dir.path().join("logs.csv"), "level,id\ninfo,1\ninfo,two\n").unwrap();
+    assert!(matches!(db.query("SELECT * FROM logs"), Err(DbError::ExternalFile { .. })));
+
+    db.drop_table("logs").unwrap();
+    assert!(dir.path().join("logs.csv").exists());
+}
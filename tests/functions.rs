@@ -0,0 +1,161 @@
+mod common;
+
+use std::sync::Arc;
+
+use rust_db::time::{Clock, ManualClock};
+use rust_db::{DataType, Database, DbError};
+
+use common::{create_table, insert, int, string, TempDir};
+
+fn slugify(args: &[DataType]) -> Result<DataType, String> {
+    match args {
+        [DataType::String(s)] => Ok(DataType::String(s.to_lowercase().replace(' ', "-"))),
+        _ => Err("expected one string".to_string()),
+    }
+}
+
+fn posts(db: &mut Database) {
+    create_table(db, "posts", &[("title", "string")]);
+    insert(db, "posts", vec![string("Hello World")]);
+    insert(db, "posts", vec![string("Goodbye")]);
+}
+
+#[test]
+fn a_registered_function_is_called_from_select_lists_and_conditions() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    posts(&mut db);
+    db.register_function("Slugify", slugify);
+
+    let rows = db.query("SELECT SLUGIFY(title) FROM posts WHERE slugify(title) = 'hello-world'").unwrap();
+    assert_eq!(rows.rows, vec![vec![string("hello-world")]]);
+
+    let e = db.query("SELECT slugify(title, title) FROM posts").unwrap_err();
+    assert!(matches!(&e, DbError::FunctionFailed { function, reason } if function == "slugify" && reason == "expected one string"), "{}", e);
+}
+
+#[test]
+fn a_registered_function_replaces_a_built_in_one_until_the_database_is_reopened() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    posts(&mut db);
+    db.register_function("upper", slugify);
+    assert_eq!(db.query("SELECT UPPER(title) FROM posts WHERE title = 'Goodbye'").unwrap().rows, vec![vec![string("goodbye")]]);
+    drop(db);
+
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    assert_eq!(db.query("SELECT UPPER(title) FROM posts WHERE title = 'Goodbye'").unwrap().rows, vec![vec![string("GOODBYE")]]);
+    assert!(matches!(db.query("SELECT slugify(title) FROM posts"), Err(DbError::FunctionNotFound(_))));
+}
+
+// What `expr` gives, selected from a one-row table
+fn eval(db: &mut Database, expr: &str) -> Result<DataType, DbError> {
+    if !db.table_exists("one") {
+        create_table(db, "one", &[("s", "string"), ("n", "int")]);
+        insert(db, "one", vec![string("  Héllo World "), int(-7)]);
+    }
+    Ok(db.query(&format!("SELECT {} FROM one", expr))?.rows.remove(0).remove(0))
+}
+
+#[test]
+fn string_functions_work_by_characters_and_take_numbers_as_they_print() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    assert_eq!(eval(&mut db, "UPPER(TRIM(s))").unwrap(), string("HÉLLO WORLD"));
+    assert_eq!(eval(&mut db, "lower(s)").unwrap(), string("  héllo world "));
+    assert_eq!(eval(&mut db, "LENGTH(s)").unwrap(), int(14));
+    assert_eq!(eval(&mut db, "SUBSTR(TRIM(s), 2, 4)").unwrap(), string("éllo"));
+    assert_eq!(eval(&mut db, "SUBSTR(TRIM(s), 7)").unwrap(), string("World"));
+    assert_eq!(eval(&mut db, "REPLACE(s, 'l', 'L')").unwrap(), string("  HéLLo WorLd "));
+    assert_eq!(eval(&mut db, "CONCAT('n', '=', n)").unwrap(), string("n=-7"));
+    assert!(eval(&mut db, "UPPER(s, s)").is_err());
+
+    let rows = db.query("SELECT n FROM one WHERE LENGTH(TRIM(s)) = 11").unwrap();
+    assert_eq!(rows.rows, vec![vec![int(-7)]]);
+}
+
+#[test]
+fn numeric_functions_keep_ints_whole_and_refuse_what_has_no_result() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    assert_eq!(eval(&mut db, "ABS(n)").unwrap(), int(7));
+    assert_eq!(eval(&mut db, "MOD(n, 3)").unwrap(), int(-1));
+    assert_eq!(eval(&mut db, "POWER(2, 10)").unwrap(), int(1024));
+    assert_eq!(eval(&mut db, "POWER(2, -1)").unwrap(), DataType::Float32(0.5));
+    assert_eq!(eval(&mut db, "ROUND(2.5)").unwrap(), DataType::Float32(3.0));
+    assert_eq!(eval(&mut db, "ROUND(-2.5)").unwrap(), DataType::Float32(-3.0));
+    assert_eq!(eval(&mut db, "ROUND(1234, -2)").unwrap(), int(1200));
+    assert_eq!(eval(&mut db, "CEIL(1.2)").unwrap(), DataType::Float32(2.0));
+    assert_eq!(eval(&mut db, "FLOOR(-1.2)").unwrap(), DataType::Float32(-2.0));
+    assert_eq!(eval(&mut db, "FLOOR(n)").unwrap(), int(-7));
+
+    assert!(matches!(eval(&mut db, "MOD(n, 0)"), Err(DbError::FunctionFailed { .. })));
+    assert!(eval(&mut db, "POWER(10, 10)").is_err());
+    assert!(eval(&mut db, "ABS(s)").is_err());
+}
+
+#[test]
+fn timestamps_are_read_apart_and_moved_by_seconds_and_intervals() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    // 2024-02-29 12:00:00 UTC
+    db.set_clock(Arc::new(ManualClock::new(1_709_208_000)) as Arc<dyn Clock>);
+    assert_eq!(eval(&mut db, "NOW()").unwrap(), string("2024-02-29 12:00:00"));
+    assert_eq!(eval(&mut db, "DATE(NOW())").unwrap(), string("2024-02-29"));
+    let parts: Vec<_> = ["YEAR", "MONTH", "DAY"].iter().map(|f| eval(&mut db, &format!("{}('2023-12-31 23:59')", f)).unwrap()).collect();
+    assert_eq!(parts, [int(2023), int(12), int(31)]);
+
+    assert_eq!(eval(&mut db, "NOW() + INTERVAL 1 DAY").unwrap(), string("2024-03-01 12:00:00"));
+    assert_eq!(eval(&mut db, "'2024-01-01' - 1").unwrap(), string("2023-12-31 23:59:59"));
+    assert_eq!(eval(&mut db, "NOW() - '2024-02-28 12:00'").unwrap(), int(86400));
+    assert_eq!(eval(&mut db, "INTERVAL 2 WEEK").unwrap(), int(1_209_600));
+    assert!(eval(&mut db, "YEAR('yesterday')").is_err());
+
+    create_table(&mut db, "events", &[("id", "int"), ("at", "string")]);
+    insert(&mut db, "events", vec![int(1), string("2024-02-20 09:00")]);
+    insert(&mut db, "events", vec![int(2), string("2024-02-24")]);
+    let rows = db.query("SELECT id FROM events WHERE at > NOW() - INTERVAL 7 DAY").unwrap();
+    assert_eq!(rows.rows, vec![vec![int(2)]]);
+}
+
+#[test]
+fn cast_converts_between_numbers_and_text_or_fails() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    assert_eq!(eval(&mut db, "CAST(' 42 ' AS int)").unwrap(), int(42));
+    assert_eq!(eval(&mut db, "CAST('2.5' AS float)").unwrap(), DataType::Float32(2.5));
+    assert_eq!(eval(&mut db, "CAST(-2.9 AS int)").unwrap(), int(-2));
+    assert_eq!(eval(&mut db, "CAST(n AS string)").unwrap(), string("-7"));
+    assert!(matches!(eval(&mut db, "CAST('abc' AS int)"), Err(DbError::InvalidExpression(_))));
+    assert!(matches!(eval(&mut db, "CAST(3000000000.0 AS int)"), Err(DbError::InvalidExpression(_))));
+
+    // Text compared as numbers once cast
+    create_table(&mut db, "people", &[("name", "string"), ("age", "string")]);
+    insert(&mut db, "people", vec![string("ann"), string("9")]);
+    insert(&mut db, "people", vec![string("bob"), string("18")]);
+    assert_eq!(db.query("SELECT name FROM people WHERE age >= '10'").unwrap().rows, vec![vec![string("ann")], vec![string("bob")]]);
+    assert_eq!(db.query("SELECT name FROM people WHERE CAST(age AS int) >= 10").unwrap().rows, vec![vec![string("bob")]]);
+}
+
+#[test]
+fn order_by_random_picks_rows_at_random_once_each() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    create_table(&mut db, "t", &[("id", "int")]);
+    for id in 0..100 {
+        insert(&mut db, "t", vec![int(id)]);
+    }
+    let pick = |db: &mut Database| -> Vec<DataType> {
+        db.query("SELECT id FROM t ORDER BY RANDOM() LIMIT 10").unwrap().rows.into_iter().map(|row| row[0].clone()).collect()
+    };
+    let first = pick(&mut db);
+    let mut distinct = first.clone();
+    distinct.sort_by_key(|id| id.to_string());
+    distinct.dedup();
+    assert_eq!(distinct.len(), 10);
+    // Ten of a hundred the same, and in the same order, by chance once in ~10^19 runs
+    assert_ne!(pick(&mut db), first);
+
+    let random = eval(&mut db, "RANDOM()").unwrap();
+    assert!(matches!(random, DataType::Float32(x) if (0.0..1.0).contains(&x)), "{:?}", random);
+}
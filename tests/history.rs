@@ -0,0 +1,65 @@
+mod common;
+
+use std::sync::Arc;
+
+use rust_db::time::{Clock, ManualClock};
+use rust_db::wal::WalOp;
+use rust_db::{recovery, DataType, Database, DbError};
+
+use common::{create_table, insert, int, string, TempDir};
+
+const START: u64 = 1_700_000_000; // 2023-11-14 22:13:20 UTC
+
+fn names(db: &mut Database, sql: &str) -> Vec<DataType> {
+    db.query(sql).unwrap().rows.into_iter().flatten().collect()
+}
+
+#[test]
+fn as_of_reads_a_table_as_it_was() {
+    let dir = TempDir::new();
+    let clock = Arc::new(ManualClock::new(START));
+    {
+        let mut db = Database::open_dir(dir.path()).unwrap();
+        db.set_clock(Arc::clone(&clock) as Arc<dyn Clock>);
+        create_table(&mut db, "users", &[("id", "int"), ("name", "string")]);
+        insert(&mut db, "users", vec![int(1), string("ann")]);
+        db.set_history_retention("users", Some(86400)).unwrap();
+        clock.advance(60);
+        insert(&mut db, "users", vec![int(2), string("bob")]);
+        clock.advance(60);
+        db.log(WalOp::Update { table: "users".to_string(), row: 0, values: vec![("name".to_string(), string("amy"))] }).unwrap();
+        clock.advance(60);
+        db.log(WalOp::Delete { table: "users".to_string(), index: 1 }).unwrap();
+        db.checkpoint().unwrap();
+    }
+    // What undoes each change is saved with the table
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    db.set_clock(Arc::clone(&clock) as Arc<dyn Clock>);
+    recovery::recover(&mut db).unwrap();
+
+    assert_eq!(names(&mut db, "SELECT name FROM users AS OF '2023-11-14 22:13:30' ORDER BY id"), [string("ann")]);
+    assert_eq!(names(&mut db, "SELECT name FROM users AS OF '2023-11-14 22:14:30' ORDER BY id"), [string("ann"), string("bob")]);
+    assert_eq!(names(&mut db, "SELECT name FROM users AS OF '2023-11-14 22:15:30' ORDER BY id"), [string("amy"), string("bob")]);
+    assert_eq!(names(&mut db, "SELECT name FROM users ORDER BY id"), [string("amy")]);
+    // The table now and then, joined
+    let rows = db.query("SELECT old.name, users.name FROM users AS OF '2023-11-14 22:14:30' old JOIN users ON old.id = users.id").unwrap();
+    assert_eq!(rows.rows, vec![vec![string("ann"), string("amy")]]);
+}
+
+#[test]
+fn history_only_goes_back_as_far_as_it_was_kept() {
+    let clock = Arc::new(ManualClock::new(START));
+    let mut db = Database::open_in_memory();
+    db.set_clock(Arc::clone(&clock) as Arc<dyn Clock>);
+    create_table(&mut db, "users", &[("id", "int")]);
+    assert!(matches!(db.as_of("users", START), Err(DbError::NoHistory(_))));
+
+    db.set_history_retention("users", Some(60)).unwrap();
+    clock.advance(10);
+    insert(&mut db, "users", vec![int(1)]);
+    assert!(matches!(db.as_of("users", START - 1), Err(DbError::HistoryUnavailable { since: START, .. })));
+    assert_eq!(db.as_of("users", START).unwrap().row_count(), 0);
+
+    db.set_history_retention("users", None).unwrap();
+    assert!(matches!(db.as_of("users", START), Err(DbError::NoHistory(_))));
+}
@@ -0,0 +1,165 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use rust_db::index::{IndexDef, IndexKind};
+use rust_db::parser::{self, Statement};
+use rust_db::planner;
+use rust_db::wal::WalOp;
+use rust_db::{recovery, DataType, Database, DbError, Table};
+
+use common::{cli, create_table, insert, int, TempDir};
+
+// Runs `script` with the client in `dir`, carrying on past errors. Returns what it printed
+fn run(dir: &Path, script: &str) -> String {
+    let output = cli(dir).args(["--continue-on-error", "-c", script]).output().unwrap();
+    String::from_utf8(output.stdout).unwrap() + &String::from_utf8(output.stderr).unwrap()
+}
+
+// How a SELECT on `table` filtered by `condition` reads it
+fn plan(db: &mut Database, table: &str, condition: &str) -> String {
+    let Statement::Select { filter, .. } = parser::parse(&format!("SELECT * FROM {} WHERE {}", table, condition)).unwrap() else {
+        unreachable!("a SELECT parses as one");
+    };
+    let snapshot = db.snapshot(table).unwrap();
+    planner::plan(&snapshot, &filter, &db.functions()).unwrap().to_string()
+}
+
+fn index(name: &str, columns: &[&str], kind: IndexKind) -> IndexDef {
+    IndexDef { name: name.to_string(), columns: columns.iter().map(|col| col.to_string()).collect(), kind, unique: false }
+}
+
+// The ids of the rows of `table` matching `condition`, in order
+fn ids(db: &mut Database, table: &str, condition: &str) -> Vec<DataType> {
+    db.query(&format!("SELECT id FROM {} WHERE {} ORDER BY id", table, condition)).unwrap()
+        .rows.into_iter().map(|row| row[0].clone()).collect()
+}
+
+// Users 1 to 10, each as old as ten times their id
+fn users(db: &mut Database) {
+    create_table(db, "users", &[("id", "int"), ("age", "int")]);
+    for id in 1..=10 {
+        insert(db, "users", vec![int(id), int(id * 10)]);
+    }
+}
+
+#[test]
+fn equality_and_range_filters_use_an_ordered_index() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    users(&mut db);
+    db.create_index("users", index("idx_age", &["age"], IndexKind::BTree)).unwrap();
+
+    assert!(plan(&mut db, "users", "age = 30").starts_with("Index lookup on users using idx_age"));
+    assert!(plan(&mut db, "users", "age >= 30 AND age < 60").starts_with("Index range scan on users using idx_age"));
+    assert!(plan(&mut db, "users", "id = 3").starts_with("Full scan"));
+    assert_eq!(ids(&mut db, "users", "age >= 30 AND age < 60"), [int(3), int(4), int(5)]);
+}
+
+#[test]
+fn an_ordered_index_is_kept_up_to_date_and_saved_with_its_table() {
+    let dir = TempDir::new();
+    {
+        let mut db = Database::open_dir(dir.path()).unwrap();
+        users(&mut db);
+        db.create_index("users", index("idx_age", &["age"], IndexKind::BTree)).unwrap();
+        insert(&mut db, "users", vec![int(11), int(35)]);
+        db.log(WalOp::Update { table: "users".to_string(), row: 0, values: vec![("age".to_string(), int(45))] }).unwrap();
+        db.log(WalOp::Delete { table: "users".to_string(), index: 3 }).unwrap();
+        assert_eq!(ids(&mut db, "users", "age > 30 AND age < 50"), [int(1), int(11)]);
+        db.checkpoint().unwrap();
+    }
+    assert!(dir.path().join("users.idx").exists());
+
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    recovery::recover(&mut db).unwrap();
+    assert!(plan(&mut db, "users", "age > 30").starts_with("Index range scan"));
+    assert_eq!(ids(&mut db, "users", "age > 30 AND age < 50"), [int(1), int(11)]);
+    assert_eq!(ids(&mut db, "users", "age = 40"), Vec::<DataType>::new());
+}
+
+#[test]
+fn a_hash_index_serves_equality_before_an_ordered_one_and_nothing_else() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    users(&mut db);
+    db.create_index("users", index("idx_id", &["id"], IndexKind::BTree)).unwrap();
+    db.create_index("users", index("idx_id_hash", &["id"], IndexKind::Hash)).unwrap();
+
+    assert!(plan(&mut db, "users", "id = 7").starts_with("Index lookup on users using idx_id_hash"));
+    assert!(plan(&mut db, "users", "id > 7").starts_with("Index range scan on users using idx_id "));
+    db.drop_index("users", "idx_id").unwrap();
+    assert!(plan(&mut db, "users", "id > 7").starts_with("Full scan"));
+
+    insert(&mut db, "users", vec![int(11), int(5)]);
+    db.log(WalOp::Delete { table: "users".to_string(), index: 6 }).unwrap();
+    assert_eq!(ids(&mut db, "users", "id = 11"), [int(11)]);
+    assert_eq!(ids(&mut db, "users", "id = 7"), Vec::<DataType>::new());
+}
+
+#[test]
+fn a_primary_key_is_looked_up_and_kept_unique_through_an_index_of_its_own() {
+    let dir = TempDir::new();
+    {
+        let mut db = Database::open_dir(dir.path()).unwrap();
+        let schema = vec![("id".to_string(), "int".to_string()), ("age".to_string(), "int".to_string())];
+        let table = Table::new("users", schema, Some("id".to_string()), db.last_lsn(), db.now());
+        db.save_table(&table).unwrap();
+        for id in 1..=10 {
+            insert(&mut db, "users", vec![int(id), int(id * 10)]);
+        }
+    }
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    recovery::recover(&mut db).unwrap();
+    assert!(plan(&mut db, "users", "id = 3").starts_with("Index lookup on users using users_pkey"));
+    let users = db.snapshot("users").unwrap();
+    assert!(matches!(users.check_unique(&[int(3), int(0)]), Err(DbError::DuplicateKey { index, .. }) if index == "users_pkey"));
+    assert!(users.check_unique(&[int(11), int(0)]).is_ok());
+    // It enforces the key, so it cannot be dropped
+    assert!(db.drop_index("users", "users_pkey").is_err());
+}
+
+#[test]
+fn a_composite_index_serves_filters_on_a_prefix_of_its_columns() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    create_table(&mut db, "orders", &[("id", "int"), ("user_id", "int"), ("created", "int")]);
+    for id in 0..12 {
+        insert(&mut db, "orders", vec![int(id), int(id % 3), int(100 + id)]);
+    }
+    db.create_index("orders", index("idx_uc", &["user_id", "created"], IndexKind::BTree)).unwrap();
+
+    assert!(plan(&mut db, "orders", "user_id = 1").starts_with("Index lookup on orders using idx_uc (user_id = 1)"));
+    assert!(plan(&mut db, "orders", "user_id = 1 AND created = 104").starts_with("Index lookup on orders using idx_uc"));
+    assert!(plan(&mut db, "orders", "user_id = 1 AND created > 104").starts_with("Index range scan on orders using idx_uc"));
+    // Without its leading column the index is no help
+    assert!(plan(&mut db, "orders", "created = 104").starts_with("Full scan"));
+
+    assert_eq!(ids(&mut db, "orders", "user_id = 1"), [int(1), int(4), int(7), int(10)]);
+    assert_eq!(ids(&mut db, "orders", "user_id = 1 AND created = 104"), [int(4)]);
+    assert_eq!(ids(&mut db, "orders", "user_id = 1 AND created > 104"), [int(7), int(10)]);
+}
+
+#[test]
+fn indexes_are_listed_dropped_and_rebuilt_by_statement() {
+    let dir = TempDir::new();
+    let output = run(dir.path(), "CREATE TABLE users id:int PRIMARY KEY age:int; CREATE TABLE pets id:int age:int; \
+        CREATE INDEX by_age ON users(age); CREATE INDEX by_age ON pets(age); SHOW INDEXES FROM users; \
+        DROP INDEX by_age; DROP INDEX users_pkey; DROP INDEX nothing; DROP INDEX by_age ON users; \
+        SELECT name, table_name FROM __indexes");
+    assert!(output.contains("Index,Columns,Kind,Unique\nusers_pkey,id,BTREE,yes\nby_age,age,BTREE,no\n"), "{}", output);
+    assert!(output.contains("[E2025] Index 'by_age' is on several tables (pets, users); use DROP INDEX by_age ON <table>"), "{}", output);
+    assert!(output.contains("[E1009] Invalid index: 'users_pkey' enforces the primary key of 'users' and cannot be dropped"), "{}", output);
+    assert!(output.contains("[E2019] Index 'nothing' does not exist"), "{}", output);
+    assert!(output.contains("Index 'by_age' dropped from 'users'\nname,table_name\nby_age,pets\nusers_pkey,users\n"), "{}", output);
+
+    // An index file matching its table is trusted, however wrong, until rebuilt
+    run(dir.path(), "INSERT INTO pets VALUES (1, 5); INSERT INTO pets VALUES (2, 7)");
+    let path = dir.path().join("data/pets.idx");
+    let saved = fs::read_to_string(&path).unwrap();
+    fs::write(&path, saved.replace("[0]]", "[9]]").replace("[1]]", "[0]]").replace("[9]]", "[1]]")).unwrap();
+    let output = run(dir.path(), "SELECT id FROM pets WHERE age = 5; REINDEX pets; SELECT id FROM pets WHERE age = 5; REINDEX __tables");
+    assert!(output.starts_with("No row found with age = 5\nTable 'pets' reindexed (1 index(es))\nid\n1\n"), "{}", output);
+    assert!(output.contains("[E3006]"), "{}", output);
+}
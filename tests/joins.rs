@@ -0,0 +1,48 @@
+mod common;
+
+use rust_db::Database;
+
+use common::{create_table, insert, int, string, TempDir};
+
+// Users 1 (ann) and 2 (bob), and orders 10 and 11 of ann and 12 of bob
+fn shop(db: &mut Database) {
+    create_table(db, "users", &[("id", "int"), ("name", "string")]);
+    insert(db, "users", vec![int(1), string("ann")]);
+    insert(db, "users", vec![int(2), string("bob")]);
+    create_table(db, "orders", &[("id", "int"), ("user_id", "int"), ("total", "int")]);
+    insert(db, "orders", vec![int(10), int(1), int(5)]);
+    insert(db, "orders", vec![int(11), int(1), int(7)]);
+    insert(db, "orders", vec![int(12), int(2), int(3)]);
+}
+
+#[test]
+fn tables_listed_in_from_give_every_combination_of_their_rows() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    shop(&mut db);
+
+    let rows = db.query("SELECT * FROM users CROSS JOIN orders").unwrap();
+    assert_eq!(rows.columns, ["users.id", "users.name", "orders.id", "orders.user_id", "orders.total"]);
+    assert_eq!(rows.rows.len(), 6);
+    let rows = db.query("SELECT name, total FROM users, orders WHERE users.id = orders.user_id AND total > 4 ORDER BY total").unwrap();
+    assert_eq!(rows.rows, vec![vec![string("ann"), int(5)], vec![string("ann"), int(7)]]);
+    // A column both tables have must be qualified
+    assert!(db.query("SELECT id FROM users, orders").is_err());
+}
+
+#[test]
+fn a_table_given_aliases_is_joined_to_itself() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    create_table(&mut db, "staff", &[("id", "int"), ("name", "string"), ("manager_id", "int")]);
+    insert(&mut db, "staff", vec![int(1), string("boss"), int(0)]);
+    insert(&mut db, "staff", vec![int(2), string("ann"), int(1)]);
+    insert(&mut db, "staff", vec![int(3), string("bob"), int(2)]);
+
+    let rows = db.query("SELECT e.name, m.name FROM staff e JOIN staff AS m ON e.manager_id = m.id ORDER BY e.id").unwrap();
+    assert_eq!(rows.columns, ["e.name", "m.name"]);
+    assert_eq!(rows.rows, vec![vec![string("ann"), string("boss")], vec![string("bob"), string("ann")]]);
+    // Once aliased, a table goes by its alias alone
+    assert!(db.query("SELECT staff.name FROM staff e JOIN staff m ON e.manager_id = m.id").is_err());
+    assert!(db.query("SELECT e.name FROM staff e JOIN staff e ON e.manager_id = e.id").is_err());
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use common::{cli, TempDir};
+
+// Runs `script` with the client in `dir`. Returns what it printed, and whether it all worked
+fn run(dir: &Path, script: &str) -> (String, bool) {
+    let output = cli(dir).args(["-c", script]).output().unwrap();
+    (String::from_utf8(output.stdout).unwrap() + &String::from_utf8(output.stderr).unwrap(), output.status.success())
+}
+
+#[test]
+fn rows_are_exported_as_json_objects_and_imported_back() {
+    let dir = TempDir::new();
+    let (output, ok) = run(dir.path(), "CREATE TABLE posts id:int title:string tags:string[]; \
+        INSERT INTO posts VALUES (1, 'Say \"hi\"', ['rust', 'db']); EXPORT TABLE posts TO 'posts.jsonl' FORMAT JSONL");
+    assert!(ok, "{}", output);
+    assert_eq!(fs::read_to_string(dir.path().join("posts.jsonl")).unwrap(), "{\"id\":1,\"title\":\"Say \\\"hi\\\"\",\"tags\":[\"rust\",\"db\"]}\n");
+
+    let (output, ok) = run(dir.path(), "DELETE FROM posts WHERE id = 1; IMPORT JSONL 'posts.jsonl' INTO posts; \
+        SELECT id FROM posts WHERE tags CONTAINS 'db'");
+    assert!(ok, "{}", output);
+    assert!(output.ends_with("Imported 1 row(s) into 'posts'\nid\n1\n"), "{}", output);
+}
+
+#[test]
+fn keys_that_are_not_columns_are_refused_unless_ignored() {
+    let dir = TempDir::new();
+    fs::write(dir.path().join("events.jsonl"), "{\"id\":1,\"kind\":\"a\",\"extra\":true}\n{\"kind\":\"b\",\"id\":2}\n").unwrap();
+    fs::write(dir.path().join("short.jsonl"), "{\"id\":3}\n").unwrap();
+    let (output, ok) = run(dir.path(), "CREATE TABLE events id:int kind:string; IMPORT JSONL 'events.jsonl' INTO events");
+    assert!(!ok);
+    assert!(output.contains("[E6003] Import failed at line 1: column 'extra' does not exist in table 'events'"), "{}", output);
+    assert!(!run(dir.path(), "IMPORT JSONL 'short.jsonl' INTO events").1);
+
+    let (output, ok) = run(dir.path(), "IMPORT 'events.jsonl' INTO events FORMAT JSONL IGNORE UNKNOWN; SELECT * FROM events");
+    assert!(ok, "{}", output);
+    assert!(output.ends_with("id,kind\n1,a\n2,b\n"), "{}", output);
+}
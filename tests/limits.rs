@@ -0,0 +1,57 @@
+mod common;
+
+use std::fs;
+use std::io::Write;
+use std::process::{Output, Stdio};
+use std::time::{Duration, Instant};
+
+use common::{cli, TempDir};
+
+// A billion combinations of the rows of t, minutes of work
+const RUNAWAY: &str = "SELECT COUNT(*) FROM t a, t b, t c WHERE a.id < b.id";
+
+// Table t of ids 0 to 999
+fn thousand_rows(dir: &TempDir) {
+    let rows: String = (0..1000).map(|id| format!("{}\n", id)).collect();
+    let mut load = cli(dir.path()).args(["-c", "CREATE TABLE t id:int; COPY t FROM STDIN CSV NO HEADER"]).stdin(Stdio::piped()).stdout(Stdio::null()).spawn().unwrap();
+    load.stdin.take().unwrap().write_all(rows.as_bytes()).unwrap();
+    assert!(load.wait().unwrap().success());
+}
+
+fn run(dir: &TempDir, args: &[&str], script: &str) -> Output {
+    cli(dir.path()).args(args).args(["-c", script]).output().unwrap()
+}
+
+#[test]
+fn a_statement_running_past_the_timeout_is_cancelled() {
+    let dir = TempDir::new();
+    thousand_rows(&dir);
+    fs::write(dir.path().join("rustdb.toml"), "[query]\nstatement_timeout_ms = 200\n").unwrap();
+    let started = Instant::now();
+    let output = run(&dir, &[], RUNAWAY);
+    assert!(started.elapsed() < Duration::from_secs(10));
+    assert!(String::from_utf8(output.stderr).unwrap()
+        .contains("[E5002] Statement cancelled after running longer than statement_timeout (200 ms)"));
+
+    // SET changes it for what follows
+    fs::remove_file(dir.path().join("rustdb.toml")).unwrap();
+    let output = run(&dir, &["--continue-on-error"], &format!("SET statement_timeout = 100; {}; SELECT COUNT(*) FROM t", RUNAWAY));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("(100 ms)"));
+    assert!(String::from_utf8(output.stdout).unwrap().ends_with("COUNT(*)\n1000\n"));
+}
+
+#[test]
+fn a_query_holding_more_than_the_memory_allowed_fails() {
+    let dir = TempDir::new();
+    thousand_rows(&dir);
+    fs::write(dir.path().join("rustdb.toml"), "[query]\nmax_memory_mb = 1\n").unwrap();
+    let output = run(&dir, &[], "SELECT a.id, b.id FROM t a, t b ORDER BY a.id");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr).unwrap().contains("[E5003] Query needs more than the 1 MiB of memory allowed (max_memory_mb)"));
+
+    let output = run(&dir, &[], "SELECT id FROM t WHERE id > 990 ORDER BY id");
+    assert!(output.status.success());
+    fs::remove_file(dir.path().join("rustdb.toml")).unwrap();
+    assert!(run(&dir, &[], "SELECT a.id, b.id FROM t a, t b WHERE a.id < 50 ORDER BY a.id").status.success());
+}
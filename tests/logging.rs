@@ -0,0 +1,41 @@
+mod common;
+
+use std::fs;
+
+use common::{cli, TempDir};
+
+#[test]
+fn statements_are_logged_with_their_outcome_and_without_passwords() {
+    let dir = TempDir::new();
+    let script = "CREATE TABLE u id:int; INSERT INTO u VALUES (1); CREATE USER bob PASSWORD 'hunter2'; SELECT * FROM nowhere";
+    let output = cli(dir.path()).args(["--memory", "--log-level", "info", "-c", script]).output().unwrap();
+    let log = String::from_utf8(output.stderr).unwrap();
+    let lines: Vec<&str> = log.lines().filter(|line| line.contains(" INFO rust_db::query: ")).collect();
+    assert_eq!(lines.len(), 4, "{}", log);
+    assert!(lines[1].ends_with(" 1 row(s), ok, user -: INSERT INTO u VALUES (1)"), "{}", lines[1]);
+    assert!(lines[2].ends_with(", ok, user -: CREATE USER bob PASSWORD ..."), "{}", lines[2]);
+    assert!(lines[3].ends_with(" 0 row(s), failed, user -: SELECT * FROM nowhere"), "{}", lines[3]);
+    assert!(!log.contains("hunter2"));
+
+    // Only warnings by default
+    let output = cli(dir.path()).args(["--memory", "-c", "CREATE TABLE u id:int"]).output().unwrap();
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn slow_statements_are_logged_to_a_file_of_their_own() {
+    let dir = TempDir::new();
+    let output = cli(dir.path()).args(["--memory", "--slow-query-ms", "0", "--slow-query-log", "slow.log", "-c", "CREATE TABLE u id:int; SELECT * FROM u"])
+        .output().unwrap();
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+    let log = fs::read_to_string(dir.path().join("slow.log")).unwrap();
+    let lines: Vec<&str> = log.lines().collect();
+    assert_eq!(lines.len(), 2, "{}", log);
+    assert!(lines[1].contains(" WARN rust_db::slow_query: ") && lines[1].ends_with(", ok, user -: SELECT * FROM u"), "{}", log);
+
+    let output = cli(dir.path()).args(["--memory", "--slow-query-ms", "60000", "--slow-query-log", "none.log", "-c", "CREATE TABLE u id:int"])
+        .output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(dir.path().join("none.log")).unwrap_or_default(), "");
+}
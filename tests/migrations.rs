@@ -0,0 +1,64 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::process::Output;
+
+use common::{cli, TempDir};
+
+fn migrate(dir: &Path) -> Output {
+    cli(dir).arg("migrate").output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn migrations_run_once_each_in_order_of_their_versions() {
+    let dir = TempDir::new();
+    fs::create_dir(dir.path().join("migrations")).unwrap();
+    fs::write(dir.path().join("migrations/002_add_index.sql"), "CREATE INDEX idx_age ON users(age)").unwrap();
+    fs::write(dir.path().join("migrations/001_create_users.sql"), "CREATE TABLE users id:int age:int;\n-- Seed\nINSERT INTO users VALUES (1, 20)\n").unwrap();
+    fs::write(dir.path().join("migrations/README.md"), "Not a migration").unwrap();
+
+    let output = migrate(dir.path());
+    assert!(output.status.success());
+    assert!(stdout(&output).starts_with("Applied migration 1 (create_users)\nApplied migration 2 (add_index)\n"), "{}", stdout(&output));
+    assert_eq!(stdout(&migrate(dir.path())), "No pending migrations\n");
+
+    let output = cli(dir.path()).args(["-c", "SELECT version, name FROM schema_migrations ORDER BY version; SELECT COUNT(*) FROM users"]).output().unwrap();
+    assert_eq!(stdout(&output), "version,name\n1,create_users\n2,add_index\nCOUNT(*)\n1\n");
+}
+
+#[test]
+fn a_failed_migration_is_not_recorded_and_runs_again_once_fixed() {
+    let dir = TempDir::new();
+    fs::create_dir(dir.path().join("migrations")).unwrap();
+    fs::write(dir.path().join("migrations/1_create.sql"), "CREATE TABLE users id:int age:int").unwrap();
+    fs::write(dir.path().join("migrations/2_seed.sql"), "INSERT INTO nowhere VALUES (1)").unwrap();
+
+    let output = migrate(dir.path());
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout(&output).contains("[E6009] Migration 2 (seed) failed"), "{}", stdout(&output));
+
+    fs::write(dir.path().join("migrations/2_seed.sql"), "INSERT INTO users VALUES (1, 20)").unwrap();
+    let output = migrate(dir.path());
+    assert!(output.status.success());
+    assert!(stdout(&output).starts_with("Applied migration 2 (seed)\n"), "{}", stdout(&output));
+}
+
+#[test]
+fn a_migration_file_without_a_version_or_with_a_version_taken_is_refused() {
+    let dir = TempDir::new();
+    fs::create_dir(dir.path().join("migrations")).unwrap();
+    fs::write(dir.path().join("migrations/create.sql"), "CREATE TABLE users id:int").unwrap();
+    assert!(stdout(&migrate(dir.path())).contains("[E6006]"));
+
+    fs::remove_file(dir.path().join("migrations/create.sql")).unwrap();
+    fs::write(dir.path().join("migrations/1_a.sql"), "CREATE TABLE a id:int").unwrap();
+    fs::write(dir.path().join("migrations/01_b.sql"), "CREATE TABLE b id:int").unwrap();
+    let output = migrate(dir.path());
+    assert!(stdout(&output).contains("have the same version"), "{}", stdout(&output));
+    assert!(!dir.path().join("data/a.json").exists());
+}
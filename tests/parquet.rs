@@ -0,0 +1,41 @@
+mod common;
+
+use std::fs::File;
+
+use ::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use ::parquet::basic::Compression;
+use arrow_array::{Array, Float32Array, Int32Array, StringArray};
+use arrow_schema::DataType as ArrowType;
+use rust_db::{parquet, DataType, Database};
+
+use common::{create_table, insert, int, string, TempDir};
+
+#[test]
+fn a_query_is_written_as_snappy_compressed_columns_of_arrow_types() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    create_table(&mut db, "users", &[("id", "int"), ("name", "string"), ("score", "float"), ("tags", "string[]")]);
+    insert(&mut db, "users", vec![int(1), string("ann"), DataType::Float32(1.5), DataType::Array(vec![string("a"), string("b")])]);
+    insert(&mut db, "users", vec![int(2), string("bob"), DataType::Float32(2.0), DataType::Array(vec![])]);
+    let path = dir.path().join("users.parquet");
+    parquet::write(&path, &db.query("SELECT * FROM users ORDER BY id").unwrap()).unwrap();
+
+    let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap()).unwrap();
+    assert_eq!(builder.metadata().row_group(0).column(0).compression(), Compression::SNAPPY);
+    let types: Vec<_> = builder.schema().fields().iter().map(|field| (field.name().clone(), field.data_type().clone(), field.is_nullable())).collect();
+    assert_eq!(types, [
+        ("id".to_string(), ArrowType::Int32, false),
+        ("name".to_string(), ArrowType::Utf8, false),
+        ("score".to_string(), ArrowType::Float32, false),
+        ("tags".to_string(), ArrowType::Utf8, false),
+    ]);
+
+    let batch = builder.build().unwrap().next().unwrap().unwrap();
+    assert_eq!(batch.num_rows(), 2);
+    let ids = batch.column(0).as_any().downcast_ref::<Int32Array>().unwrap();
+    assert_eq!(ids.values(), &[1, 2]);
+    let scores = batch.column(2).as_any().downcast_ref::<Float32Array>().unwrap();
+    assert_eq!(scores.values(), &[1.5, 2.0]);
+    let tags = batch.column(3).as_any().downcast_ref::<StringArray>().unwrap();
+    assert_eq!((tags.value(0), tags.value(1)), ("{a,b}", "{}"));
+}
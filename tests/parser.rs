@@ -0,0 +1,148 @@
+mod common;
+
+use rust_db::expr::Expr;
+use rust_db::paths::PathQuery;
+use rust_db::parser::{self, ConflictAction, SetValue, Source, Statement, TableRef};
+use rust_db::{Database, DbError};
+
+use common::TempDir;
+
+// The assignments of an UPDATE
+fn set(sql: &str) -> Vec<(String, SetValue)> {
+    match parser::parse(sql).unwrap() {
+        Statement::Update { set, .. } => set,
+        other => panic!("not an UPDATE: {:?}", other),
+    }
+}
+
+// The FROM list of a SELECT
+fn from(sql: &str) -> Vec<TableRef> {
+    match parser::parse(sql).unwrap() {
+        Statement::Select { from, joins, .. } => TableRef::list(from, joins),
+        other => panic!("not a SELECT: {:?}", other),
+    }
+}
+
+#[test]
+fn an_update_sets_a_column_to_an_expression_over_the_row() {
+    let set = set("UPDATE t SET n = n + 1, name = UPPER(name) WHERE id = 1");
+    assert!(matches!(&set[0], (column, SetValue::Expr(expr)) if column == "n" && *expr == parser::parse_expr("n + 1").unwrap()));
+    assert!(matches!(&set[1], (column, SetValue::Expr(Expr::Call { function, .. })) if column == "name" && function == "UPPER"));
+}
+
+#[test]
+fn a_value_standing_alone_is_still_a_literal() {
+    let set = set("UPDATE t SET a = 27, b = 'x', c = -1, d = active, e = (f) WHERE id = 1");
+    let literals: Vec<_> = set[..4].iter().map(|(_, value)| match value {
+        SetValue::Literal(raw) => raw.as_str(),
+        other => panic!("not a literal: {:?}", other),
+    }).collect();
+    assert_eq!(literals, ["27", "x", "-1", "active"]);
+    assert!(matches!(&set[4].1, SetValue::Expr(Expr::Column(column)) if column == "f"));
+}
+
+#[test]
+fn an_upsert_adds_to_the_row_already_holding_the_key() {
+    let Statement::Insert { on_conflict: Some(on_conflict), .. } =
+        parser::parse("INSERT INTO t VALUES (1, 2) ON CONFLICT (id) DO UPDATE SET hits = hits + EXCLUDED.hits, n = EXCLUDED.n").unwrap()
+    else {
+        panic!("not an upsert");
+    };
+    let ConflictAction::Update(set) = on_conflict.action else { panic!("not DO UPDATE") };
+    assert!(matches!(&set[0].1, SetValue::Expr(_)));
+    assert!(matches!(&set[1].1, SetValue::Excluded(source) if source == "n"));
+}
+
+#[test]
+fn a_table_as_of_a_time_keeps_its_name() {
+    let tables = from("SELECT * FROM users AS OF '2024-05-01 12:00' old JOIN users ON old.id = users.id");
+    assert_eq!((tables[0].table.as_str(), tables[0].name()), ("users", "old"));
+    assert!(matches!(tables[0].source, Source::AsOf(_)));
+    assert_eq!((tables[1].table.as_str(), &tables[1].source), ("users", &Source::Current));
+    assert!(parser::parse("CREATE VIEW v AS SELECT * FROM users AS OF '2024-05-01 12:00'").is_err());
+}
+
+#[test]
+fn a_table_with_its_deleted_rows_keeps_its_name() {
+    let tables = from("SELECT d.id FROM orders WITH DELETED d JOIN orders ON d.id = orders.id");
+    assert_eq!((tables[0].table.as_str(), tables[0].name(), &tables[0].source), ("orders", "d", &Source::Deleted));
+    assert_eq!(from("SELECT * FROM orders WITH DELETED")[0].name(), "orders");
+    assert!(parser::parse("CREATE VIEW v AS SELECT * FROM orders WITH DELETED").is_err());
+}
+
+#[test]
+fn a_path_query_is_read_from_its_table_of_edges() {
+    let tables = from("SELECT hops FROM PATH(edges, parent_id, child_id, 1, 7)");
+    assert_eq!((tables[0].table.as_str(), tables[0].name()), ("edges", "path"));
+    let query = PathQuery {
+        table: "edges".to_string(),
+        from: "parent_id".to_string(),
+        to: "child_id".to_string(),
+        start: "1".to_string(),
+        end: Some("7".to_string()),
+    };
+    assert_eq!(tables[0].source, Source::Path(query));
+}
+
+#[test]
+fn a_syntax_error_says_where_parsing_stopped() {
+    let e = parser::parse("SELECT * FRM users").unwrap_err();
+    assert_eq!((e.code(), e.offset()), ("E1001", Some(9)));
+    assert!(matches!(&e, DbError::SyntaxAt { token: Some(token), .. } if token == "FRM"));
+
+    let e = parser::parse("SELECT * FROM users WHERE id >").unwrap_err();
+    assert_eq!((e.code(), e.offset()), ("E1001", Some(30)));
+    assert!(matches!(e, DbError::SyntaxAt { token: None, .. }));
+    // Offsets count bytes, not characters
+    assert_eq!(parser::parse("SELECT 'é' FRM users").unwrap_err().offset(), Some(12));
+}
+
+#[test]
+fn other_errors_have_a_code_and_no_position() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    let e = db.query("SELECT * FROM missing").unwrap_err();
+    assert_eq!((e.code(), e.offset()), ("E2001", None));
+}
+
+#[test]
+fn malformed_input_is_an_error_rather_than_a_crash() {
+    let statements = [
+        "SELECT name, COUNT(*) FROM users u JOIN orders o ON u.id = o.user_id WHERE o.total > 10 GROUP BY name ORDER BY name DESC",
+        "INSERT INTO t VALUES (1, 'it''s', -2.5) ON CONFLICT (id) DO UPDATE SET n = n + 1",
+        "UPDATE t SET a = CAST(b AS int) WHERE c IN (1, 2) AND d BETWEEN 3 AND 4",
+        "SELECT id, RANK() OVER (PARTITION BY dept ORDER BY pay) FROM staff WHERE id = (SELECT MAX(id) FROM staff)",
+    ];
+    // Every cut short, and every one with a character dropped
+    for statement in statements {
+        let cuts = statement.char_indices().map(|(i, _)| statement[..i].to_string());
+        let drops = statement.char_indices().map(|(i, c)| format!("{}{}", &statement[..i], &statement[i + c.len_utf8()..]));
+        for input in cuts.chain(drops) {
+            let _ = parser::parse(&input);
+        }
+    }
+
+    let deep = format!("SELECT * FROM t WHERE id = {}1{}", "(".repeat(10_000), ")".repeat(10_000));
+    assert!(matches!(parser::parse(&deep), Err(DbError::SyntaxAt { reason, .. }) if reason.contains("levels deep")));
+    let deep = format!("SELECT {}1 FROM t", "-".repeat(10_000));
+    assert!(parser::parse(&deep).is_err());
+}
+
+#[test]
+fn a_table_definition_is_checked_whole_before_it_is_created() {
+    let Err(DbError::InvalidDefinition(problems)) = parser::parse("CREATE TABLE t id:int select:string id:float n:integer") else {
+        panic!("the definition is accepted");
+    };
+    assert_eq!(problems, [
+        "column name 'select' is a reserved keyword",
+        "column 'id' is defined more than once",
+        "column 'n' has unknown type 'integer' (use int, float, string, point, enum(...) or an array)",
+    ]);
+    assert_eq!(parser::parse("CREATE TABLE Where id:int").unwrap_err().code(), "E1013");
+
+    // Type names are taken in any case
+    let Ok(Statement::CreateTable { columns, .. }) = parser::parse("CREATE TABLE users id:INT name:String tags:Float[]") else {
+        panic!("the definition is refused");
+    };
+    assert_eq!(columns.iter().map(|(_, typ)| typ.as_str()).collect::<Vec<_>>(), ["int", "string", "float[]"]);
+}
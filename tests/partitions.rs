@@ -0,0 +1,90 @@
+mod common;
+
+use rust_db::partition::{self, PartitionBy, Partitioning, Scheme};
+use rust_db::parser::{self, Statement};
+use rust_db::wal::WalOp;
+use rust_db::{recovery, Database, DbError, Table};
+
+use common::{insert, int, rows, TempDir};
+
+// Events partitioned by the year they were created, as CREATE TABLE makes them
+fn events(db: &mut Database) {
+    let schema = vec![("id".to_string(), "int".to_string()), ("created".to_string(), "int".to_string())];
+    let mut table = Table::new("events", schema, None, db.last_lsn(), db.now());
+    let by = PartitionBy {
+        column: "created".to_string(),
+        scheme: Scheme::Range,
+        partitions: vec![("p2023".to_string(), Some("2024".to_string())), ("p2024".to_string(), Some("2025".to_string()))],
+    };
+    table.partitioning = Some(Partitioning::new(&table, by).unwrap());
+    for name in ["p2023", "p2024"] {
+        db.save_table(&partition::empty_partition(&table, name, table.lsn)).unwrap();
+    }
+    db.save_table(&table).unwrap();
+    for (id, created) in [(1, 2023), (2, 2024), (3, 2023), (4, 2024)] {
+        insert(db, "events", vec![int(id), int(created)]);
+    }
+}
+
+// The partitions a SELECT on events filtered by `condition` reads, and the
+// rows it finds in them before filtering
+fn partitions(db: &mut Database, condition: &str) -> (Vec<String>, usize) {
+    let Statement::Select { filter, .. } = parser::parse(&format!("SELECT * FROM events WHERE {}", condition)).unwrap() else {
+        unreachable!("a SELECT parses as one");
+    };
+    let (table, partitions) = db.snapshot_for("events", &filter).unwrap();
+    (partitions.unwrap(), table.row_count())
+}
+
+#[test]
+fn rows_go_to_their_partition_and_read_as_one_table() {
+    let dir = TempDir::new();
+    {
+        let mut db = Database::open_dir(dir.path()).unwrap();
+        events(&mut db);
+        assert!(matches!(
+            db.log(WalOp::Insert { table: "events".to_string(), row: vec![int(5), int(2030)] }),
+            Err(DbError::NoPartition { .. })
+        ));
+        db.checkpoint().unwrap();
+    }
+    assert!(dir.path().join("events#p2023.json").exists() && dir.path().join("events#p2024.json").exists());
+
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    recovery::recover(&mut db).unwrap();
+    assert_eq!(rows(&mut db, "events").len(), 4);
+    let ids = db.query("SELECT id FROM events WHERE created = 2023 ORDER BY id").unwrap().rows;
+    assert_eq!(ids, vec![vec![int(1)], vec![int(3)]]);
+}
+
+#[test]
+fn a_filter_on_the_partition_column_reads_only_the_partitions_that_can_match() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    events(&mut db);
+
+    assert_eq!(partitions(&mut db, "created = 2024"), (vec!["p2024".to_string()], 2));
+    assert_eq!(partitions(&mut db, "created < 2024"), (vec!["p2023".to_string()], 2));
+    assert_eq!(partitions(&mut db, "created >= 2023 AND id = 1"), (vec!["p2023".to_string(), "p2024".to_string()], 4));
+    assert_eq!(partitions(&mut db, "created = 2030"), (Vec::new(), 0));
+}
+
+#[test]
+fn dropping_a_partition_deletes_its_file_and_rows() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    events(&mut db);
+    db.checkpoint().unwrap();
+
+    db.drop_partition("events", "p2023").unwrap();
+    assert!(!dir.path().join("events#p2023.json").exists());
+    assert_eq!(rows(&mut db, "events"), vec![vec![int(2), int(2024)], vec![int(4), int(2024)]]);
+    assert!(matches!(db.drop_partition("events", "p2023"), Err(DbError::PartitionNotFound { .. })));
+
+    // Values it held now go to the partition above it, and a new one takes later years
+    insert(&mut db, "events", vec![int(5), int(2022)]);
+    db.add_partition("events", "p2025", Some("2026".to_string())).unwrap();
+    insert(&mut db, "events", vec![int(6), int(2025)]);
+    assert_eq!(partitions(&mut db, "created = 2022").0, ["p2024"]);
+    assert_eq!(db.query("SELECT id FROM events WHERE created > 2024").unwrap().rows, vec![vec![int(6)]]);
+}
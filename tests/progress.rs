@@ -0,0 +1,42 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use rust_db::csv::CsvOptions;
+use rust_db::formats::Format;
+use rust_db::index::{IndexDef, IndexKind};
+use rust_db::progress;
+use rust_db::Database;
+
+use common::create_table;
+
+#[test]
+fn an_operation_running_over_a_second_is_reported_as_it_goes_and_when_it_ends() {
+    let mut db = Database::open_in_memory();
+    create_table(&mut db, "t", &[("id", "int"), ("s", "string")]);
+    // Words enough to make indexing each row slow
+    let words: String = (0..100).map(|word| format!("word{} ", word)).collect();
+    let rows: String = (0..20_000).map(|id| format!("{},{}\n", id, words)).collect();
+    db.load_rows("t", &rows, &Format::Csv(CsvOptions { header: false, delimiter: ',' })).unwrap();
+
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    {
+        let reports = Arc::clone(&reports);
+        progress::set_reporter(Some(Box::new(move |progress| {
+            reports.lock().unwrap().push((progress.operation.to_string(), progress.done, progress.total, progress.unit, progress.finished));
+        })));
+    }
+    let def = IndexDef { name: "by_s".to_string(), columns: vec!["s".to_string()], kind: IndexKind::FullText, unique: false };
+    db.create_index("t", def).unwrap();
+    // A quick one is not reported at all
+    let def = IndexDef { name: "by_id".to_string(), columns: vec!["id".to_string()], kind: IndexKind::Hash, unique: false };
+    db.create_index("t", def).unwrap();
+    progress::set_reporter(None);
+
+    let reports = reports.lock().unwrap();
+    assert!(reports.iter().all(|report| report.0 == "Building index 'by_s'" && report.2 == Some(20_000) && report.3 == "rows"), "{:?}", reports);
+    // Counting up to the end, which is reported once
+    assert!(reports.len() >= 2 && reports.windows(2).all(|pair| pair[0].1 < pair[1].1), "{:?}", reports);
+    assert_eq!(reports.iter().filter(|report| report.4).count(), 1);
+    assert_eq!((reports.last().unwrap().1, reports.last().unwrap().4), (20_000, true));
+}
@@ -0,0 +1,88 @@
+//! The interactive prompt, which only runs on a terminal: each session is
+//! given one through util-linux `script`, and typed into a key at a time.
+
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use common::{cli, TempDir};
+
+// Types each of `keys` in turn at a prompt on database `data`, with `home`
+// as the home directory. Returns what the terminal showed, escapes included
+fn session(home: &Path, data: &str, keys: &[&str]) -> String {
+    let client = format!("{} --data-dir {}", env!("CARGO_BIN_EXE_rust_db"), data);
+    let mut child = Command::new("script").args(["-qec", &client, "/dev/null"])
+        .current_dir(home).env("HOME", home).env("TERM", "xterm").env_remove("RUSTDB_DATA_DIR")
+        .stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    for keys in keys {
+        // Given time to answer, so each line is read on its own
+        thread::sleep(Duration::from_millis(300));
+        stdin.write_all(keys.as_bytes()).unwrap();
+    }
+    thread::sleep(Duration::from_millis(300));
+    stdin.write_all(b"EXIT\r").unwrap();
+    drop(stdin);
+    String::from_utf8_lossy(&child.wait_with_output().unwrap().stdout).into_owned()
+}
+
+const UP: &str = "\x1b[A";
+
+#[test]
+fn lines_entered_are_recalled_in_later_sessions() {
+    let home = TempDir::new();
+    let shown = session(home.path(), "data", &["CREATE TABLE u id:int\r", "INSERT INTO u VALUES (1)\r"]);
+    assert!(shown.contains("Table 'u' created"), "{}", shown);
+    let history = std::fs::read_to_string(home.path().join(".rustdb_history")).unwrap();
+    assert!(history.contains("CREATE TABLE u id:int\nINSERT INTO u VALUES (1)\n"), "{}", history);
+
+    // Up twice reaches the insert, as EXIT was the last line
+    let shown = session(home.path(), "data", &[UP, UP, "\r", "SELECT COUNT(*) FROM u\r"]);
+    assert!(shown.contains("1 row inserted"), "{}", shown);
+    assert!(shown.contains("|        2"), "{}", shown);
+}
+
+#[test]
+fn tab_completes_keywords_tables_and_columns() {
+    let home = TempDir::new();
+    session(home.path(), "data", &["CREATE TABLE users id:int age:int\r", "INSERT INTO users VALUES (7, 20)\r"]);
+    // A keyword keeps the case it is typed in
+    let shown = session(home.path(), "data", &["sel\t", " * FR\t", " use\t", " WHERE ag\t", " = 20\r"]);
+    let history = std::fs::read_to_string(home.path().join(".rustdb_history")).unwrap();
+    assert!(history.contains("\nselect * FROM users WHERE age = 20\n"), "{}", history);
+    assert!(shown.contains("|  7"), "{}", shown);
+}
+
+#[test]
+fn ctrl_c_cancels_the_running_statement_and_returns_to_the_prompt() {
+    let home = TempDir::new();
+    let rows: String = (0..1000).map(|id| format!("{}\n", id)).collect();
+    let mut load = cli(home.path()).args(["-c", "CREATE TABLE t id:int; COPY t FROM STDIN CSV NO HEADER"]).stdin(Stdio::piped()).stdout(Stdio::null()).spawn().unwrap();
+    load.stdin.take().unwrap().write_all(rows.as_bytes()).unwrap();
+    assert!(load.wait().unwrap().success());
+
+    // A billion combinations of rows, which would take minutes
+    let shown = session(home.path(), "data", &["SELECT COUNT(*) FROM t a, t b, t c WHERE a.id < b.id\r", "\x03", "SELECT COUNT(*) FROM t\r"]);
+    assert!(shown.contains("Statement cancelled"), "{}", shown);
+    assert!(shown.contains("|     1000"), "{}", shown);
+}
+
+#[test]
+fn the_prompt_shows_an_open_transaction_and_exit_warns_before_discarding_it() {
+    let home = TempDir::new();
+    session(home.path(), "data", &["CREATE TABLE t id:int\r"]);
+    // Without autocommit the insert begins a transaction, and the first EXIT stays
+    let shown = session(home.path(), "data", &["SET autocommit = off\r", "INSERT INTO t VALUES (1)\r", "EXIT\r"]);
+    assert!(shown.contains("1 row inserted\r\n\x1b[?2004h\r\x1b[Kdbms*> "), "{}", shown);
+    assert!(shown.contains("Warning: The open transaction has 1 uncommitted change(s)"), "{}", shown);
+    assert!(shown.contains("Transaction rolled back (1 change(s) discarded)"), "{}", shown);
+
+    let shown = session(home.path(), "data", &["SELECT COUNT(*) FROM t\r"]);
+    assert!(shown.contains("|        0"), "{}", shown);
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use std::fs;
+
+use rust_db::database::Limits;
+use rust_db::wal::WalOp;
+use rust_db::{Database, DbError};
+
+use common::{cli, create_table, insert, int, rows, string, TempDir};
+
+// Table `t` holding one row, in a database capped just above its size
+fn full_database(dir: &TempDir) -> Database {
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    create_table(&mut db, "t", &[("id", "int"), ("name", "string")]);
+    insert(&mut db, "t", vec![int(1), string("a")]);
+    let size = db.database_size().unwrap();
+    db.set_limits(Limits { max_database_bytes: Some(size + 16), ..db.limits() });
+    db
+}
+
+fn update(db: &mut Database, name: &str) -> Result<(), DbError> {
+    db.log(WalOp::Update { table: "t".to_string(), row: 0, values: vec![("name".to_string(), string(name))] })
+}
+
+#[test]
+fn an_update_that_grows_a_row_past_the_size_limit_fails() {
+    let dir = TempDir::new();
+    let mut db = full_database(&dir);
+
+    assert!(matches!(update(&mut db, &"x".repeat(100)), Err(DbError::QuotaExceeded(_))));
+    assert_eq!(rows(&mut db, "t"), vec![vec![int(1), string("a")]]);
+}
+
+#[test]
+fn an_update_that_does_not_grow_a_row_runs_at_the_size_limit() {
+    let dir = TempDir::new();
+    let mut db = full_database(&dir);
+    db.set_limits(Limits { max_database_bytes: Some(1), ..db.limits() });
+
+    update(&mut db, "b").unwrap();
+    assert_eq!(rows(&mut db, "t"), vec![vec![int(1), string("b")]]);
+}
+
+#[test]
+fn a_table_past_its_row_limit_can_still_be_updated() {
+    let dir = TempDir::new();
+    let mut db = full_database(&dir);
+    db.set_limits(Limits { max_database_bytes: None, ..db.limits() });
+    insert(&mut db, "t", vec![int(2), string("b")]);
+    db.set_max_rows("t", Some(1)).unwrap();
+
+    update(&mut db, "c").unwrap();
+    assert!(matches!(db.log(WalOp::Insert { table: "t".to_string(), row: vec![int(3), string("d")] }), Err(DbError::QuotaExceeded(_))));
+}
+
+#[test]
+fn an_insert_past_the_size_limit_fails_and_a_delete_still_runs() {
+    let dir = TempDir::new();
+    let mut db = full_database(&dir);
+
+    let row = vec![int(2), string(&"x".repeat(100))];
+    assert!(matches!(db.log(WalOp::Insert { table: "t".to_string(), row: row.clone() }), Err(DbError::QuotaExceeded(_))));
+    db.log(WalOp::Delete { table: "t".to_string(), index: 0 }).unwrap();
+    assert_eq!(rows(&mut db, "t"), Vec::<Vec<_>>::new());
+}
+
+#[test]
+fn an_import_past_a_limit_adds_nothing() {
+    let dir = TempDir::new();
+    let lines: String = (0..20_000).map(|id| format!("{},{}\n", id, "x".repeat(80))).collect();
+    fs::write(dir.path().join("big.csv"), format!("id,s\n{}", lines)).unwrap();
+    fs::write(dir.path().join("two.csv"), "id,s\n5,a\n6,b\n").unwrap();
+    fs::write(dir.path().join("rustdb.toml"), "[quota]\nmax_database_mb = 1\n").unwrap();
+    let output = cli(dir.path()).args(["--continue-on-error", "-c", "CREATE TABLE t id:int s:string; IMPORT CSV 'big.csv' INTO t; \
+        INSERT INTO t VALUES (1, 'a'); ALTER TABLE t SET MAX ROWS 2; IMPORT CSV 'two.csv' INTO t; \
+        ALTER TABLE t SET MAX ROWS OFF; IMPORT CSV 'two.csv' INTO t; SELECT COUNT(*) FROM t"]).output().unwrap();
+    let output = String::from_utf8(output.stdout).unwrap() + &String::from_utf8(output.stderr).unwrap();
+    assert!(output.contains("[E5005] Quota exceeded: the database takes "), "{}", output);
+    assert!(output.contains("[E5005] Quota exceeded: table 't' holds 1 row(s), and may hold 2 (MAX ROWS)"), "{}", output);
+    assert!(output.contains("Table 't' may hold any number of rows\nImported 2 row(s) into 't'\nCOUNT(*)\n3\n"), "{}", output);
+}
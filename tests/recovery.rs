@@ -0,0 +1,142 @@
+mod common;
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+
+use rust_db::index::{IndexDef, IndexKind};
+use rust_db::recovery;
+use rust_db::Database;
+
+use common::{create_table, insert, int, rows, string, TempDir};
+
+// A database in `dir` with table `t` saved and two rows in it logged but
+// not checkpointed, dropped as a killed process would leave it
+fn crash_with_two_rows(dir: &TempDir) {
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    create_table(&mut db, "t", &[("id", "int"), ("name", "string")]);
+    db.checkpoint().unwrap();
+    insert(&mut db, "t", vec![int(1), string("a")]);
+    insert(&mut db, "t", vec![int(2), string("b")]);
+}
+
+#[test]
+fn replays_the_log_after_a_crash() {
+    let dir = TempDir::new();
+    crash_with_two_rows(&dir);
+
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    let report = recovery::recover(&mut db).unwrap();
+    assert_eq!(report, vec!["Replayed 2 WAL record(s) into table files".to_string()]);
+    assert_eq!(rows(&mut db, "t"), vec![vec![int(1), string("a")], vec![int(2), string("b")]]);
+    assert_eq!(db.read_table_file("t").unwrap().row_count(), 2);
+}
+
+#[test]
+fn cuts_off_a_torn_record_at_the_end_of_the_log() {
+    let dir = TempDir::new();
+    crash_with_two_rows(&dir);
+    let torn = r#"{"lsn":9,"op":{"Insert":{"table":"t","#;
+    OpenOptions::new().append(true).open(dir.path().join("wal.log")).unwrap().write_all(torn.as_bytes()).unwrap();
+
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    let report = recovery::recover(&mut db).unwrap();
+    assert_eq!(report[0], format!("Discarded {} byte(s) of incomplete WAL record", torn.len()));
+    assert_eq!(rows(&mut db, "t").len(), 2);
+}
+
+#[test]
+fn keeps_rows_logged_after_a_torn_record() {
+    let dir = TempDir::new();
+    crash_with_two_rows(&dir);
+    let torn = r#"{"lsn":9,"op":{"Insert":{"table":"t","#;
+    OpenOptions::new().append(true).open(dir.path().join("wal.log")).unwrap().write_all(torn.as_bytes()).unwrap();
+    {
+        // Opened and written without recovering first, as a library user may
+        let mut db = Database::open_dir(dir.path()).unwrap();
+        insert(&mut db, "t", vec![int(3), string("c")]);
+    }
+
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    recovery::recover(&mut db).unwrap();
+    assert_eq!(rows(&mut db, "t"), vec![
+        vec![int(1), string("a")],
+        vec![int(2), string("b")],
+        vec![int(3), string("c")],
+    ]);
+}
+
+#[test]
+fn replays_a_committed_transaction_but_not_an_open_one() {
+    let dir = TempDir::new();
+    {
+        let mut db = Database::open_dir(dir.path()).unwrap();
+        create_table(&mut db, "t", &[("id", "int")]);
+        db.begin().unwrap();
+        insert(&mut db, "t", vec![int(1)]);
+        insert(&mut db, "t", vec![int(2)]);
+        db.commit().unwrap();
+        db.begin().unwrap();
+        insert(&mut db, "t", vec![int(3)]);
+    }
+
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    recovery::recover(&mut db).unwrap();
+    assert_eq!(rows(&mut db, "t"), vec![vec![int(1)], vec![int(2)]]);
+}
+
+#[test]
+fn quarantines_an_unreadable_table_and_keeps_its_records() {
+    let dir = TempDir::new();
+    {
+        let mut db = Database::open_dir(dir.path()).unwrap();
+        create_table(&mut db, "t", &[("id", "int"), ("name", "string")]);
+        create_table(&mut db, "u", &[("id", "int")]);
+        let index = IndexDef { name: "t_name".to_string(), columns: vec!["name".to_string()], kind: IndexKind::default(), unique: false };
+        db.create_index("t", index).unwrap();
+        db.checkpoint().unwrap();
+        insert(&mut db, "t", vec![int(1), string("a")]);
+        insert(&mut db, "t", vec![int(2), string("b")]);
+        insert(&mut db, "u", vec![int(5)]);
+    }
+    fs::write(dir.path().join("t.json"), "not a table").unwrap();
+
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    let report = recovery::recover(&mut db).unwrap();
+    assert!(report[0].starts_with("Quarantined unreadable table 't' as .quarantine/t.json"), "{:?}", report);
+    assert_eq!(report[1..], [
+        "Kept 2 WAL record(s) of 't' in .quarantine/t.wal".to_string(),
+        "Replayed 1 WAL record(s) into table files".to_string(),
+    ]);
+
+    let quarantine = dir.path().join(".quarantine");
+    assert!(quarantine.join("t.json").exists());
+    assert!(quarantine.join("t.idx").exists());
+    assert!(!dir.path().join("t.idx").exists());
+    let kept = fs::read_to_string(quarantine.join("t.wal")).unwrap();
+    assert_eq!(kept.lines().count(), 2);
+    assert!(kept.contains(r#"{"String":"a"}"#) && kept.contains(r#"{"String":"b"}"#));
+
+    assert!(!db.table_exists("t"));
+    assert_eq!(rows(&mut db, "u"), vec![vec![int(5)]]);
+}
+
+#[test]
+fn a_save_cut_short_before_its_rename_leaves_the_last_whole_file() {
+    let dir = TempDir::new();
+    {
+        let mut db = Database::open_dir(dir.path()).unwrap();
+        create_table(&mut db, "t", &[("id", "int"), ("name", "string")]);
+        insert(&mut db, "t", vec![int(1), string("a")]);
+        db.checkpoint().unwrap();
+    }
+    let names: Vec<_> = fs::read_dir(dir.path()).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    assert!(names.iter().all(|name| !name.to_string_lossy().ends_with(".tmp")), "{:?}", names);
+    // What a crash partway through writing the next version leaves
+    fs::write(dir.path().join("t.json.tmp"), "half a tab").unwrap();
+
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    let report = recovery::recover(&mut db).unwrap();
+    assert!(report[0].starts_with("Discarded torn write") && report[0].ends_with("t.json.tmp"), "{:?}", report);
+    assert!(!dir.path().join("t.json.tmp").exists());
+    assert_eq!(rows(&mut db, "t"), vec![vec![int(1), string("a")]]);
+}
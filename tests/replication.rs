@@ -0,0 +1,48 @@
+mod common;
+
+use rust_db::Database;
+
+use common::{create_table, insert, int, rows, string};
+
+#[test]
+fn a_follower_reads_what_the_leader_wrote_before_and_after_it_joined() {
+    let mut leader = Database::open_in_memory();
+    create_table(&mut leader, "t", &[("id", "int"), ("name", "string")]);
+    insert(&mut leader, "t", vec![int(1), string("a")]);
+
+    let (base, changes) = leader.replicate().unwrap();
+    let mut follower = Database::open_in_memory();
+    for change in base {
+        follower.apply_change(change).unwrap();
+    }
+    assert_eq!(rows(&mut follower, "t"), vec![vec![int(1), string("a")]]);
+
+    insert(&mut leader, "t", vec![int(2), string("b")]);
+    leader.checkpoint().unwrap();
+    insert(&mut leader, "t", vec![int(3), string("c")]);
+    for change in changes.try_iter() {
+        follower.apply_change(change).unwrap();
+    }
+    assert_eq!(rows(&mut follower, "t"), rows(&mut leader, "t"));
+    assert_eq!(follower.last_lsn(), leader.last_lsn());
+}
+
+#[test]
+fn a_follower_gets_a_transaction_only_once_it_commits() {
+    let mut leader = Database::open_in_memory();
+    create_table(&mut leader, "t", &[("id", "int")]);
+    let (base, changes) = leader.replicate().unwrap();
+    let mut follower = Database::open_in_memory();
+    for change in base {
+        follower.apply_change(change).unwrap();
+    }
+
+    leader.begin().unwrap();
+    insert(&mut leader, "t", vec![int(1)]);
+    assert_eq!(changes.try_iter().count(), 0);
+    leader.commit().unwrap();
+    for change in changes.try_iter() {
+        follower.apply_change(change).unwrap();
+    }
+    assert_eq!(rows(&mut follower, "t"), vec![vec![int(1)]]);
+}
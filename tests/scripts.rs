@@ -0,0 +1,92 @@
+mod common;
+
+use std::fs;
+use std::io::Write;
+use std::process::{Output, Stdio};
+
+use common::{cli, TempDir};
+
+// Runs `script` with the command-line client against an in-memory database
+fn run(script: &str) -> Output {
+    let dir = TempDir::new();
+    cli(dir.path()).args(["--memory", "--format", "csv", "-c", script]).output().unwrap()
+}
+
+#[test]
+fn every_syntax_error_in_a_script_is_reported_and_nothing_runs() {
+    let output = run("SELEC 1;\nCREATE TABLE t id:int;\nSELECT * FROM t WHERE;\nINSERT INTO t VALUES (1)");
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    let errors = String::from_utf8(output.stderr).unwrap();
+    let lines: Vec<&str> = errors.lines().collect();
+    assert_eq!(lines.len(), 3, "{}", errors);
+    assert!(lines[0].contains("Line 1: [E1001] Syntax error at byte 0"));
+    assert!(lines[1].contains("Line 3: [E1001] Syntax error at byte 22"));
+}
+
+#[test]
+fn a_script_that_parses_runs() {
+    let output = run("CREATE TABLE t id:int;\nINSERT INTO t VALUES (1);\nSELECT id FROM t");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8(output.stdout).unwrap().ends_with("id\n1\n"));
+}
+
+#[test]
+fn a_script_file_stops_at_its_first_error_naming_the_line() {
+    let dir = TempDir::new();
+    fs::write(dir.path().join("seed.sql"), "-- Seed\nCREATE TABLE t id:int PRIMARY KEY;\nINSERT INTO t VALUES (1);\n\nINSERT INTO t VALUES (1);\nINSERT INTO t VALUES (2)\n").unwrap();
+    let output = cli(dir.path()).args(["--file", "seed.sql"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let errors = String::from_utf8(output.stderr).unwrap();
+    assert!(errors.contains("seed.sql, line 5: [E3001]"), "{}", errors);
+    assert!(errors.contains("Script 'seed.sql' stopped at line 5"), "{}", errors);
+
+    // The statements before the error stay applied
+    let output = cli(dir.path()).args(["-c", "SELECT id FROM t"]).output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "id\n1\n");
+}
+
+// Pipes `input` to the client in `dir`, carrying on past errors. Returns
+// what it printed, and its errors
+fn piped(dir: &TempDir, input: &str) -> (String, String) {
+    let mut child = cli(dir.path()).arg("--continue-on-error")
+        .stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    (String::from_utf8(output.stdout).unwrap(), String::from_utf8(output.stderr).unwrap())
+}
+
+#[test]
+fn a_sourced_script_can_carry_on_past_its_errors() {
+    let dir = TempDir::new();
+    fs::write(dir.path().join("rows.sql"), "INSERT INTO t VALUES (2);\nINSERT INTO t VALUES (1);\nINSERT INTO t VALUES (3)").unwrap();
+    let (stdout, errors) = piped(&dir, "CREATE TABLE t id:int PRIMARY KEY\nINSERT INTO t VALUES (1)\n\
+        SOURCE 'rows.sql' CONTINUE ON ERROR\nSELECT COUNT(*) FROM t\n");
+    assert!(errors.contains("rows.sql, line 2: [E3001]"), "{}", errors);
+    assert!(stdout.ends_with("COUNT(*)\n3\n"), "{}", stdout);
+}
+
+#[test]
+fn a_script_run_in_a_single_transaction_is_applied_whole_or_not_at_all() {
+    let dir = TempDir::new();
+    fs::write(dir.path().join("rows.sql"), "INSERT INTO t VALUES (2);\nINSERT INTO t VALUES (1);\nINSERT INTO t VALUES (3)").unwrap();
+    fs::write(dir.path().join("ddl.sql"), "CREATE TABLE u id:int;\nINSERT INTO t VALUES (4)").unwrap();
+    let (stdout, errors) = piped(&dir, "CREATE TABLE t id:int PRIMARY KEY\nINSERT INTO t VALUES (1)\n\
+        SOURCE 'rows.sql' SINGLE TRANSACTION\nSELECT COUNT(*) FROM t\nSOURCE 'ddl.sql' SINGLE TRANSACTION\nSELECT COUNT(*) FROM t\n");
+    assert!(errors.contains("Script 'rows.sql' stopped at line 2 and was rolled back (1 change(s) discarded)"), "{}", errors);
+    // A statement that cannot run in a transaction is found before anything runs
+    assert!(errors.contains("ddl.sql, line 1: [E3016]"), "{}", errors);
+    assert!(errors.contains("Script 'ddl.sql' was not run"), "{}", errors);
+    assert!(stdout.ends_with("COUNT(*)\n1\nCOUNT(*)\n1\n"), "{}", stdout);
+
+    // The flag does the same for the statements of -c and --file
+    let output = cli(dir.path()).args(["--single-transaction", "-c", "INSERT INTO t VALUES (5); INSERT INTO t VALUES (1)"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let output = cli(dir.path()).args(["--single-transaction", "--file", "rows.sql"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    fs::write(dir.path().join("more.sql"), "INSERT INTO t VALUES (6);\nINSERT INTO t VALUES (7)").unwrap();
+    let output = cli(dir.path()).args(["--single-transaction", "--file", "more.sql"]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let output = cli(dir.path()).args(["-c", "SELECT id FROM t ORDER BY id"]).output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "id\n1\n6\n7\n");
+}
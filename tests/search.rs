@@ -0,0 +1,58 @@
+mod common;
+
+use rust_db::index::{IndexDef, IndexKind};
+use rust_db::{DataType, Database};
+
+use common::{create_table, insert, int, string, TempDir};
+
+fn docs(db: &mut Database) {
+    create_table(db, "docs", &[("id", "int"), ("body", "string")]);
+    insert(db, "docs", vec![int(1), string("Rust is a systems language")]);
+    insert(db, "docs", vec![int(2), string("A database written in Rust, in Rust, for Rust users")]);
+    insert(db, "docs", vec![int(3), string("A database of recipes")]);
+    insert(db, "docs", vec![int(4), string("Nothing to see here")]);
+}
+
+fn ids(db: &mut Database, sql: &str) -> Vec<i32> {
+    db.query(sql).unwrap().rows.into_iter().map(|row| match row[0] {
+        DataType::Integer32(id) => id,
+        ref other => panic!("not an id: {:?}", other),
+    }).collect()
+}
+
+#[test]
+fn match_finds_rows_with_every_word_most_relevant_first() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    docs(&mut db);
+
+    let without = ids(&mut db, "SELECT id FROM docs WHERE body MATCH 'rust'");
+    let def = IndexDef { name: "idx_body".to_string(), columns: vec!["body".to_string()], kind: IndexKind::FullText, unique: false };
+    db.create_index("docs", def).unwrap();
+    let with = ids(&mut db, "SELECT id FROM docs WHERE body MATCH 'rust'");
+    assert_eq!(with, without);
+    assert_eq!(with, [2, 1]);
+
+    assert_eq!(ids(&mut db, "SELECT id FROM docs WHERE body MATCH 'DATABASE rust'"), [2]);
+    assert_eq!(ids(&mut db, "SELECT id FROM docs WHERE body MATCH 'database' ORDER BY id"), [2, 3]);
+    assert!(ids(&mut db, "SELECT id FROM docs WHERE body MATCH 'python'").is_empty());
+
+    // The index follows the rows
+    insert(&mut db, "docs", vec![int(5), string("rust everywhere")]);
+    assert_eq!(ids(&mut db, "SELECT id FROM docs WHERE body MATCH 'everywhere'"), [5]);
+}
+
+#[test]
+fn a_fulltext_index_takes_one_string_column() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    docs(&mut db);
+    let def = |columns: &[&str]| IndexDef {
+        name: "idx".to_string(),
+        columns: columns.iter().map(|col| col.to_string()).collect(),
+        kind: IndexKind::FullText,
+        unique: false,
+    };
+    assert!(db.create_index("docs", def(&["id"])).is_err());
+    assert!(db.create_index("docs", def(&["body", "id"])).is_err());
+}
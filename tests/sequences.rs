@@ -0,0 +1,40 @@
+mod common;
+
+use std::path::Path;
+
+use common::{cli, TempDir};
+
+// Runs `script` with the client in `dir`, carrying on past errors. Returns what it printed
+fn run(dir: &Path, script: &str) -> String {
+    let output = cli(dir).args(["--continue-on-error", "-c", script]).output().unwrap();
+    String::from_utf8(output.stdout).unwrap() + &String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn tables_taking_their_ids_from_one_sequence_share_its_values() {
+    let dir = TempDir::new();
+    let output = run(dir.path(), "CREATE SEQUENCE ids START 100; CREATE TABLE a id:int DEFAULT NEXTVAL('ids') name:string; \
+        CREATE TABLE b id:int DEFAULT NEXTVAL('ids') n:int; INSERT INTO a VALUES ('x'); INSERT INTO b VALUES (5); \
+        INSERT INTO b VALUES (6); SELECT * FROM a; SELECT * FROM b");
+    assert!(output.ends_with("id,name\n100,x\nid,n\n101,5\n102,6\n"), "{}", output);
+
+    // A value drawn is gone, even if what drew it is rolled back
+    let output = run(dir.path(), "SELECT SETVAL('ids', 500); BEGIN; INSERT INTO a VALUES ('y'); ROLLBACK; INSERT INTO b VALUES (7); \
+        SELECT id FROM b WHERE n = 7; SHOW SEQUENCES; SELECT NEXTVAL('nope')");
+    assert!(output.starts_with("setval\n500\n"), "{}", output);
+    assert!(output.contains("1 row inserted\nid\n502\nSequence,Next Value,Increment\nids,503,1\n"), "{}", output);
+    assert!(output.contains("[E2024] Sequence 'nope' does not exist"), "{}", output);
+
+    let output = run(dir.path(), "DROP SEQUENCE ids; DROP SEQUENCE ids CASCADE; INSERT INTO a VALUES (9, 'w'); SELECT id FROM a WHERE name = 'w'");
+    assert!(output.contains("[E3014] Cannot drop 'ids' as the DEFAULT of 'a.id', the DEFAULT of 'b.id' depend(s) on it"), "{}", output);
+    assert!(output.contains("Sequence 'ids' dropped\n1 row inserted\nid\n9\n"), "{}", output);
+}
+
+#[test]
+fn a_sequence_counts_by_its_increment_until_it_runs_out_of_ints() {
+    let dir = TempDir::new();
+    let output = run(dir.path(), "CREATE SEQUENCE down START WITH 3 INCREMENT BY -2; SELECT NEXTVAL('down'); SELECT NEXTVAL('down'); \
+        CREATE SEQUENCE big START 2147483647; SELECT NEXTVAL('big'); SELECT NEXTVAL('big')");
+    assert!(output.contains("nextval\n3\nnextval\n1\n"), "{}", output);
+    assert!(output.contains("nextval\n2147483647\nError: Line 1: [E3015] Sequence 'big' has no int values left"), "{}", output);
+}
@@ -0,0 +1,437 @@
+mod common;
+
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::process::{Child, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use rust_db::protocol::{self, Frame};
+
+use common::{cli, TempDir};
+
+// A port nothing listens on, as far as can be told
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+// `rust_db serve` on ports of its own, stopped when dropped
+struct Server {
+    child: Child,
+    port: u16,
+}
+
+impl Server {
+    fn start(dir: &Path, args: &[&str]) -> Server {
+        let port = free_port();
+        let child = cli(dir)
+            .args(["serve", "--host", "127.0.0.1", "--port", &port.to_string()])
+            .args(args)
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+        let server = Server { child, port };
+        server.wait_for(port);
+        server
+    }
+
+    // Waits for the server to listen on `port`
+    fn wait_for(&self, port: u16) {
+        let started = Instant::now();
+        while TcpStream::connect(("127.0.0.1", port)).is_err() {
+            assert!(started.elapsed() < Duration::from_secs(10), "the server never listened on {}", port);
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    fn connect(&self) -> Connection {
+        let stream = TcpStream::connect(("127.0.0.1", self.port)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(30))).unwrap();
+        Connection { reader: BufReader::new(stream.try_clone().unwrap()), writer: BufWriter::new(stream) }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+}
+
+impl Connection {
+    // Sends `frame` and gathers the answer: what was output, and the errors
+    fn send(&mut self, frame: Frame) -> (String, Vec<String>) {
+        protocol::write_frame(&mut self.writer, &frame).unwrap();
+        self.writer.flush().unwrap();
+        let mut output = String::new();
+        let mut errors = Vec::new();
+        loop {
+            match protocol::read_frame(&mut self.reader).unwrap().expect("the server answers") {
+                Frame::Output(text) => output.push_str(&text),
+                Frame::Error(text) => errors.push(text),
+                Frame::Done => return (output, errors),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+    }
+
+    fn query(&mut self, sql: &str) -> (String, Vec<String>) {
+        self.send(Frame::Query(sql.to_string()))
+    }
+}
+
+// A PostgreSQL client speaking just enough of the protocol to run simple queries
+struct PgConnection {
+    stream: TcpStream,
+}
+
+// What a simple query answered
+#[derive(Debug, Default)]
+struct PgAnswer {
+    columns: Vec<String>,
+    rows: Vec<Vec<String>>,
+    tags: Vec<String>,
+    errors: Vec<(String, String)>, // SQLSTATE and message
+}
+
+impl PgConnection {
+    fn connect(port: u16) -> PgConnection {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let params = b"user\0tester\0database\0default\0\0";
+        stream.write_all(&(8 + params.len() as i32).to_be_bytes()).unwrap();
+        stream.write_all(&196608i32.to_be_bytes()).unwrap();
+        stream.write_all(params).unwrap();
+        let mut connection = PgConnection { stream };
+        connection.answer();
+        connection
+    }
+
+    fn query(&mut self, sql: &str) -> PgAnswer {
+        self.stream.write_all(b"Q").unwrap();
+        self.stream.write_all(&(4 + sql.len() as i32 + 1).to_be_bytes()).unwrap();
+        self.stream.write_all(sql.as_bytes()).unwrap();
+        self.stream.write_all(b"\0").unwrap();
+        self.answer()
+    }
+
+    // Reads messages up to ReadyForQuery
+    fn answer(&mut self) -> PgAnswer {
+        let mut answer = PgAnswer::default();
+        loop {
+            let mut head = [0u8; 5];
+            self.stream.read_exact(&mut head).unwrap();
+            let len = i32::from_be_bytes(head[1..].try_into().unwrap()) as usize;
+            let mut body = vec![0u8; len - 4];
+            self.stream.read_exact(&mut body).unwrap();
+            let strings = |bytes: &[u8]| bytes.split(|b| *b == 0).map(|s| String::from_utf8_lossy(s).to_string()).collect::<Vec<_>>();
+            match head[0] {
+                b'Z' => return answer,
+                b'C' => answer.tags.push(strings(&body).remove(0)),
+                b'T' => {
+                    let mut rest = &body[2..];
+                    for _ in 0..i16::from_be_bytes([body[0], body[1]]) {
+                        let end = rest.iter().position(|b| *b == 0).unwrap();
+                        answer.columns.push(String::from_utf8_lossy(&rest[..end]).to_string());
+                        rest = &rest[end + 1 + 18..];
+                    }
+                }
+                b'D' => {
+                    let mut rest = &body[2..];
+                    let mut row = Vec::new();
+                    for _ in 0..i16::from_be_bytes([body[0], body[1]]) {
+                        let len = i32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+                        row.push(String::from_utf8_lossy(&rest[4..4 + len]).to_string());
+                        rest = &rest[4 + len..];
+                    }
+                    answer.rows.push(row);
+                }
+                b'E' => {
+                    let fields = strings(&body);
+                    let field = |tag: char| fields.iter().find_map(|field| field.strip_prefix(tag)).unwrap_or_default().to_string();
+                    answer.errors.push((field('C'), field('M')));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+// Sends an HTTP request with `headers` and `body`, giving the status and the
+// JSON answered
+fn http(port: u16, request: &str, headers: &[&str], body: &str) -> (u16, Value) {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    let mut head = format!("{} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n", request, body.len());
+    for header in headers {
+        head.push_str(&format!("{}\r\n", header));
+    }
+    stream.write_all(format!("{}\r\n{}", head, body).as_bytes()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+    (status, serde_json::from_str(body).unwrap_or(Value::Null))
+}
+
+#[test]
+fn clients_share_the_database_a_server_holds() {
+    let dir = TempDir::new();
+    let server = Server::start(dir.path(), &["--memory"]);
+    let mut first = server.connect();
+    let mut second = server.connect();
+
+    assert_eq!(first.query("CREATE TABLE t id:int name:string").1, Vec::<String>::new());
+    first.query("INSERT INTO t VALUES (1, 'a')");
+    let (output, errors) = second.query("SELECT name FROM t WHERE id = 1");
+    assert!(errors.is_empty(), "{:?}", errors);
+    assert!(output.contains('a'), "{}", output);
+
+    let (_, errors) = second.query("SELECT * FROM missing");
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].contains("E2001"), "{}", errors[0]);
+    // The connection stays usable after an error
+    assert!(second.query("SELECT COUNT(*) FROM t").1.is_empty());
+}
+
+#[test]
+fn the_client_runs_statements_on_a_server() {
+    let dir = TempDir::new();
+    let server = Server::start(dir.path(), &["--memory"]);
+    let mut client = cli(dir.path())
+        .args(["connect", &format!("127.0.0.1:{}", server.port)])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    client.stdin.take().unwrap().write_all(b"CREATE TABLE t id:int\nINSERT INTO t VALUES (42)\nSELECT id FROM t\n").unwrap();
+    let output = client.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("Connected to 127.0.0.1:"), "{}", stdout);
+    assert!(stdout.contains("42"), "{}", stdout);
+    // What it wrote is the server's
+    assert!(server.connect().query("SELECT COUNT(*) FROM t").1.is_empty());
+
+    let refused = cli(dir.path()).args(["connect", &format!("127.0.0.1:{}", free_port())]).output().unwrap();
+    assert!(!refused.status.success());
+}
+
+#[test]
+fn postgres_clients_run_simple_queries() {
+    let dir = TempDir::new();
+    let pg_port = free_port();
+    let server = Server::start(dir.path(), &["--memory", "--pg-port", &pg_port.to_string()]);
+    server.wait_for(pg_port);
+    let mut pg = PgConnection::connect(pg_port);
+
+    let answer = pg.query("CREATE TABLE t id:int name:string; INSERT INTO t VALUES (1, 'a'); INSERT INTO t VALUES (2, 'b')");
+    assert!(answer.errors.is_empty(), "{:?}", answer);
+    assert_eq!(answer.tags[1..], ["INSERT 0 1", "INSERT 0 1"]);
+
+    let answer = pg.query("SELECT id, name FROM t ORDER BY id DESC");
+    assert_eq!(answer.columns, ["id", "name"]);
+    assert_eq!(answer.rows, [["2", "b"], ["1", "a"]]);
+    assert_eq!(answer.tags, ["SELECT 2"]);
+
+    // Drivers set parameters on connect, which are taken without complaint
+    assert_eq!(pg.query("SET extra_float_digits = 3").tags, ["SET"]);
+    let answer = pg.query("SELECT * FROM missing; SELECT 1");
+    assert_eq!(answer.errors.len(), 1);
+    assert_eq!(answer.errors[0].0, "42P01");
+    assert!(answer.tags.is_empty());
+    // And what the wire protocol client wrote, they see
+    server.connect().query("INSERT INTO t VALUES (3, 'c')");
+    assert_eq!(pg.query("SELECT COUNT(*) FROM t").rows, [["3"]]);
+}
+
+#[test]
+fn statements_posted_over_http_are_answered_in_json() {
+    let dir = TempDir::new();
+    let http_port = free_port();
+    let server = Server::start(dir.path(), &["--memory", "--http", &http_port.to_string()]);
+    server.wait_for(http_port);
+    let sql = ["Content-Type: application/sql"];
+
+    let (status, body) = http(http_port, "POST /query", &sql, "CREATE TABLE t id:int name:string; INSERT INTO t VALUES (1, 'a'); SELECT * FROM t");
+    assert_eq!(status, 200, "{}", body);
+    let results = body["results"].as_array().unwrap();
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[2]["columns"], json!(["id", "name"]));
+    assert_eq!(results[2]["rows"], json!([["1", "a"]]));
+
+    let (status, body) = http(http_port, "POST /query", &sql, "SELECT * FROM t; SELECT * FRM t; SELECT 1");
+    assert_eq!(status, 400);
+    let results = body["results"].as_array().unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!((&results[1]["code"], &results[1]["offset"], &results[1]["token"]), (&json!("E1001"), &json!(9), &json!("FRM")));
+
+    let (status, body) = http(http_port, "GET /tables", &[], "");
+    assert_eq!(status, 200);
+    assert!(body.to_string().contains("\"t\""), "{}", body);
+    // A form on another site could send this, so it is refused
+    assert_eq!(http(http_port, "POST /query", &["Content-Type: text/plain"], "DELETE FROM t").0, 415);
+    assert_eq!(http(http_port, "GET /elsewhere", &[], "").0, 404);
+    assert_eq!(server.connect().query("SELECT COUNT(*) FROM t").1, Vec::<String>::new());
+}
+
+#[test]
+fn once_there_are_users_a_client_has_to_log_in() {
+    let dir = TempDir::new();
+    let server = Server::start(dir.path(), &["--memory"]);
+    let mut open = server.connect();
+    assert!(open.query("CREATE USER admin PASSWORD 'secret' SUPERUSER").1.is_empty());
+
+    let mut anonymous = server.connect();
+    let (_, errors) = anonymous.query("SHOW TABLES");
+    assert!(errors[0].starts_with("Authentication required"), "{:?}", errors);
+
+    let mut wrong = server.connect();
+    let (_, errors) = wrong.send(Frame::Login { user: "admin".to_string(), password: "guess".to_string() });
+    assert_eq!(errors, ["Authentication failed"]);
+
+    let mut admin = server.connect();
+    assert!(admin.send(Frame::Login { user: "admin".to_string(), password: "secret".to_string() }).1.is_empty());
+    assert!(admin.query("CREATE TABLE t id:int").1.is_empty());
+    // A connection made before there were users stays as it was
+    assert!(open.query("SELECT * FROM t").1.is_empty());
+}
+
+#[test]
+fn processlist_shows_the_sessions_and_kill_closes_one() {
+    let dir = TempDir::new();
+    let server = Server::start(dir.path(), &["--memory"]);
+    assert!(server.connect().query("CREATE USER admin PASSWORD 'secret' SUPERUSER").1.is_empty());
+    let login = |user: &str, password: &str| {
+        let mut connection = server.connect();
+        assert!(connection.send(Frame::Login { user: user.to_string(), password: password.to_string() }).1.is_empty());
+        connection
+    };
+    let mut admin = login("admin", "secret");
+    assert!(admin.query("CREATE USER alice PASSWORD 'pw'").1.is_empty());
+    let mut alice = login("alice", "pw");
+
+    // The id of the session of `user` in a listing
+    let id = |output: &str, user: &str| {
+        let line = output.lines().find(|line| line.contains(&format!("| {} ", user))).unwrap();
+        line.split('|').nth(1).unwrap().trim().to_string()
+    };
+    let (output, _) = admin.query("SHOW PROCESSLIST");
+    assert!(output.contains("| admin | native") && output.contains("| alice | native"), "{}", output);
+    let admin_id = id(&output, "admin");
+    // Other users only see their own sessions, and may only kill those
+    let (output, _) = alice.query("SHOW PROCESSLIST");
+    assert!(!output.contains("admin") && output.contains("| alice | native"), "{}", output);
+    let alice_id = id(&output, "alice");
+    assert!(alice.query(&format!("KILL {}", admin_id)).1[0].contains("belongs to another user"));
+    assert_eq!(alice.query("KILL 99").1, ["[E2018] Session 99 does not exist"]);
+
+    assert_eq!(admin.query(&format!("KILL {}", alice_id)), (format!("Session {} killed", alice_id), Vec::new()));
+    let _ = protocol::write_frame(&mut alice.writer, &Frame::Query("SELECT 1".to_string())).and_then(|_| alice.writer.flush());
+    assert!(matches!(protocol::read_frame(&mut alice.reader), Ok(None) | Err(_)));
+    // Until its thread notices, the session is listed as killed
+    let (output, _) = admin.query("SHOW PROCESSLIST");
+    assert!(!output.contains("| alice | native") || output.contains("| killed |"), "{}", output);
+}
+
+#[test]
+fn an_access_token_logs_in_as_its_user_until_dropped() {
+    let dir = TempDir::new();
+    let http_port = free_port();
+    let server = Server::start(dir.path(), &["--memory", "--http", &http_port.to_string()]);
+    server.wait_for(http_port);
+    assert!(server.connect().query("CREATE USER admin PASSWORD 'secret' SUPERUSER").1.is_empty());
+    let mut admin = server.connect();
+    assert!(admin.send(Frame::Login { user: "admin".to_string(), password: "secret".to_string() }).1.is_empty());
+    assert!(admin.query("CREATE USER alice PASSWORD 'pw'").1.is_empty());
+    assert!(admin.query("CREATE TABLE t id:int").1.is_empty());
+    let (output, errors) = admin.query("CREATE TOKEN ci FOR alice");
+    assert!(errors.is_empty() && output.starts_with("Token 'ci' created for 'alice'. It is shown only this once:"), "{}", output);
+    let token = output.rsplit_once(':').unwrap().1.to_string();
+    assert!(token.starts_with("ci."), "{}", token);
+    assert!(admin.query("SHOW TOKENS").0.contains("| ci    | alice |"));
+
+    let mut ci = server.connect();
+    assert!(ci.send(Frame::Token(token.clone())).1.is_empty());
+    assert_eq!(ci.query("SHOW PROCESSLIST").0.matches("| alice |").count(), 1);
+    let mut guess = server.connect();
+    assert_eq!(guess.send(Frame::Token("ci.guess".to_string())).1, ["Authentication failed"]);
+    let bearer = format!("Authorization: Bearer {}", token);
+    // Acting as alice, who was granted nothing
+    let (status, body) = http(http_port, "POST /query", &["Content-Type: application/sql", &bearer], "SELECT COUNT(*) FROM t");
+    assert_eq!((status, &body["results"][0]["error"]), (400, &json!("Permission denied: SELECT on 't' was not granted to 'alice'")));
+
+    // Only the hash of the secret is kept, and once dropped the token is no use
+    assert!(admin.query("DROP TOKEN ci").1.is_empty());
+    assert_eq!(server.connect().send(Frame::Token(token)).1, ["Authentication failed"]);
+    assert_eq!(http(http_port, "POST /query", &["Content-Type: application/sql", &bearer], "SELECT 1").0, 401);
+}
+
+#[test]
+fn a_listener_can_take_tokens_only_and_encrypt_its_connections() {
+    let dir = TempDir::new();
+    let tls = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/tls");
+    let (cert, key, ca) = (tls.join("server.crt"), tls.join("server.key"), tls.join("ca.crt"));
+    let server = Server::start(dir.path(), &["--memory", "--auth", "token", "--tls-cert", cert.to_str().unwrap(), "--tls-key", key.to_str().unwrap()]);
+    let addr = format!("localhost:{}", server.port);
+    // Runs `input` through a client connected with `args`, giving what it printed and whether it worked
+    let client = |args: &[&str], env: (&str, &str), input: &str| {
+        let mut client = cli(dir.path()).arg("connect").args(args).arg(&addr).env(env.0, env.1)
+            .stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().unwrap();
+        client.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+        let output = client.wait_with_output().unwrap();
+        (String::from_utf8(output.stdout).unwrap() + &String::from_utf8(output.stderr).unwrap(), output.status.success())
+    };
+    let ca_args = ["--tls-ca", ca.to_str().unwrap()];
+
+    let (output, ok) = client(&ca_args, ("RUSTDB_TOKEN", ""), "CREATE USER admin PASSWORD 'pw' SUPERUSER\nCREATE TOKEN ci FOR admin\n");
+    assert!(ok, "{}", output);
+    let token = output.lines().last().unwrap().to_string();
+    let (output, ok) = client(&["--token", ca_args[0], ca_args[1]], ("RUSTDB_TOKEN", &token), "SHOW TOKENS\n");
+    assert!(ok && output.contains("| ci    | admin |"), "{}", output);
+
+    let (output, ok) = client(&["--user", "admin", ca_args[0], ca_args[1]], ("RUSTDB_PASSWORD", "pw"), "SHOW TOKENS\n");
+    assert!(!ok && output.contains("This server only accepts access tokens; connect with --token"), "{}", output);
+    // Nor is a client speaking in clear text understood
+    let (output, ok) = client(&["--token"], ("RUSTDB_TOKEN", &token), "SHOW TOKENS\n");
+    assert!(!ok && output.contains("Connection lost"), "{}", output);
+}
+
+#[test]
+fn a_follower_serves_what_its_leader_writes_until_promoted() {
+    let (leader_dir, follower_dir) = (TempDir::new(), TempDir::new());
+    let leader = Server::start(leader_dir.path(), &[]);
+    let mut writer = leader.connect();
+    assert!(writer.query("CREATE TABLE t id:int").1.is_empty());
+    assert!(writer.query("INSERT INTO t VALUES (1)").1.is_empty());
+    let follower = Server::start(follower_dir.path(), &["--follow", &format!("127.0.0.1:{}", leader.port)]);
+    let mut reader = follower.connect();
+
+    // A moment behind the leader
+    let caught_up = |reader: &mut Connection, count: u32| {
+        let started = Instant::now();
+        while !reader.query("SELECT COUNT(*) FROM t").0.contains(&format!(" {} |", count)) {
+            assert!(started.elapsed() < Duration::from_secs(10), "the follower never had {} row(s)", count);
+            thread::sleep(Duration::from_millis(50));
+        }
+    };
+    caught_up(&mut reader, 1);
+    assert!(writer.query("INSERT INTO t VALUES (2)").1.is_empty());
+    caught_up(&mut reader, 2);
+    let (_, errors) = reader.query("INSERT INTO t VALUES (3)");
+    assert!(errors[0].contains("[E3009]"), "{:?}", errors);
+
+    assert!(reader.query("PROMOTE").1.is_empty());
+    assert!(reader.query("INSERT INTO t VALUES (3)").1.is_empty());
+    assert_eq!(leader.connect().query("PROMOTE").1[0].split(']').next(), Some("[E3010"));
+    // The old leader goes its own way
+    assert!(writer.query("DELETE FROM t WHERE id = 1").1.is_empty());
+    thread::sleep(Duration::from_millis(200));
+    assert!(reader.query("SELECT COUNT(*) FROM t").0.contains(" 3 |"));
+}
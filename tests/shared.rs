@@ -0,0 +1,114 @@
+mod common;
+
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+use rust_db::{Database, SharedDatabase};
+
+use common::{create_table, insert, int, TempDir};
+
+// Table `t` of `dir` saved with the one row 1, and cached
+fn shared_with_one_row(dir: &TempDir) -> SharedDatabase {
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    create_table(&mut db, "t", &[("id", "int")]);
+    insert(&mut db, "t", vec![int(1)]);
+    db.checkpoint().unwrap();
+    let shared = SharedDatabase::new(db);
+    assert_eq!(shared.read("t", |table| table.data["id"].clone()).unwrap(), vec![int(1)]);
+    shared
+}
+
+#[test]
+fn reads_a_cached_table_again_once_its_file_changes() {
+    let dir = TempDir::new();
+    let shared = shared_with_one_row(&dir);
+
+    // Edited by hand: without the checksum header the body is read as it is
+    let path = dir.path().join("t.json");
+    let saved = fs::read_to_string(&path).unwrap();
+    let body = saved.split_once('\n').unwrap().1;
+    fs::write(&path, body.replace("\"Integer32\": 1", "\"Integer32\": 12")).unwrap();
+
+    assert_eq!(shared.read("t", |table| table.data["id"].clone()).unwrap(), vec![int(12)]);
+}
+
+#[test]
+fn records_reads_served_from_the_cache() {
+    let dir = TempDir::new();
+    let shared = shared_with_one_row(&dir);
+
+    shared.write(|db| db.record_reads());
+    shared.read("t", |table| table.row_count()).unwrap();
+    let reads = shared.write(|db| db.take_reads());
+    assert!(reads.names.contains("t"));
+}
+
+#[test]
+fn reads_a_table_while_another_is_written() {
+    let dir = TempDir::new();
+    let shared = Arc::new(shared_with_one_row(&dir));
+    shared.write(|db| create_table(db, "u", &[("id", "int")]));
+
+    let read = shared.write(|db| {
+        insert(db, "u", vec![int(1)]);
+        // Still writing: a reader of t must not wait for the database
+        let (sender, receiver) = mpsc::channel();
+        let reader = Arc::clone(&shared);
+        thread::spawn(move || sender.send(reader.read("t", |table| table.row_count()).unwrap()));
+        receiver.recv_timeout(Duration::from_secs(5))
+    });
+    assert_eq!(read, Ok(1));
+}
+
+#[test]
+fn reads_what_a_write_changed() {
+    let dir = TempDir::new();
+    let shared = shared_with_one_row(&dir);
+
+    shared.write(|db| insert(db, "t", vec![int(2)]));
+    assert_eq!(shared.read("t", |table| table.data["id"].clone()).unwrap(), vec![int(1), int(2)]);
+}
+
+#[test]
+fn never_reads_rows_a_transaction_has_not_committed() {
+    let dir = TempDir::new();
+    let shared = Arc::new(shared_with_one_row(&dir));
+    let count = |shared: &Arc<SharedDatabase>| {
+        let reader = Arc::clone(shared);
+        thread::spawn(move || reader.read("t", |table| table.row_count()).unwrap()).join().unwrap()
+    };
+
+    shared.write(|db| {
+        db.begin().unwrap();
+        insert(db, "t", vec![int(2)]);
+    });
+    assert_eq!(count(&shared), 1);
+    shared.write(|db| db.rollback().unwrap());
+    assert_eq!(count(&shared), 1);
+
+    shared.write(|db| {
+        db.begin().unwrap();
+        insert(db, "t", vec![int(3)]);
+        db.commit().unwrap();
+    });
+    assert_eq!(count(&shared), 2);
+}
+
+#[test]
+fn a_panic_mid_transaction_leaves_its_rows_unseen() {
+    let dir = TempDir::new();
+    let shared = shared_with_one_row(&dir);
+
+    let panicked = panic::catch_unwind(AssertUnwindSafe(|| shared.write(|db| {
+        db.begin().unwrap();
+        insert(db, "t", vec![int(2)]);
+        panic!("in the middle of a transaction");
+    })));
+    assert!(panicked.is_err());
+
+    assert_eq!(shared.read("t", |table| table.data["id"].clone()).unwrap(), vec![int(1)]);
+    assert!(!shared.write(|db| db.in_transaction()));
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use common::{cli, TempDir};
+
+// Runs `script` with the client in `dir`. Returns what it printed, and whether it all worked
+fn run(dir: &Path, script: &str) -> (String, bool) {
+    let output = cli(dir).args(["-c", script]).output().unwrap();
+    (String::from_utf8(output.stdout).unwrap() + &String::from_utf8(output.stderr).unwrap(), output.status.success())
+}
+
+#[test]
+fn show_create_table_gives_the_statements_that_make_the_table_again() {
+    let dir = TempDir::new();
+    let setup = "CREATE SEQUENCE ids; CREATE TABLE users id:int DEFAULT NEXTVAL('ids') PRIMARY KEY name:string COLLATE NOCASE; \
+                 CREATE INDEX idx_name ON users(name); COMMENT ON TABLE users IS 'People'";
+    assert!(run(dir.path(), setup).1);
+    let (shown, ok) = run(dir.path(), "SHOW CREATE TABLE users");
+    assert!(ok, "{}", shown);
+    assert_eq!(shown, "CREATE TABLE users id:int DEFAULT NEXTVAL('ids') PRIMARY KEY name:string COLLATE nocase;\n\
+                       COMMENT ON TABLE users IS 'People';\nCREATE INDEX idx_name ON users(name) USING BTREE;\n");
+
+    // Run against another database, they make the same table
+    let mut copy = cli(dir.path()).args(["--data-dir", "copy", "-c", &format!("CREATE SEQUENCE ids; {}", shown)]).output().unwrap();
+    assert!(copy.status.success(), "{}", String::from_utf8_lossy(&copy.stderr));
+    copy = cli(dir.path()).args(["--data-dir", "copy", "-c", "SHOW CREATE TABLE users"]).output().unwrap();
+    assert_eq!(String::from_utf8(copy.stdout).unwrap(), shown);
+
+    let (shown, ok) = run(dir.path(), "SHOW CREATE TABLE nowhere");
+    assert!(!ok && shown.contains("[E2001]"), "{}", shown);
+}
+
+#[test]
+fn show_table_status_gives_the_rows_engine_codec_and_sizes_of_each_table() {
+    let dir = TempDir::new();
+    let setup = "CREATE TABLE t id:int name:string; CREATE TABLE b id:int ENGINE = binary; CREATE INDEX i ON t(id); \
+                 INSERT INTO t VALUES (1, 'ann'); INSERT INTO t VALUES (2, 'bob'); SET COMPRESSION gzip";
+    assert!(run(dir.path(), setup).1);
+    let (shown, ok) = run(dir.path(), "SHOW TABLE STATUS");
+    assert!(ok, "{}", shown);
+    let lines: Vec<Vec<&str>> = shown.lines().map(|line| line.split(',').collect()).collect();
+    assert_eq!(lines[0], ["Name", "Rows", "Indexes", "Engine", "Codec", "Format", "Raw Size", "File Size", "Ratio", "Index Size", "Modified"]);
+    assert_eq!(lines[1][..6], ["b", "0", "0", "binary", "gzip", "3"]);
+    assert_eq!(lines[2][..6], ["t", "2", "1", "json", "gzip", "3"]);
+    assert_eq!(lines[2][7], format!("{} B", fs::metadata(dir.path().join("data/t.json")).unwrap().len()));
+    assert!(lines[2][10].ends_with(" UTC"));
+}
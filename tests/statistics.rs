@@ -0,0 +1,84 @@
+mod common;
+
+use rust_db::index::{IndexDef, IndexKind};
+use rust_db::parser::{self, Statement};
+use rust_db::{planner, recovery, Database};
+
+use common::{create_table, insert, int, TempDir};
+
+// How a SELECT on `table` filtered by `condition` reads it
+fn plan(db: &mut Database, table: &str, condition: &str) -> String {
+    let Statement::Select { filter, .. } = parser::parse(&format!("SELECT * FROM {} WHERE {}", table, condition)).unwrap() else {
+        unreachable!("a SELECT parses as one");
+    };
+    let snapshot = db.snapshot(table).unwrap();
+    planner::plan(&snapshot, &filter, &db.functions()).unwrap().to_string()
+}
+
+// 100 users, 90 of them in their twenties and the rest aged 30 to 120 in tens,
+// with an index on age
+fn users(db: &mut Database) {
+    create_table(db, "users", &[("id", "int"), ("age", "int")]);
+    for id in 0..100 {
+        let age = if id < 90 { 20 + id % 10 } else { 30 + (id - 90) * 10 };
+        insert(db, "users", vec![int(id), int(age)]);
+    }
+    let def = IndexDef { name: "idx_age".to_string(), columns: vec!["age".to_string()], kind: IndexKind::BTree, unique: false };
+    db.create_index("users", def).unwrap();
+}
+
+#[test]
+fn a_histogram_estimates_ranges_over_skewed_values() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    users(&mut db);
+    // Without statistics any usable index is used
+    assert!(plan(&mut db, "users", "age BETWEEN 20 AND 29").starts_with("Index range scan"));
+
+    assert_eq!(db.analyze("users").unwrap(), 100);
+    let stats = db.snapshot("users").unwrap().stats.clone().unwrap();
+    let histogram = &stats.columns["age"].histogram;
+    assert_eq!(histogram.len(), 33);
+    assert!(histogram.windows(2).all(|bounds| bounds[0] <= bounds[1]));
+
+    // Spread evenly between the lowest and highest age, the twenties would be
+    // a tenth of the rows; the histogram knows they are most of them
+    assert!(plan(&mut db, "users", "age BETWEEN 20 AND 29").starts_with("Full scan on users"));
+    let narrow = plan(&mut db, "users", "age BETWEEN 100 AND 120");
+    assert!(narrow.starts_with("Index range scan on users using idx_age"), "{}", narrow);
+    let estimated: f64 = narrow.split("[estimated ").nth(1).unwrap().split(' ').next().unwrap().parse().unwrap();
+    assert!(estimated <= 10.0, "{}", narrow);
+    assert!(narrow.contains(" of 100 rows]"));
+}
+
+#[test]
+fn statistics_are_saved_with_their_table() {
+    let dir = TempDir::new();
+    {
+        let mut db = Database::open_dir(dir.path()).unwrap();
+        users(&mut db);
+        db.analyze("users").unwrap();
+    }
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    recovery::recover(&mut db).unwrap();
+    assert!(!db.snapshot("users").unwrap().stats.as_ref().unwrap().columns["age"].histogram.is_empty());
+    assert!(plan(&mut db, "users", "age >= 20 AND age < 30").starts_with("Full scan on users"));
+}
+
+#[test]
+fn analyze_counts_rows_and_distinct_values_and_finds_the_extremes() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    users(&mut db);
+    assert!(!plan(&mut db, "users", "age = 25").contains("[estimated"));
+
+    db.analyze("users").unwrap();
+    let users = db.snapshot("users").unwrap();
+    let stats = users.stats.as_ref().unwrap();
+    assert_eq!(stats.rows, 100);
+    let age = &stats.columns["age"];
+    assert_eq!((age.distinct, age.min.clone(), age.max.clone()), (20, Some(int(20)), Some(int(120))));
+    assert_eq!(stats.columns["id"].distinct, 100);
+    // An age is taken to be as common as any other of the twenty
+    assert!(plan(&mut db, "users", "age = 25").contains("[estimated 5 of 100 rows]"));
+}
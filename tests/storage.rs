@@ -0,0 +1,387 @@
+mod common;
+
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use rust_db::storage::{Compression, FileStorage, MemoryStorage, Storage, FORMAT_VERSION};
+use rust_db::wal::WalOp;
+use rust_db::{Database, DbError};
+
+use common::{cli, create_table, insert, int, rows, string, TempDir};
+
+// The bytes of a `.rdb` file with `dir_len` as the length of its directory,
+// `directory` after it and then `data`
+fn rdb_file(dir_len: u64, directory: &str, data: &[u8]) -> Vec<u8> {
+    let mut bytes = b"RDB1".to_vec();
+    bytes.extend(dir_len.to_le_bytes());
+    bytes.extend(directory.as_bytes());
+    bytes.extend(data);
+    bytes
+}
+
+fn open_error(bytes: &[u8]) -> io::Error {
+    let dir = TempDir::new();
+    let path = dir.path().join("db.rdb");
+    fs::write(&path, bytes).unwrap();
+    FileStorage::open(&path).err().expect("a corrupt file must not open")
+}
+
+#[test]
+fn file_storage_reads_back_what_it_wrote() {
+    let dir = TempDir::new();
+    let path = dir.path().join("db.rdb");
+    let mut storage = FileStorage::open(&path).unwrap();
+    storage.write("a.json", b"first").unwrap();
+    storage.write("b.json", b"second").unwrap();
+    storage.rename("a.json", "c.json").unwrap();
+    assert!(storage.remove("b.json").unwrap());
+
+    let storage = FileStorage::open(&path).unwrap();
+    assert_eq!(storage.keys().unwrap(), vec!["c.json".to_string()]);
+    assert_eq!(storage.read("c.json").unwrap(), Some(b"first".to_vec()));
+}
+
+#[test]
+fn file_storage_rejects_a_directory_length_past_the_end() {
+    let error = open_error(&rdb_file(u64::MAX, "[]", b""));
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    let error = open_error(&rdb_file(100, "[]", b""));
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn file_storage_rejects_a_blob_out_of_bounds() {
+    let overflowing = format!(r#"[{{"key":"t.json","offset":{},"len":2}}]"#, u64::MAX);
+    let error = open_error(&rdb_file(overflowing.len() as u64, &overflowing, b"ab"));
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+    let past_end = r#"[{"key":"t.json","offset":1,"len":2}]"#;
+    let error = open_error(&rdb_file(past_end.len() as u64, past_end, b"ab"));
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn file_storage_keeps_memory_as_on_disk_when_a_write_fails() {
+    let dir = TempDir::new();
+    let path = dir.path().join("db.rdb");
+    let mut storage = FileStorage::open(&path).unwrap();
+    storage.write("a.json", b"first").unwrap();
+
+    // A directory where the temp file goes makes every write fail
+    fs::create_dir(dir.path().join("db.rdb.tmp")).unwrap();
+    assert!(storage.write("a.json", b"changed").is_err());
+    assert!(storage.write("b.json", b"new").is_err());
+    assert!(storage.remove("a.json").is_err());
+    assert!(storage.rename("a.json", "c.json").is_err());
+
+    assert_eq!(storage.keys().unwrap(), vec!["a.json".to_string()]);
+    assert_eq!(storage.read("a.json").unwrap(), Some(b"first".to_vec()));
+    assert_eq!(FileStorage::open(&path).unwrap().read("a.json").unwrap(), Some(b"first".to_vec()));
+}
+
+// A backend of the caller's, as a browser's would be, whose blobs outlive
+// the database opened over it
+#[derive(Clone, Default)]
+struct Shared(Arc<Mutex<MemoryStorage>>);
+
+impl Storage for Shared {
+    fn read(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        self.0.lock().unwrap().read(key)
+    }
+
+    fn write(&mut self, key: &str, bytes: &[u8]) -> io::Result<()> {
+        self.0.lock().unwrap().write(key, bytes)
+    }
+
+    fn remove(&mut self, key: &str) -> io::Result<bool> {
+        self.0.lock().unwrap().remove(key)
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> io::Result<()> {
+        self.0.lock().unwrap().rename(from, to)
+    }
+
+    fn exists(&self, key: &str) -> bool {
+        self.0.lock().unwrap().exists(key)
+    }
+
+    fn keys(&self) -> io::Result<Vec<String>> {
+        self.0.lock().unwrap().keys()
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        self.0.lock().unwrap().size()
+    }
+
+    fn discard_torn_writes(&mut self) -> io::Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+}
+
+#[test]
+fn a_database_over_a_storage_backend_writes_each_change_through() {
+    let backend = Shared::default();
+    {
+        let mut db = Database::open_with(Box::new(backend.clone())).unwrap();
+        create_table(&mut db, "t", &[("id", "int")]);
+        insert(&mut db, "t", vec![int(1)]);
+    }
+    // Nothing was left in the log, which went with the database
+    let mut db = Database::open_with(Box::new(backend)).unwrap();
+    assert_eq!(rows(&mut db, "t"), vec![vec![int(1)]]);
+    insert(&mut db, "t", vec![int(2)]);
+    assert_eq!(rows(&mut db, "t"), vec![vec![int(1)], vec![int(2)]]);
+}
+
+#[test]
+fn a_table_file_changed_after_it_was_written_is_refused() {
+    let dir = TempDir::new();
+    {
+        let mut db = Database::open_dir(dir.path()).unwrap();
+        create_table(&mut db, "t", &[("id", "int")]);
+        insert(&mut db, "t", vec![int(1234)]);
+        db.checkpoint().unwrap();
+    }
+    let path = dir.path().join("t.json");
+    let saved = fs::read_to_string(&path).unwrap();
+    assert!(saved.starts_with("#rustdb crc32="));
+    fs::write(&path, saved.replacen("1234", "1235", 1)).unwrap();
+
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    let e = db.query("SELECT * FROM t").unwrap_err();
+    assert!(matches!(&e, DbError::CorruptTable { table, reason } if table == "t" && reason.starts_with("checksum mismatch")), "{}", e);
+    drop(db);
+
+    // A file from before checksums, without the header, is read as it is
+    let (_, body) = saved.split_once('\n').unwrap();
+    fs::write(&path, body).unwrap();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    assert_eq!(rows(&mut db, "t"), vec![vec![int(1234)]]);
+}
+
+#[test]
+fn tables_are_saved_compressed_once_the_database_is_set_to() {
+    let dir = TempDir::new();
+    {
+        let mut db = Database::open_dir(dir.path()).unwrap();
+        let mut settings = db.settings().unwrap();
+        settings.compression = Compression::Gzip;
+        db.save_settings(&settings).unwrap();
+        create_table(&mut db, "t", &[("id", "int"), ("name", "string")]);
+        for id in 0..100 {
+            insert(&mut db, "t", vec![int(id), string("the same long name over and over")]);
+        }
+        db.checkpoint().unwrap();
+    }
+    let saved = fs::read(dir.path().join("t.json")).unwrap();
+    let header = String::from_utf8_lossy(&saved[..saved.iter().position(|b| *b == b'\n').unwrap()]).to_string();
+    assert!(header.contains(" codec=gzip "), "{}", header);
+    assert!(!String::from_utf8_lossy(&saved).contains("the same long name"));
+
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    assert_eq!(rows(&mut db, "t").len(), 100);
+    assert_eq!(db.query("SELECT name FROM t WHERE id = 7").unwrap().rows, vec![vec![string("the same long name over and over")]]);
+}
+
+#[test]
+fn string_columns_that_repeat_are_saved_as_a_dictionary_and_codes() {
+    let dir = TempDir::new();
+    {
+        let mut db = Database::open_dir(dir.path()).unwrap();
+        create_table(&mut db, "t", &[("id", "int"), ("status", "string"), ("name", "string")]);
+        for id in 0..40 {
+            insert(&mut db, "t", vec![int(id), string(["open", "closed", "pending"][id as usize % 3]), string(&format!("name {}", id))]);
+        }
+        db.checkpoint().unwrap();
+    }
+    let saved = fs::read_to_string(dir.path().join("t.json")).unwrap();
+    let table: serde_json::Value = serde_json::from_str(saved.split_once('\n').unwrap().1).unwrap();
+    assert_eq!(table["data"]["status"]["dictionary"], serde_json::json!(["open", "closed", "pending"]));
+    assert_eq!(table["data"]["status"]["codes"][4], 1);
+    // Values that do not repeat enough are spelled out
+    assert!(table["data"]["name"].is_array());
+
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    assert_eq!(db.query("SELECT status, name FROM t WHERE id = 4").unwrap().rows, vec![vec![string("closed"), string("name 4")]]);
+    assert_eq!(db.query("SELECT COUNT(*) FROM t WHERE status = 'pending'").unwrap().rows, vec![vec![int(13)]]);
+}
+
+#[test]
+fn a_file_in_an_older_format_is_read_and_saved_in_the_current_one() {
+    let dir = TempDir::new();
+    {
+        let mut db = Database::open_dir(dir.path()).unwrap();
+        create_table(&mut db, "t", &[("id", "int")]);
+        insert(&mut db, "t", vec![int(1)]);
+        db.checkpoint().unwrap();
+    }
+    let path = dir.path().join("t.json");
+    let saved = fs::read_to_string(&path).unwrap();
+    let (header, body) = saved.split_once('\n').unwrap();
+    assert!(header.contains(&format!(" format={} ", FORMAT_VERSION)), "{}", header);
+    // Before the header, a file was its table's JSON alone
+    fs::write(&path, body).unwrap();
+    {
+        let mut db = Database::open_dir(dir.path()).unwrap();
+        assert_eq!(rows(&mut db, "t"), vec![vec![int(1)]]);
+        insert(&mut db, "t", vec![int(2)]);
+        db.checkpoint().unwrap();
+    }
+    assert!(fs::read_to_string(&path).unwrap().lines().next().unwrap().contains(&format!(" format={} ", FORMAT_VERSION)));
+
+    // One from a newer release is refused rather than misread
+    let saved = fs::read_to_string(&path).unwrap();
+    fs::write(&path, saved.replacen(&format!(" format={} ", FORMAT_VERSION), " format=99 ", 1)).unwrap();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    let e = db.query("SELECT * FROM t").unwrap_err();
+    assert!(matches!(e, DbError::NewerFormat { format: 99, .. }), "{}", e);
+    assert_eq!(e.code(), "E6008");
+}
+
+#[test]
+fn an_in_memory_database_keeps_its_tables_only_while_it_is_open() {
+    let mut db = Database::open_in_memory();
+    create_table(&mut db, "t", &[("id", "int")]);
+    insert(&mut db, "t", vec![int(1)]);
+    db.checkpoint().unwrap();
+    insert(&mut db, "t", vec![int(2)]);
+    assert_eq!(rows(&mut db, "t"), vec![vec![int(1)], vec![int(2)]]);
+    assert_eq!(db.table_names().unwrap(), ["t"]);
+
+    assert!(Database::open_in_memory().table_names().unwrap().is_empty());
+}
+
+#[test]
+fn a_database_is_open_in_one_process_at_a_time() {
+    let dir = TempDir::new();
+    let db = Database::open_dir(&dir.path().join("data")).unwrap();
+    let file = Database::open_file(&dir.path().join("db.rdb")).unwrap();
+    assert_eq!(Database::open_dir(&dir.path().join("data")).err().unwrap().kind(), io::ErrorKind::WouldBlock);
+    for target in [&["--data-dir", "data"][..], &["db.rdb"]] {
+        let output = cli(dir.path()).args(target).args(["-c", "SHOW TABLES"]).output().unwrap();
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("locked by another process"));
+    }
+    // A reader takes no lock
+    assert!(Database::open_read_only(&dir.path().join("data")).is_ok());
+
+    drop((db, file));
+    let output = cli(dir.path()).args(["--data-dir", "data", "-c", "SHOW TABLES"]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+// Every file of `dir` and what it holds
+fn contents(dir: &Path) -> Vec<(OsString, Vec<u8>)> {
+    let mut files: Vec<_> = fs::read_dir(dir).unwrap()
+        .map(|entry| entry.unwrap())
+        .map(|entry| (entry.file_name(), fs::read(entry.path()).unwrap_or_default()))
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn a_database_opened_read_only_is_read_as_it_stands_and_left_alone() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(&dir.path().join("data")).unwrap();
+    create_table(&mut db, "t", &[("id", "int")]);
+    insert(&mut db, "t", vec![int(1)]);
+    db.checkpoint().unwrap();
+    // Left in the log, not yet in the table's file
+    insert(&mut db, "t", vec![int(2)]);
+    let before = contents(&dir.path().join("data"));
+
+    let mut reader = Database::open_read_only(&dir.path().join("data")).unwrap();
+    assert_eq!(rows(&mut reader, "t"), vec![vec![int(1)], vec![int(2)]]);
+    assert!(matches!(reader.log(WalOp::Insert { table: "t".to_string(), row: vec![int(3)] }), Err(DbError::ReadOnly(_))));
+    drop(reader);
+    assert_eq!(contents(&dir.path().join("data")), before);
+    drop(db);
+    let before = contents(&dir.path().join("data"));
+
+    let output = cli(dir.path()).args(["--data-dir", "data", "--read-only", "-c", "SELECT COUNT(*) FROM t"]).output().unwrap();
+    assert!(String::from_utf8(output.stdout).unwrap().ends_with("COUNT(*)\n2\n"));
+    let output = cli(dir.path()).args(["--data-dir", "data", "--read-only", "-c", "INSERT INTO t VALUES (3)"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("[E3009]"));
+    assert_eq!(contents(&dir.path().join("data")), before);
+}
+
+#[test]
+fn vacuum_empties_the_log_and_deletes_what_dropped_tables_and_torn_writes_left() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    create_table(&mut db, "users", &[("id", "int"), ("name", "string")]);
+    for id in 0..50 {
+        insert(&mut db, "users", vec![int(id), string("someone")]);
+    }
+    fs::write(dir.path().join("gone.idx"), "an index of a dropped table").unwrap();
+    fs::write(dir.path().join("users.json.tmp"), "half a table").unwrap();
+
+    let log = fs::metadata(dir.path().join("wal.log")).unwrap().len();
+
+    // Vacuuming one table leaves the rest alone
+    let report = db.vacuum(Some("users")).unwrap();
+    assert_eq!(report.tables, 1);
+    assert!(report.reclaimed() > 0, "{:?}", report);
+    // Down to the record of the checkpoint
+    assert!(fs::metadata(dir.path().join("wal.log")).unwrap().len() * 10 < log);
+    assert!(dir.path().join("gone.idx").exists());
+    assert!(matches!(db.vacuum(Some("missing")), Err(DbError::TableNotFound { .. })));
+
+    let report = db.vacuum(None).unwrap();
+    assert_eq!(report.tables, 1);
+    assert!(!dir.path().join("gone.idx").exists());
+    assert!(!dir.path().join("users.json.tmp").exists());
+    assert_eq!(rows(&mut db, "users").len(), 50);
+
+    db.begin().unwrap();
+    assert!(matches!(db.vacuum(None), Err(DbError::TransactionActive)));
+}
+
+#[test]
+fn a_table_is_saved_by_the_engine_it_was_given_and_rewritten_when_that_changes() {
+    let dir = TempDir::new();
+    let header = || fs::read_to_string(dir.path().join("data/e.json")).unwrap_or_default().lines().next().unwrap_or_default().to_string();
+    let run = |script: &str| {
+        let output = cli(dir.path()).args(["-c", script]).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let output = run("CREATE TABLE e id:int name:string ENGINE = binary; INSERT INTO e VALUES (1, 'a'); \
+        SELECT engine FROM __tables WHERE name = 'e'; SHOW CREATE TABLE e");
+    assert!(output.ends_with("engine\nbinary\nCREATE TABLE e id:int name:string ENGINE = binary;\n"), "{}", output);
+    assert!(header().contains(" engine=binary "), "{}", header());
+
+    assert!(run("ALTER TABLE e ENGINE json").ends_with("Table 'e' now uses the json engine\n"));
+    assert!(!header().contains("engine="), "{}", header());
+    assert!(fs::read_to_string(dir.path().join("data/e.json")).unwrap().contains("\"name\": \"e\""));
+    assert_eq!(run("SELECT * FROM e"), "id,name\n1,a\n");
+
+    let output = cli(dir.path()).args(["-c", "CREATE TABLE p id:int ENGINE = paged"]).output().unwrap();
+    assert!(String::from_utf8(output.stderr).unwrap().contains("unknown engine 'paged'. Use json or binary"));
+}
+
+#[test]
+fn a_table_saved_again_unchanged_is_written_byte_for_byte_the_same() {
+    let dir = TempDir::new();
+    let output = cli(dir.path()).args(["-c", "CREATE TABLE t zeta:int alpha:string mid:float; CREATE INDEX by_alpha ON t (alpha) USING HASH; \
+        INSERT INTO t VALUES (3, 'c', 1.5); INSERT INTO t VALUES (1, 'a', 2.5); INSERT INTO t VALUES (2, 'b', 0.5)"]).output().unwrap();
+    assert!(output.status.success());
+    let files = || (fs::read(dir.path().join("data/t.json")).unwrap(), fs::read(dir.path().join("data/t.idx")).unwrap());
+    let saved = files();
+    let text = String::from_utf8(saved.0.clone()).unwrap();
+    // Maps are written in the order of their keys, not the table's columns
+    let at = |key: &str| text.find(&format!("\"{}\":", key)).unwrap();
+    assert!(at("alpha") < at("mid") && at("mid") < at("zeta"), "{}", text);
+    let index = String::from_utf8(saved.1.clone()).unwrap();
+    assert!(index.find("\"a\"").unwrap() < index.find("\"b\"").unwrap() && index.find("\"b\"").unwrap() < index.find("\"c\"").unwrap(), "{}", index);
+
+    for _ in 0..3 {
+        assert!(cli(dir.path()).args(["-c", "VACUUM"]).output().unwrap().status.success());
+        assert_eq!(files(), saved);
+    }
+}
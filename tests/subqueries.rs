@@ -0,0 +1,43 @@
+mod common;
+
+use std::path::Path;
+
+use common::{cli, TempDir};
+
+// Users ann, bob and cy, with orders 10 and 11 of ann and 12 of cy
+const SHOP: &str = "CREATE TABLE users id:int name:string; CREATE TABLE orders id:int user_id:int; \
+    INSERT INTO users VALUES (1, 'ann'); INSERT INTO users VALUES (2, 'bob'); INSERT INTO users VALUES (3, 'cy'); \
+    INSERT INTO orders VALUES (10, 1); INSERT INTO orders VALUES (11, 1); INSERT INTO orders VALUES (12, 3)";
+
+// Runs `script` on the shop with the client in `dir`. Returns what it printed, and whether it all worked
+fn run(dir: &Path, script: &str) -> (String, bool) {
+    let output = cli(dir).args(["--memory", "-c", &format!("{}; {}", SHOP, script)]).output().unwrap();
+    // What setting up the shop printed is left out
+    let setup = format!("Table 'users' created\nTable 'orders' created\n{}", "1 row inserted\n".repeat(6));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    (stdout.strip_prefix(&setup).unwrap().to_string() + &String::from_utf8(output.stderr).unwrap(), output.status.success())
+}
+
+#[test]
+fn in_and_not_in_take_their_list_from_a_subquery() {
+    let dir = TempDir::new();
+    let (output, ok) = run(dir.path(), "SELECT name FROM users WHERE id NOT IN (SELECT user_id FROM orders); \
+        SELECT name FROM users WHERE id IN (SELECT user_id FROM orders) ORDER BY name; \
+        SELECT name FROM users WHERE id NOT IN (SELECT user_id FROM orders WHERE id > 99) ORDER BY name; \
+        DELETE FROM users WHERE name IN ('ann', 'bob'); SELECT name FROM users");
+    assert!(ok, "{}", output);
+    // A subquery giving no rows leaves every row out of IN and in NOT IN
+    assert_eq!(output, "name\nbob\nname\nann\ncy\nname\nann\nbob\ncy\n2 row(s) deleted\nname\ncy\n");
+}
+
+#[test]
+fn exists_matches_each_row_against_the_subquery_through_one_equality() {
+    let dir = TempDir::new();
+    let (output, ok) = run(dir.path(), "SELECT name FROM users u WHERE EXISTS (SELECT * FROM orders o WHERE o.user_id = u.id AND o.id > 10) ORDER BY name; \
+        SELECT name FROM users WHERE NOT EXISTS (SELECT * FROM orders WHERE orders.user_id = users.id)");
+    assert!(ok, "{}", output);
+    assert_eq!(output, "name\nann\ncy\nname\nbob\n");
+
+    let (output, ok) = run(dir.path(), "SELECT name FROM users WHERE EXISTS (SELECT * FROM orders WHERE orders.user_id > users.id)");
+    assert!(!ok && output.contains("EXISTS can refer to the outer query through one '=' between an outer and an inner column"), "{}", output);
+}
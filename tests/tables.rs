@@ -0,0 +1,58 @@
+mod common;
+
+use rust_db::database::Limits;
+use rust_db::{recovery, Database, Table};
+
+use common::{create_table, insert, int, rows, TempDir};
+
+#[test]
+fn a_temporary_table_is_never_written_and_gone_once_the_database_closes() {
+    let dir = TempDir::new();
+    {
+        let mut db = Database::open_dir(dir.path()).unwrap();
+        let schema = vec![("id".to_string(), "int".to_string())];
+        db.add_temp_table(Table::new("staging", schema, None, db.last_lsn(), db.now()));
+        create_table(&mut db, "t", &[("id", "int")]);
+        insert(&mut db, "staging", vec![int(1)]);
+        insert(&mut db, "t", vec![int(2)]);
+        db.checkpoint().unwrap();
+        insert(&mut db, "staging", vec![int(3)]);
+
+        assert!(db.is_temp("staging") && !db.is_temp("t"));
+        assert_eq!(rows(&mut db, "staging"), vec![vec![int(1)], vec![int(3)]]);
+        assert_eq!(db.table_names().unwrap(), ["staging", "t"]);
+    }
+    assert!(!dir.path().join("staging.json").exists());
+
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    recovery::recover(&mut db).unwrap();
+    assert!(!db.table_exists("staging"));
+    assert_eq!(rows(&mut db, "t"), vec![vec![int(2)]]);
+}
+
+#[test]
+fn the_least_recently_used_clean_table_is_evicted_from_the_cache() {
+    let dir = TempDir::new();
+    {
+        let mut db = Database::open_dir(dir.path()).unwrap();
+        for name in ["a", "b", "c"] {
+            create_table(&mut db, name, &[("id", "int")]);
+        }
+        db.checkpoint().unwrap();
+    }
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    db.set_limits(Limits { cache_tables: 2, ..Limits::default() });
+    db.snapshot("a").unwrap();
+    db.snapshot("b").unwrap();
+    db.snapshot("a").unwrap();
+    db.snapshot("c").unwrap();
+    assert!(db.cached("a").is_some() && db.cached("c").is_some());
+    assert!(db.cached("b").is_none());
+
+    // A table with changes not yet written stays, however long unused
+    insert(&mut db, "a", vec![int(1)]);
+    db.snapshot("b").unwrap();
+    db.snapshot("c").unwrap();
+    assert!(db.cached("a").is_some());
+    assert_eq!(rows(&mut db, "a"), vec![vec![int(1)]]);
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use common::{cli, TempDir};
+
+// Runs `script` with the client in `dir`, carrying on past errors. Returns what it printed
+fn run(dir: &Path, script: &str) -> String {
+    let output = cli(dir).args(["--continue-on-error", "-c", script]).output().unwrap();
+    String::from_utf8(output.stdout).unwrap() + &String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn the_engine_stamps_rows_as_they_are_inserted_and_changed() {
+    let dir = TempDir::new();
+    let output = run(dir.path(), "CREATE TABLE o id:int PRIMARY KEY total:float WITH TIMESTAMPS; INSERT INTO o VALUES (1, 2.5); \
+        SELECT * FROM o; INSERT INTO o VALUES (2, 1.0, '2000-01-01 00:00:00', '')");
+    assert!(output.contains("[E1005] Column count mismatch: expected 2 value(s), got 4"), "{}", output);
+    let row = output.lines().find(|line| line.starts_with("1,2.5,")).unwrap();
+    let (created, updated) = row["1,2.5,".len()..].split_once(',').unwrap();
+    assert_eq!((created.len(), created), (19, updated));
+
+    // Declared as ints, they hold seconds since the Unix epoch
+    let output = run(dir.path(), "CREATE TABLE e id:int PRIMARY KEY n:int created_at:int updated_at:int WITH TIMESTAMPS; \
+        INSERT INTO e VALUES (1, 1); INSERT INTO e VALUES (2, 1)");
+    assert!(output.ends_with("1 row inserted\n1 row inserted\n"), "{}", output);
+    thread::sleep(Duration::from_millis(1100));
+    // Only a change that leaves the row different counts
+    let output = run(dir.path(), "INSERT INTO e VALUES (1, 1) ON CONFLICT (id) DO UPDATE SET n = EXCLUDED.n; \
+        INSERT INTO e VALUES (2, 5) ON CONFLICT (id) DO UPDATE SET n = EXCLUDED.n; \
+        SELECT id, created_at, updated_at FROM e ORDER BY id; \
+        INSERT INTO e VALUES (2, 6) ON CONFLICT (id) DO UPDATE SET updated_at = 0");
+    let times: Vec<Vec<u64>> = output.lines().skip_while(|line| !line.starts_with("id,")).skip(1).take(2)
+        .map(|line| line.split(',').skip(1).map(|time| time.parse().unwrap()).collect())
+        .collect();
+    assert!(times[0][1] == times[0][0] && times[1][1] > times[1][0], "{}", output);
+    assert!(output.contains("[E1015] Invalid timestamps: 'updated_at' is kept by the engine and cannot be given a value"), "{}", output);
+}
+
+#[test]
+fn timestamps_are_kept_on_an_existing_table_only_with_both_columns() {
+    let dir = TempDir::new();
+    let output = run(dir.path(), "CREATE TABLE p id:int; ALTER TABLE p SET TIMESTAMPS ON; \
+        CREATE TABLE f id:int created_at:float updated_at:int; ALTER TABLE f SET TIMESTAMPS ON");
+    assert!(output.contains("[E1003] Column 'created_at' does not exist in table 'p'"), "{}", output);
+    assert!(output.contains("[E1015] Invalid timestamps: column 'created_at' is float"), "{}", output);
+
+    // Once the engine stops keeping them, they are ordinary columns
+    let output = run(dir.path(), "CREATE TABLE e id:int created_at:int updated_at:int WITH TIMESTAMPS; \
+        ALTER TABLE e SET TIMESTAMPS OFF; INSERT INTO e VALUES (2, 5, 6); SELECT * FROM e");
+    assert!(output.ends_with("no longer keeps when rows are created and updated\n1 row inserted\nid,created_at,updated_at\n2,5,6\n"), "{}", output);
+}
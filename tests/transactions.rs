@@ -0,0 +1,140 @@
+mod common;
+
+use std::path::Path;
+
+use rust_db::Database;
+
+use common::{cli, create_table, insert, int, rows, TempDir};
+
+// Runs `script` with the client in `dir`, carrying on past errors. Returns what it printed
+fn run(dir: &Path, script: &str) -> String {
+    let output = cli(dir).args(["--continue-on-error", "-c", script]).output().unwrap();
+    String::from_utf8(output.stdout).unwrap() + &String::from_utf8(output.stderr).unwrap()
+}
+
+fn database_with_table() -> Database {
+    let mut db = Database::open_in_memory();
+    create_table(&mut db, "t", &[("id", "int")]);
+    db
+}
+
+#[test]
+fn rollback_undoes_the_whole_transaction() {
+    let mut db = database_with_table();
+    insert(&mut db, "t", vec![int(1)]);
+    db.begin().unwrap();
+    insert(&mut db, "t", vec![int(2)]);
+    insert(&mut db, "t", vec![int(3)]);
+    assert_eq!(db.rollback().unwrap(), 2);
+    assert_eq!(rows(&mut db, "t"), vec![vec![int(1)]]);
+}
+
+#[test]
+fn rollback_to_a_savepoint_keeps_what_came_before_it() {
+    let mut db = database_with_table();
+    db.begin().unwrap();
+    insert(&mut db, "t", vec![int(1)]);
+    db.savepoint("a").unwrap();
+    insert(&mut db, "t", vec![int(2)]);
+    db.savepoint("b").unwrap();
+    insert(&mut db, "t", vec![int(3)]);
+
+    assert_eq!(db.rollback_to("a").unwrap(), 2);
+    assert_eq!(rows(&mut db, "t"), vec![vec![int(1)]]);
+    // The savepoint stays set, and those after it are gone
+    insert(&mut db, "t", vec![int(4)]);
+    assert_eq!(db.rollback_to("a").unwrap(), 1);
+    assert!(db.rollback_to("b").is_err());
+
+    db.commit().unwrap();
+    assert_eq!(rows(&mut db, "t"), vec![vec![int(1)]]);
+}
+
+#[test]
+fn a_released_savepoint_is_undone_by_the_one_around_it() {
+    let mut db = database_with_table();
+    db.begin().unwrap();
+    db.savepoint("outer").unwrap();
+    insert(&mut db, "t", vec![int(1)]);
+    db.savepoint("inner").unwrap();
+    insert(&mut db, "t", vec![int(2)]);
+    db.release("inner").unwrap();
+    assert!(db.rollback_to("inner").is_err());
+    assert_eq!(rows(&mut db, "t").len(), 2);
+
+    assert_eq!(db.rollback_to("outer").unwrap(), 2);
+    assert!(rows(&mut db, "t").is_empty());
+}
+
+#[test]
+fn others_see_a_transaction_only_once_it_commits() {
+    let mut db = database_with_table();
+    insert(&mut db, "t", vec![int(1)]);
+    let before = db.snapshot("t").unwrap();
+    db.begin().unwrap();
+    insert(&mut db, "t", vec![int(2)]);
+
+    assert_eq!(db.snapshot("t").unwrap().row_count(), 2);
+    assert_eq!(db.committed_snapshot("t").unwrap().row_count(), 1);
+    assert_eq!(before.row_count(), 1);
+
+    db.commit().unwrap();
+    assert_eq!(db.committed_snapshot("t").unwrap().row_count(), 2);
+}
+
+#[test]
+fn others_never_see_a_rolled_back_transaction() {
+    let mut db = database_with_table();
+    db.begin().unwrap();
+    insert(&mut db, "t", vec![int(1)]);
+    assert_eq!(db.committed_snapshot("t").unwrap().row_count(), 0);
+    db.rollback().unwrap();
+    assert_eq!(db.committed_snapshot("t").unwrap().row_count(), 0);
+}
+
+#[test]
+fn a_suspended_transaction_reads_as_committed_until_it_resumes() {
+    let mut db = database_with_table();
+    insert(&mut db, "t", vec![int(1)]);
+    db.begin().unwrap();
+    insert(&mut db, "t", vec![int(2)]);
+
+    let suspended = db.suspend();
+    assert!(!db.in_transaction());
+    assert_eq!(rows(&mut db, "t"), vec![vec![int(1)]]);
+    db.resume(suspended);
+
+    assert_eq!(rows(&mut db, "t"), vec![vec![int(1)], vec![int(2)]]);
+    insert(&mut db, "t", vec![int(3)]);
+    assert_eq!(db.rollback().unwrap(), 2);
+    assert_eq!(rows(&mut db, "t"), vec![vec![int(1)]]);
+}
+
+#[test]
+fn a_committed_transaction_is_kept_and_a_rolled_back_one_is_not() {
+    let dir = TempDir::new();
+    let output = run(dir.path(), "CREATE TABLE t id:int; COMMIT; BEGIN; INSERT INTO t VALUES (1); INSERT INTO t VALUES (2); COMMIT; \
+        BEGIN; INSERT INTO t VALUES (3); DELETE FROM t WHERE id = 1; ROLLBACK; BEGIN; INSERT INTO t VALUES (4); BEGIN");
+    assert!(output.contains("[E3003] No transaction is in progress"), "{}", output);
+    assert!(output.contains("Transaction committed (2 change(s))\nTransaction started\n1 row inserted\n1 row(s) deleted\n\
+        Transaction rolled back (2 change(s) discarded)\nTransaction started\n"), "{}", output);
+    assert!(output.contains("[E3002] Not allowed inside a transaction; COMMIT or ROLLBACK first"), "{}", output);
+
+    // What the last one left open is gone too
+    assert_eq!(run(dir.path(), "SELECT * FROM t"), "id\n1\n2\n");
+}
+
+#[test]
+fn a_failed_step_is_rolled_back_to_its_savepoint_and_the_rest_committed() {
+    let dir = TempDir::new();
+    let output = run(dir.path(), "CREATE TABLE t id:int; SAVEPOINT a; BEGIN; INSERT INTO t VALUES (1); SAVEPOINT a; \
+        INSERT INTO t VALUES (2); SAVEPOINT b; INSERT INTO t VALUES (3); ROLLBACK TO a; RELEASE b; \
+        SAVEPOINT c; INSERT INTO t VALUES (4); RELEASE c; ROLLBACK TO SAVEPOINT a; INSERT INTO t VALUES (5); COMMIT; SELECT * FROM t");
+    assert!(output.starts_with("Table 't' created\nTransaction started\n1 row inserted\nSavepoint 'a' set\n1 row inserted\n\
+        Savepoint 'b' set\n1 row inserted\nRolled back to savepoint 'a' (2 change(s) discarded)\n"), "{}", output);
+    // A savepoint released is undone with the one it was set inside
+    assert!(output.contains("Savepoint 'c' released\nRolled back to savepoint 'a' (1 change(s) discarded)\n1 row inserted\n\
+        Transaction committed (2 change(s))\nid\n1\n5\n"), "{}", output);
+    assert!(output.contains("[E3003] No transaction is in progress"), "{}", output);
+    assert!(output.contains("[E2005] Savepoint 'b' does not exist"), "{}", output);
+}
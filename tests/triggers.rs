@@ -0,0 +1,45 @@
+mod common;
+
+use std::path::Path;
+
+use common::{cli, TempDir};
+
+// Runs `script` with the client in `dir`. Returns what it printed, and whether it all worked
+fn run(dir: &Path, script: &str) -> (String, bool) {
+    let output = cli(dir).args(["-c", script]).output().unwrap();
+    (String::from_utf8(output.stdout).unwrap(), output.status.success())
+}
+
+const SETUP: &str = "CREATE TABLE users id:int age:int; CREATE TABLE log id:int old:int new:int; \
+    CREATE TRIGGER added AFTER INSERT ON users DO 'INSERT INTO log VALUES (NEW.id, 0, NEW.age)'; \
+    CREATE TRIGGER aged AFTER UPDATE ON users DO 'INSERT INTO log VALUES (NEW.id, OLD.age, NEW.age)'; \
+    CREATE TRIGGER gone BEFORE DELETE ON users DO 'INSERT INTO log VALUES (OLD.id, OLD.age, 0)'";
+
+#[test]
+fn triggers_run_for_each_row_with_its_old_and_new_values() {
+    let dir = TempDir::new();
+    assert!(run(dir.path(), SETUP).1);
+    let (stdout, ok) = run(dir.path(), "INSERT INTO users VALUES (1, 20); INSERT INTO users VALUES (2, 30); \
+        UPDATE users SET age = 21 WHERE id = 1; DELETE FROM users WHERE id = 2; SELECT * FROM log");
+    assert!(ok, "{}", stdout);
+    assert!(stdout.ends_with("id,old,new\n1,0,20\n2,0,30\n1,20,21\n2,30,0\n"), "{}", stdout);
+
+    let (stdout, ok) = run(dir.path(), "DROP TRIGGER added ON users; INSERT INTO users VALUES (3, 40); SELECT COUNT(*) FROM log");
+    assert!(ok);
+    assert!(stdout.ends_with("COUNT(*)\n4\n"), "{}", stdout);
+    assert!(!run(dir.path(), "DROP TRIGGER added ON users").1);
+}
+
+#[test]
+fn a_failing_trigger_undoes_the_change_that_fired_it() {
+    let dir = TempDir::new();
+    assert!(run(dir.path(), SETUP).1);
+    // Each row it inserts fires it again, until the nesting limit stops it
+    assert!(run(dir.path(), "CREATE TRIGGER echo AFTER INSERT ON log DO 'INSERT INTO log VALUES (NEW.id, 0, 0)'").1);
+    let output = cli(dir.path()).args(["-c", "INSERT INTO users VALUES (1, 20)"]).output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("nested more than"));
+
+    let (stdout, _) = run(dir.path(), "SELECT COUNT(*) FROM users; SELECT COUNT(*) FROM log");
+    assert_eq!(stdout, "COUNT(*)\n0\nCOUNT(*)\n0\n");
+}
@@ -0,0 +1,40 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use common::{cli, TempDir};
+
+// Runs `script` with the client in `dir`. Returns what it printed, and whether it all worked
+fn run(dir: &Path, script: &str) -> (String, bool) {
+    let output = cli(dir).args(["-c", script]).output().unwrap();
+    (String::from_utf8(output.stdout).unwrap() + &String::from_utf8(output.stderr).unwrap(), output.status.success())
+}
+
+#[test]
+fn rows_are_left_out_once_their_time_passes_and_deleted_on_the_next_write() {
+    let dir = TempDir::new();
+    let (output, ok) = run(dir.path(), "CREATE TABLE s token:string expires:string WITH TTL expires; \
+        INSERT INTO s VALUES ('gone', '2000-01-01 00:00:00'); SELECT token FROM s; COUNT s; \
+        INSERT INTO s VALUES ('kept', '2999-01-01 00:00:00'); INSERT INTO s VALUES ('forever', 'never'); \
+        SELECT token FROM s ORDER BY token");
+    assert!(ok, "{}", output);
+    assert!(output.contains("1 row inserted\ntoken\nTable 's' contains 0 row(s).\n"), "{}", output);
+    assert!(output.ends_with("token\nforever\nkept\n"), "{}", output);
+    assert!(!fs::read_to_string(dir.path().join("data/s.json")).unwrap().contains("gone"));
+
+    // An int column holds seconds since the Unix epoch
+    let (output, ok) = run(dir.path(), "CREATE TABLE e id:int at:int WITH TTL at; INSERT INTO e VALUES (1, 60); \
+        INSERT INTO e VALUES (2, 2000000000); SELECT id FROM e");
+    assert!(ok && output.ends_with("id\n2\n"), "{}", output);
+}
+
+#[test]
+fn rows_only_expire_by_a_column_holding_a_time() {
+    let dir = TempDir::new();
+    let (output, ok) = run(dir.path(), "CREATE TABLE f x:float WITH TTL x");
+    assert!(!ok && output.contains("[E1012] Invalid TTL: column 'x' is float"), "{}", output);
+    let (output, ok) = run(dir.path(), "CREATE TABLE g x:int WITH TTL y");
+    assert!(!ok, "{}", output);
+    assert!(!dir.path().join("data/f.json").exists() && !dir.path().join("data/g.json").exists());
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use std::fs;
+
+use rust_db::parser;
+use rust_db::users::{self, Privilege, Requirement};
+use rust_db::{Database, DbError};
+
+use common::{create_table, TempDir};
+
+#[test]
+fn maintenance_statements_are_for_superusers() {
+    for sql in ["CHECKPOINT", "FLUSH", "VACUUM", "VACUUM t"] {
+        let statement = parser::parse(sql).unwrap();
+        assert!(matches!(users::requirement(&statement), Requirement::Superuser), "{}", sql);
+    }
+}
+
+#[test]
+fn explaining_a_statement_needs_select_on_its_table() {
+    for sql in ["EXPLAIN SELECT * FROM t", "EXPLAIN ANALYZE SELECT * FROM t", "EXPLAIN DELETE FROM t WHERE id = 1"] {
+        let statement = parser::parse(sql).unwrap();
+        assert!(matches!(users::requirement(&statement), Requirement::Table("t", Privilege::Select)), "{}", sql);
+    }
+}
+
+#[test]
+fn a_user_logs_in_with_a_password_kept_only_as_a_hash() {
+    let dir = TempDir::new();
+    {
+        let mut db = Database::open_dir(dir.path()).unwrap();
+        db.create_user("alice", "correct horse", false).unwrap();
+        assert!(matches!(db.create_user("alice", "other", true), Err(DbError::UserExists(_))));
+    }
+    let saved = fs::read_to_string(dir.path().join("users.conf")).unwrap();
+    assert!(saved.contains("alice") && !saved.contains("correct horse"));
+
+    let db = Database::open_dir(dir.path()).unwrap();
+    let alice = db.user("alice").unwrap().unwrap();
+    assert!(alice.verify("correct horse"));
+    assert!(!alice.verify("correct horse ") && !alice.verify(""));
+    assert!(!alice.superuser);
+    assert!(db.user("bob").unwrap().is_none());
+}
+
+#[test]
+fn grants_allow_a_user_what_they_name_on_a_table_until_revoked() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    create_table(&mut db, "t", &[("id", "int")]);
+    db.create_user("alice", "pw", false).unwrap();
+    db.create_user("root", "pw", true).unwrap();
+    assert!(db.grant("alice", "missing", &[Privilege::Select]).is_err());
+    db.grant("alice", "t", &[Privilege::Select, Privilege::Insert]).unwrap();
+
+    let alice = db.user("alice").unwrap().unwrap();
+    assert!(alice.allows(&Requirement::Table("t", Privilege::Select)));
+    assert!(!alice.allows(&Requirement::Table("t", Privilege::Delete)));
+    assert!(!alice.allows(&Requirement::Table("u", Privilege::Select)));
+    assert!(!alice.allows(&Requirement::Superuser));
+    assert!(db.user("root").unwrap().unwrap().allows(&Requirement::Superuser));
+
+    db.revoke("alice", "t", &[Privilege::Insert]).unwrap();
+    let alice = db.user("alice").unwrap().unwrap();
+    assert!(alice.allows(&Requirement::Table("t", Privilege::Select)));
+    assert!(!alice.allows(&Requirement::Table("t", Privilege::Insert)));
+}
@@ -0,0 +1,40 @@
+mod common;
+
+use std::io::Write;
+use std::process::Stdio;
+
+use rust_db::variables::{self, Variables};
+use rust_db::DbError;
+
+use common::{cli, int, string, TempDir};
+
+#[test]
+fn each_variable_is_replaced_by_its_value_outside_strings_and_comments() {
+    let variables = Variables::from([("start".to_string(), int(100)), ("who".to_string(), string("it's"))]);
+    let substituted = variables::substitute("SELECT * FROM t WHERE id > @start AND name = @who AND note <> '@start' -- @nobody", &variables);
+    assert_eq!(substituted.unwrap(), "SELECT * FROM t WHERE id > 100 AND name = 'it''s' AND note <> '@start' -- @nobody");
+    // The variable a SET assigns to stays
+    assert_eq!(variables::substitute("SET @start = @start + 1", &variables).unwrap(), "SET @start = 100 + 1");
+    assert!(matches!(variables::substitute("SELECT @end", &variables), Err(DbError::VariableNotFound(name)) if name == "end"));
+
+    assert_eq!(variables::parse("100"), int(100));
+    assert_eq!(variables::parse("'it''s'"), string("it's"));
+    assert_eq!(variables::parse("2024-01-01"), string("2024-01-01"));
+}
+
+#[test]
+fn variables_are_set_by_statement_or_backslash_command_for_the_session() {
+    let dir = TempDir::new();
+    let mut child = cli(dir.path()).arg("--memory").stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().unwrap();
+    child.stdin.take().unwrap().write_all(b"CREATE TABLE t id:int name:string\nINSERT INTO t VALUES (1, 'a'); INSERT INTO t VALUES (200, 'b')\n\
+        \\set start 100\n\\set who 'b'\nSELECT name FROM t WHERE id > @start\nSET @start = 10 * 0\nSELECT name FROM t WHERE id > @start\n\
+        SELECT id FROM t WHERE name = @who\n\\unset start\nSELECT * FROM t WHERE id > @start\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    let output = String::from_utf8(output.stdout).unwrap() + &String::from_utf8(output.stderr).unwrap();
+    assert!(output.contains("@start = 100\n@who = 'b'\nname\nb\n@start = 0\nname\na\nb\nid\n200\n@start unset\n"), "{}", output);
+    assert!(output.contains("[E2022] Variable '@start' is not set"), "{}", output);
+
+    // A single-transaction script is parsed whole before it runs, so it cannot set them
+    let output = cli(dir.path()).args(["--memory", "--single-transaction", "-c", "SET @a = 1"]).output().unwrap();
+    assert!(String::from_utf8(output.stderr).unwrap().contains("[E3016] A single-transaction script cannot set variables"));
+}
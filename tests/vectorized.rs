@@ -0,0 +1,25 @@
+mod common;
+
+use rust_db::{DataType, Database};
+
+use common::{create_table, insert, int, TempDir};
+
+// A column compared as it is goes through the whole-column path; through a
+// CAST, which leaves a float as it is, it is compared a row at a time
+#[test]
+fn negative_zero_and_nan_compare_alike_a_column_and_a_row_at_a_time() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    create_table(&mut db, "t", &[("id", "int"), ("x", "float")]);
+    for (id, x) in [(1, -0.0), (2, 0.0), (3, f32::NAN), (4, 1.5), (5, -1.5)] {
+        insert(&mut db, "t", vec![int(id), DataType::Float32(x)]);
+    }
+
+    for condition in ["= 0.0", "= -0.0", "< 0.0", ">= 0.0", "!= 0.0", "> 1000.0", "<= -1.5"] {
+        let whole = db.query(&format!("SELECT id FROM t WHERE x {}", condition)).unwrap().rows;
+        let each = db.query(&format!("SELECT id FROM t WHERE CAST(x AS float) {}", condition)).unwrap().rows;
+        assert_eq!(whole, each, "x {}", condition);
+    }
+    assert_eq!(db.query("SELECT id FROM t WHERE x = 0.0").unwrap().rows, vec![vec![int(2)]]);
+    assert_eq!(db.query("SELECT id FROM t WHERE x > 1000.0").unwrap().rows, vec![vec![int(3)]]);
+}
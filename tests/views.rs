@@ -0,0 +1,95 @@
+mod common;
+
+use rust_db::index::{IndexDef, IndexKind};
+use rust_db::parser::{self, Statement};
+use rust_db::views::Freshness;
+use rust_db::{DataType, Database, DbError};
+
+use common::{create_table, insert, int, TempDir};
+
+// Users 1 to 6, each as old as ten times their id
+fn users(db: &mut Database) {
+    create_table(db, "users", &[("id", "int"), ("age", "int")]);
+    for id in 1..=6 {
+        insert(db, "users", vec![int(id), int(id * 10)]);
+    }
+}
+
+// Creates view `name` as `select`, a SELECT * with a WHERE
+fn create_view(db: &mut Database, name: &str, select: &str, materialized: bool) -> Result<(), DbError> {
+    let Statement::Select { from, filter, .. } = parser::parse(select).unwrap() else {
+        unreachable!("a SELECT parses as one");
+    };
+    db.create_view(name, &from.table, filter, materialized)
+}
+
+fn ids(db: &mut Database, sql: &str) -> Vec<DataType> {
+    db.query(sql).unwrap().rows.into_iter().map(|row| row[0].clone()).collect()
+}
+
+#[test]
+fn a_view_runs_its_query_each_time_it_is_read() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    users(&mut db);
+    create_view(&mut db, "adults", "SELECT * FROM users WHERE age >= 30", false).unwrap();
+    create_view(&mut db, "seniors", "SELECT * FROM adults WHERE age >= 50", false).unwrap();
+
+    assert_eq!(ids(&mut db, "SELECT id FROM adults WHERE id < 5 ORDER BY id"), [int(3), int(4)]);
+    assert_eq!(ids(&mut db, "SELECT id FROM seniors ORDER BY id"), [int(5), int(6)]);
+    insert(&mut db, "users", vec![int(7), int(70)]);
+    assert_eq!(ids(&mut db, "SELECT id FROM seniors ORDER BY id"), [int(5), int(6), int(7)]);
+
+    assert!(matches!(db.check_writable("adults"), Err(DbError::ReadOnlyView(_))));
+    assert!(matches!(create_view(&mut db, "users", "SELECT * FROM adults WHERE id = 1", false), Err(DbError::ViewExists(_))));
+    assert!(create_view(&mut db, "broken", "SELECT * FROM users WHERE height > 1", false).is_err());
+    assert!(create_view(&mut db, "nowhere", "SELECT * FROM missing WHERE id = 1", false).is_err());
+    drop(db);
+
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    assert_eq!(ids(&mut db, "SELECT id FROM seniors ORDER BY id"), [int(5), int(6), int(7)]);
+}
+
+#[test]
+fn a_materialized_view_keeps_its_rows_until_refreshed() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    users(&mut db);
+    create_view(&mut db, "adults", "SELECT * FROM users WHERE age >= 30", true).unwrap();
+    let index = IndexDef { name: "idx_age".to_string(), columns: vec!["age".to_string()], kind: IndexKind::BTree, unique: false };
+    db.create_index("adults", index).unwrap();
+    let view = db.view("adults").unwrap().unwrap();
+    assert_eq!(db.freshness(&view).unwrap(), Freshness::Fresh);
+
+    insert(&mut db, "users", vec![int(7), int(70)]);
+    assert_eq!(ids(&mut db, "SELECT id FROM adults ORDER BY id"), [int(3), int(4), int(5), int(6)]);
+    assert_eq!(db.freshness(&view).unwrap(), Freshness::Stale);
+
+    assert_eq!(db.refresh_view("adults").unwrap(), 5);
+    let view = db.view("adults").unwrap().unwrap();
+    assert_eq!(db.freshness(&view).unwrap(), Freshness::Fresh);
+    assert_eq!(ids(&mut db, "SELECT id FROM adults WHERE age > 50 ORDER BY id"), [int(6), int(7)]);
+    assert_eq!(db.snapshot("adults").unwrap().index_defs[0].name, "idx_age");
+
+    db.drop_table("users").unwrap();
+    assert_eq!(db.freshness(&view).unwrap(), Freshness::Broken);
+}
+
+#[test]
+fn only_a_materialized_view_is_refreshed_and_dropping_it_drops_its_table() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    users(&mut db);
+    create_view(&mut db, "adults", "SELECT * FROM users WHERE age >= 30", false).unwrap();
+    create_view(&mut db, "seniors", "SELECT * FROM adults WHERE age >= 50", true).unwrap();
+
+    assert!(matches!(db.refresh_view("adults"), Err(DbError::NotMaterialized(_))));
+    assert!(matches!(db.refresh_view("missing"), Err(DbError::ViewNotFound(_))));
+    assert!(matches!(db.check_writable("seniors"), Err(DbError::ReadOnlyView(_))));
+    assert!(db.table_exists("seniors"));
+
+    db.drop_view("seniors").unwrap();
+    assert!(!db.table_exists("seniors"));
+    assert!(db.view("seniors").unwrap().is_none());
+    assert!(matches!(db.drop_view("seniors"), Err(DbError::ViewNotFound(_))));
+}
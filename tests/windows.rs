@@ -0,0 +1,64 @@
+mod common;
+
+use rust_db::{DataType, Database};
+
+use common::{create_table, insert, int, string, TempDir};
+
+// Staff 1 to 5: three in department a, two of them paid the same, and two in b
+fn staff(db: &mut Database) {
+    create_table(db, "staff", &[("id", "int"), ("dept", "string"), ("pay", "int")]);
+    insert(db, "staff", vec![int(1), string("a"), int(30)]);
+    insert(db, "staff", vec![int(2), string("a"), int(20)]);
+    insert(db, "staff", vec![int(3), string("b"), int(40)]);
+    insert(db, "staff", vec![int(4), string("a"), int(30)]);
+    insert(db, "staff", vec![int(5), string("b"), int(10)]);
+}
+
+#[test]
+fn row_number_and_rank_count_within_each_partition() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    staff(&mut db);
+
+    let rows = db.query(
+        "SELECT id, ROW_NUMBER() OVER (PARTITION BY dept ORDER BY pay DESC, id), RANK() OVER (PARTITION BY dept ORDER BY pay DESC) \
+         FROM staff ORDER BY id",
+    ).unwrap();
+    assert_eq!(rows.rows, vec![
+        vec![int(1), int(1), int(1)],
+        vec![int(2), int(3), int(3)],
+        vec![int(3), int(1), int(1)],
+        vec![int(4), int(2), int(1)],
+        vec![int(5), int(2), int(2)],
+    ]);
+
+    // Top one per department
+    let rows = db.query("SELECT dept, id FROM staff ORDER BY ROW_NUMBER() OVER (PARTITION BY dept ORDER BY pay DESC, id), dept LIMIT 2").unwrap();
+    assert_eq!(rows.rows, vec![vec![string("a"), int(1)], vec![string("b"), int(3)]]);
+}
+
+#[test]
+fn running_sums_and_averages_include_rows_equal_to_the_current_one() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    staff(&mut db);
+
+    let rows = db.query("SELECT id, SUM(pay) OVER (PARTITION BY dept ORDER BY pay), AVG(pay) OVER (ORDER BY id) FROM staff ORDER BY id").unwrap();
+    let running: Vec<(DataType, DataType)> = rows.rows.into_iter().map(|row| (row[1].clone(), row[2].clone())).collect();
+    assert_eq!(running.iter().map(|(sum, _)| sum.clone()).collect::<Vec<_>>(), [int(80), int(20), int(50), int(80), int(10)]);
+    assert_eq!(running[1].1, DataType::Float32(25.0));
+    assert_eq!(running[4].1, DataType::Float32(26.0));
+
+    // Without an ORDER BY each row gets its partition's total, of the rows the WHERE left
+    let rows = db.query("SELECT id, SUM(pay) OVER (PARTITION BY dept) FROM staff WHERE id > 1 ORDER BY id").unwrap();
+    assert_eq!(rows.rows.iter().map(|row| row[1].clone()).collect::<Vec<_>>(), [int(50), int(50), int(50), int(50)]);
+}
+
+#[test]
+fn window_functions_are_refused_in_a_condition() {
+    let dir = TempDir::new();
+    let mut db = Database::open_dir(dir.path()).unwrap();
+    staff(&mut db);
+
+    assert!(db.query("SELECT id FROM staff WHERE ROW_NUMBER() OVER (ORDER BY id) = 1").is_err());
+}
@@ -0,0 +1,89 @@
+mod common;
+
+use std::path::Path;
+
+use rust_db::wal::WalOp;
+use rust_db::{recovery, Database};
+
+use common::{cli, int, rows, string, TempDir};
+
+// Runs `script` with the client in `dir`. Returns what it printed, and whether it all worked
+fn run(dir: &Path, script: &str) -> (String, bool) {
+    let output = cli(dir).args(["-c", script]).output().unwrap();
+    (String::from_utf8(output.stdout).unwrap() + &String::from_utf8(output.stderr).unwrap(), output.status.success())
+}
+
+#[test]
+fn an_insert_repeating_a_key_can_update_the_row_holding_it_or_be_skipped() {
+    let dir = TempDir::new();
+    let (output, ok) = run(dir.path(), "CREATE TABLE hits page:string PRIMARY KEY n:int; INSERT INTO hits VALUES ('a', 1); \
+        INSERT INTO hits VALUES ('a', 2) ON CONFLICT (page) DO UPDATE SET n = n + EXCLUDED.n; \
+        INSERT INTO hits VALUES ('a', 5) ON CONFLICT DO NOTHING; INSERT INTO hits VALUES ('b', 5) ON CONFLICT DO NOTHING; \
+        SELECT * FROM hits ORDER BY page");
+    assert!(ok, "{}", output);
+    assert!(output.ends_with("1 row updated\n0 rows inserted\n1 row inserted\npage,n\na,3\nb,5\n"), "{}", output);
+
+    let (output, ok) = run(dir.path(), "INSERT INTO hits VALUES ('a', 9)");
+    assert!(!ok && output.contains("[E3001] Duplicate value 'a' violates unique index 'hits_pkey'"), "{}", output);
+}
+
+#[test]
+fn returning_gives_the_rows_a_write_changed_as_they_became() {
+    let dir = TempDir::new();
+    let (output, ok) = run(dir.path(), "CREATE TABLE hits page:string PRIMARY KEY n:int; INSERT INTO hits VALUES ('a', 3); \
+        INSERT INTO hits VALUES ('c', 1) RETURNING *; UPDATE hits SET n = n * 10 WHERE n > 2 RETURNING page, n; \
+        DELETE FROM hits WHERE page = 'c' RETURNING n; \
+        INSERT INTO hits VALUES ('a', 1) ON CONFLICT (page) DO UPDATE SET n = n + 1 RETURNING n");
+    assert!(ok, "{}", output);
+    assert!(output.ends_with("1 row inserted\npage,n\nc,1\npage,n\na,30\nn\n1\nn\n31\n"), "{}", output);
+
+    let (output, ok) = run(dir.path(), "DELETE FROM hits WHERE page = 'a' RETURNING size");
+    assert!(!ok, "{}", output);
+    assert!(run(dir.path(), "SELECT COUNT(*) FROM hits").0.ends_with("COUNT(*)\n1\n"));
+}
+
+#[test]
+fn a_row_updated_on_conflict_stays_where_it_is_and_is_replayed_from_what_changed() {
+    let dir = TempDir::new();
+    let (output, ok) = run(dir.path(), "CREATE TABLE hits page:string PRIMARY KEY n:int tag:string; CREATE INDEX by_n ON hits (n); \
+        INSERT INTO hits VALUES ('a', 1, 'x'); INSERT INTO hits VALUES ('b', 1, 'y'); \
+        INSERT INTO hits VALUES ('a', 2, 'z') ON CONFLICT (page) DO UPDATE SET n = n + EXCLUDED.n; \
+        SELECT * FROM hits; SELECT page FROM hits WHERE n = 3; SELECT page FROM hits WHERE n = 1");
+    assert!(ok, "{}", output);
+    assert!(output.ends_with("page,n,tag\na,3,x\nb,1,y\npage\na\npage\nb\n"), "{}", output);
+
+    // Replayed from the log, the change is the values that differ and nothing else
+    let mut db = Database::open_dir(&dir.path().join("data")).unwrap();
+    db.log(WalOp::Update { table: "hits".to_string(), row: 1, values: vec![("n".to_string(), int(7))] }).unwrap();
+    drop(db);
+    let mut db = Database::open_dir(&dir.path().join("data")).unwrap();
+    recovery::recover(&mut db).unwrap();
+    assert_eq!(rows(&mut db, "hits"), vec![vec![string("a"), int(3), string("x")], vec![string("b"), int(7), string("y")]]);
+    assert_eq!(db.query("SELECT page FROM hits WHERE n = 7").unwrap().rows, vec![vec![string("b")]]);
+}
+
+#[test]
+fn the_changes_of_a_line_are_logged_as_they_run_and_synced_as_set() {
+    let dir = TempDir::new();
+    let (output, ok) = run(dir.path(), "CREATE TABLE t id:int; SET synchronous = full; INSERT INTO t VALUES (1); \
+        SET synchronous TO Batched; INSERT INTO t VALUES (2); SELECT COUNT(*) FROM t");
+    assert!(ok, "{}", output);
+    assert!(output.ends_with("Synchronous full; each statement's changes reach the disk before it returns\n1 row inserted\n\
+        Synchronous batched; the changes of a line's statements reach the disk together\n1 row inserted\nCOUNT(*)\n2\n"), "{}", output);
+    let (output, ok) = run(dir.path(), "SET synchronous = sometimes");
+    assert!(!ok && output.contains("synchronous is full or batched, not 'sometimes'"), "{}", output);
+
+    // A batch only leaves the fsync for later; each change is in the log as soon as it runs
+    let mut db = Database::open_dir(&dir.path().join("data")).unwrap();
+    db.begin_batch();
+    db.log(WalOp::Insert { table: "t".to_string(), row: vec![int(3)] }).unwrap();
+    db.log(WalOp::Insert { table: "t".to_string(), row: vec![int(4)] }).unwrap();
+    drop(db);
+    let mut db = Database::open_dir(&dir.path().join("data")).unwrap();
+    recovery::recover(&mut db).unwrap();
+    assert_eq!(rows(&mut db, "t"), vec![vec![int(1)], vec![int(2)], vec![int(3)], vec![int(4)]]);
+    db.begin_batch();
+    db.log(WalOp::Delete { table: "t".to_string(), index: 0 }).unwrap();
+    db.end_batch().unwrap();
+    assert_eq!(rows(&mut db, "t").len(), 3);
+}